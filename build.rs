@@ -0,0 +1,68 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    grpc::generate();
+}
+
+/// Generates the `OrderEntry` service from the declaration below rather
+/// than from `proto/tradebot.proto`, so building needs no `protoc`. The
+/// messages are defined by hand in `src/grpc.rs`.
+#[cfg(feature = "grpc")]
+mod grpc {
+    use tonic_build::manual::{Builder, Method, MethodBuilder, Service};
+
+    fn method(name: &str, route: &str, input: &str, output: &str) -> MethodBuilder {
+        Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::proto::{}", input))
+            .output_type(format!("crate::grpc::proto::{}", output))
+            .codec_path("tonic_prost::ProstCodec")
+    }
+
+    pub fn generate() {
+        println!("cargo:rerun-if-changed=build.rs");
+        let service = Service::builder()
+            .name("OrderEntry")
+            .package("tradebot.v1")
+            .method(
+                method(
+                    "submit_order",
+                    "SubmitOrder",
+                    "SubmitOrderRequest",
+                    "SubmitOrderResponse",
+                )
+                .build(),
+            )
+            .method(
+                method(
+                    "cancel_order",
+                    "CancelOrder",
+                    "CancelOrderRequest",
+                    "OrderReport",
+                )
+                .build(),
+            )
+            .method(
+                method(
+                    "stream_book",
+                    "StreamBook",
+                    "StreamBookRequest",
+                    "BookSnapshot",
+                )
+                .server_streaming()
+                .build(),
+            )
+            .method(
+                method(
+                    "stream_trades",
+                    "StreamTrades",
+                    "StreamTradesRequest",
+                    "Trade",
+                )
+                .server_streaming()
+                .build(),
+            )
+            .build();
+        Builder::new().compile(&[service]);
+    }
+}
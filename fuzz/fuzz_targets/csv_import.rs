@@ -0,0 +1,45 @@
+//! CSV order events: parsing arbitrary bytes and replaying whatever parses
+//! into an engine must not panic, and the book must stay valid.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tradebot::{
+    api::{NewOrderResponse, OrderReport},
+    import,
+    matching_engine::engine::{MatchingEngine, TradingPair},
+};
+
+fuzz_target!(|data: &[u8]| {
+    let (events, _) = import::read_csv(data);
+
+    let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+    let mut engine = MatchingEngine::new();
+    engine.add_new_market(pair.clone());
+    let engine = std::cell::RefCell::new(engine);
+
+    import::replay(
+        &events,
+        |request| {
+            let mut engine = engine.borrow_mut();
+            let time = events[0].time;
+            let (pair, order) = request.to_order(engine.next_exchange_id(), time)?;
+            let (order, fills) = engine.place_limit_order(pair, order)?;
+            Ok(NewOrderResponse {
+                order: OrderReport::new(request.pair, &order),
+                fills,
+            })
+        },
+        |request| {
+            let pair = request.pair.parse::<TradingPair>()?;
+            let order = engine
+                .borrow_mut()
+                .cancel_order(&pair, request.exchange_id)?;
+            Ok(OrderReport::new(request.pair, &order))
+        },
+    );
+
+    let engine = engine.into_inner();
+    let book = engine.orderbook(&pair).unwrap();
+    assert_eq!(book.debug_validate(), Ok(()));
+});
@@ -0,0 +1,100 @@
+//! Random command sequences against an engine with tick, lot and risk
+//! limits. Every step must leave the book valid and uncrossed.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use rust_decimal::Decimal;
+use tradebot::{
+    config::{MarketConfig, RiskLimits},
+    limit_order_book::order::{Order, OrderType},
+    matching_engine::engine::{MatchingEngine, TradingPair},
+};
+
+#[derive(Debug, Arbitrary)]
+enum Command {
+    Limit {
+        bid: bool,
+        /// Hundredths, so some prices miss the tick size.
+        price: u16,
+        quantity: u16,
+        short_sale: bool,
+    },
+    Market {
+        bid: bool,
+        quantity: u16,
+    },
+    Cancel {
+        exchange_id: u8,
+    },
+}
+
+fn side(bid: bool) -> OrderType {
+    if bid {
+        OrderType::Bid
+    } else {
+        OrderType::Ask
+    }
+}
+
+fuzz_target!(|commands: Vec<Command>| {
+    let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+    let mut market = MarketConfig::new(pair.clone());
+    market.tick_size = Some(Decimal::new(5, 2));
+    market.lot_size = Some(Decimal::ONE);
+    market.risk = Some(RiskLimits {
+        max_order_quantity: Some(Decimal::from(10_000)),
+        ..RiskLimits::default()
+    });
+    let mut engine = MatchingEngine::new();
+    engine.add_market(market);
+
+    let time = chrono::DateTime::UNIX_EPOCH;
+    for command in commands {
+        // Rejections are fine; panics and broken books are not.
+        let _ = match command {
+            Command::Limit {
+                bid,
+                price,
+                quantity,
+                short_sale,
+            } => {
+                let id = engine.next_exchange_id();
+                let order = Order::new(
+                    pair.to_string(),
+                    id,
+                    side(bid),
+                    Decimal::from(quantity),
+                    Decimal::new(price.into(), 2),
+                    time,
+                    time,
+                )
+                .with_short_sale(short_sale);
+                engine.place_limit_order(pair.clone(), order).map(|_| ())
+            }
+            Command::Market { bid, quantity } => {
+                let id = engine.next_exchange_id();
+                let order = Order::new(
+                    pair.to_string(),
+                    id,
+                    side(bid),
+                    Decimal::from(quantity),
+                    Decimal::ZERO,
+                    time,
+                    time,
+                );
+                engine.execute_market_order(pair.clone(), order).map(|_| ())
+            }
+            Command::Cancel { exchange_id } => {
+                engine.cancel_order(&pair, exchange_id.into()).map(|_| ())
+            }
+        };
+
+        let book = engine.orderbook(&pair).unwrap();
+        assert_eq!(book.debug_validate(), Ok(()));
+        if let (Some(bid), Some(ask)) = (book.get_best_bid(), book.get_best_ask()) {
+            assert!(bid < ask, "crossed book: {} >= {}", bid, ask);
+        }
+    }
+});
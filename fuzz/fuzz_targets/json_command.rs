@@ -0,0 +1,16 @@
+//! Replay-file lines: any input must either fail to parse or round-trip.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tradebot::api::Command;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(line) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(command) = serde_json::from_str::<Command>(line) {
+        let encoded = serde_json::to_string(&command).unwrap();
+        assert_eq!(serde_json::from_str::<Command>(&encoded).unwrap(), command);
+    }
+});
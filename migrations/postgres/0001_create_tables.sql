@@ -0,0 +1,37 @@
+CREATE TABLE orders (
+    exchange_id BIGINT PRIMARY KEY,
+    pair TEXT NOT NULL,
+    client TEXT NOT NULL,
+    side TEXT NOT NULL,
+    price NUMERIC NOT NULL,
+    quantity NUMERIC NOT NULL,
+    filled_quantity NUMERIC NOT NULL,
+    remaining_quantity NUMERIC NOT NULL,
+    status TEXT NOT NULL,
+    fees NUMERIC NOT NULL,
+    entry_time TIMESTAMPTZ NOT NULL,
+    event_time TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX orders_open ON orders (pair, exchange_id)
+    WHERE status IN ('new', 'partially_filled');
+
+CREATE TABLE trades (
+    id BIGSERIAL PRIMARY KEY,
+    time TIMESTAMPTZ NOT NULL,
+    pair TEXT NOT NULL,
+    price NUMERIC NOT NULL,
+    quantity NUMERIC NOT NULL,
+    side TEXT NOT NULL,
+    maker_id BIGINT NOT NULL,
+    taker_id BIGINT NOT NULL
+);
+
+CREATE INDEX trades_by_time ON trades (time);
+
+CREATE TABLE balances (
+    account TEXT NOT NULL,
+    asset TEXT NOT NULL,
+    amount NUMERIC NOT NULL,
+    PRIMARY KEY (account, asset)
+);
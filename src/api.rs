@@ -0,0 +1,210 @@
+//! Request and response types shared by the HTTP server and the CLI client.
+
+use crate::{
+    limit_order_book::order::{Fill, LimitOrderBook, Order, OrderStatus, OrderType, TimeInForce},
+    matching_engine::engine::TradingPair,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewOrderRequest {
+    pub pair: String,
+    pub side: OrderType,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    #[serde(default)]
+    pub client: String,
+    /// Only valid on asks.
+    #[serde(default)]
+    pub short_sale: bool,
+    /// Only ever shrink the client's position, never flip it.
+    #[serde(default)]
+    pub reduce_only: bool,
+    /// The client's own ID for the order. Sending the same order under it
+    /// again doesn't place it twice.
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+    /// Tags the order with one of the client's strategies, for attribution.
+    #[serde(default)]
+    pub strategy: Option<String>,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+}
+
+impl NewOrderRequest {
+    /// Builds the engine order for this request, received at `time`.
+    pub fn to_order(
+        &self,
+        exchange_id: u64,
+        time: DateTime<Utc>,
+    ) -> Result<(TradingPair, Order), String> {
+        let pair = self.pair.parse::<TradingPair>()?;
+        let mut builder = Order::builder()
+            .pair(pair.to_string())
+            .exchange_id(exchange_id)
+            .side(self.side)
+            .quantity(self.quantity)
+            .price(self.price)
+            .time(time)
+            .client(self.client.clone())
+            .short_sale(self.short_sale)
+            .reduce_only(self.reduce_only)
+            .time_in_force(self.time_in_force);
+        if let Some(client_order_id) = &self.client_order_id {
+            builder = builder.client_order_id(client_order_id.clone());
+        }
+        if let Some(strategy) = &self.strategy {
+            builder = builder.strategy(strategy.clone());
+        }
+        let order = builder.build().map_err(|err| err.to_string())?;
+        Ok((pair, order))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CancelOrderRequest {
+    pub pair: String,
+    pub exchange_id: u64,
+}
+
+/// A single line of a replay file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Command {
+    New(NewOrderRequest),
+    Cancel(CancelOrderRequest),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderReport {
+    pub exchange_id: u64,
+    pub pair: String,
+    pub side: OrderType,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub filled_quantity: Decimal,
+    pub remaining_quantity: Decimal,
+    pub status: OrderStatus,
+}
+
+impl OrderReport {
+    pub fn new(pair: String, order: &Order) -> Self {
+        Self {
+            exchange_id: order.exchange_id,
+            pair,
+            side: order.order_type,
+            price: order.limit_price,
+            quantity: order.shares,
+            filled_quantity: order.filled_quantity,
+            remaining_quantity: order.remaining_quantity,
+            status: order.status,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewOrderResponse {
+    pub order: OrderReport,
+    pub fills: Vec<Fill>,
+}
+
+/// Aggregated depth, best level first on each side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookSnapshot {
+    pub pair: String,
+    pub bids: Vec<(Decimal, Decimal)>,
+    pub asks: Vec<(Decimal, Decimal)>,
+    /// `LimitOrderBook::checksum` over the levels included.
+    #[serde(default)]
+    pub checksum: u32,
+}
+
+impl BookSnapshot {
+    pub fn new(pair: String, book: &LimitOrderBook, depth: usize) -> Self {
+        let ladder = book.ladder(depth);
+        Self {
+            pair,
+            bids: ladder.bids,
+            asks: ladder.asks,
+            checksum: book.checksum(depth),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_commands_from_json() {
+        // Everything but the order itself may be left out.
+        let line = r#"{"type":"new","pair":"btc/usdt","side":"Bid","price":"99.5","quantity":"2"}"#;
+        let Command::New(request) = serde_json::from_str(line).unwrap() else {
+            panic!("expected a new order");
+        };
+        assert_eq!(request.client, "");
+        assert!(!request.short_sale && !request.reduce_only);
+        assert_eq!(request.time_in_force, TimeInForce::Gtc);
+
+        let time = Utc.with_ymd_and_hms(2024, 1, 2, 9, 30, 0).unwrap();
+        let (pair, order) = request.to_order(7, time).unwrap();
+        assert_eq!(pair.to_string(), "BTC/USDT");
+        assert_eq!(order.exchange_id, 7);
+        assert_eq!((order.limit_price, order.shares), (dec!(99.5), dec!(2)));
+        assert_eq!(
+            OrderReport::new(request.pair, &order).remaining_quantity,
+            dec!(2)
+        );
+
+        let cancel = serde_json::from_str::<Command>(
+            r#"{"type":"cancel","pair":"BTC/USDT","exchange_id":7}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            cancel,
+            Command::Cancel(CancelOrderRequest {
+                pair: "BTC/USDT".to_string(),
+                exchange_id: 7,
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_orders() {
+        let request = NewOrderRequest {
+            pair: "BTCUSDT".to_string(),
+            side: OrderType::Ask,
+            price: dec!(100),
+            quantity: dec!(1),
+            client: "desk".to_string(),
+            short_sale: false,
+            reduce_only: false,
+            client_order_id: Some("a-1".to_string()),
+            strategy: Some("arb".to_string()),
+            time_in_force: TimeInForce::Gtc,
+        };
+        assert!(request.to_order(1, Utc::now()).is_err());
+
+        let request = NewOrderRequest {
+            pair: "BTC/USDT".to_string(),
+            ..request
+        };
+        let (_, order) = request.to_order(1, Utc::now()).unwrap();
+        assert_eq!(order.client_order_id.as_deref(), Some("a-1"));
+        assert_eq!(order.strategy.as_deref(), Some("arb"));
+        let zero = NewOrderRequest {
+            quantity: dec!(0),
+            ..request
+        };
+        assert!(zero.to_order(1, Utc::now()).is_err());
+    }
+}
@@ -0,0 +1,412 @@
+//! Full-book snapshots followed by a gap-free stream of level changes, for
+//! subscribers joining late.
+//!
+//! Subscriptions are set up on the engine thread between two requests, so
+//! nothing can change the book between the snapshot and the first delta
+//! after it. Snapshots of a busy book are throttled: within
+//! `SNAPSHOT_INTERVAL` of the last one a subscriber gets that one again,
+//! along with the deltas since, which the engine keeps for the purpose.
+//!
+//! Most strategies only watch the top of the book, so the best bid and
+//! offer also get a stream of their own that carries an update only when
+//! one of their prices or sizes changes.
+
+use crate::{
+    api::BookSnapshot,
+    feed::recovery::Levels,
+    limit_order_book::{diff::BookDiff, order::LimitOrderBook},
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+use tokio::sync::broadcast;
+
+/// How long a snapshot is handed out again before a fresh one is taken.
+pub const SNAPSHOT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Deltas kept per book for subscribers given an older snapshot.
+const RECENT_CAPACITY: usize = 1024;
+
+/// Deltas a subscriber may fall behind by before it starts missing them.
+const DELTA_CAPACITY: usize = 4096;
+
+/// BBO updates a subscriber may fall behind by before it starts missing
+/// them.
+const BBO_CAPACITY: usize = 1024;
+
+/// The best bid and offer, each as price and size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bbo {
+    pub bid: Option<(Decimal, Decimal)>,
+    pub ask: Option<(Decimal, Decimal)>,
+}
+
+impl Bbo {
+    pub fn from_book(book: &LimitOrderBook) -> Self {
+        let ladder = book.ladder(1);
+        Self {
+            bid: ladder.bids.first().copied(),
+            ask: ladder.asks.first().copied(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BboUpdate {
+    pub pair: String,
+    pub bbo: Bbo,
+}
+
+/// A book's BBO as of subscribing, then each change to it.
+#[derive(Debug)]
+pub struct BboSubscription {
+    pub pair: String,
+    pub bbo: Bbo,
+    updates: broadcast::Receiver<BboUpdate>,
+}
+
+impl BboSubscription {
+    /// The next change to the BBO. Fails once the subscriber has fallen so
+    /// far behind that changes were lost; `bbo` is still the last one it
+    /// saw, and a new subscription brings it up to date.
+    pub async fn next(&mut self) -> Result<Bbo, String> {
+        loop {
+            let update = self.updates.recv().await.map_err(|err| match err {
+                broadcast::error::RecvError::Lagged(missed) => {
+                    format!("Fell behind by {} BBO updates", missed)
+                }
+                broadcast::error::RecvError::Closed => "Engine is not running".to_string(),
+            })?;
+            if update.pair == self.pair {
+                self.bbo = update.bbo;
+                return Ok(update.bbo);
+            }
+        }
+    }
+}
+
+/// The level changes one request made to a book, numbered per book from 1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookDelta {
+    pub pair: String,
+    pub sequence: u64,
+    pub diff: BookDiff,
+}
+
+/// A book's full depth as of `sequence`, then every delta after it.
+#[derive(Debug)]
+pub struct SnapshotSubscription {
+    pub sequence: u64,
+    pub snapshot: BookSnapshot,
+    backlog: VecDeque<BookDelta>,
+    deltas: broadcast::Receiver<BookDelta>,
+}
+
+impl SnapshotSubscription {
+    /// The next delta, in sequence. Fails once the subscriber has fallen so
+    /// far behind that deltas were lost; it needs a new subscription then.
+    pub async fn next(&mut self) -> Result<BookDelta, String> {
+        if let Some(delta) = self.backlog.pop_front() {
+            self.sequence = delta.sequence;
+            return Ok(delta);
+        }
+        loop {
+            let delta = self.deltas.recv().await.map_err(|err| match err {
+                broadcast::error::RecvError::Lagged(missed) => {
+                    format!("Fell behind by {} deltas", missed)
+                }
+                broadcast::error::RecvError::Closed => "Engine is not running".to_string(),
+            })?;
+            if delta.pair == self.snapshot.pair && delta.sequence > self.sequence {
+                self.sequence = delta.sequence;
+                return Ok(delta);
+            }
+        }
+    }
+}
+
+struct Stream {
+    levels: Levels,
+    sequence: u64,
+    recent: VecDeque<BookDelta>,
+    last_snapshot: Option<(Instant, u64, BookSnapshot)>,
+}
+
+/// The engine thread's side of the subscriptions: the last levels
+/// published for each book someone subscribed to, and the last BBO of
+/// every book.
+pub(crate) struct BookStreams {
+    deltas: broadcast::Sender<BookDelta>,
+    streams: HashMap<String, Stream>,
+    bbo_updates: broadcast::Sender<BboUpdate>,
+    bbos: HashMap<String, Bbo>,
+}
+
+impl BookStreams {
+    pub(crate) fn new() -> Self {
+        Self {
+            deltas: broadcast::channel(DELTA_CAPACITY).0,
+            streams: HashMap::new(),
+            bbo_updates: broadcast::channel(BBO_CAPACITY).0,
+            bbos: HashMap::new(),
+        }
+    }
+
+    /// Publishes how `book` has changed since the last call, if anyone has
+    /// subscribed to it.
+    pub(crate) fn book_changed(&mut self, pair: &str, book: &LimitOrderBook) {
+        let bbo = Bbo::from_book(book);
+        if self.bbos.get(pair) != Some(&bbo) {
+            self.bbos.insert(pair.to_string(), bbo);
+            // Sending only fails when nobody is subscribed.
+            let _ = self.bbo_updates.send(BboUpdate {
+                pair: pair.to_string(),
+                bbo,
+            });
+        }
+
+        let Some(stream) = self.streams.get_mut(pair) else {
+            return;
+        };
+        let levels = Levels::from_book(book);
+        let diff = stream.levels.diff(&levels);
+        if diff.is_empty() {
+            return;
+        }
+        stream.levels = levels;
+        stream.sequence += 1;
+        let delta = BookDelta {
+            pair: pair.to_string(),
+            sequence: stream.sequence,
+            diff,
+        };
+        if stream.recent.len() == RECENT_CAPACITY {
+            stream.recent.pop_front();
+        }
+        stream.recent.push_back(delta.clone());
+        // Sending only fails when nobody is subscribed.
+        let _ = self.deltas.send(delta);
+    }
+
+    pub(crate) fn subscribe(&mut self, pair: &str, book: &LimitOrderBook) -> SnapshotSubscription {
+        let stream = self
+            .streams
+            .entry(pair.to_string())
+            .or_insert_with(|| Stream {
+                levels: Levels::from_book(book),
+                sequence: 0,
+                recent: VecDeque::new(),
+                last_snapshot: None,
+            });
+        let oldest = stream
+            .recent
+            .front()
+            .map_or(stream.sequence, |delta| delta.sequence - 1);
+        let (sequence, snapshot) = match &stream.last_snapshot {
+            Some((taken, sequence, snapshot))
+                if taken.elapsed() < SNAPSHOT_INTERVAL && *sequence >= oldest =>
+            {
+                (*sequence, snapshot.clone())
+            }
+            _ => {
+                let snapshot = BookSnapshot::new(pair.to_string(), book, usize::MAX);
+                stream.last_snapshot = Some((Instant::now(), stream.sequence, snapshot.clone()));
+                (stream.sequence, snapshot)
+            }
+        };
+        SnapshotSubscription {
+            sequence,
+            snapshot,
+            backlog: stream
+                .recent
+                .iter()
+                .filter(|delta| delta.sequence > sequence)
+                .cloned()
+                .collect(),
+            deltas: self.deltas.subscribe(),
+        }
+    }
+
+    pub(crate) fn subscribe_bbo(&mut self, pair: &str, book: &LimitOrderBook) -> BboSubscription {
+        let bbo = *self
+            .bbos
+            .entry(pair.to_string())
+            .or_insert_with(|| Bbo::from_book(book));
+        BboSubscription {
+            pair: pair.to_string(),
+            bbo,
+            updates: self.bbo_updates.subscribe(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::NewOrderRequest,
+        limit_order_book::order::{Order, OrderType, TimeInForce},
+        matching_engine::engine::{MatchingEngine, TradingPair},
+        server::EngineHandle,
+    };
+    use chrono::Utc;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::collections::BTreeMap;
+
+    fn order(side: OrderType, price: Decimal) -> NewOrderRequest {
+        NewOrderRequest {
+            pair: "BTC/USDT".to_string(),
+            side,
+            price,
+            quantity: dec!(1),
+            client: "alice".to_string(),
+            short_sale: false,
+            reduce_only: false,
+            client_order_id: None,
+            strategy: None,
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_then_deltas() {
+        let engine = EngineHandle::spawn(|| {
+            let mut engine = MatchingEngine::new();
+            engine.add_new_market(TradingPair::new("BTC".to_string(), "USDT".to_string()));
+            engine
+        });
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            engine
+                .new_order(order(OrderType::Ask, dec!(101)))
+                .await
+                .unwrap();
+            let mut early = engine.subscribe_book("btc/usdt".to_string()).await.unwrap();
+            assert_eq!(early.sequence, 0);
+            assert_eq!(early.snapshot.asks, vec![(dec!(101), dec!(1))]);
+
+            engine
+                .new_order(order(OrderType::Bid, dec!(99)))
+                .await
+                .unwrap();
+            // Within the throttle interval: the same snapshot and the delta
+            // taken since.
+            let mut late = engine.subscribe_book("BTC/USDT".to_string()).await.unwrap();
+            assert_eq!(late.snapshot, early.snapshot);
+            engine
+                .new_order(order(OrderType::Bid, dec!(98)))
+                .await
+                .unwrap();
+
+            let mut levels = Levels {
+                bids: BTreeMap::new(),
+                asks: late.snapshot.asks.iter().copied().collect(),
+            };
+            for sequence in 1..=2 {
+                let delta = late.next().await.unwrap();
+                assert_eq!(delta.sequence, sequence);
+                assert_eq!(early.next().await.unwrap(), delta);
+                delta.diff.apply(&mut levels.bids, &mut levels.asks);
+            }
+            assert_eq!(
+                levels.bids,
+                BTreeMap::from([(dec!(98), dec!(1)), (dec!(99), dec!(1))])
+            );
+        });
+    }
+
+    #[test]
+    fn test_bbo_changes_only() {
+        let engine = EngineHandle::spawn(|| {
+            let mut engine = MatchingEngine::new();
+            engine.add_new_market(TradingPair::new("BTC".to_string(), "USDT".to_string()));
+            engine
+        });
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            engine
+                .new_order(order(OrderType::Ask, dec!(101)))
+                .await
+                .unwrap();
+            let mut bbo = engine.subscribe_bbo("BTC/USDT".to_string()).await.unwrap();
+            assert_eq!(bbo.bbo.bid, None);
+            assert_eq!(bbo.bbo.ask, Some((dec!(101), dec!(1))));
+
+            // A bid behind the best one leaves the top alone, so the next
+            // update is the size added at 99.
+            for price in [dec!(99), dec!(98), dec!(99)] {
+                engine
+                    .new_order(order(OrderType::Bid, price))
+                    .await
+                    .unwrap();
+            }
+            assert_eq!(bbo.next().await.unwrap().bid, Some((dec!(99), dec!(1))));
+            assert_eq!(bbo.next().await.unwrap().bid, Some((dec!(99), dec!(2))));
+
+            engine
+                .new_order(order(OrderType::Bid, dec!(101)))
+                .await
+                .unwrap();
+            let update = bbo.next().await.unwrap();
+            assert_eq!((update.bid, update.ask), (Some((dec!(99), dec!(2))), None));
+            assert_eq!(bbo.bbo, update);
+        });
+    }
+
+    #[test]
+    fn test_streams_per_pair_and_lagging() {
+        let limit = |exchange_id, side, price| {
+            Order::new(
+                "BTC/USDT".to_string(),
+                exchange_id,
+                side,
+                dec!(1),
+                price,
+                Utc::now(),
+                Utc::now(),
+            )
+        };
+        let mut streams = BookStreams::new();
+        let (mut btc, mut eth) = (LimitOrderBook::new(), LimitOrderBook::new());
+        btc.add_order(limit(1, OrderType::Ask, dec!(101)));
+        let mut btc_deltas = streams.subscribe("BTC/USDT", &btc);
+        let mut eth_deltas = streams.subscribe("ETH/USDT", &eth);
+        let mut bbo = streams.subscribe_bbo("BTC/USDT", &btc);
+
+        eth.add_order(limit(2, OrderType::Bid, dec!(10)));
+        streams.book_changed("ETH/USDT", &eth);
+        btc.add_order(limit(3, OrderType::Bid, dec!(99)));
+        streams.book_changed("BTC/USDT", &btc);
+        // Nothing changed, so nothing is published.
+        streams.book_changed("BTC/USDT", &btc);
+        // More top-of-book changes than a subscriber may fall behind by.
+        for exchange_id in 4..(4 + BBO_CAPACITY as u64) {
+            btc.add_order(limit(exchange_id, OrderType::Bid, dec!(100)));
+            streams.book_changed("BTC/USDT", &btc);
+            btc.cancel_order(exchange_id);
+            streams.book_changed("BTC/USDT", &btc);
+        }
+        drop(streams);
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let delta = btc_deltas.next().await.unwrap();
+            assert_eq!((delta.pair.as_str(), delta.sequence), ("BTC/USDT", 1));
+            let delta = eth_deltas.next().await.unwrap();
+            assert_eq!((delta.pair.as_str(), delta.sequence), ("ETH/USDT", 1));
+            assert_eq!(
+                eth_deltas.next().await,
+                Err("Engine is not running".to_string())
+            );
+
+            assert!(bbo.next().await.unwrap_err().starts_with("Fell behind by"));
+            assert_eq!(bbo.bbo.bid, None);
+            // What's left after the lag reads as usual.
+            assert!(bbo.next().await.is_ok());
+        });
+    }
+}
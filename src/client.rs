@@ -0,0 +1,78 @@
+//! Blocking HTTP client for a running `tradebot serve` instance.
+
+use crate::{
+    api::{BookSnapshot, ErrorResponse, NewOrderRequest, NewOrderResponse, OrderReport},
+    matching_engine::ticker::TickerStats,
+};
+use serde::de::DeserializeOwned;
+use ureq::{http::Response, Agent, Body};
+
+pub struct Client {
+    base_url: String,
+    agent: Agent,
+}
+
+impl Client {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let agent = Agent::config_builder()
+            .http_status_as_error(false)
+            .build()
+            .into();
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            agent,
+        }
+    }
+
+    pub fn new_order(&self, request: &NewOrderRequest) -> Result<NewOrderResponse, String> {
+        let response = self
+            .agent
+            .post(format!("{}/orders", self.base_url))
+            .send_json(request)
+            .map_err(|err| err.to_string())?;
+        Self::parse(response)
+    }
+
+    pub fn cancel_order(&self, pair: &str, exchange_id: u64) -> Result<OrderReport, String> {
+        let response = self
+            .agent
+            .delete(format!("{}/orders/{}", self.base_url, exchange_id))
+            .query("pair", pair)
+            .call()
+            .map_err(|err| err.to_string())?;
+        Self::parse(response)
+    }
+
+    pub fn book(&self, pair: &str, depth: usize) -> Result<BookSnapshot, String> {
+        let response = self
+            .agent
+            .get(format!("{}/book", self.base_url))
+            .query("pair", pair)
+            .query("depth", depth.to_string())
+            .call()
+            .map_err(|err| err.to_string())?;
+        Self::parse(response)
+    }
+
+    pub fn ticker(&self, pair: &str) -> Result<TickerStats, String> {
+        let response = self
+            .agent
+            .get(format!("{}/ticker/{}", self.base_url, pair))
+            .call()
+            .map_err(|err| err.to_string())?;
+        Self::parse(response)
+    }
+
+    fn parse<T: DeserializeOwned>(mut response: Response<Body>) -> Result<T, String> {
+        if response.status().is_success() {
+            return response
+                .body_mut()
+                .read_json()
+                .map_err(|err| err.to_string());
+        }
+        match response.body_mut().read_json::<ErrorResponse>() {
+            Ok(error) => Err(error.error),
+            Err(_) => Err(format!("Request failed with status {}", response.status())),
+        }
+    }
+}
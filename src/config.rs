@@ -0,0 +1,752 @@
+//! TOML configuration for setting up a `MatchingEngine`.
+//!
+//! ```toml
+//! [server]
+//! addr = "127.0.0.1:8080"
+//!
+//! [risk]
+//! max_order_quantity = "1000"
+//!
+//! [[markets]]
+//! pair = "BTC/USDT"
+//! tick_size = "0.01"
+//! lot_size = "0.001"
+//!
+//! [[accounts]]
+//! id = "desk"
+//!
+//! [[accounts]]
+//! id = "desk/arb"
+//! risk = { max_order_quantity = "10" }
+//! ```
+
+use crate::{
+    instruments::{Instrument, Instruments},
+    journal::Rotation,
+    limit_order_book::{
+        allocation::Matching, arithmetic, l3::L3Privacy, order::OrderType, prune::PrunePolicy,
+    },
+    matching_engine::{
+        accounts::{AccountId, Accounts},
+        bands::{CircuitBreaker, PriceBand},
+        calendar::Calendar,
+        corporate_actions::CorporateAction,
+        engine::TradingPair,
+        fees::FeeSchedule,
+        funding::FundingConfig,
+        liquidation::MarginConfig,
+        midpoint::MidpointConfig,
+        precision::Precision,
+        rate_limit::RateLimit,
+        risk::Exposure,
+        validation::{OrderKind, Rejection},
+    },
+    recovery::OnMismatch,
+};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{collections::BTreeMap, fmt, fs, io, net::SocketAddr, path::Path, path::PathBuf};
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EngineConfig {
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub risk: RiskLimits,
+    /// Margin requirements; positions are not liquidated when unset.
+    #[serde(default)]
+    pub margin: Option<MarginConfig>,
+    /// Orders each client may send per second; unlimited when unset.
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    pub persistence: PersistenceConfig,
+    #[serde(default)]
+    pub feed: FeedConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub markets: Vec<MarketConfig>,
+    /// When set, only these accounts may trade. Parents come before their
+    /// sub-accounts.
+    #[serde(default)]
+    pub accounts: Vec<AccountConfig>,
+    /// Contract metadata; pairs without an entry are spot.
+    #[serde(default)]
+    pub instruments: Vec<Instrument>,
+    /// Splits and dividends, applied as each goes ex.
+    #[serde(default)]
+    pub corporate_actions: Vec<CorporateAction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub addr: SocketAddr,
+    /// Serve Prometheus metrics at `/metrics`.
+    #[serde(default)]
+    pub metrics: bool,
+    /// Also serve the gRPC API here; needs the `grpc` feature.
+    pub grpc_addr: Option<SocketAddr>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            addr: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            metrics: false,
+            grpc_addr: None,
+        }
+    }
+}
+
+/// Pre-trade limits applied to every order. A market can override them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RiskLimits {
+    pub max_order_quantity: Option<Decimal>,
+    pub max_order_notional: Option<Decimal>,
+    /// Resting orders a client may have in one market.
+    pub max_open_orders: Option<usize>,
+    /// Absolute net position a client may reach in one market, counting
+    /// its resting orders on the same side as filled.
+    pub max_position: Option<Decimal>,
+    /// Notional a client may have across all markets in resting orders and
+    /// positions, the latter valued at each market's mark price. Counted in
+    /// the order's quote currency, converting other markets' notionals at
+    /// the engine's rates.
+    pub max_notional_exposure: Option<Decimal>,
+}
+
+impl RiskLimits {
+    pub fn check(&self, price: Decimal, quantity: Decimal) -> Result<(), Rejection> {
+        self.check_quantity(quantity)?;
+        if let Some(max) = self.max_order_notional {
+            let notional = arithmetic::notional(price, quantity, Decimal::ONE)?;
+            if notional > max {
+                return Err(Rejection::AboveMaxNotional { notional, max });
+            }
+        }
+        Ok(())
+    }
+
+    /// The part of `check` that doesn't need a price, for market orders.
+    pub fn check_quantity(&self, quantity: Decimal) -> Result<(), Rejection> {
+        match self.max_order_quantity {
+            Some(max) if quantity > max => Err(Rejection::AboveMaxQuantity { quantity, max }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the limits that depend on what the client already has on.
+    /// `price` is None for market orders, which never rest.
+    pub fn check_exposure(
+        &self,
+        exposure: &Exposure,
+        side: OrderType,
+        quantity: Decimal,
+        price: Option<Decimal>,
+    ) -> Result<(), String> {
+        if let (Some(max_open_orders), Some(_)) = (self.max_open_orders, price) {
+            if exposure.open_orders >= max_open_orders {
+                return Err(format!(
+                    "Open orders would exceed the maximum {}",
+                    max_open_orders
+                ));
+            }
+        }
+        if let Some(max_position) = self.max_position {
+            let added = exposure.resting + quantity;
+            let worst = match side {
+                OrderType::Bid => exposure.position + added,
+                OrderType::Ask => exposure.position - added,
+            };
+            if worst.abs() > max_position {
+                return Err(format!(
+                    "Position {} would exceed the maximum {}",
+                    worst, max_position
+                ));
+            }
+        }
+        if let Some(max_exposure) = self.max_notional_exposure {
+            if let Some(currency) = exposure.unconverted.first() {
+                return Err(format!("No rate to value {} exposure", currency));
+            }
+            let notional = price
+                .map_or(Ok(Decimal::ZERO), |price| {
+                    arithmetic::notional(price, quantity, Decimal::ONE)
+                })
+                .and_then(|notional| arithmetic::checked_add(exposure.notional, notional))
+                .map_err(|overflow| overflow.to_string())?;
+            if notional > max_exposure {
+                return Err(format!(
+                    "Notional exposure {} would exceed the maximum {}",
+                    notional, max_exposure
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccountConfig {
+    pub id: AccountId,
+    #[serde(default)]
+    pub risk: Option<RiskLimits>,
+}
+
+impl AccountConfig {
+    /// Opens the configured accounts in order.
+    pub fn open_all(configs: &[AccountConfig]) -> Result<Accounts, String> {
+        let mut accounts = Accounts::new();
+        for config in configs {
+            accounts.open(config.id.clone(), config.risk.clone())?;
+        }
+        Ok(accounts)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PersistenceConfig {
+    pub journal_path: Option<PathBuf>,
+    /// Splits the journal into segments; see `journal`.
+    pub rotation: Option<Rotation>,
+    /// Where the server checkpoints the engine when it drains; see
+    /// `recovery`.
+    pub snapshot_path: Option<PathBuf>,
+    /// What to do if the checkpoint and journal don't recover to the
+    /// recorded state.
+    #[serde(default)]
+    pub on_mismatch: OnMismatch,
+}
+
+/// What the engine publishes about book activity.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeedConfig {
+    /// Market-by-order output; off unless explicitly enabled.
+    #[serde(default)]
+    pub l3: L3Privacy,
+}
+
+/// Log verbosity and format. `RUST_LOG`, when set, replaces the levels.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoggingConfig {
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    /// Per-module levels, e.g. `"tradebot::server" = "debug"`.
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+    #[serde(default)]
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: default_log_level(),
+            modules: BTreeMap::new(),
+            format: LogFormat::default(),
+        }
+    }
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+impl LoggingConfig {
+    /// The levels as an `EnvFilter` directive string, e.g.
+    /// `info,tradebot::server=debug`.
+    pub fn directives(&self) -> String {
+        let mut directives = self.level.clone();
+        for (module, level) in &self.modules {
+            directives.push_str(&format!(",{}={}", module, level));
+        }
+        directives
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per event, for log pipelines.
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MarketConfig {
+    pub pair: TradingPair,
+    /// Prices must be a multiple of this.
+    pub tick_size: Option<Decimal>,
+    /// Quantities must be a multiple of this.
+    pub lot_size: Option<Decimal>,
+    pub min_quantity: Option<Decimal>,
+    pub max_quantity: Option<Decimal>,
+    /// Smallest order value, price times quantity, the market takes.
+    pub min_notional: Option<Decimal>,
+    /// How far past the opposite best price an order may be priced, in
+    /// percent of it.
+    pub collar_pct: Option<Decimal>,
+    /// The kinds of order the market takes; all of them if unset.
+    pub order_kinds: Option<Vec<OrderKind>>,
+    #[serde(default)]
+    pub risk: Option<RiskLimits>,
+    #[serde(default)]
+    pub fees: FeeSchedule,
+    /// Band around the reference price that limit orders must fall within.
+    pub price_band: Option<PriceBand>,
+    pub circuit_breaker: Option<CircuitBreaker>,
+    /// How each price level shares incoming orders; FIFO by default.
+    #[serde(default)]
+    pub matching: Matching,
+    /// Enables midpoint orders, which cross at the mid of the book's BBO.
+    pub midpoint: Option<MidpointConfig>,
+    /// Makes the market a perpetual that settles funding between longs and
+    /// shorts.
+    pub funding: Option<FundingConfig>,
+    /// Cancels resting orders on levels far from the touch, to bound the
+    /// book's size.
+    pub prune: Option<PrunePolicy>,
+    /// How fees, average prices, P&L and funding are rounded; not at all
+    /// by default.
+    #[serde(default)]
+    pub precision: Precision,
+    /// The market's trading sessions, at whose close DAY orders expire.
+    #[serde(alias = "session")]
+    pub calendar: Option<Calendar>,
+}
+
+impl MarketConfig {
+    pub fn new(pair: TradingPair) -> Self {
+        Self {
+            pair,
+            tick_size: None,
+            lot_size: None,
+            min_quantity: None,
+            max_quantity: None,
+            min_notional: None,
+            collar_pct: None,
+            order_kinds: None,
+            risk: None,
+            fees: FeeSchedule::default(),
+            price_band: None,
+            circuit_breaker: None,
+            matching: Matching::Fifo,
+            midpoint: None,
+            funding: None,
+            prune: None,
+            precision: Precision::default(),
+            calendar: None,
+        }
+    }
+
+    /// Checks a price and quantity against the market's increments, its
+    /// size and notional limits, and the applicable risk limits.
+    pub fn validate_order(
+        &self,
+        price: Decimal,
+        quantity: Decimal,
+        risk: &RiskLimits,
+    ) -> Result<(), Rejection> {
+        self.validate_contract_order(price, quantity, Decimal::ONE, risk)
+    }
+
+    /// Like `validate_order` for a market whose quantity is in contracts of
+    /// `multiplier` units each, so notional limits see the full value.
+    pub fn validate_contract_order(
+        &self,
+        price: Decimal,
+        quantity: Decimal,
+        multiplier: Decimal,
+        risk: &RiskLimits,
+    ) -> Result<(), Rejection> {
+        if price <= Decimal::ZERO {
+            return Err(Rejection::InvalidPrice(price));
+        }
+        if let Some(tick_size) = self.tick_size {
+            if !(price % tick_size).is_zero() {
+                return Err(Rejection::OffTick { price, tick_size });
+            }
+        }
+        self.validate_quantity(quantity, risk)?;
+        let notional = arithmetic::notional(price, quantity, multiplier)?;
+        if let Some(min) = self.min_notional {
+            if notional < min {
+                return Err(Rejection::BelowMinNotional { notional, min });
+            }
+        }
+
+        self.risk
+            .as_ref()
+            .unwrap_or(risk)
+            .check(price * multiplier, quantity)
+    }
+
+    /// The checks of `validate_order` that don't need a price.
+    pub fn validate_quantity(&self, quantity: Decimal, risk: &RiskLimits) -> Result<(), Rejection> {
+        if quantity <= Decimal::ZERO {
+            return Err(Rejection::InvalidQuantity(quantity));
+        }
+        if let Some(lot_size) = self.lot_size {
+            if !(quantity % lot_size).is_zero() {
+                return Err(Rejection::OffLot { quantity, lot_size });
+            }
+        }
+        if let Some(min) = self.min_quantity {
+            if quantity < min {
+                return Err(Rejection::BelowMinQuantity { quantity, min });
+            }
+        }
+        if let Some(max) = self.max_quantity {
+            if quantity > max {
+                return Err(Rejection::AboveMaxQuantity { quantity, max });
+            }
+        }
+        self.risk.as_ref().unwrap_or(risk).check_quantity(quantity)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    DuplicateMarket(TradingPair),
+    InvalidAccount(String),
+    InvalidInstrument(String),
+    InvalidCorporateAction(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "failed to read config: {}", err),
+            ConfigError::Parse(err) => write!(f, "failed to parse config: {}", err),
+            ConfigError::DuplicateMarket(pair) => {
+                write!(f, "market {} is configured more than once", pair)
+            }
+            ConfigError::InvalidAccount(reason) => write!(f, "invalid account: {}", reason),
+            ConfigError::InvalidInstrument(reason) => {
+                write!(f, "invalid instrument: {}", reason)
+            }
+            ConfigError::InvalidCorporateAction(reason) => {
+                write!(f, "invalid corporate action: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl EngineConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        contents.parse()
+    }
+}
+
+impl std::str::FromStr for EngineConfig {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let config: EngineConfig = toml::from_str(s).map_err(ConfigError::Parse)?;
+        for (index, market) in config.markets.iter().enumerate() {
+            if config.markets[..index]
+                .iter()
+                .any(|other| other.pair == market.pair)
+            {
+                return Err(ConfigError::DuplicateMarket(market.pair.clone()));
+            }
+        }
+        AccountConfig::open_all(&config.accounts).map_err(ConfigError::InvalidAccount)?;
+        Instruments::from_config(&config.instruments).map_err(ConfigError::InvalidInstrument)?;
+        for corporate_action in &config.corporate_actions {
+            corporate_action
+                .action
+                .validate()
+                .map_err(ConfigError::InvalidCorporateAction)?;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{instruments::AssetClass, matching_engine::insurance::LossFallback};
+    use rust_decimal_macros::dec;
+
+    const CONFIG: &str = r#"
+        [server]
+        addr = "0.0.0.0:9000"
+
+        [risk]
+        max_order_quantity = "100"
+
+        [margin]
+        currency = "USDT"
+        maintenance_margin = "0.05"
+
+        [rate_limit]
+        orders_per_second = "50"
+        burst = "100"
+
+        [persistence]
+        journal_path = "data/journal.log"
+        on_mismatch = "cancel_only"
+
+        [[markets]]
+        pair = "BTC/USDT"
+        tick_size = "0.5"
+        lot_size = "0.1"
+
+        [[markets]]
+        pair = "ETH/USDT"
+        risk = { max_order_notional = "1000" }
+        matching = { algorithm = "pro_rata", top_order_priority = true }
+        midpoint = { min_quantity = "1" }
+        funding = { interval_secs = 28800 }
+
+        [[accounts]]
+        id = "desk"
+
+        [[accounts]]
+        id = "desk/arb"
+        risk = { max_order_quantity = "10" }
+
+        [[instruments]]
+        pair = "BTC-MAR25/USD"
+        asset_class = "future"
+        multiplier = "0.1"
+        expiry = "2025-03-28T08:00:00Z"
+    "#;
+
+    #[test]
+    fn test_parse_config() {
+        let config: EngineConfig = CONFIG.parse().unwrap();
+        assert_eq!(config.server.addr, "0.0.0.0:9000".parse().unwrap());
+        assert_eq!(config.risk.max_order_quantity, Some(dec!(100)));
+        assert_eq!(config.rate_limit.unwrap().burst, dec!(100));
+        let margin = config.margin.unwrap();
+        assert_eq!(margin.maintenance_margin, dec!(0.05));
+        assert_eq!(margin.liquidation_step, dec!(1));
+        assert_eq!(margin.fallback, LossFallback::SocializedLoss);
+        assert_eq!(
+            config.persistence.journal_path,
+            Some(PathBuf::from("data/journal.log"))
+        );
+        assert_eq!(config.persistence.on_mismatch, OnMismatch::CancelOnly);
+        assert_eq!(config.markets.len(), 2);
+        assert_eq!(config.markets[0].pair.to_string(), "BTC/USDT");
+        assert_eq!(config.markets[0].tick_size, Some(dec!(0.5)));
+        assert_eq!(
+            config.markets[1].risk.as_ref().unwrap().max_order_notional,
+            Some(dec!(1000))
+        );
+        assert_eq!(config.markets[0].matching, Matching::Fifo);
+        assert_eq!(
+            config.markets[1].matching,
+            Matching::ProRata {
+                top_order_priority: true
+            }
+        );
+        assert_eq!(config.markets[0].midpoint, None);
+        assert_eq!(
+            config.markets[1].midpoint.as_ref().unwrap().min_quantity,
+            dec!(1)
+        );
+        assert_eq!(config.markets[0].funding, None);
+        assert_eq!(
+            config.markets[1].funding.as_ref().unwrap().max_rate,
+            dec!(0.0075)
+        );
+        assert_eq!(config.accounts.len(), 2);
+        assert_eq!(config.accounts[1].id, AccountId::from("desk").sub("arb"));
+        assert_eq!(config.instruments[0].asset_class, AssetClass::Future);
+        assert_eq!(config.instruments[0].multiplier, dec!(0.1));
+    }
+
+    #[test]
+    fn test_example_config_parses() {
+        let config: EngineConfig = include_str!("../tradebot.example.toml").parse().unwrap();
+        assert_eq!(config.markets.len(), 2);
+        assert!(config.server.metrics);
+        assert_eq!(config.logging.format, LogFormat::Text);
+        assert_eq!(
+            config.logging.directives(),
+            "info,tradebot::matching_engine=debug"
+        );
+        assert_eq!(config.feed.l3, L3Privacy::Anonymized);
+        assert_eq!(config.markets[0].fees.taker_bps, dec!(5));
+        assert_eq!(config.markets[0].fees.tiers.len(), 1);
+        assert_eq!(config.markets[1].fees, FeeSchedule::default());
+        assert_eq!(
+            config.markets[0]
+                .circuit_breaker
+                .as_ref()
+                .unwrap()
+                .halt_secs,
+            300
+        );
+        assert_eq!(config.markets[1].price_band, None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            "[[markets]]\npair = \"BTCUSDT\"".parse::<EngineConfig>(),
+            Err(ConfigError::Parse(_))
+        ));
+        assert!(matches!(
+            "[[markets]]\npair = \"BTC/USDT\"\n[[markets]]\npair = \"BTC/USDT\""
+                .parse::<EngineConfig>(),
+            Err(ConfigError::DuplicateMarket(_))
+        ));
+        assert!(matches!(
+            "[[accounts]]\nid = \"desk/arb\"".parse::<EngineConfig>(),
+            Err(ConfigError::InvalidAccount(_))
+        ));
+        assert!(matches!(
+            "[[instruments]]\npair = \"BTC-MAR25/USD\"\nasset_class = \"future\""
+                .parse::<EngineConfig>(),
+            Err(ConfigError::InvalidInstrument(_))
+        ));
+        assert!(matches!(
+            "[[corporate_actions]]\npair = \"AAPL/USD\"\nex_date = \"2024-08-12\"\naction = { split = \"0\" }"
+                .parse::<EngineConfig>(),
+            Err(ConfigError::InvalidCorporateAction(_))
+        ));
+        assert!(matches!(
+            "[unknown]".parse::<EngineConfig>(),
+            Err(ConfigError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_order() {
+        let config: EngineConfig = CONFIG.parse().unwrap();
+        let btc = &config.markets[0];
+        let eth = &config.markets[1];
+
+        assert!(btc
+            .validate_order(dec!(100.5), dec!(1.2), &config.risk)
+            .is_ok());
+        assert!(btc
+            .validate_order(dec!(100.25), dec!(1), &config.risk)
+            .is_err());
+        assert!(btc
+            .validate_order(dec!(100), dec!(1.25), &config.risk)
+            .is_err());
+        assert!(btc
+            .validate_order(dec!(100), dec!(101), &config.risk)
+            .is_err());
+        assert!(btc
+            .validate_order(dec!(100), dec!(0), &config.risk)
+            .is_err());
+
+        // The market's own limits replace the global ones.
+        assert!(eth
+            .validate_order(dec!(10), dec!(101), &config.risk)
+            .is_err());
+        assert!(eth.validate_order(dec!(1), dec!(101), &config.risk).is_ok());
+        assert!(eth
+            .validate_contract_order(dec!(1), dec!(101), dec!(10), &config.risk)
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_exposure() {
+        let limits = RiskLimits {
+            max_open_orders: Some(2),
+            max_position: Some(dec!(10)),
+            max_notional_exposure: Some(dec!(1000)),
+            ..RiskLimits::default()
+        };
+        let exposure = Exposure {
+            open_orders: 2,
+            position: dec!(4),
+            resting: dec!(3),
+            notional: dec!(700),
+            ..Exposure::default()
+        };
+        assert_eq!(
+            limits.check_exposure(&exposure, OrderType::Bid, dec!(1), Some(dec!(100))),
+            Err("Open orders would exceed the maximum 2".to_string())
+        );
+        // Market orders never rest, so only the other limits apply.
+        assert!(limits
+            .check_exposure(&exposure, OrderType::Bid, dec!(3), None)
+            .is_ok());
+        assert_eq!(
+            limits.check_exposure(&exposure, OrderType::Bid, dec!(4), None),
+            Err("Position 11 would exceed the maximum 10".to_string())
+        );
+        // Selling counts the resting asks against the long position.
+        assert!(limits
+            .check_exposure(&exposure, OrderType::Ask, dec!(11), None)
+            .is_ok());
+
+        let exposure = Exposure {
+            open_orders: 0,
+            ..exposure
+        };
+        assert!(limits
+            .check_exposure(&exposure, OrderType::Bid, dec!(3), Some(dec!(100)))
+            .is_ok());
+        assert_eq!(
+            limits.check_exposure(&exposure, OrderType::Bid, dec!(3), Some(dec!(101))),
+            Err("Notional exposure 1003 would exceed the maximum 1000".to_string())
+        );
+        let mut unconverted = exposure.clone();
+        unconverted.unconverted.insert("EUR".to_string());
+        assert_eq!(
+            limits.check_exposure(&unconverted, OrderType::Bid, dec!(1), Some(dec!(1))),
+            Err("No rate to value EUR exposure".to_string())
+        );
+        // Without a notional limit there's nothing to convert.
+        let positions_only = RiskLimits {
+            max_notional_exposure: None,
+            ..limits
+        };
+        assert!(positions_only
+            .check_exposure(&unconverted, OrderType::Bid, dec!(1), Some(dec!(1)))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_defaults_and_logging() {
+        let config: EngineConfig = "".parse().unwrap();
+        assert_eq!(config.server, ServerConfig::default());
+        assert_eq!(config.logging.directives(), "info");
+        assert_eq!(config.persistence, PersistenceConfig::default());
+        assert!(config.markets.is_empty() && config.rate_limit.is_none());
+
+        let config: EngineConfig = r#"
+            [logging]
+            level = "warn"
+            format = "json"
+            modules = { "tradebot::server" = "debug", "tradebot::feed" = "trace" }
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(config.logging.format, LogFormat::Json);
+        assert_eq!(
+            config.logging.directives(),
+            "warn,tradebot::feed=trace,tradebot::server=debug"
+        );
+
+        assert!(matches!(
+            EngineConfig::from_file("does/not/exist.toml"),
+            Err(ConfigError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,296 @@
+//! Price-time priority matching on `alloc` collections alone.
+//!
+//! This is the part of the book that builds without `std`: no clocks, no
+//! `Rc`, no hashing, and time is only the order of calls. It matches the
+//! same way as `LimitOrderBook` — best price first, FIFO within a level, at
+//! the maker's price — but leaves events, clients, fees and the rest of the
+//! order lifecycle to the `std` build.
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    vec::Vec,
+};
+use rust_decimal::Decimal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestingOrder {
+    pub id: u64,
+    pub side: Side,
+    pub price: Decimal,
+    /// Quantity still open.
+    pub quantity: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pub maker_id: u64,
+    pub taker_id: u64,
+    pub price: Decimal,
+    pub quantity: Decimal,
+}
+
+/// Order IDs must be unique among resting orders.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Book {
+    bids: BTreeMap<Decimal, VecDeque<RestingOrder>>,
+    asks: BTreeMap<Decimal, VecDeque<RestingOrder>>,
+    index: BTreeMap<u64, (Side, Decimal)>,
+}
+
+impl Book {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches a limit order and rests whatever is left. Returns the matches
+    /// and the quantity left resting.
+    pub fn place_limit(
+        &mut self,
+        id: u64,
+        side: Side,
+        price: Decimal,
+        quantity: Decimal,
+    ) -> (Vec<Match>, Decimal) {
+        let mut remaining = quantity;
+        let matches = self.sweep(id, side, Some(price), &mut remaining);
+        if remaining > Decimal::ZERO {
+            self.levels_mut(side)
+                .entry(price)
+                .or_default()
+                .push_back(RestingOrder {
+                    id,
+                    side,
+                    price,
+                    quantity: remaining,
+                });
+            self.index.insert(id, (side, price));
+        }
+        (matches, remaining)
+    }
+
+    /// Matches at any price; the remainder is dropped.
+    pub fn place_market(&mut self, id: u64, side: Side, quantity: Decimal) -> Vec<Match> {
+        let mut remaining = quantity;
+        self.sweep(id, side, None, &mut remaining)
+    }
+
+    pub fn cancel(&mut self, id: u64) -> Option<RestingOrder> {
+        let (side, price) = self.index.remove(&id)?;
+        let levels = self.levels_mut(side);
+        let level = levels.get_mut(&price)?;
+        let position = level.iter().position(|order| order.id == id)?;
+        let order = level.remove(position);
+        if level.is_empty() {
+            levels.remove(&price);
+        }
+        order
+    }
+
+    pub fn get(&self, id: u64) -> Option<&RestingOrder> {
+        let (side, price) = self.index.get(&id)?;
+        self.levels(*side)
+            .get(price)?
+            .iter()
+            .find(|order| order.id == id)
+    }
+
+    pub fn best_bid(&self) -> Option<Decimal> {
+        self.bids.keys().next_back().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<Decimal> {
+        self.asks.keys().next().copied()
+    }
+
+    /// Aggregated `(price, size)` levels, best first.
+    pub fn depth(&self, side: Side, levels: usize) -> Vec<(Decimal, Decimal)> {
+        let size = |(price, orders): (&Decimal, &VecDeque<RestingOrder>)| {
+            (*price, orders.iter().map(|order| order.quantity).sum())
+        };
+        match side {
+            Side::Bid => self.bids.iter().rev().take(levels).map(size).collect(),
+            Side::Ask => self.asks.iter().take(levels).map(size).collect(),
+        }
+    }
+
+    /// Number of resting orders.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    fn levels(&self, side: Side) -> &BTreeMap<Decimal, VecDeque<RestingOrder>> {
+        match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        }
+    }
+
+    fn levels_mut(&mut self, side: Side) -> &mut BTreeMap<Decimal, VecDeque<RestingOrder>> {
+        match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        }
+    }
+
+    /// Matches against the opposite side while it crosses `limit`.
+    fn sweep(
+        &mut self,
+        taker_id: u64,
+        side: Side,
+        limit: Option<Decimal>,
+        remaining: &mut Decimal,
+    ) -> Vec<Match> {
+        let mut matches = Vec::new();
+        while *remaining > Decimal::ZERO {
+            let best = match side {
+                Side::Bid => self.asks.first_entry(),
+                Side::Ask => self.bids.last_entry(),
+            };
+            let mut level = match best {
+                Some(level) => level,
+                None => break,
+            };
+            let price = *level.key();
+            let crosses = match (side, limit) {
+                (_, None) => true,
+                (Side::Bid, Some(limit)) => price <= limit,
+                (Side::Ask, Some(limit)) => price >= limit,
+            };
+            if !crosses {
+                break;
+            }
+
+            let orders = level.get_mut();
+            while *remaining > Decimal::ZERO {
+                let maker = match orders.front_mut() {
+                    Some(maker) => maker,
+                    None => break,
+                };
+                let quantity = maker.quantity.min(*remaining);
+                maker.quantity -= quantity;
+                *remaining -= quantity;
+                matches.push(Match {
+                    maker_id: maker.id,
+                    taker_id,
+                    price,
+                    quantity,
+                });
+                if maker.quantity.is_zero() {
+                    let maker_id = maker.id;
+                    orders.pop_front();
+                    self.index.remove(&maker_id);
+                }
+            }
+            if orders.is_empty() {
+                level.remove();
+            }
+        }
+        matches
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<crate::limit_order_book::order::OrderType> for Side {
+    fn from(order_type: crate::limit_order_book::order::OrderType) -> Self {
+        match order_type {
+            crate::limit_order_book::order::OrderType::Bid => Side::Bid,
+            crate::limit_order_book::order::OrderType::Ask => Side::Ask,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::{LimitOrderBook, Order, OrderType};
+    use chrono::Utc;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_price_time_priority() {
+        let mut book = Book::new();
+        book.place_limit(1, Side::Ask, dec!(101), dec!(1));
+        book.place_limit(2, Side::Ask, dec!(100), dec!(1));
+        book.place_limit(3, Side::Ask, dec!(100), dec!(2));
+
+        let (matches, resting) = book.place_limit(4, Side::Bid, dec!(100), dec!(2));
+        assert_eq!(resting, dec!(0));
+        assert_eq!(
+            matches.iter().map(|m| m.maker_id).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(book.get(3).unwrap().quantity, dec!(1));
+        assert_eq!(book.best_ask(), Some(dec!(100)));
+
+        assert_eq!(book.cancel(3).unwrap().id, 3);
+        assert_eq!(book.cancel(3), None);
+        let matches = book.place_market(5, Side::Bid, dec!(5));
+        assert_eq!(matches.len(), 1);
+        assert!(book.is_empty());
+    }
+
+    /// Random order flow must produce the same matches and depth here as in
+    /// the `std` book.
+    #[test]
+    fn test_matches_limit_order_book() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut core = Book::new();
+        let mut full = LimitOrderBook::new();
+
+        for id in 1..=2000u64 {
+            let order_type = if rng.gen_bool(0.5) {
+                OrderType::Bid
+            } else {
+                OrderType::Ask
+            };
+            let price = Decimal::from(rng.gen_range(95..=105));
+            let quantity = Decimal::from(rng.gen_range(1..=10));
+
+            if rng.gen_ratio(1, 5) && id > 1 {
+                let target = rng.gen_range(1..id);
+                assert_eq!(
+                    core.cancel(target).map(|order| order.id),
+                    full.cancel_order(target).map(|order| order.exchange_id)
+                );
+                continue;
+            }
+            let order = Order::new(
+                "BTC/USDT".to_string(),
+                id,
+                order_type,
+                quantity,
+                price,
+                Utc::now(),
+                Utc::now(),
+            );
+            let (_, fills) = full.place_order(order);
+            let (matches, _) = core.place_limit(id, order_type.into(), price, quantity);
+            let expected: Vec<_> = fills
+                .iter()
+                .map(|fill| Match {
+                    maker_id: fill.maker_id,
+                    taker_id: fill.taker_id,
+                    price: fill.price,
+                    quantity: fill.quantity,
+                })
+                .collect();
+            assert_eq!(matches, expected);
+        }
+
+        let ladder = full.ladder(usize::MAX);
+        assert_eq!(core.depth(Side::Bid, usize::MAX), ladder.bids);
+        assert_eq!(core.depth(Side::Ask, usize::MAX), ladder.asks);
+        assert_eq!(core.len(), full.orders.len());
+    }
+}
@@ -0,0 +1,337 @@
+//! Flat records for trades, book snapshots and candles, with CSV output and,
+//! behind the `parquet` feature, Parquet output. `heatmap` turns recorded
+//! snapshots into liquidity heatmaps.
+//!
+//! CSV columns keep decimals exact and timestamps in RFC 3339. The Parquet
+//! schemas use doubles and microsecond UTC timestamps so they load directly
+//! into pandas or polars.
+
+pub mod heatmap;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+
+use crate::limit_order_book::order::{Fill, LimitOrderBook, OrderType};
+use chrono::{DateTime, Duration, SecondsFormat, TimeZone, Utc};
+use rust_decimal::Decimal;
+use std::io::{self, Write};
+
+/// A row type that can be written as CSV.
+pub trait CsvRecord {
+    const HEADER: &'static [&'static str];
+
+    fn fields(&self) -> Vec<String>;
+}
+
+/// Writes a header line and one line per record.
+pub fn write_csv<W: Write, R: CsvRecord>(mut writer: W, records: &[R]) -> io::Result<()> {
+    writeln!(writer, "{}", R::HEADER.join(","))?;
+    for record in records {
+        let fields: Vec<String> = record.fields().iter().map(|field| escape(field)).collect();
+        writeln!(writer, "{}", fields.join(","))?;
+    }
+    writer.flush()
+}
+
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub(crate) fn timestamp(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+pub(crate) fn side(order_type: OrderType) -> &'static str {
+    match order_type {
+        OrderType::Bid => "buy",
+        OrderType::Ask => "sell",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TradeRecord {
+    pub time: DateTime<Utc>,
+    pub pair: String,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    /// Side of the aggressing (taker) order.
+    pub side: OrderType,
+    pub maker_id: u64,
+    pub taker_id: u64,
+}
+
+impl TradeRecord {
+    pub fn from_fill(
+        pair: impl Into<String>,
+        time: DateTime<Utc>,
+        taker_side: OrderType,
+        fill: &Fill,
+    ) -> Self {
+        Self {
+            time,
+            pair: pair.into(),
+            price: fill.price,
+            quantity: fill.quantity,
+            side: taker_side,
+            maker_id: fill.maker_id,
+            taker_id: fill.taker_id,
+        }
+    }
+}
+
+impl CsvRecord for TradeRecord {
+    const HEADER: &'static [&'static str] = &[
+        "time", "pair", "price", "quantity", "side", "maker_id", "taker_id",
+    ];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            timestamp(&self.time),
+            self.pair.clone(),
+            self.price.to_string(),
+            self.quantity.to_string(),
+            side(self.side).to_string(),
+            self.maker_id.to_string(),
+            self.taker_id.to_string(),
+        ]
+    }
+}
+
+/// One aggregated level of a book snapshot. `level` counts from zero at the
+/// best price on each side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelRecord {
+    pub time: DateTime<Utc>,
+    pub pair: String,
+    pub side: OrderType,
+    pub level: u32,
+    pub price: Decimal,
+    pub size: Decimal,
+}
+
+impl LevelRecord {
+    /// The top `depth` levels of each side of `book`, bids first.
+    pub fn from_book(
+        book: &LimitOrderBook,
+        pair: &str,
+        time: DateTime<Utc>,
+        depth: usize,
+    ) -> Vec<Self> {
+        let ladder = book.ladder(depth);
+        let side_records = |side: OrderType, levels: Vec<(Decimal, Decimal)>| {
+            levels
+                .into_iter()
+                .enumerate()
+                .map(move |(level, (price, size))| LevelRecord {
+                    time,
+                    pair: pair.to_string(),
+                    side,
+                    level: level as u32,
+                    price,
+                    size,
+                })
+        };
+        side_records(OrderType::Bid, ladder.bids)
+            .chain(side_records(OrderType::Ask, ladder.asks))
+            .collect()
+    }
+}
+
+impl CsvRecord for LevelRecord {
+    const HEADER: &'static [&'static str] = &["time", "pair", "side", "level", "price", "size"];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            timestamp(&self.time),
+            self.pair.clone(),
+            side(self.side).to_string(),
+            self.level.to_string(),
+            self.price.to_string(),
+            self.size.to_string(),
+        ]
+    }
+}
+
+/// OHLCV bar covering `[open_time, open_time + interval)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candle {
+    pub open_time: DateTime<Utc>,
+    pub pair: String,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+    pub trades: u64,
+}
+
+impl Candle {
+    /// Buckets `trades` into bars aligned to multiples of `interval` since
+    /// the Unix epoch. Trades must be in time order; each pair gets its own
+    /// bars and intervals without trades produce none.
+    pub fn aggregate(trades: &[TradeRecord], interval: Duration) -> Vec<Candle> {
+        let interval_ms = interval.num_milliseconds().max(1);
+        let mut candles: Vec<Candle> = Vec::new();
+
+        for trade in trades {
+            let millis = trade.time.timestamp_millis();
+            let open_time = Utc
+                .timestamp_millis_opt(millis - millis.rem_euclid(interval_ms))
+                .unwrap();
+            let candle = candles
+                .iter_mut()
+                .rev()
+                .find(|candle| candle.pair == trade.pair && candle.open_time == open_time);
+            match candle {
+                Some(candle) => {
+                    candle.high = candle.high.max(trade.price);
+                    candle.low = candle.low.min(trade.price);
+                    candle.close = trade.price;
+                    candle.volume += trade.quantity;
+                    candle.trades += 1;
+                }
+                None => candles.push(Candle {
+                    open_time,
+                    pair: trade.pair.clone(),
+                    open: trade.price,
+                    high: trade.price,
+                    low: trade.price,
+                    close: trade.price,
+                    volume: trade.quantity,
+                    trades: 1,
+                }),
+            }
+        }
+
+        candles
+    }
+}
+
+impl CsvRecord for Candle {
+    const HEADER: &'static [&'static str] = &[
+        "open_time",
+        "pair",
+        "open",
+        "high",
+        "low",
+        "close",
+        "volume",
+        "trades",
+    ];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            timestamp(&self.open_time),
+            self.pair.clone(),
+            self.open.to_string(),
+            self.high.to_string(),
+            self.low.to_string(),
+            self.close.to_string(),
+            self.volume.to_string(),
+            self.trades.to_string(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::Order;
+    use rust_decimal_macros::dec;
+
+    fn trade(secs: i64, price: Decimal, quantity: Decimal) -> TradeRecord {
+        TradeRecord {
+            time: Utc.timestamp_opt(1_700_000_000 + secs, 0).unwrap(),
+            pair: "BTC/USDT".to_string(),
+            price,
+            quantity,
+            side: OrderType::Bid,
+            maker_id: 1,
+            taker_id: 2,
+        }
+    }
+
+    #[test]
+    fn test_trades_csv() {
+        let mut out = Vec::new();
+        write_csv(&mut out, &[trade(0, dec!(100.5), dec!(2))]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "time,pair,price,quantity,side,maker_id,taker_id\n\
+             2023-11-14T22:13:20.000000Z,BTC/USDT,100.5,2,buy,1,2\n"
+        );
+        assert_eq!(escape("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+
+    #[test]
+    fn test_book_levels() {
+        let mut book = LimitOrderBook::new();
+        for (exchange_id, order_type, price) in [
+            (1, OrderType::Bid, dec!(99)),
+            (2, OrderType::Bid, dec!(98)),
+            (3, OrderType::Ask, dec!(101)),
+        ] {
+            book.add_order(Order::new(
+                "BTC/USDT".to_string(),
+                exchange_id,
+                order_type,
+                dec!(1),
+                price,
+                Utc::now(),
+                Utc::now(),
+            ));
+        }
+        let records = LevelRecord::from_book(&book, "BTC/USDT", Utc::now(), 10);
+        let rows: Vec<(OrderType, u32, Decimal)> = records
+            .iter()
+            .map(|record| (record.side, record.level, record.price))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (OrderType::Bid, 0, dec!(99)),
+                (OrderType::Bid, 1, dec!(98)),
+                (OrderType::Ask, 0, dec!(101))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_candles() {
+        let trades = [
+            trade(0, dec!(100), dec!(1)),
+            trade(20, dec!(104), dec!(2)),
+            trade(40, dec!(99), dec!(1)),
+            trade(50, dec!(101), dec!(3)),
+            trade(200, dec!(102), dec!(1)),
+        ];
+        let candles = Candle::aggregate(&trades, Duration::minutes(1));
+        assert_eq!(candles.len(), 3);
+        assert_eq!(
+            (
+                candles[0].open,
+                candles[0].high,
+                candles[0].low,
+                candles[0].close
+            ),
+            (dec!(100), dec!(104), dec!(100), dec!(104))
+        );
+        // 1_700_000_040 starts a new minute.
+        assert_eq!(
+            candles[1].open_time,
+            Utc.timestamp_opt(1_700_000_040, 0).unwrap()
+        );
+        assert_eq!(
+            (candles[1].open, candles[1].close, candles[1].volume),
+            (dec!(99), dec!(101), dec!(4))
+        );
+        assert_eq!(candles[1].trades, 2);
+        assert_eq!(
+            candles[2].open_time,
+            Utc.timestamp_opt(1_700_000_160, 0).unwrap()
+        );
+    }
+}
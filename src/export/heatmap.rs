@@ -0,0 +1,228 @@
+//! Liquidity heatmaps built from recorded `LevelRecord` snapshots.
+//!
+//! Each frame is one time bucket and holds the resting size in every price
+//! row, so `Heatmap::matrix` can be handed to a plotting library as is.
+
+use super::LevelRecord;
+use crate::limit_order_book::order::OrderType;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Resting size per price row during one time bucket.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HeatmapFrame {
+    /// Start of the bucket, which covers `[time, time + interval)`.
+    pub time: DateTime<Utc>,
+    /// Indexed like `Heatmap::prices`.
+    pub bids: Vec<Decimal>,
+    pub asks: Vec<Decimal>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Heatmap {
+    pub pair: String,
+    pub interval: Duration,
+    pub price_bucket: Decimal,
+    /// Lower bound of each price row, ascending.
+    pub prices: Vec<Decimal>,
+    /// One frame per bucket from the first snapshot to the last.
+    pub frames: Vec<HeatmapFrame>,
+}
+
+impl Heatmap {
+    /// Buckets the `pair`'s snapshots in `levels` by time and price.
+    ///
+    /// Records sharing a timestamp form one snapshot and must be in time
+    /// order. A frame holds the mean size over the snapshots taken in its
+    /// bucket; buckets without a snapshot repeat the previous frame, since
+    /// the book is only recorded when it changes. Time buckets are aligned
+    /// to multiples of `interval` since the Unix epoch and price rows to
+    /// multiples of `price_bucket`.
+    pub fn build(
+        levels: &[LevelRecord],
+        pair: &str,
+        interval: Duration,
+        price_bucket: Decimal,
+    ) -> Result<Heatmap, String> {
+        if price_bucket <= Decimal::ZERO {
+            return Err(format!("Invalid price bucket: {}", price_bucket));
+        }
+        if interval <= Duration::zero() {
+            return Err(format!("Invalid interval: {}", interval));
+        }
+        let row = |price: Decimal| (price / price_bucket).floor() * price_bucket;
+        let levels: Vec<&LevelRecord> = levels.iter().filter(|level| level.pair == pair).collect();
+        let prices: Vec<Decimal> = levels
+            .iter()
+            .map(|level| row(level.price))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        // Sums of each bucket's snapshots, and how many there were.
+        let interval_ms = interval.num_milliseconds().max(1);
+        let mut buckets: BTreeMap<DateTime<Utc>, (HeatmapFrame, u32)> = BTreeMap::new();
+        for snapshot in levels.chunk_by(|a, b| a.time == b.time) {
+            let millis = snapshot[0].time.timestamp_millis();
+            let time = Utc
+                .timestamp_millis_opt(millis - millis.rem_euclid(interval_ms))
+                .unwrap();
+            let (frame, count) = buckets.entry(time).or_insert_with(|| {
+                let empty = vec![Decimal::ZERO; prices.len()];
+                let frame = HeatmapFrame {
+                    time,
+                    bids: empty.clone(),
+                    asks: empty,
+                };
+                (frame, 0)
+            });
+            for level in snapshot {
+                let index = prices.binary_search(&row(level.price)).unwrap();
+                match level.side {
+                    OrderType::Bid => frame.bids[index] += level.size,
+                    OrderType::Ask => frame.asks[index] += level.size,
+                }
+            }
+            *count += 1;
+        }
+
+        let mut frames: Vec<HeatmapFrame> = Vec::new();
+        for (time, (mut frame, count)) in buckets {
+            if let Some(previous) = frames.last().cloned() {
+                let mut gap = previous.time + interval;
+                while gap < time {
+                    frames.push(HeatmapFrame {
+                        time: gap,
+                        ..previous.clone()
+                    });
+                    gap += interval;
+                }
+            }
+            let count = Decimal::from(count);
+            for size in frame.bids.iter_mut().chain(frame.asks.iter_mut()) {
+                *size /= count;
+            }
+            frames.push(frame);
+        }
+
+        Ok(Heatmap {
+            pair: pair.to_string(),
+            interval,
+            price_bucket,
+            prices,
+            frames,
+        })
+    }
+
+    /// Times of the frames, the matrix's column axis.
+    pub fn times(&self) -> Vec<DateTime<Utc>> {
+        self.frames.iter().map(|frame| frame.time).collect()
+    }
+
+    /// `side`'s sizes with one row per price and one column per frame.
+    pub fn matrix(&self, side: OrderType) -> Vec<Vec<Decimal>> {
+        (0..self.prices.len())
+            .map(|row| {
+                self.frames
+                    .iter()
+                    .map(|frame| match side {
+                        OrderType::Bid => frame.bids[row],
+                        OrderType::Ask => frame.asks[row],
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn level(secs: i64, side: OrderType, price: Decimal, size: Decimal) -> LevelRecord {
+        LevelRecord {
+            time: Utc.timestamp_opt(1_700_000_000 + secs, 0).unwrap(),
+            pair: "BTC/USDT".to_string(),
+            side,
+            level: 0,
+            price,
+            size,
+        }
+    }
+
+    #[test]
+    fn test_heatmap_buckets_time_and_price() {
+        let levels = [
+            level(0, OrderType::Bid, dec!(99.5), dec!(2)),
+            level(0, OrderType::Bid, dec!(99.2), dec!(1)),
+            level(0, OrderType::Ask, dec!(101), dec!(4)),
+            level(30, OrderType::Bid, dec!(99.5), dec!(6)),
+            level(30, OrderType::Ask, dec!(101), dec!(4)),
+            level(150, OrderType::Ask, dec!(100), dec!(1)),
+        ];
+        let heatmap = Heatmap::build(&levels, "BTC/USDT", Duration::minutes(1), dec!(1)).unwrap();
+
+        assert_eq!(heatmap.prices, vec![dec!(99), dec!(100), dec!(101)]);
+        assert_eq!(heatmap.frames.len(), 3);
+        assert_eq!(
+            heatmap.times(),
+            vec![
+                Utc.timestamp_opt(1_699_999_980, 0).unwrap(),
+                Utc.timestamp_opt(1_700_000_040, 0).unwrap(),
+                Utc.timestamp_opt(1_700_000_100, 0).unwrap(),
+            ]
+        );
+        // The first two snapshots share a bucket and are averaged.
+        assert_eq!(
+            heatmap.matrix(OrderType::Bid),
+            vec![
+                vec![dec!(4.5), dec!(4.5), dec!(0)],
+                vec![dec!(0), dec!(0), dec!(0)],
+                vec![dec!(0), dec!(0), dec!(0)],
+            ]
+        );
+        assert_eq!(heatmap.frames[2].asks, vec![dec!(0), dec!(1), dec!(0)]);
+
+        assert!(
+            Heatmap::build(&levels, "ETH/USDT", Duration::minutes(1), dec!(1))
+                .unwrap()
+                .frames
+                .is_empty()
+        );
+        assert!(Heatmap::build(&levels, "BTC/USDT", Duration::minutes(1), dec!(0)).is_err());
+    }
+
+    #[test]
+    fn test_fractional_rows_and_other_pairs() {
+        let mut other = level(10, OrderType::Bid, dec!(3000), dec!(9));
+        other.pair = "ETH/USDT".to_string();
+        let levels = [
+            level(0, OrderType::Bid, dec!(99.74), dec!(1)),
+            level(0, OrderType::Bid, dec!(99.76), dec!(2)),
+            other,
+            level(20, OrderType::Ask, dec!(100.25), dec!(3)),
+        ];
+        let heatmap =
+            Heatmap::build(&levels, "BTC/USDT", Duration::seconds(10), dec!(0.25)).unwrap();
+
+        assert_eq!(heatmap.prices, vec![dec!(99.50), dec!(99.75), dec!(100.25)]);
+        // The ETH snapshot leaves no frame of its own, and the empty bucket
+        // at 10s repeats the one before it.
+        assert_eq!(heatmap.frames.len(), 3);
+        assert_eq!(heatmap.frames[1].bids, heatmap.frames[0].bids);
+        assert_eq!(
+            heatmap.matrix(OrderType::Bid),
+            vec![
+                vec![dec!(1), dec!(1), dec!(0)],
+                vec![dec!(2), dec!(2), dec!(0)],
+                vec![dec!(0), dec!(0), dec!(0)],
+            ]
+        );
+        assert_eq!(heatmap.frames[2].asks, vec![dec!(0), dec!(0), dec!(3)]);
+
+        assert!(Heatmap::build(&levels, "BTC/USDT", Duration::zero(), dec!(1)).is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use super::{side, Candle, LevelRecord, TradeRecord};
+use chrono::{DateTime, Utc};
+use parquet::{
+    data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type},
+    errors::ParquetError,
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use std::{fs::File, path::Path, sync::Arc};
+
+pub const TRADE_SCHEMA: &str = "
+message trade {
+    required int64 time (TIMESTAMP(MICROS, true));
+    required binary pair (STRING);
+    required double price;
+    required double quantity;
+    required binary side (STRING);
+    required int64 maker_id;
+    required int64 taker_id;
+}";
+
+pub const LEVEL_SCHEMA: &str = "
+message level {
+    required int64 time (TIMESTAMP(MICROS, true));
+    required binary pair (STRING);
+    required binary side (STRING);
+    required int64 level;
+    required double price;
+    required double size;
+}";
+
+pub const CANDLE_SCHEMA: &str = "
+message candle {
+    required int64 open_time (TIMESTAMP(MICROS, true));
+    required binary pair (STRING);
+    required double open;
+    required double high;
+    required double low;
+    required double close;
+    required double volume;
+    required int64 trades;
+}";
+
+/// Column values in schema order.
+enum Column {
+    Int64(Vec<i64>),
+    Double(Vec<f64>),
+    Utf8(Vec<ByteArray>),
+}
+
+fn micros(time: &DateTime<Utc>) -> i64 {
+    time.timestamp_micros()
+}
+
+fn double(value: &Decimal) -> f64 {
+    value.to_f64().unwrap_or(f64::NAN)
+}
+
+fn utf8(value: &str) -> ByteArray {
+    ByteArray::from(value)
+}
+
+/// Writes `columns` as a single row group.
+fn write(path: impl AsRef<Path>, schema: &str, columns: Vec<Column>) -> Result<(), ParquetError> {
+    let schema = Arc::new(parse_message_type(schema)?);
+    let properties = Arc::new(WriterProperties::builder().build());
+    let mut writer = SerializedFileWriter::new(File::create(path)?, schema, properties)?;
+    let mut row_group = writer.next_row_group()?;
+    let mut columns = columns.into_iter();
+
+    while let Some(mut column_writer) = row_group.next_column()? {
+        let column = columns
+            .next()
+            .ok_or_else(|| ParquetError::General("Fewer columns than the schema".to_string()))?;
+        match column {
+            Column::Int64(values) => {
+                column_writer
+                    .typed::<Int64Type>()
+                    .write_batch(&values, None, None)?;
+            }
+            Column::Double(values) => {
+                column_writer
+                    .typed::<DoubleType>()
+                    .write_batch(&values, None, None)?;
+            }
+            Column::Utf8(values) => {
+                column_writer
+                    .typed::<ByteArrayType>()
+                    .write_batch(&values, None, None)?;
+            }
+        }
+        column_writer.close()?;
+    }
+
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+pub fn write_trades(path: impl AsRef<Path>, trades: &[TradeRecord]) -> Result<(), ParquetError> {
+    write(
+        path,
+        TRADE_SCHEMA,
+        vec![
+            Column::Int64(trades.iter().map(|trade| micros(&trade.time)).collect()),
+            Column::Utf8(trades.iter().map(|trade| utf8(&trade.pair)).collect()),
+            Column::Double(trades.iter().map(|trade| double(&trade.price)).collect()),
+            Column::Double(trades.iter().map(|trade| double(&trade.quantity)).collect()),
+            Column::Utf8(trades.iter().map(|trade| utf8(side(trade.side))).collect()),
+            Column::Int64(trades.iter().map(|trade| trade.maker_id as i64).collect()),
+            Column::Int64(trades.iter().map(|trade| trade.taker_id as i64).collect()),
+        ],
+    )
+}
+
+pub fn write_levels(path: impl AsRef<Path>, levels: &[LevelRecord]) -> Result<(), ParquetError> {
+    write(
+        path,
+        LEVEL_SCHEMA,
+        vec![
+            Column::Int64(levels.iter().map(|level| micros(&level.time)).collect()),
+            Column::Utf8(levels.iter().map(|level| utf8(&level.pair)).collect()),
+            Column::Utf8(levels.iter().map(|level| utf8(side(level.side))).collect()),
+            Column::Int64(levels.iter().map(|level| level.level as i64).collect()),
+            Column::Double(levels.iter().map(|level| double(&level.price)).collect()),
+            Column::Double(levels.iter().map(|level| double(&level.size)).collect()),
+        ],
+    )
+}
+
+pub fn write_candles(path: impl AsRef<Path>, candles: &[Candle]) -> Result<(), ParquetError> {
+    write(
+        path,
+        CANDLE_SCHEMA,
+        vec![
+            Column::Int64(
+                candles
+                    .iter()
+                    .map(|candle| micros(&candle.open_time))
+                    .collect(),
+            ),
+            Column::Utf8(candles.iter().map(|candle| utf8(&candle.pair)).collect()),
+            Column::Double(candles.iter().map(|candle| double(&candle.open)).collect()),
+            Column::Double(candles.iter().map(|candle| double(&candle.high)).collect()),
+            Column::Double(candles.iter().map(|candle| double(&candle.low)).collect()),
+            Column::Double(candles.iter().map(|candle| double(&candle.close)).collect()),
+            Column::Double(
+                candles
+                    .iter()
+                    .map(|candle| double(&candle.volume))
+                    .collect(),
+            ),
+            Column::Int64(candles.iter().map(|candle| candle.trades as i64).collect()),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::OrderType;
+    use parquet::{
+        file::reader::{FileReader, SerializedFileReader},
+        record::{Row, RowAccessor},
+    };
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_write_trades() {
+        let path =
+            std::env::temp_dir().join(format!("tradebot-trades-{}.parquet", std::process::id()));
+        let trade = TradeRecord {
+            time: Utc::now(),
+            pair: "BTC/USDT".to_string(),
+            price: dec!(100.5),
+            quantity: dec!(2),
+            side: OrderType::Ask,
+            maker_id: 1,
+            taker_id: 2,
+        };
+        write_trades(&path, &[trade.clone(), trade]).unwrap();
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.file_metadata().num_rows(), 2);
+        assert_eq!(metadata.file_metadata().schema_descr().num_columns(), 7);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_levels_and_candles() {
+        let time = Utc::now();
+        let levels_path =
+            std::env::temp_dir().join(format!("tradebot-levels-{}.parquet", std::process::id()));
+        let level = LevelRecord {
+            time,
+            pair: "BTC/USDT".to_string(),
+            side: OrderType::Bid,
+            level: 3,
+            price: dec!(99.25),
+            size: dec!(0.5),
+        };
+        write_levels(&levels_path, &[level]).unwrap();
+        let reader = SerializedFileReader::new(File::open(&levels_path).unwrap()).unwrap();
+        let rows: Vec<Row> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get_timestamp_micros(0).unwrap(), micros(&time));
+        assert_eq!(rows[0].get_string(2).unwrap(), "buy");
+        assert_eq!(rows[0].get_long(3).unwrap(), 3);
+        assert_eq!(rows[0].get_double(4).unwrap(), 99.25);
+        std::fs::remove_file(&levels_path).unwrap();
+
+        let candles_path =
+            std::env::temp_dir().join(format!("tradebot-candles-{}.parquet", std::process::id()));
+        let candle = Candle {
+            open_time: time,
+            pair: "BTC/USDT".to_string(),
+            open: dec!(100),
+            high: dec!(102),
+            low: dec!(99),
+            close: dec!(101),
+            volume: dec!(7.5),
+            trades: 4,
+        };
+        write_candles(&candles_path, &[candle]).unwrap();
+        let reader = SerializedFileReader::new(File::open(&candles_path).unwrap()).unwrap();
+        let schema = reader.metadata().file_metadata().schema_descr();
+        assert_eq!(schema.num_columns(), 8);
+        assert_eq!(schema.column(6).name(), "volume");
+        let row = reader.get_row_iter(None).unwrap().next().unwrap().unwrap();
+        assert_eq!(row.get_double(3).unwrap(), 102.0);
+        assert_eq!(row.get_double(6).unwrap(), 7.5);
+        assert_eq!(row.get_long(7).unwrap(), 4);
+        std::fs::remove_file(&candles_path).unwrap();
+    }
+}
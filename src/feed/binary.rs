@@ -0,0 +1,512 @@
+//! A fixed-width binary encoding of feed messages, in the style of SBE.
+//!
+//! Every message is an 8-byte header followed by a block whose length is
+//! fixed per template, all little-endian:
+//!
+//! | offset | header field   | type |
+//! |--------|----------------|------|
+//! | 0      | block length   | u16  |
+//! | 2      | template ID    | u16  |
+//! | 4      | schema ID      | u16  |
+//! | 6      | schema version | u16  |
+//!
+//! Both blocks start with the sequence number (u64), the time in
+//! nanoseconds since the Unix epoch (i64) and the pair as 16 bytes of
+//! zero-padded ASCII. Decimals are an i64 mantissa with a u8 scale, so
+//! `12.5` is mantissa 125, scale 1. A reader can take the sequence number
+//! and template straight from the bytes without decoding the rest.
+//!
+//! Both blocks end their fields with the message's `coalesced` count as a
+//! u32. It sits in what used to be padding, which older encoders zeroed,
+//! so their messages read back as coalescing nothing.
+
+use crate::{
+    feed::message::{BookDelta, FeedMessage, Trade},
+    limit_order_book::{diff::LevelChange, order::OrderType},
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::fmt;
+
+pub const SCHEMA_ID: u16 = 1;
+pub const SCHEMA_VERSION: u16 = 1;
+pub const HEADER_LEN: usize = 8;
+pub const SYMBOL_LEN: usize = 16;
+
+pub const BOOK_DELTA_TEMPLATE: u16 = 1;
+pub const TRADE_TEMPLATE: u16 = 2;
+/// Sequence, time, symbol.
+const COMMON_LEN: usize = 8 + 8 + SYMBOL_LEN;
+/// Price and size, their scales, side, action and the coalesced count.
+pub const BOOK_DELTA_BLOCK_LEN: usize = COMMON_LEN + 8 + 8 + 1 + 1 + 1 + 1 + 4;
+/// Maker and taker IDs, price and quantity, their scales, side, the
+/// coalesced count and a byte of padding.
+pub const TRADE_BLOCK_LEN: usize = COMMON_LEN + 8 + 8 + 8 + 8 + 1 + 1 + 1 + 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+    /// Fewer bytes than the header or block calls for.
+    Truncated {
+        needed: usize,
+        available: usize,
+    },
+    UnknownTemplate(u16),
+    UnsupportedSchema {
+        schema_id: u16,
+        version: u16,
+    },
+    /// The pair doesn't fit the symbol field or isn't ASCII.
+    InvalidSymbol(String),
+    /// The decimal's mantissa doesn't fit in an i64.
+    DecimalOutOfRange(Decimal),
+    /// The time is outside what i64 nanoseconds can hold.
+    TimeOutOfRange(DateTime<Utc>),
+    InvalidField(&'static str),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::Truncated { needed, available } => {
+                write!(f, "message needs {} bytes, {} available", needed, available)
+            }
+            CodecError::UnknownTemplate(template) => write!(f, "unknown template {}", template),
+            CodecError::UnsupportedSchema { schema_id, version } => {
+                write!(f, "unsupported schema {} version {}", schema_id, version)
+            }
+            CodecError::InvalidSymbol(symbol) => write!(f, "invalid symbol {:?}", symbol),
+            CodecError::DecimalOutOfRange(value) => {
+                write!(f, "decimal {} does not fit 64 bits", value)
+            }
+            CodecError::TimeOutOfRange(time) => write!(f, "time {} is out of range", time),
+            CodecError::InvalidField(field) => write!(f, "invalid {}", field),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+/// The fields every message starts with, read without decoding the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub block_length: u16,
+    pub template_id: u16,
+    pub sequence: u64,
+}
+
+impl Header {
+    /// Header plus block.
+    pub fn message_len(&self) -> usize {
+        HEADER_LEN + self.block_length as usize
+    }
+}
+
+/// Reads the header and sequence number at the start of `bytes`.
+pub fn peek(bytes: &[u8]) -> Result<Header, CodecError> {
+    check_len(bytes, HEADER_LEN + 8)?;
+    let schema_id = u16_at(bytes, 4);
+    let version = u16_at(bytes, 6);
+    if schema_id != SCHEMA_ID || version != SCHEMA_VERSION {
+        return Err(CodecError::UnsupportedSchema { schema_id, version });
+    }
+    Ok(Header {
+        block_length: u16_at(bytes, 0),
+        template_id: u16_at(bytes, 2),
+        sequence: u64::from_le_bytes(bytes[HEADER_LEN..HEADER_LEN + 8].try_into().unwrap()),
+    })
+}
+
+/// Appends `message` to `out`.
+pub fn encode(message: &FeedMessage, out: &mut Vec<u8>) -> Result<(), CodecError> {
+    let mut writer = Writer::new();
+    match message {
+        FeedMessage::BookDelta(delta) => {
+            writer.header(BOOK_DELTA_TEMPLATE, BOOK_DELTA_BLOCK_LEN);
+            writer.common(delta.sequence, delta.time, &delta.pair)?;
+            let (action, size) = match delta.change {
+                LevelChange::Added { size, .. } => (0, size),
+                LevelChange::Changed { size, .. } => (1, size),
+                LevelChange::Removed { .. } => (2, Decimal::ZERO),
+            };
+            let (price, price_scale) = split_decimal(delta.change.price())?;
+            let (size, size_scale) = split_decimal(size)?;
+            writer.i64(price);
+            writer.i64(size);
+            writer.bytes(&[price_scale, size_scale, side_code(delta.side), action]);
+            writer.bytes(&delta.coalesced.to_le_bytes());
+        }
+        FeedMessage::Trade(trade) => {
+            writer.header(TRADE_TEMPLATE, TRADE_BLOCK_LEN);
+            writer.common(trade.sequence, trade.time, &trade.pair)?;
+            let (price, price_scale) = split_decimal(trade.price)?;
+            let (quantity, quantity_scale) = split_decimal(trade.quantity)?;
+            writer.u64(trade.maker_id);
+            writer.u64(trade.taker_id);
+            writer.i64(price);
+            writer.i64(quantity);
+            writer.bytes(&[price_scale, quantity_scale, side_code(trade.side)]);
+            writer.bytes(&trade.coalesced.to_le_bytes());
+            writer.bytes(&[0]);
+        }
+    }
+    out.extend_from_slice(&writer.buf);
+    Ok(())
+}
+
+/// Decodes the message at the start of `bytes`, returning it with the
+/// number of bytes it took.
+pub fn decode(bytes: &[u8]) -> Result<(FeedMessage, usize), CodecError> {
+    let header = peek(bytes)?;
+    let expected = match header.template_id {
+        BOOK_DELTA_TEMPLATE => BOOK_DELTA_BLOCK_LEN,
+        TRADE_TEMPLATE => TRADE_BLOCK_LEN,
+        template => return Err(CodecError::UnknownTemplate(template)),
+    };
+    if header.block_length as usize != expected {
+        return Err(CodecError::InvalidField("block length"));
+    }
+    check_len(bytes, header.message_len())?;
+
+    let mut reader = Reader {
+        bytes: &bytes[HEADER_LEN..header.message_len()],
+        offset: 0,
+    };
+    let sequence = reader.u64();
+    let time = DateTime::from_timestamp_nanos(reader.i64());
+    let pair = reader.symbol()?;
+    let message = match header.template_id {
+        BOOK_DELTA_TEMPLATE => {
+            let (price, size) = (reader.i64(), reader.i64());
+            let [price_scale, size_scale, side, action] = reader.array();
+            let coalesced = u32::from_le_bytes(reader.array());
+            let price = join_decimal(price, price_scale)?;
+            let size = join_decimal(size, size_scale)?;
+            let change = match action {
+                0 => LevelChange::Added { price, size },
+                1 => LevelChange::Changed { price, size },
+                2 => LevelChange::Removed { price },
+                _ => return Err(CodecError::InvalidField("action")),
+            };
+            FeedMessage::BookDelta(BookDelta {
+                sequence,
+                time,
+                pair,
+                side: side_from_code(side)?,
+                change,
+                coalesced,
+            })
+        }
+        _ => {
+            let (maker_id, taker_id) = (reader.u64(), reader.u64());
+            let (price, quantity) = (reader.i64(), reader.i64());
+            let [price_scale, quantity_scale, side] = reader.array();
+            let coalesced = u32::from_le_bytes(reader.array());
+            FeedMessage::Trade(Trade {
+                sequence,
+                time,
+                pair,
+                maker_id,
+                taker_id,
+                side: side_from_code(side)?,
+                price: join_decimal(price, price_scale)?,
+                quantity: join_decimal(quantity, quantity_scale)?,
+                coalesced,
+            })
+        }
+    };
+    Ok((message, header.message_len()))
+}
+
+/// Decodes every message in `bytes`, which must end on a message boundary.
+pub fn decode_all(mut bytes: &[u8]) -> Result<Vec<FeedMessage>, CodecError> {
+    let mut messages = Vec::new();
+    while !bytes.is_empty() {
+        let (message, len) = decode(bytes)?;
+        messages.push(message);
+        bytes = &bytes[len..];
+    }
+    Ok(messages)
+}
+
+fn check_len(bytes: &[u8], needed: usize) -> Result<(), CodecError> {
+    if bytes.len() < needed {
+        return Err(CodecError::Truncated {
+            needed,
+            available: bytes.len(),
+        });
+    }
+    Ok(())
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn split_decimal(value: Decimal) -> Result<(i64, u8), CodecError> {
+    let mantissa =
+        i64::try_from(value.mantissa()).map_err(|_| CodecError::DecimalOutOfRange(value))?;
+    Ok((mantissa, value.scale() as u8))
+}
+
+fn join_decimal(mantissa: i64, scale: u8) -> Result<Decimal, CodecError> {
+    Decimal::try_from_i128_with_scale(mantissa as i128, scale as u32)
+        .map_err(|_| CodecError::InvalidField("decimal scale"))
+}
+
+fn side_code(side: OrderType) -> u8 {
+    match side {
+        OrderType::Bid => 0,
+        OrderType::Ask => 1,
+    }
+}
+
+fn side_from_code(code: u8) -> Result<OrderType, CodecError> {
+    match code {
+        0 => Ok(OrderType::Bid),
+        1 => Ok(OrderType::Ask),
+        _ => Err(CodecError::InvalidField("side")),
+    }
+}
+
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn new() -> Self {
+        Self {
+            buf: Vec::with_capacity(HEADER_LEN + TRADE_BLOCK_LEN),
+        }
+    }
+
+    fn header(&mut self, template_id: u16, block_length: usize) {
+        for field in [block_length as u16, template_id, SCHEMA_ID, SCHEMA_VERSION] {
+            self.bytes(&field.to_le_bytes());
+        }
+    }
+
+    fn common(&mut self, sequence: u64, time: DateTime<Utc>, pair: &str) -> Result<(), CodecError> {
+        if pair.len() > SYMBOL_LEN || !pair.is_ascii() {
+            return Err(CodecError::InvalidSymbol(pair.to_string()));
+        }
+        let nanos = time
+            .timestamp_nanos_opt()
+            .ok_or(CodecError::TimeOutOfRange(time))?;
+        self.u64(sequence);
+        self.i64(nanos);
+        let mut symbol = [0; SYMBOL_LEN];
+        symbol[..pair.len()].copy_from_slice(pair.as_bytes());
+        self.bytes(&symbol);
+        Ok(())
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn i64(&mut self, value: i64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+}
+
+/// Reads fields in order from a block already checked to be long enough.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn array<const N: usize>(&mut self) -> [u8; N] {
+        let array = self.bytes[self.offset..self.offset + N].try_into().unwrap();
+        self.offset += N;
+        array
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.array())
+    }
+
+    fn i64(&mut self) -> i64 {
+        i64::from_le_bytes(self.array())
+    }
+
+    fn symbol(&mut self) -> Result<String, CodecError> {
+        let symbol: [u8; SYMBOL_LEN] = self.array();
+        let len = symbol
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(SYMBOL_LEN);
+        std::str::from_utf8(&symbol[..len])
+            .ok()
+            .filter(|symbol| symbol.is_ascii())
+            .map(str::to_string)
+            .ok_or(CodecError::InvalidField("symbol"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    fn messages() -> Vec<FeedMessage> {
+        let time =
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + chrono::Duration::nanoseconds(123);
+        vec![
+            FeedMessage::BookDelta(BookDelta {
+                sequence: 1,
+                time,
+                pair: "BTC/USDT".to_string(),
+                side: OrderType::Bid,
+                change: LevelChange::Added {
+                    price: dec!(100.25),
+                    size: dec!(0.001),
+                },
+                coalesced: 0,
+            }),
+            FeedMessage::BookDelta(BookDelta {
+                sequence: 2,
+                time,
+                pair: "BTC/USDT".to_string(),
+                side: OrderType::Ask,
+                change: LevelChange::Removed { price: dec!(101) },
+                coalesced: 4,
+            }),
+            FeedMessage::Trade(Trade {
+                sequence: 3,
+                time,
+                pair: "BTC/USDT".to_string(),
+                maker_id: 7,
+                taker_id: u64::MAX,
+                side: OrderType::Ask,
+                price: dec!(-3.5),
+                quantity: dec!(12),
+                coalesced: 2,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut bytes = Vec::new();
+        for message in &messages() {
+            encode(message, &mut bytes).unwrap();
+        }
+        assert_eq!(
+            bytes.len(),
+            2 * (HEADER_LEN + BOOK_DELTA_BLOCK_LEN) + HEADER_LEN + TRADE_BLOCK_LEN
+        );
+        assert_eq!(decode_all(&bytes).unwrap(), messages());
+
+        let trade = &bytes[2 * (HEADER_LEN + BOOK_DELTA_BLOCK_LEN)..];
+        let header = peek(trade).unwrap();
+        assert_eq!((header.template_id, header.sequence), (TRADE_TEMPLATE, 3));
+        assert_eq!(header.message_len(), trade.len());
+    }
+
+    #[test]
+    fn test_rejects_bad_input() {
+        let mut bytes = Vec::new();
+        encode(&messages()[0], &mut bytes).unwrap();
+        assert!(matches!(
+            decode(&bytes[..20]),
+            Err(CodecError::Truncated { needed: 64, .. })
+        ));
+        let mut unknown = bytes.clone();
+        unknown[2] = 9;
+        assert_eq!(decode(&unknown), Err(CodecError::UnknownTemplate(9)));
+        let mut side = bytes.clone();
+        side[HEADER_LEN + COMMON_LEN + 18] = 5;
+        assert_eq!(decode(&side), Err(CodecError::InvalidField("side")));
+
+        let FeedMessage::BookDelta(mut delta) = messages()[0].clone() else {
+            unreachable!()
+        };
+        delta.pair = "A-VERY-LONG/SYMBOL".to_string();
+        assert!(matches!(
+            encode(&FeedMessage::BookDelta(delta), &mut Vec::new()),
+            Err(CodecError::InvalidSymbol(_))
+        ));
+        let trade = Trade {
+            quantity: Decimal::MAX,
+            ..match messages()[2].clone() {
+                FeedMessage::Trade(trade) => trade,
+                _ => unreachable!(),
+            }
+        };
+        assert!(matches!(
+            encode(&FeedMessage::Trade(trade), &mut Vec::new()),
+            Err(CodecError::DecimalOutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_bad_headers_and_fields() {
+        let mut bytes = Vec::new();
+        encode(&messages()[0], &mut bytes).unwrap();
+        let mut version = bytes.clone();
+        version[6] = 2;
+        assert_eq!(
+            peek(&version),
+            Err(CodecError::UnsupportedSchema {
+                schema_id: SCHEMA_ID,
+                version: 2
+            })
+        );
+        let mut block = bytes.clone();
+        block[0] += 1;
+        assert_eq!(
+            decode(&block),
+            Err(CodecError::InvalidField("block length"))
+        );
+        let mut action = bytes.clone();
+        action[HEADER_LEN + COMMON_LEN + 19] = 3;
+        assert_eq!(decode(&action), Err(CodecError::InvalidField("action")));
+        let mut symbol = bytes.clone();
+        symbol[HEADER_LEN + 16] = 0xff;
+        assert_eq!(decode(&symbol), Err(CodecError::InvalidField("symbol")));
+
+        // A trailing partial message fails the whole buffer.
+        let mut partial = bytes.clone();
+        partial.extend_from_slice(&bytes[..HEADER_LEN + 8]);
+        assert!(matches!(
+            decode_all(&partial),
+            Err(CodecError::Truncated { available: 16, .. })
+        ));
+        assert_eq!(
+            CodecError::UnknownTemplate(9).to_string(),
+            "unknown template 9"
+        );
+    }
+
+    #[test]
+    fn test_zeroed_padding_and_full_symbols() {
+        let mut bytes = Vec::new();
+        encode(&messages()[1], &mut bytes).unwrap();
+        // An older encoder's zeroed padding reads back as nothing coalesced.
+        let offset = HEADER_LEN + COMMON_LEN + 20;
+        bytes[offset..offset + 4].fill(0);
+        let (message, len) = decode(&bytes).unwrap();
+        assert_eq!(len, bytes.len());
+        assert_eq!(message.coalesced(), 0);
+        assert_eq!(message.first_sequence(), 2);
+
+        let FeedMessage::BookDelta(mut delta) = messages()[0].clone() else {
+            unreachable!()
+        };
+        delta.pair = "ABCDEFGH/IJKLMNO".to_string();
+        delta.change = LevelChange::Changed {
+            price: dec!(0.00000001),
+            size: dec!(-7),
+        };
+        let message = FeedMessage::BookDelta(delta);
+        let mut bytes = Vec::new();
+        encode(&message, &mut bytes).unwrap();
+        assert_eq!(decode_all(&bytes).unwrap(), vec![message]);
+    }
+}
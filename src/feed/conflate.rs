@@ -0,0 +1,221 @@
+//! Conflation for slow consumers.
+//!
+//! A `Conflator` holds a subscriber's messages for an interval and then
+//! sends only the latest delta of each level changed in it, along with
+//! every trade. A level both added and removed within the interval isn't
+//! sent at all. The levels a consumer ends up with are the same as with
+//! the full feed; what it loses is the intermediate states.
+//!
+//! Conflated messages keep their original sequence numbers. Each one also
+//! counts, in `coalesced`, the messages dropped since the one sent before
+//! it, so the sequence numbers a subscriber receives still cover the whole
+//! feed and a `Replica` can tell a lost datagram from a coalesced delta.
+
+use crate::{
+    feed::message::{BookDelta, FeedMessage},
+    limit_order_book::{diff::LevelChange, order::OrderType},
+};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone)]
+pub struct Conflator {
+    interval: Duration,
+    /// When the oldest pending message was pushed.
+    window_start: Option<DateTime<Utc>>,
+    pending: Vec<FeedMessage>,
+    /// The first sequence number not yet accounted for by a message sent.
+    next_sequence: Option<u64>,
+}
+
+impl Conflator {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            window_start: None,
+            pending: Vec::new(),
+            next_sequence: None,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Adds `messages` published at `time`, returning the conflated
+    /// messages once the interval since the first pending one has passed.
+    pub fn push(&mut self, messages: &[FeedMessage], time: DateTime<Utc>) -> Vec<FeedMessage> {
+        if !messages.is_empty() && self.window_start.is_none() {
+            self.window_start = Some(time);
+        }
+        self.pending.extend(messages.iter().cloned());
+        match self.window_start {
+            Some(start) if time - start >= self.interval => self.flush(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The pending messages, conflated, in sequence order.
+    pub fn flush(&mut self) -> Vec<FeedMessage> {
+        self.window_start = None;
+        let Some(first) = self.pending.first().map(FeedMessage::sequence) else {
+            return Vec::new();
+        };
+        let level = |delta: &BookDelta| (delta.pair.clone(), delta.side, delta.change.price());
+        let mut seen: HashSet<(String, OrderType, Decimal)> = HashSet::new();
+        // Levels that didn't exist before the window.
+        let mut added = HashSet::new();
+        for message in &self.pending {
+            if let FeedMessage::BookDelta(delta) = message {
+                if seen.insert(level(delta)) && matches!(delta.change, LevelChange::Added { .. }) {
+                    added.insert(level(delta));
+                }
+            }
+        }
+        seen.clear();
+        let mut messages: Vec<FeedMessage> = self
+            .pending
+            .drain(..)
+            .rev()
+            .filter(|message| match message {
+                FeedMessage::BookDelta(delta) => {
+                    let level = level(delta);
+                    let removed = matches!(delta.change, LevelChange::Removed { .. });
+                    seen.insert(level.clone()) && !(removed && added.contains(&level))
+                }
+                FeedMessage::Trade(_) => true,
+            })
+            .collect();
+        messages.reverse();
+
+        // A count too large for the field leaves a gap the consumer
+        // recovers from like any other.
+        let mut next = self.next_sequence.unwrap_or(first);
+        for message in &mut messages {
+            let coalesced = message.sequence().saturating_sub(next);
+            message.set_coalesced(u32::try_from(coalesced).unwrap_or(u32::MAX));
+            next = message.sequence() + 1;
+        }
+        self.next_sequence = Some(next);
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        feed::message::Sequencer,
+        limit_order_book::{diff::BookDiff, order::Fill},
+    };
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_conflation() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 2, 9, 30, 0).unwrap();
+        let mut sequencer = Sequencer::new();
+        let mut conflator = Conflator::new(Duration::milliseconds(100));
+        let bid = |change| BookDiff {
+            bids: vec![change],
+            asks: vec![],
+        };
+
+        let added = sequencer.book_diff(
+            "BTC/USDT",
+            start,
+            &bid(LevelChange::Added {
+                price: dec!(99),
+                size: dec!(1),
+            }),
+        );
+        assert!(conflator.push(&added, start).is_empty());
+        let trade = sequencer.fills(
+            "BTC/USDT",
+            start,
+            OrderType::Ask,
+            &[Fill {
+                price: dec!(99),
+                quantity: dec!(1),
+                ..Fill::default()
+            }],
+        );
+        let later = start + Duration::milliseconds(50);
+        assert!(conflator.push(&trade, later).is_empty());
+        let changed = sequencer.book_diff(
+            "BTC/USDT",
+            later,
+            &bid(LevelChange::Changed {
+                price: dec!(99),
+                size: dec!(3),
+            }),
+        );
+        assert!(conflator.push(&changed, later).is_empty());
+        assert_eq!(conflator.pending(), 3);
+
+        let other = sequencer.book_diff(
+            "ETH/USDT",
+            later,
+            &bid(LevelChange::Removed { price: dec!(99) }),
+        );
+        let flushed = conflator.push(&other, start + Duration::milliseconds(100));
+        let sequences: Vec<u64> = flushed.iter().map(FeedMessage::sequence).collect();
+        assert_eq!(sequences, vec![2, 3, 4]);
+        let firsts: Vec<u64> = flushed.iter().map(FeedMessage::first_sequence).collect();
+        assert_eq!(firsts, vec![1, 3, 4]);
+        assert_eq!(flushed[1], changed[0]);
+        assert_eq!(conflator.pending(), 0);
+        assert!(conflator.flush().is_empty());
+    }
+
+    #[test]
+    fn test_drops_levels_added_and_removed() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 2, 9, 30, 0).unwrap();
+        let mut sequencer = Sequencer::new();
+        let mut conflator = Conflator::new(Duration::milliseconds(100));
+        let ask = |change| BookDiff {
+            bids: vec![],
+            asks: vec![change],
+        };
+        let added = LevelChange::Added {
+            price: dec!(101),
+            size: dec!(1),
+        };
+        let removed = LevelChange::Removed { price: dec!(101) };
+
+        let mut messages = sequencer.book_diff("BTC/USDT", start, &ask(added));
+        messages.extend(sequencer.book_diff("BTC/USDT", start, &ask(removed)));
+        assert!(conflator.push(&messages, start).is_empty());
+        assert!(conflator.flush().is_empty());
+
+        // A level that existed before the window still has its removal sent.
+        let mut messages = sequencer.book_diff(
+            "BTC/USDT",
+            start,
+            &ask(LevelChange::Changed {
+                price: dec!(102),
+                size: dec!(2),
+            }),
+        );
+        messages.extend(sequencer.book_diff(
+            "BTC/USDT",
+            start,
+            &ask(LevelChange::Removed { price: dec!(102) }),
+        ));
+        assert!(conflator.push(&messages, start).is_empty());
+        let flushed = conflator.flush();
+        assert_eq!(flushed.len(), 1);
+        assert_eq!(flushed[0].sequence(), 4);
+        // It also accounts for the window dropped entirely.
+        assert_eq!(flushed[0].first_sequence(), 1);
+
+        let next = sequencer.book_diff("BTC/USDT", start, &ask(added));
+        assert!(conflator.push(&next, start).is_empty());
+        assert_eq!(conflator.flush()[0].first_sequence(), 5);
+    }
+}
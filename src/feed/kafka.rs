@@ -0,0 +1,189 @@
+//! Trades and book deltas produced to Kafka, for data pipelines built on it.
+//!
+//! Each message is the JSON the other feeds carry, produced to the trades or
+//! the book topic and keyed by its pair. Kafka sends equal keys to the same
+//! partition, so a consumer sees each pair's messages in sequence order,
+//! however many partitions the topics have.
+//!
+//! Sends are queued and delivered by a background thread. Failed deliveries
+//! are logged and counted; `flush` before exiting so queued messages aren't
+//! lost.
+
+use crate::feed::message::FeedMessage;
+use rdkafka::{
+    config::ClientConfig,
+    error::{KafkaError, KafkaResult, RDKafkaErrorCode},
+    producer::{BaseRecord, DeliveryResult, Producer, ProducerContext, ThreadedProducer},
+    ClientContext, Message,
+};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tracing::warn;
+
+/// The topics each kind of message goes to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topics {
+    pub trades: String,
+    pub book: String,
+}
+
+impl Default for Topics {
+    fn default() -> Self {
+        Self {
+            trades: "tradebot.trades".to_string(),
+            book: "tradebot.book".to_string(),
+        }
+    }
+}
+
+impl Topics {
+    pub fn topic(&self, message: &FeedMessage) -> &str {
+        match message {
+            FeedMessage::BookDelta(_) => &self.book,
+            FeedMessage::Trade(_) => &self.trades,
+        }
+    }
+}
+
+/// Counts the messages Kafka failed to take.
+#[derive(Default)]
+pub struct Deliveries {
+    failed: AtomicU64,
+}
+
+impl ClientContext for Deliveries {}
+
+impl ProducerContext for Deliveries {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: ()) {
+        if let Err((err, message)) = result {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+            warn!(%err, topic = message.topic(), "failed to deliver feed message");
+        }
+    }
+}
+
+pub struct KafkaPublisher {
+    producer: ThreadedProducer<Deliveries>,
+    topics: Topics,
+}
+
+impl KafkaPublisher {
+    /// Connects to `brokers`, e.g. `localhost:9092`, with librdkafka's
+    /// defaults otherwise.
+    pub fn connect(brokers: &str, topics: Topics) -> KafkaResult<Self> {
+        Self::with_config(
+            ClientConfig::new().set("bootstrap.servers", brokers),
+            topics,
+        )
+    }
+
+    /// Connects with `config`, for settings such as `acks`, `linger.ms` or
+    /// authentication.
+    pub fn with_config(config: &ClientConfig, topics: Topics) -> KafkaResult<Self> {
+        Ok(Self {
+            producer: config.create_with_context(Deliveries::default())?,
+            topics,
+        })
+    }
+
+    pub fn topics(&self) -> &Topics {
+        &self.topics
+    }
+
+    /// Queues `messages` in order. While the producer's queue is full it
+    /// waits for deliveries to make room.
+    pub fn publish(&self, messages: &[FeedMessage]) -> KafkaResult<()> {
+        for message in messages {
+            let json = serde_json::to_string(message).expect("feed messages serialize");
+            let mut record = BaseRecord::to(self.topics.topic(message))
+                .key(message.pair())
+                .payload(&json);
+            loop {
+                match self.producer.send(record) {
+                    Ok(()) => break,
+                    Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), unsent)) => {
+                        record = unsent;
+                        self.producer.poll(Duration::from_millis(100));
+                    }
+                    Err((err, _)) => return Err(err),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for every queued message to be delivered.
+    pub fn flush(&self, timeout: Duration) -> KafkaResult<()> {
+        self.producer.flush(timeout)
+    }
+
+    /// Messages Kafka has failed to take so far.
+    pub fn failed(&self) -> u64 {
+        self.producer.context().failed.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        feed::message::Sequencer,
+        limit_order_book::{
+            diff::{BookDiff, LevelChange},
+            order::{Fill, OrderType},
+        },
+    };
+    use chrono::{TimeZone, Utc};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_topics() {
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let fill = Fill {
+            maker_id: 1,
+            taker_id: 2,
+            price: dec!(101),
+            quantity: dec!(1),
+            ..Fill::default()
+        };
+        let trades = Sequencer::new().fills("BTC/USDT", time, OrderType::Bid, &[fill]);
+        let topics = Topics {
+            trades: "trades".to_string(),
+            ..Topics::default()
+        };
+        assert_eq!(topics.topic(&trades[0]), "trades");
+        assert_eq!(trades[0].pair(), "BTC/USDT");
+    }
+
+    #[test]
+    fn test_producer_setup() {
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let diff = BookDiff {
+            bids: Vec::new(),
+            asks: vec![LevelChange::Added {
+                price: dec!(102),
+                size: dec!(3),
+            }],
+        };
+        let deltas = Sequencer::new().book_diff("ETH/USDT", time, &diff);
+        assert_eq!(Topics::default().topic(&deltas[0]), "tradebot.book");
+
+        // Creating a producer doesn't wait for the brokers, so nothing here
+        // needs one running.
+        let publisher = KafkaPublisher::connect("127.0.0.1:9", Topics::default()).unwrap();
+        assert_eq!(publisher.topics(), &Topics::default());
+        publisher.publish(&[]).unwrap();
+        publisher.flush(Duration::ZERO).unwrap();
+        assert_eq!(publisher.failed(), 0);
+
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", "127.0.0.1:9")
+            .set("acks", "some");
+        assert!(KafkaPublisher::with_config(&config, Topics::default()).is_err());
+    }
+}
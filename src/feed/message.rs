@@ -0,0 +1,228 @@
+use crate::limit_order_book::{
+    diff::{BookDiff, LevelChange},
+    order::{Fill, OrderType},
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// A change to one aggregated level of a book.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookDelta {
+    pub sequence: u64,
+    pub time: DateTime<Utc>,
+    pub pair: String,
+    pub side: OrderType,
+    pub change: LevelChange,
+    /// Messages before this one that conflation folded into it; see
+    /// `FeedMessage::first_sequence`.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub coalesced: u32,
+}
+
+/// One fill, from the taker's side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trade {
+    pub sequence: u64,
+    pub time: DateTime<Utc>,
+    pub pair: String,
+    pub maker_id: u64,
+    pub taker_id: u64,
+    /// The side of the order that took liquidity.
+    pub side: OrderType,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub coalesced: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeedMessage {
+    BookDelta(BookDelta),
+    Trade(Trade),
+}
+
+impl FeedMessage {
+    pub fn sequence(&self) -> u64 {
+        match self {
+            FeedMessage::BookDelta(delta) => delta.sequence,
+            FeedMessage::Trade(trade) => trade.sequence,
+        }
+    }
+
+    /// The first sequence number this message accounts for. It's the
+    /// message's own unless a conflated subscription coalesced the ones
+    /// before it away, so a consumer can tell those from a gap.
+    pub fn first_sequence(&self) -> u64 {
+        self.sequence().saturating_sub(self.coalesced() as u64)
+    }
+
+    pub fn coalesced(&self) -> u32 {
+        match self {
+            FeedMessage::BookDelta(delta) => delta.coalesced,
+            FeedMessage::Trade(trade) => trade.coalesced,
+        }
+    }
+
+    pub fn set_coalesced(&mut self, coalesced: u32) {
+        match self {
+            FeedMessage::BookDelta(delta) => delta.coalesced = coalesced,
+            FeedMessage::Trade(trade) => trade.coalesced = coalesced,
+        }
+    }
+
+    pub fn pair(&self) -> &str {
+        match self {
+            FeedMessage::BookDelta(delta) => &delta.pair,
+            FeedMessage::Trade(trade) => &trade.pair,
+        }
+    }
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+/// Numbers the messages of one feed, so consumers can spot gaps. The first
+/// message is 1.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sequencer {
+    last: u64,
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The sequence number of the last message handed out; 0 before any.
+    pub fn last(&self) -> u64 {
+        self.last
+    }
+
+    /// One delta per level in `diff`, bids first.
+    pub fn book_diff(
+        &mut self,
+        pair: &str,
+        time: DateTime<Utc>,
+        diff: &BookDiff,
+    ) -> Vec<FeedMessage> {
+        let bids = diff.bids.iter().map(|change| (OrderType::Bid, change));
+        let asks = diff.asks.iter().map(|change| (OrderType::Ask, change));
+        bids.chain(asks)
+            .map(|(side, change)| {
+                FeedMessage::BookDelta(BookDelta {
+                    sequence: self.next(),
+                    time,
+                    pair: pair.to_string(),
+                    side,
+                    change: *change,
+                    coalesced: 0,
+                })
+            })
+            .collect()
+    }
+
+    /// One trade per fill of a taker on `side`.
+    pub fn fills(
+        &mut self,
+        pair: &str,
+        time: DateTime<Utc>,
+        side: OrderType,
+        fills: &[Fill],
+    ) -> Vec<FeedMessage> {
+        fills
+            .iter()
+            .map(|fill| {
+                FeedMessage::Trade(Trade {
+                    sequence: self.next(),
+                    time,
+                    pair: pair.to_string(),
+                    maker_id: fill.maker_id,
+                    taker_id: fill.taker_id,
+                    side,
+                    price: fill.price,
+                    quantity: fill.quantity,
+                    coalesced: 0,
+                })
+            })
+            .collect()
+    }
+
+    fn next(&mut self) -> u64 {
+        self.last += 1;
+        self.last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_sequenced_messages() {
+        let mut sequencer = Sequencer::new();
+        let diff = BookDiff {
+            bids: vec![LevelChange::Removed { price: dec!(99) }],
+            asks: vec![LevelChange::Added {
+                price: dec!(101),
+                size: dec!(2),
+            }],
+        };
+        let time = Utc::now();
+        let deltas = sequencer.book_diff("BTC/USDT", time, &diff);
+        let fill = Fill {
+            maker_id: 1,
+            taker_id: 2,
+            price: dec!(101),
+            quantity: dec!(1),
+            ..Fill::default()
+        };
+        let trades = sequencer.fills("BTC/USDT", time, OrderType::Bid, &[fill]);
+        let sequences: Vec<u64> = deltas
+            .iter()
+            .chain(&trades)
+            .map(FeedMessage::sequence)
+            .collect();
+        assert_eq!(sequences, vec![1, 2, 3]);
+        assert_eq!(sequencer.last(), 3);
+
+        let json = serde_json::to_string(&trades[0]).unwrap();
+        assert!(json.starts_with(r#"{"type":"trade","sequence":3,"#));
+        assert_eq!(
+            serde_json::from_str::<FeedMessage>(&json).unwrap(),
+            trades[0]
+        );
+    }
+
+    #[test]
+    fn test_coalesced_sequences() {
+        let mut sequencer = Sequencer::new();
+        let diff = BookDiff {
+            bids: vec![LevelChange::Added {
+                price: dec!(99),
+                size: dec!(1),
+            }],
+            asks: vec![],
+        };
+        let mut delta = sequencer.book_diff("ETH/USDT", Utc::now(), &diff).remove(0);
+        assert_eq!(delta.pair(), "ETH/USDT");
+        assert_eq!(delta.first_sequence(), 1);
+        // Coalesced messages aren't serialized until there are some.
+        let json = serde_json::to_string(&delta).unwrap();
+        assert!(!json.contains("coalesced"));
+
+        delta.set_coalesced(4);
+        assert_eq!(delta.coalesced(), 4);
+        assert_eq!(delta.first_sequence(), 0);
+        let json = serde_json::to_string(&delta).unwrap();
+        assert!(json.contains(r#""coalesced":4"#));
+        assert_eq!(serde_json::from_str::<FeedMessage>(&json).unwrap(), delta);
+        assert!(sequencer
+            .fills("ETH/USDT", Utc::now(), OrderType::Ask, &[])
+            .is_empty());
+        assert_eq!(sequencer.last(), 1);
+    }
+}
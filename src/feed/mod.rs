@@ -0,0 +1,13 @@
+//! Market data published from the engine's books: sequenced book deltas and
+//! trades, as JSON or in a compact binary encoding, published over UDP with
+//! a TCP service for snapshots and replays, or to Redis or Kafka.
+
+pub mod binary;
+pub mod conflate;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod message;
+pub mod recovery;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod udp;
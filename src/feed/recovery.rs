@@ -0,0 +1,637 @@
+//! Late joins and gap recovery for the UDP feed.
+//!
+//! The publisher keeps each book's published levels and its most recent
+//! messages in a `FeedHistory`. A `RecoveryServer` serves them over TCP, one
+//! JSON request and response per line:
+//!
+//! ```text
+//! > {"type":"snapshot","pair":"BTC/USDT"}
+//! < {"type":"snapshot","sequence":42,"pair":"BTC/USDT","bids":[["99","1"]],"asks":[]}
+//! > {"type":"replay","from":40,"to":42}
+//! < {"type":"replay","messages":[...]}
+//! ```
+//!
+//! A consumer buffers what it receives on the feed while it fetches a
+//! snapshot, then applies the buffered messages after the snapshot's
+//! sequence number; see `Replica`.
+
+use crate::{
+    feed::message::{FeedMessage, Sequencer},
+    limit_order_book::{
+        diff::{BookDiff, LevelChange},
+        order::{Fill, Limit, LimitOrderBook, OrderType},
+    },
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, VecDeque},
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    rc::Rc,
+    sync::{Arc, Mutex},
+    thread,
+};
+use tracing::{debug, warn};
+
+/// Aggregated levels of one book, price to size.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Levels {
+    pub bids: BTreeMap<Decimal, Decimal>,
+    pub asks: BTreeMap<Decimal, Decimal>,
+}
+
+impl Levels {
+    pub fn from_book(book: &LimitOrderBook) -> Self {
+        let side = |levels: &BTreeMap<Decimal, Rc<RefCell<Limit>>>| {
+            levels
+                .iter()
+                .map(|(price, limit)| (*price, limit.borrow().size))
+                .collect()
+        };
+        Self {
+            bids: side(&book.bids),
+            asks: side(&book.asks),
+        }
+    }
+
+    /// The changes that turn these levels into `other`.
+    pub fn diff(&self, other: &Levels) -> BookDiff {
+        BookDiff {
+            bids: side_diff(&self.bids, &other.bids),
+            asks: side_diff(&self.asks, &other.asks),
+        }
+    }
+
+    pub fn apply(&mut self, side: OrderType, change: &LevelChange) {
+        let levels = match side {
+            OrderType::Bid => &mut self.bids,
+            OrderType::Ask => &mut self.asks,
+        };
+        match change {
+            LevelChange::Removed { price } => {
+                levels.remove(price);
+            }
+            _ => {
+                levels.insert(change.price(), change.size());
+            }
+        }
+    }
+}
+
+fn side_diff(
+    old: &BTreeMap<Decimal, Decimal>,
+    new: &BTreeMap<Decimal, Decimal>,
+) -> Vec<LevelChange> {
+    let mut changes: Vec<LevelChange> = old
+        .keys()
+        .filter(|price| !new.contains_key(price))
+        .map(|price| LevelChange::Removed { price: *price })
+        .collect();
+    for (price, size) in new {
+        match old.get(price) {
+            None => changes.push(LevelChange::Added {
+                price: *price,
+                size: *size,
+            }),
+            Some(old_size) if old_size != size => changes.push(LevelChange::Changed {
+                price: *price,
+                size: *size,
+            }),
+            Some(_) => {}
+        }
+    }
+    changes.sort_by_key(LevelChange::price);
+    changes
+}
+
+/// A book's levels as of a feed sequence number.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The last message reflected in the levels.
+    pub sequence: u64,
+    pub pair: String,
+    /// Price and size, best first.
+    pub bids: Vec<(Decimal, Decimal)>,
+    pub asks: Vec<(Decimal, Decimal)>,
+}
+
+/// What the publisher has sent: the levels of every book it has published
+/// and its last `capacity` messages.
+#[derive(Debug, Clone, Default)]
+pub struct FeedHistory {
+    sequencer: Sequencer,
+    books: HashMap<String, Levels>,
+    recent: VecDeque<FeedMessage>,
+    capacity: usize,
+}
+
+impl FeedHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Self::default()
+        }
+    }
+
+    pub fn last_sequence(&self) -> u64 {
+        self.sequencer.last()
+    }
+
+    /// Deltas bringing the published levels of `pair` in line with `book`.
+    pub fn book(
+        &mut self,
+        pair: &str,
+        time: DateTime<Utc>,
+        book: &LimitOrderBook,
+    ) -> Vec<FeedMessage> {
+        let levels = Levels::from_book(book);
+        let published = self.books.entry(pair.to_string()).or_default();
+        let diff = published.diff(&levels);
+        *published = levels;
+        let messages = self.sequencer.book_diff(pair, time, &diff);
+        self.remember(&messages);
+        messages
+    }
+
+    /// Trades for `fills` taken by an order on `side`.
+    pub fn fills(
+        &mut self,
+        pair: &str,
+        time: DateTime<Utc>,
+        side: OrderType,
+        fills: &[Fill],
+    ) -> Vec<FeedMessage> {
+        let messages = self.sequencer.fills(pair, time, side, fills);
+        self.remember(&messages);
+        messages
+    }
+
+    pub fn snapshot(&self, pair: &str) -> Snapshot {
+        let levels = self.books.get(pair).cloned().unwrap_or_default();
+        Snapshot {
+            sequence: self.sequencer.last(),
+            pair: pair.to_string(),
+            bids: levels.bids.into_iter().rev().collect(),
+            asks: levels.asks.into_iter().collect(),
+        }
+    }
+
+    /// Messages `from` through `to`, or None once any of them has aged out.
+    pub fn replay(&self, from: u64, to: u64) -> Option<Vec<FeedMessage>> {
+        let oldest = self.recent.front()?.sequence();
+        if from < oldest || to > self.sequencer.last() || from > to {
+            return None;
+        }
+        let start = (from - oldest) as usize;
+        Some(
+            self.recent
+                .range(start..=start + (to - from) as usize)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    fn remember(&mut self, messages: &[FeedMessage]) {
+        self.recent.extend(messages.iter().cloned());
+        while self.recent.len() > self.capacity {
+            self.recent.pop_front();
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecoveryRequest {
+    Snapshot { pair: String },
+    Replay { from: u64, to: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecoveryResponse {
+    Snapshot(Snapshot),
+    Replay { messages: Vec<FeedMessage> },
+    Error { reason: String },
+}
+
+/// Serves snapshots and replays of a `FeedHistory` over TCP, one thread per
+/// connection.
+pub struct RecoveryServer {
+    addr: SocketAddr,
+}
+
+impl RecoveryServer {
+    pub fn spawn(listener: TcpListener, history: Arc<Mutex<FeedHistory>>) -> io::Result<Self> {
+        let addr = listener.local_addr()?;
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        warn!(%err, "recovery accept failed");
+                        continue;
+                    }
+                };
+                let history = history.clone();
+                thread::spawn(move || {
+                    if let Err(err) = serve(stream, &history) {
+                        debug!(%err, "recovery connection closed");
+                    }
+                });
+            }
+        });
+        Ok(Self { addr })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+fn serve(stream: TcpStream, history: &Mutex<FeedHistory>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let response = match serde_json::from_str::<RecoveryRequest>(&line?) {
+            Ok(RecoveryRequest::Snapshot { pair }) => {
+                RecoveryResponse::Snapshot(history.lock().unwrap().snapshot(&pair))
+            }
+            Ok(RecoveryRequest::Replay { from, to }) => {
+                match history.lock().unwrap().replay(from, to) {
+                    Some(messages) => RecoveryResponse::Replay { messages },
+                    None => RecoveryResponse::Error {
+                        reason: format!("Messages {} to {} are not available", from, to),
+                    },
+                }
+            }
+            Err(err) => RecoveryResponse::Error {
+                reason: format!("Invalid request: {}", err),
+            },
+        };
+        serde_json::to_writer(&mut writer, &response)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// A connection to a `RecoveryServer`.
+pub struct RecoveryClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl RecoveryClient {
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let writer = TcpStream::connect(addr)?;
+        Ok(Self {
+            reader: BufReader::new(writer.try_clone()?),
+            writer,
+        })
+    }
+
+    pub fn snapshot(&mut self, pair: &str) -> Result<Snapshot, String> {
+        match self.request(&RecoveryRequest::Snapshot {
+            pair: pair.to_string(),
+        })? {
+            RecoveryResponse::Snapshot(snapshot) => Ok(snapshot),
+            RecoveryResponse::Error { reason } => Err(reason),
+            response => Err(format!("Unexpected response: {:?}", response)),
+        }
+    }
+
+    pub fn replay(&mut self, from: u64, to: u64) -> Result<Vec<FeedMessage>, String> {
+        match self.request(&RecoveryRequest::Replay { from, to })? {
+            RecoveryResponse::Replay { messages } => Ok(messages),
+            RecoveryResponse::Error { reason } => Err(reason),
+            response => Err(format!("Unexpected response: {:?}", response)),
+        }
+    }
+
+    fn request(&mut self, request: &RecoveryRequest) -> Result<RecoveryResponse, String> {
+        let mut line = serde_json::to_string(request).map_err(|err| err.to_string())?;
+        line.push('\n');
+        self.writer
+            .write_all(line.as_bytes())
+            .map_err(|err| err.to_string())?;
+        line.clear();
+        self.reader
+            .read_line(&mut line)
+            .map_err(|err| err.to_string())?;
+        serde_json::from_str(&line).map_err(|err| format!("Invalid response: {}", err))
+    }
+}
+
+/// A consumer's copy of one book, rebuilt from a snapshot and the feed.
+///
+/// Every message of the feed goes through `on_message`, whatever its pair,
+/// since sequence numbers run across the whole feed. Messages that arrive
+/// before the snapshot or after a gap are buffered until they can be
+/// applied in order. A message applies once the replica has everything
+/// before its `first_sequence`, so a conflated subscription's coalesced
+/// deltas aren't taken for a gap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replica {
+    pub pair: String,
+    pub levels: Levels,
+    /// The last message applied; None until the snapshot.
+    last_sequence: Option<u64>,
+    buffered: BTreeMap<u64, FeedMessage>,
+}
+
+impl Replica {
+    pub fn new(pair: impl Into<String>) -> Self {
+        Self {
+            pair: pair.into(),
+            levels: Levels::default(),
+            last_sequence: None,
+            buffered: BTreeMap::new(),
+        }
+    }
+
+    pub fn last_sequence(&self) -> Option<u64> {
+        self.last_sequence
+    }
+
+    /// Replaces the levels with `snapshot`'s, then applies the buffered
+    /// messages that follow it.
+    pub fn apply_snapshot(&mut self, snapshot: &Snapshot) {
+        self.levels = Levels {
+            bids: snapshot.bids.iter().copied().collect(),
+            asks: snapshot.asks.iter().copied().collect(),
+        };
+        self.last_sequence = Some(snapshot.sequence);
+        self.drain_buffered();
+    }
+
+    pub fn on_message(&mut self, message: FeedMessage) {
+        match self.last_sequence {
+            Some(last) if message.sequence() <= last => {}
+            Some(last) if message.first_sequence() <= last + 1 => {
+                self.apply(&message);
+                self.drain_buffered();
+            }
+            _ => {
+                self.buffered.insert(message.sequence(), message);
+            }
+        }
+    }
+
+    /// The first and last sequence numbers missing before the buffered
+    /// messages can be applied; request them with `RecoveryClient::replay`.
+    pub fn missing(&self) -> Option<(u64, u64)> {
+        let last = self.last_sequence?;
+        let (_, next) = self.buffered.first_key_value()?;
+        Some((last + 1, next.first_sequence() - 1))
+    }
+
+    fn apply(&mut self, message: &FeedMessage) {
+        if let FeedMessage::BookDelta(delta) = message {
+            if delta.pair == self.pair {
+                self.levels.apply(delta.side, &delta.change);
+            }
+        }
+        self.last_sequence = Some(message.sequence());
+    }
+
+    fn drain_buffered(&mut self) {
+        let Some(mut last) = self.last_sequence else {
+            return;
+        };
+        while let Some(entry) = self.buffered.first_entry() {
+            if *entry.key() <= last {
+                entry.remove();
+            } else if entry.get().first_sequence() <= last + 1 {
+                let message = entry.remove();
+                self.apply(&message);
+                last = message.sequence();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{feed::conflate::Conflator, limit_order_book::order::Order};
+    use rust_decimal_macros::dec;
+
+    fn bid(price: Decimal, size: Decimal) -> BookDiff {
+        BookDiff {
+            bids: vec![LevelChange::Changed { price, size }],
+            asks: vec![],
+        }
+    }
+
+    fn snapshot(sequence: u64) -> Snapshot {
+        Snapshot {
+            sequence,
+            pair: "BTC/USDT".to_string(),
+            bids: vec![(dec!(99), dec!(1))],
+            asks: vec![],
+        }
+    }
+
+    #[test]
+    fn test_levels_diff() {
+        let mut old = Levels::default();
+        old.bids.insert(dec!(99), dec!(1));
+        old.bids.insert(dec!(98), dec!(2));
+        old.asks.insert(dec!(101), dec!(1));
+        let mut new = old.clone();
+        new.bids.remove(&dec!(99));
+        new.bids.insert(dec!(98), dec!(5));
+        new.bids.insert(dec!(97), dec!(1));
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.bids,
+            vec![
+                LevelChange::Added {
+                    price: dec!(97),
+                    size: dec!(1),
+                },
+                LevelChange::Changed {
+                    price: dec!(98),
+                    size: dec!(5),
+                },
+                LevelChange::Removed { price: dec!(99) },
+            ]
+        );
+        assert!(diff.asks.is_empty());
+        for change in &diff.bids {
+            old.apply(OrderType::Bid, change);
+        }
+        assert_eq!(old, new);
+    }
+
+    #[test]
+    fn test_history_replay_and_snapshot() {
+        let mut history = FeedHistory::new(3);
+        let mut book = LimitOrderBook::new();
+        for (id, side, price) in [
+            (1, OrderType::Bid, dec!(98)),
+            (2, OrderType::Bid, dec!(99)),
+            (3, OrderType::Ask, dec!(101)),
+            (4, OrderType::Ask, dec!(102)),
+        ] {
+            book.add_order(Order::new(
+                "BTC/USDT".to_string(),
+                id,
+                side,
+                dec!(1),
+                price,
+                Utc::now(),
+                Utc::now(),
+            ));
+            history.book("BTC/USDT", Utc::now(), &book);
+        }
+        let fill = Fill {
+            price: dec!(101),
+            quantity: dec!(1),
+            ..Fill::default()
+        };
+        history.fills("BTC/USDT", Utc::now(), OrderType::Bid, &[fill]);
+        assert_eq!(history.last_sequence(), 5);
+
+        let replayed = history.replay(3, 5).unwrap();
+        let sequences: Vec<u64> = replayed.iter().map(FeedMessage::sequence).collect();
+        assert_eq!(sequences, vec![3, 4, 5]);
+        assert!(matches!(replayed[2], FeedMessage::Trade(_)));
+        // Aged out, not yet published, or backwards.
+        assert_eq!(history.replay(2, 4), None);
+        assert_eq!(history.replay(4, 6), None);
+        assert_eq!(history.replay(5, 4), None);
+
+        let snapshot = history.snapshot("BTC/USDT");
+        assert_eq!(snapshot.sequence, 5);
+        assert_eq!(
+            snapshot.bids,
+            vec![(dec!(99), dec!(1)), (dec!(98), dec!(1))]
+        );
+        assert_eq!(
+            snapshot.asks,
+            vec![(dec!(101), dec!(1)), (dec!(102), dec!(1))]
+        );
+        let unknown = history.snapshot("ETH/USDT");
+        assert_eq!(unknown.sequence, 5);
+        assert!(unknown.bids.is_empty() && unknown.asks.is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_then_resume() {
+        let time = Utc::now();
+        let mut sequencer = Sequencer::new();
+        let mut replica = Replica::new("BTC/USDT");
+        let mut messages = Vec::new();
+        for size in [dec!(2), dec!(3), dec!(4)] {
+            messages.extend(sequencer.book_diff("BTC/USDT", time, &bid(dec!(99), size)));
+        }
+        messages.extend(sequencer.book_diff("ETH/USDT", time, &bid(dec!(10), dec!(1))));
+
+        // Everything before the snapshot waits for it.
+        for message in messages.iter().cloned() {
+            replica.on_message(message);
+        }
+        assert_eq!(replica.last_sequence(), None);
+        assert_eq!(replica.missing(), None);
+        assert!(replica.levels.bids.is_empty());
+
+        // The snapshot already reflects 1 and 2; 3 and ETH's 4 follow it.
+        let mut snapshot = snapshot(2);
+        snapshot.bids = vec![(dec!(99), dec!(3))];
+        replica.apply_snapshot(&snapshot);
+        assert_eq!(replica.last_sequence(), Some(4));
+        assert_eq!(replica.missing(), None);
+        assert_eq!(replica.levels.bids.len(), 1);
+        assert_eq!(replica.levels.bids[&dec!(99)], dec!(4));
+
+        // Duplicates are ignored.
+        replica.on_message(messages[2].clone());
+        assert_eq!(replica.last_sequence(), Some(4));
+        assert_eq!(replica.levels.bids[&dec!(99)], dec!(4));
+    }
+
+    #[test]
+    fn test_gap_detection() {
+        let time = Utc::now();
+        let mut sequencer = Sequencer::new();
+        let mut replica = Replica::new("BTC/USDT");
+        replica.apply_snapshot(&snapshot(0));
+        let messages: Vec<FeedMessage> = [dec!(2), dec!(3), dec!(4), dec!(5), dec!(6)]
+            .into_iter()
+            .flat_map(|size| sequencer.book_diff("BTC/USDT", time, &bid(dec!(99), size)))
+            .collect();
+
+        replica.on_message(messages[0].clone());
+        replica.on_message(messages[3].clone());
+        replica.on_message(messages[4].clone());
+        assert_eq!(replica.last_sequence(), Some(1));
+        assert_eq!(replica.missing(), Some((2, 3)));
+        assert_eq!(replica.levels.bids[&dec!(99)], dec!(2));
+
+        // Filling only part of the gap narrows it.
+        replica.on_message(messages[1].clone());
+        assert_eq!(replica.missing(), Some((3, 3)));
+        replica.on_message(messages[2].clone());
+        assert_eq!(replica.missing(), None);
+        assert_eq!(replica.last_sequence(), Some(5));
+        assert_eq!(replica.levels.bids[&dec!(99)], dec!(6));
+
+        // A fresh snapshot past a gap drops what it covers.
+        let later: Vec<FeedMessage> = [dec!(7), dec!(8)]
+            .into_iter()
+            .flat_map(|size| sequencer.book_diff("BTC/USDT", time, &bid(dec!(99), size)))
+            .collect();
+        replica.on_message(later[1].clone());
+        assert_eq!(replica.missing(), Some((6, 6)));
+        let mut snapshot = snapshot(7);
+        snapshot.bids = vec![(dec!(99), dec!(8))];
+        replica.apply_snapshot(&snapshot);
+        assert_eq!(replica.missing(), None);
+        assert_eq!(replica.last_sequence(), Some(7));
+        assert_eq!(replica.levels.bids[&dec!(99)], dec!(8));
+    }
+
+    #[test]
+    fn test_conflated_gap() {
+        let time = Utc::now();
+        let mut sequencer = Sequencer::new();
+        let mut conflator = Conflator::new(chrono::Duration::seconds(1));
+        let mut replica = Replica::new("BTC/USDT");
+        replica.apply_snapshot(&snapshot(0));
+
+        let mut window = |sizes: &[Decimal]| {
+            for size in sizes {
+                let messages = sequencer.book_diff("BTC/USDT", time, &bid(dec!(99), *size));
+                conflator.push(&messages, time);
+            }
+            conflator.flush()
+        };
+        let first = window(&[dec!(2), dec!(3)]);
+        let lost = window(&[dec!(4), dec!(5)]);
+        let last = window(&[dec!(6), dec!(7)]);
+        assert_eq!(lost[0].first_sequence(), 3);
+
+        for message in first {
+            replica.on_message(message);
+        }
+        assert_eq!(replica.last_sequence(), Some(2));
+        assert_eq!(replica.levels.bids[&dec!(99)], dec!(3));
+        for message in last {
+            replica.on_message(message);
+        }
+        assert_eq!(replica.last_sequence(), Some(2));
+        assert_eq!(replica.missing(), Some((3, 4)));
+
+        for message in lost {
+            replica.on_message(message);
+        }
+        assert_eq!(replica.missing(), None);
+        assert_eq!(replica.last_sequence(), Some(6));
+        assert_eq!(replica.levels.bids[&dec!(99)], dec!(7));
+    }
+}
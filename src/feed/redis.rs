@@ -0,0 +1,215 @@
+//! The feed and the engine's market events published to Redis, for stacks
+//! that already consume from it.
+//!
+//! Every message is the JSON the other feeds carry, sent to a key named
+//! after its kind and pair under a prefix, e.g. `tradebot:trades:BTC/USDT`,
+//! `tradebot:book:BTC/USDT` and `tradebot:events:BTC/USDT`. Published to
+//! channels, a message reaches only the subscribers connected at the time.
+//! Added to streams, under the field `message`, it can be read from any
+//! point and by consumer groups; streams are trimmed to about `max_len`
+//! entries if given one.
+
+use crate::{feed::message::FeedMessage, matching_engine::bands::MarketEvent};
+use ::redis::{streams::StreamMaxlen, Client, Connection, ConnectionLike, Pipeline, RedisResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// `PUBLISH` to channels.
+    PubSub,
+    /// `XADD` to streams.
+    Stream { max_len: Option<usize> },
+}
+
+/// Publishes over `C`, normally a `redis::Connection`.
+pub struct RedisPublisher<C = Connection> {
+    connection: C,
+    prefix: String,
+    delivery: Delivery,
+}
+
+impl RedisPublisher {
+    /// Connects to the server at `url`, e.g. `redis://127.0.0.1/`.
+    pub fn connect(url: &str, prefix: impl Into<String>, delivery: Delivery) -> RedisResult<Self> {
+        let connection = Client::open(url)?.get_connection()?;
+        Ok(Self::new(connection, prefix, delivery))
+    }
+}
+
+impl<C> RedisPublisher<C> {
+    pub fn new(connection: C, prefix: impl Into<String>, delivery: Delivery) -> Self {
+        Self {
+            connection,
+            prefix: prefix.into(),
+            delivery,
+        }
+    }
+
+    /// The key messages of `kind` for `pair` go to.
+    pub fn key(&self, kind: &str, pair: &str) -> String {
+        format!("{}:{}:{}", self.prefix, kind, pair)
+    }
+
+    fn pipeline(&self, entries: impl IntoIterator<Item = (String, String)>) -> Pipeline {
+        let mut pipeline = Pipeline::new();
+        for (key, json) in entries {
+            match self.delivery {
+                Delivery::PubSub => pipeline.publish(key, json),
+                Delivery::Stream { max_len: None } => pipeline.xadd(key, "*", &[("message", json)]),
+                Delivery::Stream {
+                    max_len: Some(max_len),
+                } => pipeline.xadd_maxlen(
+                    key,
+                    StreamMaxlen::Approx(max_len),
+                    "*",
+                    &[("message", json)],
+                ),
+            }
+            .ignore();
+        }
+        pipeline
+    }
+
+    fn feed_pipeline(&self, messages: &[FeedMessage]) -> Pipeline {
+        self.pipeline(messages.iter().map(|message| {
+            let kind = match message {
+                FeedMessage::BookDelta(_) => "book",
+                FeedMessage::Trade(_) => "trades",
+            };
+            let json = serde_json::to_string(message).expect("feed messages serialize");
+            (self.key(kind, message.pair()), json)
+        }))
+    }
+
+    fn events_pipeline(&self, events: &[MarketEvent]) -> Pipeline {
+        self.pipeline(events.iter().map(|event| {
+            let json = serde_json::to_string(event).expect("market events serialize");
+            (self.key("events", &event.pair().to_string()), json)
+        }))
+    }
+}
+
+impl<C: ConnectionLike> RedisPublisher<C> {
+    /// Sends `messages` in one round trip, in order.
+    pub fn publish(&mut self, messages: &[FeedMessage]) -> RedisResult<()> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        self.feed_pipeline(messages).exec(&mut self.connection)
+    }
+
+    /// Sends `events`, e.g. from `MatchingEngine::drain_market_events`, in
+    /// one round trip.
+    pub fn publish_events(&mut self, events: &[MarketEvent]) -> RedisResult<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.events_pipeline(events).exec(&mut self.connection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        feed::message::Sequencer,
+        limit_order_book::{
+            diff::{BookDiff, LevelChange},
+            order::{Fill, OrderType},
+        },
+        matching_engine::engine::TradingPair,
+    };
+    use chrono::{TimeZone, Utc};
+    use rust_decimal_macros::dec;
+
+    /// The commands of a pipeline as RESP, with line breaks shown as `|`.
+    fn packed(pipeline: &Pipeline) -> String {
+        String::from_utf8(pipeline.get_packed_pipeline())
+            .unwrap()
+            .replace("\r\n", "|")
+    }
+
+    #[test]
+    fn test_commands() {
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let fill = Fill {
+            maker_id: 1,
+            taker_id: 2,
+            price: dec!(101),
+            quantity: dec!(1),
+            ..Fill::default()
+        };
+        let trades = Sequencer::new().fills("BTC/USDT", time, OrderType::Bid, &[fill]);
+        let json = serde_json::to_string(&trades[0]).unwrap();
+
+        let channels = RedisPublisher::new((), "tradebot", Delivery::PubSub);
+        assert_eq!(
+            packed(&channels.feed_pipeline(&trades)),
+            format!(
+                "*3|$7|PUBLISH|$24|tradebot:trades:BTC/USDT|${}|{}|",
+                json.len(),
+                json
+            )
+        );
+
+        let streams = RedisPublisher::new(
+            (),
+            "tradebot",
+            Delivery::Stream {
+                max_len: Some(1000),
+            },
+        );
+        let events = [MarketEvent::Expired {
+            pair: TradingPair::new("BTC".to_string(), "USDT".to_string()),
+            time,
+            orders: vec![7],
+        }];
+        let json =
+            r#"{"type":"expired","pair":"BTC/USDT","time":"2024-01-01T00:00:00Z","orders":[7]}"#;
+        assert_eq!(
+            packed(&streams.events_pipeline(&events)),
+            format!(
+                "*8|$4|XADD|$24|tradebot:events:BTC/USDT|$6|MAXLEN|$1|~|$4|1000|$1|*|$7|message|${}|{}|",
+                json.len(),
+                json
+            )
+        );
+    }
+
+    #[test]
+    fn test_unbounded_streams_keep_message_order() {
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut sequencer = Sequencer::new();
+        let diff = BookDiff {
+            bids: vec![LevelChange::Removed { price: dec!(99) }],
+            asks: Vec::new(),
+        };
+        let mut messages = sequencer.book_diff("ETH/USDT", time, &diff);
+        let fill = Fill {
+            maker_id: 3,
+            taker_id: 4,
+            price: dec!(99),
+            quantity: dec!(2),
+            ..Fill::default()
+        };
+        messages.extend(sequencer.fills("ETH/USDT", time, OrderType::Ask, &[fill]));
+
+        let streams = RedisPublisher::new((), "md", Delivery::Stream { max_len: None });
+        assert_eq!(streams.key("book", "ETH/USDT"), "md:book:ETH/USDT");
+        let xadd = |key: &str, message: &FeedMessage| {
+            let json = serde_json::to_string(message).unwrap();
+            format!(
+                "*5|$4|XADD|${}|{}|$1|*|$7|message|${}|{}|",
+                key.len(),
+                key,
+                json.len(),
+                json
+            )
+        };
+        // Each message goes to its own kind's stream, in the order given.
+        assert_eq!(
+            packed(&streams.feed_pipeline(&messages)),
+            xadd("md:book:ETH/USDT", &messages[0]) + &xadd("md:trades:ETH/USDT", &messages[1])
+        );
+        assert_eq!(packed(&streams.feed_pipeline(&[])), "");
+    }
+}
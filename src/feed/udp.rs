@@ -0,0 +1,335 @@
+//! The binary feed over UDP.
+//!
+//! Each datagram holds one or more whole messages, so a receiver decodes it
+//! with `binary::decode_all`. Datagrams can be lost or reordered; consumers
+//! spot that from the sequence numbers and repair it through the recovery
+//! service.
+
+use crate::{
+    feed::{binary, conflate::Conflator, message::FeedMessage, recovery::FeedHistory},
+    limit_order_book::order::{Fill, LimitOrderBook, OrderType},
+};
+use chrono::{DateTime, Duration, Utc};
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{Arc, Mutex},
+};
+
+/// Largest datagram the publisher sends, leaving room for IP and UDP
+/// headers within a typical 1500 byte MTU.
+pub const MAX_DATAGRAM_LEN: usize = 1400;
+
+/// Where the feed goes and, for slow consumers, how it's conflated.
+#[derive(Debug, Clone)]
+pub struct Subscriber {
+    pub target: SocketAddr,
+    pub conflator: Option<Conflator>,
+}
+
+pub struct UdpPublisher {
+    socket: UdpSocket,
+    subscribers: Vec<Subscriber>,
+    history: Arc<Mutex<FeedHistory>>,
+}
+
+impl UdpPublisher {
+    /// Publishes to `target`, which may be a multicast group, recording what
+    /// it sends in `history` for the recovery service.
+    pub fn new(
+        socket: UdpSocket,
+        target: impl ToSocketAddrs,
+        history: Arc<Mutex<FeedHistory>>,
+    ) -> io::Result<Self> {
+        let mut publisher = Self {
+            socket,
+            subscribers: Vec::new(),
+            history,
+        };
+        publisher.subscribe(target, None)?;
+        Ok(publisher)
+    }
+
+    /// Also publishes to `target`, conflated over `conflation` if given.
+    pub fn subscribe(
+        &mut self,
+        target: impl ToSocketAddrs,
+        conflation: Option<Duration>,
+    ) -> io::Result<()> {
+        let target = target
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no target address"))?;
+        self.subscribers.push(Subscriber {
+            target,
+            conflator: conflation.map(Conflator::new),
+        });
+        Ok(())
+    }
+
+    pub fn subscribers(&self) -> &[Subscriber] {
+        &self.subscribers
+    }
+
+    pub fn history(&self) -> &Arc<Mutex<FeedHistory>> {
+        &self.history
+    }
+
+    /// Publishes the changes to `book` since it was last published.
+    pub fn publish_book(
+        &mut self,
+        pair: &str,
+        time: DateTime<Utc>,
+        book: &LimitOrderBook,
+    ) -> Result<Vec<FeedMessage>, String> {
+        // Sending under the lock keeps datagrams in sequence order.
+        let history = self.history.clone();
+        let mut history = history.lock().unwrap();
+        let messages = history.book(pair, time, book);
+        self.send(&messages, time)?;
+        Ok(messages)
+    }
+
+    /// Publishes a trade per fill of a taker on `side`.
+    pub fn publish_fills(
+        &mut self,
+        pair: &str,
+        time: DateTime<Utc>,
+        side: OrderType,
+        fills: &[Fill],
+    ) -> Result<Vec<FeedMessage>, String> {
+        let history = self.history.clone();
+        let mut history = history.lock().unwrap();
+        let messages = history.fills(pair, time, side, fills);
+        self.send(&messages, time)?;
+        Ok(messages)
+    }
+
+    /// Sends what conflated subscribers have pending, e.g. when the feed
+    /// goes quiet.
+    pub fn flush(&mut self) -> Result<(), String> {
+        for index in 0..self.subscribers.len() {
+            let Some(conflator) = &mut self.subscribers[index].conflator else {
+                continue;
+            };
+            let messages = conflator.flush();
+            self.send_to(self.subscribers[index].target, &messages)?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, messages: &[FeedMessage], time: DateTime<Utc>) -> Result<(), String> {
+        for index in 0..self.subscribers.len() {
+            let subscriber = &mut self.subscribers[index];
+            let target = subscriber.target;
+            match &mut subscriber.conflator {
+                Some(conflator) => {
+                    let conflated = conflator.push(messages, time);
+                    self.send_to(target, &conflated)?;
+                }
+                None => self.send_to(target, messages)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn send_to(&self, target: SocketAddr, messages: &[FeedMessage]) -> Result<(), String> {
+        let mut datagram = Vec::with_capacity(MAX_DATAGRAM_LEN);
+        let mut encoded = Vec::new();
+        for message in messages {
+            encoded.clear();
+            binary::encode(message, &mut encoded).map_err(|err| err.to_string())?;
+            if datagram.len() + encoded.len() > MAX_DATAGRAM_LEN {
+                self.send_datagram(target, &datagram)?;
+                datagram.clear();
+            }
+            datagram.extend_from_slice(&encoded);
+        }
+        if !datagram.is_empty() {
+            self.send_datagram(target, &datagram)?;
+        }
+        Ok(())
+    }
+
+    fn send_datagram(&self, target: SocketAddr, datagram: &[u8]) -> Result<(), String> {
+        self.socket
+            .send_to(datagram, target)
+            .map(|_| ())
+            .map_err(|err| format!("Failed to send to {}: {}", target, err))
+    }
+}
+
+/// Receives one datagram from `socket` and decodes its messages.
+pub fn receive(socket: &UdpSocket) -> Result<Vec<FeedMessage>, String> {
+    let mut buffer = [0; MAX_DATAGRAM_LEN];
+    let len = socket.recv(&mut buffer).map_err(|err| err.to_string())?;
+    binary::decode_all(&buffer[..len]).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        feed::recovery::{Levels, RecoveryClient, RecoveryServer, Replica},
+        limit_order_book::order::Order,
+    };
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::{net::TcpListener, time::Duration};
+
+    fn add(book: &mut LimitOrderBook, id: u64, side: OrderType, price: Decimal) {
+        book.add_order(Order::new(
+            "BTC/USDT".to_string(),
+            id,
+            side,
+            dec!(1),
+            price,
+            Utc::now(),
+            Utc::now(),
+        ));
+    }
+
+    #[test]
+    fn test_late_joiner_recovers() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let history = Arc::new(Mutex::new(FeedHistory::new(100)));
+        let mut publisher = UdpPublisher::new(
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+            receiver.local_addr().unwrap(),
+            history.clone(),
+        )
+        .unwrap();
+        let server =
+            RecoveryServer::spawn(TcpListener::bind("127.0.0.1:0").unwrap(), history).unwrap();
+
+        // Published before the consumer joins: only the snapshot has these.
+        let mut book = LimitOrderBook::new();
+        add(&mut book, 1, OrderType::Bid, dec!(99));
+        add(&mut book, 2, OrderType::Ask, dec!(101));
+        publisher
+            .publish_book("BTC/USDT", Utc::now(), &book)
+            .unwrap();
+        receive(&receiver).unwrap();
+
+        // The consumer is listening but hasn't got a snapshot yet.
+        let mut replica = Replica::new("BTC/USDT");
+        add(&mut book, 3, OrderType::Bid, dec!(98));
+        publisher
+            .publish_book("BTC/USDT", Utc::now(), &book)
+            .unwrap();
+        let mut client = RecoveryClient::connect(server.local_addr()).unwrap();
+        let snapshot = client.snapshot("BTC/USDT").unwrap();
+        assert_eq!(snapshot.sequence, 3);
+        for message in receive(&receiver).unwrap() {
+            replica.on_message(message);
+        }
+        replica.apply_snapshot(&snapshot);
+        assert_eq!(replica.last_sequence(), Some(3));
+
+        // A datagram goes missing; the next one is held back until replayed.
+        add(&mut book, 4, OrderType::Ask, dec!(102));
+        publisher
+            .publish_book("BTC/USDT", Utc::now(), &book)
+            .unwrap();
+        receive(&receiver).unwrap();
+        book.cancel_order(1);
+        publisher
+            .publish_book("BTC/USDT", Utc::now(), &book)
+            .unwrap();
+        for message in receive(&receiver).unwrap() {
+            replica.on_message(message);
+        }
+        assert_eq!(replica.missing(), Some((4, 4)));
+        let (from, to) = replica.missing().unwrap();
+        for message in client.replay(from, to).unwrap() {
+            replica.on_message(message);
+        }
+        assert_eq!(replica.missing(), None);
+        assert_eq!(replica.last_sequence(), Some(5));
+        assert_eq!(replica.levels, Levels::from_book(&book));
+        assert!(client.replay(1, 9).is_err());
+    }
+
+    #[test]
+    fn test_splits_datagrams() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut publisher = UdpPublisher::new(
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+            receiver.local_addr().unwrap(),
+            Arc::new(Mutex::new(FeedHistory::new(100))),
+        )
+        .unwrap();
+
+        let mut book = LimitOrderBook::new();
+        for id in 1..=30 {
+            add(&mut book, id, OrderType::Bid, Decimal::from(id));
+        }
+        let published = publisher
+            .publish_book("BTC/USDT", Utc::now(), &book)
+            .unwrap();
+        assert_eq!(published.len(), 30);
+
+        let per_datagram = MAX_DATAGRAM_LEN / (binary::HEADER_LEN + binary::BOOK_DELTA_BLOCK_LEN);
+        let first = receive(&receiver).unwrap();
+        assert_eq!(first.len(), per_datagram);
+        let second = receive(&receiver).unwrap();
+        assert_eq!(second.len(), 30 - per_datagram);
+        let received: Vec<FeedMessage> = first.into_iter().chain(second).collect();
+        assert_eq!(received, published);
+    }
+
+    #[test]
+    fn test_conflated_subscriber() {
+        let full = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let slow = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for socket in [&full, &slow] {
+            socket
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+        }
+        let mut publisher = UdpPublisher::new(
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+            full.local_addr().unwrap(),
+            Arc::new(Mutex::new(FeedHistory::new(100))),
+        )
+        .unwrap();
+        publisher
+            .subscribe(
+                slow.local_addr().unwrap(),
+                Some(chrono::Duration::seconds(1)),
+            )
+            .unwrap();
+
+        let mut book = LimitOrderBook::new();
+        let mut replica = Replica::new("BTC/USDT");
+        replica.apply_snapshot(&publisher.history().lock().unwrap().snapshot("BTC/USDT"));
+        for id in 1..=3 {
+            add(&mut book, id, OrderType::Bid, dec!(99));
+            publisher
+                .publish_book("BTC/USDT", Utc::now(), &book)
+                .unwrap();
+            assert_eq!(receive(&full).unwrap().len(), 1);
+        }
+        book.cancel_order(1);
+        publisher
+            .publish_book("BTC/USDT", Utc::now(), &book)
+            .unwrap();
+        receive(&full).unwrap();
+        publisher.flush().unwrap();
+
+        let conflated = receive(&slow).unwrap();
+        assert_eq!(conflated.len(), 1);
+        assert_eq!(conflated[0].sequence(), 4);
+        for message in conflated {
+            replica.on_message(message);
+        }
+        assert_eq!(replica.missing(), None);
+        assert_eq!(replica.levels, Levels::from_book(&book));
+    }
+}
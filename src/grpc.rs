@@ -0,0 +1,589 @@
+//! gRPC front end for a `MatchingEngine`: order entry plus streaming book
+//! and trade updates. `proto/tradebot.proto` describes the same service for
+//! generating clients in other languages.
+
+use crate::{
+    api::{self, CancelOrderRequest, NewOrderRequest},
+    limit_order_book::order::{Fill, OrderStatus, OrderType, TimeInForce},
+    matching_engine::rate_limit::RATE_LIMITED,
+    server::{EngineHandle, MarketUpdate, CANCEL_ONLY, DRAINING},
+};
+use rust_decimal::Decimal;
+use std::net::SocketAddr;
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    //! Messages of the `tradebot.v1` package, mirroring `tradebot.proto`.
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubmitOrderRequest {
+        #[prost(string, tag = "1")]
+        pub pair: String,
+        #[prost(enumeration = "Side", tag = "2")]
+        pub side: i32,
+        #[prost(string, tag = "3")]
+        pub price: String,
+        #[prost(string, tag = "4")]
+        pub quantity: String,
+        #[prost(string, tag = "5")]
+        pub client: String,
+        #[prost(bool, tag = "6")]
+        pub short_sale: bool,
+        #[prost(bool, tag = "7")]
+        pub reduce_only: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubmitOrderResponse {
+        #[prost(message, optional, tag = "1")]
+        pub order: Option<OrderReport>,
+        #[prost(message, repeated, tag = "2")]
+        pub fills: Vec<Trade>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CancelOrderRequest {
+        #[prost(string, tag = "1")]
+        pub pair: String,
+        #[prost(uint64, tag = "2")]
+        pub exchange_id: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct OrderReport {
+        #[prost(uint64, tag = "1")]
+        pub exchange_id: u64,
+        #[prost(string, tag = "2")]
+        pub pair: String,
+        #[prost(enumeration = "Side", tag = "3")]
+        pub side: i32,
+        #[prost(string, tag = "4")]
+        pub price: String,
+        #[prost(string, tag = "5")]
+        pub quantity: String,
+        #[prost(string, tag = "6")]
+        pub filled_quantity: String,
+        #[prost(string, tag = "7")]
+        pub remaining_quantity: String,
+        #[prost(enumeration = "OrderStatus", tag = "8")]
+        pub status: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Trade {
+        #[prost(string, tag = "1")]
+        pub pair: String,
+        #[prost(uint64, tag = "2")]
+        pub maker_id: u64,
+        #[prost(uint64, tag = "3")]
+        pub taker_id: u64,
+        #[prost(string, tag = "4")]
+        pub price: String,
+        #[prost(string, tag = "5")]
+        pub quantity: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StreamBookRequest {
+        #[prost(string, tag = "1")]
+        pub pair: String,
+        /// Levels per side; 0 means 10.
+        #[prost(uint32, tag = "2")]
+        pub depth: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Level {
+        #[prost(string, tag = "1")]
+        pub price: String,
+        #[prost(string, tag = "2")]
+        pub size: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct BookSnapshot {
+        #[prost(string, tag = "1")]
+        pub pair: String,
+        #[prost(message, repeated, tag = "2")]
+        pub bids: Vec<Level>,
+        #[prost(message, repeated, tag = "3")]
+        pub asks: Vec<Level>,
+        #[prost(uint32, tag = "4")]
+        pub checksum: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct StreamTradesRequest {
+        /// Empty for every market.
+        #[prost(string, tag = "1")]
+        pub pair: String,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Side {
+        Unspecified = 0,
+        Bid = 1,
+        Ask = 2,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum OrderStatus {
+        Unspecified = 0,
+        New = 1,
+        PartiallyFilled = 2,
+        Filled = 3,
+        Cancelled = 4,
+    }
+
+    include!(concat!(env!("OUT_DIR"), "/tradebot.v1.OrderEntry.rs"));
+}
+
+use proto::{
+    order_entry_server::{OrderEntry, OrderEntryServer},
+    Side,
+};
+
+const DEFAULT_DEPTH: usize = 10;
+
+/// Messages a stream can buffer for a slow client before it waits.
+const STREAM_BUFFER: usize = 64;
+
+pub struct OrderEntryService {
+    engine: EngineHandle,
+}
+
+impl OrderEntryService {
+    pub fn new(engine: EngineHandle) -> Self {
+        Self { engine }
+    }
+}
+
+#[tonic::async_trait]
+impl OrderEntry for OrderEntryService {
+    async fn submit_order(
+        &self,
+        request: Request<proto::SubmitOrderRequest>,
+    ) -> Result<Response<proto::SubmitOrderResponse>, Status> {
+        let request = new_order_request(request.into_inner())?;
+        let response = self.engine.new_order(request).await.map_err(|reason| {
+            if reason.starts_with(RATE_LIMITED) {
+                Status::resource_exhausted(reason)
+            } else if reason.starts_with(DRAINING) || reason.starts_with(CANCEL_ONLY) {
+                Status::unavailable(reason)
+            } else {
+                Status::invalid_argument(reason)
+            }
+        })?;
+        let pair = &response.order.pair;
+        Ok(Response::new(proto::SubmitOrderResponse {
+            fills: response
+                .fills
+                .iter()
+                .map(|fill| trade(pair, fill))
+                .collect(),
+            order: Some(order_report(&response.order)),
+        }))
+    }
+
+    async fn cancel_order(
+        &self,
+        request: Request<proto::CancelOrderRequest>,
+    ) -> Result<Response<proto::OrderReport>, Status> {
+        let request = request.into_inner();
+        let report = self
+            .engine
+            .cancel_order(CancelOrderRequest {
+                pair: request.pair,
+                exchange_id: request.exchange_id,
+            })
+            .await
+            .map_err(Status::not_found)?;
+        Ok(Response::new(order_report(&report)))
+    }
+
+    type StreamBookStream = ReceiverStream<Result<proto::BookSnapshot, Status>>;
+
+    async fn stream_book(
+        &self,
+        request: Request<proto::StreamBookRequest>,
+    ) -> Result<Response<Self::StreamBookStream>, Status> {
+        let request = request.into_inner();
+        let depth = match request.depth {
+            0 => DEFAULT_DEPTH,
+            depth => depth as usize,
+        };
+        // Subscribe first so no change slips in after the initial snapshot.
+        let mut updates = self.engine.subscribe();
+        let snapshot = self
+            .engine
+            .book(request.pair.clone(), depth)
+            .await
+            .map_err(Status::not_found)?;
+
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        let engine = self.engine.clone();
+        tokio::spawn(async move {
+            let mut snapshot = Ok(book_snapshot(snapshot));
+            loop {
+                if sender.send(snapshot).await.is_err() {
+                    return;
+                }
+                loop {
+                    match updates.recv().await {
+                        Ok(MarketUpdate::Book { pair }) if pair == request.pair => break,
+                        Ok(_) => continue,
+                        // Missed updates are covered by the next snapshot.
+                        Err(RecvError::Lagged(_)) => break,
+                        Err(RecvError::Closed) => return,
+                    }
+                }
+                snapshot = engine
+                    .book(request.pair.clone(), depth)
+                    .await
+                    .map(book_snapshot)
+                    .map_err(Status::unavailable);
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+
+    type StreamTradesStream = ReceiverStream<Result<proto::Trade, Status>>;
+
+    async fn stream_trades(
+        &self,
+        request: Request<proto::StreamTradesRequest>,
+    ) -> Result<Response<Self::StreamTradesStream>, Status> {
+        let filter = request.into_inner().pair;
+        let mut updates = self.engine.subscribe();
+
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(async move {
+            loop {
+                let message = match updates.recv().await {
+                    Ok(MarketUpdate::Trade { pair, fill })
+                        if filter.is_empty() || pair == filter =>
+                    {
+                        Ok(trade(&pair, &fill))
+                    }
+                    Ok(_) => continue,
+                    // Unlike book snapshots, a skipped trade cannot be
+                    // recovered, so end the stream rather than hide the gap.
+                    Err(RecvError::Lagged(missed)) => Err(Status::data_loss(format!(
+                        "Stream fell behind and missed {} updates",
+                        missed
+                    ))),
+                    Err(RecvError::Closed) => return,
+                };
+                let failed = message.is_err();
+                if sender.send(message).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+fn new_order_request(request: proto::SubmitOrderRequest) -> Result<NewOrderRequest, Status> {
+    let side = match Side::try_from(request.side) {
+        Ok(Side::Bid) => OrderType::Bid,
+        Ok(Side::Ask) => OrderType::Ask,
+        _ => return Err(Status::invalid_argument("Order side is required")),
+    };
+    Ok(NewOrderRequest {
+        pair: request.pair,
+        side,
+        price: parse_decimal("price", &request.price)?,
+        quantity: parse_decimal("quantity", &request.quantity)?,
+        client: request.client,
+        short_sale: request.short_sale,
+        reduce_only: request.reduce_only,
+        client_order_id: None,
+        strategy: None,
+        time_in_force: TimeInForce::Gtc,
+    })
+}
+
+fn parse_decimal(field: &str, value: &str) -> Result<Decimal, Status> {
+    value
+        .parse()
+        .map_err(|err| Status::invalid_argument(format!("Invalid {} {:?}: {}", field, value, err)))
+}
+
+fn side(side: OrderType) -> Side {
+    match side {
+        OrderType::Bid => Side::Bid,
+        OrderType::Ask => Side::Ask,
+    }
+}
+
+fn order_report(report: &api::OrderReport) -> proto::OrderReport {
+    let status = match report.status {
+        OrderStatus::New => proto::OrderStatus::New,
+        OrderStatus::PartiallyFilled => proto::OrderStatus::PartiallyFilled,
+        OrderStatus::Filled => proto::OrderStatus::Filled,
+        OrderStatus::Cancelled => proto::OrderStatus::Cancelled,
+    };
+    proto::OrderReport {
+        exchange_id: report.exchange_id,
+        pair: report.pair.clone(),
+        side: side(report.side) as i32,
+        price: report.price.to_string(),
+        quantity: report.quantity.to_string(),
+        filled_quantity: report.filled_quantity.to_string(),
+        remaining_quantity: report.remaining_quantity.to_string(),
+        status: status as i32,
+    }
+}
+
+fn trade(pair: &str, fill: &Fill) -> proto::Trade {
+    proto::Trade {
+        pair: pair.to_string(),
+        maker_id: fill.maker_id,
+        taker_id: fill.taker_id,
+        price: fill.price.to_string(),
+        quantity: fill.quantity.to_string(),
+    }
+}
+
+fn book_snapshot(snapshot: api::BookSnapshot) -> proto::BookSnapshot {
+    let levels = |levels: Vec<(Decimal, Decimal)>| {
+        levels
+            .into_iter()
+            .map(|(price, size)| proto::Level {
+                price: price.to_string(),
+                size: size.to_string(),
+            })
+            .collect()
+    };
+    proto::BookSnapshot {
+        pair: snapshot.pair,
+        bids: levels(snapshot.bids),
+        asks: levels(snapshot.asks),
+        checksum: snapshot.checksum,
+    }
+}
+
+/// Serves until the engine has stopped.
+pub async fn serve(addr: SocketAddr, engine: EngineHandle) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(OrderEntryServer::new(OrderEntryService::new(
+            engine.clone(),
+        )))
+        .serve_with_shutdown(addr, async move { engine.stopped().await })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching_engine::engine::{MatchingEngine, TradingPair};
+    use proto::order_entry_client::OrderEntryClient;
+    use tokio::net::TcpListener;
+    use tokio_stream::{wrappers::TcpListenerStream, StreamExt};
+    use tonic::transport::Channel;
+
+    fn order(side: Side, price: &str, quantity: &str) -> proto::SubmitOrderRequest {
+        proto::SubmitOrderRequest {
+            pair: "BTC/USDT".to_string(),
+            side: side as i32,
+            price: price.to_string(),
+            quantity: quantity.to_string(),
+            client: String::new(),
+            short_sale: false,
+            reduce_only: false,
+        }
+    }
+
+    /// An engine with BTC/USDT and ETH/USDT markets, served on a local port.
+    async fn connect() -> (EngineHandle, OrderEntryClient<Channel>) {
+        let engine = EngineHandle::spawn(|| {
+            let mut engine = MatchingEngine::new();
+            for base in ["BTC", "ETH"] {
+                engine.add_new_market(TradingPair::new(base.to_string(), "USDT".to_string()));
+            }
+            engine
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(OrderEntryServer::new(OrderEntryService::new(
+                    engine.clone(),
+                )))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        (engine, OrderEntryClient::connect(url).await.unwrap())
+    }
+
+    #[test]
+    fn test_new_order_request() {
+        let mut submitted = order(Side::Ask, "100.50", "2");
+        submitted.client = "desk".to_string();
+        submitted.reduce_only = true;
+        let request = new_order_request(submitted).unwrap();
+        assert_eq!(request.side, OrderType::Ask);
+        assert_eq!(request.price, "100.50".parse::<Decimal>().unwrap());
+        assert_eq!(request.quantity, Decimal::TWO);
+        assert_eq!(request.client, "desk");
+        assert!(request.reduce_only && !request.short_sale);
+
+        let status = new_order_request(order(Side::Bid, "abc", "1")).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(status.message().starts_with(r#"Invalid price "abc""#));
+        let status = new_order_request(order(Side::Bid, "100", "")).unwrap_err();
+        assert!(status.message().starts_with(r#"Invalid quantity """#));
+        let mut unknown = order(Side::Bid, "100", "1");
+        unknown.side = 7;
+        assert_eq!(
+            new_order_request(unknown).unwrap_err().message(),
+            "Order side is required"
+        );
+    }
+
+    #[test]
+    fn test_reports() {
+        let report = api::OrderReport {
+            exchange_id: 4,
+            pair: "BTC/USDT".to_string(),
+            side: OrderType::Bid,
+            price: "99.5".parse().unwrap(),
+            quantity: Decimal::from(3),
+            filled_quantity: Decimal::ONE,
+            remaining_quantity: Decimal::TWO,
+            status: OrderStatus::PartiallyFilled,
+        };
+        assert_eq!(
+            order_report(&report),
+            proto::OrderReport {
+                exchange_id: 4,
+                pair: "BTC/USDT".to_string(),
+                side: Side::Bid as i32,
+                price: "99.5".to_string(),
+                quantity: "3".to_string(),
+                filled_quantity: "1".to_string(),
+                remaining_quantity: "2".to_string(),
+                status: proto::OrderStatus::PartiallyFilled as i32,
+            }
+        );
+
+        let snapshot = book_snapshot(api::BookSnapshot {
+            pair: "BTC/USDT".to_string(),
+            bids: vec![(Decimal::from(99), Decimal::ONE)],
+            asks: vec![],
+            checksum: 42,
+        });
+        assert_eq!(snapshot.bids.len(), 1);
+        assert_eq!(snapshot.bids[0].price, "99");
+        assert!(snapshot.asks.is_empty());
+        assert_eq!(snapshot.checksum, 42);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_order_entry_and_streams() {
+        let (_engine, mut client) = connect().await;
+
+        let mut books = client
+            .stream_book(proto::StreamBookRequest {
+                pair: "BTC/USDT".to_string(),
+                depth: 0,
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let mut trades = client
+            .stream_trades(proto::StreamTradesRequest::default())
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(books.next().await.unwrap().unwrap().asks.is_empty());
+
+        let ask = client
+            .submit_order(order(Side::Ask, "100", "5"))
+            .await
+            .unwrap()
+            .into_inner();
+        let book = books.next().await.unwrap().unwrap();
+        assert_eq!(
+            book.asks,
+            vec![proto::Level {
+                price: "100".to_string(),
+                size: "5".to_string(),
+            }]
+        );
+
+        let bid = client
+            .submit_order(order(Side::Bid, "100", "2"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(bid.fills.len(), 1);
+        let trade = trades.next().await.unwrap().unwrap();
+        assert_eq!(trade.maker_id, ask.order.as_ref().unwrap().exchange_id);
+        assert_eq!(trade.quantity, "2");
+        assert_eq!(books.next().await.unwrap().unwrap().asks[0].size, "3");
+
+        // Cancelling the rest of the ask reports it and empties the book.
+        let ask_id = ask.order.unwrap().exchange_id;
+        let cancelled = client
+            .cancel_order(proto::CancelOrderRequest {
+                pair: "BTC/USDT".to_string(),
+                exchange_id: ask_id,
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(cancelled.exchange_id, ask_id);
+        assert_eq!(cancelled.status, proto::OrderStatus::Cancelled as i32);
+        assert_eq!(cancelled.filled_quantity, "2");
+        assert!(books.next().await.unwrap().unwrap().asks.is_empty());
+
+        let status = client
+            .submit_order(order(Side::Unspecified, "100", "1"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        let status = client
+            .cancel_order(proto::CancelOrderRequest {
+                pair: "BTC/USDT".to_string(),
+                exchange_id: 42,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_trade_filter_and_draining() {
+        let (engine, mut client) = connect().await;
+        let mut trades = client
+            .stream_trades(proto::StreamTradesRequest {
+                pair: "ETH/USDT".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+
+        for pair in ["BTC/USDT", "ETH/USDT"] {
+            for side in [Side::Ask, Side::Bid] {
+                let mut submitted = order(side, "100", "1");
+                submitted.pair = pair.to_string();
+                client.submit_order(submitted).await.unwrap();
+            }
+        }
+        // BTC's trade was published first but filtered out.
+        assert_eq!(trades.next().await.unwrap().unwrap().pair, "ETH/USDT");
+
+        engine.drain().await.unwrap();
+        let status = client
+            .submit_order(order(Side::Bid, "100", "1"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+        assert!(status.message().starts_with(DRAINING));
+    }
+}
@@ -0,0 +1,269 @@
+//! Books as they stood at any point of a recording, for research.
+//!
+//! The recording is the engine's command journal; see `replay`. To answer a
+//! query the nearest checkpoint at or before the requested point is
+//! restored into a fresh engine and the commands after it are replayed.
+//! Checkpoints are plain engine snapshots, so the caveats of `replay::verify`
+//! apply: state they don't carry, such as price bands or fee tiers, is
+//! rebuilt only from the commands after the checkpoint.
+
+use crate::{
+    limit_order_book::order::LimitOrderBook,
+    matching_engine::{
+        engine::{MatchingEngine, TradingPair},
+        snapshot::EngineSnapshot,
+    },
+    replay::{self, RecordedCommand},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+
+/// A point in the recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsOf {
+    /// After this many commands.
+    Sequence(usize),
+    /// After every command received at or before this time.
+    Time(DateTime<Utc>),
+}
+
+/// The engine as it stood after `sequence` commands.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub sequence: usize,
+    pub snapshot: EngineSnapshot,
+}
+
+/// A reconstructed book. It derefs to the book for reading but can't be
+/// changed.
+#[derive(Debug, Clone)]
+pub struct HistoricalBook {
+    pub pair: TradingPair,
+    pub sequence: usize,
+    book: LimitOrderBook,
+}
+
+impl Deref for HistoricalBook {
+    type Target = LimitOrderBook;
+
+    fn deref(&self) -> &LimitOrderBook {
+        &self.book
+    }
+}
+
+pub struct History<F> {
+    new_engine: F,
+    commands: Vec<RecordedCommand>,
+    /// Sorted by sequence.
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl<F: Fn() -> MatchingEngine> History<F> {
+    /// `commands` must be in the order the engine received them, which
+    /// `new_engine` must be configured for.
+    pub fn new(new_engine: F, commands: Vec<RecordedCommand>) -> Self {
+        Self {
+            new_engine,
+            commands,
+            checkpoints: Vec::new(),
+        }
+    }
+
+    /// Uses checkpoints taken while the recording was made.
+    pub fn with_checkpoints(mut self, mut checkpoints: Vec<Checkpoint>) -> Self {
+        checkpoints.sort_by_key(|checkpoint| checkpoint.sequence);
+        self.checkpoints = checkpoints;
+        self
+    }
+
+    /// Replays the recording once, taking a checkpoint every `every`
+    /// commands so later queries replay at most that many.
+    pub fn checkpoint_every(mut self, every: usize) -> Self {
+        self.checkpoints.clear();
+        if every == 0 {
+            return self;
+        }
+        let mut engine = (self.new_engine)();
+        for (index, chunk) in self.commands.chunks(every).enumerate() {
+            replay::run(&mut engine, chunk, &mut Vec::new());
+            self.checkpoints.push(Checkpoint {
+                sequence: index * every + chunk.len(),
+                snapshot: engine.snapshot(),
+            });
+        }
+        self
+    }
+
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    /// The number of commands applied as of `as_of`.
+    pub fn sequence(&self, as_of: AsOf) -> Result<usize, String> {
+        match as_of {
+            AsOf::Sequence(sequence) if sequence > self.commands.len() => Err(format!(
+                "Sequence {} is past the recording's {} commands",
+                sequence,
+                self.commands.len()
+            )),
+            AsOf::Sequence(sequence) => Ok(sequence),
+            AsOf::Time(time) => Ok(self
+                .commands
+                .partition_point(|recorded| recorded.time <= time)),
+        }
+    }
+
+    /// A fresh engine in the state the recorded one was in as of `as_of`.
+    pub fn engine_at(&self, as_of: AsOf) -> Result<MatchingEngine, String> {
+        let sequence = self.sequence(as_of)?;
+        let mut engine = (self.new_engine)();
+        let start = match self
+            .checkpoints
+            .iter()
+            .rev()
+            .find(|checkpoint| checkpoint.sequence <= sequence)
+        {
+            Some(checkpoint) => {
+                engine.restore(&checkpoint.snapshot)?;
+                checkpoint.sequence
+            }
+            None => 0,
+        };
+        replay::run(
+            &mut engine,
+            &self.commands[start..sequence],
+            &mut Vec::new(),
+        );
+        Ok(engine)
+    }
+
+    pub fn book_at(&self, pair: &TradingPair, as_of: AsOf) -> Result<HistoricalBook, String> {
+        let engine = self.engine_at(as_of)?;
+        let book = engine
+            .orderbook(pair)
+            .ok_or_else(|| format!("No orderbook for trading pair: {}", pair))?
+            .clone();
+        Ok(HistoricalBook {
+            pair: pair.clone(),
+            sequence: self.sequence(as_of)?,
+            book,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::{CancelOrderRequest, Command, NewOrderRequest},
+        limit_order_book::order::{OrderType, TimeInForce},
+    };
+    use chrono::{Duration, TimeZone};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn engine() -> MatchingEngine {
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(TradingPair::new("BTC".to_string(), "USDT".to_string()));
+        engine
+    }
+
+    /// Five commands a second apart from `start`; the fourth cancels the
+    /// first.
+    fn commands(start: DateTime<Utc>) -> Vec<RecordedCommand> {
+        let new = |side: OrderType, price: Decimal| {
+            Command::New(NewOrderRequest {
+                pair: "BTC/USDT".to_string(),
+                side,
+                price,
+                quantity: dec!(1),
+                client: "alice".to_string(),
+                short_sale: false,
+                reduce_only: false,
+                client_order_id: None,
+                strategy: None,
+                time_in_force: TimeInForce::Gtc,
+            })
+        };
+        [
+            new(OrderType::Bid, dec!(99)),
+            new(OrderType::Ask, dec!(101)),
+            new(OrderType::Bid, dec!(100)),
+            Command::Cancel(CancelOrderRequest {
+                pair: "BTC/USDT".to_string(),
+                exchange_id: 1,
+            }),
+            new(OrderType::Bid, dec!(101)),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(index, command)| RecordedCommand {
+            time: start + Duration::seconds(index as i64),
+            command,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_book_at() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let commands = commands(start);
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+
+        let plain = History::new(engine, commands.clone());
+        let checkpointed = History::new(engine, commands).checkpoint_every(2);
+        assert_eq!(
+            checkpointed
+                .checkpoints()
+                .iter()
+                .map(|checkpoint| checkpoint.sequence)
+                .collect::<Vec<_>>(),
+            vec![2, 4, 5]
+        );
+        for sequence in 0..=5 {
+            let expected = plain.book_at(&pair, AsOf::Sequence(sequence)).unwrap();
+            let actual = checkpointed
+                .book_at(&pair, AsOf::Sequence(sequence))
+                .unwrap();
+            assert_eq!(actual.resting_orders(), expected.resting_orders());
+        }
+
+        let book = checkpointed
+            .book_at(&pair, AsOf::Time(start + Duration::milliseconds(3500)))
+            .unwrap();
+        assert_eq!(book.sequence, 4);
+        assert_eq!(
+            (book.get_best_bid(), book.get_best_ask()),
+            (Some(dec!(100)), Some(dec!(101)))
+        );
+        let before = checkpointed
+            .book_at(&pair, AsOf::Time(start - Duration::seconds(1)))
+            .unwrap();
+        assert!(before.orders.is_empty());
+        assert!(checkpointed.book_at(&pair, AsOf::Sequence(6)).is_err());
+    }
+
+    #[test]
+    fn test_supplied_checkpoints() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let taken = History::new(engine, commands(start)).checkpoint_every(3);
+        let mut checkpoints = taken.checkpoints().to_vec();
+        checkpoints.reverse();
+        let json = serde_json::to_string(&checkpoints).unwrap();
+        let checkpoints: Vec<Checkpoint> = serde_json::from_str(&json).unwrap();
+
+        // Loaded out of order, they are sorted before use.
+        let history = History::new(engine, commands(start)).with_checkpoints(checkpoints);
+        assert_eq!(history.checkpoints()[0].sequence, 3);
+        let book = history.book_at(&pair, AsOf::Sequence(4)).unwrap();
+        assert_eq!(book.get_best_bid(), Some(dec!(100)));
+        assert!(book.get_order(1).is_none());
+        assert_eq!(history.sequence(AsOf::Time(start)), Ok(1));
+
+        let unknown = TradingPair::new("ETH".to_string(), "USDT".to_string());
+        assert!(history.book_at(&unknown, AsOf::Sequence(1)).is_err());
+        assert!(history.checkpoint_every(0).checkpoints().is_empty());
+    }
+}
@@ -0,0 +1,530 @@
+//! Loading order flow exported from other systems.
+//!
+//! The CSV format has a header row naming its columns, in any order:
+//!
+//! ```text
+//! time,type,order_id,pair,side,price,quantity,client
+//! 2024-01-02T09:30:00Z,new,A1,BTC/USDT,buy,100.5,2,alice
+//! 2024-01-02T09:30:01Z,modify,A1,BTC/USDT,,100.0,3,
+//! 1704187802000,cancel,A1,BTC/USDT,,,,
+//! ```
+//!
+//! `time` is RFC 3339 or Unix milliseconds. `order_id` is the source
+//! system's ID; it is mapped to the exchange ID assigned on replay. `side`,
+//! `price` and `quantity` are required for `new`, `price` and `quantity` for
+//! `modify`. `client` is optional.
+
+use crate::{
+    api::{CancelOrderRequest, NewOrderRequest, NewOrderResponse, OrderReport},
+    limit_order_book::order::{OrderType, TimeInForce},
+};
+use chrono::{DateTime, TimeZone, Utc};
+use rust_decimal::Decimal;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt,
+    io::BufRead,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportedAction {
+    New {
+        side: OrderType,
+        price: Decimal,
+        quantity: Decimal,
+        client: String,
+    },
+    /// Changes the price and total quantity of a live order.
+    Modify {
+        price: Decimal,
+        quantity: Decimal,
+    },
+    Cancel,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedEvent {
+    /// 1-based line in the source file.
+    pub line: usize,
+    pub time: DateTime<Utc>,
+    pub order_id: String,
+    pub pair: String,
+    pub action: ImportedAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl ImportError {
+    pub(crate) fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Parses every row it can. Rows that fail validation are reported and
+/// skipped; a missing or unusable header fails the whole file.
+pub fn read_csv(reader: impl BufRead) -> (Vec<ImportedEvent>, Vec<ImportError>) {
+    let mut events = Vec::new();
+    let mut errors = Vec::new();
+    let mut header: Option<HashMap<String, usize>> = None;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + 1;
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                errors.push(ImportError::new(line_number, err.to_string()));
+                break;
+            }
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = match split_line(&line) {
+            Ok(fields) => fields,
+            Err(message) => {
+                errors.push(ImportError::new(line_number, message));
+                continue;
+            }
+        };
+
+        let columns = match &header {
+            Some(columns) => columns,
+            None => {
+                let columns: HashMap<String, usize> = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(position, name)| (name.trim().to_ascii_lowercase(), position))
+                    .collect();
+                let missing: Vec<&str> = ["time", "type", "order_id", "pair"]
+                    .into_iter()
+                    .filter(|name| !columns.contains_key(*name))
+                    .collect();
+                if !missing.is_empty() {
+                    errors.push(ImportError::new(
+                        line_number,
+                        format!("Header is missing columns: {}", missing.join(", ")),
+                    ));
+                    return (events, errors);
+                }
+                header = Some(columns);
+                continue;
+            }
+        };
+
+        match parse_row(columns, &fields, line_number) {
+            Ok(event) => events.push(event),
+            Err(message) => errors.push(ImportError::new(line_number, message)),
+        }
+    }
+
+    if header.is_none() && errors.is_empty() {
+        errors.push(ImportError::new(0, "File has no header row"));
+    }
+    (events, errors)
+}
+
+pub(crate) fn split_line(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field".to_string());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+fn parse_row(
+    columns: &HashMap<String, usize>,
+    fields: &[String],
+    line: usize,
+) -> Result<ImportedEvent, String> {
+    let field = |name: &str| -> Option<&str> {
+        columns
+            .get(name)
+            .and_then(|position| fields.get(*position))
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    };
+    let required = |name: &str| field(name).ok_or_else(|| format!("Missing {}", name));
+    let decimal = |name: &str| -> Result<Decimal, String> {
+        let value = required(name)?;
+        let parsed: Decimal = value
+            .parse()
+            .map_err(|_| format!("Invalid {}: {:?}", name, value))?;
+        if parsed <= Decimal::ZERO {
+            return Err(format!("{} must be positive: {}", name, value));
+        }
+        Ok(parsed)
+    };
+
+    let time = parse_time(required("time")?)?;
+    let order_id = required("order_id")?.to_string();
+    let pair = required("pair")?.to_string();
+    let action = match required("type")?.to_ascii_lowercase().as_str() {
+        "new" => ImportedAction::New {
+            side: required("side")?.parse()?,
+            price: decimal("price")?,
+            quantity: decimal("quantity")?,
+            client: field("client").unwrap_or_default().to_string(),
+        },
+        "modify" => ImportedAction::Modify {
+            price: decimal("price")?,
+            quantity: decimal("quantity")?,
+        },
+        "cancel" => ImportedAction::Cancel,
+        other => return Err(format!("Invalid type: {:?}", other)),
+    };
+
+    Ok(ImportedEvent {
+        line,
+        time,
+        order_id,
+        pair,
+        action,
+    })
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(millis) = value.parse::<i64>() {
+        return Utc
+            .timestamp_millis_opt(millis)
+            .single()
+            .ok_or_else(|| format!("Invalid time: {:?}", value));
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| format!("Invalid time: {:?}", value))
+}
+
+struct LiveOrder {
+    exchange_id: u64,
+    side: OrderType,
+    client: String,
+}
+
+/// Sends `events` in time order through `new_order` and `cancel_order`,
+/// translating the file's order IDs into the exchange IDs handed back on
+/// entry. A modify is sent as a cancel followed by a new order for the
+/// unfilled part of the new quantity, so it loses queue priority. Events that
+/// cannot be applied are reported and skipped.
+pub fn replay<N, C>(
+    events: &[ImportedEvent],
+    mut new_order: N,
+    mut cancel_order: C,
+) -> Vec<ImportError>
+where
+    N: FnMut(NewOrderRequest) -> Result<NewOrderResponse, String>,
+    C: FnMut(CancelOrderRequest) -> Result<OrderReport, String>,
+{
+    let mut ordered: Vec<&ImportedEvent> = events.iter().collect();
+    ordered.sort_by_key(|event| event.time);
+
+    let mut live: HashMap<(String, String), LiveOrder> = HashMap::new();
+    let mut errors = Vec::new();
+
+    for event in ordered {
+        let key = (event.pair.clone(), event.order_id.clone());
+        let result = match &event.action {
+            ImportedAction::New {
+                side,
+                price,
+                quantity,
+                client,
+            } => match live.entry(key) {
+                Entry::Occupied(_) => Err(format!("Duplicate order_id: {}", event.order_id)),
+                Entry::Vacant(entry) => new_order(NewOrderRequest {
+                    pair: event.pair.clone(),
+                    side: *side,
+                    price: *price,
+                    quantity: *quantity,
+                    client: client.clone(),
+                    short_sale: false,
+                    reduce_only: false,
+                    client_order_id: None,
+                    strategy: None,
+                    time_in_force: TimeInForce::Gtc,
+                })
+                .map(|response| {
+                    entry.insert(LiveOrder {
+                        exchange_id: response.order.exchange_id,
+                        side: *side,
+                        client: client.clone(),
+                    });
+                }),
+            },
+            ImportedAction::Cancel => match live.remove(&key) {
+                Some(order) => cancel_order(CancelOrderRequest {
+                    pair: event.pair.clone(),
+                    exchange_id: order.exchange_id,
+                })
+                .map(|_| ()),
+                None => Err(format!("Unknown order_id: {}", event.order_id)),
+            },
+            ImportedAction::Modify { price, quantity } => match live.remove(&key) {
+                Some(order) => cancel_order(CancelOrderRequest {
+                    pair: event.pair.clone(),
+                    exchange_id: order.exchange_id,
+                })
+                .and_then(|cancelled| {
+                    let remaining = *quantity - cancelled.filled_quantity;
+                    if remaining <= Decimal::ZERO {
+                        return Err(format!(
+                            "Order {} has already filled {}",
+                            event.order_id, cancelled.filled_quantity
+                        ));
+                    }
+                    let response = new_order(NewOrderRequest {
+                        pair: event.pair.clone(),
+                        side: order.side,
+                        price: *price,
+                        quantity: remaining,
+                        client: order.client.clone(),
+                        short_sale: false,
+                        reduce_only: false,
+                        client_order_id: None,
+                        strategy: None,
+                        time_in_force: TimeInForce::Gtc,
+                    })?;
+                    live.insert(
+                        key,
+                        LiveOrder {
+                            exchange_id: response.order.exchange_id,
+                            ..order
+                        },
+                    );
+                    Ok(())
+                }),
+                None => Err(format!("Unknown order_id: {}", event.order_id)),
+            },
+        };
+        if let Err(message) = result {
+            errors.push(ImportError::new(event.line, message));
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        limit_order_book::order::Order,
+        matching_engine::engine::{MatchingEngine, TradingPair},
+    };
+    use rust_decimal_macros::dec;
+    use std::cell::RefCell;
+
+    const CSV: &str = "\
+time,type,order_id,pair,side,price,quantity,client
+2024-01-02T09:30:00Z,new,A1,BTC/USDT,sell,101,5,alice
+2024-01-02T09:30:01Z,new,B1,BTC/USDT,buy,101,2,bob
+2024-01-02T09:30:02Z,modify,A1,BTC/USDT,,102,4,
+1704187803000,new,B2,BTC/USDT,buy,99,1,\"bob, again\"
+2024-01-02T09:30:04Z,cancel,B9,BTC/USDT,,,,
+2024-01-02T09:30:05Z,new,C1,BTC/USDT,hold,99,1,
+not a time,new,C2,BTC/USDT,buy,99,1,
+2024-01-02T09:30:06Z,new,C3,BTC/USDT,buy,99,-1,
+";
+
+    #[test]
+    fn test_read_csv_reports_bad_rows() {
+        let (events, errors) = read_csv(CSV.as_bytes());
+        assert_eq!(events.len(), 5);
+        assert_eq!(
+            events[3].action,
+            ImportedAction::New {
+                side: OrderType::Bid,
+                price: dec!(99),
+                quantity: dec!(1),
+                client: "bob, again".to_string(),
+            }
+        );
+        let lines: Vec<usize> = errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, vec![7, 8, 9]);
+        assert_eq!(errors[0].to_string(), "line 7: Invalid side: \"hold\"");
+
+        let (_, errors) = read_csv("time,pair\n".as_bytes());
+        assert_eq!(
+            errors[0].message,
+            "Header is missing columns: type, order_id"
+        );
+    }
+
+    #[test]
+    fn test_replay_into_engine() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let engine = RefCell::new(MatchingEngine::new());
+        engine.borrow_mut().add_new_market(pair.clone());
+
+        let (events, _) = read_csv(CSV.as_bytes());
+        let errors = replay(
+            &events,
+            |request| {
+                let mut engine = engine.borrow_mut();
+                let order = Order::new(
+                    request.pair.clone(),
+                    engine.next_exchange_id(),
+                    request.side,
+                    request.quantity,
+                    request.price,
+                    Utc::now(),
+                    Utc::now(),
+                )
+                .with_client(request.client);
+                let (order, fills) = engine.place_limit_order(pair.clone(), order)?;
+                Ok(NewOrderResponse {
+                    order: OrderReport::new(request.pair, &order),
+                    fills,
+                })
+            },
+            |request| {
+                let order = engine
+                    .borrow_mut()
+                    .cancel_order(&pair, request.exchange_id)?;
+                Ok(OrderReport::new(request.pair, &order))
+            },
+        );
+
+        assert_eq!(errors, vec![ImportError::new(6, "Unknown order_id: B9")]);
+        let engine = engine.borrow();
+        let book = engine.orderbook(&pair).unwrap();
+        // A1 sold 2 of its 4 before being moved to 102.
+        assert_eq!(book.get_ask_depth(dec!(102)), dec!(2));
+        assert_eq!(book.get_ask_depth(dec!(101)), dec!(0));
+        assert_eq!(book.get_bid_depth(dec!(99)), dec!(1));
+        let moved = book.get_order(3).unwrap();
+        assert_eq!(moved.client, "alice");
+    }
+
+    #[test]
+    fn test_fields_and_header() {
+        assert_eq!(
+            split_line(r#"a,"b, ""c""",,d"#).unwrap(),
+            vec!["a", r#"b, "c""#, "", "d"]
+        );
+        assert!(split_line(r#"a,"b"#).is_err());
+        assert_eq!(
+            parse_time("1704187800000").unwrap(),
+            parse_time("2024-01-02T09:30:00Z").unwrap()
+        );
+        assert_eq!(
+            parse_time("2024-01-02T10:30:00+01:00").unwrap(),
+            parse_time("2024-01-02T09:30:00Z").unwrap()
+        );
+        assert!(parse_time("2024-01-02").is_err());
+
+        // Columns in any order and case, with comments and blank lines.
+        let csv = "\
+# exported from somewhere
+
+PAIR,Order_ID,Type,Time
+BTC/USDT,X,cancel,1704187800000
+";
+        let (events, errors) = read_csv(csv.as_bytes());
+        assert!(errors.is_empty());
+        assert_eq!(
+            events,
+            vec![ImportedEvent {
+                line: 4,
+                time: parse_time("2024-01-02T09:30:00Z").unwrap(),
+                order_id: "X".to_string(),
+                pair: "BTC/USDT".to_string(),
+                action: ImportedAction::Cancel,
+            }]
+        );
+
+        let (events, errors) = read_csv("# nothing here\n".as_bytes());
+        assert!(events.is_empty());
+        assert_eq!(errors, vec![ImportError::new(0, "File has no header row")]);
+        let (_, errors) = read_csv("time,type,order_id,pair\n0,close,X,BTC/USDT\n".as_bytes());
+        assert_eq!(errors[0].message, "Invalid type: \"close\"");
+    }
+
+    #[test]
+    fn test_replay_rejects_duplicates_and_filled_modifies() {
+        let csv = "\
+time,type,order_id,pair,side,price,quantity
+3,modify,A1,BTC/USDT,,100,2
+1,new,A1,BTC/USDT,buy,100,2
+2,new,A1,BTC/USDT,buy,100,2
+4,modify,A2,BTC/USDT,,100,2
+";
+        let (events, _) = read_csv(csv.as_bytes());
+        let placed = RefCell::new(Vec::new());
+        let errors = replay(
+            &events,
+            |request| {
+                let mut placed = placed.borrow_mut();
+                placed.push(request.clone());
+                let order = Order::new(
+                    request.pair.clone(),
+                    placed.len() as u64,
+                    request.side,
+                    request.quantity,
+                    request.price,
+                    Utc::now(),
+                    Utc::now(),
+                );
+                Ok(NewOrderResponse {
+                    order: OrderReport::new(request.pair, &order),
+                    fills: vec![],
+                })
+            },
+            // The order filled in full before the modify reached it.
+            |request| {
+                let mut order = Order::new(
+                    request.pair.clone(),
+                    request.exchange_id,
+                    OrderType::Bid,
+                    dec!(2),
+                    dec!(100),
+                    Utc::now(),
+                    Utc::now(),
+                );
+                order.filled_quantity = dec!(2);
+                Ok(OrderReport::new(request.pair, &order))
+            },
+        );
+
+        // Replayed in time order, not file order.
+        assert_eq!(
+            errors,
+            vec![
+                ImportError::new(4, "Duplicate order_id: A1"),
+                ImportError::new(2, "Order A1 has already filled 2"),
+                ImportError::new(5, "Unknown order_id: A2"),
+            ]
+        );
+        assert_eq!(placed.borrow().len(), 1);
+    }
+}
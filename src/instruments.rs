@@ -0,0 +1,235 @@
+//! Reference data for what each trading pair represents.
+//!
+//! Pairs without an entry are treated as spot, where one unit of quantity is
+//! one unit of the base asset.
+
+use crate::matching_engine::engine::TradingPair;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetClass {
+    #[default]
+    Spot,
+    Equity,
+    Future,
+    Perpetual,
+}
+
+/// ```toml
+/// [[instruments]]
+/// pair = "BTC-MAR25/USD"
+/// asset_class = "future"
+/// multiplier = "0.1"
+/// expiry = "2025-03-28T08:00:00Z"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Instrument {
+    pub pair: TradingPair,
+    #[serde(default)]
+    pub asset_class: AssetClass,
+    /// Units of the base asset per unit of quantity.
+    #[serde(default = "default_multiplier")]
+    pub multiplier: Decimal,
+    /// When a future stops trading.
+    pub expiry: Option<DateTime<Utc>>,
+}
+
+fn default_multiplier() -> Decimal {
+    Decimal::ONE
+}
+
+impl Instrument {
+    pub fn spot(pair: TradingPair) -> Self {
+        Self {
+            pair,
+            asset_class: AssetClass::Spot,
+            multiplier: Decimal::ONE,
+            expiry: None,
+        }
+    }
+
+    pub fn future(pair: TradingPair, multiplier: Decimal, expiry: DateTime<Utc>) -> Self {
+        Self {
+            pair,
+            asset_class: AssetClass::Future,
+            multiplier,
+            expiry: Some(expiry),
+        }
+    }
+
+    pub fn base(&self) -> &str {
+        self.pair.base()
+    }
+
+    /// The currency prices and notionals are in.
+    pub fn quote(&self) -> &str {
+        self.pair.quote()
+    }
+
+    /// Value in the quote currency of `quantity` at `price`.
+    pub fn notional(&self, price: Decimal, quantity: Decimal) -> Decimal {
+        price * quantity * self.multiplier
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        matches!(self.expiry, Some(expiry) if now >= expiry)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let pair = self.pair.to_string();
+        if self.multiplier <= Decimal::ZERO {
+            return Err(format!("{}: invalid multiplier {}", pair, self.multiplier));
+        }
+        match (self.asset_class, self.expiry) {
+            (AssetClass::Future, None) => Err(format!("{}: futures need an expiry", pair)),
+            (AssetClass::Future, Some(_)) | (_, None) => Ok(()),
+            (_, Some(_)) => Err(format!("{}: only futures expire", pair)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Instruments {
+    instruments: HashMap<TradingPair, Instrument>,
+}
+
+impl Instruments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry of the configured instruments, each pair at most once.
+    pub fn from_config(instruments: &[Instrument]) -> Result<Self, String> {
+        let mut registry = Self::new();
+        for instrument in instruments {
+            if registry.get(&instrument.pair).is_some() {
+                return Err(format!("{}: listed twice", instrument.pair));
+            }
+            registry.insert(instrument.clone())?;
+        }
+        Ok(registry)
+    }
+
+    /// Adds or replaces the entry for the instrument's pair.
+    pub fn insert(&mut self, instrument: Instrument) -> Result<(), String> {
+        instrument.validate()?;
+        self.instruments.insert(instrument.pair.clone(), instrument);
+        Ok(())
+    }
+
+    pub fn get(&self, pair: &TradingPair) -> Option<&Instrument> {
+        self.instruments.get(pair)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Instrument> {
+        self.instruments.values()
+    }
+
+    /// The pair's multiplier, one if it isn't registered.
+    pub fn multiplier(&self, pair: &TradingPair) -> Decimal {
+        self.get(pair)
+            .map_or(Decimal::ONE, |instrument| instrument.multiplier)
+    }
+
+    /// Value in the pair's quote currency of `quantity` at `price`,
+    /// saturating at `Decimal::MAX` since it feeds running totals.
+    pub fn notional(&self, pair: &TradingPair, price: Decimal, quantity: Decimal) -> Decimal {
+        price
+            .saturating_mul(quantity)
+            .saturating_mul(self.multiplier(pair))
+    }
+
+    pub fn is_expired(&self, pair: &TradingPair, now: DateTime<Utc>) -> bool {
+        self.get(pair)
+            .is_some_and(|instrument| instrument.is_expired(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_instrument_registry() {
+        let spot = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let future = TradingPair::new("BTC-MAR25".to_string(), "USD".to_string());
+        let expiry = Utc.with_ymd_and_hms(2025, 3, 28, 8, 0, 0).unwrap();
+        let mut instruments = Instruments::new();
+        instruments
+            .insert(Instrument::future(future.clone(), dec!(0.1), expiry))
+            .unwrap();
+
+        assert_eq!(instruments.notional(&spot, dec!(100), dec!(3)), dec!(300));
+        assert_eq!(instruments.notional(&future, dec!(100), dec!(3)), dec!(30));
+        assert_eq!(instruments.get(&future).unwrap().quote(), "USD");
+        assert!(!instruments.is_expired(&future, expiry - chrono::Duration::seconds(1)));
+        assert!(instruments.is_expired(&future, expiry));
+        assert!(!instruments.is_expired(&spot, expiry));
+
+        let mut undated = Instrument::future(spot.clone(), dec!(1), expiry);
+        undated.expiry = None;
+        assert!(instruments.insert(undated).is_err());
+        let mut expiring = Instrument::spot(spot.clone());
+        expiring.expiry = Some(expiry);
+        assert!(instruments.insert(expiring).is_err());
+        let mut empty = Instrument::spot(spot);
+        empty.multiplier = dec!(0);
+        assert!(instruments.insert(empty).is_err());
+    }
+
+    #[derive(Deserialize)]
+    struct Config {
+        instruments: Vec<Instrument>,
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [[instruments]]
+            pair = "BTC-MAR25/USD"
+            asset_class = "future"
+            multiplier = "0.1"
+            expiry = "2025-03-28T08:00:00Z"
+
+            [[instruments]]
+            pair = "AAPL/USD"
+            asset_class = "equity"
+            "#,
+        )
+        .unwrap();
+        let instruments = Instruments::from_config(&config.instruments).unwrap();
+        let equity = TradingPair::new("AAPL".to_string(), "USD".to_string());
+        assert_eq!(instruments.iter().count(), 2);
+        assert_eq!(
+            instruments.get(&equity).unwrap().asset_class,
+            AssetClass::Equity
+        );
+        assert_eq!(instruments.multiplier(&equity), dec!(1));
+        assert_eq!(
+            instruments.multiplier(&"BTC-MAR25/USD".parse().unwrap()),
+            dec!(0.1)
+        );
+        // Notionals saturate instead of overflowing.
+        assert_eq!(
+            instruments.notional(&equity, Decimal::MAX, dec!(2)),
+            Decimal::MAX
+        );
+
+        let twice = [config.instruments[1].clone(), config.instruments[1].clone()];
+        assert!(Instruments::from_config(&twice)
+            .unwrap_err()
+            .contains("listed twice"));
+        assert!(
+            toml::from_str::<Config>("[[instruments]]\npair = \"AAPL/USD\"\ntick = \"0.01\"")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,609 @@
+//! The command journal: every order and cancel the server takes, one line
+//! each in the recording format of `replay`, written before it is applied.
+//!
+//! ```toml
+//! [persistence]
+//! journal_path = "data/journal.log"
+//! rotation = { max_bytes = 104857600, max_age_secs = 86400, compression = "gzip" }
+//! ```
+//!
+//! Without rotation the journal is one file that grows for as long as the
+//! engine runs. With it, once `journal_path` holds `max_bytes` or spans
+//! `max_age_secs`, it is closed as a segment named after its first
+//! sequence number, e.g. `journal.000000001000.log.gz`, compressed if
+//! asked, and listed in `journal.index` with the range of sequence numbers
+//! and times it covers. `read` uses the index to open only the segments a
+//! time range overlaps. Age is measured in command time, so a simulation
+//! rotates at the same commands however fast it runs. `zstd` compression
+//! needs the `zstd` feature.
+//!
+//! `record_hash` notes the engine's state hash after the commands so far in
+//! `journal.hashes`, which `recovery` checks its replay against.
+
+use crate::replay::{self, RecordedCommand};
+use chrono::{DateTime, Duration, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsStr,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    ops::{Bound, RangeBounds},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn extension(self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+            Compression::Zstd => Some("zst"),
+        }
+    }
+}
+
+/// When to close the journal file and start a new one. Either limit left
+/// unset doesn't apply.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rotation {
+    pub max_bytes: Option<u64>,
+    /// Between the first command in the file and the next one to be added.
+    pub max_age_secs: Option<u64>,
+    /// Applied to each file as it is closed.
+    #[serde(default)]
+    pub compression: Compression,
+}
+
+/// A closed journal file, as listed in the index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Segment {
+    /// File name, in the journal's directory.
+    pub file: String,
+    pub compression: Compression,
+    /// Sequence number of the first command, counting every command the
+    /// journal has held from 0.
+    pub first_sequence: u64,
+    pub commands: u64,
+    pub first_time: DateTime<Utc>,
+    pub last_time: DateTime<Utc>,
+}
+
+/// The engine's `state_hash` once the journal's first `sequence` commands
+/// had been applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashRecord {
+    pub sequence: u64,
+    pub state_hash: u64,
+}
+
+pub struct Journal {
+    path: PathBuf,
+    rotation: Option<Rotation>,
+    writer: BufWriter<File>,
+    segments: Vec<Segment>,
+    /// What the file at `path` holds.
+    commands: u64,
+    bytes: u64,
+    first_time: Option<DateTime<Utc>>,
+    last_time: Option<DateTime<Utc>>,
+    /// Sequence of the latest hash record.
+    hashed: Option<u64>,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, carrying on after whatever it and its
+    /// index already hold.
+    pub fn open(path: impl Into<PathBuf>, rotation: Option<Rotation>) -> io::Result<Self> {
+        let path = path.into();
+        let compression = rotation.map(|rotation| rotation.compression);
+        if cfg!(not(feature = "zstd")) && compression == Some(Compression::Zstd) {
+            return Err(zstd_unsupported());
+        }
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let mut journal = Self {
+            segments: read_index(&path)?,
+            bytes: file.metadata()?.len(),
+            writer: BufWriter::new(file),
+            rotation,
+            commands: 0,
+            first_time: None,
+            last_time: None,
+            hashed: read_hashes(&path)?.last().map(|record| record.sequence),
+            path,
+        };
+        for command in read_file(&journal.path, Compression::None)? {
+            journal.track(&command);
+        }
+        Ok(journal)
+    }
+
+    /// The number of commands journaled, across every segment.
+    pub fn sequence(&self) -> u64 {
+        self.segments
+            .last()
+            .map_or(0, |segment| segment.first_sequence + segment.commands)
+            + self.commands
+    }
+
+    /// Closed segments, oldest first.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Writes `command` through to the file, first rotating if the file
+    /// has reached a limit.
+    pub fn append(&mut self, command: &RecordedCommand) -> io::Result<()> {
+        if self.rotation_due(command.time) {
+            self.rotate()?;
+        }
+        let mut line = serde_json::to_vec(command)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        self.writer.flush()?;
+        self.bytes += line.len() as u64;
+        self.track(command);
+        Ok(())
+    }
+
+    /// Flushes the file to disk.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()
+    }
+
+    /// The sequence of the latest hash record.
+    pub fn hashed(&self) -> Option<u64> {
+        self.hashed
+    }
+
+    /// Records `state_hash` as the engine's state after every command
+    /// journaled so far, unless that has been recorded already.
+    pub fn record_hash(&mut self, state_hash: u64) -> io::Result<()> {
+        let sequence = self.sequence();
+        if self.hashed == Some(sequence) {
+            return Ok(());
+        }
+        let mut hashes = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(hashes_path(&self.path))?;
+        let mut line = serde_json::to_vec(&HashRecord {
+            sequence,
+            state_hash,
+        })?;
+        line.push(b'\n');
+        hashes.write_all(&line)?;
+        self.hashed = Some(sequence);
+        Ok(())
+    }
+
+    /// Closes the file as a segment and starts an empty one, whatever its
+    /// size. Does nothing if it holds no commands.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        let (Some(first_time), Some(last_time)) = (self.first_time, self.last_time) else {
+            return Ok(());
+        };
+        self.writer.flush()?;
+        let compression = self
+            .rotation
+            .map_or(Compression::None, |rotation| rotation.compression);
+        let first_sequence = self.sequence() - self.commands;
+        let segment = Segment {
+            file: segment_name(&self.path, first_sequence, compression),
+            compression,
+            first_sequence,
+            commands: self.commands,
+            first_time,
+            last_time,
+        };
+        compress(
+            compression,
+            &self.path,
+            &self.path.with_file_name(&segment.file),
+        )?;
+        // Once the segment is in the index, the file's commands are
+        // duplicates and can go.
+        let mut index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(index_path(&self.path))?;
+        let mut line = serde_json::to_vec(&segment)?;
+        line.push(b'\n');
+        index.write_all(&line)?;
+        index.sync_data()?;
+        self.writer = BufWriter::new(File::create(&self.path)?);
+        self.segments.push(segment);
+        self.commands = 0;
+        self.bytes = 0;
+        self.first_time = None;
+        self.last_time = None;
+        Ok(())
+    }
+
+    fn rotation_due(&self, time: DateTime<Utc>) -> bool {
+        let (Some(rotation), Some(first_time)) = (self.rotation, self.first_time) else {
+            return false;
+        };
+        rotation.max_bytes.is_some_and(|max| self.bytes >= max)
+            || rotation
+                .max_age_secs
+                .is_some_and(|max| time - first_time >= Duration::seconds(max as i64))
+    }
+
+    fn track(&mut self, command: &RecordedCommand) {
+        self.commands += 1;
+        self.first_time.get_or_insert(command.time);
+        self.last_time = Some(command.time);
+    }
+}
+
+/// The segments of the journal at `path`, oldest first.
+pub fn read_index(path: &Path) -> io::Result<Vec<Segment>> {
+    let index = index_path(path);
+    if !index.exists() {
+        return Ok(Vec::new());
+    }
+    let mut segments = Vec::new();
+    for (number, line) in BufReader::new(File::open(&index)?).lines().enumerate() {
+        let segment = serde_json::from_str(&line?)
+            .map_err(|err| invalid_data(&index, format!("line {}: {}", number + 1, err)))?;
+        segments.push(segment);
+    }
+    Ok(segments)
+}
+
+/// The hashes recorded for the journal at `path`, oldest first.
+pub fn read_hashes(path: &Path) -> io::Result<Vec<HashRecord>> {
+    let hashes = hashes_path(path);
+    if !hashes.exists() {
+        return Ok(Vec::new());
+    }
+    let mut records = Vec::new();
+    for (number, line) in BufReader::new(File::open(&hashes)?).lines().enumerate() {
+        let record = serde_json::from_str(&line?)
+            .map_err(|err| invalid_data(&hashes, format!("line {}: {}", number + 1, err)))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// The commands received within `range`, in the order they were journaled,
+/// from the journal at `path` and its segments.
+pub fn read(
+    path: &Path,
+    range: impl RangeBounds<DateTime<Utc>>,
+) -> io::Result<Vec<RecordedCommand>> {
+    let mut commands = Vec::new();
+    for segment in read_index(path)? {
+        if overlaps(&range, segment.first_time, segment.last_time) {
+            commands.extend(read_file(
+                &path.with_file_name(&segment.file),
+                segment.compression,
+            )?);
+        }
+    }
+    if path.exists() {
+        commands.extend(read_file(path, Compression::None)?);
+    }
+    commands.retain(|command| range.contains(&command.time));
+    Ok(commands)
+}
+
+/// How many commands the journal at `path` holds in all, with those from
+/// sequence number `from` on, read from it and the segments that hold them.
+pub fn read_from(path: &Path, from: u64) -> io::Result<(u64, Vec<RecordedCommand>)> {
+    let mut commands = Vec::new();
+    let mut sequence = 0;
+    for segment in read_index(path)? {
+        sequence = segment.first_sequence + segment.commands;
+        if sequence > from {
+            let file = read_file(&path.with_file_name(&segment.file), segment.compression)?;
+            let skip = from.saturating_sub(segment.first_sequence) as usize;
+            commands.extend(file.into_iter().skip(skip));
+        }
+    }
+    if path.exists() {
+        let file = read_file(path, Compression::None)?;
+        let skip = from.saturating_sub(sequence) as usize;
+        sequence += file.len() as u64;
+        commands.extend(file.into_iter().skip(skip));
+    }
+    Ok((sequence, commands))
+}
+
+fn read_file(path: &Path, compression: Compression) -> io::Result<Vec<RecordedCommand>> {
+    let file = File::open(path)?;
+    let reader: Box<dyn BufRead> = match compression {
+        Compression::None => Box::new(BufReader::new(file)),
+        Compression::Gzip => Box::new(BufReader::new(GzDecoder::new(file))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::new(file)?)),
+        #[cfg(not(feature = "zstd"))]
+        Compression::Zstd => return Err(zstd_unsupported()),
+    };
+    replay::read_recording(reader).map_err(|err| invalid_data(path, err))
+}
+
+fn compress(compression: Compression, from: &Path, to: &Path) -> io::Result<()> {
+    let mut input = File::open(from)?;
+    let output = File::create(to)?;
+    let output = match compression {
+        Compression::None => {
+            let mut output = output;
+            io::copy(&mut input, &mut output)?;
+            output
+        }
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(output, flate2::Compression::default());
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            let mut encoder = zstd::Encoder::new(output, 0)?;
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?
+        }
+        #[cfg(not(feature = "zstd"))]
+        Compression::Zstd => return Err(zstd_unsupported()),
+    };
+    output.sync_all()
+}
+
+fn zstd_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "zstd compression needs a build with the `zstd` feature",
+    )
+}
+
+/// `data/journal.log` becomes `data/journal.index`.
+fn index_path(path: &Path) -> PathBuf {
+    path.with_extension("index")
+}
+
+/// `data/journal.log` becomes `data/journal.hashes`.
+fn hashes_path(path: &Path) -> PathBuf {
+    path.with_extension("hashes")
+}
+
+/// `data/journal.log` becomes `journal.000000001000.log.gz`.
+fn segment_name(path: &Path, first_sequence: u64, compression: Compression) -> String {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}.{:012}", stem, first_sequence);
+    let extensions = path
+        .extension()
+        .into_iter()
+        .chain(compression.extension().map(OsStr::new));
+    for extension in extensions {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    name
+}
+
+fn overlaps(
+    range: &impl RangeBounds<DateTime<Utc>>,
+    first: DateTime<Utc>,
+    last: DateTime<Utc>,
+) -> bool {
+    let after_start = match range.start_bound() {
+        Bound::Included(start) => last >= *start,
+        Bound::Excluded(start) => last > *start,
+        Bound::Unbounded => true,
+    };
+    let before_end = match range.end_bound() {
+        Bound::Included(end) => first <= *end,
+        Bound::Excluded(end) => first < *end,
+        Bound::Unbounded => true,
+    };
+    after_start && before_end
+}
+
+fn invalid_data(path: &Path, error: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), error),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{CancelOrderRequest, Command};
+    use chrono::TimeZone;
+
+    fn command(hour: u32) -> RecordedCommand {
+        RecordedCommand {
+            time: Utc.with_ymd_and_hms(2024, 1, 1, hour, 0, 0).unwrap(),
+            command: Command::Cancel(CancelOrderRequest {
+                pair: "BTC/USDT".to_string(),
+                exchange_id: hour as u64,
+            }),
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tradebot-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = temp_dir("journal");
+        let path = dir.join("journal.log");
+        let rotation = Rotation {
+            max_bytes: None,
+            max_age_secs: Some(4 * 3600),
+            compression: Compression::Gzip,
+        };
+        let mut journal = Journal::open(&path, Some(rotation)).unwrap();
+        for hour in 0..6 {
+            journal.append(&command(hour)).unwrap();
+        }
+        // Reopening carries on from the same sequence.
+        drop(journal);
+        let mut journal = Journal::open(&path, Some(rotation)).unwrap();
+        assert_eq!(journal.sequence(), 6);
+        for hour in 6..10 {
+            journal.append(&command(hour)).unwrap();
+        }
+
+        let segments = read_index(&path).unwrap();
+        assert_eq!(segments, journal.segments());
+        let files: Vec<&str> = segments
+            .iter()
+            .map(|segment| segment.file.as_str())
+            .collect();
+        assert_eq!(
+            files,
+            ["journal.000000000000.log.gz", "journal.000000000004.log.gz"]
+        );
+        assert_eq!(segments[1].commands, 4);
+        assert_eq!(segments[1].last_time, command(7).time);
+        assert!(dir.join(&segments[1].file).exists());
+
+        let all = read(&path, ..).unwrap();
+        assert_eq!(all, (0..10).map(command).collect::<Vec<_>>());
+        // Only the second segment and the open file overlap the range.
+        fs::remove_file(dir.join(&segments[0].file)).unwrap();
+        let tail = read(&path, command(5).time..command(9).time).unwrap();
+        assert_eq!(tail, (5..9).map(command).collect::<Vec<_>>());
+        let (held, tail) = read_from(&path, 6).unwrap();
+        assert_eq!(held, 10);
+        assert_eq!(tail, (6..10).map(command).collect::<Vec<_>>());
+        assert_eq!(read_from(&path, 11).unwrap(), (10, Vec::new()));
+
+        let max_bytes = Rotation {
+            max_bytes: Some(1),
+            ..rotation
+        };
+        let mut journal = Journal::open(&path, Some(max_bytes)).unwrap();
+        journal.append(&command(10)).unwrap();
+        assert_eq!(journal.segments().len(), 3);
+        assert_eq!(journal.sequence(), 11);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_hashes_and_manual_rotation() {
+        let dir = temp_dir("journal-hashes");
+        let path = dir.join("journal.log");
+        let mut journal = Journal::open(&path, None).unwrap();
+        // An empty file has nothing to close.
+        journal.rotate().unwrap();
+        assert!(journal.segments().is_empty());
+        journal.record_hash(11).unwrap();
+        for hour in 0..3 {
+            journal.append(&command(hour)).unwrap();
+        }
+        journal.record_hash(22).unwrap();
+        // A second hash at the same sequence is dropped.
+        journal.record_hash(33).unwrap();
+        journal.sync().unwrap();
+        drop(journal);
+
+        let mut journal = Journal::open(&path, None).unwrap();
+        assert_eq!(journal.hashed(), Some(3));
+        assert_eq!(
+            read_hashes(&path).unwrap(),
+            vec![
+                HashRecord {
+                    sequence: 0,
+                    state_hash: 11
+                },
+                HashRecord {
+                    sequence: 3,
+                    state_hash: 22
+                },
+            ]
+        );
+
+        // Without a rotation policy the journal only rotates when asked,
+        // and the segment isn't compressed.
+        journal.rotate().unwrap();
+        journal.append(&command(3)).unwrap();
+        let segment = &journal.segments()[0];
+        assert_eq!(segment.file, "journal.000000000000.log");
+        assert_eq!(segment.compression, Compression::None);
+        assert_eq!(
+            (segment.first_time, segment.last_time),
+            (command(0).time, command(2).time)
+        );
+        assert_eq!(
+            read_from(&path, 2).unwrap(),
+            (4, vec![command(2), command(3)])
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_names_and_ranges() {
+        let path = Path::new("data/journal.log");
+        assert_eq!(index_path(path), Path::new("data/journal.index"));
+        assert_eq!(hashes_path(path), Path::new("data/journal.hashes"));
+        assert_eq!(
+            segment_name(path, 1000, Compression::Zstd),
+            "journal.000000001000.log.zst"
+        );
+        assert_eq!(
+            segment_name(Path::new("journal"), 7, Compression::Gzip),
+            "journal.000000000007.gz"
+        );
+
+        let (first, last) = (command(2).time, command(4).time);
+        assert!(overlaps(&(..), first, last));
+        assert!(overlaps(&(command(4).time..), first, last));
+        assert!(!overlaps(&(command(5).time..), first, last));
+        assert!(overlaps(&(..=command(2).time), first, last));
+        assert!(!overlaps(&(..command(2).time), first, last));
+        assert!(!overlaps(
+            &(Bound::Excluded(command(4).time), Bound::Unbounded),
+            first,
+            last
+        ));
+    }
+
+    #[test]
+    fn test_missing_and_bad_files() {
+        let dir = temp_dir("journal-bad");
+        let path = dir.join("journal.log");
+        assert!(read(&path, ..).unwrap().is_empty());
+        assert_eq!(read_from(&path, 0).unwrap(), (0, Vec::new()));
+        assert!(read_hashes(&path).unwrap().is_empty());
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(index_path(&path), "{}\n").unwrap();
+        let error = read_index(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("journal.index: line 1:"));
+        assert!(Journal::open(&path, None).is_err());
+
+        if cfg!(not(feature = "zstd")) {
+            let rotation = Rotation {
+                compression: Compression::Zstd,
+                ..Rotation::default()
+            };
+            let error = Journal::open(&path, Some(rotation)).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
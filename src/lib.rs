@@ -0,0 +1,60 @@
+//! Limit order book and matching engine.
+//!
+//! Without the default `std` feature the crate is `no_std` and provides only
+//! `core_book`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod api;
+#[cfg(feature = "server")]
+pub mod book_stream;
+#[cfg(feature = "server")]
+pub mod client;
+#[cfg(feature = "std")]
+pub mod config;
+pub mod core_book;
+#[cfg(feature = "std")]
+pub mod export;
+#[cfg(feature = "std")]
+pub mod feed;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "std")]
+pub mod history;
+#[cfg(feature = "std")]
+pub mod import;
+#[cfg(feature = "std")]
+pub mod instruments;
+#[cfg(feature = "std")]
+pub mod journal;
+#[cfg(feature = "std")]
+pub mod limit_order_book;
+#[cfg(feature = "server")]
+pub mod logging;
+#[cfg(feature = "std")]
+pub mod matching_engine;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod recovery;
+#[cfg(feature = "std")]
+pub mod replay;
+#[cfg(feature = "std")]
+pub mod replication;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "server")]
+pub mod session;
+#[cfg(feature = "std")]
+pub mod simulation;
+#[cfg(feature = "std")]
+pub mod storage;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "std")]
+pub mod warm_start;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,195 @@
+//! How an incoming order's quantity is shared among the orders resting at
+//! one price level.
+
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use std::{fmt, rc::Rc};
+
+/// Allocates part of an incoming order to the resting orders of a level.
+/// The book calls it once per level it matches against.
+pub trait MatchAlgorithm: fmt::Debug {
+    /// Splits `quantity` among `resting`, which yields each order's ID and
+    /// remaining quantity in time priority. Returns the quantity each maker
+    /// fills, in the order the fills should be reported. Orders that get
+    /// nothing are left out.
+    ///
+    /// Implementations must allocate the smaller of `quantity` and the
+    /// level's total, never more than an order has left.
+    fn allocate(
+        &self,
+        resting: &mut dyn Iterator<Item = (u64, Decimal)>,
+        quantity: Decimal,
+    ) -> Vec<(u64, Decimal)>;
+}
+
+/// Strict time priority: each order fills completely before the next one
+/// starts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Fifo;
+
+impl MatchAlgorithm for Fifo {
+    fn allocate(
+        &self,
+        resting: &mut dyn Iterator<Item = (u64, Decimal)>,
+        mut quantity: Decimal,
+    ) -> Vec<(u64, Decimal)> {
+        let mut allocations = Vec::new();
+        for (exchange_id, remaining) in resting {
+            if quantity <= Decimal::ZERO {
+                break;
+            }
+            let fill = remaining.min(quantity);
+            allocations.push((exchange_id, fill));
+            quantity -= fill;
+        }
+        allocations
+    }
+}
+
+/// Shares the quantity in proportion to each order's remaining size.
+///
+/// With `top_order_priority` the first order in time priority is filled
+/// before the rest are shared out. Shares are rounded down to `lot_size`,
+/// or to the incoming quantity's precision without one, and what rounding
+/// leaves over goes to orders in time priority.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProRata {
+    pub top_order_priority: bool,
+    pub lot_size: Option<Decimal>,
+}
+
+impl ProRata {
+    fn round_down(&self, share: Decimal, scale: u32) -> Decimal {
+        match self.lot_size {
+            Some(lot_size) if lot_size > Decimal::ZERO => (share / lot_size).floor() * lot_size,
+            _ => share.round_dp_with_strategy(scale, RoundingStrategy::ToZero),
+        }
+    }
+}
+
+impl MatchAlgorithm for ProRata {
+    fn allocate(
+        &self,
+        resting: &mut dyn Iterator<Item = (u64, Decimal)>,
+        quantity: Decimal,
+    ) -> Vec<(u64, Decimal)> {
+        let resting: Vec<(u64, Decimal)> = resting.collect();
+        let total: Decimal = resting.iter().map(|(_, remaining)| remaining).sum();
+        if total <= quantity {
+            return resting;
+        }
+
+        let mut fills = vec![Decimal::ZERO; resting.len()];
+        let mut left = quantity;
+        if self.top_order_priority {
+            if let Some((_, remaining)) = resting.first() {
+                fills[0] = (*remaining).min(left);
+                left -= fills[0];
+            }
+        }
+
+        let pool: Decimal = resting
+            .iter()
+            .zip(&fills)
+            .map(|((_, remaining), fill)| remaining - fill)
+            .sum();
+        let shared = left;
+        if shared > Decimal::ZERO {
+            for ((_, remaining), fill) in resting.iter().zip(fills.iter_mut()) {
+                let share = self.round_down(shared * (remaining - *fill) / pool, quantity.scale());
+                *fill += share;
+                left -= share;
+            }
+        }
+        for ((_, remaining), fill) in resting.iter().zip(fills.iter_mut()) {
+            if left <= Decimal::ZERO {
+                break;
+            }
+            let extra = (remaining - *fill).min(left);
+            *fill += extra;
+            left -= extra;
+        }
+
+        resting
+            .iter()
+            .zip(fills)
+            .filter(|(_, fill)| *fill > Decimal::ZERO)
+            .map(|((exchange_id, _), fill)| (*exchange_id, fill))
+            .collect()
+    }
+}
+
+/// The matching algorithm a market is configured with:
+///
+/// ```toml
+/// matching = { algorithm = "pro_rata", top_order_priority = true }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Matching {
+    #[default]
+    Fifo,
+    ProRata {
+        #[serde(default)]
+        top_order_priority: bool,
+    },
+}
+
+impl Matching {
+    /// The algorithm for a market that trades in multiples of `lot_size`.
+    pub fn algorithm(self, lot_size: Option<Decimal>) -> Rc<dyn MatchAlgorithm> {
+        match self {
+            Matching::Fifo => Rc::new(Fifo),
+            Matching::ProRata { top_order_priority } => Rc::new(ProRata {
+                top_order_priority,
+                lot_size,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn allocate(
+        algorithm: &dyn MatchAlgorithm,
+        resting: &[(u64, Decimal)],
+        quantity: Decimal,
+    ) -> Vec<(u64, Decimal)> {
+        algorithm.allocate(&mut resting.iter().copied(), quantity)
+    }
+
+    #[test]
+    fn test_fifo_fills_in_time_priority() {
+        let resting = [(1, dec!(3)), (2, dec!(5)), (3, dec!(2))];
+        assert_eq!(
+            allocate(&Fifo, &resting, dec!(6)),
+            vec![(1, dec!(3)), (2, dec!(3))]
+        );
+    }
+
+    #[test]
+    fn test_pro_rata_shares_by_size() {
+        let resting = [(1, dec!(10)), (2, dec!(30)), (3, dec!(60))];
+        let pro_rata = ProRata {
+            top_order_priority: false,
+            lot_size: Some(dec!(1)),
+        };
+        assert_eq!(
+            allocate(&pro_rata, &resting, dec!(15)),
+            vec![(1, dec!(2)), (2, dec!(4)), (3, dec!(9))]
+        );
+        assert_eq!(allocate(&pro_rata, &resting, dec!(200)), resting.to_vec());
+
+        let top_first = ProRata {
+            top_order_priority: true,
+            ..pro_rata
+        };
+        assert_eq!(
+            allocate(&top_first, &resting, dec!(19)),
+            vec![(1, dec!(10)), (2, dec!(3)), (3, dec!(6))]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Overflow-checked decimal arithmetic. `Decimal` tops out around 7.9e28 and
+//! panics past it, which the notional of one absurdly priced and sized order
+//! can reach, and so can a total summed over enough large orders. Order
+//! entry takes notionals with the checked forms here and rejects what
+//! doesn't fit; running totals that can't refuse an update, like a level's
+//! volume, saturate at `Decimal::MAX` instead.
+
+use rust_decimal::Decimal;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Add,
+    Sub,
+    Mul,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Add => write!(f, "+"),
+            Operation::Sub => write!(f, "-"),
+            Operation::Mul => write!(f, "*"),
+        }
+    }
+}
+
+/// An operation whose result is out of `Decimal`'s range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overflow {
+    pub operation: Operation,
+    pub lhs: Decimal,
+    pub rhs: Decimal,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} overflows", self.lhs, self.operation, self.rhs)
+    }
+}
+
+impl std::error::Error for Overflow {}
+
+fn checked(
+    operation: Operation,
+    lhs: Decimal,
+    rhs: Decimal,
+    result: Option<Decimal>,
+) -> Result<Decimal, Overflow> {
+    result.ok_or(Overflow {
+        operation,
+        lhs,
+        rhs,
+    })
+}
+
+pub fn checked_add(lhs: Decimal, rhs: Decimal) -> Result<Decimal, Overflow> {
+    checked(Operation::Add, lhs, rhs, lhs.checked_add(rhs))
+}
+
+pub fn checked_sub(lhs: Decimal, rhs: Decimal) -> Result<Decimal, Overflow> {
+    checked(Operation::Sub, lhs, rhs, lhs.checked_sub(rhs))
+}
+
+pub fn checked_mul(lhs: Decimal, rhs: Decimal) -> Result<Decimal, Overflow> {
+    checked(Operation::Mul, lhs, rhs, lhs.checked_mul(rhs))
+}
+
+/// The value of `quantity` at `price` in a market of `multiplier` units
+/// per contract.
+pub fn notional(
+    price: Decimal,
+    quantity: Decimal,
+    multiplier: Decimal,
+) -> Result<Decimal, Overflow> {
+    checked_mul(checked_mul(price, multiplier)?, quantity)
+}
+
+/// Sums `values`, pinning the total at `Decimal::MAX` or `Decimal::MIN`
+/// once it leaves the range. A total that went past one end and came back
+/// is off by what saturation dropped.
+pub fn saturating_sum<'a>(values: impl IntoIterator<Item = &'a Decimal>) -> Decimal {
+    values
+        .into_iter()
+        .fold(Decimal::ZERO, |total, value| total.saturating_add(*value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::{Limit, Order, OrderType};
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_extreme_values() {
+        let big = dec!(1e14);
+        assert_eq!(notional(big, big, dec!(1)), Ok(dec!(1e28)));
+        assert_eq!(
+            notional(big, big, dec!(100)),
+            Err(Overflow {
+                operation: Operation::Mul,
+                lhs: dec!(1e16),
+                rhs: big
+            })
+        );
+        assert!(checked_add(Decimal::MAX, dec!(1)).is_err());
+        assert!(checked_sub(Decimal::MIN, dec!(1)).is_err());
+        assert_eq!(checked_mul(Decimal::MAX, dec!(-1)).unwrap(), Decimal::MIN);
+        assert_eq!(
+            saturating_sum(&[Decimal::MAX, dec!(1), dec!(-1)]),
+            Decimal::MAX - dec!(1)
+        );
+
+        // A level whose volume passes the maximum stays usable.
+        let mut limit = Limit::new(dec!(1e14));
+        for exchange_id in 1..=10 {
+            limit.add_order(Order::new(
+                "BTC/USDT".to_string(),
+                exchange_id,
+                OrderType::Bid,
+                dec!(1e14),
+                dec!(1e14),
+                Utc::now(),
+                Utc::now(),
+            ));
+        }
+        assert_eq!(limit.total_volume, Decimal::MAX);
+        assert_eq!(limit.size, dec!(1e15));
+        limit.reduce_order(1, dec!(1e14)).unwrap();
+        assert_eq!(limit.order_count, 9);
+    }
+
+    #[test]
+    fn test_in_range_results_and_messages() {
+        assert_eq!(checked_add(dec!(1.5), dec!(2)), Ok(dec!(3.5)));
+        assert_eq!(checked_sub(dec!(1), dec!(2.5)), Ok(dec!(-1.5)));
+        assert_eq!(notional(dec!(20), dec!(3), dec!(0.1)), Ok(dec!(6)));
+        assert_eq!(saturating_sum(&[]), dec!(0));
+        assert_eq!(
+            saturating_sum(&[Decimal::MIN, dec!(-5), dec!(2)]),
+            Decimal::MIN + dec!(2)
+        );
+
+        let overflow = checked_add(Decimal::MAX, dec!(1)).unwrap_err();
+        assert_eq!(overflow.operation, Operation::Add);
+        assert_eq!(
+            overflow.to_string(),
+            format!("{} + 1 overflows", Decimal::MAX)
+        );
+        let overflow = checked_sub(Decimal::MIN, dec!(2)).unwrap_err();
+        assert_eq!(
+            overflow.to_string(),
+            format!("{} - 2 overflows", Decimal::MIN)
+        );
+        assert_eq!(Operation::Mul.to_string(), "*");
+    }
+}
@@ -0,0 +1,227 @@
+//! A fixed-depth L2 replica for consumers that only care about the touch.
+//!
+//! Each side keeps its best `N` levels in arrays, best first, so an update
+//! costs at most a shift of `N` entries and never allocates. Levels pushed
+//! past the depth are forgotten: once a tracked level goes away the side
+//! holds fewer than `N` until the feed sends a deeper one again.
+
+use super::{
+    diff::BookDiff,
+    order::{LimitOrderBook, OrderType},
+};
+use rust_decimal::Decimal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Levels<const N: usize> {
+    prices: [Decimal; N],
+    sizes: [Decimal; N],
+    len: usize,
+}
+
+impl<const N: usize> Levels<N> {
+    const EMPTY: Self = Self {
+        prices: [Decimal::ZERO; N],
+        sizes: [Decimal::ZERO; N],
+        len: 0,
+    };
+
+    /// Sets the size at `price`, `better` ordering prices best first.
+    /// Returns false if the price is beyond the tracked depth.
+    fn update(
+        &mut self,
+        price: Decimal,
+        size: Decimal,
+        better: fn(Decimal, Decimal) -> bool,
+    ) -> bool {
+        let index = self.prices[..self.len]
+            .iter()
+            .position(|level| !better(*level, price))
+            .unwrap_or(self.len);
+        if index == N {
+            return false;
+        }
+        let exists = index < self.len && self.prices[index] == price;
+        match (exists, size.is_zero()) {
+            (true, false) => self.sizes[index] = size,
+            (true, true) => {
+                self.prices.copy_within(index + 1..self.len, index);
+                self.sizes.copy_within(index + 1..self.len, index);
+                self.len -= 1;
+            }
+            (false, true) => {}
+            (false, false) => {
+                let end = self.len.min(N - 1);
+                self.prices.copy_within(index..end, index + 1);
+                self.sizes.copy_within(index..end, index + 1);
+                self.prices[index] = price;
+                self.sizes[index] = size;
+                self.len = end + 1;
+            }
+        }
+        true
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (Decimal, Decimal)> + '_ {
+        self.prices[..self.len]
+            .iter()
+            .copied()
+            .zip(self.sizes[..self.len].iter().copied())
+    }
+}
+
+/// The top `N` price levels of each side of a book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundedBook<const N: usize> {
+    bids: Levels<N>,
+    asks: Levels<N>,
+}
+
+impl<const N: usize> Default for BoundedBook<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> BoundedBook<N> {
+    pub const fn new() -> Self {
+        Self {
+            bids: Levels::EMPTY,
+            asks: Levels::EMPTY,
+        }
+    }
+
+    /// The top levels of `book` as they stand.
+    pub fn from_book(book: &LimitOrderBook) -> Self {
+        let mut bounded = Self::new();
+        for (price, level) in book.iter_bids().take(N) {
+            bounded.update(OrderType::Bid, price, level.size());
+        }
+        for (price, level) in book.iter_asks().take(N) {
+            bounded.update(OrderType::Ask, price, level.size());
+        }
+        bounded
+    }
+
+    /// Sets the aggregate size at `price`, removing the level when `size` is
+    /// zero. Returns false if the price is beyond the tracked depth and so
+    /// was ignored.
+    pub fn update(&mut self, side: OrderType, price: Decimal, size: Decimal) -> bool {
+        match side {
+            OrderType::Bid => self.bids.update(price, size, |level, price| level > price),
+            OrderType::Ask => self.asks.update(price, size, |level, price| level < price),
+        }
+    }
+
+    pub fn apply(&mut self, diff: &BookDiff) {
+        for (side, changes) in [(OrderType::Bid, &diff.bids), (OrderType::Ask, &diff.asks)] {
+            for change in changes {
+                self.update(side, change.price(), change.size());
+            }
+        }
+    }
+
+    /// `(price, size)` of the tracked levels on `side`, best first.
+    pub fn levels(&self, side: OrderType) -> impl Iterator<Item = (Decimal, Decimal)> + '_ {
+        match side {
+            OrderType::Bid => self.bids.iter(),
+            OrderType::Ask => self.asks.iter(),
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<(Decimal, Decimal)> {
+        self.bids.iter().next()
+    }
+
+    pub fn best_ask(&self) -> Option<(Decimal, Decimal)> {
+        self.asks.iter().next()
+    }
+
+    pub fn spread(&self) -> Option<Decimal> {
+        Some(self.best_ask()?.0 - self.best_bid()?.0)
+    }
+
+    /// Number of levels tracked on `side`, at most `N`.
+    pub fn depth(&self, side: OrderType) -> usize {
+        match side {
+            OrderType::Bid => self.bids.len,
+            OrderType::Ask => self.asks.len,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::Order;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_bounded_book() {
+        let mut book = BoundedBook::<3>::new();
+        for price in [dec!(97), dec!(99), dec!(98), dec!(96)] {
+            book.update(OrderType::Bid, price, dec!(1));
+        }
+        let bids: Vec<Decimal> = book
+            .levels(OrderType::Bid)
+            .map(|(price, _)| price)
+            .collect();
+        assert_eq!(bids, vec![dec!(99), dec!(98), dec!(97)]);
+        assert!(!book.update(OrderType::Bid, dec!(95), dec!(1)));
+
+        assert!(book.update(OrderType::Bid, dec!(98), dec!(4)));
+        assert!(book.update(OrderType::Bid, dec!(99), dec!(0)));
+        let bids: Vec<_> = book.levels(OrderType::Bid).collect();
+        assert_eq!(bids, vec![(dec!(98), dec!(4)), (dec!(97), dec!(1))]);
+
+        book.update(OrderType::Ask, dec!(102), dec!(2));
+        book.update(OrderType::Ask, dec!(101), dec!(3));
+        assert_eq!(book.best_ask(), Some((dec!(101), dec!(3))));
+        assert_eq!(book.spread(), Some(dec!(3)));
+
+        let mut full = LimitOrderBook::new();
+        for (exchange_id, price) in [(1, dec!(10)), (2, dec!(11)), (3, dec!(12)), (4, dec!(13))] {
+            full.add_order(Order::new(
+                "BTC/USDT".to_string(),
+                exchange_id,
+                OrderType::Ask,
+                dec!(1),
+                price,
+                Utc::now(),
+                Utc::now(),
+            ));
+        }
+        let before = full.clone();
+        let mut mirror = BoundedBook::<2>::from_book(&full);
+        full.cancel_order(1);
+        mirror.apply(&before.diff(&full));
+        // The diff doesn't mention 12, which was never tracked.
+        let asks: Vec<Decimal> = mirror
+            .levels(OrderType::Ask)
+            .map(|(price, _)| price)
+            .collect();
+        assert_eq!(asks, vec![dec!(11)]);
+    }
+
+    #[test]
+    fn test_inserts_push_out_the_deepest_level() {
+        let mut book = BoundedBook::<2>::default();
+        assert_eq!((book.best_bid(), book.spread()), (None, None));
+        // Removing a level that isn't tracked is accepted and changes nothing.
+        assert!(book.update(OrderType::Ask, dec!(105), dec!(0)));
+        assert_eq!(book.depth(OrderType::Ask), 0);
+
+        book.update(OrderType::Ask, dec!(105), dec!(1));
+        book.update(OrderType::Ask, dec!(103), dec!(2));
+        assert!(book.update(OrderType::Ask, dec!(101), dec!(3)));
+        let asks: Vec<_> = book.levels(OrderType::Ask).collect();
+        assert_eq!(asks, vec![(dec!(101), dec!(3)), (dec!(103), dec!(2))]);
+        assert_eq!(book.depth(OrderType::Ask), 2);
+        assert_eq!(book.spread(), None);
+
+        // A zero-depth book tracks nothing.
+        let mut none = BoundedBook::<0>::new();
+        assert!(!none.update(OrderType::Bid, dec!(1), dec!(1)));
+        assert_eq!(none, BoundedBook::<0>::new());
+    }
+}
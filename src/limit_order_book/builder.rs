@@ -0,0 +1,291 @@
+//! Named construction of orders, checked before they reach a book.
+
+use super::order::{Order, OrderType, TimeInForce};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderBuildError {
+    /// A required setter wasn't called.
+    Missing(&'static str),
+    InvalidQuantity(Decimal),
+    InvalidPrice(Decimal),
+    OffTick {
+        price: Decimal,
+        tick_size: Decimal,
+    },
+}
+
+impl fmt::Display for OrderBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderBuildError::Missing(field) => write!(f, "missing {}", field),
+            OrderBuildError::InvalidQuantity(quantity) => {
+                write!(f, "invalid quantity: {}", quantity)
+            }
+            OrderBuildError::InvalidPrice(price) => write!(f, "invalid price: {}", price),
+            OrderBuildError::OffTick { price, tick_size } => write!(
+                f,
+                "price {} is not a multiple of the tick size {}",
+                price, tick_size
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OrderBuildError {}
+
+/// Builds an `Order`. The pair, exchange ID, side, quantity, price and time
+/// are required; `market` stands in for the price on orders that take any
+/// price.
+///
+/// ```
+/// use chrono::Utc;
+/// use rust_decimal_macros::dec;
+/// use tradebot::limit_order_book::order::{Order, OrderType};
+///
+/// let order = Order::builder()
+///     .pair("BTC/USDT")
+///     .exchange_id(1)
+///     .side(OrderType::Bid)
+///     .quantity(dec!(2))
+///     .price(dec!(100.5))
+///     .tick_size(dec!(0.5))
+///     .time(Utc::now())
+///     .client("alice")
+///     .build()
+///     .unwrap();
+/// assert_eq!(order.remaining_quantity, dec!(2));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OrderBuilder {
+    pair: Option<String>,
+    exchange_id: Option<u64>,
+    side: Option<OrderType>,
+    quantity: Option<Decimal>,
+    price: Option<Decimal>,
+    market: bool,
+    time: Option<DateTime<Utc>>,
+    tick_size: Option<Decimal>,
+    client: String,
+    short_sale: bool,
+    reduce_only: bool,
+    client_order_id: Option<String>,
+    strategy: Option<String>,
+    time_in_force: TimeInForce,
+}
+
+impl OrderBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pair(mut self, pair: impl Into<String>) -> Self {
+        self.pair = Some(pair.into());
+        self
+    }
+
+    pub fn exchange_id(mut self, exchange_id: u64) -> Self {
+        self.exchange_id = Some(exchange_id);
+        self
+    }
+
+    pub fn side(mut self, side: OrderType) -> Self {
+        self.side = Some(side);
+        self
+    }
+
+    pub fn quantity(mut self, quantity: Decimal) -> Self {
+        self.quantity = Some(quantity);
+        self
+    }
+
+    pub fn price(mut self, price: Decimal) -> Self {
+        self.price = Some(price);
+        self
+    }
+
+    /// Makes it an order for any price, with a limit price of zero.
+    pub fn market(mut self) -> Self {
+        self.market = true;
+        self
+    }
+
+    /// Sets both the entry and the event time.
+    pub fn time(mut self, time: DateTime<Utc>) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// Checks the price against the market's tick size.
+    pub fn tick_size(mut self, tick_size: Decimal) -> Self {
+        self.tick_size = Some(tick_size);
+        self
+    }
+
+    pub fn client(mut self, client: impl Into<String>) -> Self {
+        self.client = client.into();
+        self
+    }
+
+    pub fn short_sale(mut self, short_sale: bool) -> Self {
+        self.short_sale = short_sale;
+        self
+    }
+
+    pub fn reduce_only(mut self, reduce_only: bool) -> Self {
+        self.reduce_only = reduce_only;
+        self
+    }
+
+    pub fn client_order_id(mut self, client_order_id: impl Into<String>) -> Self {
+        self.client_order_id = Some(client_order_id.into());
+        self
+    }
+
+    pub fn strategy(mut self, strategy: impl Into<String>) -> Self {
+        self.strategy = Some(strategy.into());
+        self
+    }
+
+    pub fn time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
+
+    pub fn build(self) -> Result<Order, OrderBuildError> {
+        let pair = self.pair.ok_or(OrderBuildError::Missing("pair"))?;
+        let exchange_id = self
+            .exchange_id
+            .ok_or(OrderBuildError::Missing("exchange_id"))?;
+        let side = self.side.ok_or(OrderBuildError::Missing("side"))?;
+        let quantity = self.quantity.ok_or(OrderBuildError::Missing("quantity"))?;
+        let time = self.time.ok_or(OrderBuildError::Missing("time"))?;
+        if quantity <= Decimal::ZERO {
+            return Err(OrderBuildError::InvalidQuantity(quantity));
+        }
+        let price = match (self.market, self.price) {
+            (true, _) => Decimal::ZERO,
+            (false, None) => return Err(OrderBuildError::Missing("price")),
+            (false, Some(price)) if price <= Decimal::ZERO => {
+                return Err(OrderBuildError::InvalidPrice(price))
+            }
+            (false, Some(price)) => {
+                if let Some(tick_size) = self.tick_size {
+                    if !(price % tick_size).is_zero() {
+                        return Err(OrderBuildError::OffTick { price, tick_size });
+                    }
+                }
+                price
+            }
+        };
+
+        let mut order = Order::new(pair, exchange_id, side, quantity, price, time, time)
+            .with_client(self.client)
+            .with_short_sale(self.short_sale)
+            .with_reduce_only(self.reduce_only)
+            .with_time_in_force(self.time_in_force);
+        order.client_order_id = self.client_order_id;
+        order.strategy = self.strategy;
+        Ok(order)
+    }
+}
+
+impl Order {
+    pub fn builder() -> OrderBuilder {
+        OrderBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn bid() -> OrderBuilder {
+        Order::builder()
+            .pair("BTC/USDT")
+            .exchange_id(7)
+            .side(OrderType::Bid)
+            .quantity(dec!(3))
+            .time(Utc::now())
+    }
+
+    #[test]
+    fn test_order_builder() {
+        let order = bid()
+            .price(dec!(99))
+            .client_order_id("a-1")
+            .build()
+            .unwrap();
+        assert_eq!((order.shares, order.limit_price), (dec!(3), dec!(99)));
+        assert_eq!(order.client_order_id.as_deref(), Some("a-1"));
+        assert_eq!(bid().market().build().unwrap().limit_price, Decimal::ZERO);
+
+        assert_eq!(bid().build(), Err(OrderBuildError::Missing("price")));
+        assert_eq!(
+            bid().price(dec!(-1)).build(),
+            Err(OrderBuildError::InvalidPrice(dec!(-1)))
+        );
+        assert_eq!(
+            bid().quantity(dec!(0)).price(dec!(99)).build(),
+            Err(OrderBuildError::InvalidQuantity(dec!(0)))
+        );
+        assert_eq!(
+            bid().price(dec!(99.3)).tick_size(dec!(0.5)).build(),
+            Err(OrderBuildError::OffTick {
+                price: dec!(99.3),
+                tick_size: dec!(0.5)
+            })
+        );
+        assert_eq!(
+            Order::builder().build(),
+            Err(OrderBuildError::Missing("pair"))
+        );
+    }
+
+    #[test]
+    fn test_optional_fields_and_market_orders() {
+        let time = Utc::now();
+        let order = bid()
+            .side(OrderType::Ask)
+            .price(dec!(100.5))
+            .tick_size(dec!(0.5))
+            .time(time)
+            .client("alice")
+            .short_sale(true)
+            .reduce_only(true)
+            .strategy("mm")
+            .time_in_force(TimeInForce::Day)
+            .build()
+            .unwrap();
+        assert_eq!(order.order_type, OrderType::Ask);
+        assert_eq!((order.entry_time, order.event_time), (time, time));
+        assert_eq!(order.client, "alice");
+        assert!(order.short_sale && order.reduce_only);
+        assert_eq!(order.strategy.as_deref(), Some("mm"));
+        assert_eq!(order.time_in_force, TimeInForce::Day);
+        assert_eq!(bid().price(dec!(1)).build().unwrap().client, "");
+
+        // Market orders skip the price checks, but not the quantity's.
+        let market = bid().price(dec!(-5)).tick_size(dec!(2)).market();
+        assert_eq!(market.clone().build().unwrap().limit_price, Decimal::ZERO);
+        assert_eq!(
+            market.quantity(dec!(-1)).build(),
+            Err(OrderBuildError::InvalidQuantity(dec!(-1)))
+        );
+        assert_eq!(
+            Order::builder().pair("BTC/USDT").exchange_id(1).build(),
+            Err(OrderBuildError::Missing("side"))
+        );
+        assert_eq!(
+            OrderBuildError::OffTick {
+                price: dec!(99.3),
+                tick_size: dec!(0.5)
+            }
+            .to_string(),
+            "price 99.3 is not a multiple of the tick size 0.5"
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use super::order::LimitOrderBook;
+
+/// CRC-32 (IEEE 802.3), as used by zlib and exchange book checksums.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+impl LimitOrderBook {
+    /// The string the checksum is computed over: the top `depth` levels
+    /// interleaved best first as `bid:size:ask:size:...`, skipping a side
+    /// once it runs out. Numbers are normalized so `1.50` and `1.5` agree.
+    pub fn checksum_input(&self, depth: usize) -> String {
+        let mut bids = self.bids.iter().rev().take(depth);
+        let mut asks = self.asks.iter().take(depth);
+        let mut fields = Vec::new();
+        loop {
+            let (bid, ask) = (bids.next(), asks.next());
+            if bid.is_none() && ask.is_none() {
+                break;
+            }
+            for (price, limit) in bid.into_iter().chain(ask) {
+                fields.push(price.normalize().to_string());
+                fields.push(limit.borrow().size.normalize().to_string());
+            }
+        }
+        fields.join(":")
+    }
+
+    /// CRC-32 of the top `depth` levels per side. Two books with the same
+    /// aggregated levels always agree, whatever orders make them up.
+    pub fn checksum(&self, depth: usize) -> u32 {
+        crc32(self.checksum_input(depth).as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::{Order, OrderType};
+    use chrono::Utc;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn order(exchange_id: u64, order_type: OrderType, shares: Decimal, price: Decimal) -> Order {
+        Order::new(
+            "BTC/USDT".to_string(),
+            exchange_id,
+            order_type,
+            shares,
+            price,
+            Utc::now(),
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_checksum_tracks_levels_not_orders() {
+        let mut book = LimitOrderBook::new();
+        book.add_order(order(1, OrderType::Bid, dec!(5), dec!(99.50)));
+        book.add_order(order(2, OrderType::Bid, dec!(3), dec!(98)));
+        book.add_order(order(3, OrderType::Ask, dec!(4), dec!(101)));
+        assert_eq!(book.checksum_input(10), "99.5:5:101:4:98:3");
+        assert_eq!(book.checksum_input(1), "99.5:5:101:4");
+
+        let mut other = LimitOrderBook::new();
+        other.add_order(order(10, OrderType::Ask, dec!(4.0), dec!(101)));
+        other.add_order(order(11, OrderType::Bid, dec!(2), dec!(99.5)));
+        other.add_order(order(12, OrderType::Bid, dec!(3), dec!(99.5)));
+        other.add_order(order(13, OrderType::Bid, dec!(3), dec!(98)));
+        assert_eq!(book.checksum(10), other.checksum(10));
+
+        other.add_order(order(14, OrderType::Bid, dec!(1), dec!(97)));
+        assert_ne!(book.checksum(10), other.checksum(10));
+        assert_eq!(book.checksum(2), other.checksum(2));
+    }
+}
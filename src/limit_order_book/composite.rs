@@ -0,0 +1,210 @@
+use super::{
+    diff::{BookDiff, LevelChange},
+    order::{LimitOrderBook, OrderType},
+};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+/// One price level across all venues.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompositeLevel {
+    pub price: Decimal,
+    pub size: Decimal,
+    /// Size shown by each venue at this price, by venue name.
+    pub venues: BTreeMap<String, Decimal>,
+}
+
+type Side = BTreeMap<Decimal, BTreeMap<String, Decimal>>;
+
+/// Aggregated L2 view over several venues' books for the same instrument.
+///
+/// Seed each venue with `sync`, then keep it current with the `BookDiff`s
+/// between successive states of its book.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompositeBook {
+    bids: Side,
+    asks: Side,
+}
+
+impl CompositeBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces everything known about `venue` with `book`'s levels.
+    pub fn sync(&mut self, venue: &str, book: &LimitOrderBook) {
+        self.remove_venue(venue);
+        for (side, levels) in [(&mut self.bids, &book.bids), (&mut self.asks, &book.asks)] {
+            for (price, limit) in levels {
+                side.entry(*price)
+                    .or_default()
+                    .insert(venue.to_string(), limit.borrow().size);
+            }
+        }
+    }
+
+    /// Applies one venue's level changes.
+    pub fn apply(&mut self, venue: &str, diff: &BookDiff) {
+        for (side, changes) in [(&mut self.bids, &diff.bids), (&mut self.asks, &diff.asks)] {
+            for change in changes {
+                match change {
+                    LevelChange::Removed { price } => Self::remove(side, *price, venue),
+                    _ => {
+                        side.entry(change.price())
+                            .or_default()
+                            .insert(venue.to_string(), change.size());
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn remove_venue(&mut self, venue: &str) {
+        for side in [&mut self.bids, &mut self.asks] {
+            side.retain(|_, venues| {
+                venues.remove(venue);
+                !venues.is_empty()
+            });
+        }
+    }
+
+    /// Aggregated levels, best first.
+    pub fn levels(&self, side: OrderType, depth: usize) -> Vec<CompositeLevel> {
+        match side {
+            OrderType::Bid => self.bids.iter().rev().take(depth).map(level).collect(),
+            OrderType::Ask => self.asks.iter().take(depth).map(level).collect(),
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<CompositeLevel> {
+        self.bids.iter().next_back().map(level)
+    }
+
+    pub fn best_ask(&self) -> Option<CompositeLevel> {
+        self.asks.iter().next().map(level)
+    }
+
+    /// Whether one venue bids at or above another's offer.
+    pub fn is_crossed(&self) -> bool {
+        match (self.bids.keys().next_back(), self.asks.keys().next()) {
+            (Some(bid), Some(ask)) => bid >= ask,
+            _ => false,
+        }
+    }
+
+    fn remove(side: &mut Side, price: Decimal, venue: &str) {
+        if let Some(venues) = side.get_mut(&price) {
+            venues.remove(venue);
+            if venues.is_empty() {
+                side.remove(&price);
+            }
+        }
+    }
+}
+
+fn level((price, venues): (&Decimal, &BTreeMap<String, Decimal>)) -> CompositeLevel {
+    CompositeLevel {
+        price: *price,
+        size: venues.values().sum(),
+        venues: venues.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::Order;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    fn order(exchange_id: u64, order_type: OrderType, shares: Decimal, price: Decimal) -> Order {
+        Order::new(
+            "BTC/USDT".to_string(),
+            exchange_id,
+            order_type,
+            shares,
+            price,
+            Utc::now(),
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn test_incremental_matches_full_sync() {
+        let mut a = LimitOrderBook::new();
+        a.add_order(order(1, OrderType::Bid, dec!(2), dec!(99)));
+        a.add_order(order(2, OrderType::Ask, dec!(3), dec!(101)));
+        let mut b = LimitOrderBook::new();
+        b.add_order(order(1, OrderType::Bid, dec!(5), dec!(99)));
+        b.add_order(order(2, OrderType::Ask, dec!(1), dec!(100)));
+
+        let mut composite = CompositeBook::new();
+        composite.sync("a", &a);
+        composite.sync("b", &b);
+        let best_bid = composite.best_bid().unwrap();
+        assert_eq!(best_bid.size, dec!(7));
+        assert_eq!(best_bid.venues["b"], dec!(5));
+        assert_eq!(composite.best_ask().unwrap().price, dec!(100));
+
+        let before = a.clone();
+        a.add_order(order(3, OrderType::Bid, dec!(4), dec!(100)));
+        a.cancel_order(1);
+        composite.apply("a", &before.diff(&a));
+        assert!(composite.is_crossed());
+
+        let mut expected = CompositeBook::new();
+        expected.sync("b", &b);
+        expected.sync("a", &a);
+        assert_eq!(composite, expected);
+        assert_eq!(
+            composite
+                .levels(OrderType::Bid, 10)
+                .iter()
+                .map(|level| (level.price, level.size))
+                .collect::<Vec<_>>(),
+            vec![(dec!(100), dec!(4)), (dec!(99), dec!(5))]
+        );
+
+        composite.remove_venue("b");
+        assert_eq!(composite.levels(OrderType::Ask, 10).len(), 1);
+        assert!(!composite.is_crossed());
+    }
+
+    #[test]
+    fn test_resync_and_depth() {
+        let mut a = LimitOrderBook::new();
+        for (id, price) in [(1, dec!(97)), (2, dec!(98)), (3, dec!(99))] {
+            a.add_order(order(id, OrderType::Bid, dec!(1), price));
+        }
+        let mut composite = CompositeBook::new();
+        assert_eq!(composite.best_bid(), None);
+        assert!(!composite.is_crossed());
+        composite.sync("a", &a);
+        assert_eq!(
+            composite
+                .levels(OrderType::Bid, 2)
+                .iter()
+                .map(|level| level.price)
+                .collect::<Vec<_>>(),
+            vec![dec!(99), dec!(98)]
+        );
+
+        // A resync drops levels the venue no longer shows.
+        a.cancel_order(3);
+        a.add_order(order(4, OrderType::Ask, dec!(2), dec!(102)));
+        composite.sync("a", &a);
+        assert_eq!(composite.best_bid().unwrap().price, dec!(98));
+        assert_eq!(composite.best_ask().unwrap().size, dec!(2));
+
+        // Removing a level another venue doesn't show is a no-op for it.
+        let mut b = LimitOrderBook::new();
+        b.add_order(order(1, OrderType::Bid, dec!(6), dec!(98)));
+        composite.sync("b", &b);
+        let before = b.clone();
+        b.cancel_order(1);
+        composite.apply("b", &before.diff(&b));
+        let best_bid = composite.best_bid().unwrap();
+        assert_eq!(best_bid.size, dec!(1));
+        assert_eq!(best_bid.venues.keys().collect::<Vec<_>>(), vec!["a"]);
+    }
+}
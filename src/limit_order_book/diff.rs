@@ -0,0 +1,224 @@
+use super::order::{Limit, LimitOrderBook};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+/// A change to one aggregated price level. Sizes are the level's full new
+/// size, not an increment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LevelChange {
+    Added { price: Decimal, size: Decimal },
+    Removed { price: Decimal },
+    Changed { price: Decimal, size: Decimal },
+}
+
+impl LevelChange {
+    pub fn price(&self) -> Decimal {
+        match self {
+            LevelChange::Added { price, .. }
+            | LevelChange::Removed { price }
+            | LevelChange::Changed { price, .. } => *price,
+        }
+    }
+
+    /// Size of the level after the change; zero once removed.
+    pub fn size(&self) -> Decimal {
+        match self {
+            LevelChange::Added { size, .. } | LevelChange::Changed { size, .. } => *size,
+            LevelChange::Removed { .. } => Decimal::ZERO,
+        }
+    }
+}
+
+/// The level changes that turn one book's L2 view into another's, each side
+/// in ascending price order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookDiff {
+    pub bids: Vec<LevelChange>,
+    pub asks: Vec<LevelChange>,
+}
+
+impl BookDiff {
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+
+    /// Applies the diff to price -> size maps of the older book.
+    pub fn apply(
+        &self,
+        bids: &mut BTreeMap<Decimal, Decimal>,
+        asks: &mut BTreeMap<Decimal, Decimal>,
+    ) {
+        for (levels, changes) in [(bids, &self.bids), (asks, &self.asks)] {
+            for change in changes {
+                match change {
+                    LevelChange::Removed { price } => {
+                        levels.remove(price);
+                    }
+                    _ => {
+                        levels.insert(change.price(), change.size());
+                    }
+                }
+            }
+        }
+    }
+
+    fn side(
+        old: &BTreeMap<Decimal, Rc<RefCell<Limit>>>,
+        new: &BTreeMap<Decimal, Rc<RefCell<Limit>>>,
+    ) -> Vec<LevelChange> {
+        let mut changes = Vec::new();
+        let mut old_levels = old.iter().peekable();
+        let mut new_levels = new.iter().peekable();
+
+        // Both maps are sorted, so a single merge pass finds every change.
+        loop {
+            let change = match (old_levels.peek(), new_levels.peek()) {
+                (None, None) => break,
+                (Some((&price, _)), None) => {
+                    old_levels.next();
+                    Some(LevelChange::Removed { price })
+                }
+                (None, Some((&price, limit))) => {
+                    let size = limit.borrow().size;
+                    new_levels.next();
+                    Some(LevelChange::Added { price, size })
+                }
+                (Some((&old_price, old_limit)), Some((&new_price, new_limit))) => {
+                    if old_price < new_price {
+                        old_levels.next();
+                        Some(LevelChange::Removed { price: old_price })
+                    } else if new_price < old_price {
+                        let size = new_limit.borrow().size;
+                        new_levels.next();
+                        Some(LevelChange::Added {
+                            price: new_price,
+                            size,
+                        })
+                    } else {
+                        let (old_size, size) = (old_limit.borrow().size, new_limit.borrow().size);
+                        old_levels.next();
+                        new_levels.next();
+                        (old_size != size).then_some(LevelChange::Changed {
+                            price: new_price,
+                            size,
+                        })
+                    }
+                }
+            };
+            changes.extend(change);
+        }
+
+        changes
+    }
+}
+
+impl LimitOrderBook {
+    /// The level changes needed to bring this book's L2 view in line with
+    /// `other`'s.
+    pub fn diff(&self, other: &LimitOrderBook) -> BookDiff {
+        BookDiff {
+            bids: BookDiff::side(&self.bids, &other.bids),
+            asks: BookDiff::side(&self.asks, &other.asks),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::{Order, OrderType};
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    fn order(exchange_id: u64, order_type: OrderType, shares: Decimal, price: Decimal) -> Order {
+        Order::new(
+            "BTC/USDT".to_string(),
+            exchange_id,
+            order_type,
+            shares,
+            price,
+            Utc::now(),
+            Utc::now(),
+        )
+    }
+
+    fn levels(book: &LimitOrderBook) -> (BTreeMap<Decimal, Decimal>, BTreeMap<Decimal, Decimal>) {
+        let side = |levels: &BTreeMap<Decimal, Rc<RefCell<Limit>>>| {
+            levels
+                .iter()
+                .map(|(price, limit)| (*price, limit.borrow().size))
+                .collect()
+        };
+        (side(&book.bids), side(&book.asks))
+    }
+
+    #[test]
+    fn test_diff_between_snapshots() {
+        let mut book = LimitOrderBook::new();
+        book.add_order(order(1, OrderType::Bid, dec!(5), dec!(99)));
+        book.add_order(order(2, OrderType::Bid, dec!(3), dec!(98)));
+        book.add_order(order(3, OrderType::Ask, dec!(4), dec!(101)));
+        book.add_order(order(4, OrderType::Ask, dec!(2), dec!(102)));
+        let before = book.clone();
+        assert!(before.diff(&book).is_empty());
+
+        book.cancel_order(2);
+        book.add_order(order(5, OrderType::Bid, dec!(1), dec!(99)));
+        book.add_order(order(6, OrderType::Bid, dec!(7), dec!(97)));
+        book.place_order(order(7, OrderType::Bid, dec!(4), dec!(101)));
+
+        let diff = before.diff(&book);
+        assert_eq!(
+            diff.bids,
+            vec![
+                LevelChange::Added {
+                    price: dec!(97),
+                    size: dec!(7)
+                },
+                LevelChange::Removed { price: dec!(98) },
+                LevelChange::Changed {
+                    price: dec!(99),
+                    size: dec!(6)
+                },
+            ]
+        );
+        assert_eq!(diff.asks, vec![LevelChange::Removed { price: dec!(101) }]);
+
+        let (mut bids, mut asks) = levels(&before);
+        diff.apply(&mut bids, &mut asks);
+        assert_eq!((bids, asks), levels(&book));
+    }
+
+    #[test]
+    fn test_diff_from_and_to_empty() {
+        let empty = LimitOrderBook::new();
+        let mut book = LimitOrderBook::new();
+        book.add_order(order(1, OrderType::Bid, dec!(5), dec!(99)));
+        book.add_order(order(2, OrderType::Ask, dec!(4), dec!(101)));
+
+        let added = empty.diff(&book);
+        assert_eq!(
+            added.bids,
+            vec![LevelChange::Added {
+                price: dec!(99),
+                size: dec!(5)
+            }]
+        );
+        assert_eq!(added.asks[0].size(), dec!(4));
+        let removed = book.diff(&empty);
+        assert_eq!(removed.bids, vec![LevelChange::Removed { price: dec!(99) }]);
+        assert_eq!(removed.asks[0].size(), dec!(0));
+        assert_eq!(removed.asks[0].price(), dec!(101));
+
+        // Applying both leaves nothing behind.
+        let (mut bids, mut asks) = levels(&empty);
+        added.apply(&mut bids, &mut asks);
+        assert_eq!((bids.clone(), asks.clone()), levels(&book));
+        removed.apply(&mut bids, &mut asks);
+        assert!(bids.is_empty() && asks.is_empty());
+
+        let json = serde_json::to_string(&added.bids[0]).unwrap();
+        assert_eq!(json, r#"{"Added":{"price":"99","size":"5"}}"#);
+    }
+}
@@ -0,0 +1,49 @@
+use super::order::OrderType;
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RemovalReason {
+    Cancelled,
+    Filled,
+}
+
+/// Changes to the resting state of a `LimitOrderBook`, buffered in the order
+/// they happened until the owner drains them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BookEvent {
+    OrderAdded {
+        exchange_id: u64,
+        order_type: OrderType,
+        price: Decimal,
+        quantity: Decimal,
+        /// Number of orders ahead of this one at its price level.
+        queue_position: usize,
+    },
+    OrderExecuted {
+        exchange_id: u64,
+        order_type: OrderType,
+        price: Decimal,
+        quantity: Decimal,
+        remaining_quantity: Decimal,
+    },
+    /// `quantity` is the unfilled remainder taken off the book, which is zero
+    /// for filled orders since their executions were already reported.
+    OrderRemoved {
+        exchange_id: u64,
+        order_type: OrderType,
+        price: Decimal,
+        quantity: Decimal,
+        reason: RemovalReason,
+    },
+}
+
+impl BookEvent {
+    pub fn exchange_id(&self) -> u64 {
+        match self {
+            BookEvent::OrderAdded { exchange_id, .. }
+            | BookEvent::OrderExecuted { exchange_id, .. }
+            | BookEvent::OrderRemoved { exchange_id, .. } => *exchange_id,
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Plugins consulted for every match a book makes, which may move its price
+//! or stop it, e.g. for price-improvement experiments or crossing
+//! restrictions.
+
+use super::order::{Order, OrderType};
+use rust_decimal::Decimal;
+use std::{fmt, rc::Rc};
+
+/// A match the book is about to make, as a hook sees it. Both orders are
+/// as they were before it.
+#[derive(Debug, Clone, Copy)]
+pub struct ProposedMatch<'a> {
+    pub maker: &'a Order,
+    pub taker: &'a Order,
+    pub quantity: Decimal,
+    /// The maker's price, or where the hooks before this one moved it.
+    pub price: Decimal,
+}
+
+impl ProposedMatch<'_> {
+    /// The lowest and highest price both orders accept: the maker's price
+    /// and the taker's limit.
+    pub fn bounds(&self) -> (Decimal, Decimal) {
+        match self.taker.order_type {
+            OrderType::Bid => (self.maker.limit_price, self.taker.limit_price),
+            OrderType::Ask => (self.taker.limit_price, self.maker.limit_price),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchDecision {
+    Accept,
+    /// Executes at this price instead, brought within `bounds` if outside.
+    Adjust(Decimal),
+    /// Stops the match and the rest of the incoming order's matching; what
+    /// is left of it is cancelled, since resting it would cross the book.
+    Veto,
+}
+
+/// Registered per book and run in registration order. Each hook sees the
+/// price as the ones before it left it, and the first veto ends the round:
+/// later hooks aren't asked.
+pub trait MatchHook: fmt::Debug {
+    fn on_match(&self, proposed: &ProposedMatch) -> MatchDecision;
+}
+
+/// Runs `hooks` over a match and returns its price, or `None` if one of
+/// them vetoed it.
+pub(crate) fn decide(
+    hooks: &[Rc<dyn MatchHook>],
+    maker: &Order,
+    taker: &Order,
+    quantity: Decimal,
+) -> Option<Decimal> {
+    let mut proposed = ProposedMatch {
+        maker,
+        taker,
+        quantity,
+        price: maker.limit_price,
+    };
+    for hook in hooks {
+        match hook.on_match(&proposed) {
+            MatchDecision::Accept => {}
+            MatchDecision::Adjust(price) => {
+                let (low, high) = proposed.bounds();
+                proposed.price = price.clamp(low, high);
+            }
+            MatchDecision::Veto => return None,
+        }
+    }
+    Some(proposed.price)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::{LimitOrderBook, OrderStatus};
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+    use std::cell::RefCell;
+
+    /// Moves every match by `step`, noting the price it was shown.
+    #[derive(Debug)]
+    struct Shift {
+        step: Decimal,
+        seen: RefCell<Vec<Decimal>>,
+    }
+
+    impl MatchHook for Shift {
+        fn on_match(&self, proposed: &ProposedMatch) -> MatchDecision {
+            self.seen.borrow_mut().push(proposed.price);
+            MatchDecision::Adjust(proposed.price + self.step)
+        }
+    }
+
+    /// Refuses matches with the given maker.
+    #[derive(Debug)]
+    struct Block(u64);
+
+    impl MatchHook for Block {
+        fn on_match(&self, proposed: &ProposedMatch) -> MatchDecision {
+            if proposed.maker.exchange_id == self.0 {
+                MatchDecision::Veto
+            } else {
+                MatchDecision::Accept
+            }
+        }
+    }
+
+    fn order(exchange_id: u64, side: OrderType, shares: Decimal, price: Decimal) -> Order {
+        Order::new(
+            "BTC/USDT".to_string(),
+            exchange_id,
+            side,
+            shares,
+            price,
+            Utc::now(),
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn test_hook_ordering() {
+        let first = Rc::new(Shift {
+            step: dec!(0.5),
+            seen: RefCell::new(Vec::new()),
+        });
+        let second = Rc::new(Shift {
+            step: dec!(1),
+            seen: RefCell::new(Vec::new()),
+        });
+        let mut book = LimitOrderBook::new()
+            .with_hook(first.clone())
+            .with_hook(second.clone())
+            .with_hook(Rc::new(Block(2)));
+        book.add_order(order(1, OrderType::Ask, dec!(1), dec!(100)));
+        book.add_order(order(2, OrderType::Ask, dec!(1), dec!(101)));
+
+        // Each hook sees the price the one before left, and the last
+        // adjustment is held to the taker's limit.
+        let (taker, fills) = book.place_order(order(3, OrderType::Bid, dec!(2), dec!(101)));
+        assert_eq!(*first.seen.borrow(), vec![dec!(100), dec!(101)]);
+        assert_eq!(*second.seen.borrow(), vec![dec!(100.5), dec!(101)]);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].price, dec!(101));
+
+        // The veto came last, after both shifts had run; it stopped the
+        // second match, and the taker's remainder was cancelled.
+        assert_eq!(taker.status, OrderStatus::Cancelled);
+        assert_eq!(taker.filled_quantity, dec!(1));
+        assert_eq!(book.lowest_ask, Some(dec!(101)));
+        assert_eq!(book.highest_bid, None);
+
+        // A veto ahead of the shifts keeps them from being asked at all.
+        let mut book = LimitOrderBook::new()
+            .with_hook(Rc::new(Block(4)))
+            .with_hook(first.clone());
+        book.add_order(order(4, OrderType::Bid, dec!(1), dec!(99)));
+        let (taker, fills) = book.place_order(order(5, OrderType::Ask, dec!(1), dec!(98)));
+        assert!(fills.is_empty());
+        assert_eq!(taker.status, OrderStatus::Cancelled);
+        assert_eq!(first.seen.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_decide_clamps_to_bounds() {
+        let maker = order(1, OrderType::Bid, dec!(1), dec!(100));
+        let taker = order(2, OrderType::Ask, dec!(1), dec!(97));
+        let proposed = ProposedMatch {
+            maker: &maker,
+            taker: &taker,
+            quantity: dec!(1),
+            price: dec!(100),
+        };
+        assert_eq!(proposed.bounds(), (dec!(97), dec!(100)));
+
+        assert_eq!(decide(&[], &maker, &taker, dec!(1)), Some(dec!(100)));
+        let down: Rc<dyn MatchHook> = Rc::new(Shift {
+            step: dec!(-2),
+            seen: RefCell::new(Vec::new()),
+        });
+        assert_eq!(
+            decide(std::slice::from_ref(&down), &maker, &taker, dec!(1)),
+            Some(dec!(98))
+        );
+        // Moved twice, the price stops at the seller's limit.
+        assert_eq!(
+            decide(&[down.clone(), down], &maker, &taker, dec!(1)),
+            Some(dec!(97))
+        );
+        assert_eq!(decide(&[Rc::new(Block(1))], &maker, &taker, dec!(1)), None);
+    }
+}
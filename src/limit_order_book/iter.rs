@@ -0,0 +1,183 @@
+//! Walking a book's levels and orders in place, without copying them out.
+//!
+//! Levels sit behind `RefCell`s, so the items borrow them: hold on to one
+//! and the book can't be changed until it is dropped, which the borrow of
+//! the book already ensures.
+
+use super::order::{Limit, LimitOrderBook, Order};
+use rust_decimal::Decimal;
+use std::{
+    cell::{Ref, RefCell},
+    rc::Rc,
+};
+
+/// A borrowed price level.
+#[derive(Debug)]
+pub struct LevelRef<'a>(Ref<'a, Limit>);
+
+impl LevelRef<'_> {
+    pub fn price(&self) -> Decimal {
+        self.0.limit_price
+    }
+
+    /// Quantity resting at the level.
+    pub fn size(&self) -> Decimal {
+        self.0.size
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.queue.is_empty()
+    }
+
+    /// The level's orders, first in the queue first.
+    pub fn orders(&self) -> impl Iterator<Item = &Order> {
+        self.0
+            .queue
+            .iter()
+            .map(|exchange_id| &self.0.orders[exchange_id])
+    }
+}
+
+/// Orders level by level, each level in queue order.
+pub struct Orders<'a> {
+    levels: Box<dyn Iterator<Item = &'a Rc<RefCell<Limit>>> + 'a>,
+    level: Option<Ref<'a, Limit>>,
+    position: usize,
+}
+
+impl<'a> Iterator for Orders<'a> {
+    type Item = Ref<'a, Order>;
+
+    fn next(&mut self) -> Option<Ref<'a, Order>> {
+        loop {
+            if let Some(level) = &self.level {
+                if let Some(exchange_id) = level.queue.get(self.position).copied() {
+                    self.position += 1;
+                    return Some(Ref::map(Ref::clone(level), |limit| {
+                        &limit.orders[&exchange_id]
+                    }));
+                }
+            }
+            self.level = Some(self.levels.next()?.borrow());
+            self.position = 0;
+        }
+    }
+}
+
+impl LimitOrderBook {
+    /// Bid levels, best (highest) first.
+    pub fn iter_bids(&self) -> impl Iterator<Item = (Decimal, LevelRef<'_>)> {
+        self.bids
+            .iter()
+            .rev()
+            .map(|(price, limit)| (*price, LevelRef(limit.borrow())))
+    }
+
+    /// Ask levels, best (lowest) first.
+    pub fn iter_asks(&self) -> impl Iterator<Item = (Decimal, LevelRef<'_>)> {
+        self.asks
+            .iter()
+            .map(|(price, limit)| (*price, LevelRef(limit.borrow())))
+    }
+
+    /// Every resting order in price-time order: the bids from the best
+    /// level down, then the asks from the best level up.
+    pub fn iter_orders(&self) -> Orders<'_> {
+        Orders {
+            levels: Box::new(self.bids.values().rev().chain(self.asks.values())),
+            level: None,
+            position: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::OrderType;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_book_iterators() {
+        let mut book = LimitOrderBook::new();
+        for (exchange_id, side, price) in [
+            (1, OrderType::Bid, dec!(98)),
+            (2, OrderType::Bid, dec!(99)),
+            (3, OrderType::Ask, dec!(102)),
+            (4, OrderType::Bid, dec!(99)),
+            (5, OrderType::Ask, dec!(101)),
+        ] {
+            book.add_order(Order::new(
+                "BTC/USDT".to_string(),
+                exchange_id,
+                side,
+                dec!(1),
+                price,
+                Utc::now(),
+                Utc::now(),
+            ));
+        }
+
+        let bids: Vec<(Decimal, Decimal)> = book
+            .iter_bids()
+            .map(|(price, level)| (price, level.size()))
+            .collect();
+        assert_eq!(bids, vec![(dec!(99), dec!(2)), (dec!(98), dec!(1))]);
+        let asks: Vec<Decimal> = book.iter_asks().map(|(price, _)| price).collect();
+        assert_eq!(asks, vec![dec!(101), dec!(102)]);
+        let (_, best) = book.iter_bids().next().unwrap();
+        let queue: Vec<u64> = best.orders().map(|order| order.exchange_id).collect();
+        assert_eq!(queue, vec![2, 4]);
+
+        let ids: Vec<u64> = book.iter_orders().map(|order| order.exchange_id).collect();
+        assert_eq!(ids, vec![2, 4, 1, 5, 3]);
+        assert_eq!(LimitOrderBook::new().iter_orders().count(), 0);
+    }
+
+    #[test]
+    fn test_iterators_follow_fills_and_cancels() {
+        let mut book = LimitOrderBook::new();
+        for exchange_id in 1..=3 {
+            book.add_order(Order::new(
+                "BTC/USDT".to_string(),
+                exchange_id,
+                OrderType::Ask,
+                dec!(2),
+                dec!(100),
+                Utc::now(),
+                Utc::now(),
+            ));
+        }
+        book.cancel_order(2);
+        book.execute_market_order(Order::new(
+            "BTC/USDT".to_string(),
+            4,
+            OrderType::Bid,
+            dec!(1),
+            dec!(0),
+            Utc::now(),
+            Utc::now(),
+        ));
+
+        assert_eq!(book.iter_bids().count(), 0);
+        let (price, level) = book.iter_asks().next().unwrap();
+        assert_eq!((price, level.price()), (dec!(100), dec!(100)));
+        assert_eq!((level.len(), level.size()), (2, dec!(3)));
+        assert!(!level.is_empty());
+        let remaining: Vec<(u64, Decimal)> = level
+            .orders()
+            .map(|order| (order.exchange_id, order.remaining_quantity))
+            .collect();
+        assert_eq!(remaining, vec![(1, dec!(1)), (3, dec!(2))]);
+        drop(level);
+
+        // An asks-only book walks straight to its first level.
+        let ids: Vec<u64> = book.iter_orders().map(|order| order.exchange_id).collect();
+        assert_eq!(ids, vec![1, 3]);
+    }
+}
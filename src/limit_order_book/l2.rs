@@ -0,0 +1,198 @@
+//! Mirroring an external venue's book from its level-2 feed.
+//!
+//! Such a feed only carries aggregate sizes, so each mirrored level is one
+//! aggregate order under the client `L2_CLIENT`. It rests alongside any
+//! order-level orders at the same price, which the updates leave alone.
+//! A change in size replaces the aggregate, moving it to the back of its
+//! level's queue.
+
+use super::order::{LimitOrderBook, Order, OrderError, OrderType};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+
+/// The client the aggregate orders of mirrored levels rest under.
+pub const L2_CLIENT: &str = "l2";
+
+/// How an update's size relates to what the level had.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L2Update {
+    /// The level's new aggregate size.
+    Absolute(Decimal),
+    /// A change to the level's aggregate size.
+    Delta(Decimal),
+}
+
+impl LimitOrderBook {
+    /// The mirrored size at `price` on `side`, not counting order-level
+    /// orders.
+    pub fn l2_size(&self, side: OrderType, price: Decimal) -> Decimal {
+        self.l2_order(side, price)
+            .map_or(Decimal::ZERO, |order| order.remaining_quantity)
+    }
+
+    /// Sets the mirrored size at `price` on `side` and returns it. A size of
+    /// zero removes the level's aggregate; sizes below zero are rejected.
+    pub fn apply_l2_update(
+        &mut self,
+        side: OrderType,
+        price: Decimal,
+        update: L2Update,
+        time: DateTime<Utc>,
+    ) -> Result<Decimal, OrderError> {
+        let current = self.l2_order(side, price).cloned();
+        let size = match update {
+            L2Update::Absolute(size) => size,
+            L2Update::Delta(delta) => {
+                current
+                    .as_ref()
+                    .map_or(Decimal::ZERO, |order| order.remaining_quantity)
+                    + delta
+            }
+        };
+        if size < Decimal::ZERO {
+            return Err(OrderError::InvalidQuantity(size));
+        }
+        if current.as_ref().map(|order| order.remaining_quantity) == Some(size) {
+            return Ok(size);
+        }
+
+        let exchange_id = match current {
+            Some(order) => {
+                self.remove_order(order.clone());
+                order.exchange_id
+            }
+            None => self.unused_l2_id(),
+        };
+        if !size.is_zero() {
+            self.add_order(
+                Order::new(String::new(), exchange_id, side, size, price, time, time)
+                    .with_client(L2_CLIENT),
+            );
+        }
+        Ok(size)
+    }
+
+    /// Removes every mirrored level, returning the aggregates' exchange IDs.
+    pub fn clear_l2(&mut self) -> Vec<u64> {
+        self.cancel_all_for_client(L2_CLIENT)
+    }
+
+    fn l2_order(&self, side: OrderType, price: Decimal) -> Option<&Order> {
+        self.client_orders
+            .get(L2_CLIENT)?
+            .iter()
+            .map(|exchange_id| &self.orders[exchange_id])
+            .find(|order| order.order_type == side && order.limit_price == price)
+    }
+
+    // Aggregates take exchange IDs from the top of the range down, out of
+    // the way of the IDs handed to real orders.
+    fn unused_l2_id(&self) -> u64 {
+        (0..)
+            .map(|offset| u64::MAX - offset)
+            .find(|exchange_id| !self.orders.contains_key(exchange_id))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_apply_l2_update() {
+        let mut book = LimitOrderBook::new();
+        let now = Utc::now();
+        book.add_order(Order::new(
+            "BTC/USDT".to_string(),
+            1,
+            OrderType::Bid,
+            dec!(1),
+            dec!(99),
+            now,
+            now,
+        ));
+
+        let absolute = |size| L2Update::Absolute(size);
+        assert_eq!(
+            book.apply_l2_update(OrderType::Bid, dec!(99), absolute(dec!(5)), now),
+            Ok(dec!(5))
+        );
+        assert_eq!(
+            book.apply_l2_update(OrderType::Ask, dec!(101), absolute(dec!(3)), now),
+            Ok(dec!(3))
+        );
+        assert_eq!(book.get_bid_depth(dec!(99)), dec!(6));
+        assert_eq!(book.l2_size(OrderType::Bid, dec!(99)), dec!(5));
+        assert_eq!(book.get_best_ask(), Some(dec!(101)));
+
+        assert_eq!(
+            book.apply_l2_update(OrderType::Bid, dec!(99), L2Update::Delta(dec!(-2)), now),
+            Ok(dec!(3))
+        );
+        assert_eq!(book.get_bid_depth(dec!(99)), dec!(4));
+        assert_eq!(book.get_bid_count(dec!(99)), 2);
+        assert_eq!(
+            book.apply_l2_update(OrderType::Bid, dec!(99), L2Update::Delta(dec!(-4)), now),
+            Err(OrderError::InvalidQuantity(dec!(-1)))
+        );
+
+        // Order-level orders trade against the mirrored size like any other.
+        let fills = book.execute_order(Order::new(
+            "BTC/USDT".to_string(),
+            2,
+            OrderType::Bid,
+            dec!(1),
+            dec!(101),
+            now,
+            now,
+        ));
+        assert_eq!(fills.len(), 1);
+        assert_eq!(book.l2_size(OrderType::Ask, dec!(101)), dec!(2));
+
+        book.apply_l2_update(OrderType::Bid, dec!(99), absolute(dec!(0)), now)
+            .unwrap();
+        assert_eq!(book.get_bid_depth(dec!(99)), dec!(1));
+        assert_eq!(book.clear_l2().len(), 1);
+        assert_eq!(book.get_best_ask(), None);
+        assert!(book.get_order(1).is_some());
+    }
+
+    #[test]
+    fn test_queue_position_and_ids() {
+        let mut book = LimitOrderBook::new();
+        let now = Utc::now();
+        let queue = |book: &LimitOrderBook| -> Vec<u64> {
+            let (_, level) = book.iter_bids().next().unwrap();
+            level.orders().map(|order| order.exchange_id).collect()
+        };
+
+        // A delta on a missing level starts it from zero.
+        book.apply_l2_update(OrderType::Bid, dec!(99), L2Update::Delta(dec!(2)), now)
+            .unwrap();
+        book.apply_l2_update(OrderType::Bid, dec!(98), L2Update::Delta(dec!(1)), now)
+            .unwrap();
+        assert_eq!(book.l2_size(OrderType::Bid, dec!(98)), dec!(1));
+        assert!(book.get_order(u64::MAX).is_some() && book.get_order(u64::MAX - 1).is_some());
+
+        book.add_order(Order::new(
+            "BTC/USDT".to_string(),
+            1,
+            OrderType::Bid,
+            dec!(1),
+            dec!(99),
+            now,
+            now,
+        ));
+        assert_eq!(queue(&book), vec![u64::MAX, 1]);
+        // The same size keeps the aggregate's place; a new one loses it.
+        book.apply_l2_update(OrderType::Bid, dec!(99), L2Update::Absolute(dec!(2)), now)
+            .unwrap();
+        assert_eq!(queue(&book), vec![u64::MAX, 1]);
+        book.apply_l2_update(OrderType::Bid, dec!(99), L2Update::Absolute(dec!(4)), now)
+            .unwrap();
+        assert_eq!(queue(&book), vec![1, u64::MAX]);
+        assert_eq!(book.l2_size(OrderType::Ask, dec!(99)), dec!(0));
+    }
+}
@@ -0,0 +1,239 @@
+use super::{
+    event::{BookEvent, RemovalReason},
+    order::OrderType,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How much of the market-by-order stream is published.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum L3Privacy {
+    /// No L3 output at all.
+    #[default]
+    Off,
+    /// Orders carry feed-local IDs that cannot be matched to exchange IDs
+    /// or execution reports.
+    Anonymized,
+    /// Orders carry their exchange IDs.
+    Full,
+}
+
+/// A market-by-order update.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum L3Event {
+    Add {
+        order_id: u64,
+        side: OrderType,
+        price: Decimal,
+        quantity: Decimal,
+        /// Number of orders ahead at the level when the order joined.
+        queue_position: usize,
+    },
+    Execute {
+        order_id: u64,
+        side: OrderType,
+        price: Decimal,
+        quantity: Decimal,
+        remaining_quantity: Decimal,
+    },
+    Delete {
+        order_id: u64,
+        side: OrderType,
+        price: Decimal,
+        quantity: Decimal,
+        reason: RemovalReason,
+    },
+}
+
+/// Turns book events into L3 updates under a privacy setting.
+#[derive(Debug, Clone, Default)]
+pub struct L3Feed {
+    privacy: L3Privacy,
+    order_ids: HashMap<u64, u64>,
+    next_order_id: u64,
+}
+
+impl L3Feed {
+    pub fn new(privacy: L3Privacy) -> Self {
+        Self {
+            privacy,
+            order_ids: HashMap::new(),
+            next_order_id: 1,
+        }
+    }
+
+    pub fn privacy(&self) -> L3Privacy {
+        self.privacy
+    }
+
+    /// Converts `events`, in order. Returns nothing when the feed is off.
+    pub fn publish(&mut self, events: &[BookEvent]) -> Vec<L3Event> {
+        if self.privacy == L3Privacy::Off {
+            return Vec::new();
+        }
+        events.iter().map(|event| self.convert(event)).collect()
+    }
+
+    fn convert(&mut self, event: &BookEvent) -> L3Event {
+        match *event {
+            BookEvent::OrderAdded {
+                exchange_id,
+                order_type,
+                price,
+                quantity,
+                queue_position,
+            } => L3Event::Add {
+                order_id: self.order_id(exchange_id),
+                side: order_type,
+                price,
+                quantity,
+                queue_position,
+            },
+            BookEvent::OrderExecuted {
+                exchange_id,
+                order_type,
+                price,
+                quantity,
+                remaining_quantity,
+            } => L3Event::Execute {
+                order_id: self.order_id(exchange_id),
+                side: order_type,
+                price,
+                quantity,
+                remaining_quantity,
+            },
+            BookEvent::OrderRemoved {
+                exchange_id,
+                order_type,
+                price,
+                quantity,
+                reason,
+            } => {
+                let order_id = self.order_id(exchange_id);
+                // The order is gone, so its alias is no longer needed.
+                self.order_ids.remove(&exchange_id);
+                L3Event::Delete {
+                    order_id,
+                    side: order_type,
+                    price,
+                    quantity,
+                    reason,
+                }
+            }
+        }
+    }
+
+    fn order_id(&mut self, exchange_id: u64) -> u64 {
+        if self.privacy == L3Privacy::Full {
+            return exchange_id;
+        }
+        let next_order_id = &mut self.next_order_id;
+        *self.order_ids.entry(exchange_id).or_insert_with(|| {
+            let order_id = *next_order_id;
+            *next_order_id += 1;
+            order_id
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::{LimitOrderBook, Order};
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    fn order(exchange_id: u64, order_type: OrderType, shares: Decimal, price: Decimal) -> Order {
+        Order::new(
+            "BTC/USDT".to_string(),
+            exchange_id,
+            order_type,
+            shares,
+            price,
+            Utc::now(),
+            Utc::now(),
+        )
+    }
+
+    fn book() -> LimitOrderBook {
+        let mut book = LimitOrderBook::new();
+        book.add_order(order(41, OrderType::Ask, dec!(2), dec!(100)));
+        book.add_order(order(42, OrderType::Ask, dec!(3), dec!(100)));
+        book.place_order(order(43, OrderType::Bid, dec!(4), dec!(100)));
+        book.cancel_order(42);
+        book
+    }
+
+    #[test]
+    fn test_full_feed() {
+        let mut book = book();
+        let events = L3Feed::new(L3Privacy::Full).publish(&book.drain_events());
+        assert_eq!(
+            events,
+            vec![
+                L3Event::Add {
+                    order_id: 41,
+                    side: OrderType::Ask,
+                    price: dec!(100),
+                    quantity: dec!(2),
+                    queue_position: 0,
+                },
+                L3Event::Add {
+                    order_id: 42,
+                    side: OrderType::Ask,
+                    price: dec!(100),
+                    quantity: dec!(3),
+                    queue_position: 1,
+                },
+                L3Event::Execute {
+                    order_id: 41,
+                    side: OrderType::Ask,
+                    price: dec!(100),
+                    quantity: dec!(2),
+                    remaining_quantity: dec!(0),
+                },
+                L3Event::Delete {
+                    order_id: 41,
+                    side: OrderType::Ask,
+                    price: dec!(100),
+                    quantity: dec!(0),
+                    reason: RemovalReason::Filled,
+                },
+                L3Event::Execute {
+                    order_id: 42,
+                    side: OrderType::Ask,
+                    price: dec!(100),
+                    quantity: dec!(2),
+                    remaining_quantity: dec!(1),
+                },
+                L3Event::Delete {
+                    order_id: 42,
+                    side: OrderType::Ask,
+                    price: dec!(100),
+                    quantity: dec!(1),
+                    reason: RemovalReason::Cancelled,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_privacy_settings() {
+        let events = book().drain_events();
+        assert!(L3Feed::new(L3Privacy::Off).publish(&events).is_empty());
+
+        let anonymized = L3Feed::new(L3Privacy::Anonymized).publish(&events);
+        let ids: Vec<u64> = anonymized
+            .iter()
+            .map(|event| match event {
+                L3Event::Add { order_id, .. }
+                | L3Event::Execute { order_id, .. }
+                | L3Event::Delete { order_id, .. } => *order_id,
+            })
+            .collect();
+        assert_eq!(ids, vec![1, 2, 1, 1, 2, 2]);
+    }
+}
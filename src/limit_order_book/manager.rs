@@ -0,0 +1,189 @@
+use super::order::{Fill, LimitOrderBook, Order};
+use rust_decimal::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TopOfBook {
+    pub best_bid: Option<Decimal>,
+    pub best_ask: Option<Decimal>,
+}
+
+/// Owns one `LimitOrderBook` per symbol and routes orders to the right book
+/// using their `tick_id`.
+#[derive(Debug, Default)]
+pub struct BookManager {
+    books: HashMap<String, LimitOrderBook>,
+}
+
+impl BookManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the book for `symbol`, creating an empty one if needed.
+    pub fn add_book(&mut self, symbol: impl Into<String>) -> &mut LimitOrderBook {
+        self.books.entry(symbol.into()).or_default()
+    }
+
+    pub fn remove_book(&mut self, symbol: &str) -> Option<LimitOrderBook> {
+        self.books.remove(symbol)
+    }
+
+    pub fn book(&self, symbol: &str) -> Option<&LimitOrderBook> {
+        self.books.get(symbol)
+    }
+
+    pub fn book_mut(&mut self, symbol: &str) -> Option<&mut LimitOrderBook> {
+        self.books.get_mut(symbol)
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.books.keys().map(String::as_str)
+    }
+
+    pub fn add_order(&mut self, order: Order) -> Result<(), String> {
+        self.route(&order.tick_id)?.add_order(order);
+        Ok(())
+    }
+
+    pub fn place_order(&mut self, order: Order) -> Result<(Order, Vec<Fill>), String> {
+        Ok(self.route(&order.tick_id)?.place_order(order))
+    }
+
+    pub fn execute_order(&mut self, order: Order) -> Result<Vec<Fill>, String> {
+        Ok(self.route(&order.tick_id)?.execute_order(order))
+    }
+
+    pub fn cancel_order(&mut self, symbol: &str, exchange_id: u64) -> Option<Order> {
+        self.books.get_mut(symbol)?.cancel_order(exchange_id)
+    }
+
+    /// Looks an order up across every book, returning its symbol with it.
+    pub fn find_order(&self, exchange_id: u64) -> Option<(&str, &Order)> {
+        self.books.iter().find_map(|(symbol, book)| {
+            book.get_order(exchange_id)
+                .map(|order| (symbol.as_str(), order))
+        })
+    }
+
+    pub fn top_of_book(&self, symbol: &str) -> Option<TopOfBook> {
+        self.books.get(symbol).map(|book| TopOfBook {
+            best_bid: book.get_best_bid(),
+            best_ask: book.get_best_ask(),
+        })
+    }
+
+    /// Best bid and ask of every symbol, ordered by symbol.
+    pub fn top_of_books(&self) -> BTreeMap<&str, TopOfBook> {
+        self.books
+            .keys()
+            .map(|symbol| (symbol.as_str(), self.top_of_book(symbol).unwrap()))
+            .collect()
+    }
+
+    /// Cancels every resting order of `client` in every book.
+    pub fn cancel_all_for_client(&mut self, client: &str) -> BTreeMap<String, Vec<u64>> {
+        self.books
+            .iter_mut()
+            .map(|(symbol, book)| (symbol.clone(), book.cancel_all_for_client(client)))
+            .filter(|(_, cancelled)| !cancelled.is_empty())
+            .collect()
+    }
+
+    fn route(&mut self, symbol: &str) -> Result<&mut LimitOrderBook, String> {
+        self.books
+            .get_mut(symbol)
+            .ok_or_else(|| format!("No order book for symbol: {:?}", symbol))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::OrderType;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    fn order(symbol: &str, exchange_id: u64, order_type: OrderType, price: Decimal) -> Order {
+        Order::new(
+            symbol.to_string(),
+            exchange_id,
+            order_type,
+            dec!(10),
+            price,
+            Utc::now(),
+            Utc::now(),
+        )
+        .with_client("alice")
+    }
+
+    #[test]
+    fn test_routes_orders_by_symbol() {
+        let mut manager = BookManager::new();
+        manager.add_book("BTC/USDT");
+        manager.add_book("ETH/USDT");
+
+        manager
+            .add_order(order("BTC/USDT", 1, OrderType::Bid, dec!(100)))
+            .unwrap();
+        manager
+            .add_order(order("ETH/USDT", 2, OrderType::Ask, dec!(10)))
+            .unwrap();
+        assert!(manager
+            .add_order(order("SOL/USDT", 3, OrderType::Bid, dec!(1)))
+            .is_err());
+
+        assert_eq!(manager.book("BTC/USDT").unwrap().orders.len(), 1);
+        assert_eq!(manager.find_order(2).unwrap().0, "ETH/USDT");
+        assert!(manager.find_order(3).is_none());
+
+        let (_, fills) = manager
+            .place_order(order("ETH/USDT", 4, OrderType::Bid, dec!(10)))
+            .unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].maker_id, 2);
+        assert!(manager.book("BTC/USDT").unwrap().get_order(1).is_some());
+    }
+
+    #[test]
+    fn test_cross_symbol_queries() {
+        let mut manager = BookManager::new();
+        manager.add_book("BTC/USDT");
+        manager.add_book("ETH/USDT");
+        manager
+            .add_order(order("BTC/USDT", 1, OrderType::Bid, dec!(100)))
+            .unwrap();
+        manager
+            .add_order(order("BTC/USDT", 2, OrderType::Ask, dec!(101)))
+            .unwrap();
+        manager
+            .add_order(order("ETH/USDT", 3, OrderType::Ask, dec!(10)))
+            .unwrap();
+
+        let tops = manager.top_of_books();
+        assert_eq!(
+            tops.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    "BTC/USDT",
+                    TopOfBook {
+                        best_bid: Some(dec!(100)),
+                        best_ask: Some(dec!(101)),
+                    }
+                ),
+                (
+                    "ETH/USDT",
+                    TopOfBook {
+                        best_bid: None,
+                        best_ask: Some(dec!(10)),
+                    }
+                ),
+            ]
+        );
+
+        let cancelled = manager.cancel_all_for_client("alice");
+        assert_eq!(cancelled["BTC/USDT"].len(), 2);
+        assert_eq!(cancelled["ETH/USDT"], vec![3]);
+        assert!(manager.book("BTC/USDT").unwrap().orders.is_empty());
+    }
+}
@@ -1,3 +1,24 @@
+pub mod allocation;
+pub mod arithmetic;
+pub mod bounded;
+pub mod builder;
+pub mod checksum;
+pub mod composite;
+pub mod diff;
+pub mod event;
+pub mod hooks;
+pub mod iter;
+pub mod l2;
+pub mod l3;
+pub mod manager;
+#[cfg(test)]
+mod model;
 pub mod order;
 pub mod orderbook;
-pub mod rb_tree;
\ No newline at end of file
+pub mod prune;
+pub mod rb_tree;
+pub mod render;
+pub mod router;
+pub mod stats;
+pub mod validate;
+pub mod view;
\ No newline at end of file
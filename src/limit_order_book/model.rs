@@ -0,0 +1,281 @@
+//! Model checking for `LimitOrderBook`: proptest generates command
+//! sequences, runs them through the book and through a deliberately naive
+//! reference book, and requires identical fills and final state.
+
+use super::order::{LimitOrderBook, Order, OrderType};
+use chrono::{TimeZone, Utc};
+use proptest::{prelude::*, sample::Index};
+use rust_decimal::Decimal;
+
+#[derive(Debug, Clone)]
+enum Command {
+    Place {
+        side: OrderType,
+        price: u32,
+        quantity: u32,
+    },
+    /// Matches up to the limit price and drops the remainder.
+    Execute {
+        side: OrderType,
+        price: u32,
+        quantity: u32,
+    },
+    Market {
+        side: OrderType,
+        quantity: u32,
+    },
+    /// Cancels one of the IDs handed out so far, resting or not.
+    Cancel(Index),
+}
+
+fn side() -> impl Strategy<Value = OrderType> {
+    prop_oneof![Just(OrderType::Bid), Just(OrderType::Ask)]
+}
+
+fn command() -> impl Strategy<Value = Command> {
+    // A narrow price range keeps the book crossing often.
+    let price = 95u32..=105;
+    let quantity = 1u32..=20;
+    prop_oneof![
+        4 => (side(), price.clone(), quantity.clone())
+            .prop_map(|(side, price, quantity)| Command::Place { side, price, quantity }),
+        1 => (side(), price, quantity.clone())
+            .prop_map(|(side, price, quantity)| Command::Execute { side, price, quantity }),
+        1 => (side(), quantity).prop_map(|(side, quantity)| Command::Market { side, quantity }),
+        2 => any::<Index>().prop_map(Command::Cancel),
+    ]
+}
+
+/// `(maker_id, taker_id, price, quantity)`
+type Trade = (u64, u64, Decimal, Decimal);
+
+#[derive(Debug, Clone)]
+struct NaiveOrder {
+    id: u64,
+    side: OrderType,
+    price: Decimal,
+    remaining: Decimal,
+}
+
+/// Resting orders in arrival order; every match rescans all of them.
+#[derive(Debug, Default)]
+struct NaiveBook {
+    orders: Vec<NaiveOrder>,
+}
+
+impl NaiveBook {
+    fn sweep(
+        &mut self,
+        id: u64,
+        side: OrderType,
+        limit: Option<Decimal>,
+        quantity: &mut Decimal,
+    ) -> Vec<Trade> {
+        let mut trades = Vec::new();
+        while *quantity > Decimal::ZERO {
+            let mut best: Option<usize> = None;
+            for (index, maker) in self.orders.iter().enumerate() {
+                if maker.side == side {
+                    continue;
+                }
+                let crosses = match (side, limit) {
+                    (_, None) => true,
+                    (OrderType::Bid, Some(limit)) => maker.price <= limit,
+                    (OrderType::Ask, Some(limit)) => maker.price >= limit,
+                };
+                let better = match best {
+                    None => true,
+                    // Strictly better only, so the earliest order wins ties.
+                    Some(best) => match side {
+                        OrderType::Bid => maker.price < self.orders[best].price,
+                        OrderType::Ask => maker.price > self.orders[best].price,
+                    },
+                };
+                if crosses && better {
+                    best = Some(index);
+                }
+            }
+            let Some(best) = best else { break };
+
+            let maker = &mut self.orders[best];
+            let filled = maker.remaining.min(*quantity);
+            maker.remaining -= filled;
+            *quantity -= filled;
+            trades.push((maker.id, id, maker.price, filled));
+            if maker.remaining.is_zero() {
+                self.orders.remove(best);
+            }
+        }
+        trades
+    }
+
+    fn place(&mut self, id: u64, side: OrderType, price: Decimal, quantity: Decimal) -> Vec<Trade> {
+        let mut remaining = quantity;
+        let trades = self.sweep(id, side, Some(price), &mut remaining);
+        if remaining > Decimal::ZERO {
+            self.orders.push(NaiveOrder {
+                id,
+                side,
+                price,
+                remaining,
+            });
+        }
+        trades
+    }
+
+    fn cancel(&mut self, id: u64) -> bool {
+        let before = self.orders.len();
+        self.orders.retain(|order| order.id != id);
+        self.orders.len() != before
+    }
+
+    /// Aggregated `(price, size)` levels, best first.
+    fn levels(&self, side: OrderType) -> Vec<(Decimal, Decimal)> {
+        let mut levels: Vec<(Decimal, Decimal)> = Vec::new();
+        for order in self.orders.iter().filter(|order| order.side == side) {
+            match levels.iter_mut().find(|(price, _)| *price == order.price) {
+                Some((_, size)) => *size += order.remaining,
+                None => levels.push((order.price, order.remaining)),
+            }
+        }
+        levels.sort_by(|a, b| match side {
+            OrderType::Bid => b.0.cmp(&a.0),
+            OrderType::Ask => a.0.cmp(&b.0),
+        });
+        levels
+    }
+}
+
+fn order(id: u64, side: OrderType, price: Decimal, quantity: Decimal) -> Order {
+    let time = Utc.timestamp_opt(1_700_000_000 + id as i64, 0).unwrap();
+    Order::new(
+        "BTC/USDT".to_string(),
+        id,
+        side,
+        quantity,
+        price,
+        time,
+        time,
+    )
+}
+
+fn trades(fills: &[super::order::Fill]) -> Vec<Trade> {
+    fills
+        .iter()
+        .map(|fill| (fill.maker_id, fill.taker_id, fill.price, fill.quantity))
+        .collect()
+}
+
+fn run(commands: Vec<Command>) -> Result<(), TestCaseError> {
+    let mut book = LimitOrderBook::new();
+    let mut model = NaiveBook::default();
+
+    for (step, command) in commands.into_iter().enumerate() {
+        let id = step as u64 + 1;
+        match command {
+            Command::Place {
+                side,
+                price,
+                quantity,
+            } => {
+                let (price, quantity) = (Decimal::from(price), Decimal::from(quantity));
+                let (_, fills) = book.place_order(order(id, side, price, quantity));
+                prop_assert_eq!(trades(&fills), model.place(id, side, price, quantity));
+            }
+            Command::Execute {
+                side,
+                price,
+                quantity,
+            } => {
+                let (price, quantity) = (Decimal::from(price), Decimal::from(quantity));
+                let fills = book.execute_order(order(id, side, price, quantity));
+                let mut remaining = quantity;
+                let expected = model.sweep(id, side, Some(price), &mut remaining);
+                prop_assert_eq!(trades(&fills), expected);
+            }
+            Command::Market { side, quantity } => {
+                let quantity = Decimal::from(quantity);
+                let fills = book.execute_market_order(order(id, side, Decimal::ONE, quantity));
+                let mut remaining = quantity;
+                prop_assert_eq!(trades(&fills), model.sweep(id, side, None, &mut remaining));
+            }
+            Command::Cancel(index) => {
+                let target = index.index(id as usize) as u64 + 1;
+                prop_assert_eq!(book.cancel_order(target).is_some(), model.cancel(target));
+            }
+        }
+        prop_assert_eq!(book.debug_validate(), Ok(()));
+    }
+
+    let ladder = book.ladder(usize::MAX);
+    prop_assert_eq!(ladder.bids, model.levels(OrderType::Bid));
+    prop_assert_eq!(ladder.asks, model.levels(OrderType::Ask));
+    prop_assert_eq!(book.orders.len(), model.orders.len());
+    for resting in &model.orders {
+        let order = book.get_order(resting.id);
+        prop_assert_eq!(
+            order.map(|order| order.remaining_quantity),
+            Some(resting.remaining)
+        );
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn book_matches_naive_model(commands in prop::collection::vec(command(), 1..200)) {
+        run(commands)?;
+    }
+}
+
+#[test]
+fn naive_model_keeps_time_priority() {
+    let mut model = NaiveBook::default();
+    model.place(1, OrderType::Ask, Decimal::from(101), Decimal::from(3));
+    model.place(2, OrderType::Ask, Decimal::from(100), Decimal::from(2));
+    model.place(3, OrderType::Ask, Decimal::from(100), Decimal::from(2));
+    let trades = model.place(4, OrderType::Bid, Decimal::from(101), Decimal::from(5));
+    assert_eq!(
+        trades,
+        vec![
+            (2, 4, Decimal::from(100), Decimal::from(2)),
+            (3, 4, Decimal::from(100), Decimal::from(2)),
+            (1, 4, Decimal::from(101), Decimal::from(1)),
+        ]
+    );
+    assert_eq!(
+        model.levels(OrderType::Ask),
+        vec![(Decimal::from(101), Decimal::from(2))]
+    );
+    assert!(model.cancel(1));
+    assert!(!model.cancel(2));
+}
+
+#[test]
+fn book_matches_naive_model_on_fixed_sequence() {
+    let place = |side, price, quantity| Command::Place {
+        side,
+        price,
+        quantity,
+    };
+    run(vec![
+        place(OrderType::Bid, 99, 5),
+        place(OrderType::Bid, 100, 3),
+        place(OrderType::Bid, 100, 4),
+        place(OrderType::Ask, 104, 6),
+        // Crosses both orders at 100 and part of the one at 99.
+        place(OrderType::Ask, 99, 9),
+        Command::Execute {
+            side: OrderType::Bid,
+            price: 103,
+            quantity: 10,
+        },
+        Command::Market {
+            side: OrderType::Bid,
+            quantity: 2,
+        },
+    ])
+    .unwrap();
+}
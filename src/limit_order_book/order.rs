@@ -1,27 +1,127 @@
+use super::{
+    allocation::{Fifo, MatchAlgorithm},
+    event::{BookEvent, RemovalReason},
+    hooks::{self, MatchHook},
+    view::BookView,
+};
 use chrono::{DateTime, Utc};
 use rust_decimal::prelude::*;
 use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
 use std::{
     cell::RefCell,
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt,
     rc::Rc,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum OrderType {
     Bid,
     Ask,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Accepts `bid`/`buy` and `ask`/`sell`, in any case.
+impl std::str::FromStr for OrderType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bid" | "buy" => Ok(OrderType::Bid),
+            "ask" | "sell" => Ok(OrderType::Ask),
+            _ => Err(format!("Invalid side: {:?}", s)),
+        }
+    }
+}
+
+/// How long an order rests before the engine cancels what's left of it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TimeInForce {
+    /// Good till cancelled.
+    #[default]
+    Gtc,
+    /// Until the close of the market's session; see `MarketConfig::calendar`.
+    Day,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OrderStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderError {
+    UnknownOrder(u64),
+    InvalidQuantity(Decimal),
+    Overfill {
+        requested: Decimal,
+        remaining: Decimal,
+    },
+    InvalidTransition {
+        from: OrderStatus,
+        to: OrderStatus,
+    },
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderError::UnknownOrder(exchange_id) => write!(f, "unknown order: {}", exchange_id),
+            OrderError::InvalidQuantity(quantity) => {
+                write!(f, "invalid fill quantity: {}", quantity)
+            }
+            OrderError::Overfill {
+                requested,
+                remaining,
+            } => write!(
+                f,
+                "fill of {} exceeds remaining quantity {}",
+                requested, remaining
+            ),
+            OrderError::InvalidTransition { from, to } => {
+                write!(f, "invalid order transition: {:?} -> {:?}", from, to)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OrderError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Order {
     pub tick_id: String,
     pub exchange_id: u64,
+    /// The owning account, as an `AccountId` string.
+    pub client: String,
     pub order_type: OrderType,
     pub shares: Decimal,
     pub limit_price: Decimal,
     pub entry_time: DateTime<Utc>,
     pub event_time: DateTime<Utc>,
+    pub filled_quantity: Decimal,
+    pub remaining_quantity: Decimal,
+    pub status: OrderStatus,
+    /// Trading fees charged so far; negative when rebates exceed fees.
+    pub fees: Decimal,
+    /// Marks a sell as a short sale rather than a sale of a long position.
+    pub short_sale: bool,
+    /// Caps the order at what closes the client's position; see
+    /// `Positions::reducible`.
+    #[serde(default)]
+    pub reduce_only: bool,
+    /// The client's own ID for the order, unique per client; see
+    /// `MatchingEngine::place_limit_order`.
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+    /// The client's strategy the order belongs to, carried onto its fills,
+    /// drop copies and P&L.
+    #[serde(default)]
+    pub strategy: Option<String>,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
 }
 
 impl Order {
@@ -37,21 +137,156 @@ impl Order {
         Self {
             tick_id,
             exchange_id,
+            client: String::new(),
             order_type,
             shares,
             limit_price,
             entry_time,
             event_time,
+            filled_quantity: Decimal::zero(),
+            remaining_quantity: shares,
+            status: OrderStatus::New,
+            fees: Decimal::zero(),
+            short_sale: false,
+            reduce_only: false,
+            client_order_id: None,
+            strategy: None,
+            time_in_force: TimeInForce::Gtc,
         }
     }
+
+    pub fn with_client(mut self, client: impl Into<String>) -> Self {
+        self.client = client.into();
+        self
+    }
+
+    pub fn with_short_sale(mut self, short_sale: bool) -> Self {
+        self.short_sale = short_sale;
+        self
+    }
+
+    pub fn with_reduce_only(mut self, reduce_only: bool) -> Self {
+        self.reduce_only = reduce_only;
+        self
+    }
+
+    pub fn with_client_order_id(mut self, client_order_id: impl Into<String>) -> Self {
+        self.client_order_id = Some(client_order_id.into());
+        self
+    }
+
+    pub fn with_strategy(mut self, strategy: impl Into<String>) -> Self {
+        self.strategy = Some(strategy.into());
+        self
+    }
+
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
+
+    pub fn is_filled(&self) -> bool {
+        self.status == OrderStatus::Filled
+    }
+
+    pub fn is_active(&self) -> bool {
+        matches!(self.status, OrderStatus::New | OrderStatus::PartiallyFilled)
+    }
+
+    /// Executes `quantity` against the order, moving it to `PartiallyFilled`
+    /// or `Filled`.
+    pub fn fill(&mut self, quantity: Decimal) -> Result<(), OrderError> {
+        if quantity <= Decimal::zero() {
+            return Err(OrderError::InvalidQuantity(quantity));
+        }
+        if quantity > self.remaining_quantity {
+            return Err(OrderError::Overfill {
+                requested: quantity,
+                remaining: self.remaining_quantity,
+            });
+        }
+
+        let status = if quantity == self.remaining_quantity {
+            OrderStatus::Filled
+        } else {
+            OrderStatus::PartiallyFilled
+        };
+        self.transition(status)?;
+        self.filled_quantity += quantity;
+        self.remaining_quantity -= quantity;
+        Ok(())
+    }
+
+    /// Cancels the unfilled remainder of the order. The filled quantity is kept
+    /// so reports still carry the executed amount.
+    pub fn cancel(&mut self) -> Result<(), OrderError> {
+        self.transition(OrderStatus::Cancelled)?;
+        self.remaining_quantity = Decimal::zero();
+        Ok(())
+    }
+
+    pub fn execution_report(&self, last_quantity: Decimal, last_price: Decimal) -> ExecutionReport {
+        ExecutionReport {
+            exchange_id: self.exchange_id,
+            tick_id: self.tick_id.clone(),
+            order_type: self.order_type,
+            status: self.status,
+            last_quantity,
+            last_price,
+            cum_quantity: self.filled_quantity,
+            leaves_quantity: self.remaining_quantity,
+            cum_fee: self.fees,
+            event_time: self.event_time,
+        }
+    }
+
+    // Every status change goes through here so the allowed transitions are
+    // defined in one place.
+    fn transition(&mut self, to: OrderStatus) -> Result<(), OrderError> {
+        let allowed = matches!(
+            (self.status, to),
+            (OrderStatus::New, OrderStatus::PartiallyFilled)
+                | (OrderStatus::New, OrderStatus::Filled)
+                | (OrderStatus::New, OrderStatus::Cancelled)
+                | (OrderStatus::PartiallyFilled, OrderStatus::PartiallyFilled)
+                | (OrderStatus::PartiallyFilled, OrderStatus::Filled)
+                | (OrderStatus::PartiallyFilled, OrderStatus::Cancelled)
+        );
+
+        if !allowed {
+            return Err(OrderError::InvalidTransition {
+                from: self.status,
+                to,
+            });
+        }
+        self.status = to;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionReport {
+    pub exchange_id: u64,
+    pub tick_id: String,
+    pub order_type: OrderType,
+    pub status: OrderStatus,
+    pub last_quantity: Decimal,
+    pub last_price: Decimal,
+    pub cum_quantity: Decimal,
+    pub leaves_quantity: Decimal,
+    pub cum_fee: Decimal,
+    pub event_time: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Limit {
     pub limit_price: Decimal,
     pub orders: HashMap<u64, Order>,
+    pub queue: VecDeque<u64>,
     pub parent: Option<Box<Limit>>,
     pub size: Decimal,
+    /// Value of the orders resting at the level, saturating at
+    /// `Decimal::MAX`.
     pub total_volume: Decimal,
     pub order_count: u64,
 }
@@ -61,6 +296,7 @@ impl Limit {
         Self {
             limit_price,
             orders: HashMap::new(),
+            queue: VecDeque::new(),
             parent: None,
             size: Decimal::zero(),
             total_volume: Decimal::new(0, 0),
@@ -69,16 +305,23 @@ impl Limit {
     }
 
     pub fn add_order(&mut self, order: Order) {
-        self.size += order.shares;
-        self.total_volume += order.shares * order.limit_price;
+        self.size += order.remaining_quantity;
+        self.total_volume = self
+            .total_volume
+            .saturating_add(order.remaining_quantity.saturating_mul(order.limit_price));
         self.order_count += 1;
+        self.queue.push_back(order.exchange_id);
         self.orders.insert(order.exchange_id, order);
     }
 
     pub fn remove_order(&mut self, order: Order) {
         if let Some(order) = self.orders.remove(&order.exchange_id) {
-            self.size -= order.shares;
-            self.total_volume -= order.shares * order.limit_price;
+            self.queue
+                .retain(|exchange_id| *exchange_id != order.exchange_id);
+            self.size -= order.remaining_quantity;
+            self.total_volume = self
+                .total_volume
+                .saturating_sub(order.remaining_quantity.saturating_mul(order.limit_price));
             self.order_count -= 1;
         }
 
@@ -98,6 +341,98 @@ impl Limit {
     pub fn is_empty(&self) -> bool {
         self.size == Decimal::new(0, 0)
     }
+
+    /// Fills `quantity` of a resting order, keeping the level aggregates in
+    /// step. The order keeps its queue position until it is completely
+    /// filled, at which point it is removed. Returns the updated order.
+    pub fn reduce_order(
+        &mut self,
+        exchange_id: u64,
+        quantity: Decimal,
+    ) -> Result<Order, OrderError> {
+        let order = self
+            .orders
+            .get_mut(&exchange_id)
+            .ok_or(OrderError::UnknownOrder(exchange_id))?;
+        order.fill(quantity)?;
+        let order = order.clone();
+
+        self.size -= quantity;
+        self.total_volume = self
+            .total_volume
+            .saturating_sub(quantity.saturating_mul(self.limit_price));
+        if order.is_filled() {
+            self.orders.remove(&exchange_id);
+            self.queue.retain(|id| *id != exchange_id);
+            self.order_count -= 1;
+        }
+        Ok(order)
+    }
+
+    /// Matches `taker` against the resting orders of this level until either
+    /// side is exhausted, sharing it out as `algorithm` decides and pricing
+    /// each match as `hooks` do. The flag is set if a hook vetoed a match,
+    /// which ends the matching there.
+    pub fn match_order(
+        &mut self,
+        taker: &mut Order,
+        algorithm: &dyn MatchAlgorithm,
+        hooks: &[Rc<dyn MatchHook>],
+    ) -> (Vec<Fill>, bool) {
+        let allocations = {
+            let mut resting = self
+                .queue
+                .iter()
+                .map(|maker_id| (*maker_id, self.orders[maker_id].remaining_quantity));
+            algorithm.allocate(&mut resting, taker.remaining_quantity)
+        };
+
+        let mut fills = Vec::with_capacity(allocations.len());
+        for (maker_id, quantity) in allocations {
+            let maker = &self.orders[&maker_id];
+            let Some(price) = hooks::decide(hooks, maker, taker, quantity) else {
+                return (fills, true);
+            };
+            let (maker_client, maker_strategy) = (maker.client.clone(), maker.strategy.clone());
+
+            self.reduce_order(maker_id, quantity)
+                .expect("maker fill within remaining quantity");
+            taker
+                .fill(quantity)
+                .expect("taker fill within remaining quantity");
+
+            fills.push(Fill {
+                maker_id,
+                taker_id: taker.exchange_id,
+                price,
+                quantity,
+                maker_client,
+                maker_strategy,
+                ..Default::default()
+            });
+        }
+
+        (fills, false)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fill {
+    pub maker_id: u64,
+    pub taker_id: u64,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    #[serde(default)]
+    pub maker_client: String,
+    /// The maker order's strategy; the taker's is on the taker order.
+    #[serde(default)]
+    pub maker_strategy: Option<String>,
+    /// Filled in by the engine from the market's fee schedule; the book
+    /// itself leaves them at zero.
+    #[serde(default)]
+    pub maker_fee: Decimal,
+    #[serde(default)]
+    pub taker_fee: Decimal,
 }
 
 #[derive(Debug)]
@@ -105,8 +440,50 @@ pub struct LimitOrderBook {
     pub bids: BTreeMap<Decimal, Rc<RefCell<Limit>>>,
     pub asks: BTreeMap<Decimal, Rc<RefCell<Limit>>>,
     pub orders: HashMap<u64, Order>,
+    pub client_orders: HashMap<String, HashSet<u64>>,
     pub lowest_ask: Option<Decimal>,
     pub highest_bid: Option<Decimal>,
+    pub events: Vec<BookEvent>,
+    /// Shares incoming orders among each level's resting orders.
+    pub algorithm: Rc<dyn MatchAlgorithm>,
+    /// Consulted for every match, in order; see `MatchHook`.
+    pub hooks: Vec<Rc<dyn MatchHook>>,
+    /// Counts the changes made through the book's methods; see `version`.
+    version: u64,
+    /// The last view handed out by `read_view`.
+    pub(super) view: RefCell<Option<BookView>>,
+}
+
+impl Default for LimitOrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Levels are shared through `Rc`, so a derived clone would leave both books
+// mutating the same levels. Copy them instead.
+impl Clone for LimitOrderBook {
+    fn clone(&self) -> Self {
+        let copy_levels = |levels: &BTreeMap<Decimal, Rc<RefCell<Limit>>>| {
+            levels
+                .iter()
+                .map(|(price, limit)| (*price, Rc::new(RefCell::new(limit.borrow().clone()))))
+                .collect()
+        };
+        Self {
+            bids: copy_levels(&self.bids),
+            asks: copy_levels(&self.asks),
+            orders: self.orders.clone(),
+            client_orders: self.client_orders.clone(),
+            lowest_ask: self.lowest_ask,
+            highest_bid: self.highest_bid,
+            events: self.events.clone(),
+            algorithm: Rc::clone(&self.algorithm),
+            hooks: self.hooks.clone(),
+            version: self.version,
+            view: self.view.clone(),
+        }
+    }
 }
 
 impl LimitOrderBook {
@@ -115,13 +492,73 @@ impl LimitOrderBook {
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
             orders: HashMap::new(),
+            client_orders: HashMap::new(),
             lowest_ask: None,
             highest_bid: None,
+            events: Vec::new(),
+            algorithm: Rc::new(Fifo),
+            hooks: Vec::new(),
+            version: 0,
+            view: RefCell::new(None),
+        }
+    }
+
+    pub fn with_algorithm(mut self, algorithm: Rc<dyn MatchAlgorithm>) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Adds `hook` after the ones already registered.
+    pub fn with_hook(mut self, hook: Rc<dyn MatchHook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Goes up with every order added, removed, executed or charged, so two reads
+    /// with the same version saw the same book.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Takes the events buffered since the last call.
+    pub fn drain_events(&mut self) -> Vec<BookEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Releases capacity left behind by orders and events that are gone,
+    /// e.g. after a long replay.
+    pub fn shrink_to_fit(&mut self) {
+        for limit in self.bids.values().chain(self.asks.values()) {
+            let mut limit = limit.borrow_mut();
+            limit.orders.shrink_to_fit();
+            limit.queue.shrink_to_fit();
         }
+        self.orders.shrink_to_fit();
+        self.client_orders.shrink_to_fit();
+        self.events.shrink_to_fit();
     }
 
     pub fn add_order(&mut self, order: Order) {
         self.orders.insert(order.exchange_id, order.clone());
+        self.client_orders
+            .entry(order.client.clone())
+            .or_default()
+            .insert(order.exchange_id);
+        let levels = match order.order_type {
+            OrderType::Bid => &self.bids,
+            OrderType::Ask => &self.asks,
+        };
+        let queue_position = levels
+            .get(&order.limit_price)
+            .map_or(0, |limit| limit.borrow().queue.len());
+        self.version += 1;
+        self.events.push(BookEvent::OrderAdded {
+            exchange_id: order.exchange_id,
+            order_type: order.order_type,
+            price: order.limit_price,
+            quantity: order.remaining_quantity,
+            queue_position,
+        });
 
         match order.order_type {
             OrderType::Bid => {
@@ -146,6 +583,7 @@ impl LimitOrderBook {
 
         self.lowest_ask = self.asks.keys().next().cloned();
         self.highest_bid = self.bids.keys().next_back().cloned();
+        self.check_invariants();
     }
 
     pub fn remove_order(&mut self, order: Order) {
@@ -172,81 +610,314 @@ impl LimitOrderBook {
             }
         }
 
-        self.orders.remove(&order.exchange_id);
+        if let Some(order) = self.orders.remove(&order.exchange_id) {
+            self.unindex_client_order(&order.client, order.exchange_id);
+            self.version += 1;
+            self.events.push(BookEvent::OrderRemoved {
+                exchange_id: order.exchange_id,
+                order_type: order.order_type,
+                price: order.limit_price,
+                quantity: order.remaining_quantity,
+                reason: RemovalReason::Cancelled,
+            });
+        }
 
         self.lowest_ask = self.asks.keys().next().cloned();
         self.highest_bid = self.bids.keys().next_back().cloned();
+        self.check_invariants();
     }
 
-    pub fn execute_order(&mut self, order: Order) {
-        let mut order = order;
-        let mut limit_price = order.limit_price;
-
-        let mut removed_limit = None;
+    /// Cancels every resting order on both sides of the book and returns the
+    /// cancelled order IDs.
+    pub fn cancel_all(&mut self) -> Vec<u64> {
+        let mut cancelled = self.cancel_side(OrderType::Bid);
+        cancelled.extend(self.cancel_side(OrderType::Ask));
+        cancelled
+    }
 
-        match order.order_type {
-            OrderType::Bid => {
-                while let Some(limit) = self.asks.get_mut(&limit_price) {
-                    let mut limit = limit.borrow_mut();
-                    if limit.size >= order.shares {
-                        limit.remove_order(order.clone());
-                        if limit.is_empty() {
-                            removed_limit = Some(limit_price);
-                        }
-                        break;
-                    } else {
-                        order.shares -= limit.size;
-                        limit.remove_order(order.clone());
-                        if limit.is_empty() {
-                            removed_limit = Some(limit_price);
-                        }
-                        limit_price = limit.limit_price;
-                    }
+    /// Cancels every resting order on one side of the book. Levels are dropped
+    /// wholesale, so only the cancelled orders themselves are visited.
+    pub fn cancel_side(&mut self, side: OrderType) -> Vec<u64> {
+        let limits = match side {
+            OrderType::Bid => std::mem::take(&mut self.bids),
+            OrderType::Ask => std::mem::take(&mut self.asks),
+        };
+
+        let mut cancelled = Vec::new();
+        for limit in limits.values() {
+            for exchange_id in limit.borrow().queue.iter() {
+                if let Some(order) = self.orders.remove(exchange_id) {
+                    self.unindex_client_order(&order.client, order.exchange_id);
+                    self.version += 1;
+                    self.events.push(BookEvent::OrderRemoved {
+                        exchange_id: order.exchange_id,
+                        order_type: order.order_type,
+                        price: order.limit_price,
+                        quantity: order.remaining_quantity,
+                        reason: RemovalReason::Cancelled,
+                    });
                 }
+                cancelled.push(*exchange_id);
             }
-            OrderType::Ask => {
-                while let Some(limit) = self.bids.get_mut(&limit_price) {
-                    let mut limit = limit.borrow_mut();
-                    if limit.size >= order.shares {
-                        limit.remove_order(order.clone());
-                        if limit.is_empty() {
-                            removed_limit = Some(limit_price);
-                        }
-                        break;
-                    } else {
-                        order.shares -= limit.size;
-                        limit.remove_order(order.clone());
-                        if limit.is_empty() {
-                            removed_limit = Some(limit_price);
-                        }
-                        limit_price = limit.limit_price;
-                    }
-                }
+        }
+
+        self.lowest_ask = self.asks.keys().next().cloned();
+        self.highest_bid = self.bids.keys().next_back().cloned();
+        self.check_invariants();
+        cancelled
+    }
+
+    /// Cancels every resting order owned by `client` using the per-client
+    /// index, without scanning other clients' orders. Orders are cancelled,
+    /// and returned, oldest first.
+    pub fn cancel_all_for_client(&mut self, client: &str) -> Vec<u64> {
+        let mut exchange_ids: Vec<u64> = match self.client_orders.get(client) {
+            Some(exchange_ids) => exchange_ids.iter().copied().collect(),
+            None => return Vec::new(),
+        };
+        // The index is unordered; cancelling oldest first keeps the book's
+        // events the same from run to run.
+        exchange_ids.sort_unstable();
+
+        let mut cancelled = Vec::with_capacity(exchange_ids.len());
+        for exchange_id in exchange_ids {
+            if let Some(order) = self.orders.get(&exchange_id).cloned() {
+                self.remove_order(order);
+                cancelled.push(exchange_id);
+            }
+        }
+        cancelled
+    }
+
+    fn unindex_client_order(&mut self, client: &str, exchange_id: u64) {
+        if let Some(exchange_ids) = self.client_orders.get_mut(client) {
+            exchange_ids.remove(&exchange_id);
+            if exchange_ids.is_empty() {
+                self.client_orders.remove(client);
             }
         }
+    }
 
-        if let Some(limit_price) = removed_limit {
-            match order.order_type {
-                OrderType::Bid => {
-                    self.asks.remove(&limit_price);
-                }
-                OrderType::Ask => {
-                    self.bids.remove(&limit_price);
-                }
+    /// Sweeps the opposite side from the best price outwards, matching every
+    /// level priced at or better than the order's limit price in FIFO order.
+    /// Any unfilled remainder is not rested on the book.
+    pub fn execute_order(&mut self, order: Order) -> Vec<Fill> {
+        let mut order = order;
+        self.sweep(&mut order)
+    }
+
+    /// Executes `order` at whatever prices are available, ignoring its limit
+    /// price. Any unfilled remainder is not rested on the book.
+    pub fn execute_market_order(&mut self, order: Order) -> Vec<Fill> {
+        let mut order = order;
+        order.limit_price = match order.order_type {
+            OrderType::Bid => Decimal::MAX,
+            OrderType::Ask => Decimal::ZERO,
+        };
+        self.sweep(&mut order)
+    }
+
+    /// Matches `order` like `execute_order` and rests any unfilled remainder
+    /// on the book.
+    pub fn place_order(&mut self, order: Order) -> (Order, Vec<Fill>) {
+        let mut order = order;
+        let fills = self.sweep(&mut order);
+        if order.is_active() {
+            self.add_order(order.clone());
+        }
+        (order, fills)
+    }
+
+    /// Cancels a resting order by ID and returns it in its cancelled state.
+    pub fn cancel_order(&mut self, exchange_id: u64) -> Option<Order> {
+        let mut order = self.orders.get(&exchange_id).cloned()?;
+        self.remove_order(order.clone());
+        order
+            .cancel()
+            .expect("resting orders are always cancellable");
+        Some(order)
+    }
+
+    fn sweep(&mut self, order: &mut Order) -> Vec<Fill> {
+        let mut fills = Vec::new();
+
+        while order.remaining_quantity > Decimal::zero() {
+            let (levels, best_price) = match order.order_type {
+                OrderType::Bid => (&mut self.asks, self.lowest_ask),
+                OrderType::Ask => (&mut self.bids, self.highest_bid),
+            };
+            let limit_price = match best_price {
+                Some(price) if Self::crosses(order, price) => price,
+                _ => break,
+            };
+
+            let limit = Rc::clone(&levels[&limit_price]);
+            let (level_fills, vetoed) =
+                limit
+                    .borrow_mut()
+                    .match_order(order, self.algorithm.as_ref(), &self.hooks);
+            if level_fills.is_empty() && !vetoed {
+                break;
             }
+            if limit.borrow().is_empty() {
+                levels.remove(&limit_price);
+            }
+
+            // Book events place executions at the maker's level, whatever
+            // price the hooks settled on.
+            for fill in level_fills.iter() {
+                self.record_maker_fill(fill.maker_id, limit_price, fill.quantity);
+            }
+            fills.extend(level_fills);
+
+            self.lowest_ask = self.asks.keys().next().cloned();
+            self.highest_bid = self.bids.keys().next_back().cloned();
+            if vetoed {
+                // Resting the remainder would leave the book crossed.
+                order
+                    .cancel()
+                    .expect("a matching order is always cancellable");
+                break;
+            }
+        }
+
+        self.check_invariants();
+        fills
+    }
+
+    /// Fills `quantity` of the resting order `exchange_id` without an
+    /// incoming order, e.g. when reconciling executions reported elsewhere.
+    /// The order is only removed from the book once fully filled.
+    pub fn reduce_order(
+        &mut self,
+        exchange_id: u64,
+        quantity: Decimal,
+    ) -> Result<Order, OrderError> {
+        let (order_type, limit_price) = match self.orders.get(&exchange_id) {
+            Some(order) => (order.order_type, order.limit_price),
+            None => return Err(OrderError::UnknownOrder(exchange_id)),
+        };
+        let levels = match order_type {
+            OrderType::Bid => &mut self.bids,
+            OrderType::Ask => &mut self.asks,
+        };
+
+        let limit = Rc::clone(&levels[&limit_price]);
+        let order = limit.borrow_mut().reduce_order(exchange_id, quantity)?;
+        if limit.borrow().is_empty() {
+            levels.remove(&limit_price);
         }
+        self.record_maker_fill(exchange_id, limit_price, quantity);
 
         self.lowest_ask = self.asks.keys().next().cloned();
         self.highest_bid = self.bids.keys().next_back().cloned();
+        self.check_invariants();
+        Ok(order)
+    }
+
+    // Mirrors a fill on the indexed copy of the maker so `get_order` reflects
+    // the partial fill, and drops the maker from the indexes once it is done.
+    fn record_maker_fill(&mut self, maker_id: u64, price: Decimal, quantity: Decimal) {
+        let maker = match self.orders.get_mut(&maker_id) {
+            Some(maker) => maker,
+            None => return,
+        };
+        maker
+            .fill(quantity)
+            .expect("indexed maker out of sync with its level");
+        self.version += 1;
+        self.events.push(BookEvent::OrderExecuted {
+            exchange_id: maker.exchange_id,
+            order_type: maker.order_type,
+            price,
+            quantity,
+            remaining_quantity: maker.remaining_quantity,
+        });
+
+        if maker.is_filled() {
+            let maker = self.orders.remove(&maker_id).unwrap();
+            self.unindex_client_order(&maker.client, maker.exchange_id);
+            self.version += 1;
+            self.events.push(BookEvent::OrderRemoved {
+                exchange_id: maker.exchange_id,
+                order_type: maker.order_type,
+                price: maker.limit_price,
+                quantity: maker.remaining_quantity,
+                reason: RemovalReason::Filled,
+            });
+        }
+    }
+
+    /// Adds `fee` to a resting order's running fee total. Returns false if
+    /// the order is not on the book.
+    pub fn charge_fee(&mut self, exchange_id: u64, fee: Decimal) -> bool {
+        let order = match self.orders.get_mut(&exchange_id) {
+            Some(order) => order,
+            None => return false,
+        };
+        order.fees += fee;
+        self.version += 1;
+        let levels = match order.order_type {
+            OrderType::Bid => &self.bids,
+            OrderType::Ask => &self.asks,
+        };
+        if let Some(limit) = levels.get(&order.limit_price) {
+            if let Some(resting) = limit.borrow_mut().orders.get_mut(&exchange_id) {
+                resting.fees += fee;
+            }
+        }
+        true
+    }
+
+    fn crosses(order: &Order, price: Decimal) -> bool {
+        match order.order_type {
+            OrderType::Bid => price <= order.limit_price,
+            OrderType::Ask => price >= order.limit_price,
+        }
     }
 
     pub fn get_order(&self, exchange_id: u64) -> Option<&Order> {
         self.orders.get(&exchange_id)
     }
 
+    /// Every resting order, bids then asks, lowest price first and in queue
+    /// order within a level. Adding them to an empty book in this order
+    /// rebuilds the same priorities.
+    pub fn resting_orders(&self) -> Vec<Order> {
+        let mut orders = Vec::with_capacity(self.orders.len());
+        for limit in self.bids.values().chain(self.asks.values()) {
+            let limit = limit.borrow();
+            orders.extend(limit.queue.iter().map(|id| limit.orders[id].clone()));
+        }
+        orders
+    }
+
+    /// Remaining quantity queued ahead of a resting order at its price
+    /// level, i.e. how much must trade before it starts filling.
+    pub fn queue_position(&self, exchange_id: u64) -> Option<Decimal> {
+        let order = self.orders.get(&exchange_id)?;
+        let levels = match order.order_type {
+            OrderType::Bid => &self.bids,
+            OrderType::Ask => &self.asks,
+        };
+        let limit = levels.get(&order.limit_price)?.borrow();
+        let ahead = limit
+            .queue
+            .iter()
+            .take_while(|queued| **queued != exchange_id)
+            .map(|queued| limit.orders[queued].remaining_quantity)
+            .sum();
+        Some(ahead)
+    }
+
+    /// Number of price levels on the bid and ask sides.
+    pub fn level_count(&self) -> (usize, usize) {
+        (self.bids.len(), self.asks.len())
+    }
+
     pub fn get_bid_depth(&self, limit_price: Decimal) -> Decimal {
         let mut depth = Decimal::new(0, 0);
-        for (price, limit) in self.bids.range(limit_price..=limit_price) {
+        for (_, limit) in self.bids.range(limit_price..=limit_price) {
             depth += limit.borrow().size;
         }
         depth
@@ -254,7 +925,7 @@ impl LimitOrderBook {
 
     pub fn get_ask_depth(&self, limit_price: Decimal) -> Decimal {
         let mut depth = Decimal::new(0, 0);
-        for (price, limit) in self.asks.range(limit_price..=limit_price) {
+        for (_, limit) in self.asks.range(limit_price..=limit_price) {
             depth += limit.borrow().size;
         }
         depth
@@ -262,23 +933,23 @@ impl LimitOrderBook {
 
     pub fn get_bid_volume(&self, limit_price: Decimal) -> Decimal {
         let mut volume = Decimal::new(0, 0);
-        for (price, limit) in self.bids.range(limit_price..=limit_price) {
-            volume += limit.borrow().total_volume;
+        for (_, limit) in self.bids.range(limit_price..=limit_price) {
+            volume = volume.saturating_add(limit.borrow().total_volume);
         }
         volume
     }
 
     pub fn get_ask_volume(&self, limit_price: Decimal) -> Decimal {
         let mut volume = Decimal::new(0, 0);
-        for (price, limit) in self.asks.range(limit_price..=limit_price) {
-            volume += limit.borrow().total_volume;
+        for (_, limit) in self.asks.range(limit_price..=limit_price) {
+            volume = volume.saturating_add(limit.borrow().total_volume);
         }
         volume
     }
 
     pub fn get_bid_count(&self, limit_price: Decimal) -> usize {
         let mut count = 0;
-        for (price, limit) in self.bids.range(limit_price..=limit_price) {
+        for (_, limit) in self.bids.range(limit_price..=limit_price) {
             count += limit.borrow().order_count;
         }
         count.try_into().unwrap()
@@ -286,7 +957,7 @@ impl LimitOrderBook {
 
     pub fn get_ask_count(&self, limit_price: Decimal) -> usize {
         let mut count = 0;
-        for (price, limit) in self.asks.range(limit_price..=limit_price) {
+        for (_, limit) in self.asks.range(limit_price..=limit_price) {
             count += limit.borrow().order_count;
         }
         count.try_into().unwrap()
@@ -340,7 +1011,11 @@ impl LimitOrderBook {
 
     pub fn get_volume_at_price(&self, limit_price: Decimal) -> Option<Decimal> {
         match (self.bids.get(&limit_price), self.asks.get(&limit_price)) {
-            (Some(bid), Some(ask)) => Some(bid.borrow().total_volume + ask.borrow().total_volume),
+            (Some(bid), Some(ask)) => Some(
+                bid.borrow()
+                    .total_volume
+                    .saturating_add(ask.borrow().total_volume),
+            ),
             (Some(bid), None) => Some(bid.borrow().total_volume),
             (None, Some(ask)) => Some(ask.borrow().total_volume),
             _ => None,
@@ -352,6 +1027,77 @@ impl LimitOrderBook {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_order_partial_fill_tracking() {
+        let mut order = Order::new(
+            "tick1".into(),
+            1,
+            OrderType::Bid,
+            dec!(10),
+            dec!(100),
+            Utc::now(),
+            Utc::now(),
+        );
+        assert_eq!(order.status, OrderStatus::New);
+        assert_eq!(order.remaining_quantity, dec!(10));
+
+        order.fill(dec!(4)).unwrap();
+        assert_eq!(order.status, OrderStatus::PartiallyFilled);
+        assert_eq!(order.filled_quantity, dec!(4));
+        assert_eq!(order.remaining_quantity, dec!(6));
+        assert_eq!(order.shares, dec!(10));
+
+        let report = order.execution_report(dec!(4), dec!(100));
+        assert_eq!(report.cum_quantity, dec!(4));
+        assert_eq!(report.leaves_quantity, dec!(6));
+
+        order.fill(dec!(6)).unwrap();
+        assert!(order.is_filled());
+        assert!(!order.is_active());
+        assert_eq!(order.filled_quantity, dec!(10));
+        assert_eq!(order.remaining_quantity, dec!(0));
+    }
+
+    #[test]
+    fn test_order_invalid_transitions() {
+        let mut order = Order::new(
+            "tick1".into(),
+            1,
+            OrderType::Ask,
+            dec!(10),
+            dec!(100),
+            Utc::now(),
+            Utc::now(),
+        );
+
+        assert_eq!(
+            order.fill(dec!(11)),
+            Err(OrderError::Overfill {
+                requested: dec!(11),
+                remaining: dec!(10),
+            })
+        );
+        assert_eq!(
+            order.fill(dec!(0)),
+            Err(OrderError::InvalidQuantity(dec!(0)))
+        );
+        assert_eq!(order.status, OrderStatus::New);
+
+        order.fill(dec!(3)).unwrap();
+        order.cancel().unwrap();
+        assert_eq!(order.status, OrderStatus::Cancelled);
+        assert_eq!(order.filled_quantity, dec!(3));
+        assert_eq!(order.remaining_quantity, dec!(0));
+
+        assert_eq!(
+            order.cancel(),
+            Err(OrderError::InvalidTransition {
+                from: OrderStatus::Cancelled,
+                to: OrderStatus::Cancelled,
+            })
+        );
+    }
+
     #[test]
     fn test_limit_new() {
         let limit = Limit::new(dec!(100));
@@ -423,6 +1169,76 @@ mod tests {
         assert_eq!(limit.order_count, 0);
     }
 
+    #[test]
+    fn test_limit_reduce_order_keeps_queue_position() {
+        let mut limit = Limit::new(dec!(100));
+        for exchange_id in 1..=2 {
+            limit.add_order(Order::new(
+                format!("tick{}", exchange_id),
+                exchange_id,
+                OrderType::Bid,
+                dec!(10),
+                dec!(100),
+                Utc::now(),
+                Utc::now(),
+            ));
+        }
+
+        let order = limit.reduce_order(1, dec!(4)).unwrap();
+        assert_eq!(order.remaining_quantity, dec!(6));
+        assert_eq!(order.status, OrderStatus::PartiallyFilled);
+        assert_eq!(limit.size, dec!(16));
+        assert_eq!(limit.total_volume, dec!(1600));
+        assert_eq!(limit.order_count, 2);
+        assert_eq!(limit.queue, VecDeque::from(vec![1, 2]));
+
+        assert_eq!(
+            limit.reduce_order(1, dec!(7)),
+            Err(OrderError::Overfill {
+                requested: dec!(7),
+                remaining: dec!(6),
+            })
+        );
+        assert_eq!(
+            limit.reduce_order(3, dec!(1)),
+            Err(OrderError::UnknownOrder(3))
+        );
+
+        let order = limit.reduce_order(1, dec!(6)).unwrap();
+        assert!(order.is_filled());
+        assert!(!limit.orders.contains_key(&1));
+        assert_eq!(limit.queue, VecDeque::from(vec![2]));
+        assert_eq!(limit.size, dec!(10));
+        assert_eq!(limit.order_count, 1);
+    }
+
+    #[test]
+    fn test_book_reduce_order() {
+        let mut book = LimitOrderBook::new();
+        book.add_order(Order::new(
+            "tick1".to_string(),
+            1,
+            OrderType::Ask,
+            dec!(10),
+            dec!(101),
+            Utc::now(),
+            Utc::now(),
+        ));
+
+        book.reduce_order(1, dec!(3)).unwrap();
+        assert_eq!(book.get_ask_depth(dec!(101)), dec!(7));
+        assert_eq!(book.get_order(1).unwrap().filled_quantity, dec!(3));
+
+        book.reduce_order(1, dec!(7)).unwrap();
+        assert!(book.get_order(1).is_none());
+        assert!(book.asks.is_empty());
+        assert_eq!(book.lowest_ask, None);
+        assert_eq!(
+            book.reduce_order(1, dec!(1)),
+            Err(OrderError::UnknownOrder(1))
+        );
+    }
+
     #[test]
     fn test_limit_orderbook_new() {
         let book = LimitOrderBook::new();
@@ -617,7 +1433,102 @@ mod tests {
         assert_eq!(lob.highest_bid, Some(dec!(100)));
     }
 
+    fn client_order(
+        exchange_id: u64,
+        client: &str,
+        order_type: OrderType,
+        price: Decimal,
+    ) -> Order {
+        Order::new(
+            format!("tick{}", exchange_id),
+            exchange_id,
+            order_type,
+            dec!(10),
+            price,
+            Utc::now(),
+            Utc::now(),
+        )
+        .with_client(client)
+    }
+
     #[test]
+    fn test_cancel_side_and_all() {
+        let mut book = LimitOrderBook::new();
+        book.add_order(client_order(1, "alice", OrderType::Bid, dec!(99)));
+        book.add_order(client_order(2, "bob", OrderType::Bid, dec!(98)));
+        book.add_order(client_order(3, "alice", OrderType::Ask, dec!(101)));
+        book.add_order(client_order(4, "bob", OrderType::Ask, dec!(101)));
+
+        let mut cancelled = book.cancel_side(OrderType::Bid);
+        cancelled.sort();
+        assert_eq!(cancelled, vec![1, 2]);
+        assert!(book.bids.is_empty());
+        assert_eq!(book.highest_bid, None);
+        assert_eq!(book.lowest_ask, Some(dec!(101)));
+        assert_eq!(book.orders.len(), 2);
+
+        let mut cancelled = book.cancel_all();
+        cancelled.sort();
+        assert_eq!(cancelled, vec![3, 4]);
+        assert!(book.asks.is_empty());
+        assert!(book.orders.is_empty());
+        assert!(book.client_orders.is_empty());
+        assert_eq!(book.lowest_ask, None);
+    }
+
+    #[test]
+    fn test_cancel_all_for_client() {
+        let mut book = LimitOrderBook::new();
+        book.add_order(client_order(1, "alice", OrderType::Bid, dec!(99)));
+        book.add_order(client_order(2, "bob", OrderType::Bid, dec!(99)));
+        book.add_order(client_order(3, "alice", OrderType::Ask, dec!(101)));
+
+        assert_eq!(book.cancel_all_for_client("alice"), vec![1, 3]);
+        assert_eq!(book.orders.len(), 1);
+        assert!(book.get_order(2).is_some());
+        assert_eq!(book.get_bid_depth(dec!(99)), dec!(10));
+        assert!(book.asks.is_empty());
+        assert!(!book.client_orders.contains_key("alice"));
+
+        assert!(book.cancel_all_for_client("alice").is_empty());
+    }
+
+    #[test]
+    fn test_cancel_all_for_client_is_ordered() {
+        let mut book = LimitOrderBook::new();
+        for exchange_id in (1..=50).rev() {
+            let side = if exchange_id % 2 == 0 {
+                OrderType::Bid
+            } else {
+                OrderType::Ask
+            };
+            let price = if side == OrderType::Bid {
+                dec!(99)
+            } else {
+                dec!(101)
+            };
+            book.add_order(client_order(exchange_id, "alice", side, price));
+        }
+        book.drain_events();
+
+        let cancelled = book.cancel_all_for_client("alice");
+        assert_eq!(cancelled, (1..=50).collect::<Vec<_>>());
+        let removed: Vec<u64> = book
+            .drain_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                BookEvent::OrderRemoved { exchange_id, .. } => Some(exchange_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(removed, cancelled);
+    }
+
+    #[test]
+    #[cfg_attr(
+        feature = "validate-invariants",
+        ignore = "rests crossing orders with add_order"
+    )]
     fn test_execute_order() {
         let mut book = LimitOrderBook::new();
 
@@ -679,12 +1590,156 @@ mod tests {
             Utc::now(),
         );
 
-        book.execute_order(order5.clone());
-
+        let fills = book.execute_order(order5.clone());
+
+        assert_eq!(
+            fills,
+            vec![
+                Fill {
+                    maker_id: 4,
+                    taker_id: 5,
+                    price: dec!(8),
+                    quantity: dec!(100),
+                    ..Default::default()
+                },
+                Fill {
+                    maker_id: 3,
+                    taker_id: 5,
+                    price: dec!(9),
+                    quantity: dec!(75),
+                    ..Default::default()
+                },
+            ]
+        );
         assert_eq!(book.bids.len(), 1);
-        assert_eq!(book.asks.len(), 2);
-        assert_eq!(book.orders.len(), 4);
-        assert_eq!(book.lowest_ask, Some(dec!(8)));
+        assert_eq!(book.asks.len(), 0);
+        assert_eq!(book.orders.len(), 2);
+        assert!(book.get_order(3).is_none());
+        assert!(book.get_order(4).is_none());
+        assert_eq!(book.lowest_ask, None);
         assert_eq!(book.highest_bid, Some(dec!(10)));
     }
+
+    #[test]
+    fn test_execute_order_fifo_within_level_and_limit_price() {
+        let mut book = LimitOrderBook::new();
+        for (exchange_id, price) in [
+            (1, dec!(101)),
+            (2, dec!(101)),
+            (3, dec!(102)),
+            (4, dec!(103)),
+        ] {
+            book.add_order(Order::new(
+                format!("tick{}", exchange_id),
+                exchange_id,
+                OrderType::Ask,
+                dec!(10),
+                price,
+                Utc::now(),
+                Utc::now(),
+            ));
+        }
+
+        let taker = Order::new(
+            "tick5".to_string(),
+            5,
+            OrderType::Bid,
+            dec!(25),
+            dec!(102),
+            Utc::now(),
+            Utc::now(),
+        );
+        let fills = book.execute_order(taker);
+
+        let matched: Vec<(u64, Decimal, Decimal)> = fills
+            .iter()
+            .map(|fill| (fill.maker_id, fill.price, fill.quantity))
+            .collect();
+        assert_eq!(
+            matched,
+            vec![
+                (1, dec!(101), dec!(10)),
+                (2, dec!(101), dec!(10)),
+                (3, dec!(102), dec!(5)),
+            ]
+        );
+        assert_eq!(book.lowest_ask, Some(dec!(102)));
+        assert_eq!(book.get_ask_depth(dec!(102)), dec!(5));
+        assert_eq!(book.get_ask_depth(dec!(103)), dec!(10));
+        assert!(book.get_order(1).is_none());
+        assert!(book.get_order(2).is_none());
+        let partially_filled = book.get_order(3).unwrap();
+        assert_eq!(partially_filled.status, OrderStatus::PartiallyFilled);
+        assert_eq!(partially_filled.remaining_quantity, dec!(5));
+
+        let events = book.drain_events();
+        let removed: Vec<u64> = events
+            .iter()
+            .filter(|event| {
+                matches!(
+                    event,
+                    BookEvent::OrderRemoved {
+                        reason: RemovalReason::Filled,
+                        ..
+                    }
+                )
+            })
+            .map(BookEvent::exchange_id)
+            .collect();
+        assert_eq!(removed, vec![1, 2]);
+        assert!(events.contains(&BookEvent::OrderExecuted {
+            exchange_id: 3,
+            order_type: OrderType::Ask,
+            price: dec!(102),
+            quantity: dec!(5),
+            remaining_quantity: dec!(5),
+        }));
+        assert!(book.drain_events().is_empty());
+
+        // Nothing is priced at or below 100, so the order does not trade.
+        let taker = Order::new(
+            "tick6".to_string(),
+            6,
+            OrderType::Bid,
+            dec!(5),
+            dec!(100),
+            Utc::now(),
+            Utc::now(),
+        );
+        assert!(book.execute_order(taker).is_empty());
+        assert_eq!(book.get_ask_depth(dec!(102)), dec!(5));
+    }
+
+    #[test]
+    fn test_queue_position() {
+        let mut book = LimitOrderBook::new();
+        for (exchange_id, shares) in [(1, dec!(5)), (2, dec!(3)), (3, dec!(4))] {
+            book.add_order(Order::new(
+                "tick".to_string(),
+                exchange_id,
+                OrderType::Bid,
+                shares,
+                dec!(100),
+                Utc::now(),
+                Utc::now(),
+            ));
+        }
+        assert_eq!(book.queue_position(1), Some(dec!(0)));
+        assert_eq!(book.queue_position(3), Some(dec!(8)));
+
+        // Fills and cancels ahead move the order up the queue.
+        book.execute_order(Order::new(
+            "tick".to_string(),
+            4,
+            OrderType::Ask,
+            dec!(2),
+            dec!(100),
+            Utc::now(),
+            Utc::now(),
+        ));
+        assert_eq!(book.queue_position(3), Some(dec!(6)));
+        book.cancel_order(2);
+        assert_eq!(book.queue_position(3), Some(dec!(3)));
+        assert_eq!(book.queue_position(2), None);
+    }
 }
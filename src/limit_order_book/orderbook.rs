@@ -1,8 +1,6 @@
 #![allow(dead_code)]
 use chrono::{DateTime, Utc};
 use rust_decimal::prelude::*;
-use rust_decimal_macros::dec;
-use std::{cell::RefCell, collections::BinaryHeap, rc::Rc};
 
 pub enum OrderSide {
     Buy,
@@ -0,0 +1,131 @@
+//! Bounding a book's size by dropping levels far from the touch.
+
+use crate::limit_order_book::order::{LimitOrderBook, OrderType};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+/// Which resting levels a book keeps. Orders on the other levels are
+/// cancelled:
+///
+/// ```toml
+/// prune = { max_levels = 500, max_distance = "0.1" }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrunePolicy {
+    /// Levels kept on each side, counting from the best.
+    pub max_levels: Option<usize>,
+    /// Furthest a level may be from the mid, as a fraction of it. Not
+    /// applied while either side of the book is empty.
+    pub max_distance: Option<Decimal>,
+}
+
+impl PrunePolicy {
+    /// Prices of the levels on `side` of `book` the policy drops, farthest
+    /// from the touch first.
+    pub fn far_levels(&self, book: &LimitOrderBook, side: OrderType) -> Vec<Decimal> {
+        let mid = book.get_mid_price();
+        let (levels, count) = match side {
+            OrderType::Bid => (book.bids.keys().collect::<Vec<_>>(), book.bids.len()),
+            OrderType::Ask => (book.asks.keys().rev().collect(), book.asks.len()),
+        };
+        let excess = self
+            .max_levels
+            .map_or(0, |max_levels| count.saturating_sub(max_levels));
+        let too_far = |price: Decimal| match (self.max_distance, mid) {
+            (Some(distance), Some(mid)) => (price - mid).abs() > mid * distance,
+            _ => false,
+        };
+        levels
+            .into_iter()
+            .enumerate()
+            .take_while(|(index, price)| *index < excess || too_far(**price))
+            .map(|(_, price)| *price)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::Order;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_far_levels() {
+        let mut book = LimitOrderBook::new();
+        let prices = [dec!(80), dec!(95), dec!(98), dec!(99), dec!(101), dec!(103)];
+        for (id, price) in prices.into_iter().enumerate() {
+            let side = if price < dec!(100) {
+                OrderType::Bid
+            } else {
+                OrderType::Ask
+            };
+            book.add_order(Order::new(
+                "BTC/USDT".to_string(),
+                id as u64,
+                side,
+                dec!(1),
+                price,
+                Utc::now(),
+                Utc::now(),
+            ));
+        }
+
+        let levels = PrunePolicy {
+            max_levels: Some(2),
+            max_distance: None,
+        };
+        assert_eq!(
+            levels.far_levels(&book, OrderType::Bid),
+            vec![dec!(80), dec!(95)]
+        );
+        assert!(levels.far_levels(&book, OrderType::Ask).is_empty());
+
+        let distance = PrunePolicy {
+            max_levels: None,
+            max_distance: Some(dec!(0.025)),
+        };
+        assert_eq!(
+            distance.far_levels(&book, OrderType::Bid),
+            vec![dec!(80), dec!(95)]
+        );
+        assert_eq!(distance.far_levels(&book, OrderType::Ask), vec![dec!(103)]);
+        assert!(PrunePolicy::default()
+            .far_levels(&book, OrderType::Bid)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_one_sided_book_and_config() {
+        let policy: PrunePolicy =
+            toml::from_str("max_levels = 1\nmax_distance = \"0.01\"").unwrap();
+        assert_eq!(policy.max_levels, Some(1));
+        assert!(toml::from_str::<PrunePolicy>("max_orders = 5").is_err());
+
+        let mut book = LimitOrderBook::new();
+        for (id, price) in [(1, dec!(50)), (2, dec!(90)), (3, dec!(99))] {
+            book.add_order(Order::new(
+                "BTC/USDT".to_string(),
+                id,
+                OrderType::Bid,
+                dec!(1),
+                price,
+                Utc::now(),
+                Utc::now(),
+            ));
+        }
+        // Without a mid only the level count applies.
+        assert_eq!(
+            policy.far_levels(&book, OrderType::Bid),
+            vec![dec!(50), dec!(90)]
+        );
+        let distance = PrunePolicy {
+            max_levels: None,
+            ..policy
+        };
+        assert!(distance.far_levels(&book, OrderType::Bid).is_empty());
+        assert!(policy.far_levels(&book, OrderType::Ask).is_empty());
+    }
+}
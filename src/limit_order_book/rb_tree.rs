@@ -1,3 +1,4 @@
+#![allow(dead_code)]
 use std::cmp::Ord;
 use std::cmp::Ordering;
 use std::fmt::Debug;
@@ -31,11 +32,11 @@ impl Color {
 
 impl<K, V> Debug for Node<K, V> where K : Debug, V : Debug {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let left =  if self.left.is_some() { format!("{}", self.left.unwrap()) }
+        let left =  if let Some(left) = self.left { format!("{}", left) }
                     else { "_".to_string() };
-        let right = if self.right.is_some() { format!("{}", self.right.unwrap()) }
+        let right = if let Some(right) = self.right { format!("{}", right) }
                     else { "_".to_string() };
-        let paren = if self.parent.is_some() { format!("{}", self.parent.unwrap()) }
+        let paren = if let Some(parent) = self.parent { format!("{}", parent) }
                     else { "_".to_string() };
         write!(f, "Node {:?} parent {} left: {} right: {} k: {:?} v: {:?}, s: {}", self.color, paren, left, right, self.key, self.value, self.size)
     }
@@ -246,7 +247,7 @@ impl<K, V> RBTree<K, V> where K : Ord + Debug, V : Debug {
         result.set_child(Self::balance(id, nodes))
     }
 
-    fn balance(mut id : usize, nodes : &mut Vec<Node<K, V>>) -> usize {
+    fn balance(mut id : usize, nodes : &mut [Node<K, V>]) -> usize {
         if Self::is_red(nodes[id].right, nodes) { id = Self::rotate_left(id, nodes); }
         let left = nodes[id].left;
         if Self::is_red(left, nodes) && Self::is_red(nodes[left.unwrap()].left, nodes) {
@@ -257,7 +258,7 @@ impl<K, V> RBTree<K, V> where K : Ord + Debug, V : Debug {
         id
     }
 
-    fn maybe_flip(id : usize, nodes : &mut Vec<Node<K, V>>) {
+    fn maybe_flip(id : usize, nodes : &mut [Node<K, V>]) {
         if let Some(left) = nodes[id].left {
             if let Some(right) = nodes[id].right {
                 if nodes[left].color.is_red() && nodes[right].color.is_red() {
@@ -270,7 +271,7 @@ impl<K, V> RBTree<K, V> where K : Ord + Debug, V : Debug {
     /// This only happens when node `id` has two consecutive black left children.
     /// Black color only happens on the left when the right is present.
     /// I don't quite understand why we can assume that node `id` is red.
-    fn move_red_left(mut id : usize, nodes : &mut Vec<Node<K, V>>) -> usize {
+    fn move_red_left(mut id : usize, nodes : &mut [Node<K, V>]) -> usize {
         Self::flip_colors(id, nodes[id].left.unwrap(), nodes[id].right.unwrap(), nodes);
         if Self::is_red(nodes[nodes[id].right.unwrap()].left, nodes) {
             nodes[id].right = Some(Self::rotate_right(nodes[id].right.unwrap(), nodes));
@@ -280,7 +281,7 @@ impl<K, V> RBTree<K, V> where K : Ord + Debug, V : Debug {
         id
     }
 
-    fn move_red_right(mut id : usize, nodes : &mut Vec<Node<K, V>>) -> usize {
+    fn move_red_right(mut id : usize, nodes : &mut [Node<K, V>]) -> usize {
         let left = nodes[id].left.unwrap();
         Self::flip_colors(id, left, nodes[id].right.unwrap(), nodes);
         if Self::is_red(nodes[left].left, nodes) {
@@ -337,13 +338,13 @@ impl<K, V> RBTree<K, V> where K : Ord + Debug, V : Debug {
         }
     }
 
-    fn flip_colors(base : usize, left : usize, right : usize, nodes : &mut Vec<Node<K, V>>) {
+    fn flip_colors(base : usize, left : usize, right : usize, nodes : &mut [Node<K, V>]) {
         nodes[base].color.flip();
         nodes[left].color.flip();
         nodes[right].color.flip();
     }
 
-    fn rotate_left(h : usize, nodes : &mut Vec<Node<K, V>>) -> usize {
+    fn rotate_left(h : usize, nodes : &mut [Node<K, V>]) -> usize {
         let x = nodes[h].right.unwrap();
 
         nodes[h].right = nodes[x].left;
@@ -367,7 +368,7 @@ impl<K, V> RBTree<K, V> where K : Ord + Debug, V : Debug {
         let other = nodes.len() - 1;
         nodes.swap(id, other);
         if let Some(parent) = nodes[id].parent {
-            let mut parent_node = nodes.get_mut(parent).unwrap();
+            let parent_node = nodes.get_mut(parent).unwrap();
             if parent_node.left.is_some() && parent_node.left.unwrap() == other {
                 parent_node.left = Some(id);
             } else {
@@ -378,7 +379,7 @@ impl<K, V> RBTree<K, V> where K : Ord + Debug, V : Debug {
         DeleteResult { child : None, moved_node : other, moved_node_new_id : id }
     }
 
-    fn rotate_right(h : usize, nodes : &mut Vec<Node<K, V>>) -> usize {
+    fn rotate_right(h : usize, nodes : &mut [Node<K, V>]) -> usize {
         let x = nodes[h].left.unwrap();
 
         nodes[h].left = nodes[x].right;
@@ -407,7 +408,7 @@ impl<K, V> RBTree<K, V> where K : Ord + Debug, V : Debug {
         }
     }
 
-    /**
+    /*
      * Debug Functions
      */
 
@@ -0,0 +1,241 @@
+use super::order::LimitOrderBook;
+use rust_decimal::prelude::*;
+use std::fmt;
+
+const DEFAULT_DEPTH: usize = 10;
+const DEFAULT_BAR_WIDTH: usize = 30;
+
+/// A depth-of-market ladder: asks above the spread, bids below, each row
+/// showing the level size, the cumulative size from the touch and a bar
+/// scaled to the largest level shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ladder {
+    /// Ask levels ordered best (lowest) first.
+    pub asks: Vec<(Decimal, Decimal)>,
+    /// Bid levels ordered best (highest) first.
+    pub bids: Vec<(Decimal, Decimal)>,
+    pub bar_width: usize,
+}
+
+impl Ladder {
+    pub fn new(bids: Vec<(Decimal, Decimal)>, asks: Vec<(Decimal, Decimal)>) -> Self {
+        Self {
+            asks,
+            bids,
+            bar_width: DEFAULT_BAR_WIDTH,
+        }
+    }
+
+    pub fn from_book(book: &LimitOrderBook, depth: usize) -> Self {
+        let bids = book
+            .bids
+            .iter()
+            .rev()
+            .take(depth)
+            .map(|(price, limit)| (*price, limit.borrow().size))
+            .collect();
+        let asks = book
+            .asks
+            .iter()
+            .take(depth)
+            .map(|(price, limit)| (*price, limit.borrow().size))
+            .collect();
+        Self::new(bids, asks)
+    }
+
+    pub fn with_bar_width(mut self, bar_width: usize) -> Self {
+        self.bar_width = bar_width;
+        self
+    }
+
+    pub fn spread(&self) -> Option<Decimal> {
+        match (self.bids.first(), self.asks.first()) {
+            (Some((bid, _)), Some((ask, _))) => Some(ask - bid),
+            _ => None,
+        }
+    }
+
+    /// Rows in display order: farthest ask first, farthest bid last.
+    pub fn rows(&self) -> Vec<LadderRow> {
+        let max_size = self
+            .asks
+            .iter()
+            .chain(self.bids.iter())
+            .map(|(_, size)| *size)
+            .max()
+            .unwrap_or_default();
+
+        let mut asks = Self::side_rows(LadderSide::Ask, &self.asks, max_size, self.bar_width);
+        asks.reverse();
+        let bids = Self::side_rows(LadderSide::Bid, &self.bids, max_size, self.bar_width);
+        asks.into_iter().chain(bids).collect()
+    }
+
+    fn side_rows(
+        side: LadderSide,
+        levels: &[(Decimal, Decimal)],
+        max_size: Decimal,
+        bar_width: usize,
+    ) -> Vec<LadderRow> {
+        let mut cumulative = Decimal::zero();
+        levels
+            .iter()
+            .map(|(price, size)| {
+                cumulative += size;
+                let bar = if max_size.is_zero() {
+                    0
+                } else {
+                    (size / max_size * Decimal::from(bar_width))
+                        .round()
+                        .to_usize()
+                        .unwrap_or_default()
+                };
+                LadderRow {
+                    side,
+                    price: *price,
+                    size: *size,
+                    cumulative,
+                    bar,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LadderSide {
+    Bid,
+    Ask,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LadderRow {
+    pub side: LadderSide,
+    pub price: Decimal,
+    pub size: Decimal,
+    pub cumulative: Decimal,
+    pub bar: usize,
+}
+
+impl fmt::Display for Ladder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>4} {:>14} {:>14} {:>14}", "", "PRICE", "SIZE", "CUM")?;
+
+        let rows = self.rows();
+        let split = rows
+            .iter()
+            .position(|row| row.side == LadderSide::Bid)
+            .unwrap_or(rows.len());
+        for (index, row) in rows.iter().enumerate() {
+            if index == split {
+                self.fmt_spread(f)?;
+            }
+            let label = match row.side {
+                LadderSide::Ask => "ASK",
+                LadderSide::Bid => "BID",
+            };
+            writeln!(
+                f,
+                "{:>4} {:>14} {:>14} {:>14}  {}",
+                label,
+                row.price,
+                row.size,
+                row.cumulative,
+                "#".repeat(row.bar)
+            )?;
+        }
+        if split == rows.len() {
+            self.fmt_spread(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ladder {
+    fn fmt_spread(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.spread() {
+            Some(spread) => writeln!(f, "{:>4} {:>14}", "", format!("-- {} --", spread)),
+            None => writeln!(f, "{:>4} {:>14}", "", "-- no spread --"),
+        }
+    }
+}
+
+impl LimitOrderBook {
+    pub fn ladder(&self, depth: usize) -> Ladder {
+        Ladder::from_book(self, depth)
+    }
+}
+
+impl fmt::Display for LimitOrderBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.ladder(DEFAULT_DEPTH))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::{Order, OrderType};
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    fn book() -> LimitOrderBook {
+        let mut book = LimitOrderBook::new();
+        let levels = [
+            (1, OrderType::Bid, dec!(10), dec!(99)),
+            (2, OrderType::Bid, dec!(20), dec!(98)),
+            (3, OrderType::Ask, dec!(5), dec!(101)),
+            (4, OrderType::Ask, dec!(5), dec!(101)),
+            (5, OrderType::Ask, dec!(20), dec!(102)),
+        ];
+        for (exchange_id, order_type, shares, price) in levels {
+            book.add_order(Order::new(
+                format!("tick{}", exchange_id),
+                exchange_id,
+                order_type,
+                shares,
+                price,
+                Utc::now(),
+                Utc::now(),
+            ));
+        }
+        book
+    }
+
+    #[test]
+    fn test_ladder_rows() {
+        let ladder = book().ladder(10).with_bar_width(10);
+        let rows: Vec<(LadderSide, Decimal, Decimal, Decimal, usize)> = ladder
+            .rows()
+            .into_iter()
+            .map(|row| (row.side, row.price, row.size, row.cumulative, row.bar))
+            .collect();
+
+        assert_eq!(
+            rows,
+            vec![
+                (LadderSide::Ask, dec!(102), dec!(20), dec!(30), 10),
+                (LadderSide::Ask, dec!(101), dec!(10), dec!(10), 5),
+                (LadderSide::Bid, dec!(99), dec!(10), dec!(10), 5),
+                (LadderSide::Bid, dec!(98), dec!(20), dec!(30), 10),
+            ]
+        );
+        assert_eq!(ladder.spread(), Some(dec!(2)));
+    }
+
+    #[test]
+    fn test_ladder_depth_and_display() {
+        let ladder = book().ladder(1).with_bar_width(4);
+        let rendered = ladder.to_string();
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].trim_start().starts_with("ASK"));
+        assert!(lines[1].ends_with("####"));
+        assert!(lines[2].contains("-- 2 --"));
+        assert!(lines[3].trim_start().starts_with("BID"));
+
+        let empty = LimitOrderBook::new().to_string();
+        assert!(empty.contains("no spread"));
+    }
+}
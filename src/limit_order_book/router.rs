@@ -0,0 +1,242 @@
+use super::order::{Fill, LimitOrderBook, Order, OrderType};
+use rust_decimal::prelude::*;
+
+/// A slice of a parent order sent to one venue. `limit_price` is the worst
+/// level the router expects to reach there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChildOrder {
+    pub venue: String,
+    pub limit_price: Decimal,
+    pub quantity: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutingPlan {
+    pub children: Vec<ChildOrder>,
+    /// Quantity no venue displays at an acceptable price.
+    pub unrouted: Decimal,
+}
+
+/// Fills from every venue for one parent order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutedExecution {
+    pub children: Vec<ChildOrder>,
+    /// `(venue, fill)` in the order the children were sent.
+    pub fills: Vec<(String, Fill)>,
+    pub filled_quantity: Decimal,
+    pub remaining_quantity: Decimal,
+    /// Volume-weighted price across all fills.
+    pub average_price: Option<Decimal>,
+}
+
+/// Splits parent orders across several books for the same instrument,
+/// taking the best displayed prices first. Where venues show the same
+/// price, the one displaying more goes first, then the one added first.
+#[derive(Debug, Default)]
+pub struct SmartOrderRouter {
+    venues: Vec<(String, LimitOrderBook)>,
+}
+
+impl SmartOrderRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the book for `venue`, creating an empty one if needed.
+    pub fn add_venue(&mut self, venue: impl Into<String>) -> &mut LimitOrderBook {
+        let venue = venue.into();
+        let index = match self.venues.iter().position(|(name, _)| *name == venue) {
+            Some(index) => index,
+            None => {
+                self.venues.push((venue, LimitOrderBook::new()));
+                self.venues.len() - 1
+            }
+        };
+        &mut self.venues[index].1
+    }
+
+    pub fn venue(&self, venue: &str) -> Option<&LimitOrderBook> {
+        self.venues
+            .iter()
+            .find(|(name, _)| name == venue)
+            .map(|(_, book)| book)
+    }
+
+    pub fn venues(&self) -> impl Iterator<Item = &str> {
+        self.venues.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Allocates `quantity` against displayed liquidity priced at or better
+    /// than `limit_price`, one child per venue used.
+    pub fn plan(&self, side: OrderType, quantity: Decimal, limit_price: Decimal) -> RoutingPlan {
+        // (price, displayed size, venue index) for every crossing level.
+        let mut levels = Vec::new();
+        for (index, (_, book)) in self.venues.iter().enumerate() {
+            let crossing: Vec<_> = match side {
+                OrderType::Bid => book.asks.range(..=limit_price).collect(),
+                OrderType::Ask => book.bids.range(limit_price..).collect(),
+            };
+            for (price, limit) in crossing {
+                levels.push((*price, limit.borrow().size, index));
+            }
+        }
+        levels.sort_by(|a, b| {
+            let by_price = match side {
+                OrderType::Bid => a.0.cmp(&b.0),
+                OrderType::Ask => b.0.cmp(&a.0),
+            };
+            by_price.then(b.1.cmp(&a.1)).then(a.2.cmp(&b.2))
+        });
+
+        let mut children: Vec<ChildOrder> = Vec::new();
+        let mut remaining = quantity;
+        for (price, size, index) in levels {
+            if remaining <= Decimal::zero() {
+                break;
+            }
+            let take = size.min(remaining);
+            remaining -= take;
+            let venue = &self.venues[index].0;
+            match children.iter_mut().find(|child| child.venue == *venue) {
+                // Levels arrive best first, so this price is the child's worst.
+                Some(child) => {
+                    child.quantity += take;
+                    child.limit_price = price;
+                }
+                None => children.push(ChildOrder {
+                    venue: venue.clone(),
+                    limit_price: price,
+                    quantity: take,
+                }),
+            }
+        }
+        RoutingPlan {
+            children,
+            unrouted: remaining,
+        }
+    }
+
+    /// Plans `parent` and executes each child immediately-or-cancel on its
+    /// venue. Nothing rests; the parent's unfilled quantity is reported as
+    /// remaining.
+    pub fn route(&mut self, parent: &Order) -> RoutedExecution {
+        let plan = self.plan(
+            parent.order_type,
+            parent.remaining_quantity,
+            parent.limit_price,
+        );
+
+        let mut fills = Vec::new();
+        for child in &plan.children {
+            let (_, book) = self
+                .venues
+                .iter_mut()
+                .find(|(name, _)| *name == child.venue)
+                .expect("children only name known venues");
+            let mut order = parent.clone();
+            order.shares = child.quantity;
+            order.remaining_quantity = child.quantity;
+            order.filled_quantity = Decimal::zero();
+            order.limit_price = child.limit_price;
+            fills.extend(
+                book.execute_order(order)
+                    .into_iter()
+                    .map(|fill| (child.venue.clone(), fill)),
+            );
+        }
+
+        let filled_quantity: Decimal = fills.iter().map(|(_, fill)| fill.quantity).sum();
+        let notional: Decimal = fills
+            .iter()
+            .map(|(_, fill)| fill.price * fill.quantity)
+            .sum();
+        RoutedExecution {
+            children: plan.children,
+            fills,
+            filled_quantity,
+            remaining_quantity: parent.remaining_quantity - filled_quantity,
+            average_price: (!filled_quantity.is_zero()).then(|| notional / filled_quantity),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    fn order(id: u64, order_type: OrderType, shares: Decimal, price: Decimal) -> Order {
+        Order::new(
+            "BTC/USDT".to_string(),
+            id,
+            order_type,
+            shares,
+            price,
+            Utc::now(),
+            Utc::now(),
+        )
+    }
+
+    fn router() -> SmartOrderRouter {
+        let mut router = SmartOrderRouter::new();
+        let a = router.add_venue("a");
+        a.add_order(order(1, OrderType::Ask, dec!(3), dec!(100)));
+        a.add_order(order(2, OrderType::Ask, dec!(5), dec!(101)));
+        let b = router.add_venue("b");
+        b.add_order(order(1, OrderType::Ask, dec!(4), dec!(100)));
+        b.add_order(order(2, OrderType::Ask, dec!(10), dec!(102)));
+        router
+    }
+
+    #[test]
+    fn test_plan_by_price_then_size() {
+        let router = router();
+        let plan = router.plan(OrderType::Bid, dec!(10), dec!(101));
+        assert_eq!(
+            plan.children,
+            vec![
+                ChildOrder {
+                    venue: "b".to_string(),
+                    limit_price: dec!(100),
+                    quantity: dec!(4),
+                },
+                ChildOrder {
+                    venue: "a".to_string(),
+                    limit_price: dec!(101),
+                    quantity: dec!(6),
+                },
+            ]
+        );
+        assert_eq!(plan.unrouted, dec!(0));
+
+        let plan = router.plan(OrderType::Bid, dec!(20), dec!(100));
+        assert_eq!(plan.children.len(), 2);
+        assert_eq!(plan.unrouted, dec!(13));
+        assert!(router
+            .plan(OrderType::Ask, dec!(1), dec!(1))
+            .children
+            .is_empty());
+    }
+
+    #[test]
+    fn test_route_consolidates_fills() {
+        let mut router = router();
+        let execution = router.route(&order(9, OrderType::Bid, dec!(12), dec!(101)));
+
+        assert_eq!(execution.filled_quantity, dec!(12));
+        assert_eq!(execution.remaining_quantity, dec!(0));
+        assert_eq!(
+            execution.average_price,
+            Some((dec!(7) * dec!(100) + dec!(5) * dec!(101)) / dec!(12))
+        );
+        let venues: Vec<_> = execution
+            .fills
+            .iter()
+            .map(|(venue, fill)| (venue.as_str(), fill.maker_id))
+            .collect();
+        assert_eq!(venues, vec![("b", 1), ("a", 1), ("a", 2)]);
+        assert!(router.venue("a").unwrap().asks.is_empty());
+        assert_eq!(router.venue("b").unwrap().get_best_ask(), Some(dec!(102)));
+    }
+}
@@ -0,0 +1,230 @@
+//! Size of a book in levels, orders and bytes, for capacity planning.
+
+use super::{
+    event::BookEvent,
+    order::{Limit, LimitOrderBook, Order, OrderType},
+};
+use rust_decimal::Decimal;
+use std::{cell::RefCell, collections::HashSet, mem::size_of, rc::Rc};
+
+/// The level holding the most orders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelStats {
+    pub side: OrderType,
+    pub price: Decimal,
+    pub orders: usize,
+    pub quantity: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BookStats {
+    pub bid_levels: usize,
+    pub ask_levels: usize,
+    pub orders: usize,
+    pub clients: usize,
+    /// Heap and inline bytes the book's collections hold, including spare
+    /// capacity. An estimate: allocator overhead and the maps' exact layout
+    /// aren't counted.
+    pub estimated_bytes: usize,
+    pub largest_level: Option<LevelStats>,
+}
+
+impl BookStats {
+    pub fn levels(&self) -> usize {
+        self.bid_levels + self.ask_levels
+    }
+
+    /// Adds `other` to these totals, keeping the larger of the two largest
+    /// levels.
+    pub fn add(&mut self, other: &BookStats) {
+        self.bid_levels += other.bid_levels;
+        self.ask_levels += other.ask_levels;
+        self.orders += other.orders;
+        self.clients += other.clients;
+        self.estimated_bytes += other.estimated_bytes;
+        if other.largest_level.map(|level| level.orders)
+            > self.largest_level.map(|level| level.orders)
+        {
+            self.largest_level = other.largest_level;
+        }
+    }
+}
+
+/// Heap bytes an order's strings hold.
+fn order_bytes(order: &Order) -> usize {
+    order.tick_id.capacity()
+        + order.client.capacity()
+        + order.client_order_id.as_ref().map_or(0, String::capacity)
+}
+
+/// Bytes of a hash map with `capacity` entries of `entry` bytes, counting
+/// one control byte per bucket.
+fn table_bytes(capacity: usize, entry: usize) -> usize {
+    capacity * (entry + 1)
+}
+
+impl LimitOrderBook {
+    pub fn stats(&self) -> BookStats {
+        let mut stats = BookStats {
+            bid_levels: self.bids.len(),
+            ask_levels: self.asks.len(),
+            orders: self.orders.len(),
+            clients: self.client_orders.len(),
+            ..BookStats::default()
+        };
+
+        let level_entry = size_of::<Decimal>() + size_of::<Rc<RefCell<Limit>>>();
+        let level_alloc = 2 * size_of::<usize>() + size_of::<RefCell<Limit>>();
+        let mut bytes = size_of::<LimitOrderBook>();
+        for (side, levels) in [(OrderType::Bid, &self.bids), (OrderType::Ask, &self.asks)] {
+            for (price, limit) in levels {
+                let limit = limit.borrow();
+                bytes += level_entry + level_alloc;
+                bytes += table_bytes(limit.orders.capacity(), size_of::<(u64, Order)>());
+                bytes += limit.orders.values().map(order_bytes).sum::<usize>();
+                bytes += limit.queue.capacity() * size_of::<u64>();
+                if stats
+                    .largest_level
+                    .is_none_or(|largest| limit.queue.len() > largest.orders)
+                {
+                    stats.largest_level = Some(LevelStats {
+                        side,
+                        price: *price,
+                        orders: limit.queue.len(),
+                        quantity: limit.size,
+                    });
+                }
+            }
+        }
+        bytes += table_bytes(self.orders.capacity(), size_of::<(u64, Order)>());
+        bytes += self.orders.values().map(order_bytes).sum::<usize>();
+        bytes += table_bytes(
+            self.client_orders.capacity(),
+            size_of::<(String, HashSet<u64>)>(),
+        );
+        for (client, exchange_ids) in &self.client_orders {
+            bytes += client.capacity() + table_bytes(exchange_ids.capacity(), size_of::<u64>());
+        }
+        bytes += self.events.capacity() * size_of::<BookEvent>();
+        stats.estimated_bytes = bytes;
+        stats
+    }
+}
+
+/// Sums the stats of several books, e.g. every market of an engine.
+pub fn total(stats: impl IntoIterator<Item = BookStats>) -> BookStats {
+    let mut total = BookStats::default();
+    for book in stats {
+        total.add(&book);
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_book_stats() {
+        let mut book = LimitOrderBook::new();
+        let empty = book.stats();
+        assert_eq!(empty.levels(), 0);
+        assert_eq!(empty.largest_level, None);
+
+        for (id, side, price) in [
+            (1, OrderType::Bid, dec!(99)),
+            (2, OrderType::Bid, dec!(99)),
+            (3, OrderType::Ask, dec!(101)),
+        ] {
+            let order = Order::new(
+                "BTC/USDT".to_string(),
+                id,
+                side,
+                dec!(2),
+                price,
+                Utc::now(),
+                Utc::now(),
+            )
+            .with_client("alice");
+            book.add_order(order);
+        }
+        let stats = book.stats();
+        assert_eq!(
+            (
+                stats.bid_levels,
+                stats.ask_levels,
+                stats.orders,
+                stats.clients
+            ),
+            (1, 1, 3, 1)
+        );
+        assert_eq!(
+            stats.largest_level,
+            Some(LevelStats {
+                side: OrderType::Bid,
+                price: dec!(99),
+                orders: 2,
+                quantity: dec!(4),
+            })
+        );
+        assert!(stats.estimated_bytes > empty.estimated_bytes + 3 * size_of::<Order>());
+
+        let both = total([stats, empty]);
+        assert_eq!(both.orders, 3);
+        assert_eq!(both.largest_level, stats.largest_level);
+    }
+
+    #[test]
+    fn test_totals_and_string_bytes() {
+        let level = |side, orders| {
+            Some(LevelStats {
+                side,
+                price: dec!(100),
+                orders,
+                quantity: dec!(1),
+            })
+        };
+        let bids = BookStats {
+            bid_levels: 3,
+            orders: 4,
+            largest_level: level(OrderType::Bid, 2),
+            ..BookStats::default()
+        };
+        let asks = BookStats {
+            ask_levels: 2,
+            orders: 5,
+            largest_level: level(OrderType::Ask, 2),
+            ..BookStats::default()
+        };
+        // Ties keep the level found first.
+        let sum = total([bids, asks]);
+        assert_eq!((sum.levels(), sum.orders), (5, 9));
+        assert_eq!(sum.largest_level, bids.largest_level);
+        let deeper = BookStats {
+            largest_level: level(OrderType::Ask, 3),
+            ..asks
+        };
+        assert_eq!(total([bids, deeper]).largest_level, deeper.largest_level);
+
+        // Longer client IDs take more bytes.
+        let with_client = |client: &str| {
+            let mut book = LimitOrderBook::new();
+            book.add_order(
+                Order::new(
+                    "BTC/USDT".to_string(),
+                    1,
+                    OrderType::Bid,
+                    dec!(1),
+                    dec!(99),
+                    Utc::now(),
+                    Utc::now(),
+                )
+                .with_client(client),
+            );
+            book.stats().estimated_bytes
+        };
+        assert!(with_client(&"a".repeat(64)) > with_client("a"));
+    }
+}
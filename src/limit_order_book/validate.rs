@@ -0,0 +1,313 @@
+use super::order::{LimitOrderBook, OrderType};
+use rust_decimal::prelude::*;
+use std::{collections::HashSet, fmt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BookInvariantError {
+    CrossedBook {
+        best_bid: Decimal,
+        best_ask: Decimal,
+    },
+    LockedBook {
+        price: Decimal,
+    },
+    StaleBestPrice {
+        side: OrderType,
+        cached: Option<Decimal>,
+        actual: Option<Decimal>,
+    },
+    EmptyLevel {
+        side: OrderType,
+        price: Decimal,
+    },
+    LevelAggregateMismatch {
+        side: OrderType,
+        price: Decimal,
+    },
+    MisplacedOrder {
+        exchange_id: u64,
+        side: OrderType,
+        price: Decimal,
+    },
+    OrderMissingFromIndex {
+        exchange_id: u64,
+    },
+    OrderMissingFromLevel {
+        exchange_id: u64,
+    },
+    StaleIndexEntry {
+        exchange_id: u64,
+    },
+    ClientIndexMismatch {
+        exchange_id: u64,
+    },
+}
+
+impl fmt::Display for BookInvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookInvariantError::CrossedBook { best_bid, best_ask } => {
+                write!(f, "book is crossed: bid {} > ask {}", best_bid, best_ask)
+            }
+            BookInvariantError::LockedBook { price } => {
+                write!(f, "book is locked at {}", price)
+            }
+            BookInvariantError::StaleBestPrice {
+                side,
+                cached,
+                actual,
+            } => write!(
+                f,
+                "cached best {:?} price {:?} does not match {:?}",
+                side, cached, actual
+            ),
+            BookInvariantError::EmptyLevel { side, price } => {
+                write!(f, "empty {:?} level left at {}", side, price)
+            }
+            BookInvariantError::LevelAggregateMismatch { side, price } => write!(
+                f,
+                "{:?} level at {} does not match the sum of its orders",
+                side, price
+            ),
+            BookInvariantError::MisplacedOrder {
+                exchange_id,
+                side,
+                price,
+            } => write!(
+                f,
+                "order {} does not belong to the {:?} level at {}",
+                exchange_id, side, price
+            ),
+            BookInvariantError::OrderMissingFromIndex { exchange_id } => {
+                write!(
+                    f,
+                    "order {} rests in a level but not in the index",
+                    exchange_id
+                )
+            }
+            BookInvariantError::OrderMissingFromLevel { exchange_id } => {
+                write!(f, "order {} is indexed but rests in no level", exchange_id)
+            }
+            BookInvariantError::StaleIndexEntry { exchange_id } => {
+                write!(f, "indexed copy of order {} is out of date", exchange_id)
+            }
+            BookInvariantError::ClientIndexMismatch { exchange_id } => {
+                write!(f, "client index is inconsistent for order {}", exchange_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BookInvariantError {}
+
+impl LimitOrderBook {
+    /// Walks the whole book and checks that the cached best prices, level
+    /// aggregates, order index and client index agree with each other.
+    /// This is O(orders) and meant for tests and debugging.
+    pub fn debug_validate(&self) -> Result<(), BookInvariantError> {
+        let actual_ask = self.asks.keys().next().cloned();
+        if self.lowest_ask != actual_ask {
+            return Err(BookInvariantError::StaleBestPrice {
+                side: OrderType::Ask,
+                cached: self.lowest_ask,
+                actual: actual_ask,
+            });
+        }
+        let actual_bid = self.bids.keys().next_back().cloned();
+        if self.highest_bid != actual_bid {
+            return Err(BookInvariantError::StaleBestPrice {
+                side: OrderType::Bid,
+                cached: self.highest_bid,
+                actual: actual_bid,
+            });
+        }
+
+        if let (Some(best_bid), Some(best_ask)) = (actual_bid, actual_ask) {
+            if best_bid > best_ask {
+                return Err(BookInvariantError::CrossedBook { best_bid, best_ask });
+            }
+            if best_bid == best_ask {
+                return Err(BookInvariantError::LockedBook { price: best_bid });
+            }
+        }
+
+        let mut resting = HashSet::new();
+        for (side, levels) in [(OrderType::Bid, &self.bids), (OrderType::Ask, &self.asks)] {
+            for (price, limit) in levels.iter() {
+                let limit = limit.borrow();
+                if limit.orders.is_empty() {
+                    return Err(BookInvariantError::EmptyLevel {
+                        side,
+                        price: *price,
+                    });
+                }
+
+                let mut size = Decimal::zero();
+                let mut total_volume = Decimal::zero();
+                for (exchange_id, order) in limit.orders.iter() {
+                    if order.exchange_id != *exchange_id
+                        || order.order_type != side
+                        || order.limit_price != *price
+                    {
+                        return Err(BookInvariantError::MisplacedOrder {
+                            exchange_id: *exchange_id,
+                            side,
+                            price: *price,
+                        });
+                    }
+                    match self.orders.get(exchange_id) {
+                        None => {
+                            return Err(BookInvariantError::OrderMissingFromIndex {
+                                exchange_id: *exchange_id,
+                            })
+                        }
+                        Some(indexed) if indexed != order => {
+                            return Err(BookInvariantError::StaleIndexEntry {
+                                exchange_id: *exchange_id,
+                            })
+                        }
+                        Some(_) => {}
+                    }
+                    let indexed = self
+                        .client_orders
+                        .get(&order.client)
+                        .is_some_and(|ids| ids.contains(exchange_id));
+                    if !indexed {
+                        return Err(BookInvariantError::ClientIndexMismatch {
+                            exchange_id: *exchange_id,
+                        });
+                    }
+                    size += order.remaining_quantity;
+                    total_volume += order.remaining_quantity * order.limit_price;
+                    resting.insert(*exchange_id);
+                }
+
+                if limit.limit_price != *price
+                    || limit.size != size
+                    || limit.total_volume != total_volume
+                    || limit.order_count as usize != limit.orders.len()
+                    || limit.queue.len() != limit.orders.len()
+                    || limit.queue.iter().any(|id| !limit.orders.contains_key(id))
+                {
+                    return Err(BookInvariantError::LevelAggregateMismatch {
+                        side,
+                        price: *price,
+                    });
+                }
+            }
+        }
+
+        if let Some(exchange_id) = self.orders.keys().find(|id| !resting.contains(id)) {
+            return Err(BookInvariantError::OrderMissingFromLevel {
+                exchange_id: *exchange_id,
+            });
+        }
+        let client_index_len: usize = self.client_orders.values().map(HashSet::len).sum();
+        if client_index_len != resting.len() {
+            let exchange_id = self
+                .client_orders
+                .values()
+                .flatten()
+                .find(|id| !resting.contains(id))
+                .cloned()
+                .unwrap_or_default();
+            return Err(BookInvariantError::ClientIndexMismatch { exchange_id });
+        }
+
+        Ok(())
+    }
+
+    /// Runs `debug_validate` after a mutation when the `validate-invariants`
+    /// feature is enabled and is a no-op otherwise.
+    pub(crate) fn check_invariants(&self) {
+        #[cfg(feature = "validate-invariants")]
+        if let Err(err) = self.debug_validate() {
+            panic!("order book invariant violated: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::Order;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    fn order(exchange_id: u64, order_type: OrderType, shares: Decimal, price: Decimal) -> Order {
+        Order::new(
+            format!("tick{}", exchange_id),
+            exchange_id,
+            order_type,
+            shares,
+            price,
+            Utc::now(),
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn test_valid_book() {
+        let mut book = LimitOrderBook::new();
+        assert_eq!(book.debug_validate(), Ok(()));
+
+        book.add_order(order(1, OrderType::Bid, dec!(10), dec!(99)));
+        book.add_order(order(2, OrderType::Bid, dec!(5), dec!(99)));
+        book.add_order(order(3, OrderType::Ask, dec!(7), dec!(101)));
+        assert_eq!(book.debug_validate(), Ok(()));
+
+        book.remove_order(order(2, OrderType::Bid, dec!(5), dec!(99)));
+        assert_eq!(book.debug_validate(), Ok(()));
+    }
+
+    // add_order does not match, so it can be used to build crossed books;
+    // with `validate-invariants` enabled that panics before we get to check.
+    #[test]
+    #[cfg(not(feature = "validate-invariants"))]
+    fn test_detects_crossed_and_locked_book() {
+        let mut book = LimitOrderBook::new();
+        book.add_order(order(1, OrderType::Bid, dec!(1), dec!(101)));
+        book.add_order(order(2, OrderType::Ask, dec!(1), dec!(100)));
+        assert_eq!(
+            book.debug_validate(),
+            Err(BookInvariantError::CrossedBook {
+                best_bid: dec!(101),
+                best_ask: dec!(100),
+            })
+        );
+
+        let mut book = LimitOrderBook::new();
+        book.add_order(order(1, OrderType::Bid, dec!(1), dec!(100)));
+        book.add_order(order(2, OrderType::Ask, dec!(1), dec!(100)));
+        assert_eq!(
+            book.debug_validate(),
+            Err(BookInvariantError::LockedBook { price: dec!(100) })
+        );
+    }
+
+    #[test]
+    fn test_detects_stale_index_entry() {
+        let mut book = LimitOrderBook::new();
+        book.add_order(order(1, OrderType::Ask, dec!(10), dec!(101)));
+        book.orders
+            .insert(2, order(2, OrderType::Ask, dec!(10), dec!(101)));
+        assert_eq!(
+            book.debug_validate(),
+            Err(BookInvariantError::OrderMissingFromLevel { exchange_id: 2 })
+        );
+    }
+
+    #[test]
+    fn test_detects_level_aggregate_mismatch() {
+        let mut book = LimitOrderBook::new();
+        book.add_order(order(1, OrderType::Bid, dec!(10), dec!(99)));
+        book.bids[&dec!(99)].borrow_mut().size = dec!(3);
+        assert_eq!(
+            book.debug_validate(),
+            Err(BookInvariantError::LevelAggregateMismatch {
+                side: OrderType::Bid,
+                price: dec!(99),
+            })
+        );
+    }
+}
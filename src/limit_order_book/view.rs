@@ -0,0 +1,184 @@
+//! Read-only copies of a book for analytics.
+//!
+//! A `BookView` holds no reference into the book it came from, so it can be
+//! kept, iterated at leisure or sent to another thread while the book keeps
+//! matching. Views are shared: `LimitOrderBook::read_view` copies the book
+//! only when it has changed since the last view, and cloning a view is an
+//! `Arc` clone.
+
+use super::{
+    iter::LevelRef,
+    order::{LimitOrderBook, Order, OrderType},
+};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+
+/// One price level of a view, with its orders in queue order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelView {
+    pub price: Decimal,
+    pub size: Decimal,
+    pub orders: Vec<Order>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Levels {
+    version: u64,
+    bids: Vec<LevelView>,
+    asks: Vec<LevelView>,
+}
+
+/// The book as of one version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookView(Arc<Levels>);
+
+impl BookView {
+    fn new(book: &LimitOrderBook) -> Self {
+        let copy = |(price, level): (Decimal, LevelRef)| LevelView {
+            price,
+            size: level.size(),
+            orders: level.orders().cloned().collect(),
+        };
+        Self(Arc::new(Levels {
+            version: book.version(),
+            bids: book.iter_bids().map(copy).collect(),
+            asks: book.iter_asks().map(copy).collect(),
+        }))
+    }
+
+    /// The book version the view was taken at.
+    pub fn version(&self) -> u64 {
+        self.0.version
+    }
+
+    /// Bid levels, best first.
+    pub fn bids(&self) -> &[LevelView] {
+        &self.0.bids
+    }
+
+    /// Ask levels, best first.
+    pub fn asks(&self) -> &[LevelView] {
+        &self.0.asks
+    }
+
+    pub fn side(&self, side: OrderType) -> &[LevelView] {
+        match side {
+            OrderType::Bid => self.bids(),
+            OrderType::Ask => self.asks(),
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<Decimal> {
+        self.bids().first().map(|level| level.price)
+    }
+
+    pub fn best_ask(&self) -> Option<Decimal> {
+        self.asks().first().map(|level| level.price)
+    }
+
+    /// Every resting order, bids then asks, each side best level first.
+    pub fn orders(&self) -> impl Iterator<Item = &Order> {
+        self.bids()
+            .iter()
+            .chain(self.asks())
+            .flat_map(|level| &level.orders)
+    }
+
+    /// Whether `other` is the very same copy, not just an equal one.
+    pub fn shares(&self, other: &BookView) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl LimitOrderBook {
+    /// A view of the book as it stands, reusing the last one if nothing has
+    /// changed since.
+    pub fn read_view(&self) -> BookView {
+        let mut cached = self.view.borrow_mut();
+        match &*cached {
+            Some(view) if view.version() == self.version() => view.clone(),
+            _ => {
+                let view = BookView::new(self);
+                *cached = Some(view.clone());
+                view
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+    use std::thread;
+
+    fn order(exchange_id: u64, side: OrderType, price: Decimal) -> Order {
+        Order::new(
+            "BTC/USDT".to_string(),
+            exchange_id,
+            side,
+            dec!(2),
+            price,
+            Utc::now(),
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn test_read_view() {
+        let mut book = LimitOrderBook::new();
+        book.add_order(order(1, OrderType::Bid, dec!(98)));
+        book.add_order(order(2, OrderType::Bid, dec!(99)));
+        book.add_order(order(3, OrderType::Bid, dec!(99)));
+        book.add_order(order(4, OrderType::Ask, dec!(101)));
+
+        let view = book.read_view();
+        assert!(view.shares(&book.read_view()));
+        let ids: Vec<u64> = view.orders().map(|order| order.exchange_id).collect();
+        assert_eq!(ids, vec![2, 3, 1, 4]);
+        assert_eq!(view.bids()[0].size, dec!(4));
+        assert_eq!(
+            (view.best_bid(), view.best_ask()),
+            (Some(dec!(99)), Some(dec!(101)))
+        );
+
+        // The view stays as it was while the book moves on.
+        book.cancel_order(2);
+        let analytics = {
+            let view = view.clone();
+            thread::spawn(move || view.orders().count())
+        };
+        assert_eq!(analytics.join().unwrap(), 4);
+        let latest = book.read_view();
+        assert!(!latest.shares(&view));
+        assert!(latest.version() > view.version());
+        assert_eq!(latest.bids()[0].orders.len(), 1);
+    }
+
+    #[test]
+    fn test_empty_and_equal_views() {
+        let mut book = LimitOrderBook::new();
+        let empty = book.read_view();
+        assert_eq!((empty.best_bid(), empty.best_ask()), (None, None));
+        assert_eq!(empty.orders().count(), 0);
+
+        book.add_order(order(1, OrderType::Ask, dec!(102)));
+        book.add_order(order(2, OrderType::Ask, dec!(101)));
+        let view = book.read_view();
+        let asks: Vec<Decimal> = view
+            .side(OrderType::Ask)
+            .iter()
+            .map(|level| level.price)
+            .collect();
+        assert_eq!(asks, vec![dec!(101), dec!(102)]);
+        assert!(view.side(OrderType::Bid).is_empty());
+
+        // A copy of the same book is equal but not shared.
+        let copy = book.clone();
+        copy.view.replace(None);
+        let other = copy.read_view();
+        assert_eq!(other, view);
+        assert!(!other.shares(&view));
+    }
+}
@@ -0,0 +1,23 @@
+//! Installs the global `tracing` subscriber for the `tradebot` binary.
+
+use crate::config::{LogFormat, LoggingConfig};
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+/// Sets up logging from `config`, letting `RUST_LOG` override the levels.
+/// Fails if the directives are malformed or a subscriber is already set.
+pub fn init(config: &LoggingConfig) -> Result<(), String> {
+    let filter = match std::env::var(EnvFilter::DEFAULT_ENV) {
+        Ok(directives) => EnvFilter::try_new(directives),
+        Err(_) => EnvFilter::try_new(config.directives()),
+    }
+    .map_err(|err| format!("invalid log directives: {}", err))?;
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE);
+    match config.format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).try_init(),
+    }
+    .map_err(|err| err.to_string())
+}
@@ -1,19 +1,393 @@
-mod limit_order_book;
-mod matching_engine;
-use matching_engine::orderbook::{Order, OrderBook, OrderType};
-use rust_decimal_macros::dec;
-
-fn main() {
-    let buy_order_1 = Order::new(1.0, OrderType::Bid);
-    let buy_order_2 = Order::new(2.0, OrderType::Bid);
-    let sell_order_1 = Order::new(1.0, OrderType::Ask);
-    let sell_order_2 = Order::new(2.0, OrderType::Ask);
-
-    let mut order_book = OrderBook::new();
-    order_book.add_limit_order(buy_order_1, dec!(1.0));
-    order_book.add_limit_order(buy_order_2, dec!(1.0));
-    order_book.add_limit_order(sell_order_1, dec!(2.0));
-    order_book.add_limit_order(sell_order_2, dec!(2.0));
-
-    println!("{:?}", order_book);
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand};
+use rust_decimal::Decimal;
+use std::{
+    fs,
+    io::BufReader,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use tradebot::{
+    api::{Command, NewOrderRequest},
+    client::Client,
+    config::{EngineConfig, LogFormat, MarketConfig},
+    history::{AsOf, History},
+    import,
+    limit_order_book::{
+        order::{OrderType, TimeInForce},
+        render::Ladder,
+    },
+    logging,
+    matching_engine::engine::{MatchingEngine, TradingPair},
+    replay::{self, RecordedCommand},
+    server::{self, EngineHandle},
+};
+
+#[derive(Parser)]
+#[command(name = "tradebot", about = "Limit order book matching engine")]
+struct Cli {
+    /// Base URL of a running `tradebot serve` instance.
+    #[arg(long, global = true, default_value = "http://127.0.0.1:8080")]
+    url: String,
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Run the matching engine behind the HTTP API.
+    Serve {
+        /// TOML file describing markets, limits and the listen address.
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Listen address; overrides the config file.
+        #[arg(long)]
+        addr: Option<SocketAddr>,
+        /// Market to open, e.g. BTC/USDT. May be repeated.
+        #[arg(long = "market")]
+        markets: Vec<TradingPair>,
+        /// Serve Prometheus metrics at /metrics.
+        #[arg(long)]
+        metrics: bool,
+        /// Log one JSON object per event instead of text lines.
+        #[arg(long)]
+        log_json: bool,
+        /// Also serve the gRPC API on this address.
+        #[arg(long)]
+        grpc_addr: Option<SocketAddr>,
+    },
+    /// Submit or cancel orders.
+    #[command(subcommand)]
+    Order(OrderCommand),
+    /// Inspect order books.
+    #[command(subcommand)]
+    Book(BookCommand),
+    /// Send every command in a JSON-lines file to the engine, in order. A
+    /// `.csv` file is read as order events; see `tradebot::import`.
+    Replay { file: String },
+    /// Check that a recording replays identically, including across
+    /// snapshot/restore, without a running server.
+    Verify {
+        /// JSON-lines file of `tradebot::replay::RecordedCommand`.
+        file: PathBuf,
+        /// Engine configuration; defaults to a single BTC/USDT market.
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Commands between snapshots; 0 skips the snapshot run.
+        #[arg(long, default_value_t = 100)]
+        snapshot_every: usize,
+    },
+    /// Rebuild a book as it stood at some point of a recording, without a
+    /// running server.
+    History {
+        /// JSON-lines file of `tradebot::replay::RecordedCommand`.
+        file: PathBuf,
+        pair: TradingPair,
+        /// Number of commands to apply.
+        #[arg(long, conflicts_with = "time", required_unless_present = "time")]
+        sequence: Option<usize>,
+        /// Apply every command received at or before this time (RFC 3339).
+        #[arg(long)]
+        time: Option<DateTime<Utc>>,
+        /// Engine configuration; defaults to a single BTC/USDT market.
+        #[arg(long)]
+        config: Option<PathBuf>,
+        #[arg(long, default_value_t = 10)]
+        depth: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum OrderCommand {
+    New(NewOrderArgs),
+    Cancel {
+        #[arg(long)]
+        pair: String,
+        #[arg(long)]
+        id: u64,
+    },
+}
+
+#[derive(Args)]
+struct NewOrderArgs {
+    #[arg(long)]
+    pair: String,
+    #[arg(long)]
+    side: OrderType,
+    #[arg(long)]
+    price: Decimal,
+    #[arg(long)]
+    quantity: Decimal,
+    #[arg(long, default_value = "")]
+    client: String,
+    /// Mark a sell as a short sale.
+    #[arg(long)]
+    short: bool,
+    /// Only close out the client's position, never flip it.
+    #[arg(long)]
+    reduce_only: bool,
+    /// The client's own ID for the order; resending it is a no-op.
+    #[arg(long)]
+    client_order_id: Option<String>,
+    /// Tags the order with one of the client's strategies.
+    #[arg(long)]
+    strategy: Option<String>,
+    /// Cancel whatever is left at the close of the market's session.
+    #[arg(long)]
+    day: bool,
+}
+
+#[derive(Subcommand)]
+enum BookCommand {
+    Show {
+        pair: String,
+        #[arg(long, default_value_t = 10)]
+        depth: usize,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let client = Client::new(cli.url);
+
+    let result = match cli.command {
+        Commands::Serve {
+            config,
+            addr,
+            markets,
+            metrics,
+            log_json,
+            grpc_addr,
+        } => serve(config, addr, markets, metrics, log_json, grpc_addr),
+        Commands::Order(OrderCommand::New(args)) => client
+            .new_order(&NewOrderRequest {
+                pair: args.pair,
+                side: args.side,
+                price: args.price,
+                quantity: args.quantity,
+                client: args.client,
+                short_sale: args.short,
+                reduce_only: args.reduce_only,
+                client_order_id: args.client_order_id,
+                strategy: args.strategy,
+                time_in_force: if args.day {
+                    TimeInForce::Day
+                } else {
+                    TimeInForce::Gtc
+                },
+            })
+            .map(|response| print_json(&response)),
+        Commands::Order(OrderCommand::Cancel { pair, id }) => client
+            .cancel_order(&pair, id)
+            .map(|report| print_json(&report)),
+        Commands::Book(BookCommand::Show { pair, depth }) => {
+            client.book(&pair, depth).map(|book| {
+                println!("{}", book.pair);
+                print!("{}", Ladder::new(book.bids, book.asks));
+            })
+        }
+        Commands::Replay { file } => replay(&client, &file),
+        Commands::Verify {
+            file,
+            config,
+            snapshot_every,
+        } => verify(&file, config, snapshot_every),
+        Commands::History {
+            file,
+            pair,
+            sequence,
+            time,
+            config,
+            depth,
+        } => {
+            let as_of = match (sequence, time) {
+                (Some(sequence), _) => AsOf::Sequence(sequence),
+                (None, Some(time)) => AsOf::Time(time),
+                (None, None) => unreachable!("clap requires one of them"),
+            };
+            history(&file, config, &pair, as_of, depth)
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn serve(
+    config: Option<PathBuf>,
+    addr: Option<SocketAddr>,
+    markets: Vec<TradingPair>,
+    metrics: bool,
+    log_json: bool,
+    grpc_addr: Option<SocketAddr>,
+) -> Result<(), String> {
+    let mut config = match config {
+        Some(path) => EngineConfig::from_file(path).map_err(|err| err.to_string())?,
+        None => EngineConfig::default(),
+    };
+    if log_json {
+        config.logging.format = LogFormat::Json;
+    }
+    logging::init(&config.logging)?;
+
+    config
+        .markets
+        .extend(markets.into_iter().map(MarketConfig::new));
+    if config.markets.is_empty() {
+        config.markets.push(MarketConfig::new(TradingPair::new(
+            "BTC".to_string(),
+            "USDT".to_string(),
+        )));
+    }
+    let addr = addr.unwrap_or(config.server.addr);
+    let metrics = metrics || config.server.metrics;
+    let grpc_addr = grpc_addr.or(config.server.grpc_addr);
+    if grpc_addr.is_some() && !cfg!(feature = "grpc") {
+        return Err("gRPC needs a build with the `grpc` feature".to_string());
+    }
+
+    let engine = EngineHandle::recover(move || MatchingEngine::with_config(config))?;
+    let runtime = tokio::runtime::Runtime::new().map_err(|err| err.to_string())?;
+    tracing::info!(%addr, "listening");
+    runtime.block_on(async {
+        let signalled = engine.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                tracing::info!("shutting down");
+                if let Err(err) = signalled.shutdown().await {
+                    tracing::error!(%err, "engine didn't persist its state");
+                }
+            }
+        });
+        #[cfg(feature = "grpc")]
+        if let Some(grpc_addr) = grpc_addr {
+            tracing::info!(%grpc_addr, "listening for gRPC");
+            let engine = engine.clone();
+            tokio::spawn(async move {
+                if let Err(err) = tradebot::grpc::serve(grpc_addr, engine).await {
+                    tracing::error!(%err, "gRPC server stopped");
+                }
+            });
+        }
+        server::serve(addr, engine, metrics)
+            .await
+            .map_err(|err| err.to_string())
+    })
+}
+
+fn replay(client: &Client, file: &str) -> Result<(), String> {
+    if file.ends_with(".csv") {
+        return replay_csv(client, file);
+    }
+    let contents = fs::read_to_string(file).map_err(|err| format!("{}: {}", file, err))?;
+
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let command: Command =
+            serde_json::from_str(line).map_err(|err| format!("{}:{}: {}", file, index + 1, err))?;
+
+        let result = match command {
+            Command::New(request) => client
+                .new_order(&request)
+                .map(|response| print_json(&response)),
+            Command::Cancel(request) => client
+                .cancel_order(&request.pair, request.exchange_id)
+                .map(|report| print_json(&report)),
+        };
+        if let Err(err) = result {
+            eprintln!("{}:{}: {}", file, index + 1, err);
+        }
+    }
+    Ok(())
+}
+
+fn replay_csv(client: &Client, file: &str) -> Result<(), String> {
+    let reader = fs::File::open(file).map_err(|err| format!("{}: {}", file, err))?;
+    let (events, errors) = import::read_csv(BufReader::new(reader));
+    let replay_errors = import::replay(
+        &events,
+        |request| {
+            let response = client.new_order(&request)?;
+            print_json(&response);
+            Ok(response)
+        },
+        |request| {
+            let report = client.cancel_order(&request.pair, request.exchange_id)?;
+            print_json(&report);
+            Ok(report)
+        },
+    );
+
+    let mut errors: Vec<_> = errors.into_iter().chain(replay_errors).collect();
+    errors.sort_by_key(|error| error.line);
+    for error in errors {
+        eprintln!("{}:{}: {}", file, error.line, error.message);
+    }
+    Ok(())
+}
+
+/// The engine configuration for offline runs over a recording, and the
+/// recording itself.
+fn load_recording(
+    file: &Path,
+    config: Option<PathBuf>,
+) -> Result<(EngineConfig, Vec<RecordedCommand>), String> {
+    let mut config = match config {
+        Some(path) => EngineConfig::from_file(path).map_err(|err| err.to_string())?,
+        None => EngineConfig::default(),
+    };
+    if config.markets.is_empty() {
+        config.markets.push(MarketConfig::new(TradingPair::new(
+            "BTC".to_string(),
+            "USDT".to_string(),
+        )));
+    }
+    let reader = fs::File::open(file).map_err(|err| format!("{}: {}", file.display(), err))?;
+    let commands = replay::read_recording(BufReader::new(reader))
+        .map_err(|err| format!("{}: {}", file.display(), err))?;
+    Ok((config, commands))
+}
+
+fn verify(file: &Path, config: Option<PathBuf>, snapshot_every: usize) -> Result<(), String> {
+    let (config, commands) = load_recording(file, config)?;
+    let output = replay::verify(
+        || MatchingEngine::with_config(config.clone()),
+        &commands,
+        snapshot_every,
+    )
+    .map_err(|err| err.to_string())?;
+    println!(
+        "{} commands replayed identically ({} bytes of output)",
+        commands.len(),
+        output.len()
+    );
+    Ok(())
+}
+
+fn history(
+    file: &Path,
+    config: Option<PathBuf>,
+    pair: &TradingPair,
+    as_of: AsOf,
+    depth: usize,
+) -> Result<(), String> {
+    let (config, commands) = load_recording(file, config)?;
+    let history = History::new(|| MatchingEngine::with_config(config.clone()), commands);
+    let book = history.book_at(pair, as_of)?;
+    println!("{} after {} commands", pair, book.sequence);
+    print!("{}", Ladder::from_book(&book, depth));
+    Ok(())
+}
+
+fn print_json<T: serde::Serialize>(value: &T) {
+    println!("{}", serde_json::to_string(value).unwrap());
 }
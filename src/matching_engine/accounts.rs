@@ -0,0 +1,292 @@
+use crate::config::RiskLimits;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{borrow::Borrow, collections::BTreeMap, fmt};
+
+/// Separates a parent account from its sub-account in an `AccountId`.
+pub const SUB_ACCOUNT_SEPARATOR: char = '/';
+
+/// Identifies an account as written in `Order::client`: `desk` for a
+/// top-level account, `desk/arb` for its sub-account `arb`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AccountId(String);
+
+impl AccountId {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn sub(&self, name: &str) -> AccountId {
+        AccountId(format!("{}{}{}", self.0, SUB_ACCOUNT_SEPARATOR, name))
+    }
+
+    /// The account this one is a sub-account of, if any.
+    pub fn parent(&self) -> Option<AccountId> {
+        self.0
+            .rsplit_once(SUB_ACCOUNT_SEPARATOR)
+            .map(|(parent, _)| AccountId(parent.to_string()))
+    }
+
+    /// The top-level account at the head of this one's family.
+    pub fn root(&self) -> AccountId {
+        let root = self
+            .0
+            .split(SUB_ACCOUNT_SEPARATOR)
+            .next()
+            .unwrap_or_default();
+        AccountId(root.to_string())
+    }
+}
+
+impl fmt::Display for AccountId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&str> for AccountId {
+    fn from(id: &str) -> Self {
+        Self(id.to_string())
+    }
+}
+
+impl From<String> for AccountId {
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+impl Borrow<str> for AccountId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Account {
+    pub id: AccountId,
+    /// Checked on top of the market's limits for this account's orders and
+    /// those of its sub-accounts that set none of their own.
+    pub risk: Option<RiskLimits>,
+    balances: BTreeMap<String, Decimal>,
+}
+
+impl Account {
+    pub fn balance(&self, asset: &str) -> Decimal {
+        self.balances.get(asset).copied().unwrap_or_default()
+    }
+
+    pub fn balances(&self) -> impl Iterator<Item = (&str, Decimal)> {
+        self.balances
+            .iter()
+            .map(|(asset, amount)| (asset.as_str(), *amount))
+    }
+}
+
+/// The accounts allowed to trade. While empty, the engine accepts any
+/// client string as before.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Accounts {
+    accounts: BTreeMap<AccountId, Account>,
+}
+
+impl Accounts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Account> {
+        self.accounts.get(id)
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.accounts.contains_key(id)
+    }
+
+    /// Accounts in ID order, so each parent comes before its sub-accounts.
+    pub fn iter(&self) -> impl Iterator<Item = &Account> {
+        self.accounts.values()
+    }
+
+    /// Opens `id`; a sub-account's parent must already be open.
+    pub fn open(&mut self, id: AccountId, risk: Option<RiskLimits>) -> Result<(), String> {
+        if id.as_str().is_empty() || id.as_str().split(SUB_ACCOUNT_SEPARATOR).any(str::is_empty) {
+            return Err(format!("Invalid account ID: {:?}", id.as_str()));
+        }
+        if self.accounts.contains_key(&id) {
+            return Err(format!("Account {} already exists", id));
+        }
+        if let Some(parent) = id
+            .parent()
+            .filter(|parent| !self.accounts.contains_key(parent))
+        {
+            return Err(format!("No parent account {} for {}", parent, id));
+        }
+        self.accounts.insert(
+            id.clone(),
+            Account {
+                id,
+                risk,
+                balances: BTreeMap::new(),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn set_risk_limits(&mut self, id: &str, risk: Option<RiskLimits>) -> Result<(), String> {
+        self.account_mut(id)?.risk = risk;
+        Ok(())
+    }
+
+    /// The limits of `id` or, failing that, of its nearest ancestor that
+    /// sets any.
+    pub fn risk_limits(&self, id: &str) -> Option<&RiskLimits> {
+        let mut id = Some(AccountId::from(id));
+        while let Some(account) = id {
+            if let Some(risk) = self.accounts.get(&account).and_then(|a| a.risk.as_ref()) {
+                return Some(risk);
+            }
+            id = account.parent();
+        }
+        None
+    }
+
+    pub fn deposit(&mut self, id: &str, asset: &str, amount: Decimal) -> Result<(), String> {
+        if amount <= Decimal::ZERO {
+            return Err(format!("Invalid amount: {}", amount));
+        }
+        *self
+            .account_mut(id)?
+            .balances
+            .entry(asset.to_string())
+            .or_default() += amount;
+        Ok(())
+    }
+
+    /// Credits `amount` of `asset` to `id`, or debits it when negative. The
+    /// balance may go below zero; this books P&L at settlement rather than
+    /// moving funds.
+    pub fn adjust(&mut self, id: &str, asset: &str, amount: Decimal) -> Result<(), String> {
+        *self
+            .account_mut(id)?
+            .balances
+            .entry(asset.to_string())
+            .or_default() += amount;
+        Ok(())
+    }
+
+    /// Moves `amount` of `asset` between two accounts of the same family.
+    pub fn transfer(
+        &mut self,
+        from: &str,
+        to: &str,
+        asset: &str,
+        amount: Decimal,
+    ) -> Result<(), String> {
+        if amount <= Decimal::ZERO {
+            return Err(format!("Invalid amount: {}", amount));
+        }
+        let (from_id, to_id) = (AccountId::from(from), AccountId::from(to));
+        if from_id.root() != to_id.root() {
+            return Err(format!(
+                "Transfers are only allowed within one account family, not {} to {}",
+                from, to
+            ));
+        }
+        self.account_mut(to)?;
+        let available = self.account_mut(from)?.balance(asset);
+        if available < amount {
+            return Err(format!(
+                "Insufficient {} in {}: {} available, {} requested",
+                asset, from, available, amount
+            ));
+        }
+        *self.account_mut(from)?.balances.get_mut(asset).unwrap() -= amount;
+        *self
+            .account_mut(to)?
+            .balances
+            .entry(asset.to_string())
+            .or_default() += amount;
+        Ok(())
+    }
+
+    fn account_mut(&mut self, id: &str) -> Result<&mut Account, String> {
+        self.accounts
+            .get_mut(id)
+            .ok_or_else(|| format!("Unknown account: {:?}", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_account_ids() {
+        let desk = AccountId::from("desk");
+        let arb = desk.sub("arb");
+        assert_eq!(arb.as_str(), "desk/arb");
+        assert_eq!(arb.parent(), Some(desk.clone()));
+        assert_eq!(arb.sub("eu").root(), desk);
+        assert_eq!(desk.parent(), None);
+    }
+
+    #[test]
+    fn test_sub_accounts_and_transfers() {
+        let mut accounts = Accounts::new();
+        let desk = AccountId::from("desk");
+        assert!(accounts.open(desk.sub("arb"), None).is_err());
+        accounts.open(desk.clone(), None).unwrap();
+        accounts.open(desk.sub("arb"), None).unwrap();
+        accounts.open("other".into(), None).unwrap();
+        assert!(accounts.open(desk.clone(), None).is_err());
+        assert!(accounts.open("desk//x".into(), None).is_err());
+
+        accounts.deposit("desk", "USDT", dec!(100)).unwrap();
+        accounts
+            .transfer("desk", "desk/arb", "USDT", dec!(40))
+            .unwrap();
+        assert_eq!(accounts.get("desk").unwrap().balance("USDT"), dec!(60));
+        assert_eq!(accounts.get("desk/arb").unwrap().balance("USDT"), dec!(40));
+
+        assert!(accounts
+            .transfer("desk/arb", "desk", "USDT", dec!(41))
+            .is_err());
+        assert!(accounts.transfer("desk", "other", "USDT", dec!(1)).is_err());
+        assert!(accounts
+            .transfer("desk", "desk/nope", "USDT", dec!(1))
+            .is_err());
+        assert_eq!(accounts.get("desk").unwrap().balance("USDT"), dec!(60));
+    }
+
+    #[test]
+    fn test_risk_limits_inherited() {
+        let mut accounts = Accounts::new();
+        let limits = RiskLimits {
+            max_order_quantity: Some(dec!(5)),
+            ..RiskLimits::default()
+        };
+        accounts.open("desk".into(), Some(limits.clone())).unwrap();
+        accounts.open("desk/arb".into(), None).unwrap();
+        assert_eq!(accounts.risk_limits("desk/arb"), Some(&limits));
+
+        accounts
+            .set_risk_limits("desk/arb", Some(RiskLimits::default()))
+            .unwrap();
+        assert_eq!(
+            accounts.risk_limits("desk/arb"),
+            Some(&RiskLimits::default())
+        );
+        assert_eq!(accounts.risk_limits("nobody"), None);
+    }
+}
@@ -0,0 +1,122 @@
+//! Auto-deleveraging: closing a bankrupt position the book can't absorb
+//! against the most profitable, most leveraged positions on the other side.
+
+use crate::matching_engine::accounts::AccountId;
+use rust_decimal::Decimal;
+
+/// An opposite position that may be deleveraged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdlCandidate {
+    pub account: AccountId,
+    /// Net position in the bankrupt account's market.
+    pub position: Decimal,
+    /// Higher scores are deleveraged first.
+    pub score: Decimal,
+}
+
+impl AdlCandidate {
+    /// Ranks a position by its unrealized return on the value it was opened
+    /// at, times the account's leverage: its total notional over its equity.
+    /// Accounts without positive equity aren't candidates.
+    pub fn new(
+        account: AccountId,
+        position: Decimal,
+        unrealized_pnl: Decimal,
+        entry_value: Decimal,
+        notional: Decimal,
+        equity: Decimal,
+    ) -> Option<Self> {
+        if equity <= Decimal::ZERO || entry_value.is_zero() {
+            return None;
+        }
+        let score = unrealized_pnl / entry_value.abs() * (notional / equity);
+        Some(Self {
+            account,
+            position,
+            score,
+        })
+    }
+}
+
+/// Takes `quantity` from `candidates` in ranking order, highest score
+/// first and by account on ties. Returns each account's share, which may
+/// add up to less than `quantity` when the candidates run out.
+pub fn allocate(mut candidates: Vec<AdlCandidate>, quantity: Decimal) -> Vec<(AccountId, Decimal)> {
+    candidates.sort_by(|a, b| b.score.cmp(&a.score).then(a.account.cmp(&b.account)));
+    let mut left = quantity;
+    let mut allocations = Vec::new();
+    for candidate in candidates {
+        if left <= Decimal::ZERO {
+            break;
+        }
+        let share = candidate.position.abs().min(left);
+        left -= share;
+        allocations.push((candidate.account, share));
+    }
+    allocations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_ranking_by_profit_and_leverage() {
+        let candidate = |account: &str, position, pnl, entry, notional, equity| {
+            AdlCandidate::new(account.into(), position, pnl, entry, notional, equity)
+        };
+        let candidates = vec![
+            // 30% up at 0.3x leverage.
+            candidate("bob", dec!(-5), dec!(150), dec!(500), dec!(350), dec!(1150)).unwrap(),
+            // 30% up at 1.4x leverage.
+            candidate("erin", dec!(-5), dec!(150), dec!(500), dec!(350), dec!(250)).unwrap(),
+            candidate("dave", dec!(-1), dec!(0), dec!(70), dec!(70), dec!(1000)).unwrap(),
+        ];
+        assert!(candidate("zoe", dec!(-1), dec!(1), dec!(1), dec!(1), dec!(0)).is_none());
+
+        assert_eq!(
+            allocate(candidates.clone(), dec!(7)),
+            vec![("erin".into(), dec!(5)), ("bob".into(), dec!(2))]
+        );
+        assert_eq!(allocate(candidates, dec!(20)).len(), 3);
+    }
+
+    #[test]
+    fn test_ties_losses_and_running_out() {
+        let candidate = |account: &str, position, pnl| {
+            AdlCandidate::new(
+                account.into(),
+                position,
+                pnl,
+                dec!(100),
+                dec!(100),
+                dec!(100),
+            )
+            .unwrap()
+        };
+        assert!(
+            AdlCandidate::new("zoe".into(), dec!(1), dec!(1), dec!(0), dec!(1), dec!(1)).is_none()
+        );
+
+        // Equal scores go by account, and losing positions come last.
+        let candidates = vec![
+            candidate("carol", dec!(2), dec!(-10)),
+            candidate("bob", dec!(2), dec!(10)),
+            candidate("alice", dec!(2), dec!(10)),
+        ];
+        assert_eq!(candidates[0].score, dec!(-0.1));
+        assert_eq!(
+            allocate(candidates.clone(), dec!(5)),
+            vec![
+                ("alice".into(), dec!(2)),
+                ("bob".into(), dec!(2)),
+                ("carol".into(), dec!(1)),
+            ]
+        );
+        let allocations = allocate(candidates, dec!(9));
+        let total: Decimal = allocations.iter().map(|(_, share)| share).sum();
+        assert_eq!(total, dec!(6));
+        assert!(allocate(Vec::new(), dec!(1)).is_empty());
+    }
+}
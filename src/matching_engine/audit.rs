@@ -0,0 +1,352 @@
+//! Every state change of every order, for working out after the fact why
+//! an order ended up where it did.
+
+use crate::{
+    limit_order_book::order::{Fill, Order, OrderStatus},
+    matching_engine::fees::Liquidity,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// Who or what took an order off the book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    /// The client, directly or by client order ID.
+    Client,
+    /// Replaced by a new order under another client order ID.
+    Replaced,
+    KillSwitch,
+    /// The client's session dropped with cancel-on-disconnect on.
+    Disconnect,
+    /// The client's account fell below maintenance margin.
+    MarginCall,
+    /// Another leg of a contingent basket was rejected.
+    Basket,
+    /// Its bracket's other exit triggered or was replaced.
+    Bracket,
+    /// A corporate action left nothing valid to rest.
+    CorporateAction,
+    /// Too far from the touch under the market's prune policy.
+    Pruned,
+    /// A DAY order at the close of its session.
+    Expired,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditEvent {
+    Accepted,
+    Rejected {
+        reason: String,
+    },
+    Fill {
+        price: Decimal,
+        quantity: Decimal,
+        liquidity: Liquidity,
+    },
+    Cancelled {
+        reason: CancelReason,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// Position in the engine-wide audit log, starting at 1.
+    pub sequence: u64,
+    /// The entry that set this one off: for a fill, the taker's last entry
+    /// before it traded; otherwise the entry itself.
+    pub trigger: u64,
+    /// When the event happened. Cancels carry no time of their own and get
+    /// the latest time the log has seen.
+    pub time: DateTime<Utc>,
+    pub event: AuditEvent,
+    /// The order's status after the event; None for an order rejected on
+    /// entry, which never had one.
+    pub status: Option<OrderStatus>,
+    pub filled_quantity: Decimal,
+    pub remaining_quantity: Decimal,
+}
+
+/// The audit trails of all orders, by exchange ID.
+#[derive(Debug, Clone, Default)]
+pub struct AuditLog {
+    last_sequence: u64,
+    last_time: Option<DateTime<Utc>>,
+    trails: HashMap<u64, Vec<AuditEntry>>,
+}
+
+impl AuditLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The sequence of the latest entry, or 0 before the first.
+    pub fn last_sequence(&self) -> u64 {
+        self.last_sequence
+    }
+
+    /// Numbers entries on from `sequence`, as in an engine restored from a
+    /// snapshot taken at it.
+    pub fn resume(&mut self, sequence: u64) {
+        self.last_sequence = self.last_sequence.max(sequence);
+    }
+
+    /// Entries for `exchange_id`, oldest first.
+    pub fn trail(&self, exchange_id: u64) -> &[AuditEntry] {
+        self.trails
+            .get(&exchange_id)
+            .map_or(&[], |trail| trail.as_slice())
+    }
+
+    pub fn accepted(&mut self, order: &Order) {
+        self.push(
+            order.exchange_id,
+            order.event_time,
+            AuditEvent::Accepted,
+            Some(OrderStatus::New),
+            order.filled_quantity,
+            order.remaining_quantity,
+        );
+    }
+
+    pub fn rejected(&mut self, exchange_id: u64, time: DateTime<Utc>, reason: &str) {
+        self.push(
+            exchange_id,
+            time,
+            AuditEvent::Rejected {
+                reason: reason.to_string(),
+            },
+            None,
+            Decimal::ZERO,
+            Decimal::ZERO,
+        );
+    }
+
+    /// Both sides of each of `fills` of `taker`. `taker_leaves` is what the
+    /// taker had open before the first fill and `maker_leaves` looks up what
+    /// a maker has left afterwards.
+    pub fn fills(
+        &mut self,
+        taker: &Order,
+        mut taker_leaves: Decimal,
+        fills: &[Fill],
+        maker_leaves: impl Fn(u64) -> Decimal,
+    ) {
+        let trigger = self
+            .trail(taker.exchange_id)
+            .last()
+            .map(|entry| entry.sequence);
+        let status = |leaves: Decimal| {
+            if leaves.is_zero() {
+                OrderStatus::Filled
+            } else {
+                OrderStatus::PartiallyFilled
+            }
+        };
+        for fill in fills {
+            let maker_filled = self
+                .trail(fill.maker_id)
+                .last()
+                .map_or(Decimal::ZERO, |entry| entry.filled_quantity)
+                + fill.quantity;
+            let leaves = maker_leaves(fill.maker_id);
+            let maker = self.push(
+                fill.maker_id,
+                taker.event_time,
+                AuditEvent::Fill {
+                    price: fill.price,
+                    quantity: fill.quantity,
+                    liquidity: Liquidity::Maker,
+                },
+                Some(status(leaves)),
+                maker_filled,
+                leaves,
+            );
+            maker.trigger = trigger.unwrap_or(maker.trigger);
+            taker_leaves -= fill.quantity;
+            let entry = self.push(
+                taker.exchange_id,
+                taker.event_time,
+                AuditEvent::Fill {
+                    price: fill.price,
+                    quantity: fill.quantity,
+                    liquidity: Liquidity::Taker,
+                },
+                Some(status(taker_leaves)),
+                taker.shares - taker_leaves,
+                taker_leaves,
+            );
+            entry.trigger = trigger.unwrap_or(entry.trigger);
+        }
+    }
+
+    pub fn cancelled(&mut self, order: &Order, reason: CancelReason) {
+        let time = self.last_time.unwrap_or(order.event_time);
+        self.push(
+            order.exchange_id,
+            time,
+            AuditEvent::Cancelled { reason },
+            Some(OrderStatus::Cancelled),
+            order.filled_quantity,
+            Decimal::ZERO,
+        );
+    }
+
+    /// Appends an entry to the trail of `exchange_id`, as its own trigger.
+    fn push(
+        &mut self,
+        exchange_id: u64,
+        time: DateTime<Utc>,
+        event: AuditEvent,
+        status: Option<OrderStatus>,
+        filled_quantity: Decimal,
+        remaining_quantity: Decimal,
+    ) -> &mut AuditEntry {
+        self.last_sequence += 1;
+        self.last_time = self.last_time.max(Some(time));
+        let trail = self.trails.entry(exchange_id).or_default();
+        trail.push(AuditEntry {
+            sequence: self.last_sequence,
+            trigger: self.last_sequence,
+            time,
+            event,
+            status,
+            filled_quantity,
+            remaining_quantity,
+        });
+        trail.last_mut().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::OrderType;
+    use chrono::{Duration, TimeZone};
+    use rust_decimal_macros::dec;
+
+    fn order(exchange_id: u64, side: OrderType, shares: Decimal, time: DateTime<Utc>) -> Order {
+        Order::new(
+            "BTC/USDT".to_string(),
+            exchange_id,
+            side,
+            shares,
+            dec!(100),
+            time,
+            time,
+        )
+    }
+
+    fn fill(maker_id: u64, quantity: Decimal) -> Fill {
+        Fill {
+            maker_id,
+            taker_id: 3,
+            price: dec!(100),
+            quantity,
+            ..Fill::default()
+        }
+    }
+
+    #[test]
+    fn test_fill_trails() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 2, 9, 30, 0).unwrap();
+        let mut log = AuditLog::new();
+        log.accepted(&order(1, OrderType::Ask, dec!(2), start));
+        log.accepted(&order(2, OrderType::Ask, dec!(2), start));
+        let taker = order(3, OrderType::Bid, dec!(3), start + Duration::seconds(1));
+        log.accepted(&taker);
+        assert_eq!(log.last_sequence(), 3);
+
+        let leaves = |maker_id| if maker_id == 1 { dec!(0) } else { dec!(1) };
+        log.fills(
+            &taker,
+            dec!(3),
+            &[fill(1, dec!(2)), fill(2, dec!(1))],
+            leaves,
+        );
+        assert_eq!(log.last_sequence(), 7);
+
+        // Every fill traces back to the taker's acceptance.
+        let maker = log.trail(1);
+        assert_eq!(maker.len(), 2);
+        assert_eq!((maker[1].sequence, maker[1].trigger), (4, 3));
+        assert_eq!(maker[1].time, taker.event_time);
+        assert_eq!(maker[1].status, Some(OrderStatus::Filled));
+        assert_eq!(
+            (maker[1].filled_quantity, maker[1].remaining_quantity),
+            (dec!(2), dec!(0))
+        );
+        assert_eq!(
+            maker[1].event,
+            AuditEvent::Fill {
+                price: dec!(100),
+                quantity: dec!(2),
+                liquidity: Liquidity::Maker,
+            }
+        );
+        assert_eq!(log.trail(2)[1].status, Some(OrderStatus::PartiallyFilled));
+
+        let trail = log.trail(3);
+        let taker_fills: Vec<_> = trail[1..]
+            .iter()
+            .map(|entry| {
+                (
+                    entry.trigger,
+                    entry.status,
+                    entry.filled_quantity,
+                    entry.remaining_quantity,
+                )
+            })
+            .collect();
+        assert_eq!(
+            taker_fills,
+            vec![
+                (3, Some(OrderStatus::PartiallyFilled), dec!(2), dec!(1)),
+                (3, Some(OrderStatus::Filled), dec!(3), dec!(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejects_and_cancels() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 2, 9, 30, 0).unwrap();
+        let mut log = AuditLog::new();
+        log.resume(10);
+        log.rejected(1, start, "Insufficient balance");
+        let rejected = &log.trail(1)[0];
+        assert_eq!(rejected.sequence, 11);
+        assert_eq!(rejected.status, None);
+        assert_eq!(
+            rejected.event,
+            AuditEvent::Rejected {
+                reason: "Insufficient balance".to_string(),
+            }
+        );
+
+        // Cancels take the latest time seen, not the order's own.
+        let mut resting = order(2, OrderType::Bid, dec!(2), start - Duration::hours(1));
+        log.accepted(&resting);
+        log.rejected(3, start + Duration::seconds(5), "Halted");
+        resting.filled_quantity = dec!(1);
+        log.cancelled(&resting, CancelReason::KillSwitch);
+        let cancelled = log.trail(2).last().unwrap();
+        assert_eq!(cancelled.sequence, 14);
+        assert_eq!(cancelled.time, start + Duration::seconds(5));
+        assert_eq!(
+            cancelled.event,
+            AuditEvent::Cancelled {
+                reason: CancelReason::KillSwitch,
+            }
+        );
+        assert_eq!(cancelled.status, Some(OrderStatus::Cancelled));
+        assert_eq!(
+            (cancelled.filled_quantity, cancelled.remaining_quantity),
+            (dec!(1), dec!(0))
+        );
+
+        // Resuming never winds the sequence back.
+        log.resume(5);
+        assert_eq!(log.last_sequence(), 14);
+        assert!(log.trail(99).is_empty());
+    }
+}
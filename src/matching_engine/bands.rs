@@ -0,0 +1,323 @@
+use crate::matching_engine::engine::TradingPair;
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Which tracked price a band is centred on. When it is not available yet
+/// the band falls back along rolling average, last trade, previous close.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferenceKind {
+    #[default]
+    RollingAverage,
+    LastTrade,
+    PreviousClose,
+}
+
+/// Rejects orders priced too far from the market's reference price.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PriceBand {
+    /// Maximum distance from the reference, in percent of it.
+    pub max_deviation_pct: Decimal,
+    #[serde(default)]
+    pub reference: ReferenceKind,
+}
+
+impl PriceBand {
+    pub fn check(&self, price: Decimal, reference: Decimal) -> Result<(), String> {
+        let limit = reference * self.max_deviation_pct / dec!(100);
+        if (price - reference).abs() > limit {
+            return Err(format!(
+                "Price {} is outside the band of {}% around {}",
+                price, self.max_deviation_pct, reference
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Halts a market when trades move more than `max_move_pct` within
+/// `window_secs`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CircuitBreaker {
+    pub max_move_pct: Decimal,
+    pub window_secs: u64,
+    /// How long the market stays halted once tripped.
+    pub halt_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MarketEvent {
+    Halted {
+        pair: TradingPair,
+        time: DateTime<Utc>,
+        until: DateTime<Utc>,
+        reason: String,
+    },
+    Resumed {
+        pair: TradingPair,
+        time: DateTime<Utc>,
+    },
+    /// Resting orders cancelled on levels the market's prune policy drops.
+    Pruned {
+        pair: TradingPair,
+        time: DateTime<Utc>,
+        levels: usize,
+        orders: Vec<u64>,
+    },
+    /// DAY orders cancelled at the close of the market's session.
+    Expired {
+        pair: TradingPair,
+        time: DateTime<Utc>,
+        orders: Vec<u64>,
+    },
+}
+
+impl MarketEvent {
+    pub fn pair(&self) -> &TradingPair {
+        match self {
+            MarketEvent::Halted { pair, .. }
+            | MarketEvent::Resumed { pair, .. }
+            | MarketEvent::Pruned { pair, .. }
+            | MarketEvent::Expired { pair, .. } => pair,
+        }
+    }
+}
+
+/// Trading state the engine keeps per market for bands and halts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MarketState {
+    last_trade_price: Option<Decimal>,
+    previous_close: Option<Decimal>,
+    /// Trades inside the reference averaging window.
+    average_trades: VecDeque<(DateTime<Utc>, Decimal)>,
+    /// Trades inside the circuit breaker window.
+    recent_trades: VecDeque<(DateTime<Utc>, Decimal)>,
+    halted_until: Option<DateTime<Utc>>,
+}
+
+impl MarketState {
+    /// Window of the rolling average reference price.
+    pub const AVERAGE_WINDOW_SECS: i64 = 300;
+
+    pub fn last_trade_price(&self) -> Option<Decimal> {
+        self.last_trade_price
+    }
+
+    pub fn previous_close(&self) -> Option<Decimal> {
+        self.previous_close
+    }
+
+    pub fn set_last_trade_price(&mut self, price: Decimal) {
+        self.last_trade_price = Some(price);
+    }
+
+    pub fn set_previous_close(&mut self, price: Decimal) {
+        self.previous_close = Some(price);
+    }
+
+    /// Rolls the last trade into the previous close, for the start of a new
+    /// session. Returns the new close.
+    pub fn close_session(&mut self) -> Option<Decimal> {
+        if let Some(price) = self.last_trade_price {
+            self.previous_close = Some(price);
+        }
+        self.average_trades.clear();
+        self.previous_close
+    }
+
+    /// Rewrites every reference price with `adjust`, so bands and breakers
+    /// keep working across a split or dividend.
+    pub fn adjust_prices(&mut self, adjust: impl Fn(Decimal) -> Decimal) {
+        self.last_trade_price = self.last_trade_price.map(&adjust);
+        self.previous_close = self.previous_close.map(&adjust);
+        for (_, price) in self
+            .average_trades
+            .iter_mut()
+            .chain(self.recent_trades.iter_mut())
+        {
+            *price = adjust(*price);
+        }
+    }
+
+    /// Mean trade price over the `AVERAGE_WINDOW_SECS` before `now`.
+    pub fn rolling_average(&self, now: DateTime<Utc>) -> Option<Decimal> {
+        let cutoff = now - Duration::seconds(Self::AVERAGE_WINDOW_SECS);
+        let prices: Vec<Decimal> = self
+            .average_trades
+            .iter()
+            .filter(|(traded, _)| *traded > cutoff)
+            .map(|(_, price)| *price)
+            .collect();
+        if prices.is_empty() {
+            return None;
+        }
+        Some(prices.iter().sum::<Decimal>() / Decimal::from(prices.len()))
+    }
+
+    /// The price of `kind` at `now`, falling back to the next available one.
+    pub fn reference_price(&self, kind: ReferenceKind, now: DateTime<Utc>) -> Option<Decimal> {
+        let average = || self.rolling_average(now);
+        let last = || self.last_trade_price;
+        let close = || self.previous_close;
+        match kind {
+            ReferenceKind::RollingAverage => average().or_else(last).or_else(close),
+            ReferenceKind::LastTrade => last().or_else(average).or_else(close),
+            ReferenceKind::PreviousClose => close().or_else(average).or_else(last),
+        }
+    }
+
+    pub fn halted_until(&self) -> Option<DateTime<Utc>> {
+        self.halted_until
+    }
+
+    pub fn is_halted(&self, now: DateTime<Utc>) -> bool {
+        matches!(self.halted_until, Some(until) if now < until)
+    }
+
+    /// Lifts an expired halt, returning true if the market was halted.
+    pub fn resume_if_expired(&mut self, now: DateTime<Utc>) -> bool {
+        match self.halted_until {
+            Some(until) if now >= until => {
+                self.halted_until = None;
+                self.recent_trades.clear();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn halt(&mut self, until: DateTime<Utc>) {
+        self.halted_until = Some(until);
+    }
+
+    pub fn resume(&mut self) -> bool {
+        self.recent_trades.clear();
+        self.halted_until.take().is_some()
+    }
+
+    /// Records a trade and returns the move that trips `breaker`, in percent,
+    /// if any.
+    pub fn record_trade(
+        &mut self,
+        time: DateTime<Utc>,
+        price: Decimal,
+        breaker: Option<&CircuitBreaker>,
+    ) -> Option<Decimal> {
+        self.last_trade_price = Some(price);
+        let cutoff = time - Duration::seconds(Self::AVERAGE_WINDOW_SECS);
+        while matches!(self.average_trades.front(), Some((traded, _)) if *traded <= cutoff) {
+            self.average_trades.pop_front();
+        }
+        self.average_trades.push_back((time, price));
+
+        let breaker = breaker?;
+
+        let cutoff = time - Duration::seconds(breaker.window_secs as i64);
+        while matches!(self.recent_trades.front(), Some((traded, _)) if *traded < cutoff) {
+            self.recent_trades.pop_front();
+        }
+        self.recent_trades.push_back((time, price));
+
+        let (low, high) = self
+            .recent_trades
+            .iter()
+            .fold((price, price), |(low, high), (_, traded)| {
+                (low.min(*traded), high.max(*traded))
+            });
+        let moved = (high - low) / low * dec!(100);
+        (moved > breaker.max_move_pct).then_some(moved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_band() {
+        let band = PriceBand {
+            max_deviation_pct: dec!(5),
+            reference: ReferenceKind::default(),
+        };
+        assert!(band.check(dec!(105), dec!(100)).is_ok());
+        assert!(band.check(dec!(95), dec!(100)).is_ok());
+        assert!(band.check(dec!(105.01), dec!(100)).is_err());
+        assert!(band.check(dec!(94.99), dec!(100)).is_err());
+    }
+
+    #[test]
+    fn test_breaker_window() {
+        let breaker = CircuitBreaker {
+            max_move_pct: dec!(10),
+            window_secs: 60,
+            halt_secs: 300,
+        };
+        let start = Utc::now();
+        let mut state = MarketState::default();
+
+        assert_eq!(state.record_trade(start, dec!(100), Some(&breaker)), None);
+        assert_eq!(
+            state.record_trade(start + Duration::seconds(30), dec!(109), Some(&breaker)),
+            None
+        );
+        // The 100 print has left the window by now.
+        assert_eq!(
+            state.record_trade(start + Duration::seconds(90), dec!(115), Some(&breaker)),
+            None
+        );
+        assert_eq!(
+            state.record_trade(start + Duration::seconds(100), dec!(92), Some(&breaker)),
+            Some(dec!(25))
+        );
+        assert_eq!(state.last_trade_price(), Some(dec!(92)));
+    }
+
+    #[test]
+    fn test_reference_prices() {
+        let start = Utc::now();
+        let mut state = MarketState::default();
+        assert_eq!(
+            state.reference_price(ReferenceKind::RollingAverage, start),
+            None
+        );
+
+        state.set_previous_close(dec!(90));
+        assert_eq!(
+            state.reference_price(ReferenceKind::RollingAverage, start),
+            Some(dec!(90))
+        );
+
+        state.record_trade(start, dec!(100), None);
+        state.record_trade(start + Duration::seconds(60), dec!(110), None);
+        state.record_trade(start + Duration::seconds(120), dec!(120), None);
+        let now = start + Duration::seconds(120);
+        assert_eq!(state.rolling_average(now), Some(dec!(110)));
+        assert_eq!(
+            state.reference_price(ReferenceKind::LastTrade, now),
+            Some(dec!(120))
+        );
+        assert_eq!(
+            state.reference_price(ReferenceKind::PreviousClose, now),
+            Some(dec!(90))
+        );
+
+        // Five minutes on, only the last print is still in the window.
+        let now = start + Duration::seconds(400);
+        assert_eq!(state.rolling_average(now), Some(dec!(120)));
+        let now = start + Duration::seconds(500);
+        assert_eq!(state.rolling_average(now), None);
+        assert_eq!(
+            state.reference_price(ReferenceKind::RollingAverage, now),
+            Some(dec!(120))
+        );
+
+        assert_eq!(state.close_session(), Some(dec!(120)));
+        assert_eq!(state.previous_close(), Some(dec!(120)));
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{
+    limit_order_book::order::{Fill, Order},
+    matching_engine::engine::TradingPair,
+};
+
+/// What became of one leg: the order in its final state with its fills, or
+/// why it was rejected.
+pub type LegResult = Result<(Order, Vec<Fill>), String>;
+
+/// Limit orders submitted together, possibly across markets. Every leg is
+/// validated before any is placed, so a basket with a bad leg is rejected
+/// as a whole.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Basket {
+    pub legs: Vec<(TradingPair, Order)>,
+    /// If a leg is still rejected once placing starts, cancel the legs
+    /// resting so far and skip the rest. Fills that already happened stand.
+    pub contingent: bool,
+}
+
+impl Basket {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_leg(mut self, pair: TradingPair, order: Order) -> Self {
+        self.legs.push((pair, order));
+        self
+    }
+
+    pub fn with_contingent(mut self, contingent: bool) -> Self {
+        self.contingent = contingent;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::RiskLimits,
+        limit_order_book::order::{OrderStatus, OrderType},
+        matching_engine::{
+            engine::MatchingEngine,
+            rate_limit::{RateLimit, RATE_LIMITED},
+            risk::{BorrowCheck, Locates},
+        },
+    };
+    use chrono::Utc;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    /// Refuses every borrow, but only once asked to make the locate.
+    struct NoBorrow;
+
+    impl BorrowCheck for NoBorrow {
+        fn locate(&mut self, _client: &str, _pair: &TradingPair, _quantity: Decimal) -> bool {
+            false
+        }
+    }
+
+    fn pair(base: &str) -> TradingPair {
+        TradingPair::new(base.to_string(), "USD".to_string())
+    }
+
+    fn leg(
+        engine: &mut MatchingEngine,
+        order_type: OrderType,
+        shares: Decimal,
+        price: Decimal,
+    ) -> Order {
+        Order::new(
+            String::new(),
+            engine.next_exchange_id(),
+            order_type,
+            shares,
+            price,
+            Utc::now(),
+            Utc::now(),
+        )
+        .with_client("alice")
+    }
+
+    fn engine() -> MatchingEngine {
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair("BTC"));
+        engine.add_new_market(pair("AAPL"));
+        engine
+    }
+
+    #[test]
+    fn test_precheck_counts_earlier_legs() {
+        let mut engine = engine();
+        engine.set_rate_limit(Some(RateLimit {
+            orders_per_second: dec!(0.001),
+            burst: dec!(2),
+        }));
+        let mut basket = Basket::new();
+        for price in [dec!(100), dec!(99), dec!(98)] {
+            let bid = leg(&mut engine, OrderType::Bid, dec!(1), price);
+            basket = basket.with_leg(pair("BTC"), bid);
+        }
+        let error = engine.place_basket(basket.clone()).unwrap_err();
+        assert!(error.starts_with(&format!("Leg 3: {}", RATE_LIMITED)));
+        assert!(engine.account_orders("alice").is_empty());
+
+        // The rejected basket took no tokens.
+        basket.legs.pop();
+        let results = engine.place_basket(basket).unwrap();
+        assert!(results.iter().all(Result::is_ok));
+        engine.set_rate_limit(None);
+
+        engine.set_client_limits(
+            "alice",
+            Some(RiskLimits {
+                max_open_orders: Some(3),
+                ..RiskLimits::default()
+            }),
+        );
+        let first = leg(&mut engine, OrderType::Bid, dec!(1), dec!(97));
+        let second = leg(&mut engine, OrderType::Bid, dec!(1), dec!(96));
+        let error = engine
+            .place_basket(
+                Basket::new()
+                    .with_leg(pair("BTC"), first)
+                    .with_leg(pair("BTC"), second),
+            )
+            .unwrap_err();
+        assert!(error.starts_with("Leg 2:"));
+        assert!(error.contains("Open orders"));
+        assert_eq!(engine.account_orders("alice").len(), 2);
+    }
+
+    #[test]
+    fn test_precheck_locates_short_legs_together() {
+        let mut engine = engine();
+        engine.set_borrow_check(Locates::new().with_available("AAPL", dec!(10)));
+        let first = leg(&mut engine, OrderType::Ask, dec!(6), dec!(150)).with_short_sale(true);
+        let second = leg(&mut engine, OrderType::Ask, dec!(6), dec!(151)).with_short_sale(true);
+        let error = engine
+            .place_basket(
+                Basket::new()
+                    .with_leg(pair("AAPL"), first)
+                    .with_leg(pair("AAPL"), second),
+            )
+            .unwrap_err();
+        assert_eq!(
+            error,
+            "Leg 2: No borrow available for short sale of 12 AAPL"
+        );
+        assert!(engine.account_orders("alice").is_empty());
+
+        // Nothing was borrowed for the rejected basket.
+        let ask = leg(&mut engine, OrderType::Ask, dec!(10), dec!(150)).with_short_sale(true);
+        let results = engine
+            .place_basket(Basket::new().with_leg(pair("AAPL"), ask))
+            .unwrap();
+        assert_eq!(results[0].as_ref().unwrap().0.status, OrderStatus::New);
+
+        let bid = leg(&mut engine, OrderType::Bid, dec!(1), dec!(150)).with_short_sale(true);
+        let error = engine
+            .place_basket(Basket::new().with_leg(pair("AAPL"), bid))
+            .unwrap_err();
+        assert_eq!(error, "Leg 1: Only sell orders can be marked short");
+    }
+
+    #[test]
+    fn test_placement_failure_by_mode() {
+        for contingent in [false, true] {
+            let mut engine = engine();
+            engine.set_borrow_check(NoBorrow);
+            let bid = leg(&mut engine, OrderType::Bid, dec!(1), dec!(100));
+            let ask = leg(&mut engine, OrderType::Ask, dec!(5), dec!(150)).with_short_sale(true);
+            let last = leg(&mut engine, OrderType::Bid, dec!(1), dec!(99));
+            let basket = Basket::new()
+                .with_leg(pair("BTC"), bid)
+                .with_leg(pair("AAPL"), ask)
+                .with_leg(pair("BTC"), last)
+                .with_contingent(contingent);
+
+            let results = engine.place_basket(basket).unwrap();
+            assert!(results[1].as_ref().unwrap_err().contains("No borrow"));
+            let (first, _) = results[0].as_ref().unwrap();
+            if contingent {
+                assert_eq!(first.status, OrderStatus::Cancelled);
+                assert_eq!(results[2], Err("Basket cancelled".to_string()));
+                assert!(engine.account_orders("alice").is_empty());
+            } else {
+                assert_eq!(first.status, OrderStatus::New);
+                assert!(results[2].is_ok());
+                assert_eq!(engine.account_orders("alice").len(), 2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unwind_collects_cancel_failures() {
+        let mut engine = engine();
+        engine.set_borrow_check(NoBorrow);
+        let resting = leg(&mut engine, OrderType::Bid, dec!(1), dec!(100));
+        let resting_id = resting.exchange_id;
+        let also_resting = leg(&mut engine, OrderType::Bid, dec!(1), dec!(90));
+        // Fills the first leg after it was placed, so the basket's copy of
+        // it is stale and cancelling it fails.
+        let crossing = leg(&mut engine, OrderType::Ask, dec!(1), dec!(100)).with_client("bob");
+        let short = leg(&mut engine, OrderType::Ask, dec!(5), dec!(150)).with_short_sale(true);
+        let basket = Basket::new()
+            .with_leg(pair("BTC"), resting)
+            .with_leg(pair("BTC"), also_resting)
+            .with_leg(pair("BTC"), crossing)
+            .with_leg(pair("AAPL"), short)
+            .with_contingent(true);
+
+        let results = engine.place_basket(basket).unwrap();
+        assert!(results[0]
+            .as_ref()
+            .unwrap_err()
+            .starts_with("Basket cancel failed:"));
+        assert_eq!(
+            results[1].as_ref().unwrap().0.status,
+            OrderStatus::Cancelled
+        );
+        let (crossing, fills) = results[2].as_ref().unwrap();
+        assert!(crossing.is_filled());
+        assert_eq!(fills[0].maker_id, resting_id);
+        assert!(results[3].is_err());
+        assert!(engine.account_orders("alice").is_empty());
+    }
+}
@@ -0,0 +1,453 @@
+use crate::limit_order_book::order::{Fill, OrderType};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Exit prices attached to an entry order. Once the entry fills, a
+/// take-profit limit order rests at `take_profit` and a stop at `stop_loss`
+/// is watched against trades; whichever executes first cancels the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bracket {
+    pub take_profit: Decimal,
+    pub stop_loss: Decimal,
+}
+
+impl Bracket {
+    pub fn new(take_profit: Decimal, stop_loss: Decimal) -> Self {
+        Self {
+            take_profit,
+            stop_loss,
+        }
+    }
+
+    /// The exits must sit on either side of the entry price: above it for
+    /// the take-profit of a buy, below it for its stop, and the other way
+    /// round for a sell.
+    pub fn validate(&self, side: OrderType, entry_price: Decimal) -> Result<(), String> {
+        let valid = match side {
+            OrderType::Bid => self.stop_loss < entry_price && entry_price < self.take_profit,
+            OrderType::Ask => self.take_profit < entry_price && entry_price < self.stop_loss,
+        };
+        if !valid {
+            return Err(format!(
+                "Bracket take-profit {} and stop-loss {} must sit either side of entry price {}",
+                self.take_profit, self.stop_loss, entry_price
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Lifecycle of a bracket, reported to the client that placed it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BracketEvent {
+    EntryFilled {
+        entry_id: u64,
+        client: String,
+        quantity: Decimal,
+    },
+    /// A take-profit now rests for the whole open position, replacing any
+    /// earlier one.
+    TakeProfitPlaced {
+        entry_id: u64,
+        client: String,
+        exchange_id: u64,
+        quantity: Decimal,
+    },
+    TakeProfitFilled {
+        entry_id: u64,
+        client: String,
+        quantity: Decimal,
+    },
+    StopTriggered {
+        entry_id: u64,
+        client: String,
+        price: Decimal,
+        quantity: Decimal,
+    },
+    ChildRejected {
+        entry_id: u64,
+        client: String,
+        reason: String,
+    },
+    /// The position is flat and nothing of the bracket is left working.
+    Closed { entry_id: u64, client: String },
+    /// The client cancelled the entry before any of it filled, or took
+    /// over the exit by cancelling the take-profit.
+    Cancelled { entry_id: u64, client: String },
+}
+
+/// Work for the engine after a batch of fills.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BracketAction {
+    /// Rest a take-profit for `quantity`, cancelling `replaces` first.
+    PlaceTakeProfit {
+        entry_id: u64,
+        client: String,
+        /// The entry's strategy, which its exits trade under.
+        strategy: Option<String>,
+        side: OrderType,
+        price: Decimal,
+        quantity: Decimal,
+        replaces: Option<u64>,
+    },
+    /// Cancel the orders in `cancel` and close `quantity` at market.
+    TriggerStop {
+        entry_id: u64,
+        client: String,
+        strategy: Option<String>,
+        side: OrderType,
+        quantity: Decimal,
+        cancel: Vec<u64>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Working {
+    client: String,
+    strategy: Option<String>,
+    side: OrderType,
+    bracket: Bracket,
+    entry_open: bool,
+    /// Entry quantity filled and not yet closed by the take-profit.
+    position: Decimal,
+    take_profit_id: Option<u64>,
+}
+
+impl Working {
+    fn exit_side(&self) -> OrderType {
+        match self.side {
+            OrderType::Bid => OrderType::Ask,
+            OrderType::Ask => OrderType::Bid,
+        }
+    }
+
+    fn stop_hit(&self, price: Decimal) -> bool {
+        match self.side {
+            OrderType::Bid => price <= self.bracket.stop_loss,
+            OrderType::Ask => price >= self.bracket.stop_loss,
+        }
+    }
+}
+
+/// The brackets working on one market, keyed by entry order.
+#[derive(Debug, Clone, Default)]
+pub struct Brackets {
+    working: BTreeMap<u64, Working>,
+}
+
+impl Brackets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.working.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.working.is_empty()
+    }
+
+    pub fn open(
+        &mut self,
+        entry_id: u64,
+        client: String,
+        strategy: Option<String>,
+        side: OrderType,
+        bracket: Bracket,
+    ) {
+        self.working.insert(
+            entry_id,
+            Working {
+                client,
+                strategy,
+                side,
+                bracket,
+                entry_open: true,
+                position: Decimal::ZERO,
+                take_profit_id: None,
+            },
+        );
+    }
+
+    /// Forgets a bracket whose entry never made it onto the book.
+    pub fn discard(&mut self, entry_id: u64) {
+        self.working.remove(&entry_id);
+    }
+
+    /// Records the ID the engine gave a take-profit before placing it, so
+    /// fills it takes on arrival are attributed to the bracket.
+    pub fn set_take_profit(&mut self, entry_id: u64, exchange_id: u64) {
+        if let Some(working) = self.working.get_mut(&entry_id) {
+            working.take_profit_id = Some(exchange_id);
+        }
+    }
+
+    /// Applies a batch of fills from one order, with `is_resting` telling
+    /// which orders are still on the book afterwards. Entry fills grow the
+    /// take-profit; take-profit fills shrink the position; any fill at or
+    /// through a stop triggers it.
+    pub fn on_fills(
+        &mut self,
+        fills: &[Fill],
+        is_resting: impl Fn(u64) -> bool,
+        events: &mut Vec<BracketEvent>,
+    ) -> Vec<BracketAction> {
+        let mut actions = Vec::new();
+        if fills.is_empty() {
+            return actions;
+        }
+        let mut closed = Vec::new();
+
+        for (&entry_id, working) in self.working.iter_mut() {
+            let client = &working.client;
+            let traded = |id: Option<u64>| -> Decimal {
+                fills
+                    .iter()
+                    .filter(|fill| Some(fill.maker_id) == id || Some(fill.taker_id) == id)
+                    .map(|fill| fill.quantity)
+                    .sum()
+            };
+
+            let entry_filled = traded(Some(entry_id));
+            if !entry_filled.is_zero() {
+                working.position += entry_filled;
+                working.entry_open = is_resting(entry_id);
+                events.push(BracketEvent::EntryFilled {
+                    entry_id,
+                    client: client.clone(),
+                    quantity: entry_filled,
+                });
+            }
+            let exited = traded(working.take_profit_id);
+            if !exited.is_zero() {
+                working.position -= exited;
+                events.push(BracketEvent::TakeProfitFilled {
+                    entry_id,
+                    client: client.clone(),
+                    quantity: exited,
+                });
+                if !working.take_profit_id.is_some_and(&is_resting) {
+                    working.take_profit_id = None;
+                }
+            }
+
+            let stop_price = fills
+                .iter()
+                .map(|fill| fill.price)
+                .find(|&price| working.stop_hit(price));
+            if let (Some(price), true) = (stop_price, working.position > Decimal::ZERO) {
+                events.push(BracketEvent::StopTriggered {
+                    entry_id,
+                    client: client.clone(),
+                    price,
+                    quantity: working.position,
+                });
+                let cancel = working
+                    .take_profit_id
+                    .into_iter()
+                    .chain(working.entry_open.then_some(entry_id))
+                    .collect();
+                actions.push(BracketAction::TriggerStop {
+                    entry_id,
+                    client: client.clone(),
+                    strategy: working.strategy.clone(),
+                    side: working.exit_side(),
+                    quantity: working.position,
+                    cancel,
+                });
+                closed.push(entry_id);
+            } else if !entry_filled.is_zero() {
+                actions.push(BracketAction::PlaceTakeProfit {
+                    entry_id,
+                    client: client.clone(),
+                    strategy: working.strategy.clone(),
+                    side: working.exit_side(),
+                    price: working.bracket.take_profit,
+                    quantity: working.position,
+                    replaces: working.take_profit_id,
+                });
+            } else if working.position.is_zero() && !working.entry_open {
+                closed.push(entry_id);
+            }
+        }
+
+        for entry_id in closed {
+            let working = self.working.remove(&entry_id).unwrap();
+            events.push(BracketEvent::Closed {
+                entry_id,
+                client: working.client,
+            });
+        }
+        actions
+    }
+
+    /// Handles a client cancel of a bracket's entry or take-profit. Returns
+    /// whether `exchange_id` belonged to a bracket.
+    pub fn on_cancel(&mut self, exchange_id: u64, events: &mut Vec<BracketEvent>) -> bool {
+        let found = self.working.iter_mut().find_map(|(&entry_id, working)| {
+            if entry_id == exchange_id {
+                working.entry_open = false;
+                Some((entry_id, working.position.is_zero()))
+            } else if working.take_profit_id == Some(exchange_id) {
+                Some((entry_id, true))
+            } else {
+                None
+            }
+        });
+        let Some((entry_id, done)) = found else {
+            return false;
+        };
+        if done {
+            let working = self.working.remove(&entry_id).unwrap();
+            events.push(BracketEvent::Cancelled {
+                entry_id,
+                client: working.client,
+            });
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn fill(maker_id: u64, taker_id: u64, price: Decimal, quantity: Decimal) -> Fill {
+        Fill {
+            maker_id,
+            taker_id,
+            price,
+            quantity,
+            ..Fill::default()
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let bracket = Bracket::new(dec!(110), dec!(95));
+        assert!(bracket.validate(OrderType::Bid, dec!(100)).is_ok());
+        assert!(bracket.validate(OrderType::Ask, dec!(100)).is_err());
+        assert!(Bracket::new(dec!(90), dec!(105))
+            .validate(OrderType::Ask, dec!(100))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_take_profit_follows_entry_fills() {
+        let mut brackets = Brackets::new();
+        let mut events = Vec::new();
+        brackets.open(
+            1,
+            "alice".to_string(),
+            None,
+            OrderType::Bid,
+            Bracket::new(dec!(110), dec!(95)),
+        );
+
+        let actions =
+            brackets.on_fills(&[fill(1, 7, dec!(100), dec!(2))], |id| id == 1, &mut events);
+        assert_eq!(
+            actions,
+            vec![BracketAction::PlaceTakeProfit {
+                entry_id: 1,
+                client: "alice".to_string(),
+                strategy: None,
+                side: OrderType::Ask,
+                price: dec!(110),
+                quantity: dec!(2),
+                replaces: None,
+            }]
+        );
+        brackets.set_take_profit(1, 10);
+
+        // The rest of the entry fills: the take-profit is resized.
+        let actions = brackets.on_fills(
+            &[fill(1, 8, dec!(100), dec!(3))],
+            |id| id == 10,
+            &mut events,
+        );
+        assert!(matches!(
+            actions[..],
+            [BracketAction::PlaceTakeProfit {
+                quantity,
+                replaces: Some(10),
+                ..
+            }] if quantity == dec!(5)
+        ));
+        brackets.set_take_profit(1, 11);
+
+        // The take-profit fills in two parts and the bracket closes.
+        brackets.on_fills(
+            &[fill(11, 9, dec!(110), dec!(4))],
+            |id| id == 11,
+            &mut events,
+        );
+        assert_eq!(brackets.len(), 1);
+        brackets.on_fills(&[fill(11, 12, dec!(110), dec!(1))], |_| false, &mut events);
+        assert!(brackets.is_empty());
+        assert_eq!(
+            events.last(),
+            Some(&BracketEvent::Closed {
+                entry_id: 1,
+                client: "alice".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_stop_cancels_take_profit() {
+        let mut brackets = Brackets::new();
+        let mut events = Vec::new();
+        brackets.open(
+            1,
+            "alice".to_string(),
+            None,
+            OrderType::Ask,
+            Bracket::new(dec!(90), dec!(105)),
+        );
+        brackets.on_fills(&[fill(1, 7, dec!(100), dec!(2))], |id| id == 1, &mut events);
+        brackets.set_take_profit(1, 10);
+
+        // Trades elsewhere below the stop do nothing.
+        assert!(brackets
+            .on_fills(&[fill(3, 4, dec!(104), dec!(1))], |_| true, &mut events)
+            .is_empty());
+
+        let actions = brackets.on_fills(&[fill(3, 4, dec!(106), dec!(1))], |_| true, &mut events);
+        assert_eq!(
+            actions,
+            vec![BracketAction::TriggerStop {
+                entry_id: 1,
+                client: "alice".to_string(),
+                strategy: None,
+                side: OrderType::Bid,
+                quantity: dec!(2),
+                cancel: vec![10, 1],
+            }]
+        );
+        assert!(brackets.is_empty());
+    }
+
+    #[test]
+    fn test_cancel_unfilled_entry() {
+        let mut brackets = Brackets::new();
+        let mut events = Vec::new();
+        brackets.open(
+            1,
+            "alice".to_string(),
+            None,
+            OrderType::Bid,
+            Bracket::new(dec!(110), dec!(95)),
+        );
+        assert!(!brackets.on_cancel(2, &mut events));
+        assert!(brackets.on_cancel(1, &mut events));
+        assert!(brackets.is_empty());
+        assert!(matches!(
+            events[..],
+            [BracketEvent::Cancelled { entry_id: 1, .. }]
+        ));
+    }
+}
@@ -0,0 +1,294 @@
+//! When a market trades: a daily session, in local time at a fixed offset
+//! from UTC, on every day but the weekend and holidays.
+//!
+//! ```toml
+//! [markets.calendar]
+//! session = { open = "09:30:00", close = "16:00:00", utc_offset = "-05:00" }
+//! weekend = ["Sat", "Sun"]
+//! holidays = ["2024-12-25", "2025-01-01"]
+//! ```
+//!
+//! A bare session, as markets configured before they had calendars, is a
+//! calendar that trades every day.
+//!
+//! A close earlier than the open makes an overnight session, from the open
+//! the evening before a trading day to the close on it. The offset is
+//! fixed, so a market that observes daylight saving needs its calendar
+//! changed when the clocks do.
+
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveTime, TimeZone, Utc, Weekday,
+};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeSet;
+
+/// The daily trading hours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Session {
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+    #[serde(default = "utc", deserialize_with = "utc_offset")]
+    pub utc_offset: FixedOffset,
+}
+
+fn utc() -> FixedOffset {
+    FixedOffset::east_opt(0).unwrap()
+}
+
+/// Reads offsets written like `+09:00` or `-05:30`.
+fn utc_offset<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FixedOffset, D::Error> {
+    let offset = String::deserialize(deserializer)?;
+    offset.parse().map_err(serde::de::Error::custom)
+}
+
+impl Session {
+    pub fn new(open: NaiveTime, close: NaiveTime) -> Self {
+        Self {
+            open,
+            close,
+            utc_offset: utc(),
+        }
+    }
+
+    pub fn with_utc_offset(mut self, utc_offset: FixedOffset) -> Self {
+        self.utc_offset = utc_offset;
+        self
+    }
+
+    /// The open and close of the session that closes on `date`.
+    pub fn on(&self, date: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+        let open_date = if self.open < self.close {
+            date
+        } else {
+            date - Duration::days(1)
+        };
+        (
+            self.at(open_date.and_time(self.open)),
+            self.at(date.and_time(self.close)),
+        )
+    }
+
+    /// The local date of `time`.
+    fn date(&self, time: DateTime<Utc>) -> NaiveDate {
+        time.with_timezone(&self.utc_offset).date_naive()
+    }
+
+    fn at(&self, local: chrono::NaiveDateTime) -> DateTime<Utc> {
+        // A fixed offset maps every local time to exactly one instant.
+        self.utc_offset
+            .from_local_datetime(&local)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "CalendarConfig")]
+pub struct Calendar {
+    pub session: Session,
+    /// Days of the week without a session.
+    pub weekend: Vec<Weekday>,
+    /// Local dates without a session.
+    pub holidays: BTreeSet<NaiveDate>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CalendarConfig {
+    Calendar(CalendarFields),
+    Session(Session),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CalendarFields {
+    session: Session,
+    #[serde(default)]
+    weekend: Vec<Weekday>,
+    #[serde(default)]
+    holidays: BTreeSet<NaiveDate>,
+}
+
+impl From<CalendarConfig> for Calendar {
+    fn from(config: CalendarConfig) -> Self {
+        match config {
+            CalendarConfig::Calendar(fields) => Self {
+                session: fields.session,
+                weekend: fields.weekend,
+                holidays: fields.holidays,
+            },
+            CalendarConfig::Session(session) => Self::new(session),
+        }
+    }
+}
+
+impl Calendar {
+    /// A session every day.
+    pub fn new(session: Session) -> Self {
+        Self {
+            session,
+            weekend: Vec::new(),
+            holidays: BTreeSet::new(),
+        }
+    }
+
+    pub fn with_weekend(mut self, weekend: impl IntoIterator<Item = Weekday>) -> Self {
+        self.weekend = weekend.into_iter().collect();
+        self
+    }
+
+    pub fn with_holiday(mut self, date: NaiveDate) -> Self {
+        self.holidays.insert(date);
+        self
+    }
+
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        !self.weekend.contains(&date.weekday()) && !self.holidays.contains(&date)
+    }
+
+    /// Whether `now` falls inside a session.
+    pub fn is_trading(&self, now: DateTime<Utc>) -> bool {
+        // Only the session closing today or, overnight, tomorrow can be on.
+        let today = self.session.date(now);
+        [today, today + Duration::days(1)].into_iter().any(|date| {
+            let (open, close) = self.session.on(date);
+            self.is_trading_day(date) && open <= now && now < close
+        })
+    }
+
+    /// The first open at or after `now`. None if the weekend is the whole
+    /// week.
+    pub fn next_open(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.trading_days(self.session.date(now), 1)
+            .map(|date| self.session.on(date).0)
+            .find(|open| *open >= now)
+    }
+
+    /// The first close after `now`.
+    pub fn next_close(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.trading_days(self.session.date(now), 1)
+            .map(|date| self.session.on(date).1)
+            .find(|close| *close > now)
+    }
+
+    /// The latest close at or before `now`.
+    pub fn last_close(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.trading_days(self.session.date(now), -1)
+            .map(|date| self.session.on(date).1)
+            .find(|close| *close <= now)
+    }
+
+    /// Trading days from `start` a day at a time in `direction`, as far as
+    /// the next trading day can be: past a week of weekends and every
+    /// holiday, plus the day before or after for an overnight session.
+    fn trading_days(
+        &self,
+        start: NaiveDate,
+        direction: i64,
+    ) -> impl Iterator<Item = NaiveDate> + '_ {
+        let span = 8 + self.holidays.len() as i64;
+        (0..=span)
+            .map(move |days| start + Duration::days(days * direction))
+            .filter(|date| self.is_trading_day(*date))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MarketConfig;
+
+    #[test]
+    fn test_session_boundaries() {
+        // A market configured with a bare session trades every day.
+        let market: MarketConfig = toml::from_str(
+            r#"
+            pair = "BTC/USDT"
+
+            [session]
+            open = "09:30:00"
+            close = "16:00:00"
+            utc_offset = "-05:00"
+            "#,
+        )
+        .unwrap();
+        let calendar = market.calendar.unwrap();
+        assert!(calendar.weekend.is_empty() && calendar.holidays.is_empty());
+        // 16:00 in New York in winter is 21:00 UTC.
+        let close = Utc.with_ymd_and_hms(2024, 1, 2, 21, 0, 0).unwrap();
+        let morning = Utc.with_ymd_and_hms(2024, 1, 2, 15, 0, 0).unwrap();
+        assert_eq!(calendar.next_close(morning), Some(close));
+        assert_eq!(
+            calendar.last_close(morning),
+            Some(close - Duration::days(1))
+        );
+        assert_eq!(calendar.last_close(close), Some(close));
+        assert!(calendar.is_trading(morning));
+        assert!(!calendar.is_trading(close));
+
+        // Open overnight, 18:00 to 17:00 UTC.
+        let overnight = Calendar::new(Session::new(
+            NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        ));
+        let evening = Utc.with_ymd_and_hms(2024, 1, 2, 19, 0, 0).unwrap();
+        assert!(overnight.is_trading(evening));
+        assert!(!overnight.is_trading(evening - Duration::hours(1) - Duration::minutes(30)));
+        assert_eq!(
+            overnight.next_close(evening),
+            Some(Utc.with_ymd_and_hms(2024, 1, 3, 17, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_weekends_and_holidays() {
+        let calendar: Calendar = toml::from_str(
+            r#"
+            session = { open = "09:30:00", close = "16:00:00", utc_offset = "-05:00" }
+            weekend = ["Sat", "Sun"]
+            holidays = ["2024-12-25"]
+            "#,
+        )
+        .unwrap();
+        // 09:30 and 16:00 in New York in winter are 14:30 and 21:00 UTC.
+        let utc = |day, hour, minute| {
+            Utc.with_ymd_and_hms(2024, 12, day, hour, minute, 0)
+                .unwrap()
+        };
+        let monday = utc(23, 15, 0);
+        assert!(calendar.is_trading(monday));
+        assert!(!calendar.is_trading(utc(23, 21, 0)));
+        assert_eq!(calendar.last_close(monday), Some(utc(20, 21, 0)));
+        assert_eq!(calendar.next_close(monday), Some(utc(23, 21, 0)));
+        // Christmas is skipped.
+        assert_eq!(calendar.next_open(utc(23, 21, 0)), Some(utc(24, 14, 30)));
+        assert_eq!(calendar.next_open(utc(24, 22, 0)), Some(utc(26, 14, 30)));
+        assert!(!calendar.is_trading(utc(25, 15, 0)));
+        // So is the weekend.
+        assert_eq!(calendar.next_open(utc(27, 22, 0)), Some(utc(30, 14, 30)));
+        assert_eq!(calendar.last_close(utc(29, 12, 0)), Some(utc(27, 21, 0)));
+
+        // Open overnight, Sunday 18:00 to Friday 17:00 UTC.
+        let overnight = Calendar::new(Session::new(
+            NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        ))
+        .with_weekend([Weekday::Sat, Weekday::Sun]);
+        assert!(overnight.is_trading(utc(29, 19, 0)));
+        assert!(!overnight.is_trading(utc(27, 17, 30)));
+        assert!(!overnight.is_trading(utc(28, 19, 0)));
+        assert_eq!(overnight.next_open(utc(27, 17, 30)), Some(utc(29, 18, 0)));
+
+        let closed = Calendar::new(calendar.session).with_weekend([
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+            Weekday::Sat,
+            Weekday::Sun,
+        ]);
+        assert_eq!(closed.next_open(monday), None);
+    }
+}
@@ -0,0 +1,45 @@
+//! Engine commands as values, for queueing, routing and batching them.
+
+use crate::{
+    limit_order_book::order::{Fill, Order},
+    matching_engine::{
+        engine::{MatchingEngine, TradingPair},
+        quote::{Quote, QuoteReport},
+    },
+};
+
+/// A command for the engine, from a strategy or in a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineCommand {
+    Limit { pair: TradingPair, order: Order },
+    Market { pair: TradingPair, order: Order },
+    Cancel { pair: TradingPair, exchange_id: u64 },
+    Quote { pair: TradingPair, quote: Quote },
+}
+
+impl EngineCommand {
+    pub fn apply(self, engine: &mut MatchingEngine) -> Result<CommandOutcome, String> {
+        match self {
+            EngineCommand::Limit { pair, order } => engine
+                .place_limit_order(pair, order)
+                .map(|(order, fills)| CommandOutcome::Placed { order, fills }),
+            EngineCommand::Market { pair, order } => engine
+                .execute_market_order(pair, order)
+                .map(|fills| CommandOutcome::Executed { fills }),
+            EngineCommand::Cancel { pair, exchange_id } => engine
+                .cancel_order(&pair, exchange_id)
+                .map(|order| CommandOutcome::Cancelled { order }),
+            EngineCommand::Quote { pair, quote } => engine
+                .place_quote(pair, quote)
+                .map(|report| CommandOutcome::Quoted(Box::new(report))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandOutcome {
+    Placed { order: Order, fills: Vec<Fill> },
+    Executed { fills: Vec<Fill> },
+    Cancelled { order: Order },
+    Quoted(Box<QuoteReport>),
+}
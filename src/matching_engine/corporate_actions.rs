@@ -0,0 +1,227 @@
+//! Splits and dividends for equity markets, applied on their ex-date.
+
+use crate::{
+    limit_order_book::order::{Order, OrderType},
+    matching_engine::{accounts::AccountId, engine::TradingPair},
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CorporateActionKind {
+    /// New shares per old share: 4 for a 4-for-1 split, 0.1 for a 1-for-10
+    /// reverse split. Quantities are multiplied by it and prices divided.
+    Split(Decimal),
+    /// Cash per share, paid to longs and charged to shorts. Prices drop by
+    /// it; resting sells are left alone.
+    Dividend(Decimal),
+}
+
+impl CorporateActionKind {
+    /// A price as it stands once the action has gone ex.
+    pub fn adjust_price(&self, price: Decimal) -> Decimal {
+        match self {
+            CorporateActionKind::Split(ratio) => price / ratio,
+            CorporateActionKind::Dividend(amount) => price - amount,
+        }
+    }
+
+    /// A quantity as it stands once the action has gone ex.
+    pub fn adjust_quantity(&self, quantity: Decimal) -> Decimal {
+        match self {
+            CorporateActionKind::Split(ratio) => quantity * ratio,
+            CorporateActionKind::Dividend(_) => quantity,
+        }
+    }
+
+    /// Adjusts a resting order, rounding its price to `tick_size` away from
+    /// the opposite side and its quantity down to `lot_size`. Returns false
+    /// if it's left with nothing to rest, or no positive price.
+    pub fn adjust_order(
+        &self,
+        order: &mut Order,
+        tick_size: Option<Decimal>,
+        lot_size: Option<Decimal>,
+    ) -> bool {
+        if matches!(self, CorporateActionKind::Dividend(_)) && order.order_type == OrderType::Ask {
+            return true;
+        }
+        let price = self.adjust_price(order.limit_price);
+        order.limit_price = match (order.order_type, tick_size) {
+            (OrderType::Bid, Some(tick)) => (price / tick).floor() * tick,
+            (OrderType::Ask, Some(tick)) => (price / tick).ceil() * tick,
+            (_, None) => price,
+        };
+        let remaining = self.adjust_quantity(order.remaining_quantity);
+        let remaining = lot_size.map_or(remaining, |lot| (remaining / lot).floor() * lot);
+        order.filled_quantity = self.adjust_quantity(order.filled_quantity);
+        order.remaining_quantity = remaining;
+        order.shares = order.filled_quantity + remaining;
+        remaining > Decimal::ZERO && order.limit_price > Decimal::ZERO
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            CorporateActionKind::Split(ratio) if *ratio <= Decimal::ZERO => {
+                Err(format!("Invalid split ratio: {}", ratio))
+            }
+            CorporateActionKind::Dividend(amount) if *amount <= Decimal::ZERO => {
+                Err(format!("Invalid dividend: {}", amount))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// ```toml
+/// [[corporate_actions]]
+/// pair = "AAPL/USD"
+/// ex_date = "2024-08-12"
+/// action = { split = "4" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CorporateAction {
+    pub pair: TradingPair,
+    /// The first day the market trades without the split or dividend.
+    pub ex_date: NaiveDate,
+    pub action: CorporateActionKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CorporateActionEvent {
+    /// The action went ex; the events for its orders and positions follow.
+    Applied {
+        action: CorporateAction,
+        time: DateTime<Utc>,
+    },
+    /// A resting order re-priced or re-sized in place, keeping its priority.
+    OrderAdjusted {
+        pair: TradingPair,
+        order: Order,
+        time: DateTime<Utc>,
+    },
+    /// A resting order cancelled because it had less than a lot, or no
+    /// positive price, left after adjusting.
+    OrderCancelled {
+        pair: TradingPair,
+        order: Order,
+        time: DateTime<Utc>,
+    },
+    /// An account's position after the action, with the dividend it was
+    /// paid; negative when it paid one on a short.
+    PositionAdjusted {
+        account: AccountId,
+        pair: TradingPair,
+        position: Decimal,
+        dividend: Decimal,
+        time: DateTime<Utc>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_order_adjustments() {
+        let order = |order_type, shares, price| {
+            Order::new(
+                "AAPL/USD".to_string(),
+                1,
+                order_type,
+                shares,
+                price,
+                Utc::now(),
+                Utc::now(),
+            )
+        };
+        let split = CorporateActionKind::Split(dec!(3));
+        let mut bid = order(OrderType::Bid, dec!(10), dec!(100));
+        bid.fill(dec!(4)).unwrap();
+        assert!(split.adjust_order(&mut bid, Some(dec!(0.01)), None));
+        assert_eq!(bid.limit_price, dec!(33.33));
+        assert_eq!(
+            (bid.shares, bid.filled_quantity, bid.remaining_quantity),
+            (dec!(30), dec!(12), dec!(18))
+        );
+        let mut ask = order(OrderType::Ask, dec!(10), dec!(100));
+        assert!(split.adjust_order(&mut ask, Some(dec!(0.01)), None));
+        assert_eq!(ask.limit_price, dec!(33.34));
+
+        let reverse = CorporateActionKind::Split(dec!(0.1));
+        let mut small = order(OrderType::Bid, dec!(5), dec!(10));
+        assert!(!reverse.adjust_order(&mut small, None, Some(dec!(1))));
+
+        let dividend = CorporateActionKind::Dividend(dec!(0.5));
+        let mut ask = order(OrderType::Ask, dec!(10), dec!(100));
+        assert!(dividend.adjust_order(&mut ask, None, None));
+        assert_eq!(ask.limit_price, dec!(100));
+        let mut bid = order(OrderType::Bid, dec!(10), dec!(100));
+        assert!(dividend.adjust_order(&mut bid, None, None));
+        assert_eq!(bid.limit_price, dec!(99.5));
+
+        assert!(CorporateActionKind::Split(dec!(0)).validate().is_err());
+        assert!(CorporateActionKind::Dividend(dec!(-1)).validate().is_err());
+    }
+
+    #[derive(Deserialize)]
+    struct Config {
+        corporate_actions: Vec<CorporateAction>,
+    }
+
+    #[test]
+    fn test_config_and_dividends_below_zero() {
+        let config: Config = toml::from_str(
+            r#"
+            [[corporate_actions]]
+            pair = "AAPL/USD"
+            ex_date = "2024-08-12"
+            action = { split = "4" }
+
+            [[corporate_actions]]
+            pair = "AAPL/USD"
+            ex_date = "2024-11-08"
+            action = { dividend = "0.25" }
+            "#,
+        )
+        .unwrap();
+        let actions = config.corporate_actions;
+        assert_eq!(actions[0].action, CorporateActionKind::Split(dec!(4)));
+        assert_eq!(
+            actions[1].ex_date,
+            NaiveDate::from_ymd_opt(2024, 11, 8).unwrap()
+        );
+        assert!(actions
+            .iter()
+            .all(|action| action.action.validate().is_ok()));
+        assert!(toml::from_str::<Config>(
+            r#"
+            [[corporate_actions]]
+            pair = "AAPL/USD"
+            ex_date = "2024-08-12"
+            action = { merger = "1" }
+            "#
+        )
+        .is_err());
+
+        let dividend = actions[1].action;
+        assert_eq!(dividend.adjust_quantity(dec!(7)), dec!(7));
+        // A bid at or under the dividend is left with no positive price.
+        let mut bid = Order::new(
+            "AAPL/USD".to_string(),
+            1,
+            OrderType::Bid,
+            dec!(10),
+            dec!(0.25),
+            Utc::now(),
+            Utc::now(),
+        );
+        assert!(!dividend.adjust_order(&mut bid, Some(dec!(0.01)), None));
+        assert_eq!(bid.limit_price, dec!(0));
+        assert_eq!(bid.remaining_quantity, dec!(10));
+    }
+}
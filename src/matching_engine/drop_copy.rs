@@ -0,0 +1,275 @@
+use crate::{
+    limit_order_book::order::{Fill, Order, OrderType},
+    matching_engine::{engine::TradingPair, fees::Liquidity},
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::sync::mpsc;
+
+/// A copy of one execution or cancel, for whoever oversees every client
+/// rather than trading as one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DropCopy {
+    /// One side of a trade. Each fill is reported twice, once for the maker
+    /// and once for the taker.
+    Execution {
+        pair: TradingPair,
+        exchange_id: u64,
+        client: String,
+        /// The order's strategy, if it was tagged with one.
+        strategy: Option<String>,
+        side: OrderType,
+        liquidity: Liquidity,
+        price: Decimal,
+        quantity: Decimal,
+        /// Quantity the order still has open after this fill.
+        leaves_quantity: Decimal,
+        fee: Decimal,
+        time: DateTime<Utc>,
+    },
+    /// A resting order taken off the book, by its client or by the engine.
+    Cancel {
+        pair: TradingPair,
+        exchange_id: u64,
+        client: String,
+        strategy: Option<String>,
+        side: OrderType,
+        price: Decimal,
+        cancelled_quantity: Decimal,
+    },
+}
+
+impl DropCopy {
+    pub fn client(&self) -> &str {
+        match self {
+            DropCopy::Execution { client, .. } | DropCopy::Cancel { client, .. } => client,
+        }
+    }
+
+    /// The maker and taker executions for `fills` of `taker`, in fill order.
+    /// `taker_leaves` is what the taker had open before the first fill and
+    /// `maker_leaves` looks up what a maker has left afterwards.
+    pub fn executions(
+        pair: &TradingPair,
+        taker: &Order,
+        mut taker_leaves: Decimal,
+        fills: &[Fill],
+        maker_leaves: impl Fn(u64) -> Decimal,
+    ) -> Vec<DropCopy> {
+        let maker_side = match taker.order_type {
+            OrderType::Bid => OrderType::Ask,
+            OrderType::Ask => OrderType::Bid,
+        };
+        let mut reports = Vec::with_capacity(fills.len() * 2);
+        for fill in fills {
+            taker_leaves -= fill.quantity;
+            reports.push(DropCopy::Execution {
+                pair: pair.clone(),
+                exchange_id: fill.maker_id,
+                client: fill.maker_client.clone(),
+                strategy: fill.maker_strategy.clone(),
+                side: maker_side,
+                liquidity: Liquidity::Maker,
+                price: fill.price,
+                quantity: fill.quantity,
+                leaves_quantity: maker_leaves(fill.maker_id),
+                fee: fill.maker_fee,
+                time: taker.event_time,
+            });
+            reports.push(DropCopy::Execution {
+                pair: pair.clone(),
+                exchange_id: taker.exchange_id,
+                client: taker.client.clone(),
+                strategy: taker.strategy.clone(),
+                side: taker.order_type,
+                liquidity: Liquidity::Taker,
+                price: fill.price,
+                quantity: fill.quantity,
+                leaves_quantity: taker_leaves,
+                fee: fill.taker_fee,
+                time: taker.event_time,
+            });
+        }
+        reports
+    }
+
+    /// The cancel of `order`, as it stood just before leaving the book.
+    pub fn cancel(pair: &TradingPair, order: &Order, cancelled_quantity: Decimal) -> DropCopy {
+        DropCopy::Cancel {
+            pair: pair.clone(),
+            exchange_id: order.exchange_id,
+            client: order.client.clone(),
+            strategy: order.strategy.clone(),
+            side: order.order_type,
+            price: order.limit_price,
+            cancelled_quantity,
+        }
+    }
+}
+
+/// Sends every `DropCopy` to a single designated consumer. Nothing is
+/// buffered while no one is subscribed.
+#[derive(Debug, Default)]
+pub struct DropCopyFeed {
+    consumer: Option<mpsc::Sender<DropCopy>>,
+    /// Reports kept back between `hold` and `release`.
+    held: Option<Vec<DropCopy>>,
+}
+
+impl DropCopyFeed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the returned receiver the consumer, replacing any earlier one.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<DropCopy> {
+        let (sender, receiver) = mpsc::channel();
+        self.consumer = Some(sender);
+        receiver
+    }
+
+    pub fn is_subscribed(&self) -> bool {
+        self.consumer.is_some()
+    }
+
+    /// Sends `reports` in order, unsubscribing the consumer once it has
+    /// dropped its receiver.
+    pub fn publish(&mut self, reports: impl IntoIterator<Item = DropCopy>) {
+        let Some(consumer) = &self.consumer else {
+            return;
+        };
+        if let Some(held) = &mut self.held {
+            held.extend(reports);
+            return;
+        }
+        for report in reports {
+            if consumer.send(report).is_err() {
+                self.consumer = None;
+                return;
+            }
+        }
+    }
+
+    /// Keeps reports back until `release`, to send a batch's together.
+    pub fn hold(&mut self) {
+        self.held.get_or_insert_with(Vec::new);
+    }
+
+    /// Sends the reports kept back since `hold`.
+    pub fn release(&mut self) {
+        if let Some(held) = self.held.take() {
+            self.publish(held);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn pair() -> TradingPair {
+        TradingPair::new("BTC".to_string(), "USDT".to_string())
+    }
+
+    fn cancel(exchange_id: u64) -> DropCopy {
+        let order = Order::new(
+            "BTC/USDT".to_string(),
+            exchange_id,
+            OrderType::Bid,
+            dec!(1),
+            dec!(100),
+            Utc::now(),
+            Utc::now(),
+        );
+        DropCopy::cancel(&pair(), &order, dec!(1))
+    }
+
+    #[test]
+    fn test_executions() {
+        let taker = Order::new(
+            "BTC/USDT".to_string(),
+            3,
+            OrderType::Ask,
+            dec!(3),
+            dec!(99),
+            Utc::now(),
+            Utc::now(),
+        )
+        .with_client("taker")
+        .with_strategy("arb");
+        let fills = [1, 2].map(|maker_id| Fill {
+            maker_id,
+            taker_id: 3,
+            price: dec!(100),
+            quantity: dec!(1),
+            maker_client: format!("maker-{}", maker_id),
+            maker_fee: dec!(-0.01),
+            taker_fee: dec!(0.02),
+            ..Fill::default()
+        });
+
+        let reports = DropCopy::executions(&pair(), &taker, dec!(3), &fills, |_| dec!(4));
+        assert_eq!(reports.len(), 4);
+        let clients: Vec<&str> = reports.iter().map(DropCopy::client).collect();
+        assert_eq!(clients, vec!["maker-1", "taker", "maker-2", "taker"]);
+        assert_eq!(
+            reports[1],
+            DropCopy::Execution {
+                pair: pair(),
+                exchange_id: 3,
+                client: "taker".to_string(),
+                strategy: Some("arb".to_string()),
+                side: OrderType::Ask,
+                liquidity: Liquidity::Taker,
+                price: dec!(100),
+                quantity: dec!(1),
+                leaves_quantity: dec!(2),
+                fee: dec!(0.02),
+                time: taker.event_time,
+            }
+        );
+        let DropCopy::Execution {
+            side,
+            liquidity,
+            leaves_quantity,
+            fee,
+            ..
+        } = &reports[2]
+        else {
+            panic!("expected an execution");
+        };
+        assert_eq!(
+            (*side, *liquidity, *leaves_quantity, *fee),
+            (OrderType::Bid, Liquidity::Maker, dec!(4), dec!(-0.01))
+        );
+        assert!(matches!(
+            reports[3],
+            DropCopy::Execution {
+                leaves_quantity,
+                ..
+            } if leaves_quantity == dec!(1)
+        ));
+    }
+
+    #[test]
+    fn test_feed_holds_and_unsubscribes() {
+        let mut feed = DropCopyFeed::new();
+        // Nothing is kept for a consumer that hasn't subscribed yet.
+        feed.publish([cancel(1)]);
+        let receiver = feed.subscribe();
+        assert!(feed.is_subscribed());
+
+        feed.hold();
+        feed.publish([cancel(2)]);
+        feed.publish([cancel(3)]);
+        assert!(receiver.try_recv().is_err());
+        feed.release();
+        let received: Vec<DropCopy> = receiver.try_iter().collect();
+        assert_eq!(received, vec![cancel(2), cancel(3)]);
+
+        drop(receiver);
+        feed.publish([cancel(4)]);
+        assert!(!feed.is_subscribed());
+    }
+}
@@ -1,8 +1,61 @@
-use super::orderbook::{Order, OrderBook};
-use rust_decimal::prelude::*;
-use std::collections::HashMap;
+use crate::{
+    config::{ConfigError, EngineConfig, FeedConfig, MarketConfig, PersistenceConfig, RiskLimits},
+    instruments::Instruments,
+    limit_order_book::{
+        arithmetic,
+        hooks::MatchHook,
+        l3::{L3Event, L3Feed, L3Privacy},
+        order::{Fill, LimitOrderBook, Order, OrderStatus, OrderType, TimeInForce},
+        stats::{self, BookStats},
+        view::BookView,
+    },
+    matching_engine::{
+        accounts::{AccountId, Accounts},
+        adl::{self, AdlCandidate},
+        audit::{AuditEntry, AuditLog, CancelReason},
+        bands::{MarketEvent, MarketState},
+        basket::{Basket, LegResult},
+        bracket::{Bracket, BracketAction, BracketEvent, Brackets},
+        command::{CommandOutcome, EngineCommand},
+        corporate_actions::{CorporateAction, CorporateActionEvent, CorporateActionKind},
+        drop_copy::{DropCopy, DropCopyFeed},
+        fees::{FeeLedger, Liquidity},
+        funding::{Funding, FundingEvent},
+        insurance::{InsuranceFund, LossFallback},
+        liquidation::{LiquidationEvent, MarginConfig, MarginStatus},
+        midpoint::{mid_price, MidpointPool},
+        peg::{Peg, PeggedOrders},
+        portfolio::{Portfolio, PortfolioReport},
+        positions::Positions,
+        pricing::Pricing,
+        quote::{Quote, QuoteIds, QuoteReport, QuoteSide},
+        rate_limit::{RateLimit, RateLimiter},
+        rates::Rates,
+        risk::{BorrowCheck, Exposure, RiskEvent},
+        settlement::{SettlementRecord, SettlementReport},
+        snapshot::{EngineSnapshot, MarketSnapshot},
+        state_hash::{self, StateHasher},
+        ticker::{Ticker, TickerStats},
+        validation::{self, OrderEntry, OrderKind, OrderValidator, Rejection},
+    },
+    metrics::{Metrics, Stopwatch},
+};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::Path,
+    rc::Rc,
+    sync::{mpsc, Arc},
+};
+use tracing::{debug, info, info_span, warn};
 
-#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+/// A market's base and quote asset, written "BTC/USDT". Both are kept in
+/// upper case, so "btc/usdt" names the same market.
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub struct TradingPair {
     base: String,
     quote: String,
@@ -10,45 +63,4753 @@ pub struct TradingPair {
 
 impl TradingPair {
     pub fn new(base: String, quote: String) -> TradingPair {
-        TradingPair { base, quote }
+        TradingPair {
+            base: base.to_ascii_uppercase(),
+            quote: quote.to_ascii_uppercase(),
+        }
+    }
+
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    pub fn quote(&self) -> &str {
+        &self.quote
+    }
+}
+
+impl fmt::Display for TradingPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.base, self.quote)
+    }
+}
+
+/// An asset code: letters, digits and `-`, `_` or `.`, as in "BTC-PERP".
+fn is_asset(asset: &str) -> bool {
+    !asset.is_empty()
+        && asset
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+impl std::str::FromStr for TradingPair {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once('/') {
+            Some((base, quote)) if is_asset(base) && is_asset(quote) => {
+                Ok(TradingPair::new(base.to_string(), quote.to_string()))
+            }
+            _ => Err(format!("Invalid trading pair: {:?}", s)),
+        }
     }
+}
+
+impl TryFrom<String> for TradingPair {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
 
-    pub fn to_string(&self) -> String {
-        format!("{}/{}", self.base, self.quote)
+impl From<TradingPair> for String {
+    fn from(pair: TradingPair) -> String {
+        pair.to_string()
     }
 }
 
 pub struct MatchingEngine {
-    orderbooks: HashMap<TradingPair, OrderBook>,
+    orderbooks: HashMap<TradingPair, LimitOrderBook>,
+    market_configs: HashMap<TradingPair, MarketConfig>,
+    market_states: HashMap<TradingPair, MarketState>,
+    market_events: Vec<MarketEvent>,
+    tickers: HashMap<TradingPair, Ticker>,
+    validators: HashMap<TradingPair, Vec<Rc<dyn OrderValidator>>>,
+    risk_limits: RiskLimits,
+    accounts: Accounts,
+    instruments: Instruments,
+    rate_limiter: Option<RateLimiter>,
+    client_limits: HashMap<String, RiskLimits>,
+    killed_clients: HashSet<String>,
+    risk_events: Vec<RiskEvent>,
+    persistence: PersistenceConfig,
+    feed: FeedConfig,
+    l3_feeds: HashMap<TradingPair, L3Feed>,
+    fee_ledger: FeeLedger,
+    positions: Positions,
+    portfolio: Portfolio,
+    rates: Rates,
+    pricing: Pricing,
+    funding: HashMap<TradingPair, Funding>,
+    funding_events: Vec<FundingEvent>,
+    borrow_check: Option<Box<dyn BorrowCheck>>,
+    pegs: HashMap<TradingPair, PeggedOrders>,
+    midpoint_pools: HashMap<TradingPair, MidpointPool>,
+    margin: Option<MarginConfig>,
+    insurance: InsuranceFund,
+    liquidation_events: Vec<LiquidationEvent>,
+    /// Set while a liquidation runs, so its own orders don't start another.
+    liquidating: bool,
+    brackets: HashMap<TradingPair, Brackets>,
+    bracket_events: Vec<BracketEvent>,
+    /// Scheduled corporate actions that haven't gone ex, by ex-date.
+    corporate_actions: Vec<CorporateAction>,
+    corporate_action_events: Vec<CorporateActionEvent>,
+    /// Orders submitted with a client order ID, by client and that ID, as
+    /// of when they were last seen.
+    client_order_ids: HashMap<(String, String), (TradingPair, Order)>,
+    audit: AuditLog,
+    drop_copy: DropCopyFeed,
+    /// While `place_orders` runs, the pairs whose books it has changed.
+    batch: Option<Vec<TradingPair>>,
+    /// Each client's current quote, by market.
+    quotes: HashMap<(TradingPair, String), QuoteIds>,
+    metrics: Arc<Metrics>,
+    next_exchange_id: u64,
+}
+
+impl Default for MatchingEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MatchingEngine {
     pub fn new() -> MatchingEngine {
         MatchingEngine {
             orderbooks: HashMap::new(),
+            market_configs: HashMap::new(),
+            market_states: HashMap::new(),
+            market_events: Vec::new(),
+            tickers: HashMap::new(),
+            validators: HashMap::new(),
+            risk_limits: RiskLimits::default(),
+            accounts: Accounts::new(),
+            instruments: Instruments::new(),
+            rate_limiter: None,
+            client_limits: HashMap::new(),
+            killed_clients: HashSet::new(),
+            risk_events: Vec::new(),
+            persistence: PersistenceConfig::default(),
+            feed: FeedConfig::default(),
+            l3_feeds: HashMap::new(),
+            fee_ledger: FeeLedger::new(),
+            positions: Positions::new(),
+            portfolio: Portfolio::new(),
+            rates: Rates::new(),
+            pricing: Pricing::new(),
+            funding: HashMap::new(),
+            funding_events: Vec::new(),
+            borrow_check: None,
+            pegs: HashMap::new(),
+            midpoint_pools: HashMap::new(),
+            margin: None,
+            insurance: InsuranceFund::default(),
+            liquidation_events: Vec::new(),
+            liquidating: false,
+            brackets: HashMap::new(),
+            bracket_events: Vec::new(),
+            corporate_actions: Vec::new(),
+            corporate_action_events: Vec::new(),
+            client_order_ids: HashMap::new(),
+            audit: AuditLog::new(),
+            batch: None,
+            quotes: HashMap::new(),
+            drop_copy: DropCopyFeed::new(),
+            metrics: Arc::new(Metrics::new()),
+            next_exchange_id: 1,
+        }
+    }
+
+    pub fn from_config(path: impl AsRef<Path>) -> Result<MatchingEngine, ConfigError> {
+        Ok(Self::with_config(EngineConfig::from_file(path)?))
+    }
+
+    pub fn with_config(config: EngineConfig) -> MatchingEngine {
+        let mut engine = MatchingEngine::new();
+        engine.risk_limits = config.risk;
+        engine.set_margin(config.margin);
+        engine.rate_limiter = config.rate_limit.map(RateLimiter::new);
+        engine.persistence = config.persistence;
+        engine.feed = config.feed;
+        for market in config.markets {
+            engine.add_market(market);
+        }
+        for account in config.accounts {
+            if let Err(reason) = engine.accounts.open(account.id, account.risk) {
+                warn!(%reason, "skipped account");
+            }
+        }
+        for instrument in config.instruments {
+            if let Err(reason) = engine.instruments.insert(instrument) {
+                warn!(%reason, "skipped instrument");
+            }
+        }
+        for corporate_action in config.corporate_actions {
+            if let Err(reason) = engine.schedule_corporate_action(corporate_action) {
+                warn!(%reason, "skipped corporate action");
+            }
         }
+        engine
     }
 
     pub fn add_new_market(&mut self, pair: TradingPair) {
-        self.orderbooks.insert(pair.clone(), OrderBook::new());
-        println!("Added new market: {:?}", pair);
+        self.add_market(MarketConfig::new(pair));
     }
 
-    pub fn place_limit_order(
+    pub fn add_market(&mut self, config: MarketConfig) {
+        let pair = config.pair.clone();
+        let algorithm = config.matching.algorithm(config.lot_size);
+        self.orderbooks.insert(
+            pair.clone(),
+            LimitOrderBook::new().with_algorithm(algorithm),
+        );
+        if let Some(funding) = &config.funding {
+            self.funding
+                .insert(pair.clone(), Funding::new(funding.clone()));
+        }
+        self.portfolio.set_precision(&pair, config.precision);
+        self.market_configs.insert(pair.clone(), config);
+        self.market_states
+            .insert(pair.clone(), MarketState::default());
+        self.tickers.insert(pair.clone(), Ticker::default());
+        self.l3_feeds
+            .insert(pair.clone(), L3Feed::new(self.feed.l3));
+        info!(%pair, "added market");
+    }
+
+    /// Registers `hook` for the pair's matches, after any already there.
+    pub fn add_match_hook(
+        &mut self,
+        pair: &TradingPair,
+        hook: Rc<dyn MatchHook>,
+    ) -> Result<(), String> {
+        let orderbook = self
+            .orderbooks
+            .get_mut(pair)
+            .ok_or_else(|| format!("No orderbook for trading pair: {}", pair))?;
+        orderbook.hooks.push(hook);
+        Ok(())
+    }
+
+    /// Registers `validator` for orders entering the pair's market, after
+    /// its configured rules and any validators already there.
+    pub fn add_validator(
+        &mut self,
+        pair: &TradingPair,
+        validator: Rc<dyn OrderValidator>,
+    ) -> Result<(), String> {
+        if !self.orderbooks.contains_key(pair) {
+            return Err(format!("No orderbook for trading pair: {}", pair));
+        }
+        self.validators
+            .entry(pair.clone())
+            .or_default()
+            .push(validator);
+        Ok(())
+    }
+
+    /// Runs the order entry checks for `order` as an order of `kind`,
+    /// without placing it; see `validation`.
+    pub fn validate_order(
+        &self,
+        pair: &TradingPair,
+        order: &Order,
+        kind: OrderKind,
+    ) -> Result<(), Rejection> {
+        let (Some(config), Some(book)) = (self.market_configs.get(pair), self.orderbooks.get(pair))
+        else {
+            return Err(Rejection::UnknownMarket(pair.clone()));
+        };
+        let entry = OrderEntry {
+            pair,
+            order,
+            kind,
+            book,
+            multiplier: self.instruments.multiplier(pair),
+        };
+        let validators = self.validators.get(pair).map_or(&[][..], Vec::as_slice);
+        validation::validate(config, &self.risk_limits, validators, &entry)
+    }
+
+    pub fn market_config(&self, pair: &TradingPair) -> Option<&MarketConfig> {
+        self.market_configs.get(pair)
+    }
+
+    pub fn market_state(&self, pair: &TradingPair) -> Option<&MarketState> {
+        self.market_states.get(pair)
+    }
+
+    /// The pair's trading over the 24 hours up to `now`.
+    pub fn ticker(&self, pair: &TradingPair, now: DateTime<Utc>) -> Option<TickerStats> {
+        self.tickers
+            .get(pair)
+            .map(|ticker| ticker.stats(pair.to_string(), now))
+    }
+
+    /// Takes the halt, resume, prune and expiry events recorded since the
+    /// last call.
+    pub fn drain_market_events(&mut self) -> Vec<MarketEvent> {
+        std::mem::take(&mut self.market_events)
+    }
+
+    /// Once any account is open, only open accounts may place orders.
+    pub fn accounts(&self) -> &Accounts {
+        &self.accounts
+    }
+
+    pub fn accounts_mut(&mut self) -> &mut Accounts {
+        &mut self.accounts
+    }
+
+    pub fn instruments(&self) -> &Instruments {
+        &self.instruments
+    }
+
+    pub fn instruments_mut(&mut self) -> &mut Instruments {
+        &mut self.instruments
+    }
+
+    /// Resting orders of `account` across all markets, oldest first.
+    pub fn account_orders(&self, account: &str) -> Vec<(TradingPair, Order)> {
+        let mut orders: Vec<_> = self
+            .orderbooks
+            .iter()
+            .flat_map(|(pair, orderbook)| {
+                orderbook
+                    .client_orders
+                    .get(account)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|exchange_id| orderbook.get_order(*exchange_id))
+                    .map(move |order| (pair.clone(), order.clone()))
+            })
+            .collect();
+        orders.sort_by_key(|(_, order)| order.exchange_id);
+        orders
+    }
+
+    /// Cancels an order on behalf of `account`, which must own it or be
+    /// an ancestor of the sub-account that does.
+    pub fn cancel_account_order(
+        &mut self,
+        account: &str,
+        pair: &TradingPair,
+        exchange_id: u64,
+    ) -> Result<Order, String> {
+        let owner = self
+            .orderbooks
+            .get(pair)
+            .and_then(|orderbook| orderbook.get_order(exchange_id))
+            .map(|order| AccountId::from(order.client.as_str()));
+        let mut ancestor = owner;
+        while let Some(id) = ancestor {
+            if id.as_str() == account {
+                return self.cancel_order(pair, exchange_id);
+            }
+            ancestor = id.parent();
+        }
+        Err(format!(
+            "No resting order with id {} for account {:?}",
+            exchange_id, account
+        ))
+    }
+
+    pub fn positions(&self) -> &Positions {
+        &self.positions
+    }
+
+    pub fn portfolio(&self) -> &Portfolio {
+        &self.portfolio
+    }
+
+    /// The median of the pair's mid, last trade and index prices, which
+    /// open positions are valued at.
+    pub fn mark_price(&self, pair: &TradingPair) -> Option<Decimal> {
+        self.pricing.mark_price(pair)
+    }
+
+    pub fn index_price(&self, pair: &TradingPair) -> Option<Decimal> {
+        self.pricing.index_price(pair)
+    }
+
+    /// `account`'s portfolio with totals converted to `currency`.
+    pub fn portfolio_report(&self, account: &str, currency: &str) -> PortfolioReport {
+        self.portfolio.report(account, &self.rates, currency)
+    }
+
+    /// Sets the pair's index price from an outside source, such as other
+    /// venues' prices. It feeds the mark price and, in perpetual markets,
+    /// funding.
+    pub fn set_index_price(
         &mut self,
+        pair: &TradingPair,
         price: Decimal,
+        time: DateTime<Utc>,
+    ) -> Result<(), String> {
+        if price <= Decimal::ZERO {
+            return Err(format!("Invalid price: {}", price));
+        }
+        if !self.orderbooks.contains_key(pair) {
+            return Err(format!("No orderbook for trading pair: {}", pair));
+        }
+        self.pricing.set_index_price(pair, price);
+        self.update_prices(pair);
+        self.check_margins(time);
+        Ok(())
+    }
+
+    /// Settles funding in every perpetual market whose interval has ended by
+    /// `now`, at the rate given by its mark and index prices. Markets missing
+    /// either price skip the interval. See `drain_funding_events` for the
+    /// payments.
+    pub fn settle_funding(&mut self, now: DateTime<Utc>) {
+        for (pair, funding) in &mut self.funding {
+            if !funding.is_due(now) {
+                continue;
+            }
+            let (Some(mark), Some(index)) = (
+                self.pricing.mark_price(pair),
+                self.pricing.index_price(pair),
+            ) else {
+                warn!(%pair, "skipped funding without mark and index prices");
+                continue;
+            };
+            let rate = funding.config.rate(mark, index);
+            let payments = self.portfolio.apply_funding(pair, rate, mark);
+            info!(%pair, %rate, positions = payments.len(), "settled funding");
+            self.funding_events
+                .extend(
+                    payments
+                        .into_iter()
+                        .map(|(account, position, payment)| FundingEvent {
+                            account,
+                            pair: pair.clone(),
+                            rate,
+                            mark_price: mark,
+                            index_price: index,
+                            position,
+                            payment,
+                            time: now,
+                        }),
+                );
+        }
+    }
+
+    /// Runs the end-of-day settlement at `now`. Every market is settled at
+    /// its mark price, or its last trade without one: each account's trades
+    /// are netted, its P&L, fees and funding for the day move into its
+    /// balance of the quote currency, and its open positions are carried
+    /// into the next day at the settlement price. Each market's session then
+    /// closes at that price. Clients without an opened account are reported
+    /// but have no balance to settle into.
+    pub fn settle(&mut self, now: DateTime<Utc>) -> SettlementReport {
+        let mut pairs: Vec<TradingPair> = self.orderbooks.keys().cloned().collect();
+        pairs.sort_by_key(|pair| pair.to_string());
+        let mut report = SettlementReport {
+            time: now,
+            prices: Vec::new(),
+            records: Vec::new(),
+        };
+        for pair in pairs {
+            let state = self.market_states.entry(pair.clone()).or_default();
+            state.close_session();
+            let Some(price) = self
+                .pricing
+                .mark_price(&pair)
+                .or_else(|| state.last_trade_price())
+            else {
+                continue;
+            };
+            state.set_previous_close(price);
+
+            let multiplier = self.portfolio.multiplier(&pair);
+            for (account, holding) in self.portfolio.settle(&pair, price) {
+                let record = SettlementRecord::new(&account, &pair, &holding, price, multiplier);
+                if self.accounts.contains(account.as_str()) {
+                    self.accounts
+                        .adjust(account.as_str(), pair.quote(), record.amount())
+                        .unwrap();
+                }
+                report.records.push(record);
+            }
+            report.prices.push((pair, price));
+        }
+        info!(
+            markets = report.prices.len(),
+            records = report.records.len(),
+            "settled end of day"
+        );
+        report
+    }
+
+    /// Takes the funding payments settled since the last call.
+    pub fn drain_funding_events(&mut self) -> Vec<FundingEvent> {
+        std::mem::take(&mut self.funding_events)
+    }
+
+    pub fn margin(&self) -> Option<&MarginConfig> {
+        self.margin.as_ref()
+    }
+
+    /// Turns margin requirements and liquidations on or off, starting a new
+    /// insurance fund with the configured balance.
+    pub fn set_margin(&mut self, margin: Option<MarginConfig>) {
+        let balance = margin
+            .as_ref()
+            .map_or(Decimal::ZERO, |margin| margin.insurance_fund);
+        self.insurance = InsuranceFund::new(balance);
+        self.margin = margin;
+    }
+
+    pub fn insurance_fund(&self) -> &InsuranceFund {
+        &self.insurance
+    }
+
+    /// Adds to the insurance fund, e.g. from liquidation or trading fees.
+    pub fn deposit_insurance(&mut self, amount: Decimal) -> Result<(), String> {
+        if amount <= Decimal::ZERO {
+            return Err(format!("Invalid amount: {}", amount));
+        }
+        self.insurance.deposit(amount);
+        Ok(())
+    }
+
+    /// `account`'s equity against what its positions require, at the
+    /// current mark prices, when margin requirements are on. Its balances
+    /// are the collateral, adjusted by what the insurance fund credited or
+    /// charged it; balances and markets without a rate to the margin
+    /// currency don't count.
+    pub fn margin_status(&self, account: &str) -> Option<MarginStatus> {
+        let margin = self.margin.as_ref()?;
+        let report = self
+            .portfolio
+            .report(account, &self.rates, &margin.currency);
+        let collateral: Decimal = self
+            .accounts
+            .get(account)
+            .into_iter()
+            .flat_map(|account| account.balances())
+            .filter_map(|(asset, amount)| self.rates.convert(amount, asset, &margin.currency))
+            .sum();
+        Some(MarginStatus {
+            equity: collateral + report.net_pnl() + self.insurance.adjustment(account),
+            maintenance: report.notional * margin.maintenance_margin,
+        })
+    }
+
+    /// Takes the margin calls and liquidation steps since the last call.
+    pub fn drain_liquidation_events(&mut self) -> Vec<LiquidationEvent> {
+        std::mem::take(&mut self.liquidation_events)
+    }
+
+    /// Conversion prices between currencies, kept up to date with the mid
+    /// of every book the engine runs.
+    pub fn rates(&self) -> &Rates {
+        &self.rates
+    }
+
+    /// Sets a conversion price from outside, for currencies the engine has
+    /// no book for. A book for the same pair overwrites it as it changes.
+    pub fn set_rate(&mut self, base: &str, quote: &str, price: Decimal) {
+        self.rates.set(base, quote, price);
+    }
+
+    /// Takes the bracket lifecycle events recorded since the last call.
+    pub fn drain_bracket_events(&mut self) -> Vec<BracketEvent> {
+        std::mem::take(&mut self.bracket_events)
+    }
+
+    /// Stops the market accepting new orders until `until`. Cancels are
+    /// still accepted.
+    pub fn halt_market(
+        &mut self,
+        pair: &TradingPair,
+        time: DateTime<Utc>,
+        until: DateTime<Utc>,
+        reason: impl Into<String>,
+    ) -> Result<(), String> {
+        let state = self
+            .market_states
+            .get_mut(pair)
+            .ok_or_else(|| format!("No orderbook for trading pair: {}", pair))?;
+        state.halt(until);
+        self.market_events.push(MarketEvent::Halted {
+            pair: pair.clone(),
+            time,
+            until,
+            reason: reason.into(),
+        });
+        Ok(())
+    }
+
+    /// Lifts a halt before it expires.
+    pub fn resume_market(&mut self, pair: &TradingPair, time: DateTime<Utc>) -> Result<(), String> {
+        let state = self
+            .market_states
+            .get_mut(pair)
+            .ok_or_else(|| format!("No orderbook for trading pair: {}", pair))?;
+        if state.resume() {
+            self.market_events.push(MarketEvent::Resumed {
+                pair: pair.clone(),
+                time,
+            });
+        }
+        Ok(())
+    }
+
+    /// Seeds the previous close, e.g. from the prior session's settlement.
+    pub fn set_previous_close(&mut self, pair: &TradingPair, price: Decimal) -> Result<(), String> {
+        self.market_states
+            .get_mut(pair)
+            .ok_or_else(|| format!("No orderbook for trading pair: {}", pair))?
+            .set_previous_close(price);
+        Ok(())
+    }
+
+    /// Ends the pair's session, making its last trade the previous close.
+    pub fn close_session(&mut self, pair: &TradingPair) -> Result<Option<Decimal>, String> {
+        self.market_states
+            .get_mut(pair)
+            .map(MarketState::close_session)
+            .ok_or_else(|| format!("No orderbook for trading pair: {}", pair))
+    }
+
+    /// Cancels the resting orders on the pair's levels that its prune policy
+    /// drops, reporting them as a `MarketEvent::Pruned`. Books with a policy
+    /// are also pruned after every limit order.
+    pub fn prune(&mut self, pair: &TradingPair, time: DateTime<Utc>) -> Result<Vec<Order>, String> {
+        if !self.orderbooks.contains_key(pair) {
+            return Err(format!("No orderbook for trading pair: {}", pair));
+        }
+        Ok(self.prune_far_levels(pair, time))
+    }
+
+    /// Prunes the pair's book, then releases the memory held for orders
+    /// that have left it.
+    pub fn compact(
+        &mut self,
+        pair: &TradingPair,
+        time: DateTime<Utc>,
+    ) -> Result<Vec<Order>, String> {
+        let pruned = self.prune(pair, time)?;
+        self.orderbooks.get_mut(pair).unwrap().shrink_to_fit();
+        Ok(pruned)
+    }
+
+    /// Cancels the resting DAY orders entered before the latest close in
+    /// their market's calendar, i.e. those whose session has ended by `now`,
+    /// and returns them by market. Each market with expired orders gets a
+    /// `MarketEvent::Expired`. Meant to be called on a timer; see
+    /// `server::SWEEP_INTERVAL`.
+    pub fn expire_orders(&mut self, now: DateTime<Utc>) -> Vec<(TradingPair, Order)> {
+        let mut pairs: Vec<TradingPair> = self.orderbooks.keys().cloned().collect();
+        pairs.sort_by_key(|pair| pair.to_string());
+        let mut expired = Vec::new();
+        for pair in pairs {
+            let Some(close) = self
+                .market_configs
+                .get(&pair)
+                .and_then(|config| config.calendar.as_ref())
+                .and_then(|calendar| calendar.last_close(now))
+            else {
+                continue;
+            };
+            let mut exchange_ids: Vec<u64> = self.orderbooks[&pair]
+                .orders
+                .values()
+                .filter(|order| order.time_in_force == TimeInForce::Day && order.entry_time < close)
+                .map(|order| order.exchange_id)
+                .collect();
+            if exchange_ids.is_empty() {
+                continue;
+            }
+            exchange_ids.sort_unstable();
+            let mut orders = Vec::with_capacity(exchange_ids.len());
+            for exchange_id in exchange_ids {
+                if let Some(order) = self.cancel_resting(&pair, exchange_id, CancelReason::Expired)
+                {
+                    if let Some(brackets) = self.brackets.get_mut(&pair) {
+                        brackets.on_cancel(exchange_id, &mut self.bracket_events);
+                    }
+                    orders.push(order);
+                }
+            }
+            info!(%pair, orders = orders.len(), "expired day orders");
+            self.market_events.push(MarketEvent::Expired {
+                pair: pair.clone(),
+                time: now,
+                orders: orders.iter().map(|order| order.exchange_id).collect(),
+            });
+            self.book_changed(&pair);
+            expired.extend(orders.into_iter().map(|order| (pair.clone(), order)));
+        }
+        expired
+    }
+
+    /// Schedules a split or dividend for `apply_corporate_actions` to apply
+    /// once its ex-date arrives.
+    pub fn schedule_corporate_action(&mut self, action: CorporateAction) -> Result<(), String> {
+        action.action.validate()?;
+        if !self.orderbooks.contains_key(&action.pair) {
+            return Err(format!("No orderbook for trading pair: {}", action.pair));
+        }
+        let index = self
+            .corporate_actions
+            .partition_point(|scheduled| scheduled.ex_date <= action.ex_date);
+        self.corporate_actions.insert(index, action);
+        Ok(())
+    }
+
+    /// Scheduled corporate actions that haven't gone ex yet, by ex-date.
+    pub fn corporate_actions(&self) -> &[CorporateAction] {
+        &self.corporate_actions
+    }
+
+    /// Applies every scheduled action whose ex-date is on or before `now`,
+    /// in ex-date order. Resting orders are adjusted in place, keeping their
+    /// priority, positions are adjusted or paid the dividend, and reference
+    /// and mark prices move with them. See `drain_corporate_action_events`.
+    pub fn apply_corporate_actions(&mut self, now: DateTime<Utc>) {
+        let today = now.date_naive();
+        let due = self
+            .corporate_actions
+            .partition_point(|action| action.ex_date <= today);
+        let actions: Vec<CorporateAction> = self.corporate_actions.drain(..due).collect();
+        for action in actions {
+            self.apply_corporate_action(action, now);
+        }
+    }
+
+    /// Takes the corporate action events recorded since the last call.
+    pub fn drain_corporate_action_events(&mut self) -> Vec<CorporateActionEvent> {
+        std::mem::take(&mut self.corporate_action_events)
+    }
+
+    pub fn snapshot(&self) -> EngineSnapshot {
+        let mut markets: Vec<_> = self
+            .orderbooks
+            .iter()
+            .map(|(pair, orderbook)| MarketSnapshot {
+                pair: pair.to_string(),
+                orders: orderbook.resting_orders(),
+                last_trade_price: self.market_states[pair].last_trade_price(),
+                previous_close: self.market_states[pair].previous_close(),
+            })
+            .collect();
+        markets.sort_by(|a, b| a.pair.cmp(&b.pair));
+        EngineSnapshot {
+            next_exchange_id: self.next_exchange_id,
+            audit_sequence: self.audit.last_sequence(),
+            markets,
+            positions: self.positions.snapshot(),
+            holdings: self.portfolio.snapshot(),
+            fees: self.fee_ledger.snapshot(),
+        }
+    }
+
+    /// A deterministic hash of what `snapshot` captures: every market's
+    /// resting orders and last prices, the engine's exchange ID and audit
+    /// sequences, and its positions, holdings and fees; see `state_hash`.
+    /// A standby fed the same commands as the primary has the same hash
+    /// after each.
+    pub fn state_hash(&self) -> u64 {
+        let mut pairs: Vec<_> = self.orderbooks.keys().collect();
+        pairs.sort_by_key(|pair| pair.to_string());
+        let mut hasher = StateHasher::new();
+        hasher.write_u64(self.next_exchange_id);
+        hasher.write_u64(self.audit.last_sequence());
+        for pair in pairs {
+            hasher.write_str(&pair.to_string());
+            hasher.write_u64(state_hash::hash_book(&self.orderbooks[pair]));
+            let state = &self.market_states[pair];
+            for price in [state.last_trade_price(), state.previous_close()] {
+                hasher.write_decimal(price.unwrap_or_default());
+            }
+        }
+        let positions = self.positions.snapshot();
+        hasher.write_u64(positions.len() as u64);
+        for position in &positions {
+            hasher.write_position(position);
+        }
+        let holdings = self.portfolio.snapshot();
+        hasher.write_u64(holdings.len() as u64);
+        for holding in &holdings {
+            hasher.write_holding(holding);
+        }
+        let fees = self.fee_ledger.snapshot();
+        hasher.write_u64(fees.len() as u64);
+        for fees in &fees {
+            hasher.write_fees(fees);
+        }
+        hasher.finish()
+    }
+
+    /// The state the engine holds that `snapshot` leaves out, by name. A
+    /// checkpoint taken while there is any wouldn't restore the engine.
+    pub fn uncaptured(&self) -> Vec<&'static str> {
+        let band_windows = self.market_configs.iter().any(|(pair, config)| {
+            (config.price_band.is_some() || config.circuit_breaker.is_some())
+                && self.market_states[pair].last_trade_price().is_some()
+        });
+        let insurance = self
+            .margin
+            .as_ref()
+            .map_or(Decimal::ZERO, |margin| margin.insurance_fund);
+        [
+            (
+                "account balances",
+                self.accounts
+                    .iter()
+                    .any(|account| account.balances().any(|(_, balance)| !balance.is_zero())),
+            ),
+            ("price band and breaker windows", band_windows),
+            (
+                "halts",
+                self.market_states
+                    .values()
+                    .any(|state| state.halted_until().is_some()),
+            ),
+            (
+                "funding",
+                self.funding
+                    .values()
+                    .any(|funding| funding.last_settled().is_some()),
+            ),
+            (
+                "insurance fund",
+                self.insurance != InsuranceFund::new(insurance),
+            ),
+            (
+                "index prices",
+                self.orderbooks
+                    .keys()
+                    .any(|pair| self.pricing.index_price(pair).is_some()),
+            ),
+            (
+                "pegged orders",
+                self.pegs.values().any(|pegs| !pegs.is_empty()),
+            ),
+            (
+                "midpoint orders",
+                self.midpoint_pools.values().any(|pool| !pool.is_empty()),
+            ),
+            (
+                "brackets",
+                self.brackets.values().any(|brackets| !brackets.is_empty()),
+            ),
+            ("kill switches", !self.killed_clients.is_empty()),
+            ("quotes", !self.quotes.is_empty()),
+        ]
+        .into_iter()
+        .filter(|(_, held)| *held)
+        .map(|(name, _)| name)
+        .collect()
+    }
+
+    /// The hash of one market's resting orders, to find which market two
+    /// engines with different `state_hash`es disagree on.
+    pub fn market_hash(&self, pair: &TradingPair) -> Option<u64> {
+        self.orderbooks.get(pair).map(state_hash::hash_book)
+    }
+
+    /// Replaces the books of the snapshot's markets, which must already be
+    /// configured, with the snapshot's resting orders, and every position,
+    /// holding and fee with the snapshot's. A market's last prices are only
+    /// replaced by ones the snapshot has.
+    pub fn restore(&mut self, snapshot: &EngineSnapshot) -> Result<(), String> {
+        for market in &snapshot.markets {
+            let pair = market.pair.parse::<TradingPair>()?;
+            if !self.orderbooks.contains_key(&pair) {
+                return Err(format!("No orderbook for trading pair: {:?}", market.pair));
+            }
+        }
+        for market in &snapshot.markets {
+            let pair = market.pair.parse::<TradingPair>()?;
+            let config = &self.market_configs[&pair];
+            let mut orderbook =
+                LimitOrderBook::new().with_algorithm(config.matching.algorithm(config.lot_size));
+            orderbook.hooks = self.orderbooks[&pair].hooks.clone();
+            for order in &market.orders {
+                orderbook.add_order(order.clone());
+                self.remember_client_order(&pair, order);
+            }
+            // The orders were already announced when they first rested.
+            orderbook.drain_events();
+            // Snapshots hold plain limit orders; pegged and midpoint orders
+            // don't survive a restore.
+            self.pegs.remove(&pair);
+            self.midpoint_pools.remove(&pair);
+            self.orderbooks.insert(pair.clone(), orderbook);
+            let state = self.market_states.get_mut(&pair).unwrap();
+            if let Some(price) = market.last_trade_price {
+                state.set_last_trade_price(price);
+            }
+            if let Some(price) = market.previous_close {
+                state.set_previous_close(price);
+            }
+        }
+        self.positions.restore(&snapshot.positions)?;
+        let instruments = &self.instruments;
+        self.portfolio
+            .restore(&snapshot.holdings, |pair| instruments.multiplier(pair))?;
+        self.fee_ledger.restore(&snapshot.fees);
+        for market in &snapshot.markets {
+            self.update_prices(&market.pair.parse()?);
+        }
+        self.next_exchange_id = snapshot.next_exchange_id;
+        self.audit.resume(snapshot.audit_sequence);
+        Ok(())
+    }
+
+    /// Limits how fast each client can send orders; `None` lifts the limit.
+    pub fn set_rate_limit(&mut self, limit: Option<RateLimit>) {
+        self.rate_limiter = limit.map(RateLimiter::new);
+    }
+
+    pub fn risk_limits(&self) -> &RiskLimits {
+        &self.risk_limits
+    }
+
+    /// Replaces the limits applied to markets without their own.
+    pub fn set_risk_limits(&mut self, limits: RiskLimits) {
+        self.risk_limits = limits;
+    }
+
+    pub fn client_limits(&self, client: &str) -> Option<&RiskLimits> {
+        self.client_limits.get(client)
+    }
+
+    /// Sets limits for one client, checked on top of its market's and
+    /// account's; `None` removes them. Takes effect from the next order.
+    pub fn set_client_limits(&mut self, client: &str, limits: Option<RiskLimits>) {
+        match limits {
+            Some(limits) => self.client_limits.insert(client.to_string(), limits),
+            None => self.client_limits.remove(client),
+        };
+    }
+
+    /// Cancels every resting order of `client` in every market and rejects
+    /// its new orders, including exits the engine would send for its
+    /// brackets, until `enable_client`. Returns the cancelled orders.
+    pub fn kill_switch(&mut self, client: &str) -> Vec<(TradingPair, Order)> {
+        self.killed_clients.insert(client.to_string());
+        info!(client, "kill switch engaged");
+        self.cancel_all_for(client, CancelReason::KillSwitch)
+    }
+
+    /// Cancels every resting order of `client` in every market once its
+    /// session has dropped. Unlike `kill_switch`, the client may trade again
+    /// as soon as it reconnects. Returns the cancelled orders.
+    pub fn cancel_on_disconnect(&mut self, client: &str) -> Vec<(TradingPair, Order)> {
+        info!(client, "cancelling on disconnect");
+        self.cancel_all_for(client, CancelReason::Disconnect)
+    }
+
+    fn cancel_all_for(&mut self, client: &str, reason: CancelReason) -> Vec<(TradingPair, Order)> {
+        let mut cancelled = Vec::new();
+        for (pair, order) in self.account_orders(client) {
+            if let Ok(order) = self.cancel_order_for(&pair, order.exchange_id, reason) {
+                cancelled.push((pair, order));
+            }
+        }
+        let midpoint_orders: Vec<(TradingPair, u64)> = self
+            .midpoint_pools
+            .iter()
+            .flat_map(|(pair, pool)| {
+                pool.orders()
+                    .into_iter()
+                    .filter(|order| order.client == client)
+                    .map(|order| (pair.clone(), order.exchange_id))
+            })
+            .collect();
+        for (pair, exchange_id) in midpoint_orders {
+            if let Ok(order) = self.cancel_midpoint_for(&pair, exchange_id, reason) {
+                cancelled.push((pair, order));
+            }
+        }
+        cancelled
+    }
+
+    /// Lets a client stopped by `kill_switch` trade again. Returns whether
+    /// it had been stopped.
+    pub fn enable_client(&mut self, client: &str) -> bool {
+        let was_killed = self.killed_clients.remove(client);
+        if was_killed {
+            info!(client, "kill switch released");
+        }
+        was_killed
+    }
+
+    pub fn is_killed(&self, client: &str) -> bool {
+        self.killed_clients.contains(client)
+    }
+
+    /// Takes the orders blocked by client and account limits since the
+    /// last call.
+    pub fn drain_risk_events(&mut self) -> Vec<RiskEvent> {
+        std::mem::take(&mut self.risk_events)
+    }
+
+    /// Streams every execution and cancel, across all clients, to the
+    /// returned receiver. Only the latest subscriber receives them.
+    pub fn subscribe_drop_copy(&mut self) -> mpsc::Receiver<DropCopy> {
+        self.drop_copy.subscribe()
+    }
+
+    pub fn persistence(&self) -> &PersistenceConfig {
+        &self.persistence
+    }
+
+    /// Drains the pair's book events as market-by-order updates. Fails
+    /// unless L3 output is enabled in the feed config.
+    pub fn drain_l3_events(&mut self, pair: &TradingPair) -> Result<Vec<L3Event>, String> {
+        if self.feed.l3 == L3Privacy::Off {
+            return Err("L3 feed is disabled".to_string());
+        }
+        match (self.orderbooks.get_mut(pair), self.l3_feeds.get_mut(pair)) {
+            (Some(orderbook), Some(feed)) => Ok(feed.publish(&orderbook.drain_events())),
+            _ => Err(format!("No orderbook for trading pair: {}", pair)),
+        }
+    }
+
+    pub fn fee_ledger(&self) -> &FeeLedger {
+        &self.fee_ledger
+    }
+
+    /// Requires short sales to find a borrow before they are accepted.
+    /// Without one, short sales are accepted like any other sell.
+    pub fn set_borrow_check(&mut self, borrow_check: impl BorrowCheck + 'static) {
+        self.borrow_check = Some(Box::new(borrow_check));
+    }
+
+    /// Counters and histograms for the engine; clone the `Arc` to export
+    /// them from another thread.
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    pub fn markets(&self) -> impl Iterator<Item = &TradingPair> {
+        self.orderbooks.keys()
+    }
+
+    /// Size of every market's book, by pair.
+    pub fn book_stats(&self) -> Vec<(TradingPair, BookStats)> {
+        let mut books: Vec<(TradingPair, BookStats)> = self
+            .orderbooks
+            .iter()
+            .map(|(pair, orderbook)| (pair.clone(), orderbook.stats()))
+            .collect();
+        books.sort_by_key(|(pair, _)| pair.to_string());
+        books
+    }
+
+    /// Size of all the engine's books together.
+    pub fn memory_stats(&self) -> BookStats {
+        stats::total(self.orderbooks.values().map(LimitOrderBook::stats))
+    }
+
+    pub fn orderbook(&self, pair: &TradingPair) -> Option<&LimitOrderBook> {
+        self.orderbooks.get(pair)
+    }
+
+    /// A read-only copy of the pair's book that can outlive this borrow and
+    /// be sent to other threads; see `LimitOrderBook::read_view`.
+    pub fn book_view(&self, pair: &TradingPair) -> Option<BookView> {
+        self.orderbooks.get(pair).map(LimitOrderBook::read_view)
+    }
+
+    /// Hands out exchange IDs that are unique across all markets.
+    pub fn next_exchange_id(&mut self) -> u64 {
+        let exchange_id = self.next_exchange_id;
+        self.next_exchange_id += 1;
+        exchange_id
+    }
+
+    /// Matches `order` against the pair's book and rests whatever is left.
+    /// Returns the order in its final state together with its fills.
+    ///
+    /// An order with a `client_order_id` its client has used before isn't
+    /// placed again: if it's the same order, the result is its current
+    /// state and no fills, and if it isn't, it's rejected.
+    pub fn place_limit_order(
+        &mut self,
         pair: TradingPair,
         order: Order,
-    ) -> Result<(), String> {
-        match self.orderbooks.get_mut(&pair) {
-            Some(orderbook) => {
-                orderbook.add_limit_order(order, price);
-                Ok(())
+    ) -> Result<(Order, Vec<Fill>), String> {
+        if let Some(original) = self.resubmitted_order(&pair, &order)? {
+            return Ok((original, Vec::new()));
+        }
+        let result = self.submit_limit_order(pair.clone(), order, Origin::Client, None);
+        if let Ok((order, _)) = &result {
+            self.remember_client_order(&pair, order);
+        }
+        result
+    }
+
+    /// Applies `commands` in order in a single pass, e.g. to replace a
+    /// market maker's whole quote ladder at once, and returns each one's
+    /// result. Pegged orders, midpoint matching and marks catch up once at
+    /// the end instead of after every command, and the batch's drop copies
+    /// go out together after that. A failed command doesn't stop the rest.
+    pub fn place_orders(
+        &mut self,
+        commands: &[EngineCommand],
+    ) -> Vec<Result<CommandOutcome, String>> {
+        self.in_batch(|engine| {
+            commands
+                .iter()
+                .map(|command| command.clone().apply(engine))
+                .collect()
+        })
+    }
+
+    /// Runs `f` as one batch; see `place_orders`.
+    fn in_batch<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        if self.batch.is_some() {
+            return f(self);
+        }
+        self.batch = Some(Vec::new());
+        self.drop_copy.hold();
+        let result = f(self);
+        for pair in self.batch.take().unwrap_or_default() {
+            self.book_changed(&pair);
+        }
+        self.drop_copy.release();
+        result
+    }
+
+    /// Replaces the client's quote in the pair's market with `quote` in one
+    /// step: the old quote's resting orders are cancelled and the new sides
+    /// placed as a batch. Nothing changes if the quote itself is invalid.
+    pub fn place_quote(&mut self, pair: TradingPair, quote: Quote) -> Result<QuoteReport, String> {
+        quote.validate()?;
+        if !self.orderbooks.contains_key(&pair) {
+            return Err(format!("No orderbook for trading pair: {}", pair));
+        }
+        let key = (pair.clone(), quote.client.clone());
+        let previous = self.quotes.remove(&key).unwrap_or_default();
+        self.in_batch(|engine| {
+            let cancelled = [previous.bid, previous.ask]
+                .into_iter()
+                .flatten()
+                .filter_map(|exchange_id| {
+                    engine
+                        .cancel_order_for(&pair, exchange_id, CancelReason::Replaced)
+                        .ok()
+                })
+                .collect();
+            let mut ids = QuoteIds::default();
+            let bid = quote.bid.map(|side| {
+                engine.place_quote_side(&pair, &quote, OrderType::Bid, side, &mut ids.bid)
+            });
+            let ask = quote.ask.map(|side| {
+                engine.place_quote_side(&pair, &quote, OrderType::Ask, side, &mut ids.ask)
+            });
+            if ids != QuoteIds::default() {
+                engine.quotes.insert(key, ids);
+            }
+            Ok(QuoteReport {
+                cancelled,
+                bid,
+                ask,
+            })
+        })
+    }
+
+    /// Places one side of `quote`, noting its exchange ID in `id` if it
+    /// rests.
+    fn place_quote_side(
+        &mut self,
+        pair: &TradingPair,
+        quote: &Quote,
+        order_type: OrderType,
+        side: QuoteSide,
+        id: &mut Option<u64>,
+    ) -> Result<(Order, Vec<Fill>), String> {
+        let order = Order::new(
+            pair.to_string(),
+            self.next_exchange_id(),
+            order_type,
+            side.size,
+            side.price,
+            quote.time,
+            quote.time,
+        )
+        .with_client(quote.client.clone());
+        let result = self.place_limit_order(pair.clone(), order);
+        if let Ok((order, _)) = &result {
+            if order.is_active() {
+                *id = Some(order.exchange_id);
             }
-            None => Err(format!(
-                "No orderbook for trading pair: {:?}",
-                pair.to_string()
-            )),
         }
+        result
+    }
+
+    /// The client's current quote in the pair's market: its bid and ask
+    /// orders that are still resting.
+    pub fn quote(&self, pair: &TradingPair, client: &str) -> (Option<Order>, Option<Order>) {
+        let ids = self
+            .quotes
+            .get(&(pair.clone(), client.to_string()))
+            .copied()
+            .unwrap_or_default();
+        let resting = |id: Option<u64>| {
+            let orderbook = self.orderbooks.get(pair)?;
+            orderbook.get_order(id?).cloned()
+        };
+        (resting(ids.bid), resting(ids.ask))
+    }
+
+    /// The order `client` sent under `client_order_id`, in its current
+    /// state.
+    pub fn client_order(
+        &self,
+        client: &str,
+        client_order_id: &str,
+    ) -> Option<(TradingPair, Order)> {
+        let (pair, order) = self
+            .client_order_ids
+            .get(&(client.to_string(), client_order_id.to_string()))?;
+        if let Some(resting) = self
+            .orderbooks
+            .get(pair)
+            .and_then(|orderbook| orderbook.get_order(order.exchange_id))
+            .or_else(|| self.midpoint_pools.get(pair)?.get(order.exchange_id))
+        {
+            return Some((pair.clone(), resting.clone()));
+        }
+        let mut order = order.clone();
+        // Cancels are recorded as they happen, so an order that was open
+        // when last seen and has left the book since was filled.
+        if matches!(
+            order.status,
+            OrderStatus::New | OrderStatus::PartiallyFilled
+        ) {
+            order.filled_quantity = order.shares;
+            order.remaining_quantity = Decimal::ZERO;
+            order.status = OrderStatus::Filled;
+        }
+        Some((pair.clone(), order))
+    }
+
+    /// Cancels the resting order `client` sent under `client_order_id`.
+    pub fn cancel_client_order(
+        &mut self,
+        client: &str,
+        client_order_id: &str,
+    ) -> Result<Order, String> {
+        let (pair, order) = self
+            .client_order(client, client_order_id)
+            .ok_or_else(|| format!("Unknown client order id: {:?}", client_order_id))?;
+        let waiting = self
+            .midpoint_pools
+            .get(&pair)
+            .is_some_and(|pool| pool.get(order.exchange_id).is_some());
+        if waiting {
+            return self.cancel_midpoint_order(&pair, order.exchange_id);
+        }
+        self.cancel_order(&pair, order.exchange_id)
+    }
+
+    /// Replaces the resting order `client` sent under `original_id` with
+    /// one for `shares` in total at `price`, sent under `client_order_id`.
+    /// What the original already filled counts towards `shares`. The
+    /// replacement gets a new exchange ID and goes to the back of the
+    /// queue. The original is cancelled only once the replacement has
+    /// passed every check, with the original left out of its exposure, so
+    /// a rejected replacement leaves the original resting untouched.
+    pub fn replace_client_order(
+        &mut self,
+        client: &str,
+        original_id: &str,
+        client_order_id: &str,
+        shares: Decimal,
+        price: Decimal,
+        time: DateTime<Utc>,
+    ) -> Result<(Order, Vec<Fill>), String> {
+        let (pair, original) = self
+            .client_order(client, original_id)
+            .ok_or_else(|| format!("Unknown client order id: {:?}", original_id))?;
+        if self
+            .client_order_ids
+            .contains_key(&(client.to_string(), client_order_id.to_string()))
+        {
+            return Err(format!("Duplicate client order id: {:?}", client_order_id));
+        }
+        let remaining = shares - original.filled_quantity;
+        if remaining <= Decimal::ZERO {
+            return Err(format!(
+                "Order {:?} has already filled {}",
+                original_id, original.filled_quantity
+            ));
+        }
+        let mut replacement = Order::new(
+            pair.to_string(),
+            self.next_exchange_id(),
+            original.order_type,
+            remaining,
+            price,
+            time,
+            time,
+        )
+        .with_client(client)
+        .with_short_sale(original.short_sale)
+        .with_reduce_only(original.reduce_only)
+        .with_client_order_id(client_order_id);
+        replacement.strategy = original.strategy;
+        let result = self.submit_limit_order(
+            pair.clone(),
+            replacement,
+            Origin::Client,
+            Some(original.exchange_id),
+        );
+        if let Ok((replacement, _)) = &result {
+            self.remember_client_order(&pair, replacement);
+        }
+        result
+    }
+
+    /// Every state change of the order `exchange_id`, oldest first.
+    pub fn audit_trail(&self, exchange_id: u64) -> &[AuditEntry] {
+        self.audit.trail(exchange_id)
+    }
+
+    /// Forgets the client order IDs of orders that are no longer resting,
+    /// so they can be used again, e.g. at the start of a trading day.
+    pub fn clear_client_order_ids(&mut self) {
+        let orderbooks = &self.orderbooks;
+        self.client_order_ids.retain(|_, (pair, order)| {
+            orderbooks
+                .get(pair)
+                .is_some_and(|orderbook| orderbook.get_order(order.exchange_id).is_some())
+        });
+    }
+
+    /// The current state of the order its client already sent under
+    /// `order`'s client order ID, or an error if that was a different order.
+    fn resubmitted_order(
+        &self,
+        pair: &TradingPair,
+        order: &Order,
+    ) -> Result<Option<Order>, String> {
+        let Some(client_order_id) = &order.client_order_id else {
+            return Ok(None);
+        };
+        let Some((original_pair, original)) = self.client_order(&order.client, client_order_id)
+        else {
+            return Ok(None);
+        };
+        if original_pair != *pair
+            || original.order_type != order.order_type
+            || original.shares != order.shares
+            || original.limit_price != order.limit_price
+        {
+            return Err(format!("Duplicate client order id: {:?}", client_order_id));
+        }
+        info!(%pair, exchange_id = original.exchange_id, client_order_id, "resubmitted");
+        Ok(Some(original))
+    }
+
+    fn remember_client_order(&mut self, pair: &TradingPair, order: &Order) {
+        if let Some(client_order_id) = &order.client_order_id {
+            self.client_order_ids.insert(
+                (order.client.clone(), client_order_id.clone()),
+                (pair.clone(), order.clone()),
+            );
+        }
+    }
+
+    /// Throttles, checks and matches `order`. When it replaces the resting
+    /// order `replacing`, that order is left out of the checks and only
+    /// cancelled once `order` has passed them.
+    fn submit_limit_order(
+        &mut self,
+        pair: TradingPair,
+        order: Order,
+        origin: Origin,
+        replacing: Option<u64>,
+    ) -> Result<(Order, Vec<Fill>), String> {
+        let span = order_span(&pair, &order, "limit");
+        let _entered = span.enter();
+        debug!(price = %order.limit_price, quantity = %order.shares, "received");
+
+        let started = Stopwatch::start();
+        let (exchange_id, time) = (order.exchange_id, order.event_time);
+        let result = self
+            .throttle(&order, origin)
+            .and_then(|()| self.match_limit_order(pair.clone(), order, replacing));
+        if let Err(reason) = &result {
+            self.audit.rejected(exchange_id, time, reason);
+        }
+        self.record_metrics(
+            &pair,
+            started,
+            result.as_ref().map(|(_, fills)| fills.len()),
+        );
+        match &result {
+            Ok((order, fills)) => info!(
+                status = ?order.status,
+                filled = %order.filled_quantity,
+                fills = fills.len(),
+                "reported"
+            ),
+            Err(reason) => info!(%reason, "rejected"),
+        }
+        result
+    }
+
+    fn match_limit_order(
+        &mut self,
+        pair: TradingPair,
+        mut order: Order,
+        replacing: Option<u64>,
+    ) -> Result<(Order, Vec<Fill>), String> {
+        self.check_trading_allowed(&pair, &order, true)?;
+        match self.orderbooks.get_mut(&pair) {
+            Some(orderbook) => {
+                // A second copy of a resting order would sit in its level's
+                // queue twice and break matching against it.
+                if orderbook.get_order(order.exchange_id).is_some() {
+                    return Err(format!(
+                        "Exchange ID {} is already resting",
+                        order.exchange_id
+                    ));
+                }
+                Self::check_account(&self.accounts, &self.killed_clients, &order)?;
+                Self::cap_reduce_only(&self.positions, orderbook, &pair, &mut order, replacing)?;
+                self.validate_order(&pair, &order, OrderKind::Limit)
+                    .map_err(|rejection| rejection.to_string())?;
+                self.check_client_risk(&pair, &order, true, replacing)?;
+                Self::check_short_sale(&mut self.borrow_check, &pair, &order)?;
+                debug!("validated");
+                if let Some(exchange_id) = replacing {
+                    self.cancel_order_for(&pair, exchange_id, CancelReason::Replaced)?;
+                }
+                self.audit.accepted(&order);
+                let orderbook = self.orderbooks.get_mut(&pair).unwrap();
+                let (mut order, mut fills) = orderbook.place_order(order);
+                debug!(fills = fills.len(), "matched");
+                self.charge_fees(&pair, &mut order, &mut fills);
+                self.publish_executions(&pair, &order, order.shares, &fills);
+                let account = AccountId::from(order.client.as_str());
+                self.positions
+                    .record(&pair, &account, order.order_type, &fills);
+                self.portfolio.record(
+                    &pair,
+                    &account,
+                    order.strategy.as_deref(),
+                    order.order_type,
+                    &fills,
+                    self.instruments.multiplier(&pair),
+                );
+                self.record_trades(&pair, &fills, order.event_time);
+                self.update_brackets(&pair, &fills, order.event_time);
+                self.book_changed(&pair);
+                self.check_margins(order.event_time);
+                self.prune_far_levels(&pair, order.event_time);
+                Ok((order, fills))
+            }
+            None => Err(format!("No orderbook for trading pair: {}", pair)),
+        }
+    }
+
+    /// Executes `order` against the pair's book at any price; nothing rests.
+    /// Resending it under the same client order ID executes nothing and
+    /// returns no fills; see `place_limit_order`.
+    pub fn execute_market_order(
+        &mut self,
+        pair: TradingPair,
+        order: Order,
+    ) -> Result<Vec<Fill>, String> {
+        if self.resubmitted_order(&pair, &order)?.is_some() {
+            return Ok(Vec::new());
+        }
+        let mut executed = order.clone();
+        let result = self.submit_market_order(pair.clone(), order, Origin::Client);
+        if let Ok(fills) = &result {
+            // Whatever didn't fill is dropped, like a cancel.
+            executed.filled_quantity = fills.iter().map(|fill| fill.quantity).sum();
+            executed.remaining_quantity = Decimal::ZERO;
+            executed.status = if executed.filled_quantity == executed.shares {
+                OrderStatus::Filled
+            } else {
+                OrderStatus::Cancelled
+            };
+            self.remember_client_order(&pair, &executed);
+        }
+        result
+    }
+
+    fn submit_market_order(
+        &mut self,
+        pair: TradingPair,
+        order: Order,
+        origin: Origin,
+    ) -> Result<Vec<Fill>, String> {
+        let span = order_span(&pair, &order, "market");
+        let _entered = span.enter();
+        debug!(quantity = %order.shares, "received");
+
+        let started = Stopwatch::start();
+        let (exchange_id, time) = (order.exchange_id, order.event_time);
+        let result = self
+            .throttle(&order, origin)
+            .and_then(|()| self.match_market_order(pair.clone(), order));
+        if let Err(reason) = &result {
+            self.audit.rejected(exchange_id, time, reason);
+        }
+        self.record_metrics(&pair, started, result.as_ref().map(Vec::len));
+        match &result {
+            Ok(fills) => info!(fills = fills.len(), "reported"),
+            Err(reason) => info!(%reason, "rejected"),
+        }
+        result
+    }
+
+    fn match_market_order(
+        &mut self,
+        pair: TradingPair,
+        mut order: Order,
+    ) -> Result<Vec<Fill>, String> {
+        self.check_trading_allowed(&pair, &order, false)?;
+        match self.orderbooks.get_mut(&pair) {
+            Some(orderbook) => {
+                if order.shares <= Decimal::ZERO {
+                    return Err(format!("Invalid quantity: {}", order.shares));
+                }
+                Self::check_account(&self.accounts, &self.killed_clients, &order)?;
+                Self::cap_reduce_only(&self.positions, orderbook, &pair, &mut order, None)?;
+                self.validate_order(&pair, &order, OrderKind::Market)
+                    .map_err(|rejection| rejection.to_string())?;
+                self.check_client_risk(&pair, &order, false, None)?;
+                Self::check_short_sale(&mut self.borrow_check, &pair, &order)?;
+                debug!("validated");
+                self.audit.accepted(&order);
+                let orderbook = self.orderbooks.get_mut(&pair).unwrap();
+                let mut taker = order.clone();
+                let mut fills = orderbook.execute_market_order(order);
+                debug!(fills = fills.len(), "matched");
+                self.charge_fees(&pair, &mut taker, &mut fills);
+                self.publish_executions(&pair, &taker, taker.shares, &fills);
+                let account = AccountId::from(taker.client.as_str());
+                self.positions
+                    .record(&pair, &account, taker.order_type, &fills);
+                self.portfolio.record(
+                    &pair,
+                    &account,
+                    taker.strategy.as_deref(),
+                    taker.order_type,
+                    &fills,
+                    self.instruments.multiplier(&pair),
+                );
+                self.record_trades(&pair, &fills, taker.event_time);
+                self.update_brackets(&pair, &fills, taker.event_time);
+                self.book_changed(&pair);
+                self.check_margins(taker.event_time);
+                Ok(fills)
+            }
+            None => Err(format!("No orderbook for trading pair: {}", pair)),
+        }
+    }
+
+    /// Places every leg of `basket` in order, after checking each one as far
+    /// as it can be checked up front. Returns the first leg to fail those
+    /// checks as an error, in which case nothing was placed; otherwise one
+    /// result per leg. A contingent basket's leg that can't be cancelled when
+    /// the basket unwinds gets the reason as its result.
+    pub fn place_basket(&mut self, basket: Basket) -> Result<Vec<LegResult>, String> {
+        if basket.legs.is_empty() {
+            return Err("Empty basket".to_string());
+        }
+        for (index, (pair, order)) in basket.legs.iter().enumerate() {
+            self.precheck_order(pair, order, &basket.legs[..index])
+                .map_err(|reason| format!("Leg {}: {}", index + 1, reason))?;
+        }
+
+        let mut pairs = Vec::with_capacity(basket.legs.len());
+        let mut results: Vec<LegResult> = Vec::with_capacity(basket.legs.len());
+        for (pair, order) in basket.legs {
+            if basket.contingent && results.iter().any(Result::is_err) {
+                results.push(Err("Basket cancelled".to_string()));
+                continue;
+            }
+            let result = self.place_limit_order(pair.clone(), order);
+            if result.is_err() && basket.contingent {
+                info!(legs = results.len() + 1, "contingent basket cancelled");
+                for (pair, placed) in pairs.iter().zip(results.iter_mut()) {
+                    let Ok((order, _)) = placed else {
+                        continue;
+                    };
+                    if !order.is_active() {
+                        continue;
+                    }
+                    match self.cancel_order_for(pair, order.exchange_id, CancelReason::Basket) {
+                        Ok(cancelled) => *order = cancelled,
+                        Err(reason) => {
+                            warn!(%reason, "basket leg not cancelled");
+                            *placed = Err(format!("Basket cancel failed: {}", reason));
+                        }
+                    }
+                }
+            }
+            pairs.push(pair);
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    /// The checks a basket leg has to pass once the `earlier` legs are
+    /// placed, run without side effects. Earlier legs from the same client
+    /// count as resting orders; what they might fill is unknown.
+    fn precheck_order(
+        &self,
+        pair: &TradingPair,
+        order: &Order,
+        earlier: &[(TradingPair, Order)],
+    ) -> Result<(), String> {
+        let (Some(config), Some(state), Some(orderbook)) = (
+            self.market_configs.get(pair),
+            self.market_states.get(pair),
+            self.orderbooks.get(pair),
+        ) else {
+            return Err(format!("No orderbook for trading pair: {}", pair));
+        };
+        if state.is_halted(order.event_time) {
+            return Err(format!("Market {} is halted", pair));
+        }
+        Self::check_expiry(&self.instruments, pair, order.event_time)?;
+        if let Some(band) = &config.price_band {
+            if let Some(reference) = state.reference_price(band.reference, order.event_time) {
+                band.check(order.limit_price, reference)?;
+            }
+        }
+        Self::check_account(&self.accounts, &self.killed_clients, order)?;
+
+        let mut exposure = self.exposure(pair, order, None);
+        let (mut orders, mut reducing, mut short) = (1, Decimal::ZERO, order.shares);
+        for (market, leg) in earlier.iter().filter(|(_, leg)| leg.client == order.client) {
+            orders += 1;
+            let notional = self
+                .instruments
+                .notional(market, leg.limit_price, leg.shares);
+            self.add_notional(&mut exposure, pair, market, notional);
+            if market == pair {
+                exposure.open_orders += 1;
+                if leg.order_type == order.order_type {
+                    exposure.resting += leg.shares;
+                    if leg.reduce_only {
+                        reducing += leg.shares;
+                    }
+                }
+            }
+            if leg.short_sale && market.base() == pair.base() {
+                short += leg.shares;
+            }
+        }
+
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.check(&order.client, order.event_time, orders)?;
+        }
+        if order.reduce_only
+            && Self::reducible(&self.positions, orderbook, pair, order, None) <= reducing
+        {
+            return Err(format!(
+                "Reduce-only order would increase the position of {:?}",
+                order.client
+            ));
+        }
+        self.validate_order(pair, order, OrderKind::Limit)
+            .map_err(|rejection| rejection.to_string())?;
+        self.risk_check(pair, order, true, &exposure)?;
+        if order.short_sale {
+            if order.order_type != OrderType::Ask {
+                return Err("Only sell orders can be marked short".to_string());
+            }
+            if let Some(borrow_check) = &self.borrow_check {
+                if !borrow_check.can_locate(&order.client, pair, short) {
+                    return Err(format!(
+                        "No borrow available for short sale of {} {}",
+                        short, pair.base
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends `order` to the pair's midpoint segment, where it crosses other
+    /// midpoint orders at the mid of the book's best bid and offer, only in
+    /// executions of at least `min_quantity`. The order's limit price caps
+    /// the mid it trades at. It is never shown on the book and waits until
+    /// filled or cancelled. Client order IDs work as for `place_limit_order`.
+    pub fn place_midpoint_order(
+        &mut self,
+        pair: TradingPair,
+        order: Order,
+        min_quantity: Decimal,
+    ) -> Result<(Order, Vec<Fill>), String> {
+        let span = order_span(&pair, &order, "midpoint");
+        let _entered = span.enter();
+        if let Some(original) = self.resubmitted_order(&pair, &order)? {
+            return Ok((original, Vec::new()));
+        }
+        let config = self
+            .market_configs
+            .get(&pair)
+            .ok_or_else(|| format!("No orderbook for trading pair: {}", pair))?;
+        let midpoint = config
+            .midpoint
+            .as_ref()
+            .ok_or_else(|| format!("No midpoint segment for trading pair: {}", pair))?;
+        if min_quantity < midpoint.min_quantity || min_quantity > order.shares {
+            return Err(format!("Invalid minimum quantity: {}", min_quantity));
+        }
+        if order.reduce_only {
+            return Err("Midpoint orders cannot be reduce-only".to_string());
+        }
+
+        self.throttle(&order, Origin::Client)?;
+        self.check_trading_allowed(&pair, &order, true)?;
+        Self::check_account(&self.accounts, &self.killed_clients, &order)?;
+        self.validate_order(&pair, &order, OrderKind::Midpoint)
+            .map_err(|rejection| rejection.to_string())?;
+        self.check_client_risk(&pair, &order, true, None)?;
+        Self::check_short_sale(&mut self.borrow_check, &pair, &order)?;
+
+        let exchange_id = order.exchange_id;
+        self.audit.accepted(&order);
+        self.midpoint_pools
+            .entry(pair.clone())
+            .or_default()
+            .insert(order, min_quantity);
+        let result = self
+            .cross_midpoint(&pair, exchange_id)
+            .expect("midpoint order was just added");
+        self.remember_client_order(&pair, &result.0);
+        self.update_prices(&pair);
+        self.check_margins(result.0.event_time);
+        info!(
+            status = ?result.0.status,
+            fills = result.1.len(),
+            "reported"
+        );
+        Ok(result)
+    }
+
+    /// Takes a waiting midpoint order out of the segment.
+    pub fn cancel_midpoint_order(
+        &mut self,
+        pair: &TradingPair,
+        exchange_id: u64,
+    ) -> Result<Order, String> {
+        self.cancel_midpoint_for(pair, exchange_id, CancelReason::Client)
+    }
+
+    fn cancel_midpoint_for(
+        &mut self,
+        pair: &TradingPair,
+        exchange_id: u64,
+        reason: CancelReason,
+    ) -> Result<Order, String> {
+        let pool = self
+            .midpoint_pools
+            .get_mut(pair)
+            .ok_or_else(|| format!("No midpoint orders for trading pair: {}", pair))?;
+        let remaining = pool
+            .get(exchange_id)
+            .map(|order| order.remaining_quantity)
+            .ok_or_else(|| format!("No midpoint order with id: {}", exchange_id))?;
+        let order = pool.cancel(exchange_id).unwrap();
+        info!(%pair, exchange_id, "cancelled midpoint");
+        // Nothing of the order is left to resend, so its client order ID is
+        // free again.
+        if let Some(client_order_id) = &order.client_order_id {
+            self.client_order_ids
+                .remove(&(order.client.clone(), client_order_id.clone()));
+        }
+        self.drop_copy
+            .publish([DropCopy::cancel(pair, &order, remaining)]);
+        self.audit.cancelled(&order, reason);
+        Ok(order)
+    }
+
+    /// Places `order` at the price `peg` gives it against the current book
+    /// and keeps it pegged for as long as it rests. The order's own limit
+    /// price is ignored.
+    pub fn place_pegged_order(
+        &mut self,
+        pair: TradingPair,
+        mut order: Order,
+        peg: Peg,
+    ) -> Result<(Order, Vec<Fill>), String> {
+        let orderbook = self
+            .orderbooks
+            .get(&pair)
+            .ok_or_else(|| format!("No orderbook for trading pair: {}", pair))?;
+        let tick_size = self.market_configs[&pair].tick_size;
+        order.limit_price = PeggedOrders::initial_price(orderbook, &order, &peg, tick_size)?;
+
+        let (order, fills) = self.place_limit_order(pair.clone(), order)?;
+        if order.is_active() {
+            self.pegs
+                .entry(pair)
+                .or_default()
+                .insert(order.exchange_id, peg);
+        }
+        Ok((order, fills))
+    }
+
+    /// Places `entry` as a limit order with `bracket`'s exits attached to
+    /// whatever of it fills. See `drain_bracket_events` for progress.
+    pub fn place_bracket_order(
+        &mut self,
+        pair: TradingPair,
+        entry: Order,
+        bracket: Bracket,
+    ) -> Result<(Order, Vec<Fill>), String> {
+        bracket.validate(entry.order_type, entry.limit_price)?;
+        let entry_id = entry.exchange_id;
+        // Registered up front so fills on arrival already count.
+        self.brackets.entry(pair.clone()).or_default().open(
+            entry_id,
+            entry.client.clone(),
+            entry.strategy.clone(),
+            entry.order_type,
+            bracket,
+        );
+        let result = self.place_limit_order(pair.clone(), entry);
+        if result.is_err() {
+            if let Some(brackets) = self.brackets.get_mut(&pair) {
+                brackets.discard(entry_id);
+            }
+        }
+        result
+    }
+
+    /// Feeds fills to the pair's brackets and places or triggers their
+    /// exits. Exit orders come back through here, so a take-profit that
+    /// trades on arrival is accounted for like any other.
+    fn update_brackets(&mut self, pair: &TradingPair, fills: &[Fill], time: DateTime<Utc>) {
+        let Some(brackets) = self.brackets.get_mut(pair) else {
+            return;
+        };
+        let orderbook = &self.orderbooks[pair];
+        let actions = brackets.on_fills(
+            fills,
+            |exchange_id| orderbook.get_order(exchange_id).is_some(),
+            &mut self.bracket_events,
+        );
+
+        for action in actions {
+            match action {
+                BracketAction::PlaceTakeProfit {
+                    entry_id,
+                    client,
+                    strategy,
+                    side,
+                    price,
+                    quantity,
+                    replaces,
+                } => {
+                    if let Some(exchange_id) = replaces {
+                        self.cancel_resting(pair, exchange_id, CancelReason::Bracket);
+                    }
+                    let exchange_id = self.next_exchange_id();
+                    let mut order = Order::new(
+                        pair.to_string(),
+                        exchange_id,
+                        side,
+                        quantity,
+                        price,
+                        time,
+                        time,
+                    )
+                    .with_client(client.clone());
+                    order.strategy = strategy;
+                    self.brackets
+                        .get_mut(pair)
+                        .unwrap()
+                        .set_take_profit(entry_id, exchange_id);
+                    match self.submit_limit_order(pair.clone(), order, Origin::Engine, None) {
+                        Ok(_) => self.bracket_events.push(BracketEvent::TakeProfitPlaced {
+                            entry_id,
+                            client,
+                            exchange_id,
+                            quantity,
+                        }),
+                        Err(reason) => self.bracket_events.push(BracketEvent::ChildRejected {
+                            entry_id,
+                            client,
+                            reason,
+                        }),
+                    }
+                }
+                BracketAction::TriggerStop {
+                    entry_id,
+                    client,
+                    strategy,
+                    side,
+                    quantity,
+                    cancel,
+                } => {
+                    for exchange_id in cancel {
+                        self.cancel_resting(pair, exchange_id, CancelReason::Bracket);
+                    }
+                    let mut order = Order::new(
+                        pair.to_string(),
+                        self.next_exchange_id(),
+                        side,
+                        quantity,
+                        Decimal::ZERO,
+                        time,
+                        time,
+                    )
+                    .with_client(client.clone());
+                    order.strategy = strategy;
+                    if let Err(reason) =
+                        self.submit_market_order(pair.clone(), order, Origin::Engine)
+                    {
+                        self.bracket_events.push(BracketEvent::ChildRejected {
+                            entry_id,
+                            client,
+                            reason,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    fn publish_executions(
+        &mut self,
+        pair: &TradingPair,
+        taker: &Order,
+        taker_leaves: Decimal,
+        fills: &[Fill],
+    ) {
+        if fills.is_empty() {
+            return;
+        }
+        let orderbook = &self.orderbooks[pair];
+        let pool = self.midpoint_pools.get(pair);
+        let maker_leaves = |exchange_id| {
+            orderbook
+                .get_order(exchange_id)
+                .or_else(|| pool.and_then(|pool| pool.get(exchange_id)))
+                .map_or(Decimal::ZERO, |maker| maker.remaining_quantity)
+        };
+        self.audit.fills(taker, taker_leaves, fills, maker_leaves);
+        if self.drop_copy.is_subscribed() {
+            let reports = DropCopy::executions(pair, taker, taker_leaves, fills, maker_leaves);
+            self.drop_copy.publish(reports);
+        }
+    }
+
+    /// Takes a resting order off the book without the side effects of a
+    /// client cancel, reporting it on the drop copy and in the audit trail.
+    fn cancel_resting(
+        &mut self,
+        pair: &TradingPair,
+        exchange_id: u64,
+        reason: CancelReason,
+    ) -> Option<Order> {
+        let orderbook = self.orderbooks.get_mut(pair)?;
+        let remaining = orderbook.get_order(exchange_id)?.remaining_quantity;
+        let order = orderbook.cancel_order(exchange_id)?;
+        self.drop_copy
+            .publish([DropCopy::cancel(pair, &order, remaining)]);
+        self.audit.cancelled(&order, reason);
+        if let Some(client_order_id) = &order.client_order_id {
+            let key = (order.client.clone(), client_order_id.clone());
+            if let Some(entry) = self.client_order_ids.get_mut(&key) {
+                entry.1 = order.clone();
+            }
+        }
+        Some(order)
+    }
+
+    /// Crosses the waiting midpoint order `exchange_id` at the current mid
+    /// and settles its fills like any other trade. Returns the order's new
+    /// state, or `None` if it isn't waiting.
+    fn cross_midpoint(
+        &mut self,
+        pair: &TradingPair,
+        exchange_id: u64,
+    ) -> Option<(Order, Vec<Fill>)> {
+        let mid = mid_price(&self.orderbooks[pair]);
+        let pool = self.midpoint_pools.get_mut(pair)?;
+        let (mut order, mut fills) = match mid {
+            Some(mid) => pool.cross(exchange_id, mid)?,
+            None => (pool.get(exchange_id)?.clone(), Vec::new()),
+        };
+        if fills.is_empty() {
+            return Some((order, fills));
+        }
+
+        let filled: Decimal = fills.iter().map(|fill| fill.quantity).sum();
+        debug!(exchange_id, fills = fills.len(), %filled, "crossed at midpoint");
+        self.charge_fees(pair, &mut order, &mut fills);
+        self.publish_executions(pair, &order, order.remaining_quantity + filled, &fills);
+        let account = AccountId::from(order.client.as_str());
+        self.positions
+            .record(pair, &account, order.order_type, &fills);
+        self.portfolio.record(
+            pair,
+            &account,
+            order.strategy.as_deref(),
+            order.order_type,
+            &fills,
+            self.instruments.multiplier(pair),
+        );
+        self.record_trades(pair, &fills, order.event_time);
+        Some((order, fills))
+    }
+
+    /// Gives waiting midpoint orders, oldest first, another chance to cross
+    /// after the mid moves.
+    fn rematch_midpoint(&mut self, pair: &TradingPair) {
+        let waiting: Vec<u64> = match self.midpoint_pools.get(pair) {
+            Some(pool) if !pool.is_empty() => pool
+                .orders()
+                .iter()
+                .map(|order| order.exchange_id)
+                .collect(),
+            _ => return,
+        };
+        for exchange_id in waiting {
+            self.cross_midpoint(pair, exchange_id);
+        }
+    }
+
+    /// Brings the pair's conversion rate and mark price up to date with its
+    /// book and last trade.
+    fn update_prices(&mut self, pair: &TradingPair) {
+        let Some(orderbook) = self.orderbooks.get(pair) else {
+            return;
+        };
+        self.rates.update_from_book(pair, orderbook);
+        let last = self.market_states[pair].last_trade_price();
+        if let Some(mark) = self.pricing.update(pair, mid_price(orderbook), last) {
+            self.portfolio.mark(pair, mark);
+        }
+    }
+
+    /// Liquidates every open account that has fallen below its maintenance
+    /// margin. Runs whenever trades or index prices move the marks.
+    fn check_margins(&mut self, time: DateTime<Utc>) {
+        if self.margin.is_none() || self.liquidating {
+            return;
+        }
+        self.liquidating = true;
+        let accounts: Vec<AccountId> = self
+            .accounts
+            .iter()
+            .map(|account| account.id.clone())
+            .collect();
+        for account in accounts {
+            let breached = self
+                .margin_status(account.as_str())
+                .is_some_and(|status| status.is_breached());
+            if breached {
+                self.liquidate(&account, time);
+            }
+        }
+        self.liquidating = false;
+    }
+
+    /// Cancels `account`'s resting orders, then closes its positions with
+    /// reduce-only market orders, one `liquidation_step` of each at a time,
+    /// until it meets its maintenance margin again or nothing more fills.
+    fn liquidate(&mut self, account: &AccountId, time: DateTime<Utc>) {
+        let margin = self.margin.clone().unwrap();
+        let status = self.margin_status(account.as_str()).unwrap();
+        warn!(
+            %account,
+            equity = %status.equity,
+            maintenance = %status.maintenance,
+            "margin call"
+        );
+        self.liquidation_events.push(LiquidationEvent::MarginCall {
+            account: account.clone(),
+            status,
+            time,
+        });
+        for (pair, order) in self.account_orders(account.as_str()) {
+            let _ = self.cancel_order_for(&pair, order.exchange_id, CancelReason::MarginCall);
+        }
+
+        let mut positions: Vec<(TradingPair, Decimal)> = self
+            .positions
+            .account(account.as_str())
+            .filter(|(_, position)| !position.is_zero())
+            .map(|(pair, position)| (pair.clone(), position))
+            .collect();
+        positions.sort_by_key(|(pair, _)| pair.to_string());
+        loop {
+            let mut filled_any = false;
+            for (pair, position) in &positions {
+                let remaining = self.positions.position(account.as_str(), pair);
+                let quantity = margin.step_quantity(*position, remaining);
+                if quantity.is_zero() {
+                    continue;
+                }
+                let side = if remaining.is_sign_positive() {
+                    OrderType::Ask
+                } else {
+                    OrderType::Bid
+                };
+                let order = Order::new(
+                    pair.to_string(),
+                    self.next_exchange_id(),
+                    side,
+                    quantity,
+                    Decimal::ZERO,
+                    time,
+                    time,
+                )
+                .with_client(account.as_str())
+                .with_reduce_only(true);
+                let filled = match self.submit_market_order(pair.clone(), order, Origin::Engine) {
+                    Ok(fills) => fills.iter().map(|fill| fill.quantity).sum(),
+                    Err(reason) => {
+                        warn!(%account, %reason, "liquidation order rejected");
+                        Decimal::ZERO
+                    }
+                };
+                filled_any |= filled > Decimal::ZERO;
+                self.liquidation_events.push(LiquidationEvent::Liquidated {
+                    account: account.clone(),
+                    pair: pair.clone(),
+                    side,
+                    quantity,
+                    filled,
+                    time,
+                });
+            }
+            let mut status = self.margin_status(account.as_str()).unwrap();
+            if !status.is_breached() || !filled_any {
+                let shortfall = -status.equity;
+                if !filled_any
+                    && shortfall > Decimal::ZERO
+                    && self.insurance.balance() < shortfall
+                    && margin.fallback == LossFallback::AutoDeleverage
+                {
+                    self.auto_deleverage(account, status.equity, time);
+                    status = self.margin_status(account.as_str()).unwrap();
+                }
+                info!(%account, equity = %status.equity, "liquidation completed");
+                self.liquidation_events.push(LiquidationEvent::Completed {
+                    account: account.clone(),
+                    status,
+                    time,
+                });
+                if status.equity < Decimal::ZERO {
+                    self.cover_bankruptcy(account, -status.equity, margin.fallback, time);
+                }
+                return;
+            }
+        }
+    }
+
+    /// Closes what's left of a bankrupt account's positions against the
+    /// ranked opposite positions in each market, at prices that bring its
+    /// `equity` back to zero. Each position takes a share of the deficit in
+    /// proportion to its notional.
+    fn auto_deleverage(&mut self, account: &AccountId, equity: Decimal, time: DateTime<Utc>) {
+        let currency = self.margin.as_ref().unwrap().currency.clone();
+        let positions: Vec<(TradingPair, Decimal, Decimal, Decimal)> = self
+            .positions
+            .account(account.as_str())
+            .filter(|(_, position)| !position.is_zero())
+            .filter_map(|(pair, position)| {
+                let mark = self.pricing.mark_price(pair)?;
+                let multiplier = self.instruments.multiplier(pair);
+                let notional = self.rates.convert(
+                    (mark * position * multiplier).abs(),
+                    pair.quote(),
+                    &currency,
+                )?;
+                Some((pair.clone(), position, mark, notional))
+            })
+            .collect();
+        let total: Decimal = positions.iter().map(|(.., notional)| notional).sum();
+        if total.is_zero() {
+            return;
+        }
+
+        for (pair, position, mark, notional) in positions {
+            let multiplier = self.instruments.multiplier(&pair);
+            let Some(deficit) =
+                self.rates
+                    .convert(equity * notional / total, &currency, pair.quote())
+            else {
+                continue;
+            };
+            let price = mark - deficit / (position * multiplier);
+            let side = if position.is_sign_positive() {
+                OrderType::Ask
+            } else {
+                OrderType::Bid
+            };
+            let candidates: Vec<AdlCandidate> = self
+                .portfolio
+                .accounts()
+                .filter(|other| *other != account)
+                .filter_map(|other| {
+                    let holding = self.portfolio.holding(other.as_str(), &pair);
+                    if holding.position.is_zero()
+                        || holding.position.is_sign_positive() == position.is_sign_positive()
+                    {
+                        return None;
+                    }
+                    let status = self.margin_status(other.as_str())?;
+                    let report = self
+                        .portfolio
+                        .report(other.as_str(), &self.rates, &currency);
+                    AdlCandidate::new(
+                        other.clone(),
+                        holding.position,
+                        holding.unrealized_pnl(mark, multiplier),
+                        holding.average_price * holding.position * multiplier,
+                        report.notional,
+                        status.equity,
+                    )
+                })
+                .collect();
+
+            let fills: Vec<Fill> = adl::allocate(candidates, position.abs())
+                .into_iter()
+                .map(|(other, quantity)| Fill {
+                    price,
+                    quantity,
+                    maker_client: other.to_string(),
+                    ..Fill::default()
+                })
+                .collect();
+            for fill in &fills {
+                info!(
+                    account = %fill.maker_client,
+                    bankrupt = %account,
+                    %price,
+                    quantity = %fill.quantity,
+                    "deleveraged"
+                );
+                self.liquidation_events.push(LiquidationEvent::Deleveraged {
+                    account: fill.maker_client.as_str().into(),
+                    bankrupt: account.clone(),
+                    pair: pair.clone(),
+                    side: match side {
+                        OrderType::Bid => OrderType::Ask,
+                        OrderType::Ask => OrderType::Bid,
+                    },
+                    quantity: fill.quantity,
+                    price,
+                    time,
+                });
+            }
+            self.positions.record(&pair, account, side, &fills);
+            self.portfolio
+                .record(&pair, account, None, side, &fills, multiplier);
+        }
+    }
+
+    /// Credits a bankrupt account its `shortfall` from the insurance fund
+    /// and, under `SocializedLoss`, recovers what the fund lacked from the
+    /// other open accounts.
+    fn cover_bankruptcy(
+        &mut self,
+        account: &AccountId,
+        shortfall: Decimal,
+        fallback: LossFallback,
+        time: DateTime<Utc>,
+    ) {
+        let insurance = self.insurance.cover(account, shortfall);
+        warn!(%account, %shortfall, %insurance, "bankrupt");
+        self.liquidation_events.push(LiquidationEvent::Bankrupt {
+            account: account.clone(),
+            shortfall,
+            insurance,
+            time,
+        });
+        if insurance == shortfall || fallback != LossFallback::SocializedLoss {
+            return;
+        }
+        let equities: Vec<(AccountId, Decimal)> = self
+            .accounts
+            .iter()
+            .filter(|other| other.id != *account)
+            .filter_map(|other| {
+                let status = self.margin_status(other.id.as_str())?;
+                Some((other.id.clone(), status.equity))
+            })
+            .collect();
+        for (other, amount) in self.insurance.socialize(&equities) {
+            self.liquidation_events.push(LiquidationEvent::LossShared {
+                account: other,
+                amount,
+                time,
+            });
+        }
+    }
+
+    fn apply_corporate_action(&mut self, action: CorporateAction, time: DateTime<Utc>) {
+        let pair = action.pair.clone();
+        let kind = action.action;
+        info!(%pair, action = ?kind, "applying corporate action");
+        self.corporate_action_events
+            .push(CorporateActionEvent::Applied { action, time });
+
+        // Orders left with nothing to rest are cancelled; the rest are taken
+        // out and put back adjusted in queue order, so priorities hold.
+        let config = &self.market_configs[&pair];
+        let (tick_size, lot_size) = (config.tick_size, config.lot_size);
+        let mut adjusted = Vec::new();
+        for order in self.orderbooks[&pair].resting_orders() {
+            let mut adjusting = order.clone();
+            if kind.adjust_order(&mut adjusting, tick_size, lot_size) {
+                adjusted.push((order, adjusting));
+            } else if let Some(order) =
+                self.cancel_resting(&pair, order.exchange_id, CancelReason::CorporateAction)
+            {
+                if let Some(brackets) = self.brackets.get_mut(&pair) {
+                    brackets.on_cancel(order.exchange_id, &mut self.bracket_events);
+                }
+                self.corporate_action_events
+                    .push(CorporateActionEvent::OrderCancelled {
+                        pair: pair.clone(),
+                        order,
+                        time,
+                    });
+            }
+        }
+        let orderbook = self.orderbooks.get_mut(&pair).unwrap();
+        for (order, _) in &adjusted {
+            orderbook.remove_order(order.clone());
+        }
+        for (order, adjusting) in adjusted {
+            orderbook.add_order(adjusting.clone());
+            if adjusting != order {
+                self.corporate_action_events
+                    .push(CorporateActionEvent::OrderAdjusted {
+                        pair: pair.clone(),
+                        order: adjusting,
+                        time,
+                    });
+            }
+        }
+
+        if let CorporateActionKind::Split(ratio) = kind {
+            self.positions.split(&pair, ratio);
+        }
+        let positions = self.portfolio.apply_corporate_action(&pair, &kind);
+        self.corporate_action_events
+            .extend(positions.into_iter().map(|(account, position, dividend)| {
+                CorporateActionEvent::PositionAdjusted {
+                    account,
+                    pair: pair.clone(),
+                    position,
+                    dividend,
+                    time,
+                }
+            }));
+        let adjust = |price| kind.adjust_price(price);
+        self.market_states
+            .entry(pair.clone())
+            .or_default()
+            .adjust_prices(adjust);
+        self.pricing.adjust(&pair, adjust);
+        self.reprice_pegged(&pair);
+        self.update_prices(&pair);
+        self.check_margins(time);
+    }
+
+    fn prune_far_levels(&mut self, pair: &TradingPair, time: DateTime<Utc>) -> Vec<Order> {
+        let Some(policy) = self
+            .market_configs
+            .get(pair)
+            .and_then(|config| config.prune)
+        else {
+            return Vec::new();
+        };
+        let orderbook = &self.orderbooks[pair];
+        let mut levels = 0;
+        let mut exchange_ids = Vec::new();
+        for side in [OrderType::Bid, OrderType::Ask] {
+            for price in policy.far_levels(orderbook, side) {
+                let orders = match side {
+                    OrderType::Bid => orderbook.get_bid_orders(price),
+                    OrderType::Ask => orderbook.get_ask_orders(price),
+                };
+                levels += 1;
+                exchange_ids.extend(orders.into_iter().map(|order| order.exchange_id));
+            }
+        }
+        if exchange_ids.is_empty() {
+            return Vec::new();
+        }
+
+        let mut cancelled = Vec::with_capacity(exchange_ids.len());
+        for exchange_id in exchange_ids {
+            if let Some(order) = self.cancel_resting(pair, exchange_id, CancelReason::Pruned) {
+                if let Some(brackets) = self.brackets.get_mut(pair) {
+                    brackets.on_cancel(exchange_id, &mut self.bracket_events);
+                }
+                cancelled.push(order);
+            }
+        }
+        info!(%pair, levels, orders = cancelled.len(), "pruned far levels");
+        self.market_events.push(MarketEvent::Pruned {
+            pair: pair.clone(),
+            time,
+            levels,
+            orders: cancelled.iter().map(|order| order.exchange_id).collect(),
+        });
+        self.book_changed(pair);
+        cancelled
+    }
+
+    /// Brings what follows the pair's book up to date after it changed:
+    /// pegged orders, midpoint matching and marks. During `place_orders`
+    /// that waits for the end of the batch.
+    fn book_changed(&mut self, pair: &TradingPair) {
+        if let Some(pairs) = &mut self.batch {
+            if !pairs.contains(pair) {
+                pairs.push(pair.clone());
+            }
+            return;
+        }
+        self.reprice_pegged(pair);
+        self.rematch_midpoint(pair);
+        self.update_prices(pair);
+    }
+
+    /// Moves the pair's pegged orders after its top of book changes.
+    fn reprice_pegged(&mut self, pair: &TradingPair) {
+        let Some(pegs) = self.pegs.get_mut(pair) else {
+            return;
+        };
+        let tick_size = self.market_configs[pair].tick_size;
+        let orderbook = self.orderbooks.get_mut(pair).unwrap();
+        for repriced in pegs.reprice(orderbook, tick_size) {
+            debug!(
+                exchange_id = repriced.exchange_id,
+                from = %repriced.from,
+                to = %repriced.to,
+                "repriced peg"
+            );
+        }
+    }
+
+    fn record_metrics(
+        &self,
+        pair: &TradingPair,
+        started: Stopwatch,
+        fills: Result<usize, &String>,
+    ) {
+        self.metrics.match_latency.observe(started.elapsed());
+        match fills {
+            Ok(fills) => {
+                self.metrics.orders_accepted.inc();
+                self.metrics.trades.add(fills as u64);
+            }
+            Err(_) => self.metrics.orders_rejected.inc(),
+        }
+        if let Some(orderbook) = self.orderbooks.get(pair) {
+            let (bids, asks) = orderbook.level_count();
+            self.metrics.set_book_depth(&pair.to_string(), bids, asks);
+        }
+    }
+
+    /// Rejects orders while the market is halted or its instrument has
+    /// expired and, when `priced`, orders outside the price band. A halt that
+    /// has run its course is lifted first. The order's event time is taken
+    /// as the current time.
+    fn check_trading_allowed(
+        &mut self,
+        pair: &TradingPair,
+        order: &Order,
+        priced: bool,
+    ) -> Result<(), String> {
+        Self::check_expiry(&self.instruments, pair, order.event_time)?;
+        let (config, state) = match (
+            self.market_configs.get(pair),
+            self.market_states.get_mut(pair),
+        ) {
+            (Some(config), Some(state)) => (config, state),
+            _ => return Ok(()),
+        };
+        let now = order.event_time;
+
+        if state.resume_if_expired(now) {
+            self.market_events.push(MarketEvent::Resumed {
+                pair: pair.clone(),
+                time: now,
+            });
+        }
+        if let Some(until) = state.halted_until() {
+            return Err(format!("Market {} is halted until {}", pair, until));
+        }
+        if let (true, Some(band)) = (priced, &config.price_band) {
+            if let Some(reference) = state.reference_price(band.reference, now) {
+                band.check(order.limit_price, reference)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Feeds trades to the market's circuit breaker, halting the market if it
+    /// trips. The order that caused the move completes before the halt.
+    fn record_trades(&mut self, pair: &TradingPair, fills: &[Fill], time: DateTime<Utc>) {
+        let breaker = self.market_configs[pair].circuit_breaker.as_ref();
+        let state = self.market_states.get_mut(pair).unwrap();
+        let ticker = self.tickers.get_mut(pair).unwrap();
+        for fill in fills {
+            ticker.record_trade(time, fill.price, fill.quantity);
+            let moved = match state.record_trade(time, fill.price, breaker) {
+                Some(moved) if !state.is_halted(time) => moved,
+                _ => continue,
+            };
+            let breaker = breaker.unwrap();
+            let until = time + Duration::seconds(breaker.halt_secs as i64);
+            state.halt(until);
+            info!(%pair, %until, %moved, "circuit breaker tripped");
+            self.market_events.push(MarketEvent::Halted {
+                pair: pair.clone(),
+                time,
+                until,
+                reason: format!(
+                    "Price moved {}% within {}s",
+                    moved.round_dp(2),
+                    breaker.window_secs
+                ),
+            });
+        }
+    }
+
+    /// Applies the client rate limit, if any, to orders the client sent.
+    fn throttle(&mut self, order: &Order, origin: Origin) -> Result<(), String> {
+        match (&mut self.rate_limiter, origin) {
+            (Some(limiter), Origin::Client) => limiter.acquire(&order.client, order.event_time),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the order against the limits set for its client and account,
+    /// on top of the market's, recording a risk event when one blocks it.
+    /// The resting order `replacing` doesn't count towards the exposure.
+    fn check_client_risk(
+        &mut self,
+        pair: &TradingPair,
+        order: &Order,
+        priced: bool,
+        replacing: Option<u64>,
+    ) -> Result<(), String> {
+        let exposure = self.exposure(pair, order, replacing);
+        let result = self.risk_check(pair, order, priced, &exposure);
+        if let Err(reason) = &result {
+            self.risk_events.push(RiskEvent::OrderBlocked {
+                client: order.client.clone(),
+                pair: pair.clone(),
+                exchange_id: order.exchange_id,
+                reason: reason.clone(),
+                time: order.event_time,
+            });
+        }
+        result
+    }
+
+    /// Checks the order against the market's and its client's limits, given
+    /// what the client already has on.
+    fn risk_check(
+        &self,
+        pair: &TradingPair,
+        order: &Order,
+        priced: bool,
+        exposure: &Exposure,
+    ) -> Result<(), String> {
+        let market_limits = self.market_configs[pair]
+            .risk
+            .as_ref()
+            .unwrap_or(&self.risk_limits);
+        let client_limits = [
+            self.accounts.risk_limits(&order.client),
+            self.client_limits.get(&order.client),
+        ];
+        // Limits are in the quote currency, so prices are scaled to the
+        // value of one unit of quantity.
+        let price = priced.then_some(order.limit_price * self.instruments.multiplier(pair));
+
+        client_limits
+            .iter()
+            .flatten()
+            .try_for_each(|limits| match price {
+                Some(price) => limits
+                    .check(price, order.shares)
+                    .map_err(|rejection| rejection.to_string()),
+                None => Ok(()),
+            })
+            .and_then(|()| {
+                [Some(market_limits)]
+                    .iter()
+                    .chain(&client_limits)
+                    .flatten()
+                    .try_for_each(|limits| {
+                        limits.check_exposure(exposure, order.order_type, order.shares, price)
+                    })
+            })
+    }
+
+    /// What `order`'s client has on before the order is placed, leaving out
+    /// the resting order `replacing`.
+    fn exposure(&self, pair: &TradingPair, order: &Order, replacing: Option<u64>) -> Exposure {
+        let mut exposure = Exposure {
+            position: self.positions.position(&order.client, pair),
+            ..Exposure::default()
+        };
+        for (market, orderbook) in &self.orderbooks {
+            let resting = orderbook
+                .client_orders
+                .get(&order.client)
+                .into_iter()
+                .flatten()
+                .filter(|exchange_id| Some(**exchange_id) != replacing)
+                .filter_map(|exchange_id| orderbook.get_order(*exchange_id));
+            for resting in resting {
+                let notional = self.instruments.notional(
+                    market,
+                    resting.limit_price,
+                    resting.remaining_quantity,
+                );
+                self.add_notional(&mut exposure, pair, market, notional);
+                if market == pair {
+                    exposure.open_orders += 1;
+                    if resting.order_type == order.order_type {
+                        exposure.resting += resting.remaining_quantity;
+                    }
+                }
+            }
+        }
+        for (market, position) in self.positions.account(&order.client) {
+            let mark_price = self.pricing.mark_price(market);
+            let notional =
+                self.instruments
+                    .notional(market, mark_price.unwrap_or_default(), position.abs());
+            self.add_notional(&mut exposure, pair, market, notional);
+        }
+        exposure
+    }
+
+    /// Adds `notional`, in `market`'s quote currency, to the exposure of an
+    /// order on `pair`, whose limits are in `pair`'s quote currency.
+    fn add_notional(
+        &self,
+        exposure: &mut Exposure,
+        pair: &TradingPair,
+        market: &TradingPair,
+        notional: Decimal,
+    ) {
+        match self.rates.rate(market.quote(), pair.quote()) {
+            Some(rate) => {
+                exposure.notional = exposure
+                    .notional
+                    .saturating_add(notional.saturating_mul(rate))
+            }
+            None => {
+                exposure.unconverted.insert(market.quote().to_string());
+            }
+        }
+    }
+
+    fn check_expiry(
+        instruments: &Instruments,
+        pair: &TradingPair,
+        now: DateTime<Utc>,
+    ) -> Result<(), String> {
+        match instruments.get(pair) {
+            Some(instrument) if instrument.is_expired(now) => Err(format!(
+                "Instrument {} expired at {}",
+                pair,
+                instrument.expiry.unwrap()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_account(
+        accounts: &Accounts,
+        killed_clients: &HashSet<String>,
+        order: &Order,
+    ) -> Result<(), String> {
+        if !accounts.is_empty() && !accounts.contains(&order.client) {
+            return Err(format!("Unknown account: {:?}", order.client));
+        }
+        if killed_clients.contains(&order.client) {
+            return Err(format!("Kill switch is engaged for {:?}", order.client));
+        }
+        Ok(())
+    }
+
+    /// Shrinks a reduce-only order to the client's position, less what their
+    /// resting reduce-only orders on the same side could already close.
+    /// Rejects it when nothing is left to reduce.
+    fn cap_reduce_only(
+        positions: &Positions,
+        orderbook: &LimitOrderBook,
+        pair: &TradingPair,
+        order: &mut Order,
+        replacing: Option<u64>,
+    ) -> Result<(), String> {
+        if !order.reduce_only {
+            return Ok(());
+        }
+        let allowed = Self::reducible(positions, orderbook, pair, order, replacing);
+        if allowed <= Decimal::ZERO {
+            return Err(format!(
+                "Reduce-only order would increase the position of {:?}",
+                order.client
+            ));
+        }
+        if order.shares > allowed {
+            order.shares = allowed;
+            order.remaining_quantity = allowed;
+        }
+        Ok(())
+    }
+
+    /// What a reduce-only order from `order`'s client could still close:
+    /// their position less their resting reduce-only orders on the same
+    /// side, leaving out the order `replacing`.
+    fn reducible(
+        positions: &Positions,
+        orderbook: &LimitOrderBook,
+        pair: &TradingPair,
+        order: &Order,
+        replacing: Option<u64>,
+    ) -> Decimal {
+        let resting: Decimal = orderbook
+            .client_orders
+            .get(&order.client)
+            .into_iter()
+            .flatten()
+            .filter(|exchange_id| Some(**exchange_id) != replacing)
+            .filter_map(|exchange_id| orderbook.get_order(*exchange_id))
+            .filter(|resting| resting.reduce_only && resting.order_type == order.order_type)
+            .map(|resting| resting.remaining_quantity)
+            .sum();
+        positions.reducible(&order.client, pair, order.order_type) - resting
+    }
+
+    fn check_short_sale(
+        borrow_check: &mut Option<Box<dyn BorrowCheck>>,
+        pair: &TradingPair,
+        order: &Order,
+    ) -> Result<(), String> {
+        if !order.short_sale {
+            return Ok(());
+        }
+        if order.order_type != OrderType::Ask {
+            return Err("Only sell orders can be marked short".to_string());
+        }
+        if let Some(borrow_check) = borrow_check {
+            if !borrow_check.locate(&order.client, pair, order.shares) {
+                return Err(format!(
+                    "No borrow available for short sale of {} {}",
+                    order.shares, pair.base
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Charges both sides of each fill under the pair's fee schedule,
+    /// recording the fees on the fills and on the orders involved. A fill
+    /// can't be refused, so a notional out of range saturates.
+    fn charge_fees(&mut self, pair: &TradingPair, taker: &mut Order, fills: &mut [Fill]) {
+        let config = &self.market_configs[pair];
+        let (schedule, precision) = (&config.fees, &config.precision);
+        let multiplier = self.instruments.multiplier(pair);
+        let orderbook = self.orderbooks.get_mut(pair).unwrap();
+        let time = taker.event_time;
+        let taker_account = AccountId::from(taker.client.as_str());
+
+        for fill in fills.iter_mut() {
+            let notional = arithmetic::notional(fill.price, fill.quantity, multiplier)
+                .unwrap_or_else(|overflow| {
+                    warn!(%overflow, "fill notional saturated");
+                    Decimal::MAX
+                });
+            fill.maker_fee = self.fee_ledger.charge(
+                schedule,
+                precision,
+                &fill.maker_client.as_str().into(),
+                Liquidity::Maker,
+                notional,
+                time,
+            );
+            fill.taker_fee = self.fee_ledger.charge(
+                schedule,
+                precision,
+                &taker_account,
+                Liquidity::Taker,
+                notional,
+                time,
+            );
+            orderbook.charge_fee(fill.maker_id, fill.maker_fee);
+            taker.fees += fill.taker_fee;
+        }
+        if !fills.is_empty() {
+            // A taker with a resting remainder is already on the book.
+            orderbook.charge_fee(taker.exchange_id, taker.fees);
+        }
+    }
+
+    /// Quantity ahead of a resting order at its price level.
+    pub fn queue_position(&self, pair: &TradingPair, exchange_id: u64) -> Result<Decimal, String> {
+        match self.orderbooks.get(pair) {
+            Some(orderbook) => orderbook
+                .queue_position(exchange_id)
+                .ok_or_else(|| format!("No resting order with id: {}", exchange_id)),
+            None => Err(format!("No orderbook for trading pair: {}", pair)),
+        }
+    }
+
+    pub fn cancel_order(&mut self, pair: &TradingPair, exchange_id: u64) -> Result<Order, String> {
+        self.cancel_order_for(pair, exchange_id, CancelReason::Client)
+    }
+
+    fn cancel_order_for(
+        &mut self,
+        pair: &TradingPair,
+        exchange_id: u64,
+        reason: CancelReason,
+    ) -> Result<Order, String> {
+        match self.orderbooks.get(pair) {
+            Some(_) => {
+                let order = self
+                    .cancel_resting(pair, exchange_id, reason)
+                    .ok_or_else(|| format!("No resting order with id: {}", exchange_id))?;
+                info!(%pair, exchange_id, "cancelled");
+                if let Some(brackets) = self.brackets.get_mut(pair) {
+                    brackets.on_cancel(exchange_id, &mut self.bracket_events);
+                }
+                self.book_changed(pair);
+                Ok(order)
+            }
+            None => Err(format!("No orderbook for trading pair: {}", pair)),
+        }
+    }
+}
+
+/// Who an order came from. Orders the engine generates itself, like
+/// bracket exits, are not counted against the client's rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Origin {
+    Client,
+    Engine,
+}
+
+/// One span per order, covering everything from receipt to the report.
+fn order_span(pair: &TradingPair, order: &Order, kind: &'static str) -> tracing::Span {
+    info_span!(
+        "order",
+        exchange_id = order.exchange_id,
+        %pair,
+        side = ?order.order_type,
+        client = %order.client,
+        kind,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruments::Instrument,
+        limit_order_book::{allocation::Matching, prune::PrunePolicy},
+        matching_engine::{
+            audit::AuditEvent,
+            bands::{CircuitBreaker, PriceBand, ReferenceKind},
+            basket::Basket,
+            calendar::{Calendar, Session},
+            fees::{FeeSchedule, Liquidity},
+            funding::FundingConfig,
+            insurance::LossFallback,
+            liquidation::{LiquidationEvent, MarginConfig},
+            midpoint::MidpointConfig,
+            peg::PegReference,
+            precision::{Precision, Rounding},
+            rate_limit::RATE_LIMITED,
+            risk::Locates,
+        },
+    };
+    use chrono::{NaiveTime, TimeZone};
+    use rust_decimal_macros::dec;
+
+    fn order(
+        engine: &mut MatchingEngine,
+        order_type: OrderType,
+        shares: Decimal,
+        price: Decimal,
+    ) -> Order {
+        Order::new(
+            "BTC/USDT".to_string(),
+            engine.next_exchange_id(),
+            order_type,
+            shares,
+            price,
+            Utc::now(),
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn test_trading_pair_from_str() {
+        let pair: TradingPair = "BTC/USDT".parse().unwrap();
+        assert_eq!(
+            pair,
+            TradingPair::new("BTC".to_string(), "USDT".to_string())
+        );
+        for invalid in ["BTC", "BTCUSDT", "BTC/", "/USDT", "A/B/C", "BTC/US DT", ""] {
+            assert_eq!(
+                invalid.parse::<TradingPair>(),
+                Err(format!("Invalid trading pair: {:?}", invalid))
+            );
+        }
+
+        // Assets are kept in upper case, however they are written.
+        let lower: TradingPair = "btc/usdt".parse().unwrap();
+        assert_eq!(lower, pair);
+        assert_eq!(lower.base(), "BTC");
+        assert_eq!(lower.quote(), "USDT");
+        assert_eq!(lower.to_string(), "BTC/USDT");
+        assert_eq!(
+            serde_json::from_str::<TradingPair>(r#""btc/usdt""#).unwrap(),
+            pair
+        );
+
+        let perp: TradingPair = " btc-perp/usd ".parse().unwrap();
+        assert_eq!(perp.to_string(), "BTC-PERP/USD");
+        assert_eq!(format!("{}", perp), "BTC-PERP/USD");
+        let json = serde_json::to_string(&perp).unwrap();
+        assert_eq!(json, r#""BTC-PERP/USD""#);
+        assert_eq!(serde_json::from_str::<TradingPair>(&json).unwrap(), perp);
+        for invalid in [r#""BTC""#, r#""A/B/C""#, r#"{"base":"BTC","quote":"USDT"}"#] {
+            assert!(serde_json::from_str::<TradingPair>(invalid).is_err());
+        }
+        // Keyed maps serialize with the pair as a string too.
+        let keyed = HashMap::from([(pair.clone(), 1)]);
+        let json = serde_json::to_string(&keyed).unwrap();
+        assert_eq!(json, r#"{"BTC/USDT":1}"#);
+        assert_eq!(
+            serde_json::from_str::<HashMap<TradingPair, i32>>(&json).unwrap(),
+            keyed
+        );
+    }
+
+    #[test]
+    fn test_engine_from_config() {
+        let path =
+            std::env::temp_dir().join(format!("tradebot-config-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[risk]\nmax_order_quantity = \"10\"\n\n[feed]\nl3 = \"full\"\n\n[[markets]]\npair = \"BTC/USDT\"\ntick_size = \"0.5\"\n",
+        )
+        .unwrap();
+        let mut engine = MatchingEngine::from_config(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let pair: TradingPair = "BTC/USDT".parse().unwrap();
+        assert_eq!(engine.markets().collect::<Vec<_>>(), vec![&pair]);
+        assert_eq!(
+            engine.market_config(&pair).unwrap().tick_size,
+            Some(dec!(0.5))
+        );
+
+        let off_tick = order(&mut engine, OrderType::Bid, dec!(1), dec!(100.2));
+        assert!(engine.place_limit_order(pair.clone(), off_tick).is_err());
+        let too_large = order(&mut engine, OrderType::Bid, dec!(11), dec!(100.5));
+        assert!(engine.place_limit_order(pair.clone(), too_large).is_err());
+        let valid = order(&mut engine, OrderType::Bid, dec!(10), dec!(100.5));
+        assert!(engine.place_limit_order(pair.clone(), valid).is_ok());
+        let l3 = engine.drain_l3_events(&pair).unwrap();
+        assert!(matches!(&l3[..], [L3Event::Add { order_id, .. }] if *order_id == 3));
+
+        assert!(MatchingEngine::from_config("/nonexistent/tradebot.toml").is_err());
+    }
+
+    #[test]
+    fn test_place_and_cancel_limit_orders() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+
+        let ask = order(&mut engine, OrderType::Ask, dec!(5), dec!(100));
+        let (ask, fills) = engine.place_limit_order(pair.clone(), ask).unwrap();
+        assert!(fills.is_empty());
+        assert_eq!(ask.status, OrderStatus::New);
+        assert!(engine.drain_l3_events(&pair).is_err());
+        assert_eq!(engine.queue_position(&pair, ask.exchange_id), Ok(dec!(0)));
+
+        let bid = order(&mut engine, OrderType::Bid, dec!(8), dec!(101));
+        let (bid, fills) = engine.place_limit_order(pair.clone(), bid).unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].price, dec!(100));
+        assert_eq!(bid.status, OrderStatus::PartiallyFilled);
+
+        let book = engine.orderbook(&pair).unwrap();
+        assert_eq!(book.get_best_bid(), Some(dec!(101)));
+        assert_eq!(book.get_bid_depth(dec!(101)), dec!(3));
+        assert_eq!(book.get_best_ask(), None);
+
+        let cancelled = engine.cancel_order(&pair, bid.exchange_id).unwrap();
+        assert_eq!(cancelled.status, OrderStatus::Cancelled);
+        assert_eq!(cancelled.filled_quantity, dec!(5));
+        assert!(engine.cancel_order(&pair, bid.exchange_id).is_err());
+
+        let ask = order(&mut engine, OrderType::Ask, dec!(2), dec!(120));
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let market = order(&mut engine, OrderType::Bid, dec!(5), dec!(1));
+        let fills = engine.execute_market_order(pair.clone(), market).unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].price, dec!(120));
+        assert_eq!(fills[0].quantity, dec!(2));
+        assert_eq!(engine.orderbook(&pair).unwrap().get_best_ask(), None);
+
+        let unknown = TradingPair::new("ETH".to_string(), "USDT".to_string());
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(1));
+        assert!(engine.place_limit_order(unknown, bid).is_err());
+    }
+
+    #[test]
+    fn test_maker_taker_fees() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut market = MarketConfig::new(pair.clone());
+        market.fees = FeeSchedule::new(dec!(-1), dec!(5));
+        let mut engine = MatchingEngine::new();
+        engine.add_market(market);
+
+        let ask = order(&mut engine, OrderType::Ask, dec!(10), dec!(100)).with_client("maker");
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let bid = order(&mut engine, OrderType::Bid, dec!(4), dec!(100)).with_client("taker");
+        let (bid, fills) = engine.place_limit_order(pair.clone(), bid).unwrap();
+
+        assert_eq!(fills[0].maker_client, "maker");
+        assert_eq!(fills[0].maker_fee, dec!(-0.04));
+        assert_eq!(fills[0].taker_fee, dec!(0.2));
+        assert_eq!(bid.fees, dec!(0.2));
+        assert_eq!(bid.execution_report(dec!(4), dec!(100)).cum_fee, dec!(0.2));
+        let resting = engine.orderbook(&pair).unwrap().get_order(1).unwrap();
+        assert_eq!(resting.fees, dec!(-0.04));
+
+        // The taker's remainder rests carrying the fees it already paid.
+        let bid = order(&mut engine, OrderType::Bid, dec!(8), dec!(100)).with_client("taker");
+        let (bid, _) = engine.place_limit_order(pair.clone(), bid).unwrap();
+        let resting = engine
+            .orderbook(&pair)
+            .unwrap()
+            .get_order(bid.exchange_id)
+            .unwrap();
+        assert_eq!(resting.fees, dec!(0.3));
+        assert_eq!(engine.orderbook(&pair).unwrap().debug_validate(), Ok(()));
+
+        assert_eq!(engine.fee_ledger().fees_paid("maker"), dec!(-0.1));
+        assert_eq!(engine.fee_ledger().fees_paid("taker"), dec!(0.5));
+    }
+
+    #[test]
+    fn test_short_sales_need_a_borrow() {
+        let pair = TradingPair::new("AAPL".to_string(), "USD".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+
+        let short = order(&mut engine, OrderType::Ask, dec!(50), dec!(100)).with_short_sale(true);
+        assert!(engine.place_limit_order(pair.clone(), short).is_ok());
+
+        engine.set_borrow_check(Locates::new().with_available("AAPL", dec!(80)));
+        let short = order(&mut engine, OrderType::Ask, dec!(50), dec!(100)).with_short_sale(true);
+        assert!(engine.place_limit_order(pair.clone(), short).is_ok());
+        let short = order(&mut engine, OrderType::Ask, dec!(50), dec!(100)).with_short_sale(true);
+        assert!(engine.place_limit_order(pair.clone(), short).is_err());
+        let long = order(&mut engine, OrderType::Ask, dec!(50), dec!(100));
+        assert!(engine.place_limit_order(pair.clone(), long).is_ok());
+
+        let short_buy = order(&mut engine, OrderType::Bid, dec!(1), dec!(90)).with_short_sale(true);
+        assert!(engine.place_limit_order(pair.clone(), short_buy).is_err());
+        let short_market =
+            order(&mut engine, OrderType::Ask, dec!(40), dec!(0)).with_short_sale(true);
+        assert!(engine
+            .execute_market_order(pair.clone(), short_market)
+            .is_err());
+    }
+
+    #[test]
+    fn test_price_bands_and_circuit_breaker() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut market = MarketConfig::new(pair.clone());
+        market.price_band = Some(PriceBand {
+            max_deviation_pct: dec!(20),
+            reference: ReferenceKind::LastTrade,
+        });
+        market.circuit_breaker = Some(CircuitBreaker {
+            max_move_pct: dec!(10),
+            window_secs: 60,
+            halt_secs: 300,
+        });
+        let mut engine = MatchingEngine::new();
+        engine.add_market(market);
+
+        let start = Utc::now();
+        let at = |engine: &mut MatchingEngine, order_type, shares, price, secs| {
+            let time = start + Duration::seconds(secs);
+            let mut order = order(engine, order_type, shares, price);
+            order.entry_time = time;
+            order.event_time = time;
+            order
+        };
+
+        // No trades yet, so there is nothing to band against.
+        let ask = at(&mut engine, OrderType::Ask, dec!(1), dec!(100), 0);
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let bid = at(&mut engine, OrderType::Bid, dec!(1), dec!(100), 1);
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        assert_eq!(
+            engine.market_state(&pair).unwrap().last_trade_price(),
+            Some(dec!(100))
+        );
+
+        let far = at(&mut engine, OrderType::Ask, dec!(1), dec!(121), 2);
+        assert!(engine.place_limit_order(pair.clone(), far).is_err());
+
+        // A 15% move inside the window trips the breaker.
+        let ask = at(&mut engine, OrderType::Ask, dec!(1), dec!(115), 3);
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let bid = at(&mut engine, OrderType::Bid, dec!(1), dec!(115), 4);
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        let events = engine.drain_market_events();
+        assert!(matches!(
+            &events[..],
+            [MarketEvent::Halted { until, .. }] if *until == start + Duration::seconds(304)
+        ));
+
+        let bid = at(&mut engine, OrderType::Bid, dec!(1), dec!(110), 10);
+        assert!(engine.place_limit_order(pair.clone(), bid).is_err());
+        let market = at(&mut engine, OrderType::Bid, dec!(1), dec!(0), 10);
+        assert!(engine.execute_market_order(pair.clone(), market).is_err());
+
+        let bid = at(&mut engine, OrderType::Bid, dec!(1), dec!(110), 304);
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        assert!(matches!(
+            &engine.drain_market_events()[..],
+            [MarketEvent::Resumed { .. }]
+        ));
+
+        engine
+            .halt_market(&pair, start, start + Duration::days(1), "manual")
+            .unwrap();
+        engine.resume_market(&pair, start).unwrap();
+        assert_eq!(engine.drain_market_events().len(), 2);
+    }
+
+    #[test]
+    fn test_band_anchored_on_previous_close() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut market = MarketConfig::new(pair.clone());
+        market.price_band = Some(PriceBand {
+            max_deviation_pct: dec!(10),
+            reference: ReferenceKind::PreviousClose,
+        });
+        let mut engine = MatchingEngine::new();
+        engine.add_market(market);
+        engine.set_previous_close(&pair, dec!(100)).unwrap();
+
+        let ask = order(&mut engine, OrderType::Ask, dec!(1), dec!(111));
+        assert!(engine.place_limit_order(pair.clone(), ask).is_err());
+        let ask = order(&mut engine, OrderType::Ask, dec!(1), dec!(109));
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(109));
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+
+        // The new session is anchored on the last trade.
+        assert_eq!(engine.close_session(&pair).unwrap(), Some(dec!(109)));
+        let ask = order(&mut engine, OrderType::Ask, dec!(1), dec!(119));
+        assert!(engine.place_limit_order(pair.clone(), ask).is_ok());
+    }
+
+    #[test]
+    fn test_pegged_order_tracks_mid() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut market = MarketConfig::new(pair.clone());
+        market.tick_size = Some(dec!(0.5));
+        let mut engine = MatchingEngine::new();
+        engine.add_market(market);
+
+        let peg = Peg::new(PegReference::Mid, dec!(0));
+        let pegged = order(&mut engine, OrderType::Bid, dec!(2), dec!(0));
+        assert!(engine
+            .place_pegged_order(pair.clone(), pegged.clone(), peg)
+            .is_err());
+
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(99));
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        let ask = order(&mut engine, OrderType::Ask, dec!(1), dec!(102));
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let (pegged, _) = engine
+            .place_pegged_order(pair.clone(), pegged, peg)
+            .unwrap();
+        assert_eq!(pegged.limit_price, dec!(100.5));
+
+        // A tighter ask drags the mid, and the peg, down.
+        let ask = order(&mut engine, OrderType::Ask, dec!(1), dec!(101));
+        let ask_id = ask.exchange_id;
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let book = engine.orderbook(&pair).unwrap();
+        assert_eq!(
+            book.get_order(pegged.exchange_id).unwrap().limit_price,
+            dec!(100)
+        );
+
+        // Takers hit the pegged bid at its current price.
+        let sell = order(&mut engine, OrderType::Ask, dec!(1), dec!(100));
+        let (_, fills) = engine.place_limit_order(pair.clone(), sell).unwrap();
+        assert_eq!(fills[0].maker_id, pegged.exchange_id);
+        assert_eq!(fills[0].price, dec!(100));
+
+        // The remainder moves back out when the tighter ask goes.
+        engine.cancel_order(&pair, ask_id).unwrap();
+        let book = engine.orderbook(&pair).unwrap();
+        let pegged = book.get_order(pegged.exchange_id).unwrap();
+        assert_eq!(pegged.limit_price, dec!(100.5));
+        assert_eq!(pegged.remaining_quantity, dec!(1));
+    }
+
+    #[test]
+    fn test_bracket_order_lifecycle() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let bracket = Bracket::new(dec!(110), dec!(95));
+
+        let ask = order(&mut engine, OrderType::Ask, dec!(3), dec!(100));
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let entry = order(&mut engine, OrderType::Bid, dec!(5), dec!(100)).with_client("alice");
+        let entry_id = entry.exchange_id;
+        assert!(engine
+            .place_bracket_order(
+                pair.clone(),
+                entry.clone(),
+                Bracket::new(dec!(95), dec!(110))
+            )
+            .is_err());
+        engine
+            .place_bracket_order(pair.clone(), entry, bracket)
+            .unwrap();
+        assert_eq!(
+            engine.orderbook(&pair).unwrap().get_ask_depth(dec!(110)),
+            dec!(3)
+        );
+
+        // The rest of the entry fills and the take-profit grows with it.
+        let ask = order(&mut engine, OrderType::Ask, dec!(2), dec!(100));
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        assert_eq!(
+            engine.orderbook(&pair).unwrap().get_ask_depth(dec!(110)),
+            dec!(5)
+        );
+
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(110));
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+
+        // A trade at the stop sells the remaining 4 and pulls the take-profit.
+        let bid = order(&mut engine, OrderType::Bid, dec!(10), dec!(95));
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        let ask = order(&mut engine, OrderType::Ask, dec!(1), dec!(95));
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let book = engine.orderbook(&pair).unwrap();
+        assert_eq!(book.get_ask_depth(dec!(110)), dec!(0));
+        assert_eq!(book.get_bid_depth(dec!(95)), dec!(5));
+
+        let events = engine.drain_bracket_events();
+        assert!(events.iter().all(|event| match event {
+            BracketEvent::EntryFilled {
+                entry_id: id,
+                client,
+                ..
+            }
+            | BracketEvent::Closed {
+                entry_id: id,
+                client,
+            } => *id == entry_id && client == "alice",
+            _ => true,
+        }));
+        assert!(matches!(
+            &events[..],
+            [
+                BracketEvent::EntryFilled { .. },
+                BracketEvent::TakeProfitPlaced { .. },
+                BracketEvent::EntryFilled { .. },
+                BracketEvent::TakeProfitPlaced { .. },
+                BracketEvent::TakeProfitFilled { .. },
+                BracketEvent::StopTriggered { quantity, .. },
+                BracketEvent::Closed { .. },
+            ] if *quantity == dec!(4)
+        ));
+    }
+
+    #[test]
+    fn test_reduce_only_caps_to_position() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+
+        let close = order(&mut engine, OrderType::Ask, dec!(1), dec!(100))
+            .with_client("alice")
+            .with_reduce_only(true);
+        assert!(engine.place_limit_order(pair.clone(), close).is_err());
+
+        let ask = order(&mut engine, OrderType::Ask, dec!(3), dec!(100)).with_client("bob");
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let bid = order(&mut engine, OrderType::Bid, dec!(3), dec!(100)).with_client("alice");
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        assert_eq!(engine.positions().position("alice", &pair), dec!(3));
+
+        // Only 3 of the 5 can go; the resting 2 then leave 1 for the next.
+        let close = order(&mut engine, OrderType::Ask, dec!(5), dec!(105))
+            .with_client("alice")
+            .with_reduce_only(true);
+        let (close, _) = engine.place_limit_order(pair.clone(), close).unwrap();
+        assert_eq!(close.remaining_quantity, dec!(3));
+        let close = order(&mut engine, OrderType::Ask, dec!(1), dec!(106))
+            .with_client("alice")
+            .with_reduce_only(true);
+        assert!(engine.place_limit_order(pair.clone(), close).is_err());
+
+        let bid = order(&mut engine, OrderType::Bid, dec!(10), dec!(0)).with_client("bob");
+        let fills = engine.execute_market_order(pair.clone(), bid).unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(engine.positions().position("alice", &pair), dec!(0));
+        assert_eq!(engine.positions().position("bob", &pair), dec!(0));
+    }
+
+    #[test]
+    fn test_accounts_own_orders() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let desk = AccountId::from("desk");
+        let limits = RiskLimits {
+            max_order_quantity: Some(dec!(5)),
+            ..RiskLimits::default()
+        };
+        engine.accounts_mut().open(desk.clone(), None).unwrap();
+        engine
+            .accounts_mut()
+            .open(desk.sub("arb"), Some(limits))
+            .unwrap();
+        engine.accounts_mut().open("other".into(), None).unwrap();
+
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(99)).with_client("nobody");
+        let error = engine.place_limit_order(pair.clone(), bid).unwrap_err();
+        assert!(error.contains("Unknown account"));
+        let bid = order(&mut engine, OrderType::Bid, dec!(6), dec!(99)).with_client("desk/arb");
+        assert!(engine.place_limit_order(pair.clone(), bid).is_err());
+        let bid = order(&mut engine, OrderType::Bid, dec!(5), dec!(99)).with_client("desk/arb");
+        let bid_id = bid.exchange_id;
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+
+        assert_eq!(engine.account_orders("desk/arb").len(), 1);
+        assert!(engine.account_orders("desk").is_empty());
+        assert!(engine.cancel_account_order("other", &pair, bid_id).is_err());
+        assert!(engine.cancel_account_order("desk", &pair, bid_id).is_ok());
+    }
+
+    #[test]
+    fn test_rate_limit_spares_engine_orders() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        engine.set_rate_limit(Some(RateLimit {
+            orders_per_second: dec!(1),
+            burst: dec!(2),
+        }));
+        let now = Utc::now();
+        let at_now = |engine: &mut MatchingEngine, side, quantity, price| {
+            let mut order = order(engine, side, quantity, price).with_client("alice");
+            order.event_time = now;
+            order
+        };
+
+        let ask = at_now(&mut engine, OrderType::Ask, dec!(1), dec!(100)).with_client("bob");
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        // The entry fills at once and its take-profit goes out unthrottled.
+        let entry = at_now(&mut engine, OrderType::Bid, dec!(1), dec!(100));
+        engine
+            .place_bracket_order(pair.clone(), entry, Bracket::new(dec!(110), dec!(90)))
+            .unwrap();
+        assert_eq!(
+            engine.orderbook(&pair).unwrap().get_ask_depth(dec!(110)),
+            dec!(1)
+        );
+
+        let bid = at_now(&mut engine, OrderType::Bid, dec!(1), dec!(99));
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        let bid = at_now(&mut engine, OrderType::Bid, dec!(1), dec!(99));
+        let error = engine.place_limit_order(pair.clone(), bid).unwrap_err();
+        assert!(error.starts_with(RATE_LIMITED));
+        assert_eq!(engine.metrics().orders_rejected.get(), 1);
+    }
+
+    #[test]
+    fn test_client_exposure_limits() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        engine.set_client_limits(
+            "alice",
+            Some(RiskLimits {
+                max_open_orders: Some(2),
+                max_position: Some(dec!(5)),
+                ..RiskLimits::default()
+            }),
+        );
+
+        for _ in 0..2 {
+            let bid = order(&mut engine, OrderType::Bid, dec!(2), dec!(99)).with_client("alice");
+            engine.place_limit_order(pair.clone(), bid).unwrap();
+        }
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(98)).with_client("alice");
+        let error = engine.place_limit_order(pair.clone(), bid).unwrap_err();
+        assert!(error.contains("Open orders"));
+        // Bob has no client limits.
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(98)).with_client("bob");
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+
+        // 4 resting to buy plus 2 more would take alice to 6.
+        let ask = order(&mut engine, OrderType::Ask, dec!(10), dec!(101)).with_client("carol");
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let buy = order(&mut engine, OrderType::Bid, dec!(2), dec!(0)).with_client("alice");
+        assert!(engine.execute_market_order(pair.clone(), buy).is_err());
+        let buy = order(&mut engine, OrderType::Bid, dec!(1), dec!(0)).with_client("alice");
+        engine.execute_market_order(pair.clone(), buy).unwrap();
+
+        let events = engine.drain_risk_events();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            &events[1],
+            RiskEvent::OrderBlocked { client, reason, .. }
+                if client == "alice" && reason.contains("Position 6")
+        ));
+
+        // Raising the limit at runtime lets the order through.
+        engine.set_client_limits(
+            "alice",
+            Some(RiskLimits {
+                max_notional_exposure: Some(dec!(1000)),
+                ..RiskLimits::default()
+            }),
+        );
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(98)).with_client("alice");
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        // 99 * 4 + 98 resting plus 101 held: 595 so far.
+        let bid = order(&mut engine, OrderType::Bid, dec!(5), dec!(98)).with_client("alice");
+        assert!(engine.place_limit_order(pair.clone(), bid).is_err());
+    }
+
+    #[test]
+    fn test_exposure_converts_quote_currencies() {
+        let usd = TradingPair::new("AAPL".to_string(), "USD".to_string());
+        let eur = TradingPair::new("SAP".to_string(), "EUR".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(usd.clone());
+        engine.add_new_market(eur.clone());
+        engine.set_client_limits(
+            "alice",
+            Some(RiskLimits {
+                max_notional_exposure: Some(dec!(1000)),
+                ..RiskLimits::default()
+            }),
+        );
+
+        let bid = order(&mut engine, OrderType::Bid, dec!(4), dec!(100)).with_client("alice");
+        engine.place_limit_order(eur.clone(), bid).unwrap();
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(100)).with_client("alice");
+        let error = engine.place_limit_order(usd.clone(), bid).unwrap_err();
+        assert_eq!(error, "No rate to value EUR exposure");
+
+        // 400 EUR is 500 USD, so another 600 USD is too much.
+        engine.set_rate("EUR", "USD", dec!(1.25));
+        let bid = order(&mut engine, OrderType::Bid, dec!(6), dec!(100)).with_client("alice");
+        let error = engine.place_limit_order(usd.clone(), bid).unwrap_err();
+        assert!(error.contains("Notional exposure 1100"));
+        let bid = order(&mut engine, OrderType::Bid, dec!(5), dec!(100)).with_client("alice");
+        engine.place_limit_order(usd.clone(), bid).unwrap();
+
+        // Valued in EUR, the same orders come to 800.
+        let bid = order(&mut engine, OrderType::Bid, dec!(2), dec!(100)).with_client("alice");
+        engine.place_limit_order(eur.clone(), bid).unwrap();
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(1)).with_client("alice");
+        let error = engine.place_limit_order(eur.clone(), bid).unwrap_err();
+        assert!(error.contains("Notional exposure 1001"));
+    }
+
+    #[test]
+    fn test_kill_switch() {
+        let btc = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let eth = TradingPair::new("ETH".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(btc.clone());
+        engine.add_new_market(eth.clone());
+
+        for pair in [&btc, &eth] {
+            let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(99)).with_client("alice");
+            engine.place_limit_order(pair.clone(), bid).unwrap();
+        }
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(98)).with_client("bob");
+        engine.place_limit_order(btc.clone(), bid).unwrap();
+
+        let cancelled = engine.kill_switch("alice");
+        assert_eq!(cancelled.len(), 2);
+        assert!(cancelled
+            .iter()
+            .all(|(_, order)| order.status == OrderStatus::Cancelled));
+        assert!(engine.account_orders("alice").is_empty());
+        assert_eq!(engine.account_orders("bob").len(), 1);
+
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(99)).with_client("alice");
+        let error = engine
+            .place_limit_order(btc.clone(), bid.clone())
+            .unwrap_err();
+        assert!(error.contains("Kill switch"));
+        assert!(engine.is_killed("alice"));
+
+        assert!(engine.enable_client("alice"));
+        assert!(!engine.enable_client("alice"));
+        engine.place_limit_order(btc.clone(), bid).unwrap();
+    }
+
+    #[test]
+    fn test_drop_copy_sees_every_client() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let drop_copy = engine.subscribe_drop_copy();
+
+        let ask = order(&mut engine, OrderType::Ask, dec!(5), dec!(100)).with_client("alice");
+        let ask_id = ask.exchange_id;
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let bid = order(&mut engine, OrderType::Bid, dec!(2), dec!(100)).with_client("bob");
+        let (bid, _) = engine.place_limit_order(pair.clone(), bid).unwrap();
+        engine.cancel_order(&pair, ask_id).unwrap();
+
+        let reports: Vec<DropCopy> = drop_copy.try_iter().collect();
+        assert_eq!(reports.len(), 3);
+        assert!(matches!(
+            &reports[0],
+            DropCopy::Execution {
+                exchange_id,
+                liquidity: Liquidity::Maker,
+                leaves_quantity,
+                ..
+            } if *exchange_id == ask_id && *leaves_quantity == dec!(3)
+        ));
+        assert!(matches!(
+            &reports[1],
+            DropCopy::Execution {
+                exchange_id,
+                liquidity: Liquidity::Taker,
+                leaves_quantity,
+                ..
+            } if *exchange_id == bid.exchange_id && leaves_quantity.is_zero()
+        ));
+        assert_eq!(
+            reports[2],
+            DropCopy::Cancel {
+                pair: pair.clone(),
+                exchange_id: ask_id,
+                client: "alice".to_string(),
+                strategy: None,
+                side: OrderType::Ask,
+                price: dec!(100),
+                cancelled_quantity: dec!(3),
+            }
+        );
+        assert_eq!(
+            reports.iter().map(DropCopy::client).collect::<Vec<_>>(),
+            ["alice", "bob", "alice"]
+        );
+    }
+
+    #[test]
+    fn test_place_orders() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let drop_copy = engine.subscribe_drop_copy();
+        let resting = order(&mut engine, OrderType::Bid, dec!(1), dec!(99));
+        let resting_id = resting.exchange_id;
+        engine.place_limit_order(pair.clone(), resting).unwrap();
+
+        // Pull the old bid and quote a fresh ladder, one rung of which
+        // crosses the new ask.
+        let mut commands = vec![EngineCommand::Cancel {
+            pair: pair.clone(),
+            exchange_id: resting_id,
+        }];
+        for (side, price) in [
+            (OrderType::Ask, dec!(101)),
+            (OrderType::Ask, dec!(102)),
+            (OrderType::Bid, dec!(101)),
+            (OrderType::Bid, dec!(98)),
+        ] {
+            commands.push(EngineCommand::Limit {
+                pair: pair.clone(),
+                order: order(&mut engine, side, dec!(1), price),
+            });
+        }
+        commands.push(EngineCommand::Cancel {
+            pair: pair.clone(),
+            exchange_id: resting_id,
+        });
+
+        let results = engine.place_orders(&commands);
+        assert_eq!(results.len(), 6);
+        assert!(matches!(results[0], Ok(CommandOutcome::Cancelled { .. })));
+        assert!(matches!(
+            &results[3],
+            Ok(CommandOutcome::Placed { fills, .. }) if fills.len() == 1
+        ));
+        assert!(results[5].is_err());
+        // Marked once, on the final book: between its mid of 100 and the
+        // trade at 101.
+        assert_eq!(engine.mark_price(&pair), Some(dec!(100.5)));
+        let reports: Vec<DropCopy> = drop_copy.try_iter().collect();
+        assert_eq!(reports.len(), 3);
+    }
+
+    #[test]
+    fn test_quotes() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let quote = |bid: Decimal, ask: Decimal| Quote {
+            client: "alice".to_string(),
+            bid: Some(QuoteSide {
+                price: bid,
+                size: dec!(2),
+            }),
+            ask: Some(QuoteSide {
+                price: ask,
+                size: dec!(2),
+            }),
+            time: Utc::now(),
+        };
+
+        let report = engine
+            .place_quote(pair.clone(), quote(dec!(99), dec!(101)))
+            .unwrap();
+        assert!(report.cancelled.is_empty());
+        let lift = order(&mut engine, OrderType::Bid, dec!(1), dec!(101)).with_client("bob");
+        engine.place_limit_order(pair.clone(), lift).unwrap();
+
+        let report = engine
+            .place_quote(pair.clone(), quote(dec!(98.5), dec!(100.5)))
+            .unwrap();
+        let cancelled: Vec<Decimal> = report
+            .cancelled
+            .iter()
+            .map(|order| order.filled_quantity)
+            .collect();
+        assert_eq!(cancelled, vec![dec!(0), dec!(1)]);
+        let (bid, ask) = engine.quote(&pair, "alice");
+        assert_eq!(bid.unwrap().limit_price, dec!(98.5));
+        assert_eq!(ask.unwrap().limit_price, dec!(100.5));
+        let orderbook = engine.orderbook(&pair).unwrap();
+        assert_eq!(
+            (orderbook.get_best_bid(), orderbook.get_best_ask()),
+            (Some(dec!(98.5)), Some(dec!(100.5)))
+        );
+
+        // A crossed quote is turned down whole and the old one stays.
+        assert!(engine
+            .place_quote(pair.clone(), quote(dec!(101), dec!(100)))
+            .is_err());
+        assert!(engine.quote(&pair, "alice").1.is_some());
+
+        let mut bid_only = quote(dec!(99), dec!(100));
+        bid_only.ask = None;
+        let report = engine.place_quote(pair.clone(), bid_only).unwrap();
+        assert_eq!(report.cancelled.len(), 2);
+        assert!(report.ask.is_none());
+        let (bid, ask) = engine.quote(&pair, "alice");
+        assert_eq!((bid.unwrap().limit_price, ask), (dec!(99), None));
+    }
+
+    #[test]
+    fn test_strategy_attribution() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let drop_copy = engine.subscribe_drop_copy();
+
+        let ask = order(&mut engine, OrderType::Ask, dec!(3), dec!(100))
+            .with_client("alice")
+            .with_strategy("mm");
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let bid = order(&mut engine, OrderType::Bid, dec!(2), dec!(100))
+            .with_client("bob")
+            .with_strategy("momentum");
+        let (_, fills) = engine.place_limit_order(pair.clone(), bid).unwrap();
+        assert_eq!(fills[0].maker_strategy.as_deref(), Some("mm"));
+        let untagged = order(&mut engine, OrderType::Bid, dec!(1), dec!(100)).with_client("bob");
+        engine.place_limit_order(pair.clone(), untagged).unwrap();
+
+        let strategies: Vec<Option<String>> = drop_copy
+            .try_iter()
+            .map(|report| match report {
+                DropCopy::Execution { strategy, .. } | DropCopy::Cancel { strategy, .. } => {
+                    strategy
+                }
+            })
+            .collect();
+        let tag = |strategy: &str| Some(strategy.to_string());
+        assert_eq!(
+            strategies,
+            vec![tag("mm"), tag("momentum"), tag("mm"), None]
+        );
+
+        let portfolio = engine.portfolio();
+        assert_eq!(portfolio.strategies("bob"), vec!["momentum"]);
+        assert_eq!(portfolio.holding("bob", &pair).position, dec!(3));
+        assert_eq!(
+            portfolio
+                .strategy_holding("bob", "momentum", &pair)
+                .position,
+            dec!(2)
+        );
+        assert_eq!(
+            portfolio.strategy_holding("alice", "mm", &pair).position,
+            dec!(-3)
+        );
+    }
+
+    #[test]
+    fn test_match_hooks() {
+        use crate::limit_order_book::hooks::{MatchDecision, ProposedMatch};
+
+        /// Splits the difference between the maker's price and the taker's
+        /// limit.
+        #[derive(Debug)]
+        struct SplitImprovement;
+
+        impl MatchHook for SplitImprovement {
+            fn on_match(&self, proposed: &ProposedMatch) -> MatchDecision {
+                let (low, high) = proposed.bounds();
+                MatchDecision::Adjust((low + high) / dec!(2))
+            }
+        }
+
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let other = TradingPair::new("ETH".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        engine.add_new_market(other.clone());
+        engine
+            .add_match_hook(&pair, Rc::new(SplitImprovement))
+            .unwrap();
+        let unknown = TradingPair::new("SOL".to_string(), "USDT".to_string());
+        assert!(engine
+            .add_match_hook(&unknown, Rc::new(SplitImprovement))
+            .is_err());
+
+        for market in [&pair, &other] {
+            let ask = order(&mut engine, OrderType::Ask, dec!(1), dec!(100));
+            engine.place_limit_order(market.clone(), ask).unwrap();
+        }
+        // Hooks outlive a restore of the book.
+        engine.restore(&engine.snapshot()).unwrap();
+        let mut prices = Vec::new();
+        for market in [&pair, &other] {
+            let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(102));
+            let (_, fills) = engine.place_limit_order(market.clone(), bid).unwrap();
+            prices.push(fills[0].price);
+        }
+        assert_eq!(prices, vec![dec!(101), dec!(100)]);
+    }
+
+    #[test]
+    fn test_pro_rata_market() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut config = MarketConfig::new(pair.clone());
+        config.lot_size = Some(dec!(0.5));
+        config.matching = Matching::ProRata {
+            top_order_priority: false,
+        };
+        let mut engine = MatchingEngine::new();
+        engine.add_market(config);
+
+        let mut makers = Vec::new();
+        for shares in [dec!(1), dec!(3)] {
+            let ask = order(&mut engine, OrderType::Ask, shares, dec!(100));
+            makers.push(ask.exchange_id);
+            engine.place_limit_order(pair.clone(), ask).unwrap();
+        }
+        let bid = order(&mut engine, OrderType::Bid, dec!(2), dec!(100));
+        let (_, fills) = engine.place_limit_order(pair.clone(), bid).unwrap();
+        let filled: Vec<(u64, Decimal)> = fills
+            .iter()
+            .map(|fill| (fill.maker_id, fill.quantity))
+            .collect();
+        assert_eq!(filled, vec![(makers[0], dec!(0.5)), (makers[1], dec!(1.5))]);
+
+        let snapshot = engine.snapshot();
+        engine.restore(&snapshot).unwrap();
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(100));
+        let (_, fills) = engine.place_limit_order(pair, bid).unwrap();
+        assert_eq!(fills.len(), 2);
+    }
+
+    #[test]
+    fn test_midpoint_orders_cross_at_the_mid() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut config = MarketConfig::new(pair.clone());
+        config.midpoint = Some(MidpointConfig {
+            min_quantity: dec!(2),
+        });
+        let mut engine = MatchingEngine::new();
+        engine.add_market(config);
+        engine.add_new_market(TradingPair::new("ETH".to_string(), "USDT".to_string()));
+
+        let bid = order(&mut engine, OrderType::Bid, dec!(5), dec!(99));
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        let ask = order(&mut engine, OrderType::Ask, dec!(5), dec!(102)).with_client("alice");
+        let ask_id = ask.exchange_id;
+        let (ask, fills) = engine
+            .place_midpoint_order(pair.clone(), ask, dec!(2))
+            .unwrap();
+        assert!(fills.is_empty());
+        assert_eq!(ask.status, OrderStatus::New);
+        assert!(engine.orderbook(&pair).unwrap().lowest_ask.is_none());
+
+        // Without an offer there is no mid to cross at.
+        let bid = order(&mut engine, OrderType::Bid, dec!(3), dec!(102.5)).with_client("bob");
+        let bid_id = bid.exchange_id;
+        let (_, fills) = engine
+            .place_midpoint_order(pair.clone(), bid, dec!(3))
+            .unwrap();
+        assert!(fills.is_empty());
+
+        // The offer makes the mid 101, which is outside alice's limit until
+        // the bid moves up.
+        let offer = order(&mut engine, OrderType::Ask, dec!(1), dec!(103));
+        engine.place_limit_order(pair.clone(), offer).unwrap();
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(101));
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        assert_eq!(engine.positions().position("bob", &pair), dec!(3));
+        assert_eq!(engine.positions().position("alice", &pair), dec!(-3));
+
+        let cancelled = engine.cancel_midpoint_order(&pair, ask_id).unwrap();
+        assert_eq!(cancelled.filled_quantity, dec!(3));
+        assert!(engine.cancel_midpoint_order(&pair, bid_id).is_err());
+
+        let small = order(&mut engine, OrderType::Bid, dec!(5), dec!(101));
+        let error = engine
+            .place_midpoint_order(pair.clone(), small, dec!(1))
+            .unwrap_err();
+        assert!(error.contains("minimum quantity"));
+        let eth = order(&mut engine, OrderType::Bid, dec!(5), dec!(101));
+        assert!(engine
+            .place_midpoint_order(
+                TradingPair::new("ETH".to_string(), "USDT".to_string()),
+                eth,
+                dec!(2)
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_midpoint_client_order_ids() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut config = MarketConfig::new(pair.clone());
+        config.midpoint = Some(MidpointConfig {
+            min_quantity: dec!(1),
+        });
+        let mut engine = MatchingEngine::new();
+        engine.add_market(config);
+        let midpoint = |engine: &mut MatchingEngine, shares| {
+            let bid = order(engine, OrderType::Bid, shares, dec!(100))
+                .with_client("alice")
+                .with_client_order_id("m-1");
+            engine.place_midpoint_order(pair.clone(), bid, dec!(1))
+        };
+
+        let (waiting, _) = midpoint(&mut engine, dec!(2)).unwrap();
+        let (resent, fills) = midpoint(&mut engine, dec!(2)).unwrap();
+        assert_eq!(resent.exchange_id, waiting.exchange_id);
+        assert!(fills.is_empty());
+        assert!(midpoint(&mut engine, dec!(3)).is_err());
+        assert_eq!(
+            engine.client_order("alice", "m-1").unwrap().1.status,
+            OrderStatus::New
+        );
+
+        // Cancelling frees the ID for a new order.
+        let cancelled = engine.cancel_client_order("alice", "m-1").unwrap();
+        assert_eq!(cancelled.status, OrderStatus::Cancelled);
+        assert!(engine.client_order("alice", "m-1").is_none());
+        let (again, _) = midpoint(&mut engine, dec!(3)).unwrap();
+        assert_ne!(again.exchange_id, waiting.exchange_id);
+        assert_eq!(again.shares, dec!(3));
+    }
+
+    #[test]
+    fn test_basket_orders() {
+        let btc = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let eth = TradingPair::new("ETH".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(btc.clone());
+        engine.add_market(MarketConfig {
+            lot_size: Some(dec!(1)),
+            ..MarketConfig::new(eth.clone())
+        });
+
+        // A bad leg rejects the whole basket before anything is placed.
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(100));
+        let ask = order(&mut engine, OrderType::Ask, dec!(0.5), dec!(10));
+        let error = engine
+            .place_basket(
+                Basket::new()
+                    .with_leg(btc.clone(), bid)
+                    .with_leg(eth.clone(), ask),
+            )
+            .unwrap_err();
+        assert!(error.starts_with("Leg 2:"));
+        assert!(engine.orderbook(&btc).unwrap().orders.is_empty());
+
+        // Earlier legs count towards the client's limits.
+        engine.set_client_limits(
+            "alice",
+            Some(RiskLimits {
+                max_open_orders: Some(1),
+                ..RiskLimits::default()
+            }),
+        );
+        let legs = [
+            (btc.clone(), dec!(100)),
+            (btc.clone(), dec!(99)),
+            (eth.clone(), dec!(10)),
+        ];
+        let mut basket = Basket::new().with_contingent(true);
+        for (pair, price) in legs {
+            let bid = order(&mut engine, OrderType::Bid, dec!(1), price).with_client("alice");
+            basket = basket.with_leg(pair, bid);
+        }
+        let error = engine.place_basket(basket).unwrap_err();
+        assert!(error.starts_with("Leg 2: Open orders"));
+        assert!(engine.account_orders("alice").is_empty());
+    }
+
+    #[test]
+    fn test_contract_multiplier_and_expiry() {
+        let future = TradingPair::new("BTC-MAR25".to_string(), "USD".to_string());
+        let expiry = Utc::now() + Duration::hours(1);
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(future.clone());
+        engine
+            .instruments_mut()
+            .insert(Instrument::future(future.clone(), dec!(10), expiry))
+            .unwrap();
+        engine.set_client_limits(
+            "alice",
+            Some(RiskLimits {
+                max_order_notional: Some(dec!(5000)),
+                ..RiskLimits::default()
+            }),
+        );
+
+        // Four contracts at 100 are worth 4000, five are worth 5000.
+        let bid = order(&mut engine, OrderType::Bid, dec!(4), dec!(100)).with_client("alice");
+        engine.place_limit_order(future.clone(), bid).unwrap();
+        let bid = order(&mut engine, OrderType::Bid, dec!(6), dec!(100)).with_client("alice");
+        let error = engine.place_limit_order(future.clone(), bid).unwrap_err();
+        assert!(error.contains("Notional 6000"));
+
+        let mut late = order(&mut engine, OrderType::Ask, dec!(1), dec!(100));
+        late.event_time = expiry;
+        let error = engine.place_limit_order(future, late).unwrap_err();
+        assert!(error.contains("expired"));
+    }
+
+    #[test]
+    fn test_rates_follow_book_mids() {
+        let pair = TradingPair::new("ETH".to_string(), "BTC".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        engine.set_rate("BTC", "USD", dec!(50000));
+
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(0.049));
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        assert_eq!(engine.rates().rate("ETH", "USD"), None);
+        let ask = order(&mut engine, OrderType::Ask, dec!(1), dec!(0.051));
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        assert_eq!(engine.rates().rate("ETH", "USD"), Some(dec!(2500)));
+
+        let bid = order(&mut engine, OrderType::Bid, dec!(1), dec!(0.05));
+        let exchange_id = engine
+            .place_limit_order(pair.clone(), bid)
+            .unwrap()
+            .0
+            .exchange_id;
+        assert_eq!(
+            engine.rates().convert(dec!(2), "ETH", "BTC"),
+            Some(dec!(0.101))
+        );
+        engine.cancel_order(&pair, exchange_id).unwrap();
+        assert_eq!(engine.rates().rate("ETH", "BTC"), Some(dec!(0.05)));
+    }
+
+    #[test]
+    fn test_portfolio_follows_trades_and_marks() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        engine.set_rate("USDT", "USD", dec!(1));
+
+        let ask = order(&mut engine, OrderType::Ask, dec!(2), dec!(100)).with_client("bob");
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let bid = order(&mut engine, OrderType::Bid, dec!(2), dec!(100)).with_client("alice");
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        // The book is empty, so the mark is between the last trade and the
+        // index.
+        engine
+            .set_index_price(&pair, dec!(106), Utc::now())
+            .unwrap();
+        assert_eq!(engine.mark_price(&pair), Some(dec!(103)));
+
+        let report = engine.portfolio_report("alice", "USD");
+        assert_eq!(report.holdings[0].position, dec!(2));
+        assert_eq!(report.unrealized_pnl, dec!(6));
+        assert_eq!(report.notional, dec!(206));
+        assert_eq!(
+            engine.portfolio_report("bob", "USD").unrealized_pnl,
+            dec!(-6)
+        );
+    }
+
+    #[test]
+    fn test_precision_rounds_fees_and_average_price() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_market(MarketConfig {
+            fees: FeeSchedule::new(dec!(0), dec!(3)),
+            precision: Precision {
+                price_decimals: Some(2),
+                quantity_decimals: None,
+                rounding: Rounding::HalfUp,
+            },
+            ..MarketConfig::new(pair.clone())
+        });
+
+        for (shares, price) in [(dec!(1), dec!(100.01)), (dec!(2), dec!(100.02))] {
+            let ask = order(&mut engine, OrderType::Ask, shares, price).with_client("bob");
+            engine.place_limit_order(pair.clone(), ask).unwrap();
+        }
+        let bid = order(&mut engine, OrderType::Bid, dec!(3), dec!(100.02)).with_client("alice");
+        let (_, fills) = engine.place_limit_order(pair.clone(), bid).unwrap();
+        // 0.030003 and 0.060012 before rounding.
+        assert_eq!(
+            fills.iter().map(|fill| fill.taker_fee).collect::<Vec<_>>(),
+            vec![dec!(0.03), dec!(0.06)]
+        );
+        let holding = engine.portfolio().holding("alice", &pair);
+        assert_eq!(holding.fees, dec!(0.09));
+        // 300.05 / 3 is 100.01666...
+        assert_eq!(holding.average_price, dec!(100.02));
+    }
+
+    #[test]
+    fn test_fees_on_contract_notional() {
+        let future = TradingPair::new("BTC-MAR25".to_string(), "USD".to_string());
+        let pair = TradingPair::new("ETH".to_string(), "USD".to_string());
+        let mut engine = MatchingEngine::new();
+        for pair in [&future, &pair] {
+            engine.add_market(MarketConfig {
+                fees: FeeSchedule::new(dec!(1), dec!(10)),
+                ..MarketConfig::new(pair.clone())
+            });
+        }
+        let expiry = Utc::now() + Duration::days(30);
+        engine
+            .instruments_mut()
+            .insert(Instrument::future(future.clone(), dec!(10), expiry))
+            .unwrap();
+
+        // Two contracts of ten at 100 are worth 2000.
+        let ask = order(&mut engine, OrderType::Ask, dec!(2), dec!(100)).with_client("bob");
+        engine.place_limit_order(future.clone(), ask).unwrap();
+        let bid = order(&mut engine, OrderType::Bid, dec!(2), dec!(100)).with_client("alice");
+        let (_, fills) = engine.place_limit_order(future.clone(), bid).unwrap();
+        assert_eq!(fills[0].maker_fee, dec!(0.2));
+        assert_eq!(fills[0].taker_fee, dec!(2));
+
+        // Resting from before the contract size changed, this bid fills for
+        // more than a Decimal holds.
+        let bid = order(&mut engine, OrderType::Bid, dec!(1e13), dec!(1e13)).with_client("alice");
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        engine
+            .instruments_mut()
+            .insert(Instrument::future(pair.clone(), dec!(1e4), expiry))
+            .unwrap();
+        let ask = order(&mut engine, OrderType::Ask, dec!(1e13), dec!(1)).with_client("bob");
+        let (_, fills) = engine.place_limit_order(pair.clone(), ask).unwrap();
+        assert_eq!(fills[0].taker_fee, Decimal::MAX / dec!(10000));
+    }
+
+    #[test]
+    fn test_liquidation_in_steps() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        engine.set_margin(Some(MarginConfig {
+            currency: "USDT".to_string(),
+            maintenance_margin: dec!(0.05),
+            liquidation_step: dec!(0.5),
+            insurance_fund: dec!(0),
+            fallback: LossFallback::SocializedLoss,
+        }));
+        for (client, collateral) in [
+            ("alice", dec!(100)),
+            ("bob", dec!(1000)),
+            ("carol", dec!(1000)),
+            ("dave", dec!(1000)),
+        ] {
+            let accounts = engine.accounts_mut();
+            accounts.open(client.into(), None).unwrap();
+            accounts.deposit(client, "USDT", collateral).unwrap();
+        }
+
+        let trade = |engine: &mut MatchingEngine, client, side, shares, price| {
+            let order = order(engine, side, shares, price).with_client(client);
+            engine.place_limit_order(pair.clone(), order).unwrap()
+        };
+        trade(&mut engine, "bob", OrderType::Ask, dec!(10), dec!(100));
+        trade(&mut engine, "alice", OrderType::Bid, dec!(10), dec!(100));
+        let status = engine.margin_status("alice").unwrap();
+        assert_eq!((status.equity, status.maintenance), (dec!(100), dec!(50)));
+        trade(&mut engine, "alice", OrderType::Bid, dec!(1), dec!(50));
+        trade(&mut engine, "carol", OrderType::Bid, dec!(20), dec!(92));
+        trade(&mut engine, "dave", OrderType::Ask, dec!(1), dec!(93));
+        assert!(engine.drain_liquidation_events().is_empty());
+
+        // A trade at 92 marks alice's long at 92.25, below her margin.
+        trade(&mut engine, "dave", OrderType::Ask, dec!(1), dec!(92));
+        let events = engine.drain_liquidation_events();
+        assert_eq!(events.len(), 4);
+        assert!(
+            matches!(&events[0], LiquidationEvent::MarginCall { account, .. }
+            if account.as_str() == "alice")
+        );
+        for event in &events[1..3] {
+            assert!(matches!(event, LiquidationEvent::Liquidated { filled, .. }
+                if *filled == dec!(5)));
+        }
+        assert!(matches!(events[3], LiquidationEvent::Completed { .. }));
+        assert_eq!(engine.positions().position("alice", &pair), dec!(0));
+        assert!(engine.account_orders("alice").is_empty());
+        assert_eq!(engine.margin_status("alice").unwrap().equity, dec!(20));
+    }
+
+    #[test]
+    fn test_bankruptcy_covered_by_fund_then_shared() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        engine.set_margin(Some(MarginConfig {
+            currency: "USDT".to_string(),
+            maintenance_margin: dec!(0.05),
+            liquidation_step: dec!(1),
+            insurance_fund: dec!(30),
+            fallback: LossFallback::SocializedLoss,
+        }));
+        for (client, collateral) in [
+            ("alice", dec!(100)),
+            ("bob", dec!(1000)),
+            ("carol", dec!(1000)),
+            ("dave", dec!(1000)),
+        ] {
+            let accounts = engine.accounts_mut();
+            accounts.open(client.into(), None).unwrap();
+            accounts.deposit(client, "USDT", collateral).unwrap();
+        }
+
+        let trade = |engine: &mut MatchingEngine, client, side, shares, price| {
+            let order = order(engine, side, shares, price).with_client(client);
+            engine.place_limit_order(pair.clone(), order).unwrap()
+        };
+        trade(&mut engine, "bob", OrderType::Ask, dec!(10), dec!(100));
+        trade(&mut engine, "alice", OrderType::Bid, dec!(10), dec!(100));
+        trade(&mut engine, "carol", OrderType::Bid, dec!(20), dec!(80));
+        trade(&mut engine, "dave", OrderType::Ask, dec!(1), dec!(93));
+        // Alice's long can only be sold at 80, 100 more than she has.
+        trade(&mut engine, "dave", OrderType::Ask, dec!(1), dec!(80));
+
+        let events = engine.drain_liquidation_events();
+        let bankrupt = events
+            .iter()
+            .find_map(|event| match event {
+                LiquidationEvent::Bankrupt {
+                    shortfall,
+                    insurance,
+                    ..
+                } => Some((*shortfall, *insurance)),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(bankrupt, (dec!(100), dec!(30)));
+        let shared: Decimal = events
+            .iter()
+            .filter_map(|event| match event {
+                LiquidationEvent::LossShared { amount, .. } => Some(*amount),
+                _ => None,
+            })
+            .sum();
+        assert_eq!(shared, dec!(70));
+        assert_eq!(engine.insurance_fund().balance(), dec!(0));
+        assert_eq!(engine.margin_status("alice").unwrap().equity, dec!(0));
+    }
+
+    #[test]
+    fn test_auto_deleveraging_when_the_book_is_empty() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        engine.set_margin(Some(MarginConfig {
+            currency: "USDT".to_string(),
+            maintenance_margin: dec!(0.05),
+            liquidation_step: dec!(1),
+            insurance_fund: dec!(0),
+            fallback: LossFallback::AutoDeleverage,
+        }));
+        for (client, collateral) in [
+            ("alice", dec!(100)),
+            ("bob", dec!(1000)),
+            ("carol", dec!(1000)),
+            ("dave", dec!(1000)),
+            ("erin", dec!(100)),
+        ] {
+            let accounts = engine.accounts_mut();
+            accounts.open(client.into(), None).unwrap();
+            accounts.deposit(client, "USDT", collateral).unwrap();
+        }
+
+        let trade = |engine: &mut MatchingEngine, client, side, shares, price| {
+            let order = order(engine, side, shares, price).with_client(client);
+            engine.place_limit_order(pair.clone(), order).unwrap()
+        };
+        trade(&mut engine, "bob", OrderType::Ask, dec!(5), dec!(100));
+        trade(&mut engine, "erin", OrderType::Ask, dec!(5), dec!(100));
+        trade(&mut engine, "alice", OrderType::Bid, dec!(10), dec!(100));
+        // A trade at 70 leaves alice 200 short of her equity, with no bids
+        // to sell into.
+        trade(&mut engine, "carol", OrderType::Bid, dec!(1), dec!(70));
+        trade(&mut engine, "dave", OrderType::Ask, dec!(1), dec!(70));
+
+        // Erin made as much as bob on more leverage, so goes first.
+        let deleveraged: Vec<(String, Decimal, Decimal)> = engine
+            .drain_liquidation_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                LiquidationEvent::Deleveraged {
+                    account,
+                    quantity,
+                    price,
+                    ..
+                } => Some((account.to_string(), quantity, price)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            deleveraged,
+            vec![
+                ("erin".to_string(), dec!(5), dec!(90)),
+                ("bob".to_string(), dec!(5), dec!(90)),
+            ]
+        );
+        assert_eq!(engine.positions().position("alice", &pair), dec!(0));
+        assert_eq!(engine.positions().position("erin", &pair), dec!(0));
+        assert_eq!(engine.positions().position("dave", &pair), dec!(-1));
+        assert_eq!(engine.margin_status("alice").unwrap().equity, dec!(0));
+        assert_eq!(engine.margin_status("erin").unwrap().equity, dec!(150));
+    }
+
+    #[test]
+    fn test_memory_stats() {
+        let btc = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let eth = TradingPair::new("ETH".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(btc.clone());
+        engine.add_new_market(eth.clone());
+        for (pair, price) in [(&btc, dec!(100)), (&btc, dec!(100)), (&eth, dec!(10))] {
+            let order = order(&mut engine, OrderType::Bid, dec!(1), price);
+            engine.place_limit_order(pair.clone(), order).unwrap();
+        }
+
+        let books = engine.book_stats();
+        assert_eq!(books[0].0, btc);
+        assert_eq!(books[0].1.orders, 2);
+        let total = engine.memory_stats();
+        assert_eq!((total.levels(), total.orders), (2, 3));
+        assert_eq!(total.largest_level.unwrap().price, dec!(100));
+        assert_eq!(
+            total.estimated_bytes,
+            books[0].1.estimated_bytes + books[1].1.estimated_bytes
+        );
+    }
+
+    #[test]
+    fn test_far_levels_are_pruned() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        let mut market = MarketConfig::new(pair.clone());
+        market.prune = Some(PrunePolicy {
+            max_levels: Some(2),
+            max_distance: Some(dec!(0.1)),
+        });
+        engine.add_market(market);
+        for (side, price) in [
+            (OrderType::Bid, dec!(98)),
+            (OrderType::Bid, dec!(99)),
+            (OrderType::Ask, dec!(101)),
+            (OrderType::Ask, dec!(120)),
+            (OrderType::Bid, dec!(97)),
+        ] {
+            let order = order(&mut engine, side, dec!(1), price);
+            engine.place_limit_order(pair.clone(), order).unwrap();
+        }
+        let book = engine.orderbook(&pair).unwrap();
+        assert_eq!(book.get_bids(), vec![dec!(98), dec!(99)]);
+        assert_eq!(book.get_asks(), vec![dec!(101)]);
+        let pruned: Vec<usize> = engine
+            .drain_market_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                MarketEvent::Pruned { orders, .. } => Some(orders.len()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(pruned, vec![1, 1]);
+
+        assert!(engine.compact(&pair, Utc::now()).unwrap().is_empty());
+        assert_eq!(engine.orderbook(&pair).unwrap().orders.len(), 3);
+    }
+
+    #[test]
+    fn test_day_orders_expire_at_the_close() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let other = TradingPair::new("ETH".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_market(MarketConfig {
+            calendar: Some(Calendar::new(Session::new(
+                NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+                NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+            ))),
+            ..MarketConfig::new(pair.clone())
+        });
+        engine.add_new_market(other.clone());
+        let close = Utc.with_ymd_and_hms(2024, 1, 2, 16, 0, 0).unwrap();
+        let mut place = |time_in_force, price, time| {
+            let mut order = order(&mut engine, OrderType::Bid, dec!(1), price)
+                .with_time_in_force(time_in_force);
+            (order.entry_time, order.event_time) = (time, time);
+            engine.place_limit_order(pair.clone(), order).unwrap().0
+        };
+        let morning = close - Duration::hours(6);
+        let day = place(TimeInForce::Day, dec!(99), morning);
+        place(TimeInForce::Gtc, dec!(98), morning);
+        // Entered after the close, so good for the next session.
+        let late = place(TimeInForce::Day, dec!(97), close + Duration::hours(1));
+
+        assert!(engine
+            .expire_orders(close - Duration::seconds(1))
+            .is_empty());
+        let expired = engine.expire_orders(close + Duration::hours(2));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].1.exchange_id, day.exchange_id);
+        assert_eq!(expired[0].1.status, OrderStatus::Cancelled);
+        assert!(matches!(
+            engine.drain_market_events().as_slice(),
+            [MarketEvent::Expired { orders, .. }] if orders == &vec![day.exchange_id]
+        ));
+        let expired = engine.expire_orders(close + Duration::days(1));
+        assert_eq!(expired[0].1.exchange_id, late.exchange_id);
+        assert_eq!(engine.orderbook(&pair).unwrap().get_bids(), vec![dec!(98)]);
+
+        let order = order(&mut engine, OrderType::Bid, dec!(1), dec!(99))
+            .with_time_in_force(TimeInForce::Day);
+        assert_eq!(
+            engine.place_limit_order(other, order).unwrap_err(),
+            "Market has no session for DAY orders"
+        );
+    }
+
+    #[test]
+    fn test_split_and_dividend() {
+        let pair = TradingPair::new("AAPL".to_string(), "USD".to_string());
+        let mut engine = MatchingEngine::new();
+        let mut market = MarketConfig::new(pair.clone());
+        market.tick_size = Some(dec!(0.01));
+        engine.add_market(market);
+        let mut place = |client, side, shares, price| {
+            let order = order(&mut engine, side, shares, price).with_client(client);
+            engine.place_limit_order(pair.clone(), order).unwrap()
+        };
+        place("bob", OrderType::Ask, dec!(4), dec!(100));
+        let bid = place("alice", OrderType::Bid, dec!(10), dec!(100)).0;
+        place("carol", OrderType::Ask, dec!(5), dec!(101));
+
+        let day = |d| Utc.with_ymd_and_hms(2024, 1, d, 10, 0, 0).unwrap();
+        for (ex_date, action) in [
+            (3, CorporateActionKind::Dividend(dec!(0.25))),
+            (2, CorporateActionKind::Split(dec!(2))),
+        ] {
+            let ex_date = day(ex_date).date_naive();
+            engine
+                .schedule_corporate_action(CorporateAction {
+                    pair: pair.clone(),
+                    ex_date,
+                    action,
+                })
+                .unwrap();
+        }
+        engine.apply_corporate_actions(day(1));
+        assert_eq!(engine.corporate_actions().len(), 2);
+
+        engine.apply_corporate_actions(day(2));
+        assert_eq!(engine.corporate_actions().len(), 1);
+        let book = engine.orderbook(&pair).unwrap();
+        let alice = book.get_order(bid.exchange_id).unwrap();
+        assert_eq!(
+            (alice.limit_price, alice.remaining_quantity),
+            (dec!(50), dec!(12))
+        );
+        assert_eq!(book.get_best_ask(), Some(dec!(50.5)));
+        assert_eq!(engine.positions().position("bob", &pair), dec!(-8));
+        assert_eq!(
+            engine.portfolio().holding("alice", &pair).average_price,
+            dec!(50)
+        );
+        assert_eq!(
+            engine.market_state(&pair).unwrap().last_trade_price(),
+            Some(dec!(50))
+        );
+        let events = engine.drain_corporate_action_events();
+        assert!(matches!(events[0], CorporateActionEvent::Applied { .. }));
+        assert_eq!(events.len(), 5);
+
+        engine.apply_corporate_actions(day(3));
+        let book = engine.orderbook(&pair).unwrap();
+        assert_eq!(book.get_best_bid(), Some(dec!(49.75)));
+        assert_eq!(book.get_best_ask(), Some(dec!(50.5)));
+        let dividends: Vec<(String, Decimal)> = engine
+            .drain_corporate_action_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                CorporateActionEvent::PositionAdjusted {
+                    account, dividend, ..
+                } => Some((account.to_string(), dividend)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            dividends,
+            vec![
+                ("alice".to_string(), dec!(2)),
+                ("bob".to_string(), dec!(-2))
+            ]
+        );
+        assert_eq!(
+            engine.portfolio().holding("alice", &pair).realized_pnl,
+            dec!(2)
+        );
+    }
+
+    #[test]
+    fn test_end_of_day_settlement() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        for client in ["alice", "bob"] {
+            let accounts = engine.accounts_mut();
+            accounts.open(client.into(), None).unwrap();
+            accounts.deposit(client, "USDT", dec!(1000)).unwrap();
+        }
+        let ask = order(&mut engine, OrderType::Ask, dec!(3), dec!(100)).with_client("bob");
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let bid = order(&mut engine, OrderType::Bid, dec!(2), dec!(100)).with_client("alice");
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        let close = Utc.with_ymd_and_hms(2024, 1, 1, 17, 0, 0).unwrap();
+        // With no bids, the mark is between the last trade and the index.
+        engine.set_index_price(&pair, dec!(106), close).unwrap();
+
+        let report = engine.settle(close);
+        assert_eq!(report.prices, vec![(pair.clone(), dec!(103))]);
+        assert_eq!(report.records.len(), 2);
+        assert_eq!(report.records[0].bought, dec!(2));
+        assert_eq!(report.amount("alice", "USDT"), dec!(6));
+        assert_eq!(report.amount("bob", "USDT"), dec!(-6));
+
+        // The next day's P&L is measured from the settlement price.
+        engine.set_index_price(&pair, dec!(104), close).unwrap();
+        let report = engine.settle(close + Duration::days(1));
+        assert_eq!(report.prices, vec![(pair.clone(), dec!(102))]);
+        assert_eq!(report.records[0].bought, dec!(0));
+        assert_eq!(report.amount("alice", "USDT"), dec!(-2));
+        let balance = |engine: &MatchingEngine, client| {
+            engine.accounts().get(client).unwrap().balance("USDT")
+        };
+        assert_eq!(balance(&engine, "alice"), dec!(1004));
+        assert_eq!(balance(&engine, "bob"), dec!(996));
+        let holding = engine.portfolio().holding("alice", &pair);
+        assert_eq!(
+            (holding.position, holding.average_price),
+            (dec!(2), dec!(102))
+        );
+        assert_eq!(
+            engine.market_state(&pair).unwrap().previous_close(),
+            Some(dec!(102))
+        );
+    }
+
+    #[test]
+    fn test_funding_settles_between_longs_and_shorts() {
+        let pair = TradingPair::new("BTC-PERP".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_market(MarketConfig {
+            funding: Some(FundingConfig {
+                interval_secs: 3600,
+                max_rate: dec!(0.005),
+            }),
+            ..MarketConfig::new(pair.clone())
+        });
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        engine.settle_funding(start);
+
+        let ask = order(&mut engine, OrderType::Ask, dec!(2), dec!(100)).with_client("bob");
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let bid = order(&mut engine, OrderType::Bid, dec!(2), dec!(100)).with_client("alice");
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+
+        for (order_type, price) in [(OrderType::Bid, dec!(99)), (OrderType::Ask, dec!(101))] {
+            let quote = order(&mut engine, order_type, dec!(1), price).with_client("carol");
+            engine.place_limit_order(pair.clone(), quote).unwrap();
+        }
+
+        // Without an index price the interval passes unsettled.
+        engine.settle_funding(start + Duration::hours(1));
+        assert!(engine.drain_funding_events().is_empty());
+
+        // The mark is 1% over the index, so longs pay the capped 0.5%.
+        engine
+            .set_index_price(&pair, dec!(99), start + Duration::hours(1))
+            .unwrap();
+        engine.settle_funding(start + Duration::hours(2));
+        let events = engine.drain_funding_events();
+        let payments: Vec<(&str, Decimal)> = events
+            .iter()
+            .map(|event| (event.account.as_str(), event.payment))
+            .collect();
+        assert_eq!(payments, vec![("alice", dec!(1)), ("bob", dec!(-1))]);
+        assert_eq!(events[0].rate, dec!(0.005));
+        assert_eq!(engine.portfolio_report("alice", "USDT").funding, dec!(1));
+
+        let spot = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        assert!(engine.set_index_price(&spot, dec!(99), Utc::now()).is_err());
+    }
+
+    #[test]
+    fn test_client_order_ids() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let bid = order(&mut engine, OrderType::Bid, dec!(5), dec!(99))
+            .with_client("alice")
+            .with_client_order_id("a-1");
+        let (placed, _) = engine.place_limit_order(pair.clone(), bid.clone()).unwrap();
+
+        // Resending the same order, even under a new exchange ID, is a no-op.
+        let resent = Order {
+            exchange_id: engine.next_exchange_id(),
+            ..bid.clone()
+        };
+        let (again, fills) = engine.place_limit_order(pair.clone(), resent).unwrap();
+        assert_eq!(again.exchange_id, placed.exchange_id);
+        assert!(fills.is_empty());
+        assert_eq!(engine.orderbook(&pair).unwrap().orders.len(), 1);
+        let different = order(&mut engine, OrderType::Bid, dec!(6), dec!(99))
+            .with_client("alice")
+            .with_client_order_id("a-1");
+        assert!(engine.place_limit_order(pair.clone(), different).is_err());
+        // IDs are per client.
+        let other = order(&mut engine, OrderType::Bid, dec!(1), dec!(98))
+            .with_client("bob")
+            .with_client_order_id("a-1");
+        assert!(engine.place_limit_order(pair.clone(), other).is_ok());
+
+        let ask = order(&mut engine, OrderType::Ask, dec!(2), dec!(99)).with_client("carol");
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let (replaced, _) = engine
+            .replace_client_order("alice", "a-1", "a-2", dec!(4), dec!(98.5), Utc::now())
+            .unwrap();
+        assert_eq!(replaced.shares, dec!(2));
+        assert_eq!(replaced.limit_price, dec!(98.5));
+        assert_eq!(
+            engine.client_order("alice", "a-1").unwrap().1.status,
+            OrderStatus::Cancelled
+        );
+        assert!(engine
+            .replace_client_order("alice", "a-2", "a-1", dec!(4), dec!(98), Utc::now())
+            .is_err());
+
+        let ask = order(&mut engine, OrderType::Ask, dec!(2), dec!(98.5)).with_client("carol");
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        assert_eq!(
+            engine.client_order("alice", "a-2").unwrap().1.status,
+            OrderStatus::Filled
+        );
+        assert!(engine.cancel_client_order("alice", "a-2").is_err());
+        assert!(engine.cancel_client_order("bob", "a-1").is_ok());
+
+        engine.clear_client_order_ids();
+        assert!(engine.client_order("alice", "a-1").is_none());
+    }
+
+    #[test]
+    fn test_rejected_replace_keeps_original() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        engine.set_rate_limit(Some(RateLimit {
+            orders_per_second: dec!(1),
+            burst: dec!(2),
+        }));
+        // One resting order at a time, which a replacement doesn't exceed.
+        engine.set_client_limits(
+            "alice",
+            Some(RiskLimits {
+                max_open_orders: Some(1),
+                ..RiskLimits::default()
+            }),
+        );
+        let now = Utc::now();
+        let mut bid = order(&mut engine, OrderType::Bid, dec!(5), dec!(99))
+            .with_client("alice")
+            .with_client_order_id("a-1");
+        bid.event_time = now;
+        let (placed, _) = engine.place_limit_order(pair.clone(), bid).unwrap();
+        let behind = order(&mut engine, OrderType::Bid, dec!(5), dec!(99)).with_client("bob");
+        engine.place_limit_order(pair.clone(), behind).unwrap();
+        let drop_copy = engine.subscribe_drop_copy();
+
+        let reason = engine
+            .replace_client_order("alice", "a-1", "a-2", dec!(5), dec!(0), now)
+            .unwrap_err();
+        assert!(reason.contains("Invalid price"), "{}", reason);
+        // The rejected replacement still took alice's last token.
+        let reason = engine
+            .replace_client_order("alice", "a-1", "a-2", dec!(5), dec!(99), now)
+            .unwrap_err();
+        assert!(reason.contains(RATE_LIMITED), "{}", reason);
+
+        // The original was never touched: no cancel was reported and it
+        // keeps its place at the front of the queue.
+        let (_, original) = engine.client_order("alice", "a-1").unwrap();
+        assert_eq!(original.status, OrderStatus::New);
+        assert!(engine.client_order("alice", "a-2").is_none());
+        assert_eq!(
+            engine
+                .audit_trail(placed.exchange_id)
+                .iter()
+                .map(|entry| entry.event.clone())
+                .collect::<Vec<_>>(),
+            vec![AuditEvent::Accepted]
+        );
+        assert!(drop_copy.try_recv().is_err());
+        let ask = order(&mut engine, OrderType::Ask, dec!(2), dec!(99)).with_client("carol");
+        let (_, fills) = engine.place_limit_order(pair.clone(), ask).unwrap();
+        assert_eq!(fills[0].maker_id, placed.exchange_id);
+
+        // Once accepted, the replacement takes the original's place in
+        // alice's one open order.
+        let later = now + Duration::seconds(2);
+        let (replaced, _) = engine
+            .replace_client_order("alice", "a-1", "a-2", dec!(5), dec!(98.5), later)
+            .unwrap();
+        assert_eq!(replaced.remaining_quantity, dec!(3));
+        assert_eq!(
+            engine.client_order("alice", "a-1").unwrap().1.status,
+            OrderStatus::Cancelled
+        );
+    }
+
+    #[test]
+    fn test_audit_trail() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let time = Utc.with_ymd_and_hms(2024, 1, 2, 9, 30, 0).unwrap();
+        let mut place = |side, shares| {
+            let mut order = order(&mut engine, side, shares, dec!(100));
+            order.event_time = time;
+            let exchange_id = order.exchange_id;
+            let _ = engine.place_limit_order(pair.clone(), order);
+            exchange_id
+        };
+        let ask = place(OrderType::Ask, dec!(5));
+        let bid = place(OrderType::Bid, dec!(2));
+        let rejected = place(OrderType::Bid, dec!(0));
+        engine.cancel_order(&pair, ask).unwrap();
+
+        let summary = |exchange_id| {
+            engine
+                .audit_trail(exchange_id)
+                .iter()
+                .map(|entry| {
+                    (
+                        entry.sequence,
+                        entry.trigger,
+                        entry.status,
+                        entry.remaining_quantity,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            summary(ask),
+            vec![
+                (1, 1, Some(OrderStatus::New), dec!(5)),
+                (3, 2, Some(OrderStatus::PartiallyFilled), dec!(3)),
+                (6, 6, Some(OrderStatus::Cancelled), dec!(0)),
+            ]
+        );
+        assert_eq!(
+            summary(bid),
+            vec![
+                (2, 2, Some(OrderStatus::New), dec!(2)),
+                (4, 2, Some(OrderStatus::Filled), dec!(0)),
+            ]
+        );
+        let trail = engine.audit_trail(rejected);
+        assert_eq!(trail.len(), 1);
+        assert!(matches!(trail[0].event, AuditEvent::Rejected { .. }));
+        assert_eq!(trail[0].status, None);
+        let cancel = engine.audit_trail(ask).last().unwrap();
+        assert_eq!(
+            cancel.event,
+            AuditEvent::Cancelled {
+                reason: CancelReason::Client
+            }
+        );
+        assert_eq!((cancel.time, cancel.filled_quantity), (time, dec!(2)));
+        assert!(engine.audit_trail(99).is_empty());
     }
 }
@@ -0,0 +1,333 @@
+use crate::{
+    limit_order_book::arithmetic::saturating_sum,
+    matching_engine::{accounts::AccountId, precision::Precision, snapshot::FeeSnapshot},
+};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Which side of a trade an order was on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+/// Rates that apply once a client's rolling 30-day traded notional reaches
+/// `min_volume`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeeTier {
+    pub min_volume: Decimal,
+    pub maker_bps: Decimal,
+    pub taker_bps: Decimal,
+}
+
+/// Maker/taker rates for a market, in basis points of notional. A negative
+/// rate is a rebate.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeeSchedule {
+    #[serde(default)]
+    pub maker_bps: Decimal,
+    #[serde(default)]
+    pub taker_bps: Decimal,
+    #[serde(default)]
+    pub tiers: Vec<FeeTier>,
+}
+
+impl FeeSchedule {
+    pub fn new(maker_bps: Decimal, taker_bps: Decimal) -> Self {
+        Self {
+            maker_bps,
+            taker_bps,
+            tiers: Vec::new(),
+        }
+    }
+
+    pub fn with_tier(mut self, tier: FeeTier) -> Self {
+        self.tiers.push(tier);
+        self
+    }
+
+    /// The rate in bps for a client with `volume` traded over the window.
+    /// The highest tier reached wins; below every tier the base rates apply.
+    pub fn rate(&self, liquidity: Liquidity, volume: Decimal) -> Decimal {
+        let tier = self
+            .tiers
+            .iter()
+            .filter(|tier| volume >= tier.min_volume)
+            .max_by_key(|tier| tier.min_volume);
+        match (liquidity, tier) {
+            (Liquidity::Maker, Some(tier)) => tier.maker_bps,
+            (Liquidity::Taker, Some(tier)) => tier.taker_bps,
+            (Liquidity::Maker, None) => self.maker_bps,
+            (Liquidity::Taker, None) => self.taker_bps,
+        }
+    }
+
+    pub fn fee(&self, liquidity: Liquidity, volume: Decimal, notional: Decimal) -> Decimal {
+        notional.saturating_mul(self.rate(liquidity, volume)) / dec!(10000)
+    }
+}
+
+/// Fees charged per account and notional traded per account family,
+/// across all markets. Sub-accounts trade on their top-level account's tier.
+#[derive(Debug, Clone, Default)]
+pub struct FeeLedger {
+    fees: HashMap<AccountId, Decimal>,
+    trades: HashMap<AccountId, VecDeque<(DateTime<Utc>, Decimal)>>,
+}
+
+impl FeeLedger {
+    /// Length of the window that fee tiers are computed over.
+    pub const VOLUME_WINDOW_DAYS: i64 = 30;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Charges `account` for one side of a trade and returns the fee,
+    /// rounded to the market's `precision`. The tier is chosen from volume
+    /// before this trade, which then counts towards it.
+    pub fn charge(
+        &mut self,
+        schedule: &FeeSchedule,
+        precision: &Precision,
+        account: &AccountId,
+        liquidity: Liquidity,
+        notional: Decimal,
+        time: DateTime<Utc>,
+    ) -> Decimal {
+        let fee =
+            precision.price(schedule.fee(liquidity, self.rolling_volume(account, time), notional));
+        *self.fees.entry(account.clone()).or_default() += fee;
+        self.trades
+            .entry(account.root())
+            .or_default()
+            .push_back((time, notional));
+        fee
+    }
+
+    /// Total fees charged to `account`; negative when rebates dominate.
+    pub fn fees_paid(&self, account: &str) -> Decimal {
+        self.fees.get(account).copied().unwrap_or_default()
+    }
+
+    /// Notional traded by `account`'s whole family in the window ending at
+    /// `now`.
+    pub fn rolling_volume(&mut self, account: &AccountId, now: DateTime<Utc>) -> Decimal {
+        let trades = match self.trades.get_mut(&account.root()) {
+            Some(trades) => trades,
+            None => return Decimal::ZERO,
+        };
+        let cutoff = now - Duration::days(Self::VOLUME_WINDOW_DAYS);
+        while matches!(trades.front(), Some((time, _)) if *time <= cutoff) {
+            trades.pop_front();
+        }
+        saturating_sum(trades.iter().map(|(_, notional)| notional))
+    }
+
+    /// The fees and traded notional of every account, sorted by account.
+    pub fn snapshot(&self) -> Vec<FeeSnapshot> {
+        let mut accounts: Vec<&AccountId> = self.fees.keys().chain(self.trades.keys()).collect();
+        accounts.sort();
+        accounts.dedup();
+        accounts
+            .into_iter()
+            .map(|account| FeeSnapshot {
+                account: account.clone(),
+                paid: self.fees.get(account).copied().unwrap_or_default(),
+                trades: self
+                    .trades
+                    .get(account)
+                    .map(|trades| trades.iter().copied().collect())
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Replaces every account's fees and traded notional with the
+    /// snapshot's.
+    pub fn restore(&mut self, fees: &[FeeSnapshot]) {
+        self.fees = fees
+            .iter()
+            .map(|snapshot| (snapshot.account.clone(), snapshot.paid))
+            .collect();
+        self.trades = fees
+            .iter()
+            .filter(|snapshot| !snapshot.trades.is_empty())
+            .map(|snapshot| {
+                (
+                    snapshot.account.clone(),
+                    snapshot.trades.iter().copied().collect(),
+                )
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule() -> FeeSchedule {
+        FeeSchedule::new(dec!(-1), dec!(5))
+            .with_tier(FeeTier {
+                min_volume: dec!(1000),
+                maker_bps: dec!(-2),
+                taker_bps: dec!(4),
+            })
+            .with_tier(FeeTier {
+                min_volume: dec!(100000),
+                maker_bps: dec!(-3),
+                taker_bps: dec!(2),
+            })
+    }
+
+    #[test]
+    fn test_tier_rates() {
+        let schedule = schedule();
+        assert_eq!(schedule.rate(Liquidity::Taker, dec!(0)), dec!(5));
+        assert_eq!(schedule.rate(Liquidity::Maker, dec!(999)), dec!(-1));
+        assert_eq!(schedule.rate(Liquidity::Taker, dec!(1000)), dec!(4));
+        assert_eq!(schedule.rate(Liquidity::Maker, dec!(500000)), dec!(-3));
+        assert_eq!(schedule.fee(Liquidity::Taker, dec!(0), dec!(2000)), dec!(1));
+        assert_eq!(
+            schedule.fee(Liquidity::Maker, dec!(0), dec!(2000)),
+            dec!(-0.2)
+        );
+    }
+
+    #[test]
+    fn test_ledger_rolls_volume_window() {
+        let schedule = schedule();
+        let precision = Precision::default();
+        let mut ledger = FeeLedger::new();
+        let start = Utc::now();
+        let alice = AccountId::from("alice");
+
+        assert_eq!(
+            ledger.charge(
+                &schedule,
+                &precision,
+                &alice,
+                Liquidity::Taker,
+                dec!(1000),
+                start
+            ),
+            dec!(0.5)
+        );
+        // The first trade lifts alice into the 1000 tier.
+        assert_eq!(
+            ledger.charge(
+                &schedule,
+                &precision,
+                &alice,
+                Liquidity::Taker,
+                dec!(1000),
+                start
+            ),
+            dec!(0.4)
+        );
+        assert_eq!(ledger.fees_paid("alice"), dec!(0.9));
+        assert_eq!(ledger.fees_paid("bob"), dec!(0));
+
+        let later = start + Duration::days(31);
+        assert_eq!(ledger.rolling_volume(&alice, later), dec!(0));
+        assert_eq!(
+            ledger.charge(
+                &schedule,
+                &precision,
+                &alice,
+                Liquidity::Taker,
+                dec!(1000),
+                later
+            ),
+            dec!(0.5)
+        );
+    }
+
+    #[test]
+    fn test_sub_accounts_share_a_tier() {
+        let schedule = schedule();
+        let precision = Precision::default();
+        let mut ledger = FeeLedger::new();
+        let now = Utc::now();
+        let desk = AccountId::from("desk");
+
+        ledger.charge(
+            &schedule,
+            &precision,
+            &desk.sub("a"),
+            Liquidity::Taker,
+            dec!(1000),
+            now,
+        );
+        assert_eq!(
+            ledger.charge(
+                &schedule,
+                &precision,
+                &desk.sub("b"),
+                Liquidity::Taker,
+                dec!(1000),
+                now
+            ),
+            dec!(0.4)
+        );
+        assert_eq!(ledger.fees_paid("desk/a"), dec!(0.5));
+        assert_eq!(ledger.fees_paid("desk"), dec!(0));
+        assert_eq!(ledger.rolling_volume(&desk, now), dec!(2000));
+    }
+
+    #[test]
+    fn test_snapshot_keeps_fees_and_tiers() {
+        let schedule = schedule();
+        let precision = Precision::default();
+        let mut ledger = FeeLedger::new();
+        let now = Utc::now();
+        let desk = AccountId::from("desk");
+        ledger.charge(
+            &schedule,
+            &precision,
+            &desk.sub("a"),
+            Liquidity::Taker,
+            dec!(1000),
+            now,
+        );
+
+        // The fees are the sub-account's, the volume its family's.
+        let snapshot = ledger.snapshot();
+        assert_eq!(
+            snapshot,
+            vec![
+                FeeSnapshot {
+                    account: desk.clone(),
+                    paid: dec!(0),
+                    trades: vec![(now, dec!(1000))],
+                },
+                FeeSnapshot {
+                    account: desk.sub("a"),
+                    paid: dec!(0.5),
+                    trades: Vec::new(),
+                },
+            ]
+        );
+        let mut restored = FeeLedger::new();
+        restored.restore(&snapshot);
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(
+            restored.charge(
+                &schedule,
+                &precision,
+                &desk.sub("b"),
+                Liquidity::Taker,
+                dec!(1000),
+                now
+            ),
+            dec!(0.4)
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use crate::matching_engine::{accounts::AccountId, engine::TradingPair};
+use chrono::{DateTime, TimeZone, Utc};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::Deserialize;
+
+/// Periodic funding between longs and shorts of a perpetual market:
+///
+/// ```toml
+/// funding = { interval_secs = 28800, max_rate = "0.0075" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FundingConfig {
+    /// Time between funding payments, aligned to the Unix epoch.
+    pub interval_secs: u64,
+    /// Largest rate charged in one interval, either way.
+    #[serde(default = "default_max_rate")]
+    pub max_rate: Decimal,
+}
+
+fn default_max_rate() -> Decimal {
+    dec!(0.0075)
+}
+
+impl FundingConfig {
+    /// The rate for one interval: the mark's premium over the index, capped
+    /// at `max_rate`. Positive rates make longs pay shorts.
+    pub fn rate(&self, mark: Decimal, index: Decimal) -> Decimal {
+        let premium = (mark - index) / index;
+        premium.clamp(-self.max_rate, self.max_rate)
+    }
+}
+
+/// One funding payment in an account's ledger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FundingEvent {
+    pub account: AccountId,
+    pub pair: TradingPair,
+    pub rate: Decimal,
+    pub mark_price: Decimal,
+    pub index_price: Decimal,
+    pub position: Decimal,
+    /// Amount the account paid in the quote currency; negative when it
+    /// received funding.
+    pub payment: Decimal,
+    pub time: DateTime<Utc>,
+}
+
+/// Funding state the engine keeps per perpetual market.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Funding {
+    pub config: FundingConfig,
+    /// Start of the interval funding was last settled for.
+    last_settled: Option<DateTime<Utc>>,
+}
+
+impl Funding {
+    pub fn new(config: FundingConfig) -> Self {
+        Self {
+            config,
+            last_settled: None,
+        }
+    }
+
+    /// Start of the interval funding was last settled for, once the clock
+    /// has started.
+    pub fn last_settled(&self) -> Option<DateTime<Utc>> {
+        self.last_settled
+    }
+
+    /// Whether an interval boundary has passed since the last call that
+    /// returned true. The first call only starts the clock, and several
+    /// boundaries missed between calls are settled once.
+    pub fn is_due(&mut self, now: DateTime<Utc>) -> bool {
+        let interval = (self.config.interval_secs as i64).max(1);
+        let seconds = now.timestamp();
+        let boundary = Utc
+            .timestamp_opt(seconds - seconds.rem_euclid(interval), 0)
+            .unwrap();
+        match self.last_settled {
+            Some(last) if boundary <= last => false,
+            Some(_) => {
+                self.last_settled = Some(boundary);
+                true
+            }
+            None => {
+                self.last_settled = Some(boundary);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_funding_rate_and_schedule() {
+        let mut funding = Funding::new(FundingConfig {
+            interval_secs: 3600,
+            max_rate: dec!(0.01),
+        });
+        assert_eq!(funding.config.rate(dec!(101), dec!(100)), dec!(0.01));
+        assert_eq!(funding.config.rate(dec!(99.5), dec!(100)), dec!(-0.005));
+        assert_eq!(funding.config.rate(dec!(50), dec!(100)), dec!(-0.01));
+
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 30, 0).unwrap();
+        assert!(!funding.is_due(start));
+        assert!(!funding.is_due(start + Duration::minutes(29)));
+        assert!(funding.is_due(start + Duration::minutes(30)));
+        assert!(!funding.is_due(start + Duration::minutes(31)));
+        assert!(funding.is_due(start + Duration::hours(5)));
+    }
+
+    #[test]
+    fn test_config_and_missed_intervals() {
+        let config: FundingConfig = toml::from_str("interval_secs = 28800").unwrap();
+        assert_eq!(config.max_rate, dec!(0.0075));
+        assert!(toml::from_str::<FundingConfig>("interval_secs = 60\nrate = \"0.1\"").is_err());
+
+        let mut funding = Funding::new(config);
+        assert_eq!(funding.last_settled(), None);
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 7, 0, 0).unwrap();
+        funding.is_due(start);
+        assert_eq!(
+            funding.last_settled(),
+            Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap())
+        );
+        // Two days of missed boundaries settle once, for the latest.
+        let later = start + Duration::days(2);
+        assert!(funding.is_due(later));
+        assert!(!funding.is_due(later));
+        assert_eq!(
+            funding.last_settled(),
+            Some(Utc.with_ymd_and_hms(2024, 1, 3, 0, 0, 0).unwrap())
+        );
+        // A clock that goes backwards never settles twice.
+        assert!(!funding.is_due(start));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::matching_engine::accounts::AccountId;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Who bears what a bankrupt account owes once the insurance fund is
+/// empty.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LossFallback {
+    /// Accounts with positive equity, in proportion to it.
+    #[default]
+    SocializedLoss,
+    /// Nobody: the fund goes negative and the venue carries the deficit.
+    FundDeficit,
+    /// The opposite side: positions the book can't absorb are closed at
+    /// the bankruptcy price against the most profitable and leveraged
+    /// positions on the other side. Any loss left over is a fund deficit.
+    AutoDeleverage,
+}
+
+/// The insurance fund's balance and the equity adjustments it has made, in
+/// the margin currency.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InsuranceFund {
+    balance: Decimal,
+    /// Credited to bankrupt accounts, debited from those sharing a loss.
+    adjustments: HashMap<AccountId, Decimal>,
+}
+
+impl InsuranceFund {
+    pub fn new(balance: Decimal) -> Self {
+        Self {
+            balance,
+            ..Self::default()
+        }
+    }
+
+    /// Negative once the fund has paid out more than it held.
+    pub fn balance(&self) -> Decimal {
+        self.balance
+    }
+
+    pub fn deposit(&mut self, amount: Decimal) {
+        self.balance += amount;
+    }
+
+    /// What the fund has added to, or taken from, `account`'s equity.
+    pub fn adjustment(&self, account: &str) -> Decimal {
+        self.adjustments.get(account).copied().unwrap_or_default()
+    }
+
+    /// Brings a bankrupt account's equity back to zero by crediting it
+    /// `shortfall`. Returns the part the fund's balance covered.
+    pub fn cover(&mut self, account: &AccountId, shortfall: Decimal) -> Decimal {
+        let covered = shortfall.min(self.balance.max(Decimal::ZERO));
+        self.balance -= shortfall;
+        *self.adjustments.entry(account.clone()).or_default() += shortfall;
+        covered
+    }
+
+    /// Recovers the fund's deficit from `equities`, each account paying in
+    /// proportion to its positive equity and never more than it. Returns
+    /// what each account paid.
+    pub fn socialize(&mut self, equities: &[(AccountId, Decimal)]) -> Vec<(AccountId, Decimal)> {
+        let deficit = -self.balance;
+        let total: Decimal = equities
+            .iter()
+            .map(|(_, equity)| equity.max(&Decimal::ZERO))
+            .sum();
+        if deficit <= Decimal::ZERO || total <= Decimal::ZERO {
+            return Vec::new();
+        }
+        let recovered = deficit.min(total);
+        let mut charges = Vec::new();
+        for (account, equity) in equities {
+            if *equity <= Decimal::ZERO {
+                continue;
+            }
+            let charge = recovered * equity / total;
+            *self.adjustments.entry(account.clone()).or_default() -= charge;
+            self.balance += charge;
+            charges.push((account.clone(), charge));
+        }
+        charges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_fund_covers_then_socializes() {
+        let mut fund = InsuranceFund::new(dec!(30));
+        let bankrupt = AccountId::from("alice");
+        assert_eq!(fund.cover(&bankrupt, dec!(100)), dec!(30));
+        assert_eq!(fund.balance(), dec!(-70));
+        assert_eq!(fund.adjustment("alice"), dec!(100));
+
+        let charges = fund.socialize(&[
+            ("bob".into(), dec!(300)),
+            ("carol".into(), dec!(-5)),
+            ("dave".into(), dec!(400)),
+        ]);
+        assert_eq!(
+            charges,
+            vec![("bob".into(), dec!(30)), ("dave".into(), dec!(40))]
+        );
+        assert_eq!(fund.balance(), dec!(0));
+        assert_eq!(fund.adjustment("dave"), dec!(-40));
+        assert!(fund.socialize(&[("bob".into(), dec!(300))]).is_empty());
+    }
+
+    #[test]
+    fn test_deficits_beyond_equity() {
+        let mut fund = InsuranceFund::default();
+        fund.deposit(dec!(10));
+        assert_eq!(fund.cover(&"alice".into(), dec!(60)), dec!(10));
+        // An empty fund covers nothing but still credits the account.
+        assert_eq!(fund.cover(&"alice".into(), dec!(40)), dec!(0));
+        assert_eq!(fund.adjustment("alice"), dec!(100));
+        assert_eq!(fund.balance(), dec!(-90));
+
+        assert!(fund.socialize(&[("bob".into(), dec!(-1))]).is_empty());
+        // Nobody pays more than their equity, and the rest stays a deficit.
+        let charges = fund.socialize(&[("bob".into(), dec!(20)), ("carol".into(), dec!(30))]);
+        assert_eq!(
+            charges,
+            vec![("bob".into(), dec!(20)), ("carol".into(), dec!(30))]
+        );
+        assert_eq!(fund.balance(), dec!(-40));
+        assert_eq!(fund.adjustment("carol"), dec!(-30));
+        assert_eq!(fund.adjustment("dave"), dec!(0));
+    }
+}
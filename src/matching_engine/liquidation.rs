@@ -0,0 +1,198 @@
+use crate::{
+    limit_order_book::order::OrderType,
+    matching_engine::{accounts::AccountId, engine::TradingPair, insurance::LossFallback},
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+/// Margin trading for accounts opened in the engine, with their balances
+/// as collateral:
+///
+/// ```toml
+/// [margin]
+/// currency = "USDT"
+/// maintenance_margin = "0.05"
+/// liquidation_step = "0.25"
+/// insurance_fund = "10000"
+/// fallback = "socialized_loss"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MarginConfig {
+    /// The currency equity and requirements are measured in.
+    pub currency: String,
+    /// Equity an account must keep, as a fraction of its position notional.
+    pub maintenance_margin: Decimal,
+    /// Fraction of each position closed per liquidation step; the whole
+    /// position goes in one step by default.
+    #[serde(default = "default_liquidation_step")]
+    pub liquidation_step: Decimal,
+    /// Starting balance of the fund that covers bankrupt accounts.
+    #[serde(default)]
+    pub insurance_fund: Decimal,
+    /// Who covers a bankruptcy the fund can't.
+    #[serde(default)]
+    pub fallback: LossFallback,
+}
+
+fn default_liquidation_step() -> Decimal {
+    Decimal::ONE
+}
+
+impl MarginConfig {
+    /// What one step closes of a position that was `position` when the
+    /// liquidation began, given `remaining` is still open.
+    pub fn step_quantity(&self, position: Decimal, remaining: Decimal) -> Decimal {
+        let step = match self.liquidation_step {
+            step if step > Decimal::ZERO && step < Decimal::ONE => step,
+            _ => Decimal::ONE,
+        };
+        (position.abs() * step).min(remaining.abs())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MarginStatus {
+    /// Collateral plus P&L, less fees and funding.
+    pub equity: Decimal,
+    /// Equity the account's positions require.
+    pub maintenance: Decimal,
+}
+
+impl MarginStatus {
+    /// Equity over the maintenance requirement; None without positions.
+    pub fn margin_ratio(&self) -> Option<Decimal> {
+        (!self.maintenance.is_zero()).then(|| self.equity / self.maintenance)
+    }
+
+    pub fn is_breached(&self) -> bool {
+        self.maintenance > Decimal::ZERO && self.equity < self.maintenance
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LiquidationEvent {
+    /// The account fell below its maintenance margin. Its resting orders
+    /// were cancelled and its positions are closed in steps.
+    MarginCall {
+        account: AccountId,
+        status: MarginStatus,
+        time: DateTime<Utc>,
+    },
+    /// One step sent to the book on the account's behalf.
+    Liquidated {
+        account: AccountId,
+        pair: TradingPair,
+        side: OrderType,
+        /// What the step asked for and what of it filled.
+        quantity: Decimal,
+        filled: Decimal,
+        time: DateTime<Utc>,
+    },
+    /// The liquidation stopped with the account back above its maintenance
+    /// margin, or with nothing more that could be closed.
+    Completed {
+        account: AccountId,
+        status: MarginStatus,
+        time: DateTime<Utc>,
+    },
+    /// The liquidation left the account with negative equity, which was
+    /// credited back to zero.
+    Bankrupt {
+        account: AccountId,
+        shortfall: Decimal,
+        /// The part the insurance fund's balance covered.
+        insurance: Decimal,
+        time: DateTime<Utc>,
+    },
+    /// A position closed against a bankrupt account's at its bankruptcy
+    /// price, because the book couldn't absorb it.
+    Deleveraged {
+        account: AccountId,
+        bankrupt: AccountId,
+        pair: TradingPair,
+        /// The side `account` closed on.
+        side: OrderType,
+        quantity: Decimal,
+        price: Decimal,
+        time: DateTime<Utc>,
+    },
+    /// An account's share of a bankruptcy the insurance fund couldn't
+    /// cover.
+    LossShared {
+        account: AccountId,
+        amount: Decimal,
+        time: DateTime<Utc>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_margin_status_and_steps() {
+        let status = MarginStatus {
+            equity: dec!(40),
+            maintenance: dec!(50),
+        };
+        assert!(status.is_breached());
+        assert_eq!(status.margin_ratio(), Some(dec!(0.8)));
+        assert!(!MarginStatus::default().is_breached());
+
+        let config = MarginConfig {
+            currency: "USDT".to_string(),
+            maintenance_margin: dec!(0.05),
+            liquidation_step: dec!(0.4),
+            insurance_fund: dec!(0),
+            fallback: LossFallback::default(),
+        };
+        assert_eq!(config.step_quantity(dec!(-10), dec!(-10)), dec!(4));
+        assert_eq!(config.step_quantity(dec!(-10), dec!(-2)), dec!(2));
+        let whole = MarginConfig {
+            liquidation_step: dec!(0),
+            ..config
+        };
+        assert_eq!(whole.step_quantity(dec!(10), dec!(10)), dec!(10));
+    }
+
+    #[test]
+    fn test_config_defaults_and_healthy_status() {
+        let config: MarginConfig = toml::from_str(
+            r#"
+            currency = "USDT"
+            maintenance_margin = "0.05"
+            fallback = "auto_deleverage"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.liquidation_step, dec!(1));
+        assert_eq!(config.insurance_fund, dec!(0));
+        assert_eq!(config.fallback, LossFallback::AutoDeleverage);
+        // A step above one still closes no more than the whole position.
+        let over = MarginConfig {
+            liquidation_step: dec!(1.5),
+            ..config
+        };
+        assert_eq!(over.step_quantity(dec!(8), dec!(3)), dec!(3));
+        assert!(toml::from_str::<MarginConfig>(
+            "currency = \"USDT\"\nmaintenance_margin = \"0.05\"\nleverage = 10"
+        )
+        .is_err());
+
+        let healthy = MarginStatus {
+            equity: dec!(50),
+            maintenance: dec!(50),
+        };
+        assert!(!healthy.is_breached());
+        assert_eq!(healthy.margin_ratio(), Some(dec!(1)));
+        let flat = MarginStatus {
+            equity: dec!(-5),
+            maintenance: dec!(0),
+        };
+        assert!(!flat.is_breached());
+        assert_eq!(flat.margin_ratio(), None);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::limit_order_book::order::{Fill, LimitOrderBook, Order, OrderType};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+/// Enables a market's midpoint segment:
+///
+/// ```toml
+/// midpoint = { min_quantity = "5" }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MidpointConfig {
+    /// Smallest minimum execution size an order may ask for.
+    #[serde(default)]
+    pub min_quantity: Decimal,
+}
+
+/// The mid of the book's best bid and offer, if it has both.
+pub fn mid_price(book: &LimitOrderBook) -> Option<Decimal> {
+    match (book.highest_bid, book.lowest_ask) {
+        (Some(bid), Some(ask)) => Some((bid + ask) / Decimal::TWO),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Resting {
+    order: Order,
+    min_quantity: Decimal,
+}
+
+impl Resting {
+    /// Whether the order accepts `quantity` at `price`. An order with less
+    /// left than its minimum may still complete.
+    fn accepts(&self, price: Decimal, quantity: Decimal) -> bool {
+        let within_limit = match self.order.order_type {
+            OrderType::Bid => price <= self.order.limit_price,
+            OrderType::Ask => price >= self.order.limit_price,
+        };
+        within_limit && quantity >= self.min_quantity.min(self.order.remaining_quantity)
+    }
+}
+
+/// Orders waiting to cross at the primary book's midpoint. They are never
+/// shown on the book, and each side keeps arrival order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MidpointPool {
+    bids: Vec<Resting>,
+    asks: Vec<Resting>,
+}
+
+impl MidpointPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+
+    /// Adds an order that only executes in lots of at least `min_quantity`.
+    pub fn insert(&mut self, order: Order, min_quantity: Decimal) {
+        let side = match order.order_type {
+            OrderType::Bid => &mut self.bids,
+            OrderType::Ask => &mut self.asks,
+        };
+        side.push(Resting {
+            order,
+            min_quantity,
+        });
+    }
+
+    pub fn get(&self, exchange_id: u64) -> Option<&Order> {
+        self.bids
+            .iter()
+            .chain(&self.asks)
+            .map(|resting| &resting.order)
+            .find(|order| order.exchange_id == exchange_id)
+    }
+
+    /// Every waiting order, oldest first.
+    pub fn orders(&self) -> Vec<&Order> {
+        let mut orders: Vec<&Order> = self
+            .bids
+            .iter()
+            .chain(&self.asks)
+            .map(|resting| &resting.order)
+            .collect();
+        orders.sort_by_key(|order| order.exchange_id);
+        orders
+    }
+
+    /// Takes an order out of the pool and returns it in its cancelled state.
+    pub fn cancel(&mut self, exchange_id: u64) -> Option<Order> {
+        for side in [&mut self.bids, &mut self.asks] {
+            if let Some(index) = side
+                .iter()
+                .position(|resting| resting.order.exchange_id == exchange_id)
+            {
+                let mut order = side.remove(index).order;
+                order
+                    .cancel()
+                    .expect("waiting orders are always cancellable");
+                return Some(order);
+            }
+        }
+        None
+    }
+
+    /// Crosses the waiting order `exchange_id` with the opposite side at
+    /// `mid`, oldest first, skipping orders whose limit or minimum size
+    /// rules the trade out. Returns the order's new state and fills, with
+    /// it as the taker; filled orders leave the pool.
+    pub fn cross(&mut self, exchange_id: u64, mid: Decimal) -> Option<(Order, Vec<Fill>)> {
+        let is_bid = self
+            .bids
+            .iter()
+            .any(|resting| resting.order.exchange_id == exchange_id);
+        let (own, other) = if is_bid {
+            (&mut self.bids, &mut self.asks)
+        } else {
+            (&mut self.asks, &mut self.bids)
+        };
+        let index = own
+            .iter()
+            .position(|resting| resting.order.exchange_id == exchange_id)?;
+        let taker = &mut own[index];
+
+        let mut fills = Vec::new();
+        for maker in other.iter_mut() {
+            let quantity = taker
+                .order
+                .remaining_quantity
+                .min(maker.order.remaining_quantity);
+            if !taker.accepts(mid, quantity) || !maker.accepts(mid, quantity) {
+                continue;
+            }
+            maker
+                .order
+                .fill(quantity)
+                .expect("maker fill within remaining quantity");
+            taker
+                .order
+                .fill(quantity)
+                .expect("taker fill within remaining quantity");
+            fills.push(Fill {
+                maker_id: maker.order.exchange_id,
+                taker_id: exchange_id,
+                price: mid,
+                quantity,
+                maker_client: maker.order.client.clone(),
+                maker_strategy: maker.order.strategy.clone(),
+                ..Default::default()
+            });
+            if taker.order.is_filled() {
+                break;
+            }
+        }
+
+        other.retain(|resting| !resting.order.is_filled());
+        let order = taker.order.clone();
+        if order.is_filled() {
+            own.remove(index);
+        }
+        Some((order, fills))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    fn order(exchange_id: u64, order_type: OrderType, shares: Decimal, price: Decimal) -> Order {
+        Order::new(
+            "BTC/USDT".to_string(),
+            exchange_id,
+            order_type,
+            shares,
+            price,
+            Utc::now(),
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn test_cross_respects_limits_and_minimums() {
+        let mut pool = MidpointPool::new();
+        pool.insert(order(1, OrderType::Ask, dec!(10), dec!(101)), dec!(1));
+        pool.insert(order(2, OrderType::Ask, dec!(3), dec!(99)), dec!(3));
+        pool.insert(order(3, OrderType::Ask, dec!(4), dec!(99)), dec!(1));
+        pool.insert(order(4, OrderType::Bid, dec!(5), dec!(100.5)), dec!(2));
+
+        // Order 1 asks more than the mid, so orders 2 and 3 share the bid.
+        let (bid, fills) = pool.cross(4, dec!(100)).unwrap();
+        assert!(bid.is_filled());
+        let filled: Vec<(u64, Decimal)> = fills
+            .iter()
+            .map(|fill| (fill.maker_id, fill.quantity))
+            .collect();
+        assert_eq!(filled, vec![(2, dec!(3)), (3, dec!(2))]);
+        assert!(fills.iter().all(|fill| fill.price == dec!(100)));
+        assert_eq!(pool.get(3).unwrap().remaining_quantity, dec!(2));
+        assert!(pool.get(2).is_none() && pool.get(4).is_none());
+
+        pool.insert(order(5, OrderType::Bid, dec!(1), dec!(99.5)), dec!(1));
+        let (bid, fills) = pool.cross(5, dec!(100)).unwrap();
+        assert!(fills.is_empty());
+        assert_eq!(bid.remaining_quantity, dec!(1));
+        assert_eq!(pool.cancel(5).unwrap().remaining_quantity, dec!(0));
+        assert_eq!(pool.orders().len(), 2);
+    }
+
+    #[test]
+    fn test_mid_price_and_completing_below_minimum() {
+        let mut book = LimitOrderBook::new();
+        book.add_order(order(1, OrderType::Bid, dec!(1), dec!(99)));
+        assert_eq!(mid_price(&book), None);
+        book.add_order(order(2, OrderType::Ask, dec!(1), dec!(100)));
+        assert_eq!(mid_price(&book), Some(dec!(99.5)));
+
+        let mut pool = MidpointPool::new();
+        assert!(pool.is_empty());
+        assert!(pool.cross(7, dec!(99.5)).is_none());
+        pool.insert(order(8, OrderType::Ask, dec!(2), dec!(99)), dec!(5));
+        pool.insert(order(6, OrderType::Bid, dec!(9), dec!(100)), dec!(1));
+        assert_eq!(
+            pool.orders()
+                .iter()
+                .map(|order| order.exchange_id)
+                .collect::<Vec<_>>(),
+            vec![6, 8]
+        );
+
+        // The ask has less left than its minimum, so it may still complete.
+        let (ask, fills) = pool.cross(8, dec!(99.5)).unwrap();
+        assert!(ask.is_filled());
+        assert_eq!(fills[0].maker_id, 6);
+        assert_eq!(fills[0].quantity, dec!(2));
+        assert_eq!(pool.get(6).unwrap().remaining_quantity, dec!(7));
+        assert!(pool.cancel(8).is_none());
+        assert!(pool.cancel(6).is_some());
+        assert!(pool.is_empty());
+
+        let config: MidpointConfig = toml::from_str(r#"min_quantity = "5""#).unwrap();
+        assert_eq!(config.min_quantity, dec!(5));
+        assert!(toml::from_str::<MidpointConfig>("size = 1").is_err());
+    }
+}
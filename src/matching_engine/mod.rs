@@ -1,2 +1,32 @@
+pub mod accounts;
+pub mod adl;
+pub mod audit;
+pub mod bands;
+pub mod basket;
+pub mod bracket;
+pub mod calendar;
+pub mod command;
+pub mod corporate_actions;
+pub mod drop_copy;
 pub mod engine;
+pub mod fees;
+pub mod funding;
+pub mod insurance;
+pub mod liquidation;
+pub mod midpoint;
 pub mod orderbook;
+pub mod peg;
+pub mod portfolio;
+pub mod positions;
+pub mod precision;
+pub mod pricing;
+pub mod quote;
+pub mod rate_limit;
+pub mod rates;
+pub mod risk;
+pub mod settlement;
+pub mod snapshot;
+pub mod state_hash;
+pub mod surveillance;
+pub mod ticker;
+pub mod validation;
@@ -13,6 +13,12 @@ pub struct OrderBook {
     asks: HashMap<Decimal, LimitOrder>,
 }
 
+impl Default for OrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl OrderBook {
     pub fn new() -> OrderBook {
         OrderBook {
@@ -38,13 +44,13 @@ impl OrderBook {
 
     pub fn ask_limits(&mut self) -> Vec<&mut LimitOrder> {
         let mut limits = self.asks.values_mut().collect::<Vec<&mut LimitOrder>>();
-        limits.sort_by(|a, b| a.price.cmp(&b.price));
+        limits.sort_by_key(|limit| limit.price);
         limits
     }
 
     pub fn bid_limits(&mut self) -> Vec<&mut LimitOrder> {
         let mut limits = self.bids.values_mut().collect::<Vec<&mut LimitOrder>>();
-        limits.sort_by(|a, b| b.price.cmp(&a.price));
+        limits.sort_by_key(|limit| std::cmp::Reverse(limit.price));
         limits
     }
 
@@ -53,7 +59,7 @@ impl OrderBook {
             OrderType::Bid => match self.bids.get_mut(&price) {
                 Some(limit_order) => limit_order.add_order(order),
                 None => {
-                    let mut limit_order = LimitOrder::new(price.clone());
+                    let mut limit_order = LimitOrder::new(price);
                     limit_order.add_order(order);
                     self.bids.insert(price, limit_order);
                 }
@@ -61,7 +67,7 @@ impl OrderBook {
             OrderType::Ask => match self.asks.get_mut(&price) {
                 Some(limit_order) => limit_order.add_order(order),
                 None => {
-                    let mut limit_order = LimitOrder::new(price.clone());
+                    let mut limit_order = LimitOrder::new(price);
                     limit_order.add_order(order);
                     self.asks.insert(price, limit_order);
                 }
@@ -84,7 +90,7 @@ impl LimitOrder {
         }
     }
 
-    fn total_volume(&self) -> f64 {
+    pub fn total_volume(&self) -> f64 {
         self.orders
             .iter()
             .map(|order| order.size)
@@ -133,6 +139,7 @@ impl Order {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::get_first)]
 pub mod tests {
     use super::*;
     use rust_decimal_macros::dec;
@@ -0,0 +1,288 @@
+use crate::limit_order_book::order::{Limit, LimitOrderBook, Order, OrderType};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+/// Re-pricing passes run per book change before giving up on settling.
+const MAX_PASSES: usize = 16;
+
+/// The price a pegged order tracks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PegReference {
+    /// Best price on the order's own side.
+    Primary,
+    /// Best price on the opposite side.
+    Market,
+    /// Midpoint of the best bid and ask.
+    Mid,
+}
+
+/// Keeps a resting order's price a fixed offset behind a reference price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Peg {
+    pub reference: PegReference,
+    /// Distance behind the reference: subtracted for bids, added for asks.
+    pub offset: Decimal,
+    /// Worst price the order will follow the reference to.
+    pub limit: Option<Decimal>,
+}
+
+impl Peg {
+    pub fn new(reference: PegReference, offset: Decimal) -> Self {
+        Self {
+            reference,
+            offset,
+            limit: None,
+        }
+    }
+
+    pub fn with_limit(mut self, limit: Decimal) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Where `order` should rest given the rest of `book`, rounded to
+    /// `tick_size` away from the opposite side. The order itself is left out
+    /// of the reference so a pegged order never chases its own price. None
+    /// when the reference side is empty.
+    pub fn price(
+        &self,
+        book: &LimitOrderBook,
+        order: &Order,
+        tick_size: Option<Decimal>,
+    ) -> Option<Decimal> {
+        let best_bid = best_excluding(book.bids.iter().rev(), order.exchange_id);
+        let best_ask = best_excluding(book.asks.iter(), order.exchange_id);
+        let reference =
+            match (self.reference, order.order_type) {
+                (PegReference::Primary, OrderType::Bid)
+                | (PegReference::Market, OrderType::Ask) => best_bid?,
+                (PegReference::Primary, OrderType::Ask)
+                | (PegReference::Market, OrderType::Bid) => best_ask?,
+                (PegReference::Mid, _) => (best_bid? + best_ask?) / Decimal::TWO,
+            };
+
+        let price = match order.order_type {
+            OrderType::Bid => {
+                let price = reference - self.offset;
+                let price = tick_size.map_or(price, |tick| (price / tick).floor() * tick);
+                self.limit.map_or(price, |limit| price.min(limit))
+            }
+            OrderType::Ask => {
+                let price = reference + self.offset;
+                let price = tick_size.map_or(price, |tick| (price / tick).ceil() * tick);
+                self.limit.map_or(price, |limit| price.max(limit))
+            }
+        };
+        Some(price)
+    }
+}
+
+fn best_excluding<'a>(
+    levels: impl Iterator<Item = (&'a Decimal, &'a Rc<RefCell<Limit>>)>,
+    exchange_id: u64,
+) -> Option<Decimal> {
+    levels
+        .filter(|(_, limit)| {
+            let limit = limit.borrow();
+            !(limit.orders.len() == 1 && limit.orders.contains_key(&exchange_id))
+        })
+        .map(|(price, _)| *price)
+        .next()
+}
+
+/// Whether a bid or ask at `price` would trade with the opposite side.
+fn crosses(book: &LimitOrderBook, order_type: OrderType, price: Decimal) -> bool {
+    match order_type {
+        OrderType::Bid => book.lowest_ask.is_some_and(|ask| price >= ask),
+        OrderType::Ask => book.highest_bid.is_some_and(|bid| price <= bid),
+    }
+}
+
+/// A pegged order moving to follow its reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Repriced {
+    pub exchange_id: u64,
+    pub from: Decimal,
+    pub to: Decimal,
+}
+
+/// The pegged orders resting on one book.
+#[derive(Debug, Clone, Default)]
+pub struct PeggedOrders {
+    pegs: BTreeMap<u64, Peg>,
+    /// Best bid and ask as of the last re-pricing pass.
+    last_top: (Option<Decimal>, Option<Decimal>),
+}
+
+impl PeggedOrders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Prices `order` off `book` for its first placement. Pegged orders only
+    /// ever provide liquidity, so one that would cross is rejected.
+    pub fn initial_price(
+        book: &LimitOrderBook,
+        order: &Order,
+        peg: &Peg,
+        tick_size: Option<Decimal>,
+    ) -> Result<Decimal, String> {
+        let price = peg
+            .price(book, order, tick_size)
+            .ok_or_else(|| format!("No {:?} reference price to peg to", peg.reference))?;
+        if crosses(book, order.order_type, price) {
+            return Err(format!("Pegged price {} would cross the book", price));
+        }
+        Ok(price)
+    }
+
+    pub fn insert(&mut self, exchange_id: u64, peg: Peg) {
+        self.pegs.insert(exchange_id, peg);
+    }
+
+    pub fn get(&self, exchange_id: u64) -> Option<&Peg> {
+        self.pegs.get(&exchange_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pegs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pegs.is_empty()
+    }
+
+    /// Moves pegged orders to follow their references if the top of `book`
+    /// has changed since the last pass. Orders that are no longer resting
+    /// are forgotten. A move to a new price loses time priority; a move
+    /// that would cross the book is skipped. Passes repeat until the top of
+    /// book settles, since one pegged order moving can shift another's
+    /// reference.
+    pub fn reprice(
+        &mut self,
+        book: &mut LimitOrderBook,
+        tick_size: Option<Decimal>,
+    ) -> Vec<Repriced> {
+        self.pegs.retain(|id, _| book.orders.contains_key(id));
+        let mut repriced = Vec::new();
+
+        for _ in 0..MAX_PASSES {
+            let top = (book.highest_bid, book.lowest_ask);
+            if top == self.last_top {
+                break;
+            }
+            self.last_top = top;
+
+            for (&exchange_id, peg) in &self.pegs {
+                let order = book.orders[&exchange_id].clone();
+                let price = match peg.price(book, &order, tick_size) {
+                    Some(price) if price > Decimal::ZERO && price != order.limit_price => price,
+                    _ => continue,
+                };
+                if crosses(book, order.order_type, price) {
+                    continue;
+                }
+                book.remove_order(order.clone());
+                book.add_order(Order {
+                    limit_price: price,
+                    ..order.clone()
+                });
+                repriced.push(Repriced {
+                    exchange_id,
+                    from: order.limit_price,
+                    to: price,
+                });
+            }
+        }
+        repriced
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    fn order(exchange_id: u64, order_type: OrderType, shares: Decimal, price: Decimal) -> Order {
+        Order::new(
+            "BTC/USDT".to_string(),
+            exchange_id,
+            order_type,
+            shares,
+            price,
+            Utc::now(),
+            Utc::now(),
+        )
+    }
+
+    fn book() -> LimitOrderBook {
+        let mut book = LimitOrderBook::new();
+        book.add_order(order(1, OrderType::Bid, dec!(5), dec!(99)));
+        book.add_order(order(2, OrderType::Ask, dec!(5), dec!(102)));
+        book
+    }
+
+    #[test]
+    fn test_peg_prices() {
+        let book = book();
+        let bid = order(10, OrderType::Bid, dec!(1), dec!(0));
+        let ask = order(11, OrderType::Ask, dec!(1), dec!(0));
+
+        let primary = Peg::new(PegReference::Primary, dec!(0));
+        assert_eq!(primary.price(&book, &bid, None), Some(dec!(99)));
+        assert_eq!(primary.price(&book, &ask, None), Some(dec!(102)));
+
+        let market = Peg::new(PegReference::Market, dec!(1));
+        assert_eq!(market.price(&book, &bid, None), Some(dec!(101)));
+        assert_eq!(market.price(&book, &ask, None), Some(dec!(100)));
+
+        // Mid is 100.5; ticks round away from the other side.
+        let mid = Peg::new(PegReference::Mid, dec!(0));
+        assert_eq!(mid.price(&book, &bid, None), Some(dec!(100.5)));
+        assert_eq!(mid.price(&book, &bid, Some(dec!(1))), Some(dec!(100)));
+        assert_eq!(mid.price(&book, &ask, Some(dec!(1))), Some(dec!(101)));
+
+        let capped = mid.with_limit(dec!(100.25));
+        assert_eq!(capped.price(&book, &bid, None), Some(dec!(100.25)));
+        assert_eq!(capped.price(&book, &ask, None), Some(dec!(100.5)));
+
+        assert_eq!(mid.price(&LimitOrderBook::new(), &bid, None), None);
+        let crossing = Peg::new(PegReference::Market, dec!(-1));
+        assert!(PeggedOrders::initial_price(&book, &bid, &crossing, None).is_err());
+    }
+
+    #[test]
+    fn test_reprice_follows_top_of_book() {
+        let mut book = book();
+        let mut pegs = PeggedOrders::new();
+        let peg = Peg::new(PegReference::Primary, dec!(0)).with_limit(dec!(100));
+        book.add_order(order(10, OrderType::Bid, dec!(2), dec!(99)));
+        pegs.insert(10, peg);
+        assert!(pegs.reprice(&mut book, None).is_empty());
+
+        // Best bid improves: the peg follows up to its limit.
+        book.add_order(order(3, OrderType::Bid, dec!(1), dec!(101)));
+        let repriced = pegs.reprice(&mut book, None);
+        assert_eq!(
+            repriced,
+            vec![Repriced {
+                exchange_id: 10,
+                from: dec!(99),
+                to: dec!(100),
+            }]
+        );
+        assert_eq!(book.get_bid_depth(dec!(100)), dec!(2));
+
+        // The better bid leaves: back to 99, not its own 100.
+        book.cancel_order(3);
+        pegs.reprice(&mut book, None);
+        assert_eq!(book.get_order(10).unwrap().limit_price, dec!(99));
+        assert_eq!(book.get_bid_depth(dec!(99)), dec!(7));
+
+        book.cancel_order(10);
+        pegs.reprice(&mut book, None);
+        assert!(pegs.is_empty());
+    }
+}
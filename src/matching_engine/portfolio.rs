@@ -0,0 +1,796 @@
+use crate::{
+    export::{self, CsvRecord},
+    limit_order_book::order::{Fill, OrderType},
+    matching_engine::{
+        accounts::AccountId, corporate_actions::CorporateActionKind, engine::TradingPair,
+        precision::Precision, rates::Rates, snapshot::HoldingSnapshot,
+    },
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+/// One account's holding in one market, in the market's quote currency.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Holding {
+    /// Net quantity, positive when long.
+    pub position: Decimal,
+    /// Average price the open position was built at.
+    pub average_price: Decimal,
+    pub realized_pnl: Decimal,
+    pub fees: Decimal,
+    /// Funding paid, less funding received.
+    pub funding: Decimal,
+    /// Quantities bought and sold since the last settlement.
+    pub bought: Decimal,
+    pub sold: Decimal,
+}
+
+impl Holding {
+    /// Books a trade of `quantity`, negative when selling. Closing trades
+    /// realize P&L against the average price; a trade through zero opens
+    /// the rest at `price`. The average price and P&L are rounded to
+    /// `precision`.
+    fn trade(
+        &mut self,
+        quantity: Decimal,
+        price: Decimal,
+        multiplier: Decimal,
+        precision: &Precision,
+    ) {
+        if quantity.is_sign_positive() {
+            self.bought += quantity;
+        } else {
+            self.sold -= quantity;
+        }
+        let position = self.position;
+        if position.is_zero() || position.is_sign_positive() == quantity.is_sign_positive() {
+            let size = position.abs() + quantity.abs();
+            self.average_price = precision
+                .price((self.average_price * position.abs() + price * quantity.abs()) / size);
+            self.position += quantity;
+            return;
+        }
+        let closed = quantity.abs().min(position.abs());
+        let direction = if position.is_sign_positive() {
+            Decimal::ONE
+        } else {
+            Decimal::NEGATIVE_ONE
+        };
+        self.realized_pnl +=
+            precision.price((price - self.average_price) * closed * direction * multiplier);
+        self.position += quantity;
+        if self.position.is_zero() {
+            self.average_price = Decimal::ZERO;
+        } else if self.position.is_sign_positive() != position.is_sign_positive() {
+            self.average_price = price;
+        }
+    }
+
+    /// P&L of the open position if it were closed at `mark`.
+    pub fn unrealized_pnl(&self, mark: Decimal, multiplier: Decimal) -> Decimal {
+        (mark - self.average_price) * self.position * multiplier
+    }
+}
+
+/// Positions, P&L and fees per account and market, built from the engine's
+/// fills and valued at each market's mark price.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Portfolio {
+    holdings: HashMap<AccountId, HashMap<TradingPair, Holding>>,
+    /// The trading of orders tagged with a strategy, again by account.
+    strategies: HashMap<(AccountId, String), HashMap<TradingPair, Holding>>,
+    multipliers: HashMap<TradingPair, Decimal>,
+    precisions: HashMap<TradingPair, Precision>,
+    marks: HashMap<TradingPair, Decimal>,
+}
+
+impl Portfolio {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn holding(&self, account: &str, pair: &TradingPair) -> Holding {
+        self.holdings
+            .get(account)
+            .and_then(|holdings| holdings.get(pair))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = &AccountId> {
+        self.holdings.keys()
+    }
+
+    pub fn mark_price(&self, pair: &TradingPair) -> Option<Decimal> {
+        self.marks.get(pair).copied()
+    }
+
+    /// Sets how amounts booked in `pair` are rounded.
+    pub fn set_precision(&mut self, pair: &TradingPair, precision: Precision) {
+        self.precisions.insert(pair.clone(), precision);
+    }
+
+    /// Sets the price open positions in `pair` are valued at.
+    pub fn mark(&mut self, pair: &TradingPair, price: Decimal) {
+        self.marks.insert(pair.clone(), price);
+    }
+
+    /// `account`'s trading under `strategy` in `pair`. Only fills are
+    /// attributed: funding, corporate actions and settlements apply to the
+    /// account's holdings alone.
+    pub fn strategy_holding(&self, account: &str, strategy: &str, pair: &TradingPair) -> Holding {
+        self.strategies
+            .get(&(account.into(), strategy.to_string()))
+            .and_then(|holdings| holdings.get(pair))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The strategies `account` has traded under, sorted.
+    pub fn strategies(&self, account: &str) -> Vec<&str> {
+        let mut strategies: Vec<&str> = self
+            .strategies
+            .keys()
+            .filter(|(owner, _)| owner.as_str() == account)
+            .map(|(_, strategy)| strategy.as_str())
+            .collect();
+        strategies.sort();
+        strategies
+    }
+
+    /// Books `fills` taken by `taker` on `taker_side`, with their fees, in a
+    /// market of `multiplier` units per contract. Tagged orders are also
+    /// booked to their strategy: the taker's is `taker_strategy`, each
+    /// maker's is on its fill.
+    pub fn record(
+        &mut self,
+        pair: &TradingPair,
+        taker: &AccountId,
+        taker_strategy: Option<&str>,
+        taker_side: OrderType,
+        fills: &[Fill],
+        multiplier: Decimal,
+    ) {
+        let bought = match taker_side {
+            OrderType::Bid => Decimal::ONE,
+            OrderType::Ask => Decimal::NEGATIVE_ONE,
+        };
+        self.multipliers.insert(pair.clone(), multiplier);
+        let precision = self.precision(pair);
+        for fill in fills {
+            let taker_holding = self.entry(taker.clone(), pair);
+            taker_holding.trade(bought * fill.quantity, fill.price, multiplier, &precision);
+            taker_holding.fees += fill.taker_fee;
+            let maker_holding = self.entry(fill.maker_client.as_str().into(), pair);
+            maker_holding.trade(-bought * fill.quantity, fill.price, multiplier, &precision);
+            maker_holding.fees += fill.maker_fee;
+
+            if let Some(strategy) = taker_strategy {
+                let holding = self.strategy_entry(taker.clone(), strategy, pair);
+                holding.trade(bought * fill.quantity, fill.price, multiplier, &precision);
+                holding.fees += fill.taker_fee;
+            }
+            if let Some(strategy) = &fill.maker_strategy {
+                let maker = fill.maker_client.as_str().into();
+                let holding = self.strategy_entry(maker, strategy, pair);
+                holding.trade(-bought * fill.quantity, fill.price, multiplier, &precision);
+                holding.fees += fill.maker_fee;
+            }
+        }
+    }
+
+    /// Charges every open position in `pair` `rate` of its value at `mark`,
+    /// longs paying when the rate is positive. Returns each account's
+    /// position and what it paid, by account.
+    pub fn apply_funding(
+        &mut self,
+        pair: &TradingPair,
+        rate: Decimal,
+        mark: Decimal,
+    ) -> Vec<(AccountId, Decimal, Decimal)> {
+        let multiplier = self.multipliers.get(pair).copied().unwrap_or(Decimal::ONE);
+        let precision = self.precision(pair);
+        let mut payments = Vec::new();
+        for (account, holdings) in &mut self.holdings {
+            let Some(holding) = holdings
+                .get_mut(pair)
+                .filter(|holding| !holding.position.is_zero())
+            else {
+                continue;
+            };
+            let payment = precision.price(holding.position * mark * multiplier * rate);
+            holding.funding += payment;
+            payments.push((account.clone(), holding.position, payment));
+        }
+        payments.sort_by(|a, b| a.0.cmp(&b.0));
+        payments
+    }
+
+    /// Applies a split or dividend to every open position in `pair`; a
+    /// dividend is booked as realized P&L. Returns each account's position
+    /// afterwards and the dividend it was paid, by account.
+    pub fn apply_corporate_action(
+        &mut self,
+        pair: &TradingPair,
+        action: &CorporateActionKind,
+    ) -> Vec<(AccountId, Decimal, Decimal)> {
+        let multiplier = self.multiplier(pair);
+        let precision = self.precision(pair);
+        let mut adjusted = Vec::new();
+        for (account, holdings) in &mut self.holdings {
+            let Some(holding) = holdings
+                .get_mut(pair)
+                .filter(|holding| !holding.position.is_zero())
+            else {
+                continue;
+            };
+            let dividend = match action {
+                CorporateActionKind::Split(_) => {
+                    holding.position = precision.quantity(action.adjust_quantity(holding.position));
+                    holding.average_price =
+                        precision.price(action.adjust_price(holding.average_price));
+                    holding.bought = precision.quantity(action.adjust_quantity(holding.bought));
+                    holding.sold = precision.quantity(action.adjust_quantity(holding.sold));
+                    Decimal::ZERO
+                }
+                CorporateActionKind::Dividend(amount) => {
+                    precision.price(holding.position * amount * multiplier)
+                }
+            };
+            holding.realized_pnl += dividend;
+            adjusted.push((account.clone(), holding.position, dividend));
+        }
+        if let Some(mark) = self.marks.get_mut(pair) {
+            *mark = action.adjust_price(*mark);
+        }
+        adjusted.sort_by(|a, b| a.0.cmp(&b.0));
+        adjusted
+    }
+
+    /// Marks every holding in `pair` to `price` for the end of the day.
+    /// Returns each account's holding as it stood, by account, then starts
+    /// its next day: the open position is carried at `price`, and its P&L,
+    /// fees, funding and traded quantities go back to zero.
+    pub fn settle(&mut self, pair: &TradingPair, price: Decimal) -> Vec<(AccountId, Holding)> {
+        let mut settled = Vec::new();
+        for (account, holdings) in &mut self.holdings {
+            let Some(holding) = holdings
+                .get_mut(pair)
+                .filter(|holding| **holding != Holding::default())
+            else {
+                continue;
+            };
+            settled.push((account.clone(), *holding));
+            *holding = Holding {
+                position: holding.position,
+                average_price: if holding.position.is_zero() {
+                    Decimal::ZERO
+                } else {
+                    price
+                },
+                ..Holding::default()
+            };
+        }
+        self.marks.insert(pair.clone(), price);
+        settled.sort_by(|a, b| a.0.cmp(&b.0));
+        settled
+    }
+
+    /// The number of units each contract in `pair` stands for.
+    pub fn multiplier(&self, pair: &TradingPair) -> Decimal {
+        self.multipliers.get(pair).copied().unwrap_or(Decimal::ONE)
+    }
+
+    pub fn precision(&self, pair: &TradingPair) -> Precision {
+        self.precisions.get(pair).copied().unwrap_or_default()
+    }
+
+    /// `account`'s holdings with their totals in `currency`, converted at
+    /// `rates`. Markets whose quote currency has no rate to `currency` are
+    /// listed but left out of the totals.
+    pub fn report(&self, account: &str, rates: &Rates, currency: &str) -> PortfolioReport {
+        self.report_holdings(account, self.holdings.get(account), rates, currency)
+    }
+
+    /// Like `report`, for `account`'s trading under `strategy`; see
+    /// `strategy_holding`.
+    pub fn strategy_report(
+        &self,
+        account: &str,
+        strategy: &str,
+        rates: &Rates,
+        currency: &str,
+    ) -> PortfolioReport {
+        let holdings = self.strategies.get(&(account.into(), strategy.to_string()));
+        self.report_holdings(account, holdings, rates, currency)
+    }
+
+    fn report_holdings(
+        &self,
+        account: &str,
+        holdings: Option<&HashMap<TradingPair, Holding>>,
+        rates: &Rates,
+        currency: &str,
+    ) -> PortfolioReport {
+        let mut report = PortfolioReport {
+            account: account.to_string(),
+            currency: currency.to_string(),
+            ..PortfolioReport::default()
+        };
+        for (pair, holding) in holdings.into_iter().flatten() {
+            let multiplier = self.multipliers.get(pair).copied().unwrap_or(Decimal::ONE);
+            let mark_price = self.mark_price(pair);
+            let mark = mark_price.unwrap_or(holding.average_price);
+            let row = HoldingReport {
+                account: account.to_string(),
+                pair: pair.to_string(),
+                currency: pair.quote().to_string(),
+                position: holding.position,
+                average_price: holding.average_price,
+                mark_price,
+                notional: (mark * holding.position * multiplier).abs(),
+                realized_pnl: holding.realized_pnl,
+                unrealized_pnl: holding.unrealized_pnl(mark, multiplier),
+                fees: holding.fees,
+                funding: holding.funding,
+            };
+            match rates.rate(pair.quote(), currency) {
+                Some(rate) => {
+                    report.notional += row.notional * rate;
+                    report.realized_pnl += row.realized_pnl * rate;
+                    report.unrealized_pnl += row.unrealized_pnl * rate;
+                    report.fees += row.fees * rate;
+                    report.funding += row.funding * rate;
+                }
+                None => report.unconverted.push(row.pair.clone()),
+            }
+            report.holdings.push(row);
+        }
+        report.holdings.sort_by(|a, b| a.pair.cmp(&b.pair));
+        report.unconverted.sort();
+        report
+    }
+
+    /// Every holding, the account's own and its strategies', sorted by
+    /// account, strategy and pair.
+    pub fn snapshot(&self) -> Vec<HoldingSnapshot> {
+        let own = self
+            .holdings
+            .iter()
+            .map(|(account, holdings)| (account, None, holdings));
+        let strategies = self
+            .strategies
+            .iter()
+            .map(|((account, strategy), holdings)| (account, Some(strategy), holdings));
+        let mut snapshot: Vec<HoldingSnapshot> = own
+            .chain(strategies)
+            .flat_map(|(account, strategy, holdings)| {
+                holdings.iter().map(move |(pair, holding)| HoldingSnapshot {
+                    account: account.clone(),
+                    strategy: strategy.cloned(),
+                    pair: pair.to_string(),
+                    holding: *holding,
+                })
+            })
+            .collect();
+        snapshot.sort_by(|a, b| {
+            (&a.account, &a.strategy, &a.pair).cmp(&(&b.account, &b.strategy, &b.pair))
+        });
+        snapshot
+    }
+
+    /// Replaces every holding with the snapshot's, in markets of `multiplier`
+    /// units per contract. Marks are left to be set again.
+    pub fn restore(
+        &mut self,
+        holdings: &[HoldingSnapshot],
+        multiplier: impl Fn(&TradingPair) -> Decimal,
+    ) -> Result<(), String> {
+        self.holdings.clear();
+        self.strategies.clear();
+        for snapshot in holdings {
+            let pair: TradingPair = snapshot.pair.parse()?;
+            self.multipliers.insert(pair.clone(), multiplier(&pair));
+            let account = snapshot.account.clone();
+            let holding = match &snapshot.strategy {
+                Some(strategy) => self.strategy_entry(account, strategy, &pair),
+                None => self.entry(account, &pair),
+            };
+            *holding = snapshot.holding;
+        }
+        Ok(())
+    }
+
+    fn entry(&mut self, account: AccountId, pair: &TradingPair) -> &mut Holding {
+        self.holdings
+            .entry(account)
+            .or_default()
+            .entry(pair.clone())
+            .or_default()
+    }
+
+    fn strategy_entry(
+        &mut self,
+        account: AccountId,
+        strategy: &str,
+        pair: &TradingPair,
+    ) -> &mut Holding {
+        self.strategies
+            .entry((account, strategy.to_string()))
+            .or_default()
+            .entry(pair.clone())
+            .or_default()
+    }
+}
+
+/// One market of a `PortfolioReport`, in the market's quote currency.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HoldingReport {
+    pub account: String,
+    pub pair: String,
+    pub currency: String,
+    pub position: Decimal,
+    pub average_price: Decimal,
+    /// None while the market has neither traded nor been marked; the
+    /// position is then valued at its average price.
+    pub mark_price: Option<Decimal>,
+    pub notional: Decimal,
+    pub realized_pnl: Decimal,
+    pub unrealized_pnl: Decimal,
+    pub fees: Decimal,
+    pub funding: Decimal,
+}
+
+impl CsvRecord for HoldingReport {
+    const HEADER: &'static [&'static str] = &[
+        "account",
+        "pair",
+        "currency",
+        "position",
+        "average_price",
+        "mark_price",
+        "notional",
+        "realized_pnl",
+        "unrealized_pnl",
+        "fees",
+        "funding",
+    ];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.account.clone(),
+            self.pair.clone(),
+            self.currency.clone(),
+            self.position.to_string(),
+            self.average_price.to_string(),
+            self.mark_price
+                .map(|price| price.to_string())
+                .unwrap_or_default(),
+            self.notional.to_string(),
+            self.realized_pnl.to_string(),
+            self.unrealized_pnl.to_string(),
+            self.fees.to_string(),
+            self.funding.to_string(),
+        ]
+    }
+}
+
+/// A snapshot of one account's portfolio.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortfolioReport {
+    pub account: String,
+    /// The currency the totals are in.
+    pub currency: String,
+    /// One row per market, by pair.
+    pub holdings: Vec<HoldingReport>,
+    pub notional: Decimal,
+    pub realized_pnl: Decimal,
+    pub unrealized_pnl: Decimal,
+    pub fees: Decimal,
+    pub funding: Decimal,
+    /// Markets left out of the totals for lack of a conversion rate.
+    pub unconverted: Vec<String>,
+}
+
+impl PortfolioReport {
+    /// Realized plus unrealized P&L, less fees and funding paid.
+    pub fn net_pnl(&self) -> Decimal {
+        self.realized_pnl + self.unrealized_pnl - self.fees - self.funding
+    }
+
+    /// Writes the holdings as CSV, one row per market.
+    pub fn write_csv<W: Write>(&self, writer: W) -> io::Result<()> {
+        export::write_csv(writer, &self.holdings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn fill(maker_client: &str, price: Decimal, quantity: Decimal) -> Fill {
+        Fill {
+            maker_client: maker_client.to_string(),
+            price,
+            quantity,
+            taker_fee: dec!(1),
+            ..Fill::default()
+        }
+    }
+
+    #[test]
+    fn test_realized_and_unrealized_pnl() {
+        let btc = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let eth = TradingPair::new("ETH".to_string(), "EUR".to_string());
+        let alice = AccountId::from("alice");
+        let mut portfolio = Portfolio::new();
+
+        portfolio.record(
+            &btc,
+            &alice,
+            None,
+            OrderType::Bid,
+            &[
+                fill("bob", dec!(100), dec!(2)),
+                fill("bob", dec!(110), dec!(2)),
+            ],
+            Decimal::ONE,
+        );
+        assert_eq!(portfolio.holding("alice", &btc).average_price, dec!(105));
+        // Selling 6 closes the long of 4 and opens a short of 2 at 120.
+        portfolio.record(
+            &btc,
+            &alice,
+            None,
+            OrderType::Ask,
+            &[fill("carol", dec!(120), dec!(6))],
+            Decimal::ONE,
+        );
+        let holding = portfolio.holding("alice", &btc);
+        assert_eq!(holding.position, dec!(-2));
+        assert_eq!(holding.average_price, dec!(120));
+        assert_eq!(holding.realized_pnl, dec!(60));
+        assert_eq!(holding.fees, dec!(3));
+        assert_eq!(portfolio.holding("bob", &btc).position, dec!(-4));
+
+        portfolio.record(
+            &eth,
+            &alice,
+            None,
+            OrderType::Bid,
+            &[fill("bob", dec!(10), dec!(1))],
+            dec!(10),
+        );
+        portfolio.mark(&btc, dec!(115));
+        portfolio.mark(&eth, dec!(12));
+
+        let mut rates = Rates::new();
+        rates.set("USDT", "USD", dec!(1));
+        let report = portfolio.report("alice", &rates, "USD");
+        assert_eq!(report.holdings.len(), 2);
+        assert_eq!(report.holdings[0].unrealized_pnl, dec!(10));
+        assert_eq!(report.holdings[1].unrealized_pnl, dec!(20));
+        assert_eq!(report.unconverted, vec!["ETH/EUR".to_string()]);
+        assert_eq!(report.net_pnl(), dec!(67));
+
+        rates.set("EUR", "USD", dec!(1.5));
+        let report = portfolio.report("alice", &rates, "USD");
+        assert_eq!(report.notional, dec!(410));
+        assert_eq!(report.net_pnl(), dec!(95.5));
+
+        let mut out = Vec::new();
+        report.write_csv(&mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.contains("alice,BTC/USDT,USDT,-2,120,115,230,60,10,3,0\n"));
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let future = TradingPair::new("BTC-MAR25".to_string(), "USD".to_string());
+        let mut portfolio = Portfolio::new();
+        let mut maker = fill("bob", dec!(100), dec!(3));
+        maker.maker_strategy = Some("mm".to_string());
+        portfolio.record(
+            &future,
+            &"alice".into(),
+            Some("momentum"),
+            OrderType::Bid,
+            &[maker],
+            dec!(10),
+        );
+
+        let snapshot = portfolio.snapshot();
+        let keys: Vec<(&str, Option<&str>)> = snapshot
+            .iter()
+            .map(|holding| (holding.account.as_str(), holding.strategy.as_deref()))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("alice", None),
+                ("alice", Some("momentum")),
+                ("bob", None),
+                ("bob", Some("mm"))
+            ]
+        );
+
+        let mut restored = Portfolio::new();
+        restored.restore(&snapshot, |_| dec!(10)).unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(
+            restored.strategy_holding("bob", "mm", &future).position,
+            dec!(-3)
+        );
+        // The multiplier comes back with the holdings, for funding.
+        let payments = restored.apply_funding(&future, dec!(0.01), dec!(100));
+        assert_eq!(payments[0], ("alice".into(), dec!(3), dec!(30)));
+    }
+
+    #[test]
+    fn test_funding_and_corporate_actions() {
+        let pair = TradingPair::new("ACME".to_string(), "USD".to_string());
+        let mut portfolio = Portfolio::new();
+        portfolio.set_precision(
+            &pair,
+            Precision {
+                price_decimals: Some(2),
+                ..Precision::default()
+            },
+        );
+        portfolio.record(
+            &pair,
+            &"alice".into(),
+            None,
+            OrderType::Bid,
+            &[fill("bob", dec!(100), dec!(3))],
+            Decimal::ONE,
+        );
+        // Carol's flat holding pays no funding.
+        for side in [OrderType::Bid, OrderType::Ask] {
+            portfolio.record(
+                &pair,
+                &"carol".into(),
+                None,
+                side,
+                &[fill("dave", dec!(100), dec!(1))],
+                Decimal::ONE,
+            );
+        }
+
+        let payments = portfolio.apply_funding(&pair, dec!(0.0001234), dec!(100));
+        assert_eq!(
+            payments,
+            vec![
+                ("alice".into(), dec!(3), dec!(0.04)),
+                ("bob".into(), dec!(-3), dec!(-0.04)),
+            ]
+        );
+        assert_eq!(portfolio.holding("alice", &pair).funding, dec!(0.04));
+
+        portfolio.mark(&pair, dec!(110));
+        let split = portfolio.apply_corporate_action(&pair, &CorporateActionKind::Split(dec!(2)));
+        assert_eq!(split[0], ("alice".into(), dec!(6), dec!(0)));
+        let alice = portfolio.holding("alice", &pair);
+        assert_eq!((alice.average_price, alice.bought), (dec!(50), dec!(6)));
+        assert_eq!(portfolio.mark_price(&pair), Some(dec!(55)));
+
+        // Longs are paid the dividend and shorts charged it.
+        let dividend =
+            portfolio.apply_corporate_action(&pair, &CorporateActionKind::Dividend(dec!(0.5)));
+        assert_eq!(
+            dividend,
+            vec![
+                ("alice".into(), dec!(6), dec!(3)),
+                ("bob".into(), dec!(-6), dec!(-3)),
+            ]
+        );
+        assert_eq!(portfolio.holding("bob", &pair).realized_pnl, dec!(-3));
+        assert_eq!(portfolio.mark_price(&pair), Some(dec!(54.5)));
+    }
+
+    #[test]
+    fn test_settle_carries_open_positions() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut portfolio = Portfolio::new();
+        portfolio.record(
+            &pair,
+            &"alice".into(),
+            None,
+            OrderType::Bid,
+            &[fill("bob", dec!(100), dec!(2))],
+            Decimal::ONE,
+        );
+        portfolio.record(
+            &pair,
+            &"carol".into(),
+            None,
+            OrderType::Bid,
+            &[fill("bob", dec!(100), dec!(1))],
+            Decimal::ONE,
+        );
+        portfolio.record(
+            &pair,
+            &"carol".into(),
+            None,
+            OrderType::Ask,
+            &[fill("bob", dec!(110), dec!(1))],
+            Decimal::ONE,
+        );
+
+        let settled = portfolio.settle(&pair, dec!(105));
+        let accounts: Vec<&str> = settled
+            .iter()
+            .map(|(account, _)| account.as_str())
+            .collect();
+        assert_eq!(accounts, vec!["alice", "bob", "carol"]);
+        let carol = settled[2].1;
+        assert_eq!(
+            (carol.realized_pnl, carol.fees, carol.bought, carol.sold),
+            (dec!(10), dec!(2), dec!(1), dec!(1))
+        );
+
+        assert_eq!(
+            portfolio.holding("alice", &pair),
+            Holding {
+                position: dec!(2),
+                average_price: dec!(105),
+                ..Holding::default()
+            }
+        );
+        assert_eq!(portfolio.holding("bob", &pair).realized_pnl, dec!(0));
+        assert_eq!(portfolio.holding("carol", &pair), Holding::default());
+        assert_eq!(portfolio.mark_price(&pair), Some(dec!(105)));
+        // Carol is flat with nothing booked, so is not settled again.
+        assert_eq!(portfolio.settle(&pair, dec!(106)).len(), 2);
+    }
+
+    #[test]
+    fn test_strategy_reports() {
+        let btc = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let eth = TradingPair::new("ETH".to_string(), "USDT".to_string());
+        let mut portfolio = Portfolio::new();
+        for (pair, strategy) in [(&btc, "trend"), (&eth, "arb"), (&btc, "arb")] {
+            portfolio.record(
+                pair,
+                &"alice".into(),
+                Some(strategy),
+                OrderType::Bid,
+                &[fill("bob", dec!(10), dec!(1))],
+                Decimal::ONE,
+            );
+        }
+        assert_eq!(portfolio.strategies("alice"), vec!["arb", "trend"]);
+        assert!(portfolio.strategies("bob").is_empty());
+        let mut accounts: Vec<&str> = portfolio.accounts().map(AccountId::as_str).collect();
+        accounts.sort();
+        assert_eq!(accounts, vec!["alice", "bob"]);
+
+        // Funding is booked to the account only.
+        portfolio.apply_funding(&btc, dec!(0.01), dec!(10));
+        assert_eq!(portfolio.holding("alice", &btc).funding, dec!(0.2));
+
+        let rates = Rates::new();
+        let report = portfolio.strategy_report("alice", "arb", &rates, "USDT");
+        let pairs: Vec<&str> = report
+            .holdings
+            .iter()
+            .map(|holding| holding.pair.as_str())
+            .collect();
+        assert_eq!(pairs, vec!["BTC/USDT", "ETH/USDT"]);
+        // Unmarked positions are valued at their average price.
+        assert_eq!(report.holdings[0].mark_price, None);
+        assert_eq!(report.notional, dec!(20));
+        assert_eq!(report.funding, dec!(0));
+        assert_eq!(report.fees, dec!(2));
+        assert!(portfolio
+            .strategy_report("alice", "none", &rates, "USDT")
+            .holdings
+            .is_empty());
+    }
+}
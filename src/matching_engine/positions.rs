@@ -0,0 +1,187 @@
+use crate::{
+    limit_order_book::order::{Fill, OrderType},
+    matching_engine::{accounts::AccountId, engine::TradingPair, snapshot::PositionSnapshot},
+};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// Net position per account and market, in the base asset: positive when
+/// long, negative when short. Built purely from the engine's fills.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Positions {
+    net: HashMap<AccountId, HashMap<TradingPair, Decimal>>,
+}
+
+impl Positions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn position(&self, account: &str, pair: &TradingPair) -> Decimal {
+        self.net
+            .get(account)
+            .and_then(|positions| positions.get(pair))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Every market `account` has traded, with its net position in each.
+    pub fn account(&self, account: &str) -> impl Iterator<Item = (&TradingPair, Decimal)> {
+        self.net
+            .get(account)
+            .into_iter()
+            .flatten()
+            .map(|(pair, position)| (pair, *position))
+    }
+
+    /// Books `fills` taken by `taker` on `taker_side`; each maker was on the
+    /// other side.
+    pub fn record(
+        &mut self,
+        pair: &TradingPair,
+        taker: &AccountId,
+        taker_side: OrderType,
+        fills: &[Fill],
+    ) {
+        let bought = match taker_side {
+            OrderType::Bid => Decimal::ONE,
+            OrderType::Ask => Decimal::NEGATIVE_ONE,
+        };
+        for fill in fills {
+            *self.entry(taker.clone(), pair) += bought * fill.quantity;
+            *self.entry(fill.maker_client.as_str().into(), pair) -= bought * fill.quantity;
+        }
+    }
+
+    /// Multiplies every position in `pair` by `ratio`, as in a stock split.
+    pub fn split(&mut self, pair: &TradingPair, ratio: Decimal) {
+        for positions in self.net.values_mut() {
+            if let Some(position) = positions.get_mut(pair) {
+                *position *= ratio;
+            }
+        }
+    }
+
+    /// The most an order on `side` can execute without taking the account's
+    /// position through zero: the long to sell or the short to buy back.
+    pub fn reducible(&self, account: &str, pair: &TradingPair, side: OrderType) -> Decimal {
+        let position = self.position(account, pair);
+        match side {
+            OrderType::Bid => (-position).max(Decimal::ZERO),
+            OrderType::Ask => position.max(Decimal::ZERO),
+        }
+    }
+
+    /// Every position, sorted by account and pair.
+    pub fn snapshot(&self) -> Vec<PositionSnapshot> {
+        let mut positions: Vec<PositionSnapshot> = self
+            .net
+            .iter()
+            .flat_map(|(account, positions)| {
+                positions.iter().map(|(pair, position)| PositionSnapshot {
+                    account: account.clone(),
+                    pair: pair.to_string(),
+                    position: *position,
+                })
+            })
+            .collect();
+        positions.sort_by(|a, b| (&a.account, &a.pair).cmp(&(&b.account, &b.pair)));
+        positions
+    }
+
+    /// Replaces every position with the snapshot's.
+    pub fn restore(&mut self, positions: &[PositionSnapshot]) -> Result<(), String> {
+        let mut net: HashMap<AccountId, HashMap<TradingPair, Decimal>> = HashMap::new();
+        for snapshot in positions {
+            net.entry(snapshot.account.clone())
+                .or_default()
+                .insert(snapshot.pair.parse()?, snapshot.position);
+        }
+        self.net = net;
+        Ok(())
+    }
+
+    fn entry(&mut self, account: AccountId, pair: &TradingPair) -> &mut Decimal {
+        self.net
+            .entry(account)
+            .or_default()
+            .entry(pair.clone())
+            .or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_positions_from_fills() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut positions = Positions::new();
+        let fill = |maker_client: &str, quantity| Fill {
+            maker_client: maker_client.to_string(),
+            quantity,
+            ..Fill::default()
+        };
+
+        positions.record(
+            &pair,
+            &"alice".into(),
+            OrderType::Bid,
+            &[fill("bob", dec!(2)), fill("carol", dec!(1))],
+        );
+        assert_eq!(positions.position("alice", &pair), dec!(3));
+        assert_eq!(positions.position("bob", &pair), dec!(-2));
+        assert_eq!(positions.reducible("alice", &pair, OrderType::Ask), dec!(3));
+        assert_eq!(positions.reducible("alice", &pair, OrderType::Bid), dec!(0));
+        assert_eq!(positions.reducible("bob", &pair, OrderType::Bid), dec!(2));
+
+        positions.record(
+            &pair,
+            &"alice".into(),
+            OrderType::Ask,
+            &[fill("bob", dec!(2))],
+        );
+        assert_eq!(positions.position("alice", &pair), dec!(1));
+        assert_eq!(positions.position("bob", &pair), dec!(0));
+        assert_eq!(positions.account("alice").count(), 1);
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let btc = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let eth = TradingPair::new("ETH".to_string(), "USDT".to_string());
+        let fill = Fill {
+            maker_client: "bob".to_string(),
+            quantity: dec!(2),
+            ..Fill::default()
+        };
+        let mut positions = Positions::new();
+        positions.record(
+            &eth,
+            &"alice".into(),
+            OrderType::Bid,
+            std::slice::from_ref(&fill),
+        );
+        positions.record(&btc, &"alice".into(), OrderType::Ask, &[fill]);
+
+        let snapshot = positions.snapshot();
+        let order: Vec<(&str, &str)> = snapshot
+            .iter()
+            .map(|position| (position.account.as_str(), position.pair.as_str()))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ("alice", "BTC/USDT"),
+                ("alice", "ETH/USDT"),
+                ("bob", "BTC/USDT"),
+                ("bob", "ETH/USDT")
+            ]
+        );
+        let mut restored = Positions::new();
+        restored.restore(&snapshot).unwrap();
+        assert_eq!(restored, positions);
+    }
+}
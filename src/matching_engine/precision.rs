@@ -0,0 +1,122 @@
+//! Per-market rounding of the amounts the engine derives by arithmetic:
+//! fees, average entry prices, realized P&L, funding payments and corporate
+//! action adjustments. Decimal division keeps up to 28 digits, so without
+//! rounding these pick up dust that accumulates across trades and can
+//! differ in the last digits between otherwise identical runs.
+//!
+//! ```toml
+//! precision = { price_decimals = 2, quantity_decimals = 4, rounding = "half_up" }
+//! ```
+//!
+//! Prices and amounts in the quote currency are rounded to `price_decimals`,
+//! quantities to `quantity_decimals`. Either left unset leaves those values
+//! as computed.
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    /// To the nearest, ties to even, so ties don't all lean one way.
+    #[default]
+    HalfEven,
+    /// To the nearest, ties away from zero.
+    HalfUp,
+    /// Towards zero.
+    Down,
+    /// Away from zero.
+    Up,
+    /// Towards negative infinity.
+    Floor,
+    /// Towards positive infinity.
+    Ceiling,
+}
+
+impl Rounding {
+    pub fn strategy(self) -> RoundingStrategy {
+        match self {
+            Rounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::Down => RoundingStrategy::ToZero,
+            Rounding::Up => RoundingStrategy::AwayFromZero,
+            Rounding::Floor => RoundingStrategy::ToNegativeInfinity,
+            Rounding::Ceiling => RoundingStrategy::ToPositiveInfinity,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Precision {
+    pub price_decimals: Option<u32>,
+    pub quantity_decimals: Option<u32>,
+    #[serde(default)]
+    pub rounding: Rounding,
+}
+
+impl Precision {
+    /// Rounds a price, or an amount in the quote currency.
+    pub fn price(&self, value: Decimal) -> Decimal {
+        self.round(value, self.price_decimals)
+    }
+
+    pub fn quantity(&self, value: Decimal) -> Decimal {
+        self.round(value, self.quantity_decimals)
+    }
+
+    fn round(&self, value: Decimal, decimals: Option<u32>) -> Decimal {
+        match decimals {
+            Some(decimals) => value.round_dp_with_strategy(decimals, self.rounding.strategy()),
+            None => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_rounding_modes() {
+        let precision = |rounding| Precision {
+            price_decimals: Some(2),
+            quantity_decimals: None,
+            rounding,
+        };
+        let round = |rounding, value| precision(rounding).price(value);
+        assert_eq!(round(Rounding::HalfEven, dec!(1.125)), dec!(1.12));
+        assert_eq!(round(Rounding::HalfEven, dec!(1.135)), dec!(1.14));
+        assert_eq!(round(Rounding::HalfUp, dec!(1.125)), dec!(1.13));
+        assert_eq!(round(Rounding::HalfUp, dec!(-1.125)), dec!(-1.13));
+        assert_eq!(round(Rounding::Down, dec!(-1.129)), dec!(-1.12));
+        assert_eq!(round(Rounding::Up, dec!(-1.121)), dec!(-1.13));
+        assert_eq!(round(Rounding::Floor, dec!(1.129)), dec!(1.12));
+        assert_eq!(round(Rounding::Floor, dec!(-1.121)), dec!(-1.13));
+        assert_eq!(round(Rounding::Ceiling, dec!(1.121)), dec!(1.13));
+        // Without a setting values pass through untouched.
+        let value = dec!(1) / dec!(3);
+        assert_eq!(precision(Rounding::Up).quantity(value), value);
+        assert_eq!(Precision::default().price(value), value);
+    }
+
+    #[test]
+    fn test_from_config() {
+        let precision: Precision =
+            toml::from_str("price_decimals = 2\nquantity_decimals = 4\nrounding = \"half_up\"\n")
+                .unwrap();
+        assert_eq!(precision.rounding, Rounding::HalfUp);
+        assert_eq!(precision.price(dec!(2.005)), dec!(2.01));
+        assert_eq!(precision.quantity(dec!(0.123456)), dec!(0.1235));
+        // Values already within the decimals are left as they are.
+        assert_eq!(precision.quantity(dec!(1.5)), dec!(1.5));
+
+        let precision: Precision = toml::from_str("quantity_decimals = 0").unwrap();
+        assert_eq!(precision.rounding, Rounding::HalfEven);
+        assert_eq!(precision.quantity(dec!(2.5)), dec!(2));
+        assert_eq!(precision.price(dec!(2.5)), dec!(2.5));
+        assert!(toml::from_str::<Precision>("decimals = 2").is_err());
+        assert!(toml::from_str::<Precision>("rounding = \"nearest\"").is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use crate::matching_engine::engine::TradingPair;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// The median of `prices`, averaging the middle two of an even count.
+pub fn median(prices: &mut [Decimal]) -> Option<Decimal> {
+    prices.sort_unstable();
+    let middle = prices.len() / 2;
+    match prices.len() {
+        0 => None,
+        len if len % 2 == 1 => Some(prices[middle]),
+        _ => Some((prices[middle - 1] + prices[middle]) / Decimal::TWO),
+    }
+}
+
+/// Index prices fed from outside and the mark prices derived from them.
+///
+/// A market's mark is the median of its book's mid, its last trade and its
+/// index, of those it has, so one stale or thin source can't move it far.
+/// Unrealized P&L, exposure limits and funding value positions at the mark.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pricing {
+    index_prices: HashMap<TradingPair, Decimal>,
+    mark_prices: HashMap<TradingPair, Decimal>,
+}
+
+impl Pricing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn index_price(&self, pair: &TradingPair) -> Option<Decimal> {
+        self.index_prices.get(pair).copied()
+    }
+
+    /// Sets the pair's index price; call `update` to move the mark with it.
+    pub fn set_index_price(&mut self, pair: &TradingPair, price: Decimal) {
+        self.index_prices.insert(pair.clone(), price);
+    }
+
+    /// Rewrites the pair's index and mark prices with `adjust`, e.g. for a
+    /// split.
+    pub fn adjust(&mut self, pair: &TradingPair, adjust: impl Fn(Decimal) -> Decimal) {
+        for prices in [&mut self.index_prices, &mut self.mark_prices] {
+            if let Some(price) = prices.get_mut(pair) {
+                *price = adjust(*price);
+            }
+        }
+    }
+
+    pub fn mark_price(&self, pair: &TradingPair) -> Option<Decimal> {
+        self.mark_prices.get(pair).copied()
+    }
+
+    /// Recomputes the pair's mark from the book's current `mid` and `last`
+    /// trade price. Returns the new mark, or None if the pair has no price
+    /// at all yet.
+    pub fn update(
+        &mut self,
+        pair: &TradingPair,
+        mid: Option<Decimal>,
+        last: Option<Decimal>,
+    ) -> Option<Decimal> {
+        let mut prices: Vec<Decimal> = [mid, last, self.index_price(pair)]
+            .into_iter()
+            .flatten()
+            .collect();
+        let mark = median(&mut prices)?;
+        self.mark_prices.insert(pair.clone(), mark);
+        Some(mark)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_mark_is_median_of_sources() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut pricing = Pricing::new();
+        assert_eq!(pricing.update(&pair, None, None), None);
+        assert_eq!(
+            pricing.update(&pair, Some(dec!(100)), None),
+            Some(dec!(100))
+        );
+
+        // A last trade far from the mid and index is ignored.
+        pricing.set_index_price(&pair, dec!(101));
+        assert_eq!(
+            pricing.update(&pair, Some(dec!(100)), Some(dec!(150))),
+            Some(dec!(101))
+        );
+        assert_eq!(
+            pricing.update(&pair, None, Some(dec!(104))),
+            Some(dec!(102.5))
+        );
+        assert_eq!(pricing.mark_price(&pair), Some(dec!(102.5)));
+    }
+
+    #[test]
+    fn test_median_and_adjust() {
+        assert_eq!(median(&mut []), None);
+        assert_eq!(median(&mut [dec!(3), dec!(1), dec!(2)]), Some(dec!(2)));
+        assert_eq!(
+            median(&mut [dec!(4), dec!(1), dec!(3), dec!(2)]),
+            Some(dec!(2.5))
+        );
+
+        let pair = TradingPair::new("ACME".to_string(), "USD".to_string());
+        let other = TradingPair::new("BTC".to_string(), "USD".to_string());
+        let mut pricing = Pricing::new();
+        pricing.set_index_price(&pair, dec!(200));
+        pricing.set_index_price(&other, dec!(50));
+        // A mark is only kept once `update` has run for the pair.
+        assert_eq!(pricing.mark_price(&pair), None);
+        pricing.update(&pair, Some(dec!(202)), None);
+
+        pricing.adjust(&pair, |price| price / dec!(2));
+        assert_eq!(pricing.index_price(&pair), Some(dec!(100)));
+        assert_eq!(pricing.mark_price(&pair), Some(dec!(100.5)));
+        assert_eq!(pricing.index_price(&other), Some(dec!(50)));
+    }
+}
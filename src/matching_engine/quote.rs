@@ -0,0 +1,160 @@
+//! Two-sided quotes: a market maker's bid and ask for one market, sent and
+//! replaced as one.
+
+use crate::limit_order_book::order::{Fill, Order};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuoteSide {
+    pub price: Decimal,
+    pub size: Decimal,
+}
+
+/// Replaces everything `client` quoted before in the market. A side left
+/// out is pulled rather than kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quote {
+    pub client: String,
+    pub bid: Option<QuoteSide>,
+    pub ask: Option<QuoteSide>,
+    pub time: DateTime<Utc>,
+}
+
+impl Quote {
+    /// Checks the quote on its own, before anything is replaced.
+    pub fn validate(&self) -> Result<(), String> {
+        for side in self.bid.iter().chain(&self.ask) {
+            if side.price <= Decimal::ZERO || side.size <= Decimal::ZERO {
+                return Err(format!(
+                    "Invalid quote side: {} at {}",
+                    side.size, side.price
+                ));
+            }
+        }
+        if let (Some(bid), Some(ask)) = (self.bid, self.ask) {
+            if bid.price >= ask.price {
+                return Err(format!(
+                    "Quote bid {} is not below its ask {}",
+                    bid.price, ask.price
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a quote did. A side the engine turned down is left empty, with the
+/// reason in place of its order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuoteReport {
+    /// The previous quote's orders that were still resting.
+    pub cancelled: Vec<Order>,
+    pub bid: Option<Result<(Order, Vec<Fill>), String>>,
+    pub ask: Option<Result<(Order, Vec<Fill>), String>>,
+}
+
+/// Exchange IDs of a client's current quote in one market.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct QuoteIds {
+    pub bid: Option<u64>,
+    pub ask: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::MarketConfig,
+        limit_order_book::order::OrderType,
+        matching_engine::engine::{MatchingEngine, TradingPair},
+    };
+    use rust_decimal_macros::dec;
+
+    fn quote(bid: Option<(Decimal, Decimal)>, ask: Option<(Decimal, Decimal)>) -> Quote {
+        let side = |(price, size)| QuoteSide { price, size };
+        Quote {
+            client: "mm".to_string(),
+            bid: bid.map(side),
+            ask: ask.map(side),
+            time: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(quote(Some((dec!(99), dec!(1))), Some((dec!(101), dec!(2))))
+            .validate()
+            .is_ok());
+        // A one-sided quote, or none at all to pull both sides.
+        assert!(quote(None, Some((dec!(101), dec!(2)))).validate().is_ok());
+        assert!(quote(None, None).validate().is_ok());
+
+        assert_eq!(
+            quote(Some((dec!(99), dec!(0))), None).validate(),
+            Err("Invalid quote side: 0 at 99".to_string())
+        );
+        assert_eq!(
+            quote(None, Some((dec!(-1), dec!(1)))).validate(),
+            Err("Invalid quote side: 1 at -1".to_string())
+        );
+        assert_eq!(
+            quote(Some((dec!(101), dec!(1))), Some((dec!(101), dec!(1)))).validate(),
+            Err("Quote bid 101 is not below its ask 101".to_string())
+        );
+    }
+
+    #[test]
+    fn test_filled_and_rejected_sides() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        let two_sided = quote(Some((dec!(100), dec!(1))), Some((dec!(102), dec!(1))));
+        assert_eq!(
+            engine.place_quote(pair.clone(), two_sided.clone()),
+            Err("No orderbook for trading pair: BTC/USDT".to_string())
+        );
+        engine.add_market(MarketConfig {
+            tick_size: Some(dec!(1)),
+            ..MarketConfig::new(pair.clone())
+        });
+        let now = Utc::now();
+        let ask = Order::new(
+            pair.to_string(),
+            engine.next_exchange_id(),
+            OrderType::Ask,
+            dec!(1),
+            dec!(100),
+            now,
+            now,
+        );
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+
+        // The bid trades away at once, so only the ask is left quoted.
+        let report = engine.place_quote(pair.clone(), two_sided).unwrap();
+        let (bid, fills) = report.bid.unwrap().unwrap();
+        assert!(!bid.is_active());
+        assert_eq!(fills.len(), 1);
+        let (bid, ask) = engine.quote(&pair, "mm");
+        assert!(bid.is_none());
+        assert_eq!(ask.unwrap().limit_price, dec!(102));
+
+        // A side the market turns down is reported without failing the
+        // other one.
+        let report = engine
+            .place_quote(
+                pair.clone(),
+                quote(Some((dec!(99.5), dec!(1))), Some((dec!(103), dec!(1)))),
+            )
+            .unwrap();
+        assert_eq!(report.cancelled.len(), 1);
+        assert_eq!(
+            report.bid,
+            Some(Err(
+                "Price 99.5 is not a multiple of tick size 1".to_string()
+            ))
+        );
+        assert!(report.ask.unwrap().is_ok());
+        let (bid, ask) = engine.quote(&pair, "mm");
+        assert_eq!((bid, ask.unwrap().limit_price), (None, dec!(103)));
+    }
+}
@@ -0,0 +1,146 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Every rate-limit rejection starts with this, so front ends can tell
+/// throttling apart from other rejections.
+pub const RATE_LIMITED: &str = "Rate limit exceeded";
+
+/// Orders each client may send: a sustained rate plus a burst allowance.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub orders_per_second: Decimal,
+    /// Orders that can be sent back to back after a quiet spell.
+    pub burst: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Bucket {
+    tokens: Decimal,
+    updated: DateTime<Utc>,
+}
+
+/// A token bucket per client. Buckets refill on the order's own timestamp,
+/// so a backtest is throttled exactly as the live engine would be.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: HashMap<String, Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub fn limit(&self) -> &RateLimit {
+        &self.limit
+    }
+
+    /// Takes a token for `client` at `now`, or rejects the order.
+    pub fn acquire(&mut self, client: &str, now: DateTime<Utc>) -> Result<(), String> {
+        self.check(client, now, 1)?;
+        let tokens = self.tokens(client, now);
+        let bucket = self.buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens,
+            updated: now,
+        });
+        bucket.tokens = tokens - Decimal::ONE;
+        bucket.updated = bucket.updated.max(now);
+        Ok(())
+    }
+
+    /// Rejects unless `client` could send `orders` orders at `now`. Takes no
+    /// tokens.
+    pub fn check(&self, client: &str, now: DateTime<Utc>, orders: usize) -> Result<(), String> {
+        if self.tokens(client, now) < Decimal::from(orders) {
+            return Err(format!(
+                "{} for {:?}: {} orders per second",
+                RATE_LIMITED, client, self.limit.orders_per_second
+            ));
+        }
+        Ok(())
+    }
+
+    /// The tokens in `client`'s bucket once refilled up to `now`.
+    fn tokens(&self, client: &str, now: DateTime<Utc>) -> Decimal {
+        let Some(bucket) = self.buckets.get(client) else {
+            return self.limit.burst;
+        };
+        // Timestamps that go backwards refill nothing.
+        if now <= bucket.updated {
+            return bucket.tokens;
+        }
+        let elapsed = (now - bucket.updated)
+            .num_microseconds()
+            .map_or(Decimal::MAX, |micros| Decimal::new(micros, 6));
+        let refill = elapsed
+            .checked_mul(self.limit.orders_per_second)
+            .unwrap_or(Decimal::MAX);
+        bucket.tokens.saturating_add(refill).min(self.limit.burst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_token_bucket() {
+        let mut limiter = RateLimiter::new(RateLimit {
+            orders_per_second: dec!(2),
+            burst: dec!(3),
+        });
+        let start = Utc::now();
+
+        for _ in 0..3 {
+            limiter.acquire("alice", start).unwrap();
+        }
+        let error = limiter.acquire("alice", start).unwrap_err();
+        assert!(error.starts_with(RATE_LIMITED));
+        limiter.acquire("bob", start).unwrap();
+
+        // Half a second buys one more order at two per second.
+        let later = start + Duration::milliseconds(500);
+        limiter.acquire("alice", later).unwrap();
+        assert!(limiter.acquire("alice", later).is_err());
+        assert!(limiter.acquire("alice", start).is_err());
+
+        // A long quiet spell refills only up to the burst.
+        let much_later = later + Duration::hours(1);
+        for _ in 0..3 {
+            limiter.acquire("alice", much_later).unwrap();
+        }
+        assert!(limiter.acquire("alice", much_later).is_err());
+    }
+
+    #[test]
+    fn test_check_takes_no_tokens() {
+        let mut limiter = RateLimiter::new(RateLimit {
+            orders_per_second: dec!(1),
+            burst: dec!(2),
+        });
+        let start = Utc::now();
+
+        limiter.check("alice", start, 2).unwrap();
+        assert!(limiter.check("alice", start, 3).is_err());
+        limiter.acquire("alice", start).unwrap();
+        limiter.check("alice", start, 1).unwrap();
+        let error = limiter.check("alice", start, 2).unwrap_err();
+        assert!(error.starts_with(RATE_LIMITED));
+
+        // Checking ahead of time doesn't refill the bucket early.
+        limiter
+            .check("alice", start + Duration::seconds(1), 2)
+            .unwrap();
+        limiter.acquire("alice", start).unwrap();
+        assert!(limiter.acquire("alice", start).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{
+    limit_order_book::order::LimitOrderBook,
+    matching_engine::{engine::TradingPair, midpoint::mid_price},
+};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// Conversion prices between currencies, so amounts in different quote
+/// currencies can be added up in one reporting currency.
+///
+/// Each rate is the price of one unit of a base currency in a quote
+/// currency. Rates come from the engine's own books, at their mid, or from
+/// outside; whichever was set last for a pair is used.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rates {
+    prices: HashMap<(String, String), Decimal>,
+}
+
+impl Rates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the price of one `base` in `quote`. Non-positive prices are
+    /// ignored.
+    pub fn set(&mut self, base: &str, quote: &str, price: Decimal) {
+        if price > Decimal::ZERO && base != quote {
+            self.prices
+                .insert((base.to_string(), quote.to_string()), price);
+        }
+    }
+
+    /// Takes the pair's rate from the mid of `book`, if it has both sides.
+    pub fn update_from_book(&mut self, pair: &TradingPair, book: &LimitOrderBook) {
+        if let Some(mid) = mid_price(book) {
+            self.set(pair.base(), pair.quote(), mid);
+        }
+    }
+
+    /// The price of one `from` in `to`, directly, through the inverse rate,
+    /// or crossed through one other currency.
+    pub fn rate(&self, from: &str, to: &str) -> Option<Decimal> {
+        if from == to {
+            return Some(Decimal::ONE);
+        }
+        if let Some(rate) = self.direct(from, to) {
+            return Some(rate);
+        }
+        self.currencies()
+            .filter(|via| *via != from && *via != to)
+            .find_map(|via| Some(self.direct(from, via)? * self.direct(via, to)?))
+    }
+
+    /// `amount` of `from` expressed in `to`.
+    pub fn convert(&self, amount: Decimal, from: &str, to: &str) -> Option<Decimal> {
+        self.rate(from, to).map(|rate| amount * rate)
+    }
+
+    fn direct(&self, from: &str, to: &str) -> Option<Decimal> {
+        let key = |base: &str, quote: &str| (base.to_string(), quote.to_string());
+        match self.prices.get(&key(from, to)) {
+            Some(price) => Some(*price),
+            None => self
+                .prices
+                .get(&key(to, from))
+                .map(|price| Decimal::ONE / price),
+        }
+    }
+
+    fn currencies(&self) -> impl Iterator<Item = &str> {
+        let mut currencies: Vec<&str> = self
+            .prices
+            .keys()
+            .flat_map(|(base, quote)| [base.as_str(), quote.as_str()])
+            .collect();
+        currencies.sort_unstable();
+        currencies.dedup();
+        currencies.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::{Order, OrderType};
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_rates_convert_directly_inverted_and_crossed() {
+        let mut rates = Rates::new();
+        rates.set("EUR", "USD", dec!(1.25));
+        rates.set("USDT", "USD", dec!(1));
+        assert_eq!(rates.convert(dec!(10), "EUR", "USD"), Some(dec!(12.5)));
+        assert_eq!(rates.convert(dec!(10), "USD", "EUR"), Some(dec!(8)));
+        assert_eq!(rates.convert(dec!(10), "EUR", "USDT"), Some(dec!(12.5)));
+        assert_eq!(rates.rate("USD", "USD"), Some(dec!(1)));
+        assert_eq!(rates.rate("EUR", "JPY"), None);
+
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut book = LimitOrderBook::new();
+        for (exchange_id, order_type, price) in [
+            (1, OrderType::Bid, dec!(99)),
+            (2, OrderType::Ask, dec!(101)),
+        ] {
+            book.place_order(Order::new(
+                pair.to_string(),
+                exchange_id,
+                order_type,
+                dec!(1),
+                price,
+                Utc::now(),
+                Utc::now(),
+            ));
+        }
+        rates.update_from_book(&pair, &book);
+        assert_eq!(rates.convert(dec!(2), "BTC", "USD"), Some(dec!(200)));
+    }
+
+    #[test]
+    fn test_ignored_and_replaced_rates() {
+        let mut rates = Rates::new();
+        rates.set("EUR", "USD", dec!(0));
+        rates.set("EUR", "USD", dec!(-1));
+        rates.set("USD", "USD", dec!(2));
+        assert_eq!(rates, Rates::new());
+
+        // A direct rate is used before inverting the opposite one.
+        rates.set("EUR", "USD", dec!(1.25));
+        rates.set("USD", "EUR", dec!(0.5));
+        assert_eq!(rates.rate("USD", "EUR"), Some(dec!(0.5)));
+        assert_eq!(rates.rate("EUR", "USD"), Some(dec!(1.25)));
+
+        // Only one intermediate currency is tried.
+        rates.set("GBP", "EUR", dec!(1.2));
+        rates.set("JPY", "GBP", dec!(0.005));
+        assert!(rates.rate("GBP", "USD").is_some());
+        assert_eq!(rates.rate("JPY", "USD"), None);
+
+        // A one-sided book has no mid and leaves the rate alone.
+        let pair = TradingPair::new("BTC".to_string(), "USD".to_string());
+        let mut book = LimitOrderBook::new();
+        book.place_order(Order::new(
+            pair.to_string(),
+            1,
+            OrderType::Bid,
+            dec!(1),
+            dec!(99),
+            Utc::now(),
+            Utc::now(),
+        ));
+        rates.set("BTC", "USD", dec!(100));
+        rates.update_from_book(&pair, &book);
+        assert_eq!(rates.rate("BTC", "USD"), Some(dec!(100)));
+    }
+}
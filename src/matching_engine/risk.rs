@@ -0,0 +1,134 @@
+use crate::matching_engine::engine::TradingPair;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::{BTreeSet, HashMap};
+
+/// A client's standing when a new order arrives, for the limits that
+/// depend on more than the order itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Exposure {
+    /// Resting orders in the order's market.
+    pub open_orders: usize,
+    /// Net position in the order's market; positive when long.
+    pub position: Decimal,
+    /// Resting quantity on the order's side of its market.
+    pub resting: Decimal,
+    /// Resting order and position notional across all markets, in the
+    /// order's quote currency.
+    pub notional: Decimal,
+    /// Quote currencies with no rate to the order's, whose notional is
+    /// missing from `notional`.
+    pub unconverted: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RiskEvent {
+    /// A client or account limit rejected an order.
+    OrderBlocked {
+        client: String,
+        pair: TradingPair,
+        exchange_id: u64,
+        reason: String,
+        time: DateTime<Utc>,
+    },
+}
+
+/// Decides whether a short sale can be covered by borrowed stock. Called once
+/// per short order before it reaches the book.
+pub trait BorrowCheck {
+    /// Returns true if `quantity` of the pair's base asset can be borrowed
+    /// for `client`. Implementations may reserve the borrow as a side effect.
+    fn locate(&mut self, client: &str, pair: &TradingPair, quantity: Decimal) -> bool;
+
+    /// Returns true if `locate` would succeed, without reserving anything.
+    /// Baskets ask before placing any leg. Assumes it would by default.
+    fn can_locate(&self, _client: &str, _pair: &TradingPair, _quantity: Decimal) -> bool {
+        true
+    }
+}
+
+/// A fixed pool of borrowable quantity per base asset, used up first come
+/// first served.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Locates {
+    available: HashMap<String, Decimal>,
+}
+
+impl Locates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_available(mut self, asset: impl Into<String>, quantity: Decimal) -> Self {
+        self.available.insert(asset.into(), quantity);
+        self
+    }
+
+    pub fn available(&self, asset: &str) -> Decimal {
+        self.available.get(asset).copied().unwrap_or_default()
+    }
+}
+
+impl BorrowCheck for Locates {
+    fn locate(&mut self, _client: &str, pair: &TradingPair, quantity: Decimal) -> bool {
+        match self.available.get_mut(pair.base()) {
+            Some(available) if *available >= quantity => {
+                *available -= quantity;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn can_locate(&self, _client: &str, pair: &TradingPair, quantity: Decimal) -> bool {
+        self.available(pair.base()) >= quantity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_locates_are_used_up() {
+        let pair = TradingPair::new("AAPL".to_string(), "USD".to_string());
+        let mut locates = Locates::new().with_available("AAPL", dec!(100));
+
+        assert!(locates.can_locate("alice", &pair, dec!(60)));
+        assert!(locates.locate("alice", &pair, dec!(60)));
+        assert!(!locates.can_locate("bob", &pair, dec!(60)));
+        assert!(!locates.locate("bob", &pair, dec!(60)));
+        assert!(locates.locate("bob", &pair, dec!(40)));
+        assert_eq!(locates.available("AAPL"), dec!(0));
+
+        let msft = TradingPair::new("MSFT".to_string(), "USD".to_string());
+        assert!(!locates.locate("alice", &msft, dec!(1)));
+    }
+
+    /// Grants every other locate.
+    struct Alternating(bool);
+
+    impl BorrowCheck for Alternating {
+        fn locate(&mut self, _client: &str, _pair: &TradingPair, _quantity: Decimal) -> bool {
+            self.0 = !self.0;
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_can_locate_defaults_to_true() {
+        let pair = TradingPair::new("AAPL".to_string(), "USD".to_string());
+        let mut check = Alternating(false);
+        assert!(check.can_locate("alice", &pair, dec!(1)));
+        assert!(check.locate("alice", &pair, dec!(1)));
+        // Asking again doesn't change what the next locate does.
+        assert!(check.can_locate("alice", &pair, dec!(1)));
+        assert!(!check.locate("alice", &pair, dec!(1)));
+
+        let locates = Locates::new().with_available("AAPL", dec!(5));
+        assert!(locates.can_locate("alice", &pair, dec!(5)));
+        assert!(locates.can_locate("alice", &pair, dec!(5)));
+        assert_eq!(locates.available("AAPL"), dec!(5));
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{
+    export::{self, CsvRecord},
+    matching_engine::{accounts::AccountId, engine::TradingPair, portfolio::Holding},
+};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use rust_decimal::Decimal;
+use std::io::{self, Write};
+
+/// When the end-of-day settlement runs: once a day, at the first check at
+/// or after `time` (UTC).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettlementSchedule {
+    pub time: NaiveTime,
+    last_settled: Option<NaiveDate>,
+}
+
+impl SettlementSchedule {
+    pub fn new(time: NaiveTime) -> Self {
+        Self {
+            time,
+            last_settled: None,
+        }
+    }
+
+    /// Whether the day's settlement time has passed by `now` without a
+    /// previous call having returned true for that day.
+    pub fn is_due(&mut self, now: DateTime<Utc>) -> bool {
+        let today = now.date_naive();
+        if now.time() < self.time || self.last_settled == Some(today) {
+            return false;
+        }
+        self.last_settled = Some(today);
+        true
+    }
+}
+
+/// One account's day in one market, in the market's quote currency.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SettlementRecord {
+    pub account: String,
+    pub pair: String,
+    pub currency: String,
+    pub bought: Decimal,
+    pub sold: Decimal,
+    /// The position carried into the next day.
+    pub position: Decimal,
+    pub settlement_price: Decimal,
+    pub realized_pnl: Decimal,
+    /// The open position's P&L from its carried price to the settlement
+    /// price.
+    pub variation_margin: Decimal,
+    pub fees: Decimal,
+    pub funding: Decimal,
+}
+
+impl SettlementRecord {
+    pub fn new(
+        account: &AccountId,
+        pair: &TradingPair,
+        holding: &Holding,
+        settlement_price: Decimal,
+        multiplier: Decimal,
+    ) -> Self {
+        Self {
+            account: account.to_string(),
+            pair: pair.to_string(),
+            currency: pair.quote().to_string(),
+            bought: holding.bought,
+            sold: holding.sold,
+            position: holding.position,
+            settlement_price,
+            realized_pnl: holding.realized_pnl,
+            variation_margin: holding.unrealized_pnl(settlement_price, multiplier),
+            fees: holding.fees,
+            funding: holding.funding,
+        }
+    }
+
+    /// Quantity bought less quantity sold over the day.
+    pub fn net_quantity(&self) -> Decimal {
+        self.bought - self.sold
+    }
+
+    /// What the day moves into the account's balance: realized and
+    /// variation P&L, less fees and funding paid.
+    pub fn amount(&self) -> Decimal {
+        self.realized_pnl + self.variation_margin - self.fees - self.funding
+    }
+}
+
+impl CsvRecord for SettlementRecord {
+    const HEADER: &'static [&'static str] = &[
+        "account",
+        "pair",
+        "currency",
+        "bought",
+        "sold",
+        "net_quantity",
+        "position",
+        "settlement_price",
+        "realized_pnl",
+        "variation_margin",
+        "fees",
+        "funding",
+        "amount",
+    ];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.account.clone(),
+            self.pair.clone(),
+            self.currency.clone(),
+            self.bought.to_string(),
+            self.sold.to_string(),
+            self.net_quantity().to_string(),
+            self.position.to_string(),
+            self.settlement_price.to_string(),
+            self.realized_pnl.to_string(),
+            self.variation_margin.to_string(),
+            self.fees.to_string(),
+            self.funding.to_string(),
+            self.amount().to_string(),
+        ]
+    }
+}
+
+/// The outcome of one end-of-day settlement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettlementReport {
+    pub time: DateTime<Utc>,
+    /// The price each market was settled at, by pair.
+    pub prices: Vec<(TradingPair, Decimal)>,
+    /// One row per account and market, by pair and then account.
+    pub records: Vec<SettlementRecord>,
+}
+
+impl SettlementReport {
+    /// What the settlement moved into `account`'s balance of `currency`.
+    pub fn amount(&self, account: &str, currency: &str) -> Decimal {
+        self.records
+            .iter()
+            .filter(|record| record.account == account && record.currency == currency)
+            .map(SettlementRecord::amount)
+            .sum()
+    }
+
+    pub fn write_csv<W: Write>(&self, writer: W) -> io::Result<()> {
+        export::write_csv(writer, &self.records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_schedule_and_record() {
+        let mut schedule = SettlementSchedule::new(NaiveTime::from_hms_opt(17, 0, 0).unwrap());
+        let morning = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+        assert!(!schedule.is_due(morning));
+        assert!(schedule.is_due(morning + Duration::hours(8)));
+        assert!(!schedule.is_due(morning + Duration::hours(9)));
+        assert!(!schedule.is_due(morning + Duration::days(1)));
+        assert!(schedule.is_due(morning + Duration::days(1) + Duration::hours(10)));
+
+        let holding = Holding {
+            position: dec!(2),
+            average_price: dec!(100),
+            realized_pnl: dec!(5),
+            fees: dec!(1),
+            funding: dec!(0.5),
+            bought: dec!(3),
+            sold: dec!(1),
+        };
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let record = SettlementRecord::new(&"alice".into(), &pair, &holding, dec!(104), dec!(1));
+        assert_eq!(record.variation_margin, dec!(8));
+        assert_eq!(record.net_quantity(), dec!(2));
+        assert_eq!(record.amount(), dec!(11.5));
+
+        let mut out = Vec::new();
+        export::write_csv(&mut out, &[record]).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        assert!(csv.ends_with("alice,BTC/USDT,USDT,3,1,2,2,104,5,8,1,0.5,11.5\n"));
+    }
+
+    #[test]
+    fn test_report_amounts_by_currency() {
+        let btc = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let future = TradingPair::new("BTC-MAR25".to_string(), "USD".to_string());
+        let short = Holding {
+            position: dec!(-1),
+            average_price: dec!(100),
+            fees: dec!(2),
+            sold: dec!(1),
+            ..Holding::default()
+        };
+        let report = SettlementReport {
+            time: Utc::now(),
+            prices: vec![(btc.clone(), dec!(90)), (future.clone(), dec!(110))],
+            records: vec![
+                SettlementRecord::new(&"alice".into(), &btc, &short, dec!(90), dec!(1)),
+                SettlementRecord::new(&"bob".into(), &btc, &short, dec!(90), dec!(1)),
+                // Ten units per contract.
+                SettlementRecord::new(&"alice".into(), &future, &short, dec!(110), dec!(10)),
+            ],
+        };
+        assert_eq!(report.records[0].net_quantity(), dec!(-1));
+        assert_eq!(report.amount("alice", "USDT"), dec!(8));
+        assert_eq!(report.amount("alice", "USD"), dec!(-102));
+        assert_eq!(report.amount("carol", "USDT"), dec!(0));
+
+        let mut out = Vec::new();
+        report.write_csv(&mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.starts_with(&SettlementRecord::HEADER.join(",")));
+    }
+}
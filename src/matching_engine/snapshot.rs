@@ -0,0 +1,151 @@
+//! Point-in-time copy of the engine's resting orders and what its trades
+//! have built up.
+
+use crate::{
+    limit_order_book::order::Order,
+    matching_engine::{accounts::AccountId, portfolio::Holding},
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Resting orders per market, the exchange ID and audit sequences, and the
+/// positions, holdings and fees trading has left behind. Market
+/// configuration comes from the engine being restored into, and marks and
+/// rates are rebuilt from the restored books. Account balances, price band
+/// and breaker windows, halts, funding, the insurance fund, pegged and
+/// midpoint orders, brackets and kill switches are not captured; see
+/// `MatchingEngine::uncaptured`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EngineSnapshot {
+    pub next_exchange_id: u64,
+    /// Absent from older snapshots, which restore without it.
+    #[serde(default)]
+    pub audit_sequence: u64,
+    /// Sorted by pair.
+    pub markets: Vec<MarketSnapshot>,
+    /// Sorted by account and pair; absent from older snapshots, like the
+    /// holdings and fees.
+    #[serde(default)]
+    pub positions: Vec<PositionSnapshot>,
+    /// Sorted by account, strategy and pair.
+    #[serde(default)]
+    pub holdings: Vec<HoldingSnapshot>,
+    /// Sorted by account.
+    #[serde(default)]
+    pub fees: Vec<FeeSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarketSnapshot {
+    pub pair: String,
+    /// In `LimitOrderBook::resting_orders` order.
+    pub orders: Vec<Order>,
+    #[serde(default)]
+    pub last_trade_price: Option<Decimal>,
+    #[serde(default)]
+    pub previous_close: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PositionSnapshot {
+    pub account: AccountId,
+    pub pair: String,
+    pub position: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HoldingSnapshot {
+    pub account: AccountId,
+    /// Set for a holding booked to one of the account's strategies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<String>,
+    pub pair: String,
+    pub holding: Holding,
+}
+
+/// One account's fees, and the trades its family's fee tier is computed
+/// from when it is a top-level account.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeSnapshot {
+    pub account: AccountId,
+    pub paid: Decimal,
+    /// Time and notional, oldest first.
+    #[serde(default)]
+    pub trades: Vec<(DateTime<Utc>, Decimal)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_older_snapshots_deserialize() {
+        let json = r#"{"next_exchange_id":7,"markets":[{"pair":"BTC/USDT","orders":[]}]}"#;
+        let snapshot: EngineSnapshot = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            snapshot,
+            EngineSnapshot {
+                next_exchange_id: 7,
+                markets: vec![MarketSnapshot {
+                    pair: "BTC/USDT".to_string(),
+                    orders: vec![],
+                    last_trade_price: None,
+                    previous_close: None,
+                }],
+                ..EngineSnapshot::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let snapshot = EngineSnapshot {
+            next_exchange_id: 3,
+            audit_sequence: 9,
+            markets: vec![MarketSnapshot {
+                pair: "BTC/USDT".to_string(),
+                orders: vec![],
+                last_trade_price: Some(dec!(100)),
+                previous_close: Some(dec!(98)),
+            }],
+            positions: vec![PositionSnapshot {
+                account: AccountId::new("desk"),
+                pair: "BTC/USDT".to_string(),
+                position: dec!(-2),
+            }],
+            holdings: vec![
+                HoldingSnapshot {
+                    account: AccountId::new("desk"),
+                    strategy: None,
+                    pair: "BTC/USDT".to_string(),
+                    holding: Holding {
+                        position: dec!(-2),
+                        average_price: dec!(100),
+                        ..Holding::default()
+                    },
+                },
+                HoldingSnapshot {
+                    account: AccountId::new("desk"),
+                    strategy: Some("arb".to_string()),
+                    pair: "BTC/USDT".to_string(),
+                    holding: Holding::default(),
+                },
+            ],
+            fees: vec![FeeSnapshot {
+                account: AccountId::new("desk"),
+                paid: dec!(0.4),
+                trades: vec![(Utc::now(), dec!(200))],
+            }],
+        };
+        let json = serde_json::to_string(&snapshot).unwrap();
+        // A holding without a strategy leaves the field out.
+        assert_eq!(json.matches(r#""strategy""#).count(), 1);
+        assert!(json.contains(r#""account":"desk""#));
+        assert_eq!(
+            serde_json::from_str::<EngineSnapshot>(&json).unwrap(),
+            snapshot
+        );
+    }
+}
@@ -0,0 +1,419 @@
+use crate::{
+    limit_order_book::order::OrderType,
+    matching_engine::{
+        accounts::AccountId, drop_copy::DropCopy, engine::TradingPair, fees::Liquidity,
+    },
+};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use std::collections::{HashMap, HashSet};
+
+/// Thresholds for the surveillance heuristics. They flag activity worth a
+/// closer look; none of them proves intent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SurveillanceConfig {
+    /// Cancels per execution above which a client is flagged.
+    pub max_cancel_ratio: Decimal,
+    /// Cancels a client needs before its ratio is considered at all.
+    pub min_cancels: u64,
+    /// How soon after an execution a cancel on the other side counts as
+    /// layering.
+    pub layering_window: Duration,
+    /// Smallest cancelled quantity that can count as layering.
+    pub layering_min_quantity: Decimal,
+}
+
+impl Default for SurveillanceConfig {
+    fn default() -> Self {
+        Self {
+            max_cancel_ratio: Decimal::from(10),
+            min_cancels: 20,
+            layering_window: Duration::seconds(1),
+            layering_min_quantity: Decimal::from(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlertKind {
+    /// Both sides of a trade belong to the same account family.
+    SelfMatch {
+        maker_id: u64,
+        taker_id: u64,
+        counterparty: String,
+        quantity: Decimal,
+    },
+    /// The client cancels far more than it trades.
+    HighCancelRatio { cancels: u64, executions: u64 },
+    /// A large order was pulled right after the client traded on the
+    /// opposite side.
+    Layering {
+        exchange_id: u64,
+        side: OrderType,
+        cancelled_quantity: Decimal,
+        since_execution: Duration,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alert {
+    pub client: String,
+    pub pair: TradingPair,
+    pub kind: AlertKind,
+    pub time: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct Activity {
+    cancels: u64,
+    executions: u64,
+    /// Set while the client is over the cancel ratio, so it is flagged
+    /// once per breach rather than on every cancel.
+    flagged: bool,
+}
+
+/// Watches a drop-copy feed and records alerts for suspicious patterns.
+#[derive(Debug, Default)]
+pub struct Surveillance {
+    config: SurveillanceConfig,
+    activity: HashMap<String, Activity>,
+    last_executions: HashMap<(String, TradingPair, OrderType), DateTime<Utc>>,
+    /// The maker half of the trade being reported, until its taker half
+    /// arrives.
+    pending_maker: Option<(u64, String)>,
+    alerts: Vec<Alert>,
+}
+
+impl Surveillance {
+    pub fn new(config: SurveillanceConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Checks one report, stamped with the time it was `received`.
+    pub fn observe(&mut self, report: &DropCopy, received: DateTime<Utc>) {
+        match report {
+            DropCopy::Execution {
+                pair,
+                exchange_id,
+                client,
+                side,
+                liquidity,
+                quantity,
+                ..
+            } => {
+                self.activity.entry(client.clone()).or_default().executions += 1;
+                self.last_executions
+                    .insert((client.clone(), pair.clone(), *side), received);
+                self.check_cancel_ratio(client, pair, received);
+
+                // Every fill is reported maker first, then taker.
+                match liquidity {
+                    Liquidity::Maker => {
+                        self.pending_maker = Some((*exchange_id, client.clone()));
+                    }
+                    Liquidity::Taker => {
+                        let Some((maker_id, maker)) = self.pending_maker.take() else {
+                            return;
+                        };
+                        if AccountId::from(maker.as_str()).root()
+                            == AccountId::from(client.as_str()).root()
+                        {
+                            self.alerts.push(Alert {
+                                client: client.clone(),
+                                pair: pair.clone(),
+                                kind: AlertKind::SelfMatch {
+                                    maker_id,
+                                    taker_id: *exchange_id,
+                                    counterparty: maker,
+                                    quantity: *quantity,
+                                },
+                                time: received,
+                            });
+                        }
+                    }
+                }
+            }
+            DropCopy::Cancel {
+                pair,
+                exchange_id,
+                client,
+                side,
+                cancelled_quantity,
+                ..
+            } => {
+                self.activity.entry(client.clone()).or_default().cancels += 1;
+                self.check_cancel_ratio(client, pair, received);
+
+                if *cancelled_quantity < self.config.layering_min_quantity {
+                    return;
+                }
+                let opposite = match side {
+                    OrderType::Bid => OrderType::Ask,
+                    OrderType::Ask => OrderType::Bid,
+                };
+                let key = (client.clone(), pair.clone(), opposite);
+                let Some(&executed) = self.last_executions.get(&key) else {
+                    return;
+                };
+                let since_execution = received - executed;
+                if since_execution <= self.config.layering_window {
+                    self.alerts.push(Alert {
+                        client: client.clone(),
+                        pair: pair.clone(),
+                        kind: AlertKind::Layering {
+                            exchange_id: *exchange_id,
+                            side: *side,
+                            cancelled_quantity: *cancelled_quantity,
+                            since_execution,
+                        },
+                        time: received,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Takes the alerts raised since the last call.
+    pub fn drain_alerts(&mut self) -> Vec<Alert> {
+        std::mem::take(&mut self.alerts)
+    }
+
+    /// Clients that have been flagged for their cancel ratio and are still
+    /// over it.
+    pub fn flagged_clients(&self) -> HashSet<&str> {
+        self.activity
+            .iter()
+            .filter(|(_, activity)| activity.flagged)
+            .map(|(client, _)| client.as_str())
+            .collect()
+    }
+
+    fn check_cancel_ratio(&mut self, client: &str, pair: &TradingPair, time: DateTime<Utc>) {
+        let activity = self.activity.get_mut(client).unwrap();
+        let ratio = Decimal::from(activity.cancels) / Decimal::from(activity.executions.max(1));
+        let breached =
+            activity.cancels >= self.config.min_cancels && ratio > self.config.max_cancel_ratio;
+        if breached && !activity.flagged {
+            self.alerts.push(Alert {
+                client: client.to_string(),
+                pair: pair.clone(),
+                kind: AlertKind::HighCancelRatio {
+                    cancels: activity.cancels,
+                    executions: activity.executions,
+                },
+                time,
+            });
+        }
+        activity.flagged = breached;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{limit_order_book::order::Order, matching_engine::engine::MatchingEngine};
+    use rust_decimal_macros::dec;
+
+    fn order(
+        engine: &mut MatchingEngine,
+        client: &str,
+        order_type: OrderType,
+        shares: Decimal,
+        price: Decimal,
+        time: DateTime<Utc>,
+    ) -> Order {
+        Order::new(
+            "BTC/USDT".to_string(),
+            engine.next_exchange_id(),
+            order_type,
+            shares,
+            price,
+            time,
+            time,
+        )
+        .with_client(client)
+    }
+
+    #[test]
+    fn test_flags_self_matches_and_layering() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let drop_copy = engine.subscribe_drop_copy();
+        let mut surveillance = Surveillance::new(SurveillanceConfig::default());
+        let now = Utc::now();
+
+        let ask = order(
+            &mut engine,
+            "alice/a",
+            OrderType::Ask,
+            dec!(1),
+            dec!(101),
+            now,
+        );
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        let layer = order(&mut engine, "bob", OrderType::Bid, dec!(50), dec!(99), now);
+        let layer_id = layer.exchange_id;
+        engine.place_limit_order(pair.clone(), layer).unwrap();
+        let lift = order(
+            &mut engine,
+            "alice/b",
+            OrderType::Bid,
+            dec!(1),
+            dec!(101),
+            now,
+        );
+        engine.place_limit_order(pair.clone(), lift).unwrap();
+
+        let bid = order(
+            &mut engine,
+            "carol",
+            OrderType::Bid,
+            dec!(1),
+            dec!(100),
+            now,
+        );
+        engine.place_limit_order(pair.clone(), bid).unwrap();
+        let hit = order(&mut engine, "bob", OrderType::Ask, dec!(1), dec!(100), now);
+        engine.place_limit_order(pair.clone(), hit).unwrap();
+        for report in drop_copy.try_iter() {
+            surveillance.observe(&report, now);
+        }
+
+        engine.cancel_order(&pair, layer_id).unwrap();
+        let later = now + Duration::milliseconds(200);
+        for report in drop_copy.try_iter() {
+            surveillance.observe(&report, later);
+        }
+
+        let alerts = surveillance.drain_alerts();
+        assert_eq!(alerts.len(), 2, "{:?}", alerts);
+        assert!(matches!(
+            &alerts[0].kind,
+            AlertKind::SelfMatch { counterparty, .. } if counterparty == "alice/a"
+        ));
+        assert_eq!(alerts[1].client, "bob");
+        assert_eq!(
+            alerts[1].kind,
+            AlertKind::Layering {
+                exchange_id: layer_id,
+                side: OrderType::Bid,
+                cancelled_quantity: dec!(50),
+                since_execution: Duration::milliseconds(200),
+            }
+        );
+
+        // Pulling an order on the side it just traded is not layering.
+
+        let ask = order(
+            &mut engine,
+            "bob",
+            OrderType::Ask,
+            dec!(50),
+            dec!(110),
+            later,
+        );
+        let ask_id = ask.exchange_id;
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        engine.cancel_order(&pair, ask_id).unwrap();
+        for report in drop_copy.try_iter() {
+            surveillance.observe(&report, later);
+        }
+        assert!(surveillance.drain_alerts().is_empty());
+    }
+
+    #[test]
+    fn test_flags_high_cancel_ratio_once() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let drop_copy = engine.subscribe_drop_copy();
+        let mut surveillance = Surveillance::new(SurveillanceConfig {
+            max_cancel_ratio: dec!(2),
+            min_cancels: 3,
+            ..SurveillanceConfig::default()
+        });
+        let now = Utc::now();
+
+        for _ in 0..5 {
+            let bid = order(&mut engine, "dave", OrderType::Bid, dec!(1), dec!(90), now);
+            let bid_id = bid.exchange_id;
+            engine.place_limit_order(pair.clone(), bid).unwrap();
+            engine.cancel_order(&pair, bid_id).unwrap();
+        }
+        for report in drop_copy.try_iter() {
+            surveillance.observe(&report, now);
+        }
+
+        let alerts = surveillance.drain_alerts();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(
+            alerts[0].kind,
+            AlertKind::HighCancelRatio {
+                cancels: 3,
+                executions: 0
+            }
+        );
+        assert!(surveillance.flagged_clients().contains("dave"));
+    }
+
+    #[test]
+    fn test_thresholds_and_ratio_recovery() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let execution = |exchange_id, side, liquidity, time| DropCopy::Execution {
+            pair: pair.clone(),
+            exchange_id,
+            client: "erin".to_string(),
+            strategy: None,
+            side,
+            liquidity,
+            price: dec!(100),
+            quantity: dec!(1),
+            leaves_quantity: dec!(0),
+            fee: dec!(0),
+            time,
+        };
+        let cancel = |exchange_id, cancelled_quantity| DropCopy::Cancel {
+            pair: pair.clone(),
+            exchange_id,
+            client: "erin".to_string(),
+            strategy: None,
+            side: OrderType::Ask,
+            price: dec!(105),
+            cancelled_quantity,
+        };
+        let mut surveillance = Surveillance::new(SurveillanceConfig {
+            max_cancel_ratio: dec!(1),
+            min_cancels: 2,
+            ..SurveillanceConfig::default()
+        });
+        let now = Utc::now();
+
+        // A taker half without its maker half is not a self-match.
+        surveillance.observe(&execution(1, OrderType::Bid, Liquidity::Taker, now), now);
+        // Too small, then too late, to count as layering.
+        surveillance.observe(&cancel(2, dec!(5)), now);
+        surveillance.observe(&cancel(3, dec!(50)), now + Duration::seconds(2));
+        let alerts = surveillance.drain_alerts();
+        assert_eq!(alerts.len(), 1, "{:?}", alerts);
+        assert_eq!(
+            alerts[0].kind,
+            AlertKind::HighCancelRatio {
+                cancels: 2,
+                executions: 1
+            }
+        );
+
+        // Trading brings the ratio back under, and the next breach flags
+        // again.
+        surveillance.observe(&execution(4, OrderType::Bid, Liquidity::Taker, now), now);
+        assert!(surveillance.flagged_clients().is_empty());
+        surveillance.observe(&cancel(5, dec!(1)), now);
+        surveillance.observe(&cancel(6, dec!(1)), now);
+        assert_eq!(surveillance.drain_alerts().len(), 1);
+        assert_eq!(surveillance.flagged_clients(), HashSet::from(["erin"]));
+    }
+}
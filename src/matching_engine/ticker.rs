@@ -0,0 +1,144 @@
+//! Rolling 24-hour statistics per market, as exchanges publish them on
+//! their ticker endpoints.
+
+use crate::limit_order_book::arithmetic::saturating_sum;
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// The window every figure but `last` covers.
+pub const TICKER_WINDOW_HOURS: i64 = 24;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TickerStats {
+    pub pair: String,
+    /// Base quantity traded.
+    pub volume: Decimal,
+    /// Quote value traded, i.e. price times quantity summed over trades.
+    pub quote_volume: Decimal,
+    pub high: Option<Decimal>,
+    pub low: Option<Decimal>,
+    /// The last trade's price, however long ago it was.
+    pub last: Option<Decimal>,
+    /// From the window's first trade to its last, in percent.
+    pub price_change_pct: Option<Decimal>,
+    pub trades: usize,
+}
+
+/// The trades of one market inside the window.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ticker {
+    /// Time, price and quantity, oldest first.
+    trades: VecDeque<(DateTime<Utc>, Decimal, Decimal)>,
+    last: Option<Decimal>,
+}
+
+impl Ticker {
+    pub fn record_trade(&mut self, time: DateTime<Utc>, price: Decimal, quantity: Decimal) {
+        self.trades.push_back((time, price, quantity));
+        self.last = Some(price);
+        self.expire(time);
+    }
+
+    /// The statistics over the window ending at `now`.
+    pub fn stats(&self, pair: String, now: DateTime<Utc>) -> TickerStats {
+        let start = now - Duration::hours(TICKER_WINDOW_HOURS);
+        let trades: Vec<_> = self
+            .trades
+            .iter()
+            .filter(|(time, _, _)| *time > start && *time <= now)
+            .collect();
+        let price_change_pct = match (trades.first(), trades.last()) {
+            (Some((_, open, _)), Some((_, close, _))) if !open.is_zero() => {
+                Some((close - open) / open * dec!(100))
+            }
+            _ => None,
+        };
+        TickerStats {
+            pair,
+            volume: saturating_sum(trades.iter().map(|(_, _, quantity)| quantity)),
+            quote_volume: trades
+                .iter()
+                .fold(Decimal::ZERO, |total, (_, price, quantity)| {
+                    total.saturating_add(price.saturating_mul(*quantity))
+                }),
+            high: trades.iter().map(|(_, price, _)| *price).max(),
+            low: trades.iter().map(|(_, price, _)| *price).min(),
+            last: self.last,
+            price_change_pct,
+            trades: trades.len(),
+        }
+    }
+
+    fn expire(&mut self, now: DateTime<Utc>) {
+        let start = now - Duration::hours(TICKER_WINDOW_HOURS);
+        while matches!(self.trades.front(), Some((time, _, _)) if *time <= start) {
+            self.trades.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_rolling_window() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut ticker = Ticker::default();
+        let empty = ticker.stats("BTC/USDT".to_string(), start);
+        assert_eq!(
+            (empty.volume, empty.last, empty.high),
+            (dec!(0), None, None)
+        );
+
+        ticker.record_trade(start, dec!(100), dec!(2));
+        ticker.record_trade(start + Duration::hours(6), dec!(120), dec!(1));
+        ticker.record_trade(start + Duration::hours(12), dec!(90), dec!(1));
+        let stats = ticker.stats("BTC/USDT".to_string(), start + Duration::hours(12));
+        assert_eq!(stats.volume, dec!(4));
+        assert_eq!(stats.quote_volume, dec!(410));
+        assert_eq!((stats.high, stats.low), (Some(dec!(120)), Some(dec!(90))));
+        assert_eq!(stats.price_change_pct, Some(dec!(-10)));
+        assert_eq!(stats.trades, 3);
+
+        // A day after the first trade it has left the window.
+        let stats = ticker.stats("BTC/USDT".to_string(), start + Duration::hours(24));
+        assert_eq!(stats.volume, dec!(2));
+        assert_eq!(stats.price_change_pct, Some(dec!(-25)));
+
+        // Once every trade is older than a day only the last price is left.
+        ticker.record_trade(start + Duration::hours(40), dec!(95), dec!(1));
+        let stats = ticker.stats("BTC/USDT".to_string(), start + Duration::hours(70));
+        assert_eq!((stats.volume, stats.trades), (dec!(0), 0));
+        assert_eq!((stats.last, stats.high), (Some(dec!(95)), None));
+    }
+
+    #[test]
+    fn test_flat_open_and_trades_outside_the_window() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut ticker = Ticker::default();
+        // A zero opening price has no change to report against it.
+        ticker.record_trade(start, dec!(0), dec!(1));
+        ticker.record_trade(start + Duration::hours(1), dec!(50), dec!(2));
+        let stats = ticker.stats("ETH/USDT".to_string(), start + Duration::hours(1));
+        assert_eq!(stats.pair, "ETH/USDT");
+        assert_eq!(stats.price_change_pct, None);
+        assert_eq!((stats.low, stats.quote_volume), (Some(dec!(0)), dec!(100)));
+
+        // Trades after `now` are left out, though `last` still shows them.
+        let stats = ticker.stats("ETH/USDT".to_string(), start + Duration::minutes(30));
+        assert_eq!((stats.trades, stats.volume), (1, dec!(1)));
+        assert_eq!(stats.last, Some(dec!(50)));
+
+        // A single trade opens and closes the window by itself.
+        let mut ticker = Ticker::default();
+        ticker.record_trade(start, dec!(200), dec!(3));
+        let stats = ticker.stats("ETH/USDT".to_string(), start);
+        assert_eq!(stats.price_change_pct, Some(dec!(0)));
+        assert_eq!((stats.high, stats.low), (Some(dec!(200)), Some(dec!(200))));
+    }
+}
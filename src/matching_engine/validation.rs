@@ -0,0 +1,483 @@
+//! Order entry checks, run in a fixed order before an order reaches the
+//! book: the kinds of order the market takes, its time in force, the price and quantity
+//! against its increments and size limits, notional limits, the price
+//! collar, then any validators registered for the market. The first check
+//! to fail rejects the order with a `Rejection` saying which and why.
+
+use super::engine::TradingPair;
+use crate::{
+    config::{MarketConfig, RiskLimits},
+    limit_order_book::{
+        arithmetic::Overflow,
+        order::{LimitOrderBook, Order, OrderType, TimeInForce},
+    },
+};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use std::{fmt, rc::Rc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderKind {
+    Limit,
+    Market,
+    Midpoint,
+}
+
+impl OrderKind {
+    /// Whether orders of the kind carry a price to check.
+    pub fn is_priced(self) -> bool {
+        self != OrderKind::Market
+    }
+}
+
+impl fmt::Display for OrderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderKind::Limit => write!(f, "limit"),
+            OrderKind::Market => write!(f, "market"),
+            OrderKind::Midpoint => write!(f, "midpoint"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    UnknownMarket(TradingPair),
+    KindNotAllowed(OrderKind),
+    /// A DAY order in a market without a calendar to end its day.
+    NoSession,
+    InvalidPrice(Decimal),
+    InvalidQuantity(Decimal),
+    OffTick {
+        price: Decimal,
+        tick_size: Decimal,
+    },
+    OffLot {
+        quantity: Decimal,
+        lot_size: Decimal,
+    },
+    BelowMinQuantity {
+        quantity: Decimal,
+        min: Decimal,
+    },
+    AboveMaxQuantity {
+        quantity: Decimal,
+        max: Decimal,
+    },
+    BelowMinNotional {
+        notional: Decimal,
+        min: Decimal,
+    },
+    AboveMaxNotional {
+        notional: Decimal,
+        max: Decimal,
+    },
+    /// Priced further through the opposite best than the collar allows.
+    OutsideCollar {
+        price: Decimal,
+        touch: Decimal,
+        collar_pct: Decimal,
+    },
+    /// The order's notional is too large to represent.
+    Overflow(Overflow),
+    /// From a registered validator.
+    Custom(String),
+}
+
+impl From<Overflow> for Rejection {
+    fn from(overflow: Overflow) -> Self {
+        Rejection::Overflow(overflow)
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::UnknownMarket(pair) => {
+                write!(f, "No orderbook for trading pair: {}", pair)
+            }
+            Rejection::KindNotAllowed(kind) => {
+                write!(f, "Market does not accept {} orders", kind)
+            }
+            Rejection::NoSession => write!(f, "Market has no session for DAY orders"),
+            Rejection::InvalidPrice(price) => write!(f, "Invalid price: {}", price),
+            Rejection::InvalidQuantity(quantity) => write!(f, "Invalid quantity: {}", quantity),
+            Rejection::OffTick { price, tick_size } => write!(
+                f,
+                "Price {} is not a multiple of tick size {}",
+                price, tick_size
+            ),
+            Rejection::OffLot { quantity, lot_size } => write!(
+                f,
+                "Quantity {} is not a multiple of lot size {}",
+                quantity, lot_size
+            ),
+            Rejection::BelowMinQuantity { quantity, min } => {
+                write!(f, "Quantity {} is below the minimum {}", quantity, min)
+            }
+            Rejection::AboveMaxQuantity { quantity, max } => {
+                write!(f, "Quantity {} exceeds the maximum {}", quantity, max)
+            }
+            Rejection::BelowMinNotional { notional, min } => {
+                write!(f, "Notional {} is below the minimum {}", notional, min)
+            }
+            Rejection::AboveMaxNotional { notional, max } => {
+                write!(f, "Notional {} exceeds the maximum {}", notional, max)
+            }
+            Rejection::OutsideCollar {
+                price,
+                touch,
+                collar_pct,
+            } => write!(
+                f,
+                "Price {} is more than {}% through the best price {}",
+                price, collar_pct, touch
+            ),
+            Rejection::Overflow(overflow) => write!(f, "Order value out of range: {}", overflow),
+            Rejection::Custom(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// An order as the checks see it.
+#[derive(Debug, Clone, Copy)]
+pub struct OrderEntry<'a> {
+    pub pair: &'a TradingPair,
+    pub order: &'a Order,
+    pub kind: OrderKind,
+    /// The market's book, which the order hasn't touched yet.
+    pub book: &'a LimitOrderBook,
+    /// Units of the underlying per unit of quantity.
+    pub multiplier: Decimal,
+}
+
+/// A market-specific check registered with the engine, run after the
+/// market's configured rules.
+pub trait OrderValidator: fmt::Debug {
+    fn validate(&self, entry: &OrderEntry) -> Result<(), Rejection>;
+}
+
+/// Runs the market's rules and then `validators` over `entry`.
+pub fn validate(
+    config: &MarketConfig,
+    risk: &RiskLimits,
+    validators: &[Rc<dyn OrderValidator>],
+    entry: &OrderEntry,
+) -> Result<(), Rejection> {
+    if let Some(kinds) = &config.order_kinds {
+        if !kinds.contains(&entry.kind) {
+            return Err(Rejection::KindNotAllowed(entry.kind));
+        }
+    }
+    let order = entry.order;
+    if order.time_in_force == TimeInForce::Day && config.calendar.is_none() {
+        return Err(Rejection::NoSession);
+    }
+    if entry.kind.is_priced() {
+        config.validate_contract_order(order.limit_price, order.shares, entry.multiplier, risk)?;
+        if let Some(collar_pct) = config.collar_pct {
+            check_collar(entry, collar_pct)?;
+        }
+    } else {
+        config.validate_quantity(order.shares, risk)?;
+    }
+    validators
+        .iter()
+        .try_for_each(|validator| validator.validate(entry))
+}
+
+/// A buy may be priced at most `collar_pct` above the best ask, and a sell
+/// at most that far below the best bid. With nothing to cross, anything
+/// goes.
+fn check_collar(entry: &OrderEntry, collar_pct: Decimal) -> Result<(), Rejection> {
+    let price = entry.order.limit_price;
+    let (touch, through) = match entry.order.order_type {
+        OrderType::Bid => match entry.book.get_best_ask() {
+            Some(ask) => (ask, price - ask),
+            None => return Ok(()),
+        },
+        OrderType::Ask => match entry.book.get_best_bid() {
+            Some(bid) => (bid, bid - price),
+            None => return Ok(()),
+        },
+    };
+    if through > touch * collar_pct / dec!(100) {
+        return Err(Rejection::OutsideCollar {
+            price,
+            touch,
+            collar_pct,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{limit_order_book::l2::L2Update, matching_engine::engine::MatchingEngine};
+    use chrono::Utc;
+
+    /// Turns away one client.
+    #[derive(Debug)]
+    struct Blocklist(&'static str);
+
+    impl OrderValidator for Blocklist {
+        fn validate(&self, entry: &OrderEntry) -> Result<(), Rejection> {
+            if entry.order.client == self.0 {
+                return Err(Rejection::Custom(format!("Client {} is blocked", self.0)));
+            }
+            Ok(())
+        }
+    }
+
+    fn order(engine: &mut MatchingEngine, client: &str, side: OrderType, price: Decimal) -> Order {
+        let mut order = Order::new(
+            "BTC/USDT".to_string(),
+            engine.next_exchange_id(),
+            side,
+            dec!(1),
+            price,
+            Utc::now(),
+            Utc::now(),
+        );
+        order.client = client.to_string();
+        order
+    }
+
+    #[test]
+    fn test_validation_pipeline() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_market(MarketConfig {
+            tick_size: Some(dec!(1)),
+            min_notional: Some(dec!(50)),
+            collar_pct: Some(dec!(5)),
+            order_kinds: Some(vec![OrderKind::Limit]),
+            ..MarketConfig::new(pair.clone())
+        });
+        engine
+            .add_validator(&pair, Rc::new(Blocklist("mallory")))
+            .unwrap();
+
+        let check = |engine: &mut MatchingEngine, client, side, price, kind| {
+            let order = order(engine, client, side, price);
+            engine.validate_order(&pair, &order, kind)
+        };
+        assert_eq!(
+            check(
+                &mut engine,
+                "alice",
+                OrderType::Bid,
+                dec!(40),
+                OrderKind::Limit
+            ),
+            Err(Rejection::BelowMinNotional {
+                notional: dec!(40),
+                min: dec!(50)
+            })
+        );
+        assert_eq!(
+            check(
+                &mut engine,
+                "alice",
+                OrderType::Bid,
+                dec!(100),
+                OrderKind::Market
+            ),
+            Err(Rejection::KindNotAllowed(OrderKind::Market))
+        );
+        assert!(matches!(
+            check(
+                &mut engine,
+                "alice",
+                OrderType::Bid,
+                dec!(100.5),
+                OrderKind::Limit
+            ),
+            Err(Rejection::OffTick { .. })
+        ));
+        assert!(matches!(
+            check(
+                &mut engine,
+                "mallory",
+                OrderType::Bid,
+                dec!(100),
+                OrderKind::Limit
+            ),
+            Err(Rejection::Custom(_))
+        ));
+
+        // The collar only applies once there is a price to go through.
+        let ask = order(&mut engine, "alice", OrderType::Ask, dec!(100));
+        engine.place_limit_order(pair.clone(), ask).unwrap();
+        assert!(check(
+            &mut engine,
+            "bob",
+            OrderType::Bid,
+            dec!(105),
+            OrderKind::Limit
+        )
+        .is_ok());
+        assert_eq!(
+            check(
+                &mut engine,
+                "bob",
+                OrderType::Bid,
+                dec!(106),
+                OrderKind::Limit
+            ),
+            Err(Rejection::OutsideCollar {
+                price: dec!(106),
+                touch: dec!(100),
+                collar_pct: dec!(5)
+            })
+        );
+
+        // Placing goes through the same checks, before the book is touched.
+        let bid = order(&mut engine, "bob", OrderType::Bid, dec!(106));
+        let error = engine.place_limit_order(pair.clone(), bid).unwrap_err();
+        assert_eq!(
+            error,
+            "Price 106 is more than 5% through the best price 100"
+        );
+        assert_eq!(engine.orderbook(&pair).unwrap().orders.len(), 1);
+
+        // An order too large to value is turned away rather than panicking.
+        let mut huge = order(&mut engine, "bob", OrderType::Bid, dec!(1e15));
+        huge.shares = dec!(1e15);
+        assert!(matches!(
+            engine.validate_order(&pair, &huge, OrderKind::Limit),
+            Err(Rejection::Overflow(_))
+        ));
+    }
+
+    fn entry_order(side: OrderType, shares: Decimal, price: Decimal) -> Order {
+        let now = Utc::now();
+        Order::new("BTC/USDT".to_string(), 1, side, shares, price, now, now)
+    }
+
+    #[test]
+    fn test_unpriced_and_day_orders() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let config = MarketConfig {
+            tick_size: Some(dec!(1)),
+            lot_size: Some(dec!(1)),
+            min_quantity: Some(dec!(2)),
+            min_notional: Some(dec!(50)),
+            collar_pct: Some(dec!(5)),
+            ..MarketConfig::new(pair.clone())
+        };
+        let risk = RiskLimits {
+            max_order_quantity: Some(dec!(10)),
+            ..RiskLimits::default()
+        };
+        let mut book = LimitOrderBook::new();
+        book.apply_l2_update(
+            OrderType::Ask,
+            dec!(100),
+            L2Update::Absolute(dec!(5)),
+            Utc::now(),
+        )
+        .unwrap();
+        let check = |order: &Order, kind, multiplier| {
+            let entry = OrderEntry {
+                pair: &pair,
+                order,
+                kind,
+                book: &book,
+                multiplier,
+            };
+            validate(&config, &risk, &[], &entry)
+        };
+
+        // A market order has no price for the tick, notional or collar
+        // checks, but its quantity still has to pass.
+        let market = entry_order(OrderType::Bid, dec!(3), dec!(0));
+        assert_eq!(check(&market, OrderKind::Market, dec!(1)), Ok(()));
+        let market = entry_order(OrderType::Bid, dec!(1), dec!(0));
+        assert_eq!(
+            check(&market, OrderKind::Market, dec!(1)),
+            Err(Rejection::BelowMinQuantity {
+                quantity: dec!(1),
+                min: dec!(2)
+            })
+        );
+        let market = entry_order(OrderType::Bid, dec!(2.5), dec!(0));
+        assert!(matches!(
+            check(&market, OrderKind::Market, dec!(1)),
+            Err(Rejection::OffLot { .. })
+        ));
+        let market = entry_order(OrderType::Bid, dec!(11), dec!(0));
+        assert!(matches!(
+            check(&market, OrderKind::Market, dec!(1)),
+            Err(Rejection::AboveMaxQuantity { max, .. }) if max == dec!(10)
+        ));
+
+        // Midpoint orders are priced like limits, and the notional counts
+        // the contract multiplier.
+        let small = entry_order(OrderType::Bid, dec!(2), dec!(4));
+        assert!(matches!(
+            check(&small, OrderKind::Midpoint, dec!(1)),
+            Err(Rejection::BelowMinNotional { .. })
+        ));
+        assert_eq!(check(&small, OrderKind::Midpoint, dec!(10)), Ok(()));
+
+        // Without a calendar nothing ends a DAY order's day.
+        let day =
+            entry_order(OrderType::Bid, dec!(2), dec!(100)).with_time_in_force(TimeInForce::Day);
+        assert_eq!(
+            check(&day, OrderKind::Limit, dec!(1)),
+            Err(Rejection::NoSession)
+        );
+    }
+
+    #[test]
+    fn test_sell_collar_and_unknown_markets() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let config = MarketConfig {
+            collar_pct: Some(dec!(5)),
+            ..MarketConfig::new(pair.clone())
+        };
+        let mut book = LimitOrderBook::new();
+        let check = |book: &LimitOrderBook, price| {
+            let order = entry_order(OrderType::Ask, dec!(1), price);
+            let entry = OrderEntry {
+                pair: &pair,
+                order: &order,
+                kind: OrderKind::Limit,
+                book,
+                multiplier: dec!(1),
+            };
+            validate(&config, &RiskLimits::default(), &[], &entry)
+        };
+        assert_eq!(check(&book, dec!(1)), Ok(()));
+
+        // A sell goes through the best bid downwards.
+        book.apply_l2_update(
+            OrderType::Bid,
+            dec!(100),
+            L2Update::Absolute(dec!(5)),
+            Utc::now(),
+        )
+        .unwrap();
+        assert_eq!(check(&book, dec!(95)), Ok(()));
+        let rejection = check(&book, dec!(94)).unwrap_err();
+        assert_eq!(
+            rejection.to_string(),
+            "Price 94 is more than 5% through the best price 100"
+        );
+
+        let engine = MatchingEngine::new();
+        let order = entry_order(OrderType::Ask, dec!(1), dec!(100));
+        let rejection = engine
+            .validate_order(&pair, &order, OrderKind::Limit)
+            .unwrap_err();
+        assert_eq!(rejection, Rejection::UnknownMarket(pair));
+        assert_eq!(
+            rejection.to_string(),
+            "No orderbook for trading pair: BTC/USDT"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Engine counters and histograms, rendered in the Prometheus text
+//! exposition format.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+/// Upper bounds, in seconds, of the latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.000_001, 0.000_005, 0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1, 1.0,
+];
+
+/// Measures elapsed time for the histograms. `Instant::now` panics on
+/// wasm32-unknown-unknown, so there every measurement reads as zero.
+#[derive(Debug, Clone, Copy)]
+pub struct Stopwatch {
+    #[cfg(not(target_arch = "wasm32"))]
+    started: Instant,
+}
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            started: Instant::now(),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.started.elapsed();
+        #[cfg(target_arch = "wasm32")]
+        return Duration::ZERO;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A latency histogram with fixed buckets.
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        let mut cumulative = 0;
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(self.buckets.iter()) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let count = self.count();
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let sum = self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        let _ = writeln!(out, "{}_sum {}", name, sum);
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+/// Everything the engine reports. Shared between the engine thread and
+/// whoever exports it, so all fields are safe to update concurrently.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub orders_accepted: Counter,
+    pub orders_rejected: Counter,
+    pub trades: Counter,
+    /// Time spent validating and matching an order.
+    pub match_latency: Histogram,
+    /// Time a request waited before the engine picked it up.
+    pub queue_lag: Histogram,
+    /// Price levels by pair and side.
+    book_depth: Mutex<BTreeMap<(String, &'static str), usize>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_book_depth(&self, pair: &str, bids: usize, asks: usize) {
+        let mut book_depth = self.book_depth.lock().unwrap();
+        book_depth.insert((pair.to_string(), "bid"), bids);
+        book_depth.insert((pair.to_string(), "ask"), asks);
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            (
+                "tradebot_orders_accepted_total",
+                "Orders accepted by the engine.",
+                &self.orders_accepted,
+            ),
+            (
+                "tradebot_orders_rejected_total",
+                "Orders rejected by validation or risk checks.",
+                &self.orders_rejected,
+            ),
+            ("tradebot_trades_total", "Fills executed.", &self.trades),
+        ];
+        for (name, help, counter) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, counter.get());
+        }
+
+        self.match_latency.render(
+            &mut out,
+            "tradebot_match_latency_seconds",
+            "Time to validate and match an order.",
+        );
+        self.queue_lag.render(
+            &mut out,
+            "tradebot_queue_lag_seconds",
+            "Time requests wait for the engine thread.",
+        );
+
+        let _ = writeln!(out, "# HELP tradebot_book_depth Price levels per side.");
+        let _ = writeln!(out, "# TYPE tradebot_book_depth gauge");
+        for ((pair, side), depth) in self.book_depth.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "tradebot_book_depth{{pair=\"{}\",side=\"{}\"}} {}",
+                pair, side, depth
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.orders_accepted.add(3);
+        metrics.orders_rejected.inc();
+        metrics.match_latency.observe(Duration::from_micros(3));
+        metrics.match_latency.observe(Duration::from_millis(2));
+        metrics.set_book_depth("BTC/USDT", 3, 2);
+
+        let text = metrics.render();
+        assert!(text.contains("tradebot_orders_accepted_total 3\n"));
+        assert!(text.contains("tradebot_orders_rejected_total 1\n"));
+        assert!(text.contains("tradebot_match_latency_seconds_bucket{le=\"0.000005\"} 1\n"));
+        assert!(text.contains("tradebot_match_latency_seconds_bucket{le=\"0.005\"} 2\n"));
+        assert!(text.contains("tradebot_match_latency_seconds_count 2\n"));
+        assert!(text.contains("tradebot_book_depth{pair=\"BTC/USDT\",side=\"bid\"} 3\n"));
+    }
+
+    #[test]
+    fn test_histogram_overflow_and_depth_updates() {
+        let metrics = Metrics::new();
+        // Slower than the last bucket: only +Inf, the sum and the count.
+        metrics.queue_lag.observe(Duration::from_secs(2));
+        metrics.queue_lag.observe(Duration::from_millis(500));
+        metrics.set_book_depth("ETH/USDT", 1, 1);
+        metrics.set_book_depth("ETH/USDT", 4, 0);
+
+        let text = metrics.render();
+        assert!(text.contains("tradebot_queue_lag_seconds_bucket{le=\"0.1\"} 0\n"));
+        assert!(text.contains("tradebot_queue_lag_seconds_bucket{le=\"1\"} 1\n"));
+        assert!(text.contains("tradebot_queue_lag_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("tradebot_queue_lag_seconds_sum 2.5\n"));
+        assert_eq!(metrics.queue_lag.count(), 2);
+        assert!(text.contains("tradebot_book_depth{pair=\"ETH/USDT\",side=\"bid\"} 4\n"));
+        assert!(text.contains("tradebot_book_depth{pair=\"ETH/USDT\",side=\"ask\"} 0\n"));
+        assert_eq!(text.matches("tradebot_book_depth{").count(), 2);
+        assert!(text.contains("# TYPE tradebot_trades_total counter\ntradebot_trades_total 0\n"));
+    }
+}
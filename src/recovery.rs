@@ -0,0 +1,543 @@
+//! Rebuilding the engine's state on restart from the last checkpoint and
+//! the journal commands after it, with a check that it came back right.
+//!
+//! A checkpoint is the engine's snapshot plus its `state_hash` and the
+//! journal's sequence when it was taken, written to `snapshot_path` each
+//! time the server drains. Alongside the journal the server records the
+//! engine's hash every `SWEEP_INTERVAL` it has journaled something, and
+//! when it drains. Recovery restores the snapshot and compares its hash
+//! with the checkpoint's, then replays the journal from that sequence on,
+//! comparing the hash at each recorded sequence. Commands after the last
+//! record, at most a sweep's worth after a crash, can't be checked.
+//!
+//! A mismatch, a journal shorter than the checkpoint or the hashes say it
+//! was, or a checkpoint taken while the engine held state snapshots leave
+//! out means the state on disk can't be trusted; `on_mismatch` decides
+//! whether the engine then refuses to start or starts only taking cancels.
+
+use crate::{
+    config::PersistenceConfig,
+    journal,
+    matching_engine::{engine::MatchingEngine, snapshot::EngineSnapshot},
+    replay,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, io, path::Path};
+
+/// What to do when recovery doesn't reproduce the recorded state.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnMismatch {
+    #[default]
+    Refuse,
+    /// Start, but reject new orders so clients can only cancel.
+    CancelOnly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Journal commands applied before the snapshot was taken.
+    pub sequence: u64,
+    pub state_hash: u64,
+    pub snapshot: EngineSnapshot,
+    /// The engine's state the snapshot left out; see
+    /// `MatchingEngine::uncaptured`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub uncaptured: Vec<String>,
+}
+
+impl Checkpoint {
+    pub fn new(engine: &MatchingEngine, sequence: u64) -> Self {
+        Self {
+            sequence,
+            state_hash: engine.state_hash(),
+            snapshot: engine.snapshot(),
+            uncaptured: engine.uncaptured().into_iter().map(String::from).collect(),
+        }
+    }
+
+    /// The checkpoint at `path`, or `None` if there isn't one yet.
+    pub fn read(path: &Path) -> io::Result<Option<Self>> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        serde_json::from_slice(&bytes).map(Some).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), err),
+            )
+        })
+    }
+
+    /// Replaces the checkpoint at `path`. It is written aside and renamed
+    /// over, so a crash leaves the old one.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let partial = path.with_extension("partial");
+        fs::write(
+            &partial,
+            serde_json::to_vec(self).expect("checkpoints serialize"),
+        )?;
+        fs::rename(&partial, path)
+    }
+}
+
+/// Why the recovered state can't be trusted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Mismatch {
+    /// The checkpoint's snapshot didn't restore to the hash recorded with it.
+    Checkpoint { recorded: u64, restored: u64 },
+    /// The engine held state the checkpoint's snapshot doesn't capture.
+    Uncaptured { state: Vec<String> },
+    /// Replaying up to journal command `sequence` didn't reproduce the hash
+    /// recorded after it.
+    Replay {
+        sequence: u64,
+        recorded: u64,
+        replayed: u64,
+    },
+    /// The journal holds `held` commands, but the checkpoint or a recorded
+    /// hash covers `expected`.
+    Truncated { expected: u64, held: u64 },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Checkpoint { recorded, restored } => write!(
+                f,
+                "restored state hash {:016x} doesn't match the checkpoint's {:016x}",
+                restored, recorded
+            ),
+            Mismatch::Uncaptured { state } => write!(
+                f,
+                "the checkpoint doesn't capture the engine's {}",
+                state.join(", ")
+            ),
+            Mismatch::Replay {
+                sequence,
+                recorded,
+                replayed,
+            } => write!(
+                f,
+                "state hash {:016x} after journal command {} doesn't match the recorded {:016x}",
+                replayed, sequence, recorded
+            ),
+            Mismatch::Truncated { expected, held } => write!(
+                f,
+                "the journal holds {} commands, but {} were recorded",
+                held, expected
+            ),
+        }
+    }
+}
+
+/// How the engine's state was rebuilt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecoveryReport {
+    /// The journal sequence of the checkpoint restored, if there was one.
+    pub checkpoint_sequence: Option<u64>,
+    /// The hash the checkpoint recorded.
+    pub recorded_hash: Option<u64>,
+    /// The hash of the checkpoint's snapshot once restored.
+    pub restored_hash: Option<u64>,
+    /// Journal commands replayed after the checkpoint.
+    pub replayed: u64,
+    /// Of those, the ones the engine rejected again.
+    pub rejected: u64,
+    /// The latest journal sequence whose recorded hash the replay was
+    /// checked against.
+    pub verified_through: Option<u64>,
+    /// Why the recovered state can't be trusted, if it can't: the first
+    /// problem found.
+    pub mismatch: Option<Mismatch>,
+    /// The engine's hash once recovered.
+    pub state_hash: u64,
+}
+
+impl RecoveryReport {
+    pub fn is_verified(&self) -> bool {
+        self.mismatch.is_none()
+    }
+}
+
+impl fmt::Display for RecoveryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.checkpoint_sequence {
+            Some(sequence) => write!(f, "restored the checkpoint at {}", sequence)?,
+            None => write!(f, "no checkpoint")?,
+        }
+        write!(
+            f,
+            ", replayed {} journaled commands ({} rejected), state hash {:016x}",
+            self.replayed, self.rejected, self.state_hash
+        )?;
+        if let Some(sequence) = self.verified_through {
+            write!(f, ", checked through command {}", sequence)?;
+        }
+        if let Some(mismatch) = &self.mismatch {
+            write!(f, "; {}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Restores `engine`, freshly built from the configuration, from the
+/// checkpoint and journal `persistence` names. Errors are failures to read
+/// them; a state that doesn't match is reported instead.
+pub fn recover(
+    engine: &mut MatchingEngine,
+    persistence: &PersistenceConfig,
+) -> Result<RecoveryReport, String> {
+    let checkpoint = match &persistence.snapshot_path {
+        Some(path) => Checkpoint::read(path)
+            .map_err(|err| format!("Failed to read the checkpoint: {}", err))?,
+        None => None,
+    };
+    let mut report = RecoveryReport {
+        checkpoint_sequence: None,
+        recorded_hash: None,
+        restored_hash: None,
+        replayed: 0,
+        rejected: 0,
+        verified_through: None,
+        mismatch: None,
+        state_hash: 0,
+    };
+    if let Some(checkpoint) = &checkpoint {
+        engine.restore(&checkpoint.snapshot)?;
+        let restored = engine.state_hash();
+        report.checkpoint_sequence = Some(checkpoint.sequence);
+        report.recorded_hash = Some(checkpoint.state_hash);
+        report.restored_hash = Some(restored);
+        if restored != checkpoint.state_hash {
+            report.mismatch = Some(Mismatch::Checkpoint {
+                recorded: checkpoint.state_hash,
+                restored,
+            });
+        } else if !checkpoint.uncaptured.is_empty() {
+            report.mismatch = Some(Mismatch::Uncaptured {
+                state: checkpoint.uncaptured.clone(),
+            });
+        }
+    }
+    if let Some(path) = &persistence.journal_path {
+        let from = report.checkpoint_sequence.unwrap_or(0);
+        let hashes = journal::read_hashes(path)
+            .map_err(|err| format!("Failed to read the journal's hashes: {}", err))?;
+        let mut hashes = hashes
+            .into_iter()
+            .filter(|record| record.sequence > from)
+            .peekable();
+        let (held, commands) = journal::read_from(path, from)
+            .map_err(|err| format!("Failed to read the journal: {}", err))?;
+        // The commands the checkpoint covers are gone.
+        if held < from {
+            report.mismatch.get_or_insert(Mismatch::Truncated {
+                expected: from,
+                held,
+            });
+        }
+        for (sequence, command) in (from + 1..).zip(&commands) {
+            if let replay::Output::Rejected { .. } = replay::apply(engine, command) {
+                report.rejected += 1;
+            }
+            let Some(record) = hashes.next_if(|record| record.sequence == sequence) else {
+                continue;
+            };
+            let state_hash = engine.state_hash();
+            if state_hash == record.state_hash {
+                report.verified_through = Some(sequence);
+            } else {
+                report.mismatch.get_or_insert(Mismatch::Replay {
+                    sequence,
+                    recorded: record.state_hash,
+                    replayed: state_hash,
+                });
+            }
+        }
+        report.replayed = commands.len() as u64;
+        if let Some(record) = hashes.next() {
+            report.mismatch.get_or_insert(Mismatch::Truncated {
+                expected: record.sequence,
+                held,
+            });
+        }
+    }
+    report.state_hash = engine.state_hash();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::{CancelOrderRequest, Command, NewOrderRequest},
+        journal::Journal,
+        limit_order_book::order::{OrderType, TimeInForce},
+        matching_engine::engine::TradingPair,
+        replay::RecordedCommand,
+    };
+    use chrono::{TimeZone, Utc};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("tradebot-recovery-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn persistence(dir: &Path) -> PersistenceConfig {
+        PersistenceConfig {
+            journal_path: Some(dir.join("journal.log")),
+            snapshot_path: Some(dir.join("checkpoint.json")),
+            ..PersistenceConfig::default()
+        }
+    }
+
+    fn new_engine() -> MatchingEngine {
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(TradingPair::new("BTC".to_string(), "USDT".to_string()));
+        engine
+    }
+
+    fn order(client: &str, side: OrderType, price: Decimal) -> RecordedCommand {
+        RecordedCommand {
+            time: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            command: Command::New(NewOrderRequest {
+                pair: "BTC/USDT".to_string(),
+                side,
+                price,
+                quantity: dec!(1),
+                client: client.to_string(),
+                short_sale: false,
+                reduce_only: false,
+                client_order_id: None,
+                strategy: None,
+                time_in_force: TimeInForce::Gtc,
+            }),
+        }
+    }
+
+    fn cancel(exchange_id: u64) -> RecordedCommand {
+        RecordedCommand {
+            time: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            command: Command::Cancel(CancelOrderRequest {
+                pair: "BTC/USDT".to_string(),
+                exchange_id,
+            }),
+        }
+    }
+
+    /// Runs `commands` as the server would: journals and applies each,
+    /// checkpoints after the first `checkpoint` and records the hash after
+    /// every later one, with `hash` deciding what is recorded.
+    fn run(
+        dir: &Path,
+        engine: &mut MatchingEngine,
+        commands: &[RecordedCommand],
+        checkpoint: usize,
+        hash: impl Fn(u64, u64) -> u64,
+    ) {
+        let mut journal = Journal::open(dir.join("journal.log"), None).unwrap();
+        for (index, command) in commands.iter().enumerate() {
+            journal.append(command).unwrap();
+            replay::apply(engine, command);
+            if index + 1 == checkpoint {
+                Checkpoint::new(engine, journal.sequence())
+                    .write(dir.join("checkpoint.json").as_path())
+                    .unwrap();
+            } else if index + 1 > checkpoint {
+                journal
+                    .record_hash(hash(journal.sequence(), engine.state_hash()))
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_recover() {
+        let dir = temp_dir("recover");
+        let persistence = persistence(&dir);
+        let mut engine = new_engine();
+        let commands = [
+            order("alice", OrderType::Ask, dec!(101)),
+            order("alice", OrderType::Ask, dec!(102)),
+            order("alice", OrderType::Bid, dec!(101)),
+            cancel(2),
+            cancel(2),
+        ];
+        run(&dir, &mut engine, &commands, 3, |_, hash| hash);
+
+        let mut recovered = new_engine();
+        let report = recover(&mut recovered, &persistence).unwrap();
+        assert!(report.is_verified(), "{}", report);
+        assert_eq!(report.checkpoint_sequence, Some(3));
+        assert_eq!(report.restored_hash, report.recorded_hash);
+        assert_eq!((report.replayed, report.rejected), (2, 1));
+        assert_eq!(report.verified_through, Some(5));
+        assert_eq!(report.state_hash, engine.state_hash());
+
+        // A journal tail that replays differently is caught by the hashes.
+        let journal_path = dir.join("journal.log");
+        let journaled = fs::read_to_string(&journal_path).unwrap();
+        let mut lines: Vec<&str> = journaled.lines().collect();
+        let tampered = lines[3].replace("\"exchange_id\":2", "\"exchange_id\":1");
+        lines[3] = &tampered;
+        fs::write(&journal_path, lines.join("\n") + "\n").unwrap();
+        let report = recover(&mut new_engine(), &persistence).unwrap();
+        assert_eq!(report.restored_hash, report.recorded_hash);
+        assert!(matches!(
+            report.mismatch,
+            Some(Mismatch::Replay { sequence: 4, .. })
+        ));
+        fs::write(&journal_path, journaled).unwrap();
+
+        // A checkpoint that doesn't restore to its hash is reported.
+        let path = dir.join("checkpoint.json");
+        let mut checkpoint = Checkpoint::read(&path).unwrap().unwrap();
+        checkpoint.snapshot.markets[0].orders.clear();
+        checkpoint.write(&path).unwrap();
+        let report = recover(&mut new_engine(), &persistence).unwrap();
+        assert!(matches!(
+            report.mismatch,
+            Some(Mismatch::Checkpoint { recorded, .. }) if recorded == checkpoint.state_hash
+        ));
+
+        // So is a journal that lost what the checkpoint covers.
+        fs::remove_file(dir.join("journal.log")).unwrap();
+        checkpoint = Checkpoint::new(&engine, 5);
+        checkpoint.write(&path).unwrap();
+        let report = recover(&mut new_engine(), &persistence).unwrap();
+        assert_eq!(
+            report.mismatch,
+            Some(Mismatch::Truncated {
+                expected: 5,
+                held: 0
+            })
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_positions_survive_checkpoint_and_replay() {
+        let dir = temp_dir("positions");
+        let mut engine = new_engine();
+        let commands = [
+            order("alice", OrderType::Ask, dec!(101)),
+            order("alice", OrderType::Ask, dec!(102)),
+            order("bob", OrderType::Bid, dec!(101)),
+            order("bob", OrderType::Bid, dec!(102)),
+        ];
+        run(&dir, &mut engine, &commands, 3, |_, hash| hash);
+
+        let mut recovered = new_engine();
+        let report = recover(&mut recovered, &persistence(&dir)).unwrap();
+        assert!(report.is_verified(), "{}", report);
+        assert_eq!(report.verified_through, Some(4));
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        // One trade from before the checkpoint, one replayed after it.
+        assert_eq!(recovered.positions().position("bob", &pair), dec!(2));
+        assert_eq!(recovered.positions().position("alice", &pair), dec!(-2));
+        let holding = recovered.portfolio().holding("alice", &pair);
+        assert_eq!(holding.average_price, dec!(101.5));
+        assert_eq!(holding, engine.portfolio().holding("alice", &pair));
+        assert_eq!(recovered.snapshot(), engine.snapshot());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupted_checkpoint_hash() {
+        let dir = temp_dir("checkpoint-hash");
+        let mut engine = new_engine();
+        let commands = [
+            order("alice", OrderType::Ask, dec!(101)),
+            order("bob", OrderType::Bid, dec!(100)),
+        ];
+        run(&dir, &mut engine, &commands, 1, |_, hash| hash);
+        let path = dir.join("checkpoint.json");
+        let mut checkpoint = Checkpoint::read(&path).unwrap().unwrap();
+        let restored = checkpoint.state_hash;
+        checkpoint.state_hash ^= 1;
+        checkpoint.write(&path).unwrap();
+
+        let report = recover(&mut new_engine(), &persistence(&dir)).unwrap();
+        assert_eq!(
+            report.mismatch,
+            Some(Mismatch::Checkpoint {
+                recorded: restored ^ 1,
+                restored
+            })
+        );
+        // The journal after it still replays to the recorded hashes.
+        assert_eq!(report.verified_through, Some(2));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_mid_journal_hash_mismatch() {
+        let dir = temp_dir("journal-hash");
+        let mut engine = new_engine();
+        let commands = [
+            order("alice", OrderType::Ask, dec!(101)),
+            order("alice", OrderType::Ask, dec!(102)),
+            order("bob", OrderType::Bid, dec!(101)),
+            cancel(2),
+        ];
+        run(&dir, &mut engine, &commands, 1, |sequence, hash| {
+            if sequence == 2 {
+                hash ^ 1
+            } else {
+                hash
+            }
+        });
+
+        let report = recover(&mut new_engine(), &persistence(&dir)).unwrap();
+        match report.mismatch {
+            Some(Mismatch::Replay {
+                sequence,
+                recorded,
+                replayed,
+            }) => {
+                assert_eq!(sequence, 2);
+                assert_eq!(recorded, replayed ^ 1);
+            }
+            mismatch => panic!("unexpected mismatch: {:?}", mismatch),
+        }
+        // Later hashes are still checked.
+        assert_eq!(report.verified_through, Some(4));
+        assert_eq!(report.state_hash, engine.state_hash());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_uncaptured_state_is_not_verified() {
+        let dir = temp_dir("uncaptured");
+        let mut engine = new_engine();
+        engine.kill_switch("carol");
+        run(
+            &dir,
+            &mut engine,
+            &[order("alice", OrderType::Ask, dec!(101))],
+            1,
+            |_, hash| hash,
+        );
+
+        let report = recover(&mut new_engine(), &persistence(&dir)).unwrap();
+        assert_eq!(report.restored_hash, report.recorded_hash);
+        assert_eq!(
+            report.mismatch,
+            Some(Mismatch::Uncaptured {
+                state: vec!["kill switches".to_string()]
+            })
+        );
+        assert!(!report.is_verified());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,492 @@
+//! Active-passive replication. The primary streams every command it
+//! sequences, together with the state hash it reached (see
+//! `matching_engine::state_hash`), to a standby that applies them in the
+//! same order and checks it reaches the same hash. Should the primary fail,
+//! the standby is promoted and carries on from the last command it applied.
+//!
+//! A standby connecting, or reconnecting after a gap, says how many
+//! commands it has applied and the hash it got to. The primary checks both
+//! against its own log and answers with the commands the standby is
+//! missing, or refuses if their histories differ; such a standby has to
+//! start over from a fresh engine. Messages are plain serde values, so any
+//! transport that keeps them in order will do, e.g. JSON lines over TCP.
+
+use crate::{
+    matching_engine::engine::MatchingEngine,
+    replay::{self, Output, RecordedCommand},
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReplicationMessage {
+    /// From a standby: how far it has got.
+    Hello { sequence: u64, state_hash: u64 },
+    /// From the primary: the `sequence`th command, numbered from 1, and the
+    /// hash of the state after it.
+    Command {
+        sequence: u64,
+        command: RecordedCommand,
+        state_hash: u64,
+    },
+    /// From the primary while it has nothing to send, so the standby can
+    /// tell a quiet primary from a dead one.
+    Heartbeat { sequence: u64 },
+}
+
+impl ReplicationMessage {
+    fn name(&self) -> &'static str {
+        match self {
+            ReplicationMessage::Hello { .. } => "hello",
+            ReplicationMessage::Command { .. } => "command",
+            ReplicationMessage::Heartbeat { .. } => "heartbeat",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplicationError {
+    /// The standby claims commands the primary never sequenced.
+    AheadOfPrimary { standby: u64, primary: u64 },
+    /// The two engines reached different states after the same commands.
+    HashMismatch {
+        sequence: u64,
+        expected: u64,
+        actual: u64,
+    },
+    /// A command arrived out of order, or the heartbeat shows commands
+    /// were missed; the standby needs to say hello again.
+    Gap { expected: u64, received: u64 },
+    /// A message meant for the other side, by type.
+    Unexpected(&'static str),
+}
+
+impl fmt::Display for ReplicationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplicationError::AheadOfPrimary { standby, primary } => write!(
+                f,
+                "standby has applied {} commands but the primary only {}",
+                standby, primary
+            ),
+            ReplicationError::HashMismatch {
+                sequence,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "state diverged at command {}: expected hash {:016x}, got {:016x}",
+                sequence, expected, actual
+            ),
+            ReplicationError::Gap { expected, received } => {
+                write!(f, "expected command {} but received {}", expected, received)
+            }
+            ReplicationError::Unexpected(message) => {
+                write!(f, "unexpected {} message", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplicationError {}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    command: RecordedCommand,
+    state_hash: u64,
+}
+
+/// An engine and every command it has applied, which both sides keep so a
+/// standby can become a primary.
+struct Replica {
+    engine: MatchingEngine,
+    initial_hash: u64,
+    log: Vec<Entry>,
+}
+
+impl Replica {
+    fn new(engine: MatchingEngine) -> Self {
+        Self {
+            initial_hash: engine.state_hash(),
+            engine,
+            log: Vec::new(),
+        }
+    }
+
+    fn sequence(&self) -> u64 {
+        self.log.len() as u64
+    }
+
+    fn state_hash(&self) -> u64 {
+        self.log
+            .last()
+            .map_or(self.initial_hash, |entry| entry.state_hash)
+    }
+
+    fn apply(&mut self, command: RecordedCommand) -> (Output, u64) {
+        let output = replay::apply(&mut self.engine, &command);
+        let state_hash = self.engine.state_hash();
+        self.log.push(Entry {
+            command,
+            state_hash,
+        });
+        (output, state_hash)
+    }
+}
+
+pub struct Primary {
+    replica: Replica,
+}
+
+impl Primary {
+    /// `engine` must be in the state a fresh standby's is in, which is
+    /// easiest if both are built the same way and nothing has run yet.
+    pub fn new(engine: MatchingEngine) -> Self {
+        Self {
+            replica: Replica::new(engine),
+        }
+    }
+
+    pub fn engine(&self) -> &MatchingEngine {
+        &self.replica.engine
+    }
+
+    /// The number of commands applied.
+    pub fn sequence(&self) -> u64 {
+        self.replica.sequence()
+    }
+
+    /// Applies `command` and returns what it produced along with the
+    /// message that replicates it.
+    pub fn apply(&mut self, command: RecordedCommand) -> (Output, ReplicationMessage) {
+        let (output, state_hash) = self.replica.apply(command.clone());
+        let message = ReplicationMessage::Command {
+            sequence: self.sequence(),
+            command,
+            state_hash,
+        };
+        (output, message)
+    }
+
+    pub fn heartbeat(&self) -> ReplicationMessage {
+        ReplicationMessage::Heartbeat {
+            sequence: self.sequence(),
+        }
+    }
+
+    /// Answers a standby's hello with the commands it is missing, oldest
+    /// first.
+    pub fn handshake(
+        &self,
+        hello: &ReplicationMessage,
+    ) -> Result<Vec<ReplicationMessage>, ReplicationError> {
+        let ReplicationMessage::Hello {
+            sequence,
+            state_hash,
+        } = *hello
+        else {
+            return Err(ReplicationError::Unexpected(hello.name()));
+        };
+        if sequence > self.sequence() {
+            return Err(ReplicationError::AheadOfPrimary {
+                standby: sequence,
+                primary: self.sequence(),
+            });
+        }
+        let expected = match sequence {
+            0 => self.replica.initial_hash,
+            _ => self.replica.log[sequence as usize - 1].state_hash,
+        };
+        if state_hash != expected {
+            return Err(ReplicationError::HashMismatch {
+                sequence,
+                expected,
+                actual: state_hash,
+            });
+        }
+        Ok(self.replica.log[sequence as usize..]
+            .iter()
+            .zip(sequence + 1..)
+            .map(|(entry, sequence)| ReplicationMessage::Command {
+                sequence,
+                command: entry.command.clone(),
+                state_hash: entry.state_hash,
+            })
+            .collect())
+    }
+}
+
+pub struct Standby {
+    replica: Replica,
+    last_heard: Option<DateTime<Utc>>,
+}
+
+impl Standby {
+    pub fn new(engine: MatchingEngine) -> Self {
+        Self {
+            replica: Replica::new(engine),
+            last_heard: None,
+        }
+    }
+
+    pub fn engine(&self) -> &MatchingEngine {
+        &self.replica.engine
+    }
+
+    /// The number of commands applied.
+    pub fn sequence(&self) -> u64 {
+        self.replica.sequence()
+    }
+
+    /// What to send the primary on connecting.
+    pub fn hello(&self) -> ReplicationMessage {
+        ReplicationMessage::Hello {
+            sequence: self.sequence(),
+            state_hash: self.replica.state_hash(),
+        }
+    }
+
+    /// Takes in a message from the primary, received at `now`. After a
+    /// `HashMismatch` the standby's state can't be trusted and it has to be
+    /// rebuilt; after a `Gap` it only needs to say hello again.
+    pub fn receive(
+        &mut self,
+        message: ReplicationMessage,
+        now: DateTime<Utc>,
+    ) -> Result<(), ReplicationError> {
+        self.last_heard = Some(now);
+        match message {
+            ReplicationMessage::Command {
+                sequence,
+                command,
+                state_hash,
+            } => {
+                let expected = self.sequence() + 1;
+                if sequence != expected {
+                    return Err(ReplicationError::Gap {
+                        expected,
+                        received: sequence,
+                    });
+                }
+                let (_, actual) = self.replica.apply(command);
+                if actual != state_hash {
+                    return Err(ReplicationError::HashMismatch {
+                        sequence,
+                        expected: state_hash,
+                        actual,
+                    });
+                }
+                Ok(())
+            }
+            ReplicationMessage::Heartbeat { sequence } if sequence > self.sequence() => {
+                Err(ReplicationError::Gap {
+                    expected: self.sequence() + 1,
+                    received: sequence,
+                })
+            }
+            ReplicationMessage::Heartbeat { .. } => Ok(()),
+            ReplicationMessage::Hello { .. } => Err(ReplicationError::Unexpected(message.name())),
+        }
+    }
+
+    /// Whether nothing has come from the primary for longer than `timeout`
+    /// before `now`, which is the cue to promote the standby.
+    pub fn primary_silent(&self, now: DateTime<Utc>, timeout: Duration) -> bool {
+        self.last_heard
+            .is_none_or(|last_heard| now - last_heard > timeout)
+    }
+
+    /// Takes over as primary with the commands applied so far, which new
+    /// standbys can then catch up on.
+    pub fn promote(self) -> Primary {
+        Primary {
+            replica: self.replica,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::{Command, NewOrderRequest},
+        limit_order_book::order::{OrderType, TimeInForce},
+        matching_engine::engine::TradingPair,
+    };
+    use chrono::TimeZone;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn engine() -> MatchingEngine {
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(TradingPair::new("BTC".to_string(), "USDT".to_string()));
+        engine
+    }
+
+    fn command(index: i64, side: OrderType, price: Decimal) -> RecordedCommand {
+        RecordedCommand {
+            time: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + Duration::seconds(index),
+            command: Command::New(NewOrderRequest {
+                pair: "BTC/USDT".to_string(),
+                side,
+                price,
+                quantity: dec!(1),
+                client: "alice".to_string(),
+                short_sale: false,
+                reduce_only: false,
+                client_order_id: None,
+                strategy: None,
+                time_in_force: TimeInForce::Gtc,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_failover() {
+        let now = Utc::now();
+        let mut primary = Primary::new(engine());
+        primary.apply(command(0, OrderType::Ask, dec!(101)));
+        primary.apply(command(1, OrderType::Bid, dec!(99)));
+
+        // A standby joining late catches up from the handshake.
+        let mut standby = Standby::new(engine());
+        for message in primary.handshake(&standby.hello()).unwrap() {
+            standby.receive(message, now).unwrap();
+        }
+        let (_, message) = primary.apply(command(2, OrderType::Bid, dec!(101)));
+        standby.receive(message, now).unwrap();
+        assert_eq!(standby.sequence(), 3);
+        assert_eq!(standby.engine().state_hash(), primary.engine().state_hash());
+
+        // A command lost in transit shows up as a gap at the next one.
+        primary.apply(command(3, OrderType::Ask, dec!(102)));
+        let (_, message) = primary.apply(command(4, OrderType::Ask, dec!(103)));
+        assert_eq!(
+            standby.receive(message, now),
+            Err(ReplicationError::Gap {
+                expected: 4,
+                received: 5
+            })
+        );
+        for message in primary.handshake(&standby.hello()).unwrap() {
+            standby.receive(message, now).unwrap();
+        }
+        standby.receive(primary.heartbeat(), now).unwrap();
+
+        // The primary goes quiet and the standby takes over, serving a new
+        // standby from its own log.
+        let later = now + Duration::seconds(5);
+        assert!(!standby.primary_silent(later, Duration::seconds(10)));
+        assert!(standby.primary_silent(later, Duration::seconds(1)));
+        let mut promoted = standby.promote();
+        promoted.apply(command(5, OrderType::Bid, dec!(100)));
+        let mut replacement = Standby::new(engine());
+        for message in promoted.handshake(&replacement.hello()).unwrap() {
+            replacement.receive(message, later).unwrap();
+        }
+        assert_eq!(replacement.sequence(), 6);
+        assert_eq!(
+            replacement.engine().state_hash(),
+            promoted.engine().state_hash()
+        );
+
+        // An engine with a history of its own is turned away.
+        let mut stranger = Standby::new(engine());
+        stranger
+            .receive(
+                ReplicationMessage::Command {
+                    sequence: 1,
+                    command: command(0, OrderType::Bid, dec!(90)),
+                    state_hash: 0,
+                },
+                now,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            promoted.handshake(&stranger.hello()),
+            Err(ReplicationError::HashMismatch { sequence: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_refusals() {
+        let now = Utc::now();
+        let mut primary = Primary::new(engine());
+        primary.apply(command(0, OrderType::Ask, dec!(101)));
+
+        let ahead = ReplicationMessage::Hello {
+            sequence: 2,
+            state_hash: 0,
+        };
+        let error = primary.handshake(&ahead).unwrap_err();
+        assert_eq!(
+            error,
+            ReplicationError::AheadOfPrimary {
+                standby: 2,
+                primary: 1
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "standby has applied 2 commands but the primary only 1"
+        );
+        let error = primary.handshake(&primary.heartbeat()).unwrap_err();
+        assert_eq!(error.to_string(), "unexpected heartbeat message");
+
+        // An engine set up differently differs before any command.
+        let mut other = engine();
+        other.add_new_market(TradingPair::new("ETH".to_string(), "USDT".to_string()));
+        let standby = Standby::new(other);
+        assert!(matches!(
+            primary.handshake(&standby.hello()),
+            Err(ReplicationError::HashMismatch { sequence: 0, .. })
+        ));
+
+        // A heartbeat ahead of the standby means it missed commands.
+        let mut standby = Standby::new(engine());
+        assert_eq!(
+            standby.receive(primary.heartbeat(), now),
+            Err(ReplicationError::Gap {
+                expected: 1,
+                received: 1
+            })
+        );
+        assert_eq!(standby.sequence(), 0);
+        let hello = standby.hello();
+        assert_eq!(
+            standby.receive(hello, now),
+            Err(ReplicationError::Unexpected("hello"))
+        );
+    }
+
+    #[test]
+    fn test_messages_and_silence() {
+        let now = Utc::now();
+        let mut primary = Primary::new(engine());
+        let mut standby = Standby::new(engine());
+        // Never having heard from the primary counts as silence.
+        assert!(standby.primary_silent(now, Duration::days(1)));
+        assert!(primary.handshake(&standby.hello()).unwrap().is_empty());
+
+        let (_, message) = primary.apply(command(0, OrderType::Bid, dec!(99)));
+        let json = serde_json::to_string(&message).unwrap();
+        assert!(json.starts_with(r#"{"type":"command","sequence":1,"#));
+        let received: ReplicationMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(received, message);
+        standby.receive(received, now).unwrap();
+
+        // Heartbeats at the standby's sequence are quiet but keep it from
+        // taking over.
+        let json = serde_json::to_string(&primary.heartbeat()).unwrap();
+        assert_eq!(json, r#"{"type":"heartbeat","sequence":1}"#);
+        let later = now + Duration::seconds(30);
+        assert!(standby.primary_silent(later, Duration::seconds(10)));
+        standby
+            .receive(serde_json::from_str(&json).unwrap(), later)
+            .unwrap();
+        assert!(!standby.primary_silent(later, Duration::seconds(10)));
+        assert!(primary.handshake(&standby.hello()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,1017 @@
+//! HTTP front end for a `MatchingEngine`.
+//!
+//! The engine is not `Send`, so it lives on a dedicated thread and the
+//! request handlers talk to it through an `EngineHandle`.
+//!
+//! To stop, `drain` the engine, which turns away new orders and persists
+//! its state, then `shutdown` it. Front ends serve until `stopped`. Started
+//! with `recover`, the engine picks up from that state.
+
+use crate::{
+    api::{
+        BookSnapshot, CancelOrderRequest, Command, ErrorResponse, NewOrderRequest,
+        NewOrderResponse, OrderReport,
+    },
+    book_stream::{BboSubscription, BookStreams, SnapshotSubscription},
+    journal::Journal,
+    limit_order_book::{
+        order::{Fill, Order},
+        view::BookView,
+    },
+    matching_engine::{
+        engine::{MatchingEngine, TradingPair},
+        rate_limit::RATE_LIMITED,
+        ticker::TickerStats,
+    },
+    metrics::Metrics,
+    recovery::{self, Checkpoint, OnMismatch, RecoveryReport},
+    replay::RecordedCommand,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::{
+    collections::BTreeSet,
+    io,
+    net::SocketAddr,
+    path,
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast, oneshot, watch},
+};
+use tracing::{info, warn};
+
+type Reply<T> = oneshot::Sender<Result<T, String>>;
+
+enum Request {
+    NewOrder(NewOrderRequest, Reply<NewOrderResponse>),
+    CancelOrder(CancelOrderRequest, Reply<OrderReport>),
+    Book(String, usize, Reply<BookSnapshot>),
+    BookView(String, Reply<BookView>),
+    Ticker(String, Reply<TickerStats>),
+    CancelOnDisconnect(String, Reply<Vec<OrderReport>>),
+    Subscribe(String, Reply<SnapshotSubscription>),
+    SubscribeBbo(String, Reply<BboSubscription>),
+    Drain(Reply<()>),
+    Shutdown(Reply<()>),
+}
+
+/// Every rejection of an order while draining starts with this.
+pub const DRAINING: &str = "Engine is draining";
+
+/// Every rejection of an order in cancel-only mode starts with this.
+pub const CANCEL_ONLY: &str = "Engine is in cancel-only mode";
+
+/// Where the engine thread is in its life.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lifecycle {
+    Running,
+    /// Recovery didn't reproduce the recorded state, so new orders are
+    /// rejected until the engine is restarted.
+    CancelOnly,
+    /// New orders are rejected; everything else is served.
+    Draining,
+    /// The thread has exited; requests fail.
+    Stopped,
+}
+
+/// Published by the engine thread after each request that changes a book.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarketUpdate {
+    Trade { pair: String, fill: Fill },
+    Book { pair: String },
+}
+
+/// Updates a subscriber may fall behind by before it starts missing them.
+const UPDATE_CAPACITY: usize = 1024;
+
+/// How often the engine thread expires DAY orders, between requests or
+/// while idle.
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+pub struct EngineHandle {
+    requests: mpsc::Sender<(Instant, Request)>,
+    metrics: Arc<Metrics>,
+    updates: broadcast::Sender<MarketUpdate>,
+    lifecycle: watch::Receiver<Lifecycle>,
+    recovery: Option<Arc<RecoveryReport>>,
+}
+
+impl EngineHandle {
+    /// Builds the engine with `init` on a new thread and serves requests
+    /// against it until it is shut down or every handle is dropped. Orders
+    /// and cancels are journaled first if the engine's configuration names
+    /// a journal.
+    pub fn spawn<F>(init: F) -> Self
+    where
+        F: FnOnce() -> MatchingEngine + Send + 'static,
+    {
+        Self::start(init, false).expect("engine thread failed to start")
+    }
+
+    /// Like `spawn`, but first restores the engine from the checkpoint and
+    /// journal its configuration names; see `recovery`. Fails if they can't
+    /// be read, or don't recover to the recorded state and
+    /// `on_mismatch` says to refuse.
+    pub fn recover<F>(init: F) -> Result<Self, String>
+    where
+        F: FnOnce() -> MatchingEngine + Send + 'static,
+    {
+        Self::start(init, true)
+    }
+
+    fn start<F>(init: F, recover: bool) -> Result<Self, String>
+    where
+        F: FnOnce() -> MatchingEngine + Send + 'static,
+    {
+        let (requests, receiver) = mpsc::channel::<(Instant, Request)>();
+        let (started_sender, started) = mpsc::sync_channel(1);
+        let (updates, _) = broadcast::channel(UPDATE_CAPACITY);
+        let (lifecycle_sender, lifecycle) = watch::channel(Lifecycle::Running);
+        let mut publisher = Publisher {
+            updates: updates.clone(),
+            streams: BookStreams::new(),
+        };
+        thread::spawn(move || {
+            let mut engine = init();
+            let persistence = engine.persistence().clone();
+            let mut report = None;
+            if recover {
+                match recovery::recover(&mut engine, &persistence) {
+                    Ok(recovered) if recovered.is_verified() => {
+                        info!(report = %recovered, "recovered");
+                        report = Some(Arc::new(recovered));
+                    }
+                    Ok(recovered) if persistence.on_mismatch == OnMismatch::CancelOnly => {
+                        warn!(report = %recovered, "recovered state doesn't match; cancel only");
+                        lifecycle_sender.send_replace(Lifecycle::CancelOnly);
+                        report = Some(Arc::new(recovered));
+                    }
+                    Ok(recovered) => {
+                        let _ =
+                            started_sender.send(Err(format!("Refusing to start: {}", recovered)));
+                        return;
+                    }
+                    Err(err) => {
+                        let _ = started_sender.send(Err(err));
+                        return;
+                    }
+                }
+            }
+            let mut journal = persistence.journal_path.as_ref().map(|path| {
+                Journal::open(path, persistence.rotation).unwrap_or_else(|err| {
+                    panic!("can't open the journal {}: {}", path.display(), err)
+                })
+            });
+            let metrics = engine.metrics().clone();
+            let _ = started_sender.send(Ok((metrics.clone(), report)));
+            let mut last_sweep = Instant::now();
+            let shutdown = loop {
+                match receiver.recv_timeout(SWEEP_INTERVAL) {
+                    Ok((sent, request)) => {
+                        metrics.queue_lag.observe(sent.elapsed());
+                        let shutdown = handle_request(
+                            &mut engine,
+                            request,
+                            &mut publisher,
+                            &mut journal,
+                            persistence.snapshot_path.as_deref(),
+                            &lifecycle_sender,
+                        );
+                        if shutdown.is_some() {
+                            break shutdown;
+                        }
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break None,
+                }
+                if last_sweep.elapsed() >= SWEEP_INTERVAL {
+                    last_sweep = Instant::now();
+                    sweep(&mut engine, &mut publisher, &mut journal);
+                    if let Err(err) = record_hash(&engine, &mut journal) {
+                        warn!(%err, "failed to record the state hash");
+                    }
+                }
+            };
+            // Close the journal and the queue before saying so: requests
+            // still queued are dropped, and their callers told.
+            drop(journal);
+            drop(receiver);
+            lifecycle_sender.send_replace(Lifecycle::Stopped);
+            if let Some((reply, result)) = shutdown {
+                let _ = reply.send(result);
+            }
+        });
+        let (metrics, recovery) = started
+            .recv()
+            .map_err(|_| "Engine thread panicked during init".to_string())??;
+        Ok(Self {
+            requests,
+            metrics,
+            updates,
+            lifecycle,
+            recovery,
+        })
+    }
+
+    /// How the engine was recovered, if it was started with `recover`.
+    pub fn recovery(&self) -> Option<&RecoveryReport> {
+        self.recovery.as_deref()
+    }
+
+    pub fn lifecycle(&self) -> Lifecycle {
+        *self.lifecycle.borrow()
+    }
+
+    /// Resolves once the engine thread has exited, e.g. for a server's
+    /// graceful shutdown.
+    pub async fn stopped(&self) {
+        let mut lifecycle = self.lifecycle.clone();
+        // An error means the thread is gone, which is as good as stopped.
+        let _ = lifecycle
+            .wait_for(|lifecycle| *lifecycle == Lifecycle::Stopped)
+            .await;
+    }
+
+    /// Stops accepting new orders, then syncs the journal and writes a
+    /// snapshot, if the configuration names them, once every request sent
+    /// before has been handled. Cancels and queries are still served.
+    /// Draining again persists again.
+    pub async fn drain(&self) -> Result<(), String> {
+        self.call(Request::Drain).await
+    }
+
+    /// Drains the engine and stops its thread. Requests sent after this
+    /// fail. Resolves once the thread has exited.
+    pub async fn shutdown(&self) -> Result<(), String> {
+        self.call(Request::Shutdown).await
+    }
+
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// Trades and book changes from requests handled after this call.
+    pub fn subscribe(&self) -> broadcast::Receiver<MarketUpdate> {
+        self.updates.subscribe()
+    }
+
+    pub async fn new_order(&self, request: NewOrderRequest) -> Result<NewOrderResponse, String> {
+        self.call(|reply| Request::NewOrder(request, reply)).await
+    }
+
+    pub async fn cancel_order(&self, request: CancelOrderRequest) -> Result<OrderReport, String> {
+        self.call(|reply| Request::CancelOrder(request, reply))
+            .await
+    }
+
+    pub async fn book(&self, pair: String, depth: usize) -> Result<BookSnapshot, String> {
+        self.call(|reply| Request::Book(pair, depth, reply)).await
+    }
+
+    /// A full copy of the pair's book to iterate off the engine thread.
+    /// The engine only copies the book again once it has changed.
+    pub async fn book_view(&self, pair: String) -> Result<BookView, String> {
+        self.call(|reply| Request::BookView(pair, reply)).await
+    }
+
+    /// The pair's volume, range and last price over the past 24 hours.
+    pub async fn ticker(&self, pair: String) -> Result<TickerStats, String> {
+        self.call(|reply| Request::Ticker(pair, reply)).await
+    }
+
+    /// Cancels every resting order of `client` after its session dropped.
+    pub async fn cancel_on_disconnect(&self, client: String) -> Result<Vec<OrderReport>, String> {
+        self.call(|reply| Request::CancelOnDisconnect(client, reply))
+            .await
+    }
+
+    /// The pair's full book and then every change to its levels, with no
+    /// gap between the two.
+    pub async fn subscribe_book(&self, pair: String) -> Result<SnapshotSubscription, String> {
+        self.call(|reply| Request::Subscribe(pair, reply)).await
+    }
+
+    /// The pair's best bid and offer, then each change to either price or
+    /// size. Book changes that leave the top alone aren't sent.
+    pub async fn subscribe_bbo(&self, pair: String) -> Result<BboSubscription, String> {
+        self.call(|reply| Request::SubscribeBbo(pair, reply)).await
+    }
+
+    async fn call<T>(&self, request: impl FnOnce(Reply<T>) -> Request) -> Result<T, String> {
+        let (reply, response) = oneshot::channel();
+        self.requests
+            .send((Instant::now(), request(reply)))
+            .map_err(|_| "Engine is not running".to_string())?;
+        response
+            .await
+            .map_err(|_| "Engine dropped the request".to_string())?
+    }
+}
+
+/// Where the engine thread sends what changed.
+struct Publisher {
+    updates: broadcast::Sender<MarketUpdate>,
+    streams: BookStreams,
+}
+
+impl Publisher {
+    fn book_changed(&mut self, engine: &MatchingEngine, pair: &str) {
+        if let Ok(pair) = pair.parse::<TradingPair>() {
+            if let Some(book) = engine.orderbook(&pair) {
+                self.streams.book_changed(&pair.to_string(), book);
+            }
+        }
+        // Sending only fails when nobody is subscribed.
+        let _ = self.updates.send(MarketUpdate::Book {
+            pair: pair.to_string(),
+        });
+    }
+}
+
+/// Journals the cancels the engine made itself, after the fact, so the
+/// journal replays to the same state.
+fn record_cancels(
+    journal: &mut Option<Journal>,
+    time: DateTime<Utc>,
+    cancelled: &[(TradingPair, Order)],
+) {
+    for (pair, order) in cancelled {
+        let command = Command::Cancel(CancelOrderRequest {
+            pair: pair.to_string(),
+            exchange_id: order.exchange_id,
+        });
+        if let Err(err) = record(journal, time, command) {
+            warn!(%err, exchange_id = order.exchange_id, "unjournaled cancel");
+        }
+    }
+}
+
+/// Notes the engine's state hash beside the journal, if anything has been
+/// journaled since it was last noted.
+fn record_hash(engine: &MatchingEngine, journal: &mut Option<Journal>) -> io::Result<()> {
+    match journal {
+        Some(journal) if journal.hashed() != Some(journal.sequence()) => {
+            journal.record_hash(engine.state_hash())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Writes `command` to the journal, if there is one, before it is applied.
+fn record(
+    journal: &mut Option<Journal>,
+    time: DateTime<Utc>,
+    command: Command,
+) -> Result<(), String> {
+    let Some(journal) = journal else {
+        return Ok(());
+    };
+    journal
+        .append(&RecordedCommand { time, command })
+        .map_err(|err| format!("Failed to journal the command: {}", err))
+}
+
+/// Handles `request`, unless it is a shutdown, which is handed back with
+/// the outcome of persisting for the caller to answer once it has stopped.
+fn handle_request(
+    engine: &mut MatchingEngine,
+    request: Request,
+    publisher: &mut Publisher,
+    journal: &mut Option<Journal>,
+    snapshot_path: Option<&path::Path>,
+    lifecycle: &watch::Sender<Lifecycle>,
+) -> Option<(Reply<()>, Result<(), String>)> {
+    let updates = &publisher.updates;
+    // Sending only fails when nobody is subscribed.
+    match request {
+        Request::NewOrder(_, reply) if *lifecycle.borrow() == Lifecycle::CancelOnly => {
+            let _ = reply.send(Err(format!(
+                "{}; recovery didn't match the recorded state",
+                CANCEL_ONLY
+            )));
+        }
+        Request::NewOrder(_, reply) if *lifecycle.borrow() == Lifecycle::Draining => {
+            let _ = reply.send(Err(format!("{}; not accepting orders", DRAINING)));
+        }
+        Request::NewOrder(request, reply) => {
+            let time = Utc::now();
+            let result = record(journal, time, Command::New(request.clone()))
+                .and_then(|()| new_order(engine, request, time));
+            if let Ok(response) = &result {
+                let pair = &response.order.pair;
+                for fill in &response.fills {
+                    let _ = updates.send(MarketUpdate::Trade {
+                        pair: pair.clone(),
+                        fill: fill.clone(),
+                    });
+                }
+                let pair = pair.clone();
+                publisher.book_changed(engine, &pair);
+            }
+            let _ = reply.send(result);
+        }
+        Request::CancelOrder(request, reply) => {
+            let result = record(journal, Utc::now(), Command::Cancel(request.clone()))
+                .and_then(|()| request.pair.parse::<TradingPair>())
+                .and_then(|pair| engine.cancel_order(&pair, request.exchange_id))
+                .map(|order| OrderReport::new(request.pair, &order));
+            if let Ok(report) = &result {
+                publisher.book_changed(engine, &report.pair);
+            }
+            let _ = reply.send(result);
+        }
+        Request::Book(pair, depth, reply) => {
+            let result = pair.parse::<TradingPair>().and_then(|trading_pair| {
+                let book = engine
+                    .orderbook(&trading_pair)
+                    .ok_or_else(|| format!("No orderbook for trading pair: {:?}", pair))?;
+                Ok(BookSnapshot::new(pair, book, depth))
+            });
+            let _ = reply.send(result);
+        }
+        Request::BookView(pair, reply) => {
+            let result = pair.parse::<TradingPair>().and_then(|trading_pair| {
+                engine
+                    .book_view(&trading_pair)
+                    .ok_or_else(|| format!("No orderbook for trading pair: {:?}", pair))
+            });
+            let _ = reply.send(result);
+        }
+        Request::Ticker(pair, reply) => {
+            let result = pair.parse::<TradingPair>().and_then(|trading_pair| {
+                engine
+                    .ticker(&trading_pair, Utc::now())
+                    .ok_or_else(|| format!("No orderbook for trading pair: {:?}", pair))
+            });
+            let _ = reply.send(result);
+        }
+        Request::CancelOnDisconnect(client, reply) => {
+            let cancelled = engine.cancel_on_disconnect(&client);
+            record_cancels(journal, Utc::now(), &cancelled);
+            let pairs: BTreeSet<String> =
+                cancelled.iter().map(|(pair, _)| pair.to_string()).collect();
+            for pair in pairs {
+                publisher.book_changed(engine, &pair);
+            }
+            let reports = cancelled
+                .iter()
+                .map(|(pair, order)| OrderReport::new(pair.to_string(), order))
+                .collect();
+            let _ = reply.send(Ok(reports));
+        }
+        Request::Subscribe(pair, reply) => {
+            let result = pair.parse::<TradingPair>().and_then(|trading_pair| {
+                let book = engine
+                    .orderbook(&trading_pair)
+                    .ok_or_else(|| format!("No orderbook for trading pair: {:?}", pair))?;
+                Ok(publisher.streams.subscribe(&trading_pair.to_string(), book))
+            });
+            let _ = reply.send(result);
+        }
+        Request::Drain(reply) => {
+            lifecycle.send_replace(Lifecycle::Draining);
+            let _ = reply.send(persist(engine, journal, snapshot_path));
+        }
+        Request::Shutdown(reply) => {
+            lifecycle.send_replace(Lifecycle::Draining);
+            return Some((reply, persist(engine, journal, snapshot_path)));
+        }
+        Request::SubscribeBbo(pair, reply) => {
+            let result = pair.parse::<TradingPair>().and_then(|trading_pair| {
+                let book = engine
+                    .orderbook(&trading_pair)
+                    .ok_or_else(|| format!("No orderbook for trading pair: {:?}", pair))?;
+                Ok(publisher
+                    .streams
+                    .subscribe_bbo(&trading_pair.to_string(), book))
+            });
+            let _ = reply.send(result);
+        }
+    }
+    None
+}
+
+/// Makes what the engine has done survive a restart: the journal is synced
+/// to disk and the checkpoint replaced.
+fn persist(
+    engine: &MatchingEngine,
+    journal: &mut Option<Journal>,
+    snapshot_path: Option<&path::Path>,
+) -> Result<(), String> {
+    record_hash(engine, journal)
+        .map_err(|err| format!("Failed to record the state hash: {}", err))?;
+    if let Some(journal) = journal {
+        journal
+            .sync()
+            .map_err(|err| format!("Failed to sync the journal: {}", err))?;
+    }
+    if let Some(path) = snapshot_path {
+        let sequence = journal.as_ref().map_or(0, Journal::sequence);
+        Checkpoint::new(engine, sequence)
+            .write(path)
+            .map_err(|err| format!("Failed to write the snapshot {}: {}", path.display(), err))?;
+    }
+    Ok(())
+}
+
+/// Expires the DAY orders whose session has closed.
+fn sweep(engine: &mut MatchingEngine, publisher: &mut Publisher, journal: &mut Option<Journal>) {
+    let now = Utc::now();
+    let expired = engine.expire_orders(now);
+    record_cancels(journal, now, &expired);
+    let pairs: BTreeSet<String> = expired.iter().map(|(pair, _)| pair.to_string()).collect();
+    for pair in pairs {
+        publisher.book_changed(engine, &pair);
+    }
+}
+
+fn new_order(
+    engine: &mut MatchingEngine,
+    request: NewOrderRequest,
+    time: DateTime<Utc>,
+) -> Result<NewOrderResponse, String> {
+    let (pair, order) = request.to_order(engine.next_exchange_id(), time)?;
+    let (order, fills) = engine.place_limit_order(pair, order)?;
+    Ok(NewOrderResponse {
+        order: OrderReport::new(request.pair, &order),
+        fills,
+    })
+}
+
+#[derive(Deserialize)]
+struct PairQuery {
+    pair: String,
+    #[serde(default = "default_depth")]
+    depth: usize,
+}
+
+fn default_depth() -> usize {
+    10
+}
+
+struct ApiError(String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = if self.0.starts_with(RATE_LIMITED) {
+            StatusCode::TOO_MANY_REQUESTS
+        } else if self.0.starts_with(DRAINING) || self.0.starts_with(CANCEL_ONLY) {
+            StatusCode::SERVICE_UNAVAILABLE
+        } else {
+            StatusCode::BAD_REQUEST
+        };
+        (status, Json(ErrorResponse { error: self.0 })).into_response()
+    }
+}
+
+async fn post_order(
+    State(engine): State<EngineHandle>,
+    Json(request): Json<NewOrderRequest>,
+) -> Result<Json<NewOrderResponse>, ApiError> {
+    engine.new_order(request).await.map(Json).map_err(ApiError)
+}
+
+async fn delete_order(
+    State(engine): State<EngineHandle>,
+    Path(exchange_id): Path<u64>,
+    Query(query): Query<PairQuery>,
+) -> Result<Json<OrderReport>, ApiError> {
+    let request = CancelOrderRequest {
+        pair: query.pair,
+        exchange_id,
+    };
+    engine
+        .cancel_order(request)
+        .await
+        .map(Json)
+        .map_err(ApiError)
+}
+
+async fn get_book(
+    State(engine): State<EngineHandle>,
+    Query(query): Query<PairQuery>,
+) -> Result<Json<BookSnapshot>, ApiError> {
+    engine
+        .book(query.pair, query.depth)
+        .await
+        .map(Json)
+        .map_err(ApiError)
+}
+
+/// The pair is the rest of the path, slash included: `/ticker/BTC/USDT`.
+async fn get_ticker(
+    State(engine): State<EngineHandle>,
+    Path(pair): Path<String>,
+) -> Result<Json<TickerStats>, ApiError> {
+    engine.ticker(pair).await.map(Json).map_err(ApiError)
+}
+
+async fn get_recovery(State(engine): State<EngineHandle>) -> Json<Option<RecoveryReport>> {
+    Json(engine.recovery().cloned())
+}
+
+async fn get_metrics(State(engine): State<EngineHandle>) -> String {
+    engine.metrics().render()
+}
+
+pub fn router(engine: EngineHandle) -> Router {
+    Router::new()
+        .route("/orders", post(post_order))
+        .route("/orders/{exchange_id}", delete(delete_order))
+        .route("/book", get(get_book))
+        .route("/ticker/{*pair}", get(get_ticker))
+        .route("/recovery", get(get_recovery))
+        .with_state(engine)
+}
+
+/// `router` plus a Prometheus scrape endpoint at `/metrics`.
+pub fn router_with_metrics(engine: EngineHandle) -> Router {
+    router(engine.clone()).route("/metrics", get(get_metrics).with_state(engine))
+}
+
+/// Serves until the engine has stopped, finishing the requests in flight.
+pub async fn serve(addr: SocketAddr, engine: EngineHandle, metrics: bool) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    let app = if metrics {
+        router_with_metrics(engine.clone())
+    } else {
+        router(engine.clone())
+    };
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move { engine.stopped().await })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::Client,
+        config::{EngineConfig, MarketConfig, PersistenceConfig},
+        limit_order_book::{
+            checksum::crc32,
+            order::{OrderType, TimeInForce},
+        },
+        matching_engine::rate_limit::RateLimit,
+    };
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::fs;
+
+    fn start_server() -> String {
+        serve_http(EngineHandle::spawn(|| {
+            let mut engine = MatchingEngine::new();
+            engine.add_new_market(TradingPair::new("BTC".to_string(), "USDT".to_string()));
+            engine
+        }))
+    }
+
+    fn serve_http(engine: EngineHandle) -> String {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            runtime
+                .block_on(async { axum::serve(listener, router_with_metrics(engine)).await })
+                .unwrap();
+        });
+        url
+    }
+
+    #[test]
+    fn test_order_entry_round_trip() {
+        let url = start_server();
+        let client = Client::new(url.clone());
+
+        let ask = client
+            .new_order(&NewOrderRequest {
+                pair: "BTC/USDT".to_string(),
+                side: OrderType::Ask,
+                price: dec!(100),
+                quantity: dec!(5),
+                client: "alice".to_string(),
+                short_sale: false,
+                reduce_only: false,
+                client_order_id: None,
+                strategy: None,
+                time_in_force: TimeInForce::Gtc,
+            })
+            .unwrap();
+        assert!(ask.fills.is_empty());
+
+        let bid = client
+            .new_order(&NewOrderRequest {
+                pair: "BTC/USDT".to_string(),
+                side: OrderType::Bid,
+                price: dec!(99),
+                quantity: dec!(2),
+                client: "bob".to_string(),
+                short_sale: false,
+                reduce_only: false,
+                client_order_id: None,
+                strategy: None,
+                time_in_force: TimeInForce::Gtc,
+            })
+            .unwrap();
+
+        let book = client.book("BTC/USDT", 10).unwrap();
+        assert_eq!(book.asks, vec![(dec!(100), dec!(5))]);
+        assert_eq!(book.bids, vec![(dec!(99), dec!(2))]);
+        assert_eq!(book.checksum, crc32(b"99:2:100:5"));
+
+        let cancelled = client
+            .cancel_order("BTC/USDT", bid.order.exchange_id)
+            .unwrap();
+        assert_eq!(cancelled.remaining_quantity, dec!(0));
+        assert!(client.book("BTC/USDT", 10).unwrap().bids.is_empty());
+
+        let error = client
+            .cancel_order("BTC/USDT", bid.order.exchange_id)
+            .unwrap_err();
+        assert!(error.contains("No resting order"));
+        assert!(client.book("ETH/USDT", 10).is_err());
+
+        let metrics = ureq::get(format!("{}/metrics", url))
+            .call()
+            .unwrap()
+            .body_mut()
+            .read_to_string()
+            .unwrap();
+        assert!(metrics.contains("tradebot_orders_accepted_total 2\n"));
+        assert!(metrics.contains("tradebot_queue_lag_seconds_count "));
+        assert!(metrics.contains("tradebot_book_depth{pair=\"BTC/USDT\",side=\"ask\"} 1\n"));
+
+        assert_eq!(client.ticker("BTC/USDT").unwrap().last, None);
+        client
+            .new_order(&NewOrderRequest {
+                pair: "BTC/USDT".to_string(),
+                side: OrderType::Bid,
+                price: dec!(100),
+                quantity: dec!(2),
+                client: "bob".to_string(),
+                short_sale: false,
+                reduce_only: false,
+                client_order_id: None,
+                strategy: None,
+                time_in_force: TimeInForce::Gtc,
+            })
+            .unwrap();
+        let ticker = client.ticker("BTC/USDT").unwrap();
+        assert_eq!(ticker.pair, "BTC/USDT");
+        assert_eq!((ticker.volume, ticker.quote_volume), (dec!(2), dec!(200)));
+        assert_eq!(
+            (ticker.high, ticker.last),
+            (Some(dec!(100)), Some(dec!(100)))
+        );
+        assert_eq!(ticker.price_change_pct, Some(dec!(0)));
+        assert!(client.ticker("ETH/USDT").is_err());
+    }
+
+    fn order(client: &str, side: OrderType, price: Decimal, quantity: Decimal) -> NewOrderRequest {
+        NewOrderRequest {
+            pair: "BTC/USDT".to_string(),
+            side,
+            price,
+            quantity,
+            client: client.to_string(),
+            short_sale: false,
+            reduce_only: false,
+            client_order_id: None,
+            strategy: None,
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    #[test]
+    fn test_http_status_codes() {
+        let engine = EngineHandle::spawn(|| {
+            MatchingEngine::with_config(EngineConfig {
+                markets: vec![MarketConfig::new(TradingPair::new(
+                    "BTC".to_string(),
+                    "USDT".to_string(),
+                ))],
+                rate_limit: Some(RateLimit {
+                    orders_per_second: dec!(0.001),
+                    burst: dec!(1),
+                }),
+                ..EngineConfig::default()
+            })
+        });
+        let url = serve_http(engine.clone());
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .build()
+            .into();
+        let post = |request: &NewOrderRequest| {
+            let mut response = agent
+                .post(format!("{}/orders", url))
+                .send_json(request)
+                .unwrap();
+            let body = response.body_mut().read_json::<ErrorResponse>().ok();
+            (response.status(), body.map(|body| body.error))
+        };
+
+        let bid = order("alice", OrderType::Bid, dec!(99), dec!(1));
+        assert_eq!(post(&bid).0, StatusCode::OK);
+        let (status, error) = post(&bid);
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert!(error.unwrap().starts_with(RATE_LIMITED));
+        let unknown = NewOrderRequest {
+            pair: "BTCUSDT".to_string(),
+            ..order("bob", OrderType::Bid, dec!(99), dec!(1))
+        };
+        assert_eq!(post(&unknown).0, StatusCode::BAD_REQUEST);
+
+        // Started without `recover`, there's no report to show.
+        let recovery = agent
+            .get(format!("{}/recovery", url))
+            .call()
+            .unwrap()
+            .body_mut()
+            .read_to_string()
+            .unwrap();
+        assert_eq!(recovery, "null");
+
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(engine.drain())
+            .unwrap();
+        let (status, error) = post(&order("bob", OrderType::Bid, dec!(99), dec!(1)));
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(error.unwrap().starts_with(DRAINING));
+    }
+
+    #[test]
+    fn test_updates_and_cancel_on_disconnect() {
+        let engine = EngineHandle::spawn(|| {
+            let mut engine = MatchingEngine::new();
+            engine.add_new_market(TradingPair::new("BTC".to_string(), "USDT".to_string()));
+            engine
+        });
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let mut updates = engine.subscribe();
+            for request in [
+                order("alice", OrderType::Ask, dec!(100), dec!(5)),
+                order("alice", OrderType::Ask, dec!(101), dec!(1)),
+                order("bob", OrderType::Bid, dec!(100), dec!(2)),
+            ] {
+                engine.new_order(request).await.unwrap();
+            }
+            let book = || MarketUpdate::Book {
+                pair: "BTC/USDT".to_string(),
+            };
+            assert_eq!(updates.recv().await.unwrap(), book());
+            assert_eq!(updates.recv().await.unwrap(), book());
+            // Trades go out before the book change they made.
+            let MarketUpdate::Trade { pair, fill } = updates.recv().await.unwrap() else {
+                panic!("expected a trade");
+            };
+            assert_eq!((pair.as_str(), fill.quantity), ("BTC/USDT", dec!(2)));
+            assert_eq!(updates.recv().await.unwrap(), book());
+
+            let cancelled = engine
+                .cancel_on_disconnect("alice".to_string())
+                .await
+                .unwrap();
+            let remaining: Vec<Decimal> = cancelled
+                .iter()
+                .map(|report| report.quantity - report.filled_quantity)
+                .collect();
+            assert_eq!(remaining, vec![dec!(3), dec!(1)]);
+            assert_eq!(updates.recv().await.unwrap(), book());
+            let snapshot = engine.book("BTC/USDT".to_string(), 10).await.unwrap();
+            assert!(snapshot.asks.is_empty() && snapshot.bids.is_empty());
+
+            // Nothing left to cancel publishes nothing.
+            assert!(engine
+                .cancel_on_disconnect("alice".to_string())
+                .await
+                .unwrap()
+                .is_empty());
+            assert!(updates.try_recv().is_err());
+        });
+    }
+
+    #[test]
+    fn test_shutdown_and_recover() {
+        let dir = std::env::temp_dir().join(format!("tradebot-drain-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut config = EngineConfig {
+            markets: vec![MarketConfig::new(TradingPair::new(
+                "BTC".to_string(),
+                "USDT".to_string(),
+            ))],
+            persistence: PersistenceConfig {
+                journal_path: Some(dir.join("journal.log")),
+                snapshot_path: Some(dir.join("snapshot.json")),
+                ..PersistenceConfig::default()
+            },
+            ..EngineConfig::default()
+        };
+        let start = |config: &EngineConfig| {
+            let config = config.clone();
+            EngineHandle::recover(move || MatchingEngine::with_config(config))
+        };
+        let engine = start(&config).unwrap();
+        assert_eq!(engine.recovery().unwrap().checkpoint_sequence, None);
+        let order = |side, price| NewOrderRequest {
+            pair: "BTC/USDT".to_string(),
+            side,
+            price,
+            quantity: dec!(1),
+            client: "alice".to_string(),
+            short_sale: false,
+            reduce_only: false,
+            client_order_id: None,
+            strategy: None,
+            time_in_force: TimeInForce::Gtc,
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let ask = engine
+                .new_order(order(OrderType::Ask, dec!(100)))
+                .await
+                .unwrap();
+            engine
+                .new_order(order(OrderType::Ask, dec!(101)))
+                .await
+                .unwrap();
+            assert_eq!(engine.lifecycle(), Lifecycle::Running);
+
+            engine.drain().await.unwrap();
+            assert_eq!(engine.lifecycle(), Lifecycle::Draining);
+            let error = engine
+                .new_order(order(OrderType::Bid, dec!(100)))
+                .await
+                .unwrap_err();
+            assert!(error.starts_with(DRAINING));
+            let checkpoint = Checkpoint::read(&dir.join("snapshot.json"))
+                .unwrap()
+                .unwrap();
+            assert_eq!(checkpoint.snapshot.markets[0].orders.len(), 2);
+
+            // Cancels still go through, and shutting down persists them.
+            engine
+                .cancel_order(CancelOrderRequest {
+                    pair: "BTC/USDT".to_string(),
+                    exchange_id: ask.order.exchange_id,
+                })
+                .await
+                .unwrap();
+            engine.shutdown().await.unwrap();
+            engine.stopped().await;
+            assert_eq!(engine.lifecycle(), Lifecycle::Stopped);
+            assert!(engine.book("BTC/USDT".to_string(), 10).await.is_err());
+        });
+        let mut checkpoint = Checkpoint::read(&dir.join("snapshot.json"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(checkpoint.snapshot.markets[0].orders.len(), 1);
+        // Two orders and a cancel; the rejected order was never journaled.
+        assert_eq!(checkpoint.sequence, 3);
+        let journal = fs::read_to_string(dir.join("journal.log")).unwrap();
+        assert_eq!(journal.lines().count(), 3);
+
+        // Restarted, the engine carries on from the checkpoint.
+        let engine = start(&config).unwrap();
+        let report = engine.recovery().unwrap();
+        assert!(report.is_verified());
+        assert_eq!(report.checkpoint_sequence, Some(3));
+        assert_eq!(report.replayed, 0);
+        let book = runtime
+            .block_on(engine.book("BTC/USDT".to_string(), 10))
+            .unwrap();
+        assert_eq!(book.asks, vec![(dec!(101), dec!(1))]);
+        drop(engine);
+
+        // A checkpoint that doesn't restore to its hash stops the engine
+        // from starting, or starts it taking only cancels.
+        checkpoint.state_hash ^= 1;
+        checkpoint.write(&dir.join("snapshot.json")).unwrap();
+        let error = start(&config).err().unwrap();
+        assert!(error.starts_with("Refusing to start"));
+        config.persistence.on_mismatch = OnMismatch::CancelOnly;
+        let engine = start(&config).unwrap();
+        assert_eq!(engine.lifecycle(), Lifecycle::CancelOnly);
+        assert!(!engine.recovery().unwrap().is_verified());
+        let error = runtime
+            .block_on(engine.new_order(order(OrderType::Bid, dec!(90))))
+            .unwrap_err();
+        assert!(error.starts_with(CANCEL_ONLY));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
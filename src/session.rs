@@ -0,0 +1,655 @@
+//! Order entry over long-lived TCP sessions, the way a venue's native
+//! gateway works.
+//!
+//! Each line is one JSON message, numbered by its sender from 1:
+//!
+//! ```text
+//! > {"seq":1,"type":"logon","token":"secret"}
+//! < {"seq":1,"type":"logon_accepted","client":"alice","heartbeat_interval_ms":30000}
+//! > {"seq":2,"type":"new_order","pair":"BTC/USDT","side":"Bid","price":"99","quantity":"1"}
+//! < {"seq":2,"type":"order_accepted","order":{...},"fills":[]}
+//! ```
+//!
+//! The first message must be a logon with a token the server knows; orders
+//! then go in under the token's client, whatever they say. A message
+//! numbered past the one expected is dropped and answered with a resend
+//! request for the gap; one numbered below it ends the session unless it is
+//! marked as a possible duplicate. Either side may ask for messages again
+//! with a resend request, and the server resends its own marked as possible
+//! duplicates. A side that hears nothing for a heartbeat interval sends a
+//! heartbeat; the server logs out a client it hasn't heard from in two.
+
+use crate::{
+    api::{CancelOrderRequest, NewOrderRequest, NewOrderResponse, OrderReport},
+    server::EngineHandle,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream,
+    },
+    time::timeout,
+};
+use tracing::{debug, info, warn};
+
+/// Sent messages a session keeps for resend requests.
+const RESEND_CAPACITY: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Logon {
+        token: String,
+        /// Overrides the server's `SessionConfig::cancel_on_disconnect`.
+        #[serde(default)]
+        cancel_on_disconnect: Option<bool>,
+    },
+    NewOrder(NewOrderRequest),
+    CancelOrder(CancelOrderRequest),
+    Heartbeat,
+    ResendRequest {
+        from: u64,
+        to: u64,
+    },
+    Logout,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    LogonAccepted {
+        client: String,
+        heartbeat_interval_ms: u64,
+        cancel_on_disconnect: bool,
+    },
+    OrderAccepted(NewOrderResponse),
+    OrderCancelled(OrderReport),
+    /// `ref_seq` is the sequence number of the message turned down.
+    Reject {
+        ref_seq: u64,
+        reason: String,
+    },
+    Heartbeat,
+    ResendRequest {
+        from: u64,
+        to: u64,
+    },
+    Logout {
+        reason: String,
+    },
+}
+
+/// A message on the wire with its sequence number.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub seq: u64,
+    /// Set on messages sent again in answer to a resend request.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub possible_duplicate: bool,
+    #[serde(flatten)]
+    pub message: T,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionConfig {
+    /// Client names by authentication token.
+    pub tokens: HashMap<String, String>,
+    pub heartbeat_interval: Duration,
+    /// Whether a client's resting orders are cancelled when its session
+    /// drops without logging out, unless it says otherwise at logon.
+    pub cancel_on_disconnect: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            tokens: HashMap::new(),
+            heartbeat_interval: Duration::from_secs(30),
+            cancel_on_disconnect: false,
+        }
+    }
+}
+
+/// Accepts sessions on `listener` until it fails.
+pub async fn serve(
+    listener: TcpListener,
+    engine: EngineHandle,
+    config: Arc<SessionConfig>,
+) -> io::Result<()> {
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let engine = engine.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            match handle(stream, &engine, &config).await {
+                Ok(()) => debug!(%addr, "session closed"),
+                Err(err) => warn!(%addr, %err, "session failed"),
+            }
+        });
+    }
+}
+
+struct Session {
+    writer: OwnedWriteHalf,
+    client: Option<String>,
+    /// Sequence number expected on the next client message.
+    inbound: u64,
+    /// Sequence number of the last message sent.
+    outbound: u64,
+    sent: VecDeque<Envelope<ServerMessage>>,
+    cancel_on_disconnect: bool,
+    /// Set once the client has logged out, rather than dropped.
+    logged_out: bool,
+}
+
+impl Session {
+    async fn send(&mut self, message: ServerMessage) -> io::Result<()> {
+        self.outbound += 1;
+        let envelope = Envelope {
+            seq: self.outbound,
+            possible_duplicate: false,
+            message,
+        };
+        self.write(&envelope).await?;
+        if self.sent.len() == RESEND_CAPACITY {
+            self.sent.pop_front();
+        }
+        self.sent.push_back(envelope);
+        Ok(())
+    }
+
+    async fn resend(&mut self, ref_seq: u64, from: u64, to: u64) -> io::Result<()> {
+        let first = self.sent.front().map_or(self.outbound + 1, |sent| sent.seq);
+        if from < first || to > self.outbound || from > to {
+            let reason = format!("Messages {} to {} are not available", from, to);
+            return self.send(ServerMessage::Reject { ref_seq, reason }).await;
+        }
+        let resent: Vec<_> = self
+            .sent
+            .range((from - first) as usize..=(to - first) as usize)
+            .cloned()
+            .collect();
+        for mut envelope in resent {
+            envelope.possible_duplicate = true;
+            self.write(&envelope).await?;
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, envelope: &Envelope<ServerMessage>) -> io::Result<()> {
+        let mut line = serde_json::to_vec(envelope).map_err(io::Error::other)?;
+        line.push(b'\n');
+        self.writer.write_all(&line).await
+    }
+}
+
+async fn handle(
+    stream: TcpStream,
+    engine: &EngineHandle,
+    config: &SessionConfig,
+) -> io::Result<()> {
+    let (reader, writer) = stream.into_split();
+    let mut session = Session {
+        writer,
+        client: None,
+        inbound: 1,
+        outbound: 0,
+        sent: VecDeque::new(),
+        cancel_on_disconnect: config.cancel_on_disconnect,
+        logged_out: false,
+    };
+    let result = run(&mut session, reader, engine, config).await;
+    if let Some(client) = &session.client {
+        if session.cancel_on_disconnect && !session.logged_out {
+            let cancelled = engine
+                .cancel_on_disconnect(client.clone())
+                .await
+                .map_err(io::Error::other)?;
+            info!(%client, cancelled = cancelled.len(), "cancelled on disconnect");
+        }
+    }
+    result
+}
+
+async fn run(
+    session: &mut Session,
+    reader: OwnedReadHalf,
+    engine: &EngineHandle,
+    config: &SessionConfig,
+) -> io::Result<()> {
+    let mut lines = BufReader::new(reader).lines();
+    let mut silent_intervals = 0;
+
+    loop {
+        let line = match timeout(config.heartbeat_interval, lines.next_line()).await {
+            Err(_) => {
+                silent_intervals += 1;
+                if silent_intervals >= 2 {
+                    let reason = "Heartbeat timeout".to_string();
+                    return session.send(ServerMessage::Logout { reason }).await;
+                }
+                session.send(ServerMessage::Heartbeat).await?;
+                continue;
+            }
+            Ok(line) => match line? {
+                Some(line) => line,
+                None => return Ok(()),
+            },
+        };
+        silent_intervals = 0;
+
+        let envelope = match serde_json::from_str::<Envelope<ClientMessage>>(&line) {
+            Ok(envelope) => envelope,
+            Err(err) => {
+                let reason = format!("Invalid message: {}", err);
+                session
+                    .send(ServerMessage::Reject { ref_seq: 0, reason })
+                    .await?;
+                continue;
+            }
+        };
+        let seq = envelope.seq;
+        if seq > session.inbound {
+            let (from, to) = (session.inbound, seq - 1);
+            session
+                .send(ServerMessage::ResendRequest { from, to })
+                .await?;
+            continue;
+        }
+        if seq < session.inbound {
+            if envelope.possible_duplicate {
+                continue;
+            }
+            let reason = format!(
+                "Sequence number {} is below the expected {}",
+                seq, session.inbound
+            );
+            return session.send(ServerMessage::Logout { reason }).await;
+        }
+        session.inbound += 1;
+
+        let message = match (&session.client, envelope.message) {
+            (
+                None,
+                ClientMessage::Logon {
+                    token,
+                    cancel_on_disconnect,
+                },
+            ) => {
+                let Some(client) = config.tokens.get(&token).cloned() else {
+                    let reason = "Invalid token".to_string();
+                    return session.send(ServerMessage::Logout { reason }).await;
+                };
+                info!(%client, "session logged on");
+                session.client = Some(client.clone());
+                if let Some(cancel_on_disconnect) = cancel_on_disconnect {
+                    session.cancel_on_disconnect = cancel_on_disconnect;
+                }
+                let heartbeat_interval_ms = config.heartbeat_interval.as_millis() as u64;
+                session
+                    .send(ServerMessage::LogonAccepted {
+                        client,
+                        heartbeat_interval_ms,
+                        cancel_on_disconnect: session.cancel_on_disconnect,
+                    })
+                    .await?;
+                continue;
+            }
+            (None, _) => {
+                let reason = "Not logged on".to_string();
+                return session.send(ServerMessage::Logout { reason }).await;
+            }
+            (Some(client), message) => (client.clone(), message),
+        };
+
+        let response = match message {
+            (_, ClientMessage::Logon { .. }) => Err("Already logged on".to_string()),
+            (client, ClientMessage::NewOrder(mut request)) => {
+                request.client = client;
+                engine
+                    .new_order(request)
+                    .await
+                    .map(ServerMessage::OrderAccepted)
+            }
+            (_, ClientMessage::CancelOrder(request)) => engine
+                .cancel_order(request)
+                .await
+                .map(ServerMessage::OrderCancelled),
+            (_, ClientMessage::Heartbeat) => continue,
+            (_, ClientMessage::ResendRequest { from, to }) => {
+                session.resend(seq, from, to).await?;
+                continue;
+            }
+            (_, ClientMessage::Logout) => {
+                session.logged_out = true;
+                let reason = "Logout".to_string();
+                return session.send(ServerMessage::Logout { reason }).await;
+            }
+        };
+        let message = response.unwrap_or_else(|reason| ServerMessage::Reject {
+            ref_seq: seq,
+            reason,
+        });
+        session.send(message).await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        limit_order_book::order::{OrderType, TimeInForce},
+        matching_engine::engine::{MatchingEngine, TradingPair},
+    };
+    use rust_decimal_macros::dec;
+    use std::io::{BufRead, BufReader as StdBufReader, Write};
+
+    struct TestClient {
+        reader: StdBufReader<std::net::TcpStream>,
+        writer: std::net::TcpStream,
+    }
+
+    impl TestClient {
+        fn send(&mut self, seq: u64, message: ClientMessage) {
+            let envelope = Envelope {
+                seq,
+                possible_duplicate: false,
+                message,
+            };
+            self.send_line(&serde_json::to_string(&envelope).unwrap());
+        }
+
+        fn send_line(&mut self, line: &str) {
+            self.writer.write_all(line.as_bytes()).unwrap();
+            self.writer.write_all(b"\n").unwrap();
+        }
+
+        fn receive(&mut self) -> Envelope<ServerMessage> {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+    }
+
+    struct TestServer {
+        runtime: tokio::runtime::Runtime,
+        engine: EngineHandle,
+        addr: std::net::SocketAddr,
+    }
+
+    impl TestServer {
+        fn start(heartbeat_interval: Duration, cancel_on_disconnect: bool) -> Self {
+            let engine = EngineHandle::spawn(|| {
+                let mut engine = MatchingEngine::new();
+                engine.add_new_market(TradingPair::new("BTC".to_string(), "USDT".to_string()));
+                engine
+            });
+            let config = Arc::new(SessionConfig {
+                tokens: HashMap::from([("secret".to_string(), "alice".to_string())]),
+                heartbeat_interval,
+                cancel_on_disconnect,
+            });
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+            let addr = listener.local_addr().unwrap();
+            runtime.spawn(serve(listener, engine.clone(), config));
+            Self {
+                runtime,
+                engine,
+                addr,
+            }
+        }
+
+        fn connect(&self) -> TestClient {
+            let writer = std::net::TcpStream::connect(self.addr).unwrap();
+            let reader = StdBufReader::new(writer.try_clone().unwrap());
+            TestClient { reader, writer }
+        }
+
+        /// Connects and logs on as alice.
+        fn logon(&self, cancel_on_disconnect: Option<bool>) -> TestClient {
+            let mut client = self.connect();
+            client.send(
+                1,
+                ClientMessage::Logon {
+                    token: "secret".to_string(),
+                    cancel_on_disconnect,
+                },
+            );
+            client.receive();
+            client
+        }
+
+        fn resting_bids(&self) -> usize {
+            let book = self
+                .runtime
+                .block_on(self.engine.book("BTC/USDT".to_string(), 10))
+                .unwrap();
+            book.bids.len()
+        }
+    }
+
+    fn bid(client: &str) -> NewOrderRequest {
+        NewOrderRequest {
+            pair: "BTC/USDT".to_string(),
+            side: OrderType::Bid,
+            price: dec!(99),
+            quantity: dec!(1),
+            client: client.to_string(),
+            short_sale: false,
+            reduce_only: false,
+            client_order_id: None,
+            strategy: None,
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    #[test]
+    fn test_session() {
+        let server = TestServer::start(Duration::from_secs(30), false);
+        let mut client = server.connect();
+        client.send(1, ClientMessage::NewOrder(bid("alice")));
+        assert!(matches!(
+            client.receive().message,
+            ServerMessage::Logout { .. }
+        ));
+
+        let mut client = server.connect();
+        client.send(
+            1,
+            ClientMessage::Logon {
+                token: "secret".to_string(),
+                cancel_on_disconnect: None,
+            },
+        );
+        let logon = client.receive();
+        assert_eq!(logon.seq, 1);
+        assert!(matches!(
+            logon.message,
+            ServerMessage::LogonAccepted { ref client, .. } if client == "alice"
+        ));
+
+        client.send(2, ClientMessage::NewOrder(bid("mallory")));
+        let accepted = client.receive();
+        let ServerMessage::OrderAccepted(response) = &accepted.message else {
+            panic!("expected an acceptance, got {:?}", accepted);
+        };
+        assert_eq!(response.order.remaining_quantity, dec!(1));
+
+        // A gap is answered with a resend request and the message dropped.
+        client.send(5, ClientMessage::Heartbeat);
+        assert_eq!(
+            client.receive().message,
+            ServerMessage::ResendRequest { from: 3, to: 4 }
+        );
+
+        client.send(3, ClientMessage::ResendRequest { from: 1, to: 2 });
+        let resent = (client.receive(), client.receive());
+        assert_eq!((resent.0.seq, resent.1.seq), (1, 2));
+        assert!(resent.0.possible_duplicate && resent.1.possible_duplicate);
+        assert_eq!(resent.1.message, accepted.message);
+
+        client.send(
+            4,
+            ClientMessage::CancelOrder(CancelOrderRequest {
+                pair: "BTC/USDT".to_string(),
+                exchange_id: 999,
+            }),
+        );
+        let reject = client.receive();
+        assert_eq!(reject.seq, 4);
+        assert!(matches!(
+            reject.message,
+            ServerMessage::Reject { ref_seq: 4, .. }
+        ));
+
+        client.send(5, ClientMessage::Logout);
+        assert!(matches!(
+            client.receive().message,
+            ServerMessage::Logout { .. }
+        ));
+    }
+
+    #[test]
+    fn test_session_heartbeats() {
+        let server = TestServer::start(Duration::from_millis(50), false);
+        let mut client = server.logon(None);
+        assert_eq!(client.receive().message, ServerMessage::Heartbeat);
+        assert_eq!(
+            client.receive().message,
+            ServerMessage::Logout {
+                reason: "Heartbeat timeout".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_cancel_on_disconnect() {
+        let server = TestServer::start(Duration::from_secs(30), true);
+        let mut client = server.logon(None);
+        client.send(2, ClientMessage::NewOrder(bid("alice")));
+        client.receive();
+        let mut kept = server.logon(Some(false));
+        kept.send(2, ClientMessage::NewOrder(bid("alice")));
+        kept.receive();
+        assert_eq!(server.resting_bids(), 1);
+
+        drop(client);
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while server.resting_bids() == 1 {
+            assert!(std::time::Instant::now() < deadline, "orders not cancelled");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // Both orders were alice's, so the drop took the other session's too.
+        assert_eq!(server.resting_bids(), 0);
+
+        let mut client = server.logon(None);
+        client.send(2, ClientMessage::NewOrder(bid("alice")));
+        client.receive();
+        client.send(3, ClientMessage::Logout);
+        client.receive();
+        drop(client);
+        drop(kept);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(server.resting_bids(), 1);
+    }
+
+    #[test]
+    fn test_rejects_without_ending_the_session() {
+        let server = TestServer::start(Duration::from_secs(30), false);
+        let mut client = server.connect();
+        client.send(
+            1,
+            ClientMessage::Logon {
+                token: "wrong".to_string(),
+                cancel_on_disconnect: None,
+            },
+        );
+        assert_eq!(
+            client.receive().message,
+            ServerMessage::Logout {
+                reason: "Invalid token".to_string()
+            }
+        );
+
+        let mut client = server.logon(Some(true));
+        client.send_line("not json");
+        let reject = client.receive();
+        assert_eq!(reject.seq, 2);
+        let ServerMessage::Reject { ref_seq, reason } = reject.message else {
+            panic!("expected a reject, got {:?}", reject);
+        };
+        assert_eq!(ref_seq, 0);
+        assert!(reason.starts_with("Invalid message"));
+        client.send(
+            2,
+            ClientMessage::Logon {
+                token: "secret".to_string(),
+                cancel_on_disconnect: None,
+            },
+        );
+        assert_eq!(
+            client.receive().message,
+            ServerMessage::Reject {
+                ref_seq: 2,
+                reason: "Already logged on".to_string()
+            }
+        );
+        client.send(3, ClientMessage::ResendRequest { from: 2, to: 9 });
+        assert_eq!(
+            client.receive().message,
+            ServerMessage::Reject {
+                ref_seq: 3,
+                reason: "Messages 2 to 9 are not available".to_string()
+            }
+        );
+        client.send(4, ClientMessage::NewOrder(bid("alice")));
+        assert!(matches!(
+            client.receive().message,
+            ServerMessage::OrderAccepted(_)
+        ));
+    }
+
+    #[test]
+    fn test_duplicates_and_low_sequence_numbers() {
+        let server = TestServer::start(Duration::from_secs(30), false);
+        let mut client = server.logon(None);
+        client.send(2, ClientMessage::NewOrder(bid("alice")));
+        client.receive();
+
+        // A marked duplicate is skipped without a reply or a second order.
+        let duplicate = Envelope {
+            seq: 2,
+            possible_duplicate: true,
+            message: ClientMessage::NewOrder(bid("alice")),
+        };
+        let line = serde_json::to_string(&duplicate).unwrap();
+        assert!(line.contains(r#""possible_duplicate":true"#));
+        client.send_line(&line);
+        client.send(3, ClientMessage::ResendRequest { from: 2, to: 2 });
+        let resent = client.receive();
+        assert!(resent.possible_duplicate);
+        assert_eq!(resent.seq, 2);
+        let book = server
+            .runtime
+            .block_on(server.engine.book("BTC/USDT".to_string(), 10))
+            .unwrap();
+        assert_eq!(book.bids, vec![(dec!(99), dec!(1))]);
+
+        client.send(3, ClientMessage::Heartbeat);
+        assert_eq!(
+            client.receive().message,
+            ServerMessage::Logout {
+                reason: "Sequence number 3 is below the expected 4".to_string()
+            }
+        );
+    }
+}
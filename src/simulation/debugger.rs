@@ -0,0 +1,294 @@
+//! Stepping a simulation backward and forward, to find out why a strategy
+//! did what it did.
+//!
+//! Agents can't be copied, so going back rebuilds the simulation from its
+//! factory and runs it forward again to the target step. That only finds
+//! the same state if the simulation is deterministic, i.e. every agent is
+//! seeded and acts on the `now` it is given rather than the wall clock.
+//! Checkpoints are the stops for `back` and `forward`, and record the
+//! engine's resting orders the first time through; re-runs are checked
+//! against them so a simulation that isn't deterministic is caught rather
+//! than debugged.
+
+use super::Simulation;
+use crate::matching_engine::snapshot::EngineSnapshot;
+use chrono::{DateTime, Duration, Utc};
+
+/// The simulation as it stood after `step` steps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub step: usize,
+    /// When the next step runs.
+    pub time: DateTime<Utc>,
+    pub snapshot: EngineSnapshot,
+}
+
+pub struct Debugger<F> {
+    new_simulation: F,
+    start: DateTime<Utc>,
+    step: Duration,
+    every: usize,
+    simulation: Simulation,
+    /// Steps run on `simulation`.
+    position: usize,
+    /// Sorted by step.
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl<F: Fn() -> Simulation> Debugger<F> {
+    /// Steps run at `start`, `start + step`, ...; a checkpoint is taken
+    /// every `every` of them, starting before the first.
+    pub fn new(
+        new_simulation: F,
+        start: DateTime<Utc>,
+        step: Duration,
+        every: usize,
+    ) -> Result<Self, String> {
+        if step <= Duration::zero() {
+            return Err(format!("Invalid simulation step: {}", step));
+        }
+        if every == 0 {
+            return Err("Checkpoints need at least one step between them".to_string());
+        }
+        let simulation = new_simulation();
+        let mut debugger = Self {
+            new_simulation,
+            start,
+            step,
+            every,
+            simulation,
+            position: 0,
+            checkpoints: Vec::new(),
+        };
+        debugger.checkpoint()?;
+        Ok(debugger)
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    /// The number of steps run so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// When the next step runs.
+    pub fn now(&self) -> DateTime<Utc> {
+        self.start + self.step * self.position as i32
+    }
+
+    /// The checkpoints passed so far, oldest first.
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    /// Runs the next step.
+    pub fn step_forward(&mut self) -> Result<(), String> {
+        self.simulation.step(self.now())?;
+        self.position += 1;
+        if self.position.is_multiple_of(self.every) {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    /// Runs to the next checkpoint and returns its step.
+    pub fn forward(&mut self) -> Result<usize, String> {
+        let target = (self.position / self.every + 1) * self.every;
+        self.seek(target)?;
+        Ok(target)
+    }
+
+    /// Goes back to the last checkpoint before the current step and returns
+    /// its step. At the start there is nowhere to go.
+    pub fn back(&mut self) -> Result<usize, String> {
+        if self.position == 0 {
+            return Err("Already at the start of the simulation".to_string());
+        }
+        let target = (self.position - 1) / self.every * self.every;
+        self.seek(target)?;
+        Ok(target)
+    }
+
+    /// Goes to the state after `step` steps, re-running from the start if
+    /// it is behind the current one.
+    pub fn seek(&mut self, step: usize) -> Result<(), String> {
+        if step < self.position {
+            self.simulation = (self.new_simulation)();
+            self.position = 0;
+        }
+        while self.position < step {
+            self.step_forward()?;
+        }
+        Ok(())
+    }
+
+    /// Steps forward until `stop` holds, for at most `limit` steps, and
+    /// returns the step it first held after. Use it to find the step where
+    /// an order appeared, then `back` to watch it happen.
+    pub fn run_until(
+        &mut self,
+        limit: usize,
+        mut stop: impl FnMut(&Simulation) -> bool,
+    ) -> Result<Option<usize>, String> {
+        for _ in 0..limit {
+            self.step_forward()?;
+            if stop(&self.simulation) {
+                return Ok(Some(self.position));
+            }
+        }
+        Ok(None)
+    }
+
+    fn checkpoint(&mut self) -> Result<(), String> {
+        let checkpoint = Checkpoint {
+            step: self.position,
+            time: self.now(),
+            snapshot: self.simulation.engine.snapshot(),
+        };
+        let index = self.position / self.every;
+        match self.checkpoints.get(index) {
+            Some(recorded) if *recorded != checkpoint => Err(format!(
+                "Simulation diverged from its first run by step {}",
+                self.position
+            )),
+            Some(_) => Ok(()),
+            None => {
+                self.checkpoints.push(checkpoint);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        limit_order_book::order::{Order, OrderType},
+        matching_engine::engine::{MatchingEngine, TradingPair},
+        simulation::{
+            order_flow::{OrderFlowConfig, OrderFlowGenerator, SizeDistribution},
+            Agent,
+        },
+    };
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+    use std::{cell::Cell, rc::Rc};
+
+    fn pair() -> TradingPair {
+        TradingPair::new("BTC".to_string(), "USDT".to_string())
+    }
+
+    fn simulation(start: DateTime<Utc>) -> Simulation {
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair());
+        let mut simulation = Simulation::new(engine);
+        simulation.add_agent(
+            OrderFlowGenerator::new(
+                OrderFlowConfig {
+                    pair: pair(),
+                    client: "flow".to_string(),
+                    seed: 5,
+                    limit_rate: dec!(20),
+                    market_rate: dec!(2),
+                    cancel_rate: dec!(8),
+                    size: SizeDistribution::Fixed(dec!(1)),
+                    lot_size: dec!(1),
+                    tick_size: dec!(0.5),
+                    max_offset_ticks: 5,
+                    crossing_probability: 0.1,
+                    initial_mid: dec!(100),
+                },
+                start,
+            )
+            .unwrap(),
+        );
+        simulation
+    }
+
+    /// Places one bid per run, at a price that differs between runs.
+    struct Unseeded(Rc<Cell<u32>>);
+
+    impl Agent for Unseeded {
+        fn on_tick(
+            &mut self,
+            engine: &mut MatchingEngine,
+            now: DateTime<Utc>,
+        ) -> Result<(), String> {
+            if engine.orderbook(&pair()).unwrap().orders.is_empty() {
+                self.0.set(self.0.get() + 1);
+                let id = engine.next_exchange_id();
+                let price = dec!(90) + rust_decimal::Decimal::from(self.0.get());
+                let bid = Order::new(
+                    pair().to_string(),
+                    id,
+                    OrderType::Bid,
+                    dec!(1),
+                    price,
+                    now,
+                    now,
+                );
+                engine.place_limit_order(pair(), bid)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_step_back_and_forth() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+        let step = Duration::milliseconds(100);
+        let mut debugger = Debugger::new(|| simulation(start), start, step, 10).unwrap();
+
+        // Find the step where the flow's tenth order was placed.
+        let found = debugger
+            .run_until(1000, |simulation| {
+                simulation
+                    .engine
+                    .orderbook(&pair())
+                    .unwrap()
+                    .orders
+                    .contains_key(&10)
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(debugger.position(), found);
+        let orders = debugger.simulation().engine.snapshot();
+
+        // Back to the checkpoint before it, where the order isn't there yet,
+        // and step through to it again.
+        let checkpoint = debugger.back().unwrap();
+        assert_eq!(checkpoint, (found - 1) / 10 * 10);
+        assert_eq!(debugger.now(), start + step * checkpoint as i32);
+        while debugger.position() < found {
+            debugger.step_forward().unwrap();
+        }
+        assert_eq!(debugger.simulation().engine.snapshot(), orders);
+
+        let next = debugger.forward().unwrap();
+        assert_eq!(next, (found / 10 + 1) * 10);
+        assert_eq!(debugger.checkpoints().len(), next / 10 + 1);
+        assert_eq!(debugger.back().unwrap(), next - 10);
+        assert!(debugger.seek(0).is_ok());
+        assert!(debugger.back().is_err());
+    }
+
+    #[test]
+    fn test_catches_divergence() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+        let runs = Rc::new(Cell::new(0));
+        let new_simulation = || {
+            let mut engine = MatchingEngine::new();
+            engine.add_new_market(pair());
+            let mut simulation = Simulation::new(engine);
+            simulation.add_agent(Unseeded(Rc::clone(&runs)));
+            simulation
+        };
+        let mut debugger = Debugger::new(new_simulation, start, Duration::seconds(1), 2).unwrap();
+        assert_eq!(debugger.forward().unwrap(), 2);
+        debugger.seek(0).unwrap();
+        assert!(debugger.forward().is_err());
+    }
+}
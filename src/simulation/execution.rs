@@ -0,0 +1,348 @@
+use super::Agent;
+use crate::{
+    limit_order_book::order::{Order, OrderType},
+    matching_engine::engine::{MatchingEngine, TradingPair},
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::*;
+
+/// How a parent order's quantity is spread over its slices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// Equal quantity per slice.
+    Twap,
+    /// Quantity per slice proportional to the expected volume in it, one
+    /// weight per slice.
+    Vwap { volume_profile: Vec<Decimal> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParentOrder {
+    pub client: String,
+    pub pair: TradingPair,
+    pub side: OrderType,
+    pub quantity: Decimal,
+    /// Worst price any child may trade at.
+    pub limit_price: Decimal,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub slices: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionReport {
+    pub filled_quantity: Decimal,
+    /// Quantity the schedule could not get done before `end`.
+    pub remaining_quantity: Decimal,
+    pub average_price: Option<Decimal>,
+    /// Book mid when the first slice went out.
+    pub arrival_price: Option<Decimal>,
+    pub child_orders: usize,
+    pub finished_at: DateTime<Utc>,
+}
+
+/// Works a parent order as a series of immediate-or-cancel children, one
+/// at the start of each slice of `[start, end)`. Each child asks for the
+/// schedule's cumulative target minus what has filled so far, so a slice
+/// that could not fill is caught up by the next one. The last slice goes
+/// for whatever is left.
+pub struct ExecutionAlgo {
+    parent: ParentOrder,
+    /// Cumulative share of the parent to have done by the end of each slice.
+    targets: Vec<Decimal>,
+    next_slice: usize,
+    filled: Decimal,
+    notional: Decimal,
+    arrival_price: Option<Decimal>,
+    child_orders: usize,
+    report: Option<CompletionReport>,
+}
+
+impl ExecutionAlgo {
+    pub fn new(parent: ParentOrder, schedule: Schedule) -> Result<Self, String> {
+        if parent.slices == 0 || parent.end <= parent.start {
+            return Err("An execution needs at least one slice and end after start".to_string());
+        }
+        if parent.quantity <= Decimal::zero() {
+            return Err(format!("Invalid quantity: {}", parent.quantity));
+        }
+        let weights = match schedule {
+            Schedule::Twap => vec![Decimal::ONE; parent.slices],
+            Schedule::Vwap { volume_profile } => volume_profile,
+        };
+        if weights.len() != parent.slices || weights.iter().any(|w| w.is_sign_negative()) {
+            return Err(format!(
+                "Volume profile needs {} non-negative weights",
+                parent.slices
+            ));
+        }
+        let total: Decimal = weights.iter().sum();
+        if total.is_zero() {
+            return Err("Volume profile is all zero".to_string());
+        }
+        let mut cumulative = Decimal::zero();
+        let targets = weights
+            .iter()
+            .map(|weight| {
+                cumulative += weight;
+                cumulative / total
+            })
+            .collect();
+
+        Ok(Self {
+            parent,
+            targets,
+            next_slice: 0,
+            filled: Decimal::zero(),
+            notional: Decimal::zero(),
+            arrival_price: None,
+            child_orders: 0,
+            report: None,
+        })
+    }
+
+    pub fn parent(&self) -> &ParentOrder {
+        &self.parent
+    }
+
+    pub fn filled_quantity(&self) -> Decimal {
+        self.filled
+    }
+
+    /// Set once the parent is filled or its window has closed.
+    pub fn report(&self) -> Option<&CompletionReport> {
+        self.report.as_ref()
+    }
+
+    fn slice_start(&self, slice: usize) -> DateTime<Utc> {
+        let window = self.parent.end - self.parent.start;
+        self.parent.start + window * slice as i32 / self.parent.slices as i32
+    }
+
+    /// How much the next child should ask for to be back on schedule.
+    fn child_quantity(&self, engine: &MatchingEngine, slice: usize) -> Decimal {
+        let target = if slice + 1 == self.parent.slices {
+            self.parent.quantity
+        } else {
+            self.parent.quantity * self.targets[slice]
+        };
+        let mut quantity = (target - self.filled).max(Decimal::zero());
+        let lot_size = engine
+            .market_config(&self.parent.pair)
+            .and_then(|config| config.lot_size);
+        if let Some(lot_size) = lot_size.filter(|lot| !lot.is_zero()) {
+            quantity = (quantity / lot_size).floor() * lot_size;
+        }
+        quantity
+    }
+
+    fn send_child(
+        &mut self,
+        engine: &mut MatchingEngine,
+        quantity: Decimal,
+        now: DateTime<Utc>,
+    ) -> Result<(), String> {
+        let order = Order::new(
+            self.parent.pair.to_string(),
+            engine.next_exchange_id(),
+            self.parent.side,
+            quantity,
+            self.parent.limit_price,
+            now,
+            now,
+        )
+        .with_client(self.parent.client.clone());
+        self.child_orders += 1;
+
+        // A rejected child (halted market, band breach) is retried by the
+        // next slice's catch-up.
+        let Ok((order, fills)) = engine.place_limit_order(self.parent.pair.clone(), order) else {
+            return Ok(());
+        };
+        for fill in &fills {
+            self.filled += fill.quantity;
+            self.notional = self
+                .notional
+                .saturating_add(fill.price.saturating_mul(fill.quantity));
+        }
+        if order.is_active() {
+            engine.cancel_order(&self.parent.pair, order.exchange_id)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, now: DateTime<Utc>) {
+        self.report = Some(CompletionReport {
+            filled_quantity: self.filled,
+            remaining_quantity: self.parent.quantity - self.filled,
+            average_price: (!self.filled.is_zero()).then(|| self.notional / self.filled),
+            arrival_price: self.arrival_price,
+            child_orders: self.child_orders,
+            finished_at: now,
+        });
+    }
+}
+
+impl Agent for ExecutionAlgo {
+    fn on_tick(&mut self, engine: &mut MatchingEngine, now: DateTime<Utc>) -> Result<(), String> {
+        if self.report.is_some() || now < self.parent.start {
+            return Ok(());
+        }
+        if self.arrival_price.is_none() {
+            self.arrival_price = engine
+                .orderbook(&self.parent.pair)
+                .and_then(|book| book.get_mid_price());
+        }
+
+        // Only the latest due slice sends; its target covers any skipped.
+        let mut due = None;
+        while self.next_slice < self.parent.slices && self.slice_start(self.next_slice) <= now {
+            due = Some(self.next_slice);
+            self.next_slice += 1;
+        }
+        if let Some(slice) = due.filter(|_| now < self.parent.end) {
+            let quantity = self.child_quantity(engine, slice);
+            if quantity > Decimal::zero() {
+                self.send_child(engine, quantity, now)?;
+            }
+        }
+
+        if self.filled >= self.parent.quantity || now >= self.parent.end {
+            self.finish(now);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use chrono::{Duration, TimeZone};
+    use rust_decimal_macros::dec;
+
+    fn pair() -> TradingPair {
+        TradingPair::new("BTC".to_string(), "USDT".to_string())
+    }
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap()
+    }
+
+    fn rest_ask(engine: &mut MatchingEngine, quantity: Decimal, price: Decimal) {
+        let order = Order::new(
+            pair().to_string(),
+            engine.next_exchange_id(),
+            OrderType::Ask,
+            quantity,
+            price,
+            start(),
+            start(),
+        );
+        engine.place_limit_order(pair(), order).unwrap();
+    }
+
+    fn parent(quantity: Decimal, slices: usize) -> ParentOrder {
+        ParentOrder {
+            client: "algo".to_string(),
+            pair: pair(),
+            side: OrderType::Bid,
+            quantity,
+            limit_price: dec!(101),
+            start: start(),
+            end: start() + Duration::minutes(10),
+            slices,
+        }
+    }
+
+    #[test]
+    fn test_twap_in_simulation() {
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair());
+        rest_ask(&mut engine, dec!(100), dec!(100));
+
+        let mut simulation = Simulation::new(engine);
+        let algo = ExecutionAlgo::new(parent(dec!(10), 5), Schedule::Twap).unwrap();
+        simulation.add_agent(algo);
+        simulation
+            .run(
+                start(),
+                start() + Duration::minutes(10),
+                Duration::seconds(30),
+            )
+            .unwrap();
+
+        let ask = simulation
+            .engine
+            .orderbook(&pair())
+            .unwrap()
+            .get_ask_depth(dec!(100));
+        assert_eq!(ask, dec!(90));
+    }
+
+    #[test]
+    fn test_catch_up_after_thin_liquidity() {
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair());
+        let mut algo = ExecutionAlgo::new(parent(dec!(10), 5), Schedule::Twap).unwrap();
+
+        // Only 1 of the first slice's 2 is available.
+        rest_ask(&mut engine, dec!(1), dec!(100));
+        algo.on_tick(&mut engine, start()).unwrap();
+        assert_eq!(algo.filled_quantity(), dec!(1));
+        assert!(engine.orderbook(&pair()).unwrap().orders.is_empty());
+
+        // The second slice asks for 4 - 1 = 3.
+        rest_ask(&mut engine, dec!(50), dec!(100));
+        algo.on_tick(&mut engine, start() + Duration::minutes(2))
+            .unwrap();
+        assert_eq!(algo.filled_quantity(), dec!(4));
+
+        // Skipping straight to the last slice finishes the parent.
+        algo.on_tick(&mut engine, start() + Duration::minutes(9))
+            .unwrap();
+        let report = algo.report().unwrap();
+        assert_eq!(report.filled_quantity, dec!(10));
+        assert_eq!(report.remaining_quantity, dec!(0));
+        assert_eq!(report.average_price, Some(dec!(100)));
+        assert_eq!(report.child_orders, 3);
+    }
+
+    #[test]
+    fn test_vwap_follows_profile() {
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair());
+        rest_ask(&mut engine, dec!(100), dec!(100));
+        let schedule = Schedule::Vwap {
+            volume_profile: vec![dec!(1), dec!(3)],
+        };
+        let mut algo = ExecutionAlgo::new(parent(dec!(8), 2), schedule).unwrap();
+
+        algo.on_tick(&mut engine, start()).unwrap();
+        assert_eq!(algo.filled_quantity(), dec!(2));
+        algo.on_tick(&mut engine, start() + Duration::minutes(5))
+            .unwrap();
+        assert_eq!(algo.filled_quantity(), dec!(8));
+        assert!(algo.report().is_some());
+
+        let bad = Schedule::Vwap {
+            volume_profile: vec![dec!(1)],
+        };
+        assert!(ExecutionAlgo::new(parent(dec!(8), 2), bad).is_err());
+    }
+
+    #[test]
+    fn test_window_closes_unfilled() {
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair());
+        let mut algo = ExecutionAlgo::new(parent(dec!(10), 2), Schedule::Twap).unwrap();
+
+        algo.on_tick(&mut engine, start()).unwrap();
+        algo.on_tick(&mut engine, start() + Duration::minutes(10))
+            .unwrap();
+        let report = algo.report().unwrap();
+        assert_eq!(report.remaining_quantity, dec!(10));
+        assert_eq!(report.average_price, None);
+        assert_eq!(report.child_orders, 1);
+    }
+}
@@ -0,0 +1,249 @@
+//! Fault injection for replays and backtests: commands delivered twice,
+//! acknowledgements lost on the way back, and feed messages arriving out
+//! of order, so strategies and feed handlers can be run against the kind
+//! of mess real networks make.
+//!
+//! Faults are drawn from a seeded RNG, so a faulty run is as repeatable as
+//! a clean one. `replay` applies them to a recording; a `Gateway` given an
+//! injector applies them to a strategy's commands; `reorder` shuffles any
+//! message stream before it reaches a handler.
+
+use crate::{
+    matching_engine::engine::MatchingEngine,
+    replay::{self, Output, RecordedCommand},
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+/// How often each fault happens. Probabilities are per command.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Faults {
+    /// The engine processes the command but its sender never hears back.
+    pub drop_ack: f64,
+    /// The engine receives the command a second time, right after the first.
+    pub duplicate: f64,
+    /// Messages are shuffled within consecutive windows of this many; 0 or
+    /// 1 keeps them in order.
+    pub reorder_window: usize,
+}
+
+/// Counts of the faults injected so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FaultStats {
+    pub dropped_acks: usize,
+    pub duplicated: usize,
+    /// Messages that ended up somewhere other than where they were sent.
+    pub reordered: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct FaultInjector {
+    faults: Faults,
+    rng: StdRng,
+    stats: FaultStats,
+}
+
+impl FaultInjector {
+    pub fn new(faults: Faults, seed: u64) -> Self {
+        Self {
+            faults,
+            rng: StdRng::seed_from_u64(seed),
+            stats: FaultStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> FaultStats {
+        self.stats
+    }
+
+    /// How many times the engine receives the next command: once, or twice
+    /// when it is duplicated.
+    pub fn copies(&mut self) -> usize {
+        if self.rng.gen::<f64>() < self.faults.duplicate {
+            self.stats.duplicated += 1;
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Whether the acknowledgement of the command just processed is lost.
+    pub fn drop_ack(&mut self) -> bool {
+        let dropped = self.rng.gen::<f64>() < self.faults.drop_ack;
+        self.stats.dropped_acks += dropped as usize;
+        dropped
+    }
+
+    /// Shuffles `messages` within consecutive windows; no message moves
+    /// further than the window it was sent in.
+    pub fn reorder<T: Clone + PartialEq>(&mut self, messages: Vec<T>) -> Vec<T> {
+        if self.faults.reorder_window <= 1 {
+            return messages;
+        }
+        let mut reordered = messages.clone();
+        for window in reordered.chunks_mut(self.faults.reorder_window) {
+            window.shuffle(&mut self.rng);
+        }
+        self.stats.reordered += messages
+            .iter()
+            .zip(&reordered)
+            .filter(|(sent, received)| sent != received)
+            .count();
+        reordered
+    }
+}
+
+/// One delivery of a recorded command to the engine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    /// Position of the command in the recording, from 0.
+    pub index: usize,
+    pub output: Output,
+    /// False when the output never made it back to the sender.
+    pub acknowledged: bool,
+}
+
+/// Replays `commands` through `engine` with `injector`'s duplicates and
+/// lost acknowledgements, returning every delivery in order.
+pub fn replay(
+    engine: &mut MatchingEngine,
+    commands: &[RecordedCommand],
+    injector: &mut FaultInjector,
+) -> Vec<Delivery> {
+    let mut deliveries = Vec::new();
+    for (index, recorded) in commands.iter().enumerate() {
+        for _ in 0..injector.copies() {
+            let output = replay::apply(engine, recorded);
+            deliveries.push(Delivery {
+                index,
+                output,
+                acknowledged: !injector.drop_ack(),
+            });
+        }
+    }
+    deliveries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::{Command, NewOrderRequest},
+        limit_order_book::order::{OrderType, TimeInForce},
+        matching_engine::engine::TradingPair,
+    };
+    use chrono::{Duration, TimeZone, Utc};
+    use rust_decimal_macros::dec;
+
+    fn commands() -> Vec<RecordedCommand> {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        (0..20)
+            .map(|index| RecordedCommand {
+                time: start + Duration::seconds(index),
+                command: Command::New(NewOrderRequest {
+                    pair: "BTC/USDT".to_string(),
+                    side: OrderType::Bid,
+                    price: dec!(100),
+                    quantity: dec!(1),
+                    client: "alice".to_string(),
+                    short_sale: false,
+                    reduce_only: false,
+                    client_order_id: Some(format!("order-{}", index)),
+                    strategy: None,
+                    time_in_force: TimeInForce::Gtc,
+                }),
+            })
+            .collect()
+    }
+
+    fn engine() -> MatchingEngine {
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(TradingPair::new("BTC".to_string(), "USDT".to_string()));
+        engine
+    }
+
+    #[test]
+    fn test_faulty_replay() {
+        let faults = Faults {
+            drop_ack: 0.2,
+            duplicate: 0.3,
+            reorder_window: 4,
+        };
+        let mut injector = FaultInjector::new(faults.clone(), 7);
+        let mut engine = engine();
+        let deliveries = replay(&mut engine, &commands(), &mut injector);
+        let stats = injector.stats();
+        assert!(stats.duplicated > 0 && stats.dropped_acks > 0);
+        assert_eq!(deliveries.len(), 20 + stats.duplicated);
+        assert_eq!(
+            deliveries
+                .iter()
+                .filter(|delivery| !delivery.acknowledged)
+                .count(),
+            stats.dropped_acks
+        );
+        // Client order IDs make the duplicates harmless.
+        assert_eq!(
+            engine
+                .orderbook(&TradingPair::new("BTC".to_string(), "USDT".to_string()))
+                .unwrap()
+                .orders
+                .len(),
+            20
+        );
+
+        // The same seed injects the same faults.
+        let mut again = FaultInjector::new(faults, 7);
+        assert_eq!(
+            replay(&mut self::engine(), &commands(), &mut again),
+            deliveries
+        );
+
+        let sent: Vec<usize> = (0..10).collect();
+        let received = injector.reorder(sent.clone());
+        assert_ne!(received, sent);
+        for (window, chunk) in received.chunks(4).enumerate() {
+            let mut chunk = chunk.to_vec();
+            chunk.sort_unstable();
+            assert_eq!(chunk, sent[window * 4..(window * 4 + 4).min(10)]);
+        }
+    }
+
+    #[test]
+    fn test_no_faults_and_certain_faults() {
+        // With no faults the replay is the recording, delivered once each.
+        let mut clean = FaultInjector::new(Faults::default(), 1);
+        let deliveries = replay(&mut engine(), &commands(), &mut clean);
+        assert_eq!(
+            deliveries
+                .iter()
+                .map(|delivery| delivery.index)
+                .collect::<Vec<_>>(),
+            (0..20).collect::<Vec<_>>()
+        );
+        assert!(deliveries.iter().all(|delivery| delivery.acknowledged));
+        let sent: Vec<usize> = (0..10).collect();
+        assert_eq!(clean.reorder(sent.clone()), sent);
+        assert_eq!(clean.stats(), FaultStats::default());
+
+        // At probability 1 every command goes twice and nothing comes back.
+        let faults = Faults {
+            drop_ack: 1.0,
+            duplicate: 1.0,
+            reorder_window: 1,
+        };
+        let mut certain = FaultInjector::new(faults, 1);
+        let deliveries = replay(&mut engine(), &commands()[..3], &mut certain);
+        let indexes: Vec<usize> = deliveries.iter().map(|delivery| delivery.index).collect();
+        assert_eq!(indexes, vec![0, 0, 1, 1, 2, 2]);
+        assert!(deliveries.iter().all(|delivery| !delivery.acknowledged));
+        assert_eq!(certain.reorder(sent.clone()), sent);
+        assert_eq!(
+            certain.stats(),
+            FaultStats {
+                dropped_acks: 6,
+                duplicated: 3,
+                reordered: 0
+            }
+        );
+    }
+}
@@ -0,0 +1,397 @@
+use super::{faults::FaultInjector, scheduler::Scheduler, Agent};
+pub use crate::matching_engine::command::{CommandOutcome, EngineCommand};
+use crate::matching_engine::engine::MatchingEngine;
+use chrono::{DateTime, Duration, Utc};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::HashMap;
+
+/// Delay between a strategy deciding to act and the engine receiving the
+/// command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LatencyModel {
+    Fixed(Duration),
+    /// Normally distributed, truncated at zero.
+    Normal {
+        mean: Duration,
+        std_dev: Duration,
+    },
+    /// Looks up the submitting client, falling back to `default`.
+    PerClient {
+        default: Box<LatencyModel>,
+        clients: HashMap<String, LatencyModel>,
+    },
+}
+
+impl Default for LatencyModel {
+    fn default() -> Self {
+        LatencyModel::Fixed(Duration::zero())
+    }
+}
+
+impl LatencyModel {
+    pub fn sample(&self, rng: &mut impl Rng, client: &str) -> Duration {
+        match self {
+            LatencyModel::Fixed(latency) => *latency,
+            LatencyModel::Normal { mean, std_dev } => {
+                // Box-Muller transform.
+                let u1 = 1.0 - rng.gen::<f64>();
+                let u2 = rng.gen::<f64>();
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                let nanos = mean.num_nanoseconds().unwrap_or_default() as f64
+                    + z * std_dev.num_nanoseconds().unwrap_or_default() as f64;
+                Duration::nanoseconds(nanos.max(0.0) as i64)
+            }
+            LatencyModel::PerClient { default, clients } => {
+                clients.get(client).unwrap_or(default).sample(rng, client)
+            }
+        }
+    }
+}
+
+/// What happened to a command once the engine received it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandReport {
+    pub sent_at: DateTime<Utc>,
+    pub received_at: DateTime<Utc>,
+    pub command: EngineCommand,
+    pub outcome: Result<CommandOutcome, String>,
+}
+
+/// Holds commands in flight until their sampled latency has elapsed.
+pub struct Gateway {
+    model: LatencyModel,
+    rng: StdRng,
+    in_flight: Scheduler<(DateTime<Utc>, EngineCommand)>,
+    faults: Option<FaultInjector>,
+}
+
+impl Gateway {
+    pub fn new(model: LatencyModel, seed: u64) -> Self {
+        Self {
+            model,
+            rng: StdRng::seed_from_u64(seed),
+            in_flight: Scheduler::new(),
+            faults: None,
+        }
+    }
+
+    /// Duplicates commands on the way in and drops their reports on the
+    /// way out as `faults` decides. A duplicate takes its own latency.
+    pub fn with_faults(mut self, faults: FaultInjector) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+
+    pub fn faults(&self) -> Option<&FaultInjector> {
+        self.faults.as_ref()
+    }
+
+    /// Sends `command` at `now`; returns when the engine will first receive
+    /// it.
+    pub fn submit(
+        &mut self,
+        now: DateTime<Utc>,
+        client: &str,
+        command: EngineCommand,
+    ) -> DateTime<Utc> {
+        let copies = self.faults.as_mut().map_or(1, FaultInjector::copies);
+        let received_at = now + self.model.sample(&mut self.rng, client);
+        self.in_flight.schedule(received_at, (now, command.clone()));
+        let mut first = received_at;
+        for _ in 1..copies {
+            let received_at = now + self.model.sample(&mut self.rng, client);
+            self.in_flight.schedule(received_at, (now, command.clone()));
+            first = first.min(received_at);
+        }
+        first
+    }
+
+    /// Applies every command received by `now`, in arrival order, and
+    /// reports those whose acknowledgement got back.
+    pub fn deliver(
+        &mut self,
+        engine: &mut MatchingEngine,
+        now: DateTime<Utc>,
+    ) -> Vec<CommandReport> {
+        let mut reports = Vec::new();
+        while let Some((received_at, (sent_at, command))) = self.in_flight.pop_due(now) {
+            let outcome = command.clone().apply(engine);
+            if self.faults.as_mut().is_some_and(FaultInjector::drop_ack) {
+                continue;
+            }
+            reports.push(CommandReport {
+                sent_at,
+                received_at,
+                command,
+                outcome,
+            });
+        }
+        reports
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+}
+
+/// A strategy that only sees the engine read-only and acts through
+/// commands, so its decisions can be delayed.
+pub trait Strategy {
+    /// Client name used to pick a latency from [`LatencyModel::PerClient`].
+    fn client(&self) -> &str;
+
+    /// Decides what to send at `now`. Orders must carry exchange IDs the
+    /// strategy owns, since they are only placed after the delay.
+    fn on_tick(&mut self, engine: &MatchingEngine, now: DateTime<Utc>) -> Vec<EngineCommand>;
+
+    /// Called for each of this strategy's commands once the engine has
+    /// processed it.
+    fn on_report(&mut self, _report: &CommandReport) {}
+}
+
+/// Runs a [`Strategy`] as an [`Agent`], routing its commands through a
+/// [`Gateway`]. Delivery is checked on every simulation step, so the step
+/// bounds how finely latency is resolved.
+pub struct Delayed<S> {
+    strategy: S,
+    gateway: Gateway,
+}
+
+impl<S: Strategy> Delayed<S> {
+    pub fn new(strategy: S, model: LatencyModel, seed: u64) -> Self {
+        Self {
+            strategy,
+            gateway: Gateway::new(model, seed),
+        }
+    }
+
+    pub fn with_faults(mut self, faults: FaultInjector) -> Self {
+        self.gateway = self.gateway.with_faults(faults);
+        self
+    }
+
+    pub fn strategy(&self) -> &S {
+        &self.strategy
+    }
+
+    pub fn gateway(&self) -> &Gateway {
+        &self.gateway
+    }
+}
+
+impl<S: Strategy> Agent for Delayed<S> {
+    fn on_tick(&mut self, engine: &mut MatchingEngine, now: DateTime<Utc>) -> Result<(), String> {
+        for report in self.gateway.deliver(engine, now) {
+            self.strategy.on_report(&report);
+        }
+        for command in self.strategy.on_tick(engine, now) {
+            let client = self.strategy.client().to_string();
+            self.gateway.submit(now, &client, command);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        limit_order_book::order::{Order, OrderType},
+        matching_engine::engine::TradingPair,
+        simulation::{faults::Faults, Simulation},
+    };
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+    use std::{cell::RefCell, rc::Rc};
+
+    fn pair() -> TradingPair {
+        TradingPair::new("BTC".to_string(), "USDT".to_string())
+    }
+
+    #[test]
+    fn test_latency_models() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let fixed = LatencyModel::Fixed(Duration::milliseconds(5));
+        assert_eq!(fixed.sample(&mut rng, "any"), Duration::milliseconds(5));
+
+        let normal = LatencyModel::Normal {
+            mean: Duration::milliseconds(10),
+            std_dev: Duration::milliseconds(3),
+        };
+        let samples: Vec<Duration> = (0..5000).map(|_| normal.sample(&mut rng, "any")).collect();
+        assert!(samples.iter().all(|latency| *latency >= Duration::zero()));
+        let mean = samples
+            .iter()
+            .map(|latency| latency.num_microseconds().unwrap())
+            .sum::<i64>()
+            / 5000;
+        assert!((9_500..10_500).contains(&mean), "mean {}", mean);
+
+        let per_client = LatencyModel::PerClient {
+            default: Box::new(fixed),
+            clients: HashMap::from([(
+                "colo".to_string(),
+                LatencyModel::Fixed(Duration::microseconds(50)),
+            )]),
+        };
+        assert_eq!(
+            per_client.sample(&mut rng, "colo"),
+            Duration::microseconds(50)
+        );
+        assert_eq!(
+            per_client.sample(&mut rng, "retail"),
+            Duration::milliseconds(5)
+        );
+    }
+
+    /// Lifts the best ask once, at the first tick.
+    struct Lifter {
+        sent: bool,
+        reports: Rc<RefCell<Vec<CommandReport>>>,
+    }
+
+    impl Strategy for Lifter {
+        fn client(&self) -> &str {
+            "lifter"
+        }
+
+        fn on_tick(&mut self, engine: &MatchingEngine, now: DateTime<Utc>) -> Vec<EngineCommand> {
+            if self.sent {
+                return Vec::new();
+            }
+            self.sent = true;
+            let price = engine.orderbook(&pair()).unwrap().get_best_ask().unwrap();
+            let order = Order::new(
+                pair().to_string(),
+                1_000,
+                OrderType::Bid,
+                dec!(1),
+                price,
+                now,
+                now,
+            )
+            .with_client("lifter".to_string());
+            vec![EngineCommand::Limit {
+                pair: pair(),
+                order,
+            }]
+        }
+
+        fn on_report(&mut self, report: &CommandReport) {
+            self.reports.borrow_mut().push(report.clone());
+        }
+    }
+
+    /// Pulls its ask 5ms after the lifter decides, beating the lifter's
+    /// 10ms latency.
+    struct Canceller;
+
+    impl Agent for Canceller {
+        fn on_tick(
+            &mut self,
+            engine: &mut MatchingEngine,
+            now: DateTime<Utc>,
+        ) -> Result<(), String> {
+            if now.timestamp_subsec_millis() == 5 {
+                engine.cancel_order(&pair(), 1)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_duplicated_order_rests_once() {
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair());
+        let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let order = |exchange_id, order_type| {
+            Order::new(
+                pair().to_string(),
+                exchange_id,
+                order_type,
+                dec!(1),
+                dec!(100),
+                start,
+                start,
+            )
+        };
+        let faults = Faults {
+            duplicate: 1.0,
+            ..Faults::default()
+        };
+        let mut gateway = Gateway::new(LatencyModel::Fixed(Duration::milliseconds(5)), 0)
+            .with_faults(FaultInjector::new(faults, 0));
+        gateway.submit(
+            start,
+            "maker",
+            EngineCommand::Limit {
+                pair: pair(),
+                order: order(7, OrderType::Ask),
+            },
+        );
+        assert_eq!(gateway.in_flight(), 2);
+
+        let reports = gateway.deliver(&mut engine, start + Duration::milliseconds(5));
+        assert_eq!(reports.len(), 2);
+        assert!(reports[0].outcome.is_ok());
+        let error = reports[1].outcome.as_ref().unwrap_err();
+        assert!(error.contains("already resting"));
+        let book = engine.orderbook(&pair()).unwrap();
+        assert_eq!(book.get_ask_count(dec!(100)), 1);
+        assert_eq!(book.get_ask_depth(dec!(100)), dec!(1));
+
+        // The book still matches against the order it kept.
+        let (_, fills) = engine
+            .place_limit_order(pair(), order(8, OrderType::Bid))
+            .unwrap();
+        assert_eq!(fills.len(), 1);
+    }
+
+    #[test]
+    fn test_delayed_command_misses_cancelled_quote() {
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair());
+        let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let ask = Order::new(
+            pair().to_string(),
+            1,
+            OrderType::Ask,
+            dec!(1),
+            dec!(100),
+            start,
+            start,
+        );
+        engine.place_limit_order(pair(), ask).unwrap();
+
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let lifter = Lifter {
+            sent: false,
+            reports: reports.clone(),
+        };
+        let mut simulation = Simulation::new(engine);
+        simulation.add_agent(Delayed::new(
+            lifter,
+            LatencyModel::Fixed(Duration::milliseconds(10)),
+            0,
+        ));
+        simulation.add_agent(Canceller);
+        simulation
+            .run(
+                start,
+                start + Duration::milliseconds(20),
+                Duration::milliseconds(1),
+            )
+            .unwrap();
+
+        let reports = reports.borrow();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].received_at, start + Duration::milliseconds(10));
+        match &reports[0].outcome {
+            Ok(CommandOutcome::Placed { order, fills }) => {
+                assert!(fills.is_empty());
+                assert_eq!(order.remaining_quantity, dec!(1));
+            }
+            other => panic!("unexpected outcome {:?}", other),
+        }
+    }
+}
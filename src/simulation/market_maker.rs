@@ -0,0 +1,263 @@
+use super::Agent;
+use crate::{
+    limit_order_book::order::{Order, OrderType},
+    matching_engine::engine::{MatchingEngine, TradingPair},
+};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketMakerConfig {
+    pub client: String,
+    pub pair: TradingPair,
+    /// Distance between the bid and the ask, in price units.
+    pub spread: Decimal,
+    pub quote_size: Decimal,
+    /// How far both quotes move against each unit of inventory held, so a
+    /// long maker quotes lower to sell down its position.
+    pub skew_per_unit: Decimal,
+    /// The maker stops adding to a position beyond this size.
+    pub max_inventory: Decimal,
+    pub refresh_interval: Duration,
+    /// Used as the fair price until the book has a two-sided market.
+    pub reference_price: Decimal,
+    pub tick_size: Option<Decimal>,
+}
+
+/// Keeps a bid and an ask around the book mid, cancelling and replacing them
+/// every `refresh_interval`. Executions are picked up when the previous
+/// quotes are cancelled, so inventory lags fills by at most one refresh.
+pub struct MarketMaker {
+    config: MarketMakerConfig,
+    inventory: Decimal,
+    fair_price: Decimal,
+    quotes: Vec<(u64, OrderType, Decimal)>,
+    last_refresh: Option<DateTime<Utc>>,
+}
+
+impl MarketMaker {
+    pub fn new(config: MarketMakerConfig) -> Self {
+        let fair_price = config.reference_price;
+        Self {
+            config,
+            inventory: Decimal::zero(),
+            fair_price,
+            quotes: Vec::new(),
+            last_refresh: None,
+        }
+    }
+
+    pub fn config(&self) -> &MarketMakerConfig {
+        &self.config
+    }
+
+    /// Net position: positive when long.
+    pub fn inventory(&self) -> Decimal {
+        self.inventory
+    }
+
+    /// IDs of the quotes currently working in the engine.
+    pub fn quote_ids(&self) -> Vec<u64> {
+        self.quotes
+            .iter()
+            .map(|(exchange_id, _, _)| *exchange_id)
+            .collect()
+    }
+
+    /// The bid and ask prices the maker would quote right now, before
+    /// inventory limits are applied.
+    pub fn quote_prices(&self) -> (Decimal, Decimal) {
+        let reservation = self.fair_price - self.config.skew_per_unit * self.inventory;
+        let half_spread = self.config.spread / Decimal::TWO;
+        let (bid, ask) = (reservation - half_spread, reservation + half_spread);
+        match self.config.tick_size {
+            Some(tick) if !tick.is_zero() => {
+                ((bid / tick).floor() * tick, (ask / tick).ceil() * tick)
+            }
+            _ => (bid, ask),
+        }
+    }
+
+    fn pull_quotes(&mut self, engine: &mut MatchingEngine) {
+        for (exchange_id, order_type, size) in std::mem::take(&mut self.quotes) {
+            // An order that is no longer resting was filled completely.
+            let filled = match engine.cancel_order(&self.config.pair, exchange_id) {
+                Ok(order) => order.filled_quantity,
+                Err(_) => size,
+            };
+            match order_type {
+                OrderType::Bid => self.inventory += filled,
+                OrderType::Ask => self.inventory -= filled,
+            }
+        }
+    }
+
+    fn place_quote(
+        &mut self,
+        engine: &mut MatchingEngine,
+        order_type: OrderType,
+        price: Decimal,
+        size: Decimal,
+        now: DateTime<Utc>,
+    ) -> Result<(), String> {
+        if size <= Decimal::zero() || price <= Decimal::zero() {
+            return Ok(());
+        }
+        let order = Order::new(
+            self.config.pair.to_string(),
+            engine.next_exchange_id(),
+            order_type,
+            size,
+            price,
+            now,
+            now,
+        )
+        .with_client(self.config.client.clone());
+        let (order, _) = engine.place_limit_order(self.config.pair.clone(), order)?;
+
+        // Anything that traded on entry is already part of the position.
+        match order_type {
+            OrderType::Bid => self.inventory += order.filled_quantity,
+            OrderType::Ask => self.inventory -= order.filled_quantity,
+        }
+        if order.is_active() {
+            self.quotes
+                .push((order.exchange_id, order_type, order.remaining_quantity));
+        }
+        Ok(())
+    }
+}
+
+impl Agent for MarketMaker {
+    fn on_tick(&mut self, engine: &mut MatchingEngine, now: DateTime<Utc>) -> Result<(), String> {
+        if let Some(last_refresh) = self.last_refresh {
+            if now - last_refresh < self.config.refresh_interval {
+                return Ok(());
+            }
+        }
+        self.last_refresh = Some(now);
+
+        self.pull_quotes(engine);
+        if let Some(mid) = engine
+            .orderbook(&self.config.pair)
+            .and_then(|book| book.get_mid_price())
+        {
+            self.fair_price = mid;
+        }
+
+        let (bid, ask) = self.quote_prices();
+        let bid_size = self
+            .config
+            .quote_size
+            .min(self.config.max_inventory - self.inventory);
+        let ask_size = self
+            .config
+            .quote_size
+            .min(self.config.max_inventory + self.inventory);
+        self.place_quote(engine, OrderType::Bid, bid, bid_size, now)?;
+        self.place_quote(engine, OrderType::Ask, ask, ask_size, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use rust_decimal_macros::dec;
+
+    fn config() -> MarketMakerConfig {
+        MarketMakerConfig {
+            client: "mm".to_string(),
+            pair: TradingPair::new("BTC".to_string(), "USDT".to_string()),
+            spread: dec!(2),
+            quote_size: dec!(5),
+            skew_per_unit: dec!(0.5),
+            max_inventory: dec!(8),
+            refresh_interval: Duration::seconds(1),
+            reference_price: dec!(100),
+            tick_size: Some(dec!(0.5)),
+        }
+    }
+
+    fn engine() -> MatchingEngine {
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(config().pair);
+        engine
+    }
+
+    fn take(engine: &mut MatchingEngine, order_type: OrderType, size: Decimal, price: Decimal) {
+        let pair = config().pair;
+        let order = Order::new(
+            pair.to_string(),
+            engine.next_exchange_id(),
+            order_type,
+            size,
+            price,
+            Utc::now(),
+            Utc::now(),
+        );
+        engine.place_limit_order(pair, order).unwrap();
+    }
+
+    #[test]
+    fn test_quotes_around_reference_price() {
+        let mut engine = engine();
+        let mut maker = MarketMaker::new(config());
+        let now = Utc::now();
+        maker.on_tick(&mut engine, now).unwrap();
+
+        let book = engine.orderbook(&config().pair).unwrap();
+        assert_eq!(book.get_best_bid(), Some(dec!(99)));
+        assert_eq!(book.get_best_ask(), Some(dec!(101)));
+        assert_eq!(book.get_bid_depth(dec!(99)), dec!(5));
+        assert_eq!(maker.quote_ids().len(), 2);
+
+        // Not due for a refresh yet, so the same quotes stay in place.
+        let quotes = maker.quote_ids();
+        maker
+            .on_tick(&mut engine, now + Duration::milliseconds(500))
+            .unwrap();
+        assert_eq!(maker.quote_ids(), quotes);
+    }
+
+    #[test]
+    fn test_skews_and_limits_inventory() {
+        let mut engine = engine();
+        let mut maker = MarketMaker::new(config());
+        let now = Utc::now();
+        maker.on_tick(&mut engine, now).unwrap();
+
+        // Someone sells 5 into the maker's bid.
+        take(&mut engine, OrderType::Ask, dec!(5), dec!(99));
+        maker
+            .on_tick(&mut engine, now + Duration::seconds(1))
+            .unwrap();
+        assert_eq!(maker.inventory(), dec!(5));
+
+        // Long 5: quotes shift down by 2.5 and the bid only covers the
+        // remaining 3 units of inventory headroom.
+        let book = engine.orderbook(&config().pair).unwrap();
+        assert_eq!(book.get_best_bid(), Some(dec!(96.5)));
+        assert_eq!(book.get_best_ask(), Some(dec!(98.5)));
+        assert_eq!(book.get_bid_depth(dec!(96.5)), dec!(3));
+        assert_eq!(book.get_ask_depth(dec!(98.5)), dec!(5));
+    }
+
+    #[test]
+    fn test_runs_in_simulation() {
+        let mut simulation = Simulation::new(engine());
+        simulation.add_agent(MarketMaker::new(config()));
+        let start = Utc::now();
+        simulation
+            .run(
+                start,
+                start + Duration::seconds(10),
+                Duration::milliseconds(250),
+            )
+            .unwrap();
+
+        let book = simulation.engine.orderbook(&config().pair).unwrap();
+        assert_eq!(book.orders.len(), 2);
+        assert_eq!(book.get_spread(), Some(dec!(2)));
+    }
+}
@@ -0,0 +1,175 @@
+pub mod debugger;
+pub mod execution;
+pub mod faults;
+pub mod latency;
+pub mod market_maker;
+pub mod order_flow;
+pub mod paper;
+pub mod queue;
+pub mod scheduler;
+pub mod sharded;
+
+use crate::matching_engine::{
+    calendar::Calendar,
+    engine::MatchingEngine,
+    settlement::{SettlementReport, SettlementSchedule},
+};
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+
+/// A participant driven by simulated time.
+pub trait Agent {
+    /// Called once per simulation step; the agent may submit or cancel
+    /// orders on `engine`.
+    fn on_tick(&mut self, engine: &mut MatchingEngine, now: DateTime<Utc>) -> Result<(), String>;
+}
+
+/// Steps a set of agents against one engine on a fixed time grid.
+pub struct Simulation {
+    pub engine: MatchingEngine,
+    agents: Vec<Box<dyn Agent>>,
+    settlement: Option<SettlementSchedule>,
+    settlements: Vec<SettlementReport>,
+    calendar: Option<Calendar>,
+}
+
+impl Simulation {
+    pub fn new(engine: MatchingEngine) -> Self {
+        Self {
+            engine,
+            agents: Vec::new(),
+            settlement: None,
+            settlements: Vec::new(),
+            calendar: None,
+        }
+    }
+
+    pub fn add_agent(&mut self, agent: impl Agent + 'static) {
+        self.agents.push(Box::new(agent));
+    }
+
+    /// Runs the engine's end-of-day settlement once each simulated day, at
+    /// the first step at or after `time`.
+    pub fn settle_daily_at(&mut self, time: NaiveTime) {
+        self.settlement = Some(SettlementSchedule::new(time));
+    }
+
+    /// Only ticks the agents while `calendar` has a session on. Steps
+    /// outside it still expire DAY orders and settle.
+    pub fn trade_on(&mut self, calendar: Calendar) {
+        self.calendar = Some(calendar);
+    }
+
+    /// The settlements run so far, oldest first.
+    pub fn settlements(&self) -> &[SettlementReport] {
+        &self.settlements
+    }
+
+    /// Ticks every agent, in the order they were added, at `start`,
+    /// `start + step`, ... up to and including `end`. Corporate actions
+    /// going ex are applied and DAY orders expired before the agents tick.
+    pub fn run(&mut self, start: DateTime<Utc>, end: DateTime<Utc>, step: Duration) -> Result<(), String> {
+        if step <= Duration::zero() {
+            return Err(format!("Invalid simulation step: {}", step));
+        }
+
+        let mut now = start;
+        while now <= end {
+            self.step(now)?;
+            now += step;
+        }
+        Ok(())
+    }
+
+    /// Runs the single step of `run` at `now`.
+    pub fn step(&mut self, now: DateTime<Utc>) -> Result<(), String> {
+        self.engine.apply_corporate_actions(now);
+        self.engine.expire_orders(now);
+        let trading = self
+            .calendar
+            .as_ref()
+            .is_none_or(|calendar| calendar.is_trading(now));
+        if trading {
+            for agent in self.agents.iter_mut() {
+                agent.on_tick(&mut self.engine, now)?;
+            }
+        }
+        if let Some(schedule) = &mut self.settlement {
+            if schedule.is_due(now) {
+                self.settlements.push(self.engine.settle(now));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching_engine::{calendar::Session, engine::TradingPair};
+    use chrono::TimeZone;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Records the times it was ticked at.
+    struct Recorder(Rc<RefCell<Vec<DateTime<Utc>>>>);
+
+    impl Agent for Recorder {
+        fn on_tick(&mut self, _: &mut MatchingEngine, now: DateTime<Utc>) -> Result<(), String> {
+            self.0.borrow_mut().push(now);
+            Ok(())
+        }
+    }
+
+    fn simulation() -> (Simulation, Rc<RefCell<Vec<DateTime<Utc>>>>) {
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(TradingPair::new("BTC".to_string(), "USDT".to_string()));
+        let mut simulation = Simulation::new(engine);
+        let ticks = Rc::new(RefCell::new(Vec::new()));
+        simulation.add_agent(Recorder(ticks.clone()));
+        (simulation, ticks)
+    }
+
+    #[test]
+    fn test_run_steps() {
+        let (mut simulation, ticks) = simulation();
+        let start = Utc.with_ymd_and_hms(2024, 1, 2, 9, 30, 0).unwrap();
+        let step = Duration::seconds(10);
+        assert!(simulation.run(start, start, Duration::zero()).is_err());
+        assert!(ticks.borrow().is_empty());
+
+        // Both ends are included.
+        simulation
+            .run(start, start + Duration::seconds(30), step)
+            .unwrap();
+        assert_eq!(ticks.borrow().len(), 4);
+        assert_eq!(ticks.borrow()[3], start + Duration::seconds(30));
+        simulation
+            .run(start, start + Duration::seconds(25), step)
+            .unwrap();
+        assert_eq!(ticks.borrow().len(), 7);
+    }
+
+    #[test]
+    fn test_trading_hours_and_settlement() {
+        let (mut simulation, ticks) = simulation();
+        let hour = |hour| NaiveTime::from_hms_opt(hour, 0, 0).unwrap();
+        simulation.trade_on(Calendar::new(Session::new(hour(9), hour(17))));
+        simulation.settle_daily_at(hour(18));
+
+        let start = Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap();
+        simulation
+            .run(start, start + Duration::hours(47), Duration::hours(1))
+            .unwrap();
+        // Eight hourly ticks a day, from the open up to the close.
+        assert_eq!(ticks.borrow().len(), 16);
+        assert_eq!(ticks.borrow()[0], start + Duration::hours(9));
+        let settled: Vec<DateTime<Utc>> = simulation
+            .settlements()
+            .iter()
+            .map(|report| report.time)
+            .collect();
+        assert_eq!(
+            settled,
+            vec![start + Duration::hours(18), start + Duration::hours(42)]
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use super::Agent;
+use crate::{
+    limit_order_book::order::{Order, OrderStatus, OrderType},
+    matching_engine::engine::{MatchingEngine, TradingPair},
+};
+use chrono::{DateTime, Duration, Utc};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rust_decimal::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeDistribution {
+    Fixed(Decimal),
+    Uniform { min: Decimal, max: Decimal },
+    Exponential { mean: Decimal },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderFlowConfig {
+    pub pair: TradingPair,
+    pub client: String,
+    pub seed: u64,
+    /// Mean arrivals per second of each event type. Each type is an
+    /// independent Poisson process.
+    pub limit_rate: Decimal,
+    pub market_rate: Decimal,
+    pub cancel_rate: Decimal,
+    pub size: SizeDistribution,
+    pub lot_size: Decimal,
+    pub tick_size: Decimal,
+    /// Limit orders are placed 1..=max_offset_ticks away from mid.
+    pub max_offset_ticks: u32,
+    /// Chance that a limit order is placed through the mid instead of
+    /// behind it, making it marketable.
+    pub crossing_probability: f64,
+    /// Mid used while the book is one-sided or empty.
+    pub initial_mid: Decimal,
+}
+
+/// Generated orders are numbered by the engine when they are applied, so
+/// their exchange ID is 0 until then.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlowEventKind {
+    Limit(Order),
+    Market(Order),
+    Cancel(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowEvent {
+    pub time: DateTime<Utc>,
+    pub kind: FlowEventKind,
+}
+
+/// Seeded generator of synthetic order flow. The same config and start time
+/// always produce the same event sequence. Cancels pick among the orders it
+/// has applied that rested, so there are none until it drives an engine.
+pub struct OrderFlowGenerator {
+    config: OrderFlowConfig,
+    rng: StdRng,
+    now: DateTime<Utc>,
+    mid: Decimal,
+    live_orders: Vec<u64>,
+    pending: Option<FlowEvent>,
+    rejected: u64,
+}
+
+impl OrderFlowGenerator {
+    pub fn new(config: OrderFlowConfig, start: DateTime<Utc>) -> Result<Self, String> {
+        if config.tick_size <= Decimal::ZERO || config.lot_size <= Decimal::ZERO {
+            return Err(format!(
+                "Order flow needs a positive tick size and lot size, got {} and {}",
+                config.tick_size, config.lot_size
+            ));
+        }
+        Ok(Self {
+            rng: StdRng::seed_from_u64(config.seed),
+            mid: config.initial_mid,
+            config,
+            now: start,
+            live_orders: Vec::new(),
+            pending: None,
+            rejected: 0,
+        })
+    }
+
+    pub fn config(&self) -> &OrderFlowConfig {
+        &self.config
+    }
+
+    /// Orders the engine rejected while the generator drove it as an agent.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// Sets the price new limit orders are placed around.
+    pub fn set_mid(&mut self, mid: Decimal) {
+        self.mid = mid;
+    }
+
+    /// Generates the next event. Returns `None` if every rate is zero.
+    pub fn next_event(&mut self) -> Option<FlowEvent> {
+        if let Some(event) = self.pending.take() {
+            return Some(event);
+        }
+
+        let rates = [
+            self.config.limit_rate.to_f64().unwrap_or_default(),
+            self.config.market_rate.to_f64().unwrap_or_default(),
+            self.config.cancel_rate.to_f64().unwrap_or_default(),
+        ];
+        let total_rate: f64 = rates.iter().map(|rate| rate.max(0.0)).sum();
+        if total_rate <= 0.0 {
+            return None;
+        }
+
+        // The superposition of the three processes is Poisson with the summed
+        // rate; each arrival is of a type with probability rate / total.
+        let wait = -(1.0 - self.rng.gen::<f64>()).ln() / total_rate;
+        self.now += Duration::nanoseconds((wait * 1e9) as i64);
+
+        let pick = self.rng.gen::<f64>() * total_rate;
+        let kind = if pick < rates[0] {
+            FlowEventKind::Limit(self.limit_order())
+        } else if pick < rates[0] + rates[1] || self.live_orders.is_empty() {
+            FlowEventKind::Market(self.market_order())
+        } else {
+            let index = self.rng.gen_range(0..self.live_orders.len());
+            FlowEventKind::Cancel(self.live_orders.swap_remove(index))
+        };
+
+        Some(FlowEvent {
+            time: self.now,
+            kind,
+        })
+    }
+
+    /// Applies `event` to `engine`, numbering its order with the engine's
+    /// next exchange ID. Cancels of orders that already traded away are
+    /// ignored.
+    pub fn apply(&mut self, engine: &mut MatchingEngine, event: FlowEvent) -> Result<(), String> {
+        match event.kind {
+            FlowEventKind::Limit(mut order) => {
+                order.exchange_id = engine.next_exchange_id();
+                let (order, _) = engine.place_limit_order(self.config.pair.clone(), order)?;
+                if matches!(
+                    order.status,
+                    OrderStatus::New | OrderStatus::PartiallyFilled
+                ) {
+                    self.live_orders.push(order.exchange_id);
+                }
+                Ok(())
+            }
+            FlowEventKind::Market(mut order) => {
+                order.exchange_id = engine.next_exchange_id();
+                engine
+                    .execute_market_order(self.config.pair.clone(), order)
+                    .map(|_| ())
+            }
+            FlowEventKind::Cancel(exchange_id) => {
+                let _ = engine.cancel_order(&self.config.pair, exchange_id);
+                Ok(())
+            }
+        }
+    }
+
+    fn limit_order(&mut self) -> Order {
+        let order_type = self.side();
+        let tick = self.config.tick_size;
+        let mid = (self.mid / tick).round() * tick;
+        let offset =
+            Decimal::from(self.rng.gen_range(1..=self.config.max_offset_ticks.max(1))) * tick;
+        let crossing = self
+            .rng
+            .gen_bool(self.config.crossing_probability.clamp(0.0, 1.0));
+        let price = match (order_type, crossing) {
+            (OrderType::Bid, false) | (OrderType::Ask, true) => mid - offset,
+            (OrderType::Ask, false) | (OrderType::Bid, true) => mid + offset,
+        };
+
+        self.order(order_type, price.max(tick))
+    }
+
+    fn market_order(&mut self) -> Order {
+        let order_type = self.side();
+        self.order(order_type, Decimal::ZERO)
+    }
+
+    fn order(&mut self, order_type: OrderType, price: Decimal) -> Order {
+        let size = self.size();
+        Order::new(
+            self.config.pair.to_string(),
+            0,
+            order_type,
+            size,
+            price,
+            self.now,
+            self.now,
+        )
+        .with_client(self.config.client.clone())
+    }
+
+    fn side(&mut self) -> OrderType {
+        if self.rng.gen_bool(0.5) {
+            OrderType::Bid
+        } else {
+            OrderType::Ask
+        }
+    }
+
+    fn size(&mut self) -> Decimal {
+        let size = match self.config.size {
+            SizeDistribution::Fixed(size) => size,
+            SizeDistribution::Uniform { min, max } => {
+                let min = min.to_f64().unwrap_or_default();
+                let max = max.to_f64().unwrap_or_default().max(min);
+                Decimal::from_f64(self.rng.gen_range(min..=max)).unwrap_or_default()
+            }
+            SizeDistribution::Exponential { mean } => {
+                let mean = mean.to_f64().unwrap_or_default();
+                Decimal::from_f64(-(1.0 - self.rng.gen::<f64>()).ln() * mean).unwrap_or_default()
+            }
+        };
+        let lot = self.config.lot_size;
+        ((size / lot).round() * lot).max(lot)
+    }
+}
+
+impl Iterator for OrderFlowGenerator {
+    type Item = FlowEvent;
+
+    fn next(&mut self) -> Option<FlowEvent> {
+        self.next_event()
+    }
+}
+
+impl Agent for OrderFlowGenerator {
+    /// Applies every event due by `now`, re-centering on the book mid first.
+    /// Orders the engine rejects are counted rather than failing the run.
+    fn on_tick(&mut self, engine: &mut MatchingEngine, now: DateTime<Utc>) -> Result<(), String> {
+        loop {
+            if let Some(mid) = engine
+                .orderbook(&self.config.pair)
+                .and_then(|book| book.get_mid_price())
+            {
+                self.mid = mid;
+            }
+            let event = match self.next_event() {
+                Some(event) if event.time <= now => event,
+                Some(event) => {
+                    self.pending = Some(event);
+                    return Ok(());
+                }
+                None => return Ok(()),
+            };
+            if self.apply(engine, event).is_err() {
+                self.rejected += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::MarketConfig, simulation::Simulation};
+    use rust_decimal_macros::dec;
+
+    fn config(seed: u64) -> OrderFlowConfig {
+        OrderFlowConfig {
+            pair: TradingPair::new("BTC".to_string(), "USDT".to_string()),
+            client: "flow".to_string(),
+            seed,
+            limit_rate: dec!(50),
+            market_rate: dec!(5),
+            cancel_rate: dec!(20),
+            size: SizeDistribution::Uniform {
+                min: dec!(1),
+                max: dec!(10),
+            },
+            lot_size: dec!(0.1),
+            tick_size: dec!(0.5),
+            max_offset_ticks: 10,
+            crossing_probability: 0.1,
+            initial_mid: dec!(100),
+        }
+    }
+
+    #[test]
+    fn test_seeded_flow_is_reproducible() {
+        let start = Utc::now();
+        let a: Vec<FlowEvent> = OrderFlowGenerator::new(config(7), start)
+            .unwrap()
+            .take(200)
+            .collect();
+        let b: Vec<FlowEvent> = OrderFlowGenerator::new(config(7), start)
+            .unwrap()
+            .take(200)
+            .collect();
+        let c: Vec<FlowEvent> = OrderFlowGenerator::new(config(8), start)
+            .unwrap()
+            .take(200)
+            .collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_event_shape() {
+        let start = Utc::now();
+        let events: Vec<FlowEvent> = OrderFlowGenerator::new(config(1), start)
+            .unwrap()
+            .take(2000)
+            .collect();
+
+        assert!(events.windows(2).all(|pair| pair[0].time <= pair[1].time));
+        // 2000 arrivals at 75/s should take roughly 26.7 seconds.
+        let elapsed = (events.last().unwrap().time - start).num_milliseconds();
+        assert!((20_000..35_000).contains(&elapsed), "elapsed {}", elapsed);
+
+        let mut limits = 0;
+        for event in events.iter() {
+            match &event.kind {
+                FlowEventKind::Limit(order) => {
+                    limits += 1;
+                    assert!((order.limit_price % dec!(0.5)).is_zero());
+                    assert!((order.limit_price - dec!(100)).abs() <= dec!(5));
+                    assert!(order.shares >= dec!(1) && order.shares <= dec!(10));
+                    assert!((order.shares % dec!(0.1)).is_zero());
+                    assert_eq!(order.exchange_id, 0);
+                }
+                FlowEventKind::Market(order) => assert!(order.shares > dec!(0)),
+                // Nothing has rested on an engine to cancel.
+                FlowEventKind::Cancel(_) => panic!("cancel without an engine"),
+            }
+        }
+        // Two thirds of the flow is limit orders.
+        assert!((1100..1550).contains(&limits), "limits {}", limits);
+    }
+
+    #[test]
+    fn test_drives_engine() {
+        let pair = config(3).pair;
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let mut simulation = Simulation::new(engine);
+        let start = Utc::now();
+        simulation.add_agent(OrderFlowGenerator::new(config(3), start).unwrap());
+        simulation
+            .run(
+                start,
+                start + Duration::seconds(30),
+                Duration::milliseconds(100),
+            )
+            .unwrap();
+
+        let book = simulation.engine.orderbook(&pair).unwrap();
+        assert!(!book.orders.is_empty());
+        assert_eq!(book.debug_validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let start = Utc::now();
+        for (tick_size, lot_size) in [(dec!(0), dec!(0.1)), (dec!(0.5), dec!(0))] {
+            let config = OrderFlowConfig {
+                tick_size,
+                lot_size,
+                ..config(1)
+            };
+            assert!(OrderFlowGenerator::new(config, start).is_err());
+        }
+    }
+
+    #[test]
+    fn test_counts_rejections() {
+        let pair = config(3).pair;
+        let mut engine = MatchingEngine::new();
+        // Half the generator's prices are off the market's tick.
+        engine.add_market(MarketConfig {
+            tick_size: Some(dec!(1)),
+            ..MarketConfig::new(pair.clone())
+        });
+        // Its own orders take IDs from the engine, after one handed out here.
+        let taken = engine.next_exchange_id();
+        let start = Utc::now();
+        let mut flow = OrderFlowGenerator::new(config(3), start).unwrap();
+        flow.on_tick(&mut engine, start + Duration::seconds(10))
+            .unwrap();
+
+        assert!(flow.rejected() > 0);
+        let book = engine.orderbook(&pair).unwrap();
+        assert!(!book.orders.is_empty());
+        assert!(book.orders.keys().all(|exchange_id| *exchange_id > taken));
+        assert!(book
+            .orders
+            .values()
+            .all(|order| order.limit_price.fract().is_zero()));
+    }
+}
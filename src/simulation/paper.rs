@@ -0,0 +1,443 @@
+//! Paper trading against a live venue, as its level-2 feed shows it.
+//!
+//! The venue's book is mirrored from the feed (see `limit_order_book::l2`)
+//! and strategy orders never reach the venue. A marketable order takes the
+//! mirrored liquidity at once, and the mirror shrinks accordingly until the
+//! venue's next update for the level replaces it. What's left rests only
+//! here, as a `QueuedOrder` at the back of the venue's level, and fills as
+//! the venue's trades work through the queue in front of it.
+
+use super::queue::{QueueModel, QueuedOrder};
+use crate::{
+    limit_order_book::{
+        l2::L2Update,
+        order::{LimitOrderBook, Order, OrderType},
+    },
+    matching_engine::fees::Liquidity,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+/// One message of the venue's feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VenueUpdate {
+    /// A level's new aggregate size; zero removes it.
+    Level {
+        side: OrderType,
+        price: Decimal,
+        size: Decimal,
+    },
+    Trade {
+        price: Decimal,
+        quantity: Decimal,
+    },
+}
+
+/// A simulated execution of a strategy order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaperFill {
+    pub exchange_id: u64,
+    pub side: OrderType,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub liquidity: Liquidity,
+    pub time: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaperCommand {
+    Place(Box<Order>),
+    Cancel(u64),
+}
+
+/// A strategy run against the mirrored book.
+pub trait PaperStrategy {
+    /// Called after every venue update with the book as it now stands.
+    fn on_update(&mut self, book: &LimitOrderBook, time: DateTime<Utc>) -> Vec<PaperCommand>;
+
+    fn on_fill(&mut self, _fill: &PaperFill) {}
+}
+
+pub struct PaperTrader {
+    book: LimitOrderBook,
+    model: Box<dyn QueueModel>,
+    resting: BTreeMap<u64, QueuedOrder>,
+}
+
+impl PaperTrader {
+    /// Resting orders move up their queues as `model` places the venue's
+    /// cancels.
+    pub fn new(model: impl QueueModel + 'static) -> Self {
+        Self {
+            book: LimitOrderBook::new(),
+            model: Box::new(model),
+            resting: BTreeMap::new(),
+        }
+    }
+
+    /// The mirrored venue book, which doesn't include strategy orders.
+    pub fn book(&self) -> &LimitOrderBook {
+        &self.book
+    }
+
+    /// Strategy orders resting on the venue's levels, by exchange ID.
+    pub fn resting(&self) -> &BTreeMap<u64, QueuedOrder> {
+        &self.resting
+    }
+
+    /// Takes in one feed message and returns the resting orders it filled.
+    pub fn on_update(
+        &mut self,
+        update: VenueUpdate,
+        time: DateTime<Utc>,
+    ) -> Result<Vec<PaperFill>, String> {
+        let mut fills = Vec::new();
+        match update {
+            VenueUpdate::Level { side, price, size } => {
+                self.book
+                    .apply_l2_update(side, price, L2Update::Absolute(size), time)
+                    .map_err(|err| err.to_string())?;
+                for order in self.resting.values_mut() {
+                    if order.side == side && order.price == price {
+                        order.on_level_update(size, self.model.as_ref());
+                    }
+                }
+            }
+            VenueUpdate::Trade { price, quantity } => {
+                for (exchange_id, order) in self.resting.iter_mut() {
+                    let filled = order.on_trade(price, quantity);
+                    if filled > Decimal::ZERO {
+                        fills.push(PaperFill {
+                            exchange_id: *exchange_id,
+                            side: order.side,
+                            price: order.price,
+                            quantity: filled,
+                            liquidity: Liquidity::Maker,
+                            time,
+                        });
+                    }
+                }
+                self.resting.retain(|_, order| !order.is_filled());
+            }
+        }
+        Ok(fills)
+    }
+
+    /// Executes what of `order` crosses the mirrored book and rests the
+    /// rest. Returns the order as it stands and its fills.
+    pub fn place(&mut self, order: Order) -> Result<(Order, Vec<PaperFill>), String> {
+        let mut order = order;
+        if order.remaining_quantity <= Decimal::ZERO {
+            return Err(format!("Invalid quantity: {}", order.remaining_quantity));
+        }
+        if self.resting.contains_key(&order.exchange_id) {
+            return Err(format!("Duplicate exchange id: {}", order.exchange_id));
+        }
+        let (opposite, levels): (_, Vec<(Decimal, Decimal)>) = match order.order_type {
+            OrderType::Bid => (
+                OrderType::Ask,
+                self.book
+                    .iter_asks()
+                    .map(|(price, level)| (price, level.size()))
+                    .collect(),
+            ),
+            OrderType::Ask => (
+                OrderType::Bid,
+                self.book
+                    .iter_bids()
+                    .map(|(price, level)| (price, level.size()))
+                    .collect(),
+            ),
+        };
+
+        let mut fills = Vec::new();
+        for (price, size) in levels {
+            let crosses = match order.order_type {
+                OrderType::Bid => price <= order.limit_price,
+                OrderType::Ask => price >= order.limit_price,
+            };
+            if order.remaining_quantity.is_zero() || !crosses {
+                break;
+            }
+            let quantity = size.min(order.remaining_quantity);
+            order.fill(quantity).map_err(|err| err.to_string())?;
+            self.book
+                .apply_l2_update(
+                    opposite,
+                    price,
+                    L2Update::Delta(-quantity),
+                    order.event_time,
+                )
+                .map_err(|err| err.to_string())?;
+            fills.push(PaperFill {
+                exchange_id: order.exchange_id,
+                side: order.order_type,
+                price,
+                quantity,
+                liquidity: Liquidity::Taker,
+                time: order.event_time,
+            });
+        }
+
+        if order.is_active() {
+            let level_size = self.book.l2_size(order.order_type, order.limit_price);
+            self.resting.insert(
+                order.exchange_id,
+                QueuedOrder::new(
+                    order.order_type,
+                    order.limit_price,
+                    order.remaining_quantity,
+                    level_size,
+                ),
+            );
+        }
+        Ok((order, fills))
+    }
+
+    /// Pulls a resting order, returning what was left of it.
+    pub fn cancel(&mut self, exchange_id: u64) -> Option<QueuedOrder> {
+        self.resting.remove(&exchange_id)
+    }
+
+    /// Feeds `update` through and lets `strategy` react to it, passing it
+    /// every fill. Commands the trader turns down are returned with the
+    /// reason rather than ending the run.
+    pub fn run(
+        &mut self,
+        strategy: &mut impl PaperStrategy,
+        update: VenueUpdate,
+        time: DateTime<Utc>,
+    ) -> Result<Vec<(PaperCommand, String)>, String> {
+        for fill in self.on_update(update, time)? {
+            strategy.on_fill(&fill);
+        }
+        let mut rejected = Vec::new();
+        for command in strategy.on_update(&self.book, time) {
+            match &command {
+                PaperCommand::Place(order) => match self.place(*order.clone()) {
+                    Ok((_, fills)) => fills.iter().for_each(|fill| strategy.on_fill(fill)),
+                    Err(reason) => rejected.push((command, reason)),
+                },
+                PaperCommand::Cancel(exchange_id) => {
+                    if self.cancel(*exchange_id).is_none() {
+                        let reason = format!("Unknown order: {}", exchange_id);
+                        rejected.push((command, reason));
+                    }
+                }
+            }
+        }
+        Ok(rejected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::queue::Conservative;
+    use rust_decimal_macros::dec;
+
+    /// Joins the best bid once, with one lot.
+    #[derive(Default)]
+    struct JoinBid {
+        placed: bool,
+        fills: Vec<PaperFill>,
+    }
+
+    impl PaperStrategy for JoinBid {
+        fn on_update(&mut self, book: &LimitOrderBook, time: DateTime<Utc>) -> Vec<PaperCommand> {
+            match book.get_best_bid() {
+                Some(bid) if !self.placed => {
+                    self.placed = true;
+                    let order = Order::new(
+                        "BTC/USDT".to_string(),
+                        1,
+                        OrderType::Bid,
+                        dec!(1),
+                        bid,
+                        time,
+                        time,
+                    );
+                    vec![PaperCommand::Place(Box::new(order))]
+                }
+                _ => Vec::new(),
+            }
+        }
+
+        fn on_fill(&mut self, fill: &PaperFill) {
+            self.fills.push(fill.clone());
+        }
+    }
+
+    #[test]
+    fn test_paper_trading() {
+        let now = Utc::now();
+        let level = |side, price, size| VenueUpdate::Level { side, price, size };
+        let mut trader = PaperTrader::new(Conservative);
+        let mut strategy = JoinBid::default();
+        trader
+            .run(
+                &mut strategy,
+                level(OrderType::Ask, dec!(101), dec!(3)),
+                now,
+            )
+            .unwrap();
+        assert!(!strategy.placed);
+        trader
+            .run(
+                &mut strategy,
+                level(OrderType::Bid, dec!(100), dec!(5)),
+                now,
+            )
+            .unwrap();
+        assert_eq!(trader.resting()[&1].ahead(), dec!(5));
+
+        // Two cancels from behind leave the queue ahead alone; a trade of
+        // four then leaves one lot in front.
+        trader
+            .run(
+                &mut strategy,
+                level(OrderType::Bid, dec!(100), dec!(7)),
+                now,
+            )
+            .unwrap();
+        trader
+            .run(
+                &mut strategy,
+                level(OrderType::Bid, dec!(100), dec!(5)),
+                now,
+            )
+            .unwrap();
+        let trade = |quantity| VenueUpdate::Trade {
+            price: dec!(100),
+            quantity,
+        };
+        trader.run(&mut strategy, trade(dec!(4)), now).unwrap();
+        assert!(strategy.fills.is_empty());
+        trader.run(&mut strategy, trade(dec!(2)), now).unwrap();
+        assert_eq!(strategy.fills.len(), 1);
+        assert_eq!(strategy.fills[0].liquidity, Liquidity::Maker);
+        assert!(trader.resting().is_empty());
+
+        // A marketable order takes the mirrored size, which stays taken
+        // until the venue next reports the level.
+        let buy = Order::new(
+            "BTC/USDT".to_string(),
+            2,
+            OrderType::Bid,
+            dec!(5),
+            dec!(101),
+            now,
+            now,
+        );
+        let (order, fills) = trader.place(buy).unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!((fills[0].price, fills[0].quantity), (dec!(101), dec!(3)));
+        assert_eq!(fills[0].liquidity, Liquidity::Taker);
+        assert_eq!(order.remaining_quantity, dec!(2));
+        assert_eq!(trader.book().get_best_ask(), None);
+        assert_eq!(trader.resting()[&2].price, dec!(101));
+        assert!(trader.cancel(2).is_some());
+    }
+
+    /// Sends its commands on the first update only.
+    struct Scripted(Vec<PaperCommand>);
+
+    impl PaperStrategy for Scripted {
+        fn on_update(&mut self, _book: &LimitOrderBook, _time: DateTime<Utc>) -> Vec<PaperCommand> {
+            std::mem::take(&mut self.0)
+        }
+    }
+
+    fn order(exchange_id: u64, side: OrderType, quantity: Decimal, price: Decimal) -> Order {
+        let now = Utc::now();
+        Order::new(
+            "BTC/USDT".to_string(),
+            exchange_id,
+            side,
+            quantity,
+            price,
+            now,
+            now,
+        )
+    }
+
+    #[test]
+    fn test_sell_sweeps_bid_levels() {
+        let now = Utc::now();
+        let mut trader = PaperTrader::new(Conservative);
+        for (price, size) in [
+            (dec!(100), dec!(2)),
+            (dec!(99), dec!(3)),
+            (dec!(98), dec!(4)),
+        ] {
+            let update = VenueUpdate::Level {
+                side: OrderType::Bid,
+                price,
+                size,
+            };
+            assert!(trader.on_update(update, now).unwrap().is_empty());
+        }
+
+        // The sell stops at its limit, above the 98 level.
+        let (sold, fills) = trader
+            .place(order(1, OrderType::Ask, dec!(4), dec!(99)))
+            .unwrap();
+        let taken: Vec<_> = fills
+            .iter()
+            .map(|fill| (fill.price, fill.quantity))
+            .collect();
+        assert_eq!(taken, vec![(dec!(100), dec!(2)), (dec!(99), dec!(2))]);
+        assert!(fills.iter().all(|fill| fill.side == OrderType::Ask));
+        assert!(!sold.is_active());
+        assert!(trader.resting().is_empty());
+        assert_eq!(trader.book().get_best_bid(), Some(dec!(99)));
+        assert_eq!(trader.book().l2_size(OrderType::Bid, dec!(99)), dec!(1));
+
+        // The venue's next report for the level replaces the mirror.
+        let update = VenueUpdate::Level {
+            side: OrderType::Bid,
+            price: dec!(99),
+            size: dec!(6),
+        };
+        trader.on_update(update, now).unwrap();
+        assert_eq!(trader.book().l2_size(OrderType::Bid, dec!(99)), dec!(6));
+    }
+
+    #[test]
+    fn test_rejected_commands() {
+        let now = Utc::now();
+        let mut trader = PaperTrader::new(Conservative);
+        assert!(trader
+            .place(order(1, OrderType::Bid, dec!(0), dec!(90)))
+            .is_err());
+        let (resting, fills) = trader
+            .place(order(1, OrderType::Bid, dec!(2), dec!(90)))
+            .unwrap();
+        assert!(fills.is_empty());
+        assert!(resting.is_active());
+        assert_eq!(trader.resting()[&1].ahead(), dec!(0));
+        let duplicate = trader.place(order(1, OrderType::Bid, dec!(1), dec!(91)));
+        assert_eq!(duplicate.unwrap_err(), "Duplicate exchange id: 1");
+
+        // Turned-down commands come back with their reasons while the
+        // rest still go through.
+        let mut strategy = Scripted(vec![
+            PaperCommand::Cancel(42),
+            PaperCommand::Place(Box::new(order(1, OrderType::Ask, dec!(1), dec!(95)))),
+            PaperCommand::Cancel(1),
+        ]);
+        let update = VenueUpdate::Trade {
+            price: dec!(90),
+            quantity: dec!(0),
+        };
+        let rejected = trader.run(&mut strategy, update, now).unwrap();
+        let reasons: Vec<_> = rejected.iter().map(|(_, reason)| reason.as_str()).collect();
+        assert_eq!(
+            reasons,
+            vec!["Unknown order: 42", "Duplicate exchange id: 1"]
+        );
+        assert_eq!(rejected[0].0, PaperCommand::Cancel(42));
+        assert!(trader.resting().is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+//! Estimating when a simulated passive order would have filled, when all
+//! the backtest has is L2 data: trades and aggregate level sizes.
+//!
+//! The order joins the back of its level. Trades at its price use up the
+//! quantity ahead of it before reaching it, and a trade through its price
+//! fills it outright. An L2 feed doesn't say where in the queue a cancel
+//! came from, so how much of a level's shrinkage was ahead of the order is
+//! left to a `QueueModel`.
+
+use crate::limit_order_book::order::OrderType;
+use rust_decimal::prelude::*;
+
+/// Decides where in the queue cancels came from.
+pub trait QueueModel {
+    /// How much of `cancelled` leaving a level of `level_size`, with
+    /// `ahead` of it in front of our order, was ahead of it. Results outside
+    /// `[0, min(ahead, cancelled)]` are clamped.
+    fn cancelled_ahead(&self, ahead: Decimal, level_size: Decimal, cancelled: Decimal) -> Decimal;
+}
+
+/// Cancels come from behind the order first, so it only moves up once
+/// everything behind it has gone. Never overstates fills.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Conservative;
+
+impl QueueModel for Conservative {
+    fn cancelled_ahead(&self, ahead: Decimal, level_size: Decimal, cancelled: Decimal) -> Decimal {
+        cancelled - (level_size - ahead)
+    }
+}
+
+/// Cancels are spread evenly over the level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProRata;
+
+impl QueueModel for ProRata {
+    fn cancelled_ahead(&self, ahead: Decimal, level_size: Decimal, cancelled: Decimal) -> Decimal {
+        if level_size.is_zero() {
+            return Decimal::ZERO;
+        }
+        cancelled * ahead / level_size
+    }
+}
+
+/// A passive order that exists only in the backtest, tracked against the
+/// observed level it would have rested at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedOrder {
+    pub side: OrderType,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    filled: Decimal,
+    /// Observed quantity in front of the order.
+    ahead: Decimal,
+    /// Observed size of the level, which doesn't include the order.
+    level_size: Decimal,
+}
+
+impl QueuedOrder {
+    /// Joins the back of a level currently showing `level_size`.
+    pub fn new(side: OrderType, price: Decimal, quantity: Decimal, level_size: Decimal) -> Self {
+        Self {
+            side,
+            price,
+            quantity,
+            filled: Decimal::ZERO,
+            ahead: level_size,
+            level_size,
+        }
+    }
+
+    pub fn ahead(&self) -> Decimal {
+        self.ahead
+    }
+
+    pub fn filled(&self) -> Decimal {
+        self.filled
+    }
+
+    pub fn remaining(&self) -> Decimal {
+        self.quantity - self.filled
+    }
+
+    pub fn is_filled(&self) -> bool {
+        self.remaining().is_zero()
+    }
+
+    /// Takes in a trade printed at `price`, returning how much of the order
+    /// it would have filled.
+    pub fn on_trade(&mut self, price: Decimal, quantity: Decimal) -> Decimal {
+        let through = match self.side {
+            OrderType::Bid => price < self.price,
+            OrderType::Ask => price > self.price,
+        };
+        let fill = if through {
+            self.remaining()
+        } else if price == self.price {
+            let reached = (quantity - self.ahead).max(Decimal::ZERO);
+            self.ahead = (self.ahead - quantity).max(Decimal::ZERO);
+            self.level_size = (self.level_size - quantity).max(Decimal::ZERO);
+            reached.min(self.remaining())
+        } else {
+            Decimal::ZERO
+        };
+        self.filled += fill;
+        fill
+    }
+
+    /// Takes in the level's new observed size. Trades were already taken
+    /// off in `on_trade`, so growth is new orders joining behind and any
+    /// other shrinkage is cancels, placed by `model`.
+    pub fn on_level_update(&mut self, size: Decimal, model: &dyn QueueModel) {
+        if size < self.level_size {
+            let cancelled = self.level_size - size;
+            let ahead = model
+                .cancelled_ahead(self.ahead, self.level_size, cancelled)
+                .max(Decimal::ZERO)
+                .min(self.ahead.min(cancelled));
+            self.ahead -= ahead;
+        }
+        self.level_size = size;
+        self.ahead = self.ahead.min(size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_queue_models() {
+        let mut conservative = QueuedOrder::new(OrderType::Bid, dec!(100), dec!(5), dec!(10));
+        let mut pro_rata = conservative.clone();
+
+        // New orders join behind, then 8 of the 20 cancel.
+        for order in [&mut conservative, &mut pro_rata] {
+            order.on_level_update(dec!(20), &Conservative);
+        }
+        conservative.on_level_update(dec!(12), &Conservative);
+        pro_rata.on_level_update(dec!(12), &ProRata);
+        assert_eq!(conservative.ahead(), dec!(10));
+        assert_eq!(pro_rata.ahead(), dec!(6));
+
+        assert_eq!(conservative.on_trade(dec!(100), dec!(8)), dec!(0));
+        assert_eq!(pro_rata.on_trade(dec!(100), dec!(8)), dec!(2));
+        assert_eq!(conservative.on_trade(dec!(100.5), dec!(8)), dec!(0));
+        assert_eq!(conservative.ahead(), dec!(2));
+        assert_eq!(conservative.on_trade(dec!(100), dec!(4)), dec!(2));
+
+        // Selling through the bid fills what's left.
+        assert_eq!(conservative.on_trade(dec!(99.5), dec!(1)), dec!(3));
+        assert!(conservative.is_filled());
+    }
+
+    /// Claims every cancel came from ahead, and then some.
+    struct Overstated;
+
+    impl QueueModel for Overstated {
+        fn cancelled_ahead(&self, _: Decimal, _: Decimal, cancelled: Decimal) -> Decimal {
+            cancelled * dec!(10)
+        }
+    }
+
+    #[test]
+    fn test_asks_and_clamping() {
+        let mut ask = QueuedOrder::new(OrderType::Ask, dec!(101), dec!(3), dec!(4));
+        ask.on_level_update(dec!(6), &Conservative);
+        // Only the 4 ahead can have cancelled from ahead, however many left.
+        ask.on_level_update(dec!(1), &Overstated);
+        assert_eq!(ask.ahead(), dec!(0));
+
+        assert_eq!(ask.on_trade(dec!(100.5), dec!(5)), dec!(0));
+        assert_eq!(ask.on_trade(dec!(101), dec!(2)), dec!(2));
+        assert_eq!(ask.remaining(), dec!(1));
+        // A buy through the offer fills only what's left.
+        assert_eq!(ask.on_trade(dec!(102), dec!(50)), dec!(1));
+        assert_eq!((ask.filled(), ask.is_filled()), (dec!(3), true));
+
+        assert_eq!(ProRata.cancelled_ahead(dec!(0), dec!(0), dec!(5)), dec!(0));
+        let mut empty = QueuedOrder::new(OrderType::Bid, dec!(100), dec!(1), dec!(0));
+        empty.on_level_update(dec!(0), &ProRata);
+        assert_eq!(empty.on_trade(dec!(100), dec!(1)), dec!(1));
+    }
+}
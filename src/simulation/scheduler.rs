@@ -0,0 +1,135 @@
+use chrono::{DateTime, Utc};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+};
+
+struct Entry<T> {
+    time: DateTime<Utc>,
+    seq: u64,
+    item: T,
+}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.time, self.seq) == (other.time, other.seq)
+    }
+}
+
+impl<T> Eq for Entry<T> {}
+
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.time, self.seq).cmp(&(other.time, other.seq))
+    }
+}
+
+/// Priority queue of items keyed by the time they become due. Items due at
+/// the same instant come out in the order they were scheduled.
+pub struct Scheduler<T> {
+    queue: BinaryHeap<Reverse<Entry<T>>>,
+    next_seq: u64,
+}
+
+impl<T> Default for Scheduler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Scheduler<T> {
+    pub fn new() -> Self {
+        Self {
+            queue: BinaryHeap::new(),
+            next_seq: 0,
+        }
+    }
+
+    pub fn schedule(&mut self, time: DateTime<Utc>, item: T) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.queue.push(Reverse(Entry { time, seq, item }));
+    }
+
+    /// When the earliest pending item becomes due.
+    pub fn next_time(&self) -> Option<DateTime<Utc>> {
+        self.queue.peek().map(|Reverse(entry)| entry.time)
+    }
+
+    /// Removes and returns the earliest item if it is due by `now`.
+    pub fn pop_due(&mut self, now: DateTime<Utc>) -> Option<(DateTime<Utc>, T)> {
+        if self.next_time()? > now {
+            return None;
+        }
+        self.queue
+            .pop()
+            .map(|Reverse(entry)| (entry.time, entry.item))
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_pops_in_time_then_insertion_order() {
+        let start = Utc::now();
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(start + Duration::milliseconds(20), "c");
+        scheduler.schedule(start + Duration::milliseconds(10), "a");
+        scheduler.schedule(start + Duration::milliseconds(10), "b");
+        scheduler.schedule(start + Duration::milliseconds(30), "d");
+
+        assert_eq!(
+            scheduler.next_time(),
+            Some(start + Duration::milliseconds(10))
+        );
+        assert_eq!(scheduler.pop_due(start), None);
+
+        let mut due = Vec::new();
+        while let Some((_, item)) = scheduler.pop_due(start + Duration::milliseconds(20)) {
+            due.push(item);
+        }
+        assert_eq!(due, vec!["a", "b", "c"]);
+        assert_eq!(scheduler.len(), 1);
+    }
+
+    #[test]
+    fn test_interleaved_scheduling() {
+        let start = Utc::now();
+        let mut scheduler = Scheduler::default();
+        assert!(scheduler.is_empty());
+        assert_eq!(scheduler.next_time(), None);
+        assert_eq!(scheduler.pop_due(start), None);
+
+        // An item scheduled while draining, even one already overdue,
+        // still comes out in time order.
+        scheduler.schedule(start + Duration::milliseconds(10), 1);
+        assert_eq!(
+            scheduler.pop_due(start + Duration::milliseconds(10)),
+            Some((start + Duration::milliseconds(10), 1))
+        );
+        scheduler.schedule(start + Duration::milliseconds(30), 3);
+        scheduler.schedule(start - Duration::milliseconds(5), 2);
+        let due = scheduler.pop_due(start + Duration::milliseconds(10));
+        assert_eq!(due, Some((start - Duration::milliseconds(5), 2)));
+        assert_eq!(scheduler.pop_due(start + Duration::milliseconds(10)), None);
+        assert_eq!(scheduler.len(), 1);
+        assert!(!scheduler.is_empty());
+    }
+}
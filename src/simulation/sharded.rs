@@ -0,0 +1,288 @@
+//! Markets spread over worker threads, for simulations with many of them.
+//!
+//! Each shard is a thread with its own `MatchingEngine`, and each market
+//! lives on exactly one shard, chosen by hashing its pair. Markets don't
+//! interact, so shards never need to talk to each other; what they report
+//! comes back merged on one channel. Within a shard, events keep the order
+//! of the commands that caused them. Across shards there is no order.
+
+use super::latency::{CommandOutcome, EngineCommand};
+use crate::{
+    config::MarketConfig,
+    matching_engine::{
+        drop_copy::DropCopy,
+        engine::{MatchingEngine, TradingPair},
+    },
+};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+};
+
+/// What a shard reports, in the combined stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShardEvent {
+    /// The result of the command `submit` numbered `command`. It comes
+    /// after the command's drop copies.
+    Outcome {
+        shard: usize,
+        command: u64,
+        outcome: Result<CommandOutcome, String>,
+    },
+    DropCopy {
+        shard: usize,
+        copy: DropCopy,
+    },
+}
+
+enum Message {
+    AddMarket(Box<MarketConfig>),
+    Command(u64, Box<EngineCommand>),
+}
+
+pub struct ShardedEngine {
+    shards: Vec<Sender<Message>>,
+    workers: Vec<JoinHandle<()>>,
+    events: Receiver<ShardEvent>,
+    next_command: u64,
+    next_exchange_id: u64,
+}
+
+impl ShardedEngine {
+    /// Starts `shards` workers, each building its engine with `init`.
+    pub fn spawn<F>(shards: usize, init: F) -> Result<Self, String>
+    where
+        F: Fn() -> MatchingEngine + Clone + Send + 'static,
+    {
+        if shards == 0 {
+            return Err("A sharded engine needs at least one shard".to_string());
+        }
+        let (events, receiver) = mpsc::channel();
+        let mut senders = Vec::with_capacity(shards);
+        let mut workers = Vec::with_capacity(shards);
+        for shard in 0..shards {
+            let (sender, messages) = mpsc::channel();
+            let (init, events) = (init.clone(), events.clone());
+            workers.push(thread::spawn(move || run(shard, init(), messages, events)));
+            senders.push(sender);
+        }
+        Ok(Self {
+            shards: senders,
+            workers,
+            events: receiver,
+            next_command: 0,
+            next_exchange_id: 1,
+        })
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// The shard that holds `pair`'s market.
+    pub fn shard_for(&self, pair: &TradingPair) -> usize {
+        let mut hasher = DefaultHasher::new();
+        pair.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    /// Exchange IDs unique across shards, for the orders of submitted
+    /// commands.
+    pub fn next_exchange_id(&mut self) -> u64 {
+        let exchange_id = self.next_exchange_id;
+        self.next_exchange_id += 1;
+        exchange_id
+    }
+
+    pub fn add_market(&self, config: MarketConfig) {
+        let shard = self.shard_for(&config.pair);
+        self.send(shard, Message::AddMarket(Box::new(config)));
+    }
+
+    /// Routes `command` to its market's shard and returns the number its
+    /// outcome will carry.
+    pub fn submit(&mut self, command: EngineCommand) -> u64 {
+        let pair = match &command {
+            EngineCommand::Limit { pair, .. }
+            | EngineCommand::Market { pair, .. }
+            | EngineCommand::Cancel { pair, .. }
+            | EngineCommand::Quote { pair, .. } => pair,
+        };
+        let shard = self.shard_for(pair);
+        self.next_command += 1;
+        self.send(
+            shard,
+            Message::Command(self.next_command, Box::new(command)),
+        );
+        self.next_command
+    }
+
+    /// Events from all shards, as they arrive.
+    pub fn events(&self) -> &Receiver<ShardEvent> {
+        &self.events
+    }
+
+    /// Lets the shards finish what was submitted and stops them. Returns
+    /// the events not yet received.
+    pub fn shutdown(self) -> Vec<ShardEvent> {
+        drop(self.shards);
+        for worker in self.workers {
+            worker.join().expect("shard panicked");
+        }
+        self.events.try_iter().collect()
+    }
+
+    fn send(&self, shard: usize, message: Message) {
+        self.shards[shard]
+            .send(message)
+            .expect("shard stopped unexpectedly");
+    }
+}
+
+fn run(
+    shard: usize,
+    mut engine: MatchingEngine,
+    messages: Receiver<Message>,
+    events: Sender<ShardEvent>,
+) {
+    let drop_copies = engine.subscribe_drop_copy();
+    for message in messages {
+        let (command, outcome) = match message {
+            Message::AddMarket(config) => {
+                engine.add_market(*config);
+                continue;
+            }
+            Message::Command(command, engine_command) => {
+                (command, engine_command.apply(&mut engine))
+            }
+        };
+        // Sending only fails once the `ShardedEngine` has been dropped, and
+        // then nobody is listening anyway.
+        for copy in drop_copies.try_iter() {
+            let _ = events.send(ShardEvent::DropCopy { shard, copy });
+        }
+        let _ = events.send(ShardEvent::Outcome {
+            shard,
+            command,
+            outcome,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::{Order, OrderType};
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_sharded_engine() {
+        let mut engine = ShardedEngine::spawn(3, MatchingEngine::new).unwrap();
+        let pairs: Vec<TradingPair> = ["BTC", "ETH", "SOL", "ADA", "XRP"]
+            .iter()
+            .map(|base| TradingPair::new(base.to_string(), "USDT".to_string()))
+            .collect();
+        for pair in &pairs {
+            engine.add_market(MarketConfig::new(pair.clone()));
+        }
+
+        let mut submitted = Vec::new();
+        for pair in &pairs {
+            for side in [OrderType::Ask, OrderType::Bid] {
+                let order = Order::new(
+                    pair.to_string(),
+                    engine.next_exchange_id(),
+                    side,
+                    dec!(1),
+                    dec!(100),
+                    Utc::now(),
+                    Utc::now(),
+                );
+                let command = engine.submit(EngineCommand::Limit {
+                    pair: pair.clone(),
+                    order,
+                });
+                submitted.push((command, engine.shard_for(pair)));
+            }
+        }
+        let unknown = TradingPair::new("DOGE".to_string(), "USDT".to_string());
+        let rejected = engine.submit(EngineCommand::Cancel {
+            pair: unknown,
+            exchange_id: 1,
+        });
+
+        let events = engine.shutdown();
+        let mut fills = 0;
+        for (command, shard) in submitted {
+            let outcome = events.iter().find_map(|event| match event {
+                ShardEvent::Outcome {
+                    shard: from,
+                    command: number,
+                    outcome,
+                } if *number == command => Some((*from, outcome.clone())),
+                _ => None,
+            });
+            let (from, outcome) = outcome.unwrap();
+            assert_eq!(from, shard);
+            if let Ok(CommandOutcome::Placed { fills: placed, .. }) = outcome {
+                fills += placed.len();
+            }
+        }
+        assert_eq!(fills, pairs.len());
+        let executions = events
+            .iter()
+            .filter(|event| matches!(event, ShardEvent::DropCopy { .. }))
+            .count();
+        assert_eq!(executions, 2 * pairs.len());
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ShardEvent::Outcome { command, outcome: Err(_), .. } if *command == rejected
+        )));
+    }
+
+    #[test]
+    fn test_order_within_a_shard() {
+        assert!(ShardedEngine::spawn(0, MatchingEngine::new).is_err());
+        let mut engine = ShardedEngine::spawn(1, MatchingEngine::new).unwrap();
+        assert_eq!(engine.shard_count(), 1);
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        assert_eq!(engine.shard_for(&pair), 0);
+        engine.add_market(MarketConfig::new(pair.clone()));
+
+        let mut exchange_ids = Vec::new();
+        for side in [OrderType::Ask, OrderType::Bid, OrderType::Bid] {
+            let exchange_id = engine.next_exchange_id();
+            exchange_ids.push(exchange_id);
+            let order = Order::new(
+                pair.to_string(),
+                exchange_id,
+                side,
+                dec!(1),
+                dec!(100),
+                Utc::now(),
+                Utc::now(),
+            );
+            engine.submit(EngineCommand::Limit {
+                pair: pair.clone(),
+                order,
+            });
+        }
+
+        assert_eq!(exchange_ids, vec![1, 2, 3]);
+
+        // Commands come back in order, each after its drop copies.
+        let events: Vec<ShardEvent> = engine.events().iter().take(5).collect();
+        let kinds: Vec<Option<u64>> = events
+            .iter()
+            .map(|event| match event {
+                ShardEvent::Outcome { command, .. } => Some(*command),
+                ShardEvent::DropCopy { .. } => None,
+            })
+            .collect();
+        assert_eq!(kinds, vec![Some(1), None, None, Some(2), Some(3)]);
+        assert!(engine.shutdown().is_empty());
+    }
+}
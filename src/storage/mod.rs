@@ -0,0 +1,414 @@
+//! Rows the engine's state is stored as outside the process: orders as they
+//! last stood, trades, and account balances. Trades are
+//! `export::TradeRecord`s.
+//!
+//! Backends implement `Storage`: `sqlite` behind the `sqlite` feature and
+//! `postgres` behind the `postgres` feature. `Batched` sits in front of
+//! either and saves rows in batches, which is what keeps a database up with
+//! a busy market.
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+use crate::{
+    limit_order_book::order::{Order, OrderStatus, OrderType},
+    matching_engine::accounts::Accounts,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::{collections::BTreeMap, future::Future};
+
+pub use crate::export::TradeRecord;
+
+/// An order as of its latest event, keyed by exchange ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderRecord {
+    pub exchange_id: u64,
+    pub pair: String,
+    pub client: String,
+    pub side: OrderType,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub filled_quantity: Decimal,
+    pub remaining_quantity: Decimal,
+    pub status: OrderStatus,
+    pub fees: Decimal,
+    pub entry_time: DateTime<Utc>,
+    pub event_time: DateTime<Utc>,
+}
+
+impl OrderRecord {
+    pub fn from_order(pair: impl Into<String>, order: &Order) -> Self {
+        Self {
+            exchange_id: order.exchange_id,
+            pair: pair.into(),
+            client: order.client.clone(),
+            side: order.order_type,
+            price: order.limit_price,
+            quantity: order.shares,
+            filled_quantity: order.filled_quantity,
+            remaining_quantity: order.remaining_quantity,
+            status: order.status,
+            fees: order.fees,
+            entry_time: order.entry_time,
+            event_time: order.event_time,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        matches!(self.status, OrderStatus::New | OrderStatus::PartiallyFilled)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceRecord {
+    pub account: String,
+    pub asset: String,
+    pub amount: Decimal,
+}
+
+impl BalanceRecord {
+    /// Every balance of every account, by account and then asset.
+    pub fn from_accounts(accounts: &Accounts) -> Vec<Self> {
+        accounts
+            .iter()
+            .flat_map(|account| {
+                account.balances().map(|(asset, amount)| BalanceRecord {
+                    account: account.id.to_string(),
+                    asset: asset.to_string(),
+                    amount,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Somewhere to keep orders, trades and balances. Methods are async so a
+/// networked database doesn't block the caller; SQLite's finish at once.
+pub trait Storage {
+    type Error: std::error::Error;
+
+    /// Inserts each order, or replaces the stored one with the same
+    /// exchange ID.
+    fn save_orders(
+        &mut self,
+        orders: &[OrderRecord],
+    ) -> impl Future<Output = Result<(), Self::Error>>;
+
+    fn save_trades(
+        &mut self,
+        trades: &[TradeRecord],
+    ) -> impl Future<Output = Result<(), Self::Error>>;
+
+    /// Sets each balance, leaving those of other accounts and assets as
+    /// they were.
+    fn save_balances(
+        &mut self,
+        balances: &[BalanceRecord],
+    ) -> impl Future<Output = Result<(), Self::Error>>;
+
+    fn order(
+        &self,
+        exchange_id: u64,
+    ) -> impl Future<Output = Result<Option<OrderRecord>, Self::Error>>;
+
+    /// The pair's new and partially filled orders, by exchange ID.
+    fn open_orders(
+        &self,
+        pair: &str,
+    ) -> impl Future<Output = Result<Vec<OrderRecord>, Self::Error>>;
+
+    /// Trades at or after `from` and before `to`, in the order they were
+    /// saved.
+    fn trades_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> impl Future<Output = Result<Vec<TradeRecord>, Self::Error>>;
+
+    /// The account's balances, by asset.
+    fn balances(
+        &self,
+        account: &str,
+    ) -> impl Future<Output = Result<Vec<BalanceRecord>, Self::Error>>;
+}
+
+/// Buffers orders and trades for a `Storage` and saves them once
+/// `batch_size` are waiting. Of an order updated several times while
+/// buffered, only the latest state is saved.
+pub struct Batched<S> {
+    storage: S,
+    batch_size: usize,
+    orders: BTreeMap<u64, OrderRecord>,
+    trades: Vec<TradeRecord>,
+}
+
+impl<S: Storage> Batched<S> {
+    pub fn new(storage: S, batch_size: usize) -> Self {
+        Self {
+            storage,
+            batch_size,
+            orders: BTreeMap::new(),
+            trades: Vec::new(),
+        }
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// For saving balances and other writes that aren't batched; flush
+    /// first if they have to land after the buffered rows.
+    pub fn storage_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    /// Rows waiting to be saved.
+    pub fn pending(&self) -> usize {
+        self.orders.len() + self.trades.len()
+    }
+
+    pub async fn push_order(&mut self, order: OrderRecord) -> Result<(), S::Error> {
+        self.orders.insert(order.exchange_id, order);
+        self.flush_if_full().await
+    }
+
+    pub async fn push_trade(&mut self, trade: TradeRecord) -> Result<(), S::Error> {
+        self.trades.push(trade);
+        self.flush_if_full().await
+    }
+
+    /// Saves everything buffered, orders first. What fails to save stays
+    /// buffered for the next try.
+    pub async fn flush(&mut self) -> Result<(), S::Error> {
+        if !self.orders.is_empty() {
+            let orders: Vec<OrderRecord> = self.orders.values().cloned().collect();
+            self.storage.save_orders(&orders).await?;
+            self.orders.clear();
+        }
+        if !self.trades.is_empty() {
+            self.storage.save_trades(&self.trades).await?;
+            self.trades.clear();
+        }
+        Ok(())
+    }
+
+    async fn flush_if_full(&mut self) -> Result<(), S::Error> {
+        if self.pending() >= self.batch_size {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn status_name(status: OrderStatus) -> &'static str {
+    match status {
+        OrderStatus::New => "new",
+        OrderStatus::PartiallyFilled => "partially_filled",
+        OrderStatus::Filled => "filled",
+        OrderStatus::Cancelled => "cancelled",
+    }
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn parse_status(name: &str) -> Result<OrderStatus, String> {
+    match name {
+        "new" => Ok(OrderStatus::New),
+        "partially_filled" => Ok(OrderStatus::PartiallyFilled),
+        "filled" => Ok(OrderStatus::Filled),
+        "cancelled" => Ok(OrderStatus::Cancelled),
+        _ => Err(format!("Invalid order status: {:?}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+    use std::{
+        fmt,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    /// Keeps what each save was given, or turns trades away while
+    /// `fail_trades` is set.
+    #[derive(Default)]
+    struct Recorder {
+        order_batches: Vec<Vec<OrderRecord>>,
+        trade_batches: Vec<Vec<TradeRecord>>,
+        fail_trades: bool,
+    }
+
+    impl Storage for Recorder {
+        type Error = fmt::Error;
+
+        async fn save_orders(&mut self, orders: &[OrderRecord]) -> Result<(), fmt::Error> {
+            self.order_batches.push(orders.to_vec());
+            Ok(())
+        }
+
+        async fn save_trades(&mut self, trades: &[TradeRecord]) -> Result<(), fmt::Error> {
+            if self.fail_trades {
+                return Err(fmt::Error);
+            }
+            self.trade_batches.push(trades.to_vec());
+            Ok(())
+        }
+
+        async fn save_balances(&mut self, _: &[BalanceRecord]) -> Result<(), fmt::Error> {
+            Ok(())
+        }
+
+        async fn order(&self, _: u64) -> Result<Option<OrderRecord>, fmt::Error> {
+            Ok(None)
+        }
+
+        async fn open_orders(&self, _: &str) -> Result<Vec<OrderRecord>, fmt::Error> {
+            Ok(Vec::new())
+        }
+
+        async fn trades_between(
+            &self,
+            _: DateTime<Utc>,
+            _: DateTime<Utc>,
+        ) -> Result<Vec<TradeRecord>, fmt::Error> {
+            Ok(Vec::new())
+        }
+
+        async fn balances(&self, _: &str) -> Result<Vec<BalanceRecord>, fmt::Error> {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Runs a future that never waits.
+    fn ready<T>(future: impl Future<Output = T>) -> T {
+        match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("the future waited"),
+        }
+    }
+
+    fn time() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    fn order(exchange_id: u64, status: OrderStatus) -> OrderRecord {
+        let mut order = Order::new(
+            "BTC/USDT".to_string(),
+            exchange_id,
+            OrderType::Ask,
+            dec!(1),
+            dec!(100),
+            time(),
+            time(),
+        );
+        order.status = status;
+        OrderRecord::from_order("BTC/USDT", &order)
+    }
+
+    fn trade() -> TradeRecord {
+        TradeRecord {
+            time: time(),
+            pair: "BTC/USDT".to_string(),
+            price: dec!(100),
+            quantity: dec!(1),
+            side: OrderType::Bid,
+            maker_id: 1,
+            taker_id: 2,
+        }
+    }
+
+    #[test]
+    fn test_batching() {
+        let trade = trade();
+
+        let mut batched = Batched::new(Recorder::default(), 3);
+        ready(batched.push_order(order(1, OrderStatus::New))).unwrap();
+        ready(batched.push_order(order(1, OrderStatus::PartiallyFilled))).unwrap();
+        ready(batched.push_order(order(2, OrderStatus::Filled))).unwrap();
+        assert_eq!(batched.pending(), 2);
+        assert!(batched.storage().order_batches.is_empty());
+        // The third row fills the batch, which saves each order once.
+        ready(batched.push_trade(trade.clone())).unwrap();
+        assert_eq!(batched.pending(), 0);
+        assert_eq!(
+            batched.storage().order_batches,
+            [vec![
+                order(1, OrderStatus::PartiallyFilled),
+                order(2, OrderStatus::Filled)
+            ]]
+        );
+        assert_eq!(batched.storage().trade_batches, [vec![trade.clone()]]);
+
+        ready(batched.push_trade(trade.clone())).unwrap();
+        ready(batched.flush()).unwrap();
+        assert_eq!(batched.storage().trade_batches.len(), 2);
+        assert_eq!(batched.storage().order_batches.len(), 1);
+    }
+
+    #[test]
+    fn test_failed_flush_keeps_rows() {
+        let storage = Recorder {
+            fail_trades: true,
+            ..Recorder::default()
+        };
+        let mut batched = Batched::new(storage, 10);
+        ready(batched.push_order(order(1, OrderStatus::New))).unwrap();
+        ready(batched.push_trade(trade())).unwrap();
+        // The orders are saved before the trades fail, so only the trade
+        // waits for the next try.
+        assert!(ready(batched.flush()).is_err());
+        assert_eq!(batched.pending(), 1);
+        assert_eq!(batched.storage().order_batches.len(), 1);
+
+        batched.storage_mut().fail_trades = false;
+        ready(batched.push_trade(trade())).unwrap();
+        ready(batched.flush()).unwrap();
+        assert_eq!(batched.pending(), 0);
+        assert_eq!(batched.storage().trade_batches, [vec![trade(), trade()]]);
+        assert_eq!(batched.storage().order_batches.len(), 1);
+    }
+
+    #[test]
+    fn test_records() {
+        let mut record = order(7, OrderStatus::New);
+        assert_eq!(
+            (record.exchange_id, record.side, record.price),
+            (7, OrderType::Ask, dec!(100))
+        );
+        assert_eq!(
+            (record.quantity, record.remaining_quantity),
+            (dec!(1), dec!(1))
+        );
+        assert!(record.is_open());
+        record.status = OrderStatus::PartiallyFilled;
+        assert!(record.is_open());
+        record.status = OrderStatus::Cancelled;
+        assert!(!record.is_open());
+
+        let mut accounts = Accounts::new();
+        assert!(BalanceRecord::from_accounts(&accounts).is_empty());
+        accounts.open("bob".into(), None).unwrap();
+        accounts.open("alice".into(), None).unwrap();
+        accounts.deposit("bob", "USDT", dec!(50)).unwrap();
+        accounts.deposit("alice", "USDT", dec!(10)).unwrap();
+        accounts.deposit("alice", "BTC", dec!(1)).unwrap();
+        let balances: Vec<_> = BalanceRecord::from_accounts(&accounts)
+            .into_iter()
+            .map(|record| (record.account, record.asset, record.amount))
+            .collect();
+        assert_eq!(
+            balances,
+            [
+                ("alice".to_string(), "BTC".to_string(), dec!(1)),
+                ("alice".to_string(), "USDT".to_string(), dec!(10)),
+                ("bob".to_string(), "USDT".to_string(), dec!(50)),
+            ]
+        );
+    }
+}
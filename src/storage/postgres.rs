@@ -0,0 +1,255 @@
+//! Postgres storage, for running the engine as a service.
+//!
+//! The schema lives in `migrations/postgres` and is applied by `migrate`,
+//! which records what it has run so it can be called on every start.
+//! Decimals are stored as `NUMERIC` and times as `TIMESTAMPTZ`, both
+//! exactly. Each save is one transaction of multi-row statements, so pair
+//! the storage with `Batched` to keep up with a high trade rate.
+
+use super::{parse_status, status_name, BalanceRecord, OrderRecord, Storage, TradeRecord};
+use crate::{export::side, limit_order_book::order::OrderType};
+use chrono::{DateTime, Utc};
+use sqlx::{
+    error::BoxDynError,
+    migrate::{MigrateError, Migrator},
+    postgres::{PgPool, PgRow},
+    Postgres, QueryBuilder, Row,
+};
+use std::{collections::BTreeMap, str::FromStr};
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("migrations/postgres");
+
+/// Postgres takes at most this many bind parameters per statement.
+const MAX_PARAMETERS: usize = u16::MAX as usize;
+
+// Macros rather than constants, so queries can be put together with
+// `concat!` and stay the static strings sqlx requires.
+macro_rules! order_columns {
+    () => {
+        "exchange_id, pair, client, side, price, quantity, filled_quantity, \
+         remaining_quantity, status, fees, entry_time, event_time"
+    };
+}
+
+macro_rules! trade_columns {
+    () => {
+        "time, pair, price, quantity, side, maker_id, taker_id"
+    };
+}
+
+pub struct PostgresStorage {
+    pool: PgPool,
+}
+
+impl PostgresStorage {
+    /// Connects to the database at `url`, e.g.
+    /// `postgres://tradebot@localhost/tradebot`.
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        Ok(Self::with_pool(PgPool::connect(url).await?))
+    }
+
+    pub fn with_pool(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Applies the migrations the database hasn't had yet.
+    pub async fn migrate(&self) -> Result<(), MigrateError> {
+        MIGRATOR.run(&self.pool).await
+    }
+}
+
+impl Storage for PostgresStorage {
+    type Error = sqlx::Error;
+
+    async fn save_orders(&mut self, orders: &[OrderRecord]) -> Result<(), sqlx::Error> {
+        // An upsert can't touch the same row twice, so only the last state
+        // of each order goes in.
+        let orders: BTreeMap<u64, &OrderRecord> = orders
+            .iter()
+            .map(|order| (order.exchange_id, order))
+            .collect();
+        let orders: Vec<&OrderRecord> = orders.into_values().collect();
+        let mut transaction = self.pool.begin().await?;
+        for chunk in orders.chunks(MAX_PARAMETERS / 12) {
+            let mut insert = QueryBuilder::<Postgres>::new(concat!(
+                "INSERT INTO orders (",
+                order_columns!(),
+                ") "
+            ));
+            insert.push_values(chunk, |mut row, order| {
+                row.push_bind(order.exchange_id as i64)
+                    .push_bind(order.pair.clone())
+                    .push_bind(order.client.clone())
+                    .push_bind(side(order.side))
+                    .push_bind(order.price)
+                    .push_bind(order.quantity)
+                    .push_bind(order.filled_quantity)
+                    .push_bind(order.remaining_quantity)
+                    .push_bind(status_name(order.status))
+                    .push_bind(order.fees)
+                    .push_bind(order.entry_time)
+                    .push_bind(order.event_time);
+            });
+            insert.push(
+                " ON CONFLICT (exchange_id) DO UPDATE SET \
+                 filled_quantity = EXCLUDED.filled_quantity, \
+                 remaining_quantity = EXCLUDED.remaining_quantity, \
+                 status = EXCLUDED.status, \
+                 fees = EXCLUDED.fees, \
+                 event_time = EXCLUDED.event_time",
+            );
+            insert.build().execute(&mut *transaction).await?;
+        }
+        transaction.commit().await
+    }
+
+    async fn save_trades(&mut self, trades: &[TradeRecord]) -> Result<(), sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        for chunk in trades.chunks(MAX_PARAMETERS / 7) {
+            let mut insert = QueryBuilder::<Postgres>::new(concat!(
+                "INSERT INTO trades (",
+                trade_columns!(),
+                ") "
+            ));
+            insert.push_values(chunk, |mut row, trade| {
+                row.push_bind(trade.time)
+                    .push_bind(trade.pair.clone())
+                    .push_bind(trade.price)
+                    .push_bind(trade.quantity)
+                    .push_bind(side(trade.side))
+                    .push_bind(trade.maker_id as i64)
+                    .push_bind(trade.taker_id as i64);
+            });
+            insert.build().execute(&mut *transaction).await?;
+        }
+        transaction.commit().await
+    }
+
+    async fn save_balances(&mut self, balances: &[BalanceRecord]) -> Result<(), sqlx::Error> {
+        let balances: BTreeMap<(&str, &str), &BalanceRecord> = balances
+            .iter()
+            .map(|balance| ((balance.account.as_str(), balance.asset.as_str()), balance))
+            .collect();
+        let balances: Vec<&BalanceRecord> = balances.into_values().collect();
+        let mut transaction = self.pool.begin().await?;
+        for chunk in balances.chunks(MAX_PARAMETERS / 3) {
+            let mut insert =
+                QueryBuilder::<Postgres>::new("INSERT INTO balances (account, asset, amount) ");
+            insert.push_values(chunk, |mut row, balance| {
+                row.push_bind(balance.account.clone())
+                    .push_bind(balance.asset.clone())
+                    .push_bind(balance.amount);
+            });
+            insert.push(" ON CONFLICT (account, asset) DO UPDATE SET amount = EXCLUDED.amount");
+            insert.build().execute(&mut *transaction).await?;
+        }
+        transaction.commit().await
+    }
+
+    async fn order(&self, exchange_id: u64) -> Result<Option<OrderRecord>, sqlx::Error> {
+        sqlx::query(concat!(
+            "SELECT ",
+            order_columns!(),
+            " FROM orders WHERE exchange_id = $1"
+        ))
+        .bind(exchange_id as i64)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|row| order_record(&row))
+        .transpose()
+    }
+
+    async fn open_orders(&self, pair: &str) -> Result<Vec<OrderRecord>, sqlx::Error> {
+        sqlx::query(concat!(
+            "SELECT ",
+            order_columns!(),
+            " FROM orders WHERE pair = $1 AND status IN ('new', 'partially_filled') \
+             ORDER BY exchange_id"
+        ))
+        .bind(pair)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(order_record)
+        .collect()
+    }
+
+    async fn trades_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<TradeRecord>, sqlx::Error> {
+        sqlx::query(concat!(
+            "SELECT ",
+            trade_columns!(),
+            " FROM trades WHERE time >= $1 AND time < $2 ORDER BY id"
+        ))
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| {
+            Ok(TradeRecord {
+                time: row.try_get("time")?,
+                pair: row.try_get("pair")?,
+                price: row.try_get("price")?,
+                quantity: row.try_get("quantity")?,
+                side: parse(row, "side", OrderType::from_str)?,
+                maker_id: row.try_get::<i64, _>("maker_id")? as u64,
+                taker_id: row.try_get::<i64, _>("taker_id")? as u64,
+            })
+        })
+        .collect()
+    }
+
+    async fn balances(&self, account: &str) -> Result<Vec<BalanceRecord>, sqlx::Error> {
+        sqlx::query("SELECT account, asset, amount FROM balances WHERE account = $1 ORDER BY asset")
+            .bind(account)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| {
+                Ok(BalanceRecord {
+                    account: row.try_get("account")?,
+                    asset: row.try_get("asset")?,
+                    amount: row.try_get("amount")?,
+                })
+            })
+            .collect()
+    }
+}
+
+fn order_record(row: &PgRow) -> Result<OrderRecord, sqlx::Error> {
+    Ok(OrderRecord {
+        exchange_id: row.try_get::<i64, _>("exchange_id")? as u64,
+        pair: row.try_get("pair")?,
+        client: row.try_get("client")?,
+        side: parse(row, "side", OrderType::from_str)?,
+        price: row.try_get("price")?,
+        quantity: row.try_get("quantity")?,
+        filled_quantity: row.try_get("filled_quantity")?,
+        remaining_quantity: row.try_get("remaining_quantity")?,
+        status: parse(row, "status", parse_status)?,
+        fees: row.try_get("fees")?,
+        entry_time: row.try_get("entry_time")?,
+        event_time: row.try_get("event_time")?,
+    })
+}
+
+/// Reads the text in `column` with `from_text`.
+fn parse<T, E: Into<BoxDynError>>(
+    row: &PgRow,
+    column: &str,
+    from_text: impl FnOnce(&str) -> Result<T, E>,
+) -> Result<T, sqlx::Error> {
+    let text: String = row.try_get(column)?;
+    from_text(&text).map_err(|err| sqlx::Error::ColumnDecode {
+        index: column.to_string(),
+        source: err.into(),
+    })
+}
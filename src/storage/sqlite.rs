@@ -0,0 +1,473 @@
+//! SQLite storage, for installations too small to want a database server
+//! or to manage files of their own.
+//!
+//! Decimals are stored as text so they read back exactly. Times are stored
+//! as RFC 3339 text in UTC at microsecond precision, which sorts the same
+//! as the times themselves, so range queries compare them directly.
+
+use super::{parse_status, status_name, BalanceRecord, OrderRecord, Storage, TradeRecord};
+use crate::{
+    export::{side, timestamp},
+    limit_order_book::order::{OrderStatus, OrderType},
+};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, types::Type, Connection, OptionalExtension, Row};
+use rust_decimal::Decimal;
+use std::{error::Error, path::Path, str::FromStr};
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS orders (
+    exchange_id INTEGER PRIMARY KEY,
+    pair TEXT NOT NULL,
+    client TEXT NOT NULL,
+    side TEXT NOT NULL,
+    price TEXT NOT NULL,
+    quantity TEXT NOT NULL,
+    filled_quantity TEXT NOT NULL,
+    remaining_quantity TEXT NOT NULL,
+    status TEXT NOT NULL,
+    fees TEXT NOT NULL,
+    entry_time TEXT NOT NULL,
+    event_time TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS orders_by_pair ON orders (pair, status);
+CREATE TABLE IF NOT EXISTS trades (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    time TEXT NOT NULL,
+    pair TEXT NOT NULL,
+    price TEXT NOT NULL,
+    quantity TEXT NOT NULL,
+    side TEXT NOT NULL,
+    maker_id INTEGER NOT NULL,
+    taker_id INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS trades_by_time ON trades (time);
+CREATE TABLE IF NOT EXISTS balances (
+    account TEXT NOT NULL,
+    asset TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    PRIMARY KEY (account, asset)
+);
+";
+
+const ORDER_COLUMNS: &str = "exchange_id, pair, client, side, price, quantity, \
+    filled_quantity, remaining_quantity, status, fees, entry_time, event_time";
+
+const TRADE_COLUMNS: &str = "time, pair, price, quantity, side, maker_id, taker_id";
+
+pub struct SqliteStorage {
+    connection: Connection,
+}
+
+impl SqliteStorage {
+    /// Opens or creates the database at `path`, creating any missing tables.
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    pub fn with_connection(connection: Connection) -> rusqlite::Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Inserts each order, or replaces the stored one with the same
+    /// exchange ID, in one transaction.
+    pub fn save_orders(&mut self, orders: &[OrderRecord]) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut insert = transaction.prepare_cached(&format!(
+                "INSERT OR REPLACE INTO orders ({}) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                ORDER_COLUMNS
+            ))?;
+            for order in orders {
+                insert.execute(params![
+                    order.exchange_id as i64,
+                    order.pair,
+                    order.client,
+                    side(order.side),
+                    order.price.to_string(),
+                    order.quantity.to_string(),
+                    order.filled_quantity.to_string(),
+                    order.remaining_quantity.to_string(),
+                    status_name(order.status),
+                    order.fees.to_string(),
+                    timestamp(&order.entry_time),
+                    timestamp(&order.event_time),
+                ])?;
+            }
+        }
+        transaction.commit()
+    }
+
+    /// Appends `trades` in one transaction.
+    pub fn save_trades(&mut self, trades: &[TradeRecord]) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut insert = transaction.prepare_cached(&format!(
+                "INSERT INTO trades ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                TRADE_COLUMNS
+            ))?;
+            for trade in trades {
+                insert.execute(params![
+                    timestamp(&trade.time),
+                    trade.pair,
+                    trade.price.to_string(),
+                    trade.quantity.to_string(),
+                    side(trade.side),
+                    trade.maker_id as i64,
+                    trade.taker_id as i64,
+                ])?;
+            }
+        }
+        transaction.commit()
+    }
+
+    /// Sets each balance, leaving those of other accounts and assets as
+    /// they were.
+    pub fn save_balances(&mut self, balances: &[BalanceRecord]) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut insert = transaction.prepare_cached(
+                "INSERT OR REPLACE INTO balances (account, asset, amount) VALUES (?1, ?2, ?3)",
+            )?;
+            for balance in balances {
+                insert.execute(params![
+                    balance.account,
+                    balance.asset,
+                    balance.amount.to_string()
+                ])?;
+            }
+        }
+        transaction.commit()
+    }
+
+    pub fn order(&self, exchange_id: u64) -> rusqlite::Result<Option<OrderRecord>> {
+        self.connection
+            .query_row(
+                &format!(
+                    "SELECT {} FROM orders WHERE exchange_id = ?1",
+                    ORDER_COLUMNS
+                ),
+                [exchange_id as i64],
+                order_record,
+            )
+            .optional()
+    }
+
+    /// The pair's new and partially filled orders, by exchange ID.
+    pub fn open_orders(&self, pair: &str) -> rusqlite::Result<Vec<OrderRecord>> {
+        let mut select = self.connection.prepare_cached(&format!(
+            "SELECT {} FROM orders WHERE pair = ?1 AND status IN (?2, ?3) \
+             ORDER BY exchange_id",
+            ORDER_COLUMNS
+        ))?;
+        let orders = select.query_map(
+            params![
+                pair,
+                status_name(OrderStatus::New),
+                status_name(OrderStatus::PartiallyFilled)
+            ],
+            order_record,
+        )?;
+        orders.collect()
+    }
+
+    /// Trades at or after `from` and before `to`, in the order they were
+    /// saved.
+    pub fn trades_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> rusqlite::Result<Vec<TradeRecord>> {
+        let mut select = self.connection.prepare_cached(&format!(
+            "SELECT {} FROM trades WHERE time >= ?1 AND time < ?2 ORDER BY id",
+            TRADE_COLUMNS
+        ))?;
+        let trades = select.query_map(params![timestamp(&from), timestamp(&to)], |row| {
+            Ok(TradeRecord {
+                time: parse(row, 0, parse_time)?,
+                pair: row.get(1)?,
+                price: parse(row, 2, Decimal::from_str)?,
+                quantity: parse(row, 3, Decimal::from_str)?,
+                side: parse(row, 4, OrderType::from_str)?,
+                maker_id: row.get::<_, i64>(5)? as u64,
+                taker_id: row.get::<_, i64>(6)? as u64,
+            })
+        })?;
+        trades.collect()
+    }
+
+    /// The account's balances, by asset.
+    pub fn balances(&self, account: &str) -> rusqlite::Result<Vec<BalanceRecord>> {
+        let mut select = self.connection.prepare_cached(
+            "SELECT account, asset, amount FROM balances WHERE account = ?1 ORDER BY asset",
+        )?;
+        let balances = select.query_map([account], |row| {
+            Ok(BalanceRecord {
+                account: row.get(0)?,
+                asset: row.get(1)?,
+                amount: parse(row, 2, Decimal::from_str)?,
+            })
+        })?;
+        balances.collect()
+    }
+}
+
+/// Each call runs to completion before returning its future.
+impl Storage for SqliteStorage {
+    type Error = rusqlite::Error;
+
+    async fn save_orders(&mut self, orders: &[OrderRecord]) -> rusqlite::Result<()> {
+        SqliteStorage::save_orders(self, orders)
+    }
+
+    async fn save_trades(&mut self, trades: &[TradeRecord]) -> rusqlite::Result<()> {
+        SqliteStorage::save_trades(self, trades)
+    }
+
+    async fn save_balances(&mut self, balances: &[BalanceRecord]) -> rusqlite::Result<()> {
+        SqliteStorage::save_balances(self, balances)
+    }
+
+    async fn order(&self, exchange_id: u64) -> rusqlite::Result<Option<OrderRecord>> {
+        SqliteStorage::order(self, exchange_id)
+    }
+
+    async fn open_orders(&self, pair: &str) -> rusqlite::Result<Vec<OrderRecord>> {
+        SqliteStorage::open_orders(self, pair)
+    }
+
+    async fn trades_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> rusqlite::Result<Vec<TradeRecord>> {
+        SqliteStorage::trades_between(self, from, to)
+    }
+
+    async fn balances(&self, account: &str) -> rusqlite::Result<Vec<BalanceRecord>> {
+        SqliteStorage::balances(self, account)
+    }
+}
+
+fn order_record(row: &Row<'_>) -> rusqlite::Result<OrderRecord> {
+    Ok(OrderRecord {
+        exchange_id: row.get::<_, i64>(0)? as u64,
+        pair: row.get(1)?,
+        client: row.get(2)?,
+        side: parse(row, 3, OrderType::from_str)?,
+        price: parse(row, 4, Decimal::from_str)?,
+        quantity: parse(row, 5, Decimal::from_str)?,
+        filled_quantity: parse(row, 6, Decimal::from_str)?,
+        remaining_quantity: parse(row, 7, Decimal::from_str)?,
+        status: parse(row, 8, parse_status)?,
+        fees: parse(row, 9, Decimal::from_str)?,
+        entry_time: parse(row, 10, parse_time)?,
+        event_time: parse(row, 11, parse_time)?,
+    })
+}
+
+/// Reads the text in column `index` with `from_text`.
+fn parse<T, E: Into<Box<dyn Error + Send + Sync>>>(
+    row: &Row<'_>,
+    index: usize,
+    from_text: impl FnOnce(&str) -> Result<T, E>,
+) -> rusqlite::Result<T> {
+    let text: String = row.get(index)?;
+    from_text(&text)
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, err.into()))
+}
+
+fn parse_time(text: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(text).map(|time| time.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        limit_order_book::order::Order,
+        matching_engine::{
+            accounts::{AccountId, Accounts},
+            engine::{MatchingEngine, TradingPair},
+        },
+    };
+    use chrono::{Duration, TimeZone};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_round_trip() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+
+        let mut saved = Vec::new();
+        let mut trades = Vec::new();
+        for (exchange_id, side, minutes) in [
+            (1, OrderType::Ask, 0),
+            (2, OrderType::Ask, 1),
+            (3, OrderType::Bid, 2),
+        ] {
+            let time = start + Duration::minutes(minutes);
+            let mut order = Order::new(
+                pair.to_string(),
+                exchange_id,
+                side,
+                dec!(1.5),
+                dec!(100.25),
+                time,
+                time,
+            );
+            order.client = format!("client-{}", exchange_id);
+            let (order, fills) = engine.place_limit_order(pair.clone(), order).unwrap();
+            saved.push(OrderRecord::from_order(pair.to_string(), &order));
+            trades.extend(
+                fills
+                    .iter()
+                    .map(|fill| TradeRecord::from_fill(pair.to_string(), time, side, fill)),
+            );
+        }
+        storage.save_orders(&saved).unwrap();
+        storage.save_trades(&trades).unwrap();
+        assert_eq!(storage.order(3).unwrap().as_ref(), Some(&saved[2]));
+        assert_eq!(saved[2].status, OrderStatus::Filled);
+        assert_eq!(storage.order(9).unwrap(), None);
+
+        // Order 3 filled order 1; saving order 1 again replaces it.
+        let mut filled = saved[0].clone();
+        filled.filled_quantity = filled.quantity;
+        filled.remaining_quantity = dec!(0);
+        filled.status = OrderStatus::Filled;
+        storage.save_orders(&[filled.clone()]).unwrap();
+        assert_eq!(storage.order(1).unwrap(), Some(filled));
+        assert_eq!(storage.open_orders("BTC/USDT").unwrap(), [saved[1].clone()]);
+        assert_eq!(
+            storage
+                .trades_between(start, start + Duration::minutes(2))
+                .unwrap(),
+            []
+        );
+        assert_eq!(
+            storage
+                .trades_between(start + Duration::minutes(2), start + Duration::hours(1))
+                .unwrap(),
+            trades
+        );
+
+        let mut accounts = Accounts::new();
+        accounts.open(AccountId::new("desk"), None).unwrap();
+        accounts.deposit("desk", "USDT", dec!(1000.50)).unwrap();
+        accounts.deposit("desk", "BTC", dec!(2)).unwrap();
+        storage
+            .save_balances(&BalanceRecord::from_accounts(&accounts))
+            .unwrap();
+        accounts.adjust("desk", "USDT", dec!(-0.50)).unwrap();
+        storage
+            .save_balances(&BalanceRecord::from_accounts(&accounts))
+            .unwrap();
+        let amounts: Vec<_> = storage
+            .balances("desk")
+            .unwrap()
+            .into_iter()
+            .map(|balance| (balance.asset, balance.amount))
+            .collect();
+        assert_eq!(
+            amounts,
+            [
+                ("BTC".to_string(), dec!(2)),
+                ("USDT".to_string(), dec!(1000))
+            ]
+        );
+    }
+
+    fn record(exchange_id: u64, status: OrderStatus) -> OrderRecord {
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        OrderRecord {
+            exchange_id,
+            pair: "ETH/USDT".to_string(),
+            client: "carol".to_string(),
+            side: OrderType::Bid,
+            price: dec!(2000.10),
+            quantity: dec!(3),
+            filled_quantity: dec!(1),
+            remaining_quantity: dec!(2),
+            status,
+            fees: dec!(0.002),
+            entry_time: time,
+            event_time: time + Duration::microseconds(1_500_250),
+        }
+    }
+
+    #[test]
+    fn test_reopening_a_file() {
+        let path = std::env::temp_dir().join(format!("tradebot-sqlite-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut storage = SqliteStorage::open(&path).unwrap();
+        let orders = [
+            record(1, OrderStatus::PartiallyFilled),
+            record(2, OrderStatus::Cancelled),
+            record(3, OrderStatus::New),
+        ];
+        storage.save_orders(&orders).unwrap();
+        drop(storage);
+
+        // Opening again keeps the tables and what is in them.
+        let storage = SqliteStorage::open(&path).unwrap();
+        assert_eq!(storage.order(1).unwrap().as_ref(), Some(&orders[0]));
+        assert_eq!(
+            storage.open_orders("ETH/USDT").unwrap(),
+            [orders[0].clone(), orders[2].clone()]
+        );
+        assert!(storage.open_orders("BTC/USDT").unwrap().is_empty());
+        assert!(storage.balances("nobody").unwrap().is_empty());
+        drop(storage);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unreadable_rows() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+        storage
+            .save_orders(&[record(1, OrderStatus::New), record(2, OrderStatus::New)])
+            .unwrap();
+        let connection = storage.connection();
+        connection
+            .execute(
+                "UPDATE orders SET status = 'open' WHERE exchange_id = 1",
+                [],
+            )
+            .unwrap();
+        connection
+            .execute(
+                "UPDATE orders SET price = 'cheap' WHERE exchange_id = 2",
+                [],
+            )
+            .unwrap();
+
+        // A row that doesn't parse fails the read, naming the column.
+        assert!(matches!(
+            storage.order(1),
+            Err(rusqlite::Error::FromSqlConversionFailure(8, Type::Text, _))
+        ));
+        assert!(matches!(
+            storage.order(2),
+            Err(rusqlite::Error::FromSqlConversionFailure(4, Type::Text, _))
+        ));
+        assert!(storage.open_orders("ETH/USDT").is_err());
+        assert_eq!(
+            parse_status("open").unwrap_err(),
+            "Invalid order status: \"open\""
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use crate::limit_order_book::{
+    event::BookEvent,
+    order::OrderType,
+    render::{Ladder, LadderSide},
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::Constraint,
+    style::{Color, Style},
+    widgets::{Block, Row, Table},
+    DefaultTerminal, Frame,
+};
+use rust_decimal::prelude::*;
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::mpsc::{Receiver, TryRecvError},
+    time::Duration,
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Aggregated view of a book rebuilt from its `BookEvent`s, so the UI can run
+/// on a different thread from the book it is watching.
+#[derive(Debug, Default)]
+pub struct DepthMirror {
+    bids: BTreeMap<Decimal, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+}
+
+impl DepthMirror {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply(&mut self, event: &BookEvent) {
+        match *event {
+            BookEvent::OrderAdded {
+                order_type,
+                price,
+                quantity,
+                ..
+            } => self.adjust(order_type, price, quantity),
+            BookEvent::OrderExecuted {
+                order_type,
+                price,
+                quantity,
+                ..
+            }
+            | BookEvent::OrderRemoved {
+                order_type,
+                price,
+                quantity,
+                ..
+            } => self.adjust(order_type, price, -quantity),
+        }
+    }
+
+    pub fn ladder(&self, depth: usize) -> Ladder {
+        Ladder::new(
+            self.bids
+                .iter()
+                .rev()
+                .take(depth)
+                .map(|(price, size)| (*price, *size))
+                .collect(),
+            self.asks
+                .iter()
+                .take(depth)
+                .map(|(price, size)| (*price, *size))
+                .collect(),
+        )
+    }
+
+    fn adjust(&mut self, order_type: OrderType, price: Decimal, delta: Decimal) {
+        let levels = match order_type {
+            OrderType::Bid => &mut self.bids,
+            OrderType::Ask => &mut self.asks,
+        };
+        let size = levels.entry(price).or_default();
+        *size += delta;
+        if *size <= Decimal::zero() {
+            levels.remove(&price);
+        }
+    }
+}
+
+/// Runs the depth-of-market UI until `q` or `Esc` is pressed, applying book
+/// events from `events` as they arrive. `Up`/`Down` change the number of
+/// levels shown per side.
+pub fn run(events: Receiver<BookEvent>, depth: usize) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = run_loop(&mut terminal, events, depth);
+    ratatui::restore();
+    result
+}
+
+fn run_loop(
+    terminal: &mut DefaultTerminal,
+    events: Receiver<BookEvent>,
+    depth: usize,
+) -> io::Result<()> {
+    let mut mirror = DepthMirror::new();
+    let mut depth = depth.max(1);
+    let mut connected = true;
+
+    loop {
+        while connected {
+            match events.try_recv() {
+                Ok(event) => mirror.apply(&event),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => connected = false,
+            }
+        }
+
+        terminal.draw(|frame| draw(frame, &mirror.ladder(depth), connected))?;
+
+        if event::poll(REFRESH_INTERVAL)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Up => depth += 1,
+                    KeyCode::Down => depth = (depth - 1).max(1),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, ladder: &Ladder, connected: bool) {
+    let rows = ladder.rows().into_iter().map(|row| {
+        let color = match row.side {
+            LadderSide::Ask => Color::Red,
+            LadderSide::Bid => Color::Green,
+        };
+        Row::new(vec![
+            row.price.to_string(),
+            row.size.to_string(),
+            row.cumulative.to_string(),
+            "█".repeat(row.bar),
+        ])
+        .style(Style::default().fg(color))
+    });
+
+    let title = match (ladder.spread(), connected) {
+        (_, false) => " depth (feed disconnected) ".to_string(),
+        (Some(spread), true) => format!(" depth (spread {}) ", spread),
+        (None, true) => " depth ".to_string(),
+    };
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(14),
+            Constraint::Length(14),
+            Constraint::Length(14),
+            Constraint::Min(10),
+        ],
+    )
+    .header(Row::new(vec!["PRICE", "SIZE", "CUM", ""]))
+    .block(Block::bordered().title(title));
+
+    frame.render_widget(table, frame.area());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::{LimitOrderBook, Order};
+    use chrono::Utc;
+    use ratatui::{backend::TestBackend, Terminal};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_mirror_tracks_book() {
+        let mut book = LimitOrderBook::new();
+        for (exchange_id, order_type, price) in [
+            (1, OrderType::Bid, dec!(99)),
+            (2, OrderType::Ask, dec!(101)),
+            (3, OrderType::Ask, dec!(102)),
+        ] {
+            book.add_order(Order::new(
+                format!("tick{}", exchange_id),
+                exchange_id,
+                order_type,
+                dec!(10),
+                price,
+                Utc::now(),
+                Utc::now(),
+            ));
+        }
+        book.execute_order(Order::new(
+            "tick4".to_string(),
+            4,
+            OrderType::Bid,
+            dec!(15),
+            dec!(102),
+            Utc::now(),
+            Utc::now(),
+        ));
+        book.cancel_side(OrderType::Bid);
+
+        let mut mirror = DepthMirror::new();
+        for event in book.drain_events() {
+            mirror.apply(&event);
+        }
+        assert_eq!(mirror.ladder(10), book.ladder(10));
+    }
+
+    fn added(exchange_id: u64, order_type: OrderType, price: Decimal) -> BookEvent {
+        BookEvent::OrderAdded {
+            exchange_id,
+            order_type,
+            price,
+            quantity: dec!(2),
+            queue_position: 0,
+        }
+    }
+
+    fn render(ladder: &Ladder, connected: bool) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(60, 8)).unwrap();
+        terminal
+            .draw(|frame| draw(frame, ladder, connected))
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content()
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_draw_ladder() {
+        let mut mirror = DepthMirror::new();
+        for (exchange_id, order_type, price) in [
+            (1, OrderType::Bid, dec!(98)),
+            (2, OrderType::Bid, dec!(99)),
+            (3, OrderType::Ask, dec!(101)),
+        ] {
+            mirror.apply(&added(exchange_id, order_type, price));
+        }
+        // Only the best level per side at depth 1.
+        let ladder = mirror.ladder(1);
+        assert_eq!(
+            ladder,
+            Ladder::new(vec![(dec!(99), dec!(2))], vec![(dec!(101), dec!(2))])
+        );
+
+        let screen = render(&ladder, true);
+        assert!(screen.contains("depth (spread 2)"));
+        assert!(screen.contains("PRICE"));
+        assert!(screen.contains("101"));
+        assert!(!screen.contains("98"));
+        assert!(render(&ladder, false).contains("depth (feed disconnected)"));
+        assert!(render(&Ladder::new(vec![], vec![]), true).contains(" depth "));
+    }
+}
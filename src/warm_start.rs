@@ -0,0 +1,332 @@
+//! Seeding a market's book from an exchange depth snapshot, so a backtest
+//! starts from a realistic book instead of an empty one.
+//!
+//! Two formats are read: Binance's REST depth snapshot,
+//!
+//! ```text
+//! {"lastUpdateId": 1027024, "bids": [["4.00", "431.00"]], "asks": [["4.02", "12.00"]]}
+//! ```
+//!
+//! and a generic CSV with a header row naming its columns, in any order:
+//!
+//! ```text
+//! side,price,size
+//! bid,4.00,431
+//! ask,4.02,12
+//! ```
+//!
+//! Depth snapshots only carry aggregate sizes, so each level is seeded as
+//! one synthetic order under the client `WARM_START_CLIENT`.
+
+use crate::{
+    import::{split_line, ImportError},
+    limit_order_book::order::{Order, OrderType},
+    matching_engine::{
+        engine::{MatchingEngine, TradingPair},
+        snapshot::{EngineSnapshot, MarketSnapshot},
+    },
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    io::BufRead,
+};
+
+/// The client the synthetic orders rest under.
+pub const WARM_START_CLIENT: &str = "warm_start";
+
+/// Aggregate size per price level, as `(price, size)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DepthSnapshot {
+    pub bids: Vec<(Decimal, Decimal)>,
+    pub asks: Vec<(Decimal, Decimal)>,
+}
+
+#[derive(Deserialize)]
+struct BinanceDepth {
+    bids: Vec<(Decimal, Decimal)>,
+    asks: Vec<(Decimal, Decimal)>,
+}
+
+impl DepthSnapshot {
+    /// Reads the response of Binance's `GET /api/v3/depth`. Fields other than
+    /// the two sides are ignored.
+    pub fn from_binance_json(json: &str) -> Result<Self, ImportError> {
+        let depth: BinanceDepth = serde_json::from_str(json)
+            .map_err(|err| ImportError::new(err.line(), err.to_string()))?;
+        Self {
+            bids: depth.bids,
+            asks: depth.asks,
+        }
+        .checked()
+    }
+
+    /// Reads the generic CSV format. Unlike order flow, a snapshot with a
+    /// bad row is unusable, so the first error fails the whole file.
+    pub fn read_csv(reader: impl BufRead) -> Result<Self, ImportError> {
+        let mut depth = Self::default();
+        let mut header: Option<HashMap<String, usize>> = None;
+
+        for (index, line) in reader.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.map_err(|err| ImportError::new(line_number, err.to_string()))?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields =
+                split_line(&line).map_err(|message| ImportError::new(line_number, message))?;
+            let Some(columns) = &header else {
+                let columns: HashMap<String, usize> = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(position, name)| (name.trim().to_ascii_lowercase(), position))
+                    .collect();
+                let missing: Vec<&str> = ["side", "price", "size"]
+                    .into_iter()
+                    .filter(|name| !columns.contains_key(*name))
+                    .collect();
+                if !missing.is_empty() {
+                    return Err(ImportError::new(
+                        line_number,
+                        format!("Header is missing columns: {}", missing.join(", ")),
+                    ));
+                }
+                header = Some(columns);
+                continue;
+            };
+
+            let field = |name: &str| {
+                fields
+                    .get(columns[name])
+                    .map(|value| value.trim())
+                    .filter(|value| !value.is_empty())
+                    .ok_or_else(|| ImportError::new(line_number, format!("Missing {}", name)))
+            };
+            let decimal = |name: &str| {
+                let value = field(name)?;
+                value.parse::<Decimal>().map_err(|_| {
+                    ImportError::new(line_number, format!("Invalid {}: {:?}", name, value))
+                })
+            };
+            let side: OrderType = field("side")?
+                .parse()
+                .map_err(|message| ImportError::new(line_number, message))?;
+            let level = (decimal("price")?, decimal("size")?);
+            match side {
+                OrderType::Bid => depth.bids.push(level),
+                OrderType::Ask => depth.asks.push(level),
+            }
+        }
+
+        if header.is_none() {
+            return Err(ImportError::new(0, "File has no header row"));
+        }
+        depth.checked()
+    }
+
+    /// Rejects levels that aren't positive or appear twice, and books that
+    /// cross. The errors are about the whole snapshot, so carry line 0.
+    fn checked(self) -> Result<Self, ImportError> {
+        for levels in [&self.bids, &self.asks] {
+            let mut prices = HashSet::new();
+            for (price, size) in levels {
+                if *price <= Decimal::ZERO || *size <= Decimal::ZERO {
+                    return Err(ImportError::new(
+                        0,
+                        format!("Invalid level: {} at {}", size, price),
+                    ));
+                }
+                if !prices.insert(*price) {
+                    return Err(ImportError::new(
+                        0,
+                        format!("Level {} appears twice", price),
+                    ));
+                }
+            }
+        }
+        let best_bid = self.bids.iter().map(|(price, _)| *price).max();
+        let best_ask = self.asks.iter().map(|(price, _)| *price).min();
+        if let (Some(bid), Some(ask)) = (best_bid, best_ask) {
+            if bid >= ask {
+                return Err(ImportError::new(
+                    0,
+                    format!("Book is crossed: bid {} >= ask {}", bid, ask),
+                ));
+            }
+        }
+        Ok(self)
+    }
+
+    /// Replaces the pair's book with one synthetic order per level, resting
+    /// since `time`, and returns their exchange IDs. Nothing matches; the
+    /// market's increments and risk limits aren't applied.
+    pub fn seed(
+        &self,
+        engine: &mut MatchingEngine,
+        pair: &TradingPair,
+        time: DateTime<Utc>,
+    ) -> Result<Vec<u64>, String> {
+        if engine.orderbook(pair).is_none() {
+            return Err(format!("No orderbook for trading pair: {}", pair));
+        }
+        let levels = self
+            .bids
+            .iter()
+            .map(|level| (OrderType::Bid, level))
+            .chain(self.asks.iter().map(|level| (OrderType::Ask, level)));
+        let orders: Vec<Order> = levels
+            .map(|(side, (price, size))| {
+                let mut order = Order::new(
+                    pair.to_string(),
+                    engine.next_exchange_id(),
+                    side,
+                    *size,
+                    *price,
+                    time,
+                    time,
+                );
+                order.client = WARM_START_CLIENT.to_string();
+                order
+            })
+            .collect();
+        let exchange_ids = orders.iter().map(|order| order.exchange_id).collect();
+        // Restoring hands the ID taken here out again.
+        let snapshot = EngineSnapshot {
+            next_exchange_id: engine.next_exchange_id(),
+            // Restoring never winds the audit sequence back.
+            audit_sequence: 0,
+            markets: vec![MarketSnapshot {
+                pair: pair.to_string(),
+                orders,
+                last_trade_price: None,
+                previous_close: None,
+            }],
+            // Restoring replaces these, so they are carried over as they are.
+            positions: engine.positions().snapshot(),
+            holdings: engine.portfolio().snapshot(),
+            fees: engine.fee_ledger().snapshot(),
+        };
+        engine.restore(&snapshot)?;
+        Ok(exchange_ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_seed_from_snapshots() {
+        let json = r#"{
+            "lastUpdateId": 1027024,
+            "bids": [["100.00", "2.5"], ["99.50", "4"]],
+            "asks": [["100.50", "1"], ["101.00", "3"]]
+        }"#;
+        let binance = DepthSnapshot::from_binance_json(json).unwrap();
+        let csv = "# taken at the open\nprice,side,size\n100,buy,2.5\n99.5,buy,4\n100.5,sell,1\n101,sell,3\n";
+        let generic = DepthSnapshot::read_csv(Cursor::new(csv)).unwrap();
+        assert_eq!(generic, binance);
+
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        engine.add_new_market(pair.clone());
+        let ids = binance.seed(&mut engine, &pair, Utc::now()).unwrap();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+        assert_eq!(engine.next_exchange_id(), 5);
+        let ladder = engine.orderbook(&pair).unwrap().ladder(5);
+        assert_eq!(
+            ladder.bids,
+            vec![(dec!(100), dec!(2.5)), (dec!(99.5), dec!(4))]
+        );
+        assert_eq!(
+            ladder.asks,
+            vec![(dec!(100.5), dec!(1)), (dec!(101), dec!(3))]
+        );
+
+        let crossed = "side,price,size\nbid,101,1\nask,100.5,1\n";
+        let error = DepthSnapshot::read_csv(Cursor::new(crossed)).unwrap_err();
+        assert_eq!(error.message, "Book is crossed: bid 101 >= ask 100.5");
+        let error = DepthSnapshot::read_csv(Cursor::new("side,price\nbid,1\n")).unwrap_err();
+        assert_eq!(error.message, "Header is missing columns: size");
+        assert!(
+            DepthSnapshot::from_binance_json(r#"{"bids": [["1", "-2"]], "asks": []}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_bad_rows_and_levels() {
+        let read = |csv: &str| DepthSnapshot::read_csv(Cursor::new(csv)).unwrap_err();
+        let error = read("side,price,size\nbid,100,1\nbid,abc,1\n");
+        assert_eq!(
+            (error.line, error.message.as_str()),
+            (3, "Invalid price: \"abc\"")
+        );
+        let error = read("side,price,size\n\nask,100,\n");
+        assert_eq!((error.line, error.message.as_str()), (3, "Missing size"));
+        let error = read("side,price,size\nhold,100,1\n");
+        assert_eq!(error.line, 2);
+        let error = read("# nothing but comments\n\n");
+        assert_eq!(
+            (error.line, error.message.as_str()),
+            (0, "File has no header row")
+        );
+        let error = read("side,price,size\nask,100,1\nask,100,2\n");
+        assert_eq!(
+            (error.line, error.message.as_str()),
+            (0, "Level 100 appears twice")
+        );
+        let error = read("side,price,size\nbid,0,1\n");
+        assert_eq!(error.message, "Invalid level: 1 at 0");
+
+        // One-sided and empty books aren't crossed.
+        let depth = DepthSnapshot::read_csv(Cursor::new("side,price,size\n")).unwrap();
+        assert_eq!(depth, DepthSnapshot::default());
+        let json = r#"{"bids": [], "asks": [["5", "1"]]}"#;
+        assert_eq!(
+            DepthSnapshot::from_binance_json(json).unwrap().asks,
+            vec![(dec!(5), dec!(1))]
+        );
+        assert!(DepthSnapshot::from_binance_json("{").is_err());
+    }
+
+    #[test]
+    fn test_seed_replaces_the_book() {
+        let pair = TradingPair::new("BTC".to_string(), "USDT".to_string());
+        let mut engine = MatchingEngine::new();
+        let depth = DepthSnapshot {
+            bids: vec![(dec!(99), dec!(2))],
+            asks: vec![(dec!(101), dec!(1))],
+        };
+        let error = depth.seed(&mut engine, &pair, Utc::now()).unwrap_err();
+        assert_eq!(error, "No orderbook for trading pair: BTC/USDT");
+
+        engine.add_new_market(pair.clone());
+        let now = Utc::now();
+        let resting = Order::new(
+            pair.to_string(),
+            engine.next_exchange_id(),
+            OrderType::Bid,
+            dec!(5),
+            dec!(90),
+            now,
+            now,
+        );
+        engine.place_limit_order(pair.clone(), resting).unwrap();
+        let ids = depth.seed(&mut engine, &pair, now).unwrap();
+        assert_eq!(ids, vec![2, 3]);
+
+        // The earlier order is gone and the seeded ones rest under the
+        // warm start client.
+        let book = engine.orderbook(&pair).unwrap();
+        assert_eq!(book.orders.len(), 2);
+        assert!(book
+            .orders
+            .values()
+            .all(|order| order.client == WARM_START_CLIENT));
+        assert_eq!(book.get_best_bid(), Some(dec!(99)));
+    }
+}
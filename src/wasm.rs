@@ -0,0 +1,188 @@
+//! `wasm-bindgen` bindings so the matching logic can run in the browser.
+//!
+//! Requests and responses cross the boundary as JSON in the same shapes as
+//! the HTTP API (`tradebot::api`), with decimals as strings. Build with
+//!
+//! ```sh
+//! cargo rustc --lib --crate-type cdylib --release --target wasm32-unknown-unknown \
+//!     --no-default-features --features wasm
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/tradebot.wasm
+//! ```
+
+use crate::{
+    api::{BookSnapshot, Command, NewOrderResponse, OrderReport},
+    config::EngineConfig,
+    matching_engine::engine::{MatchingEngine, TradingPair},
+};
+use chrono::Utc;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct Engine {
+    engine: MatchingEngine,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Engine {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Engine {
+        Engine {
+            engine: MatchingEngine::new(),
+        }
+    }
+
+    /// Builds an engine from the TOML accepted by `tradebot serve --config`.
+    #[wasm_bindgen(js_name = fromConfig)]
+    pub fn from_config(toml: &str) -> Result<Engine, JsError> {
+        let config: EngineConfig = toml.parse().map_err(|err| js_error(&err))?;
+        Ok(Engine {
+            engine: MatchingEngine::with_config(config),
+        })
+    }
+
+    /// Opens a market such as `"BTC/USDT"`.
+    #[wasm_bindgen(js_name = addMarket)]
+    pub fn add_market(&mut self, pair: &str) -> Result<(), JsError> {
+        let pair = pair.parse::<TradingPair>().map_err(|err| js_error(&err))?;
+        self.engine.add_new_market(pair);
+        Ok(())
+    }
+
+    pub fn markets(&self) -> Vec<String> {
+        let mut markets: Vec<_> = self.engine.markets().map(TradingPair::to_string).collect();
+        markets.sort();
+        markets
+    }
+
+    /// Applies one command in the replay-file format, e.g.
+    /// `{"type":"new","pair":"BTC/USDT","side":"Bid","price":"100","quantity":"1"}`,
+    /// and returns the order response or cancel report as JSON.
+    pub fn submit(&mut self, command: &str) -> Result<String, JsError> {
+        self.submit_json(command).map_err(|err| js_error(&err))
+    }
+
+    /// Aggregated depth as `BookSnapshot` JSON.
+    pub fn book(&self, pair: &str, depth: usize) -> Result<String, JsError> {
+        self.book_json(pair, depth).map_err(|err| js_error(&err))
+    }
+}
+
+impl Engine {
+    fn submit_json(&mut self, command: &str) -> Result<String, String> {
+        let command: Command = serde_json::from_str(command).map_err(|err| err.to_string())?;
+        let response = match command {
+            Command::New(request) => {
+                let (pair, order) = request.to_order(self.engine.next_exchange_id(), Utc::now())?;
+                let (order, fills) = self.engine.place_limit_order(pair, order)?;
+                serde_json::to_string(&NewOrderResponse {
+                    order: OrderReport::new(request.pair, &order),
+                    fills,
+                })
+            }
+            Command::Cancel(request) => {
+                let pair = request.pair.parse::<TradingPair>()?;
+                let order = self.engine.cancel_order(&pair, request.exchange_id)?;
+                serde_json::to_string(&OrderReport::new(request.pair, &order))
+            }
+        };
+        response.map_err(|err| err.to_string())
+    }
+
+    fn book_json(&self, pair: &str, depth: usize) -> Result<String, String> {
+        let trading_pair = pair.parse::<TradingPair>()?;
+        let book = self
+            .engine
+            .orderbook(&trading_pair)
+            .ok_or_else(|| format!("No orderbook for trading pair: {:?}", pair))?;
+        serde_json::to_string(&BookSnapshot::new(pair.to_string(), book, depth))
+            .map_err(|err| err.to_string())
+    }
+}
+
+fn js_error(err: &impl ToString) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::order::OrderStatus;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_submit_and_book() {
+        let mut engine = Engine::new();
+        engine.engine.add_new_market("BTC/USDT".parse().unwrap());
+
+        engine
+            .submit_json(
+                r#"{"type":"new","pair":"BTC/USDT","side":"Ask","price":"100","quantity":"5"}"#,
+            )
+            .unwrap();
+        let response: NewOrderResponse = serde_json::from_str(
+            &engine
+                .submit_json(
+                    r#"{"type":"new","pair":"BTC/USDT","side":"Bid","price":"100","quantity":"2"}"#,
+                )
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.fills[0].quantity, dec!(2));
+
+        let book: BookSnapshot =
+            serde_json::from_str(&engine.book_json("BTC/USDT", 10).unwrap()).unwrap();
+        assert_eq!(book.asks, vec![(dec!(100), dec!(3))]);
+        assert!(engine
+            .submit_json(r#"{"type":"cancel","pair":"BTC/USDT","exchange_id":9}"#)
+            .is_err());
+        assert_eq!(engine.markets(), vec!["BTC/USDT".to_string()]);
+    }
+
+    #[test]
+    fn test_cancel_and_errors() {
+        let mut engine = Engine::default();
+        assert!(engine.add_market("ETH/USDT").is_ok());
+        assert!(engine.add_market("BTC/USDT").is_ok());
+        assert_eq!(engine.markets(), vec!["BTC/USDT", "ETH/USDT"]);
+
+        let response: NewOrderResponse = serde_json::from_str(
+            &engine
+                .submit_json(
+                    r#"{"type":"new","pair":"ETH/USDT","side":"Bid","price":"10","quantity":"4"}"#,
+                )
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(response.fills.is_empty());
+        let report: OrderReport = serde_json::from_str(
+            &engine
+                .submit_json(&format!(
+                    r#"{{"type":"cancel","pair":"ETH/USDT","exchange_id":{}}}"#,
+                    response.order.exchange_id
+                ))
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(report.status, OrderStatus::Cancelled);
+        assert_eq!(report.quantity, dec!(4));
+        assert_eq!(report.filled_quantity, dec!(0));
+        let book: BookSnapshot =
+            serde_json::from_str(&engine.book_json("ETH/USDT", 10).unwrap()).unwrap();
+        assert!(book.bids.is_empty());
+
+        assert!(engine.submit_json("not json").is_err());
+        assert!(engine
+            .submit_json(
+                r#"{"type":"new","pair":"SOL/USDT","side":"Bid","price":"1","quantity":"1"}"#
+            )
+            .is_err());
+        assert!(engine.book_json("SOL/USDT", 10).is_err());
+        assert!(engine.book_json("SOLUSDT", 10).is_err());
+    }
+}